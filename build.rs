@@ -0,0 +1,19 @@
+//! Embeds the `git describe` output of the source tree as `TOPO_GIT_DESCRIBE` for the provenance of the outputs,
+//! see `provenance::RunInfo`. Nothing is embedded when building outside of a git checkout, e.g. from a source archive.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=.git/index");
+    let describe = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--tags"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(describe) = describe {
+        println!("cargo:rustc-env=TOPO_GIT_DESCRIBE={}", describe.trim());
+    }
+}
@@ -0,0 +1,42 @@
+"""Smoke test of the Python bindings, see the "Python bindings" section of the README.
+
+Run with `make python-test`, which builds the extension module with maturin and runs pytest.
+"""
+
+import pytest
+
+import topo_rust
+
+
+def test_calculate_topo_identical_lines():
+    lines = ["LINESTRING (0 0, 100 0)", "LINESTRING (100 0, 100 100)"]
+
+    result = topo_rust.calculate_topo(lines, lines, 10.0, 5.0)
+
+    assert result["precision"] == pytest.approx(1.0)
+    assert result["recall"] == pytest.approx(1.0)
+    assert result["f1_score"] == pytest.approx(1.0)
+    assert result["proposal_count"] == result["matched_proposal_count"]
+    assert result["ground_truth_count"] == result["matched_ground_truth_count"]
+    assert result["ground_truth_count"] > 0
+
+
+def test_calculate_topo_missing_edge():
+    ground_truth = ["LINESTRING (0 0, 100 0)", "LINESTRING (0 50, 100 50)"]
+    proposal = ["LINESTRING (0 1, 100 1)"]
+
+    result = topo_rust.calculate_topo(proposal, ground_truth, 10.0, 5.0)
+
+    assert result["precision"] == pytest.approx(1.0)
+    assert result["recall"] == pytest.approx(0.5)
+
+
+def test_sample_points():
+    points = topo_rust.sample_points("LINESTRING (0 0, 25 0)", 10.0)
+
+    assert points == [(0.0, 0.0), (10.0, 0.0), (20.0, 0.0), (25.0, 0.0)]
+
+
+def test_invalid_wkt_raises_value_error():
+    with pytest.raises(ValueError):
+        topo_rust.sample_points("POINT (0 0)", 10.0)
@@ -1,18 +1,188 @@
+#[cfg(feature = "gdal-io")]
 use core::slice;
+#[cfg(feature = "gdal-io")]
 use libc::c_char;
+#[cfg(feature = "gdal-io")]
 use proj_sys;
+#[cfg(feature = "gdal-io")]
 use std::{
     ffi::{c_int, CString},
-    ptr::null_mut,
     str::from_utf8,
 };
 
-use anyhow::anyhow;
+use crate::error::{Error, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+
+use super::Crs;
 
 pub type EpsgCode = u32;
 
-pub fn epsg_4326() -> gdal::spatial_ref::SpatialRef {
-    gdal::spatial_ref::SpatialRef::from_epsg(4326).unwrap()
+/// Definition of a CRS as given in a config file. Either a bare EPSG code, or a string containing an
+/// "EPSG:<code>" authority string, a PROJ string, or WKT.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CrsDefinition {
+    EpsgCode(EpsgCode),
+    Definition(String),
+}
+
+impl CrsDefinition {
+    /// The CRS of the definition. The kind of string definitions is detected from their contents: "EPSG:" prefix or
+    /// only digits for EPSG codes, "+" prefix for PROJ strings, anything else is treated as WKT. PROJ strings require
+    /// the `gdal-io` feature.
+    pub fn to_crs(&self) -> Result<Crs> {
+        #[cfg(feature = "gdal-io")]
+        {
+            Crs::from_spatial_ref(&self.to_spatial_ref()?)
+        }
+        #[cfg(not(feature = "gdal-io"))]
+        match self {
+            CrsDefinition::EpsgCode(code) => Ok(Crs::Epsg(*code)),
+            CrsDefinition::Definition(definition) => {
+                let definition = definition.trim();
+                if let Some(epsg_code) = parse_epsg_definition(definition)? {
+                    Ok(Crs::Epsg(epsg_code))
+                } else if definition.starts_with('+') {
+                    Err(Error::ConfigError(format!(
+                        "Parsing the PROJ string '{}' requires the gdal-io feature",
+                        definition
+                    )))
+                } else {
+                    Ok(Crs::from_wkt(definition))
+                }
+            }
+        }
+    }
+
+    /// Create a spatial ref with traditional GIS axis order from the definition, see `to_crs`.
+    #[cfg(feature = "gdal-io")]
+    pub fn to_spatial_ref(&self) -> Result<gdal::spatial_ref::SpatialRef> {
+        let spatial_ref = match self {
+            CrsDefinition::EpsgCode(code) => gdal::spatial_ref::SpatialRef::from_epsg(*code)?,
+            CrsDefinition::Definition(definition) => {
+                let definition = definition.trim();
+                if let Some(epsg_code) = parse_epsg_definition(definition)? {
+                    gdal::spatial_ref::SpatialRef::from_epsg(epsg_code)?
+                } else if definition.starts_with('+') {
+                    gdal::spatial_ref::SpatialRef::from_proj4(definition)
+                        .with_context(|| format!("Parsing PROJ string '{}'", definition))?
+                } else {
+                    gdal::spatial_ref::SpatialRef::from_wkt(definition)
+                        .with_context(|| format!("Parsing WKT '{}'", definition))?
+                }
+            }
+        };
+        set_traditional_gis_axis_order(&spatial_ref);
+        Ok(spatial_ref)
+    }
+}
+
+/// The EPSG code of a definition with an "EPSG:" prefix or of only digits, `None` for other definitions.
+fn parse_epsg_definition(definition: &str) -> Result<Option<EpsgCode>> {
+    let epsg_code = definition
+        .strip_prefix("EPSG:")
+        .or_else(|| Some(definition).filter(|def| def.chars().all(|c| c.is_ascii_digit())));
+    epsg_code
+        .map(|epsg_code| {
+            epsg_code
+                .parse()
+                .with_context(|| format!("Parsing EPSG code from '{}'", definition))
+        })
+        .transpose()
+}
+
+/// WGS84 geographic CRS. Geometries in it have traditional GIS axis order, i.e. x=longitude, y=latitude.
+pub fn epsg_4326() -> Crs {
+    Crs::Epsg(4326)
+}
+
+/// Set the axis mapping strategy of a spatial ref to the traditional GIS order (x=easting/longitude,
+/// y=northing/latitude).
+///
+/// GDAL 3 defaults to the axis order defined by the authority for newly constructed spatial refs, which is
+/// latitude-first for EPSG:4326. All geometries in this crate use the traditional order.
+#[cfg(feature = "gdal-io")]
+pub fn set_traditional_gis_axis_order(spatial_ref: &gdal::spatial_ref::SpatialRef) {
+    spatial_ref
+        .set_axis_mapping_strategy(gdal_sys::OSRAxisMappingStrategy::OAMS_TRADITIONAL_GIS_ORDER);
+}
+
+/// The projected CRS which graphs in a geographic CRS are projected to before the TOPO computation.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Projection {
+    /// The WGS84 UTM zone of the ground truth. Its scale distortion grows towards the zone boundaries, to about 0.1%
+    /// at the far edge of an area which straddles one.
+    #[default]
+    Utm,
+    /// A transverse Mercator projection whose central meridian runs through the center of the area of interest.
+    LocalTransverseMercator,
+    /// A Lambert azimuthal equal-area projection centered on the area of interest.
+    LocalAzimuthalEqualArea,
+}
+
+impl Projection {
+    /// The PROJ string of the projection centered on `center`, given as WGS84 longitude and latitude. `None` for
+    /// `Projection::Utm`, which is not centered.
+    pub fn local_proj_string(&self, center: geo::Coord) -> Option<String> {
+        let proj = match self {
+            Projection::Utm => return None,
+            Projection::LocalTransverseMercator => "tmerc +k=1",
+            Projection::LocalAzimuthalEqualArea => "laea",
+        };
+        Some(format!(
+            "+proj={} +lat_0={} +lon_0={} +x_0=0 +y_0=0 +datum=WGS84 +units=m +no_defs",
+            proj, center.y, center.x
+        ))
+    }
+}
+
+/// A definition of the CRS which PROJ and `CrsDefinition` accept: its EPSG authority string if it has an EPSG code,
+/// otherwise its WKT, e.g. for a CRS created from a PROJ string.
+pub fn proj_definition(crs: &Crs) -> Result<String> {
+    match crs {
+        Crs::Epsg(code) => Ok(epsg_code_to_authority_string(*code)),
+        Crs::Wkt(wkt) => Ok(wkt.clone()),
+        Crs::Unknown => Err(Error::CrsMismatch("The CRS is unknown".to_string())),
+    }
+}
+
+/// Description of a CRS as stored in the PROJ database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrsInfo {
+    /// Authority name, e.g. "EPSG".
+    pub auth_name: String,
+    /// Code within the authority, e.g. "32654".
+    pub code: String,
+    /// Name of the CRS, e.g. "WGS 84 / UTM zone 54N".
+    pub name: String,
+}
+
+impl CrsInfo {
+    /// The CRS as an authority string, e.g. "EPSG:32654".
+    pub fn authority_string(&self) -> String {
+        format!("{}:{}", self.auth_name, self.code)
+    }
+}
+
+/// Convert a distance in meters to degrees of longitude and latitude at the given latitude on the WGS84 ellipsoid.
+///
+/// # Arguments
+/// * lat - latitude in degrees.
+/// * meters - the distance to convert.
+///
+/// # Returns
+/// The distance in degrees of longitude and in degrees of latitude.
+pub fn meters_to_degrees_at(lat: f64, meters: f64) -> (f64, f64) {
+    let lat = lat.to_radians();
+    // Series expansions of the length of a degree on the WGS84 ellipsoid.
+    let meters_per_lat_degree = 111132.92 - 559.82 * (2.0 * lat).cos() + 1.175 * (4.0 * lat).cos()
+        - 0.0023 * (6.0 * lat).cos();
+    let meters_per_lon_degree =
+        111412.84 * lat.cos() - 93.5 * (3.0 * lat).cos() + 0.118 * (5.0 * lat).cos();
+    (
+        meters / meters_per_lon_degree,
+        meters / meters_per_lat_degree,
+    )
 }
 
 /// Query UTM zones which contain the lon/lat WGS84 coordinate.
@@ -24,13 +194,79 @@ pub fn epsg_4326() -> gdal::spatial_ref::SpatialRef {
 ///     with all datums are returned.
 ///
 /// # Returns
+/// Info of the found EPSG UTM zones.
+#[cfg(feature = "gdal-io")]
+pub fn query_utm_crs_info_list(
+    lon: f64,
+    lat: f64,
+    datum_name: Option<&str>,
+) -> Result<Vec<CrsInfo>> {
+    let (crs_infos, _) = query_projected_crs_info_list(lon, lat)?;
+    let mut results = Vec::new();
+    for crs_info in crs_infos {
+        if !crs_info.name.contains("UTM zone") {
+            continue;
+        }
+        if let Some(datum_name) = datum_name {
+            // UTM zone names start with the datum name as e.g. "WGS 87 / UTM zone ..."
+            // Split out the datum name and remvove the spaces.
+            let crs_datum = crs_info.name.split('/').next().ok_or_else(|| {
+                Error::invalid_data(format!(
+                    "CRS '{}' does not have a datum specifier",
+                    crs_info.name
+                ))
+            })?;
+            let crs_datum = crs_datum.replace(' ', "");
+            if crs_datum != datum_name {
+                continue;
+            }
+        }
+        results.push(crs_info);
+    }
+    Ok(results)
+}
+
+/// Query UTM zones which contain the lon/lat WGS84 coordinate, see `query_utm_crs_info_list`.
+///
+/// # Returns
 /// EPSG authority codes for the found UTM zones.
-pub fn query_utm_crs_info(
+#[cfg(feature = "gdal-io")]
+pub fn query_utm_crs_info(lon: f64, lat: f64, datum_name: Option<&str>) -> Result<Vec<EpsgCode>> {
+    query_utm_crs_info_list(lon, lat, datum_name)?
+        .into_iter()
+        .map(|crs_info| {
+            crs_info
+                .code
+                .parse()
+                .with_context(|| format!("Parsing EPSG code of {:?}", crs_info))
+        })
+        .collect()
+}
+
+/// Query UTM zones which contain the lon/lat WGS84 coordinate, see `query_utm_crs_info_list`.
+///
+/// # Returns
+/// Authority strings such as "EPSG:32654" for the found UTM zones.
+#[cfg(feature = "gdal-io")]
+pub fn query_utm_crs_authority_strings(
     lon: f64,
     lat: f64,
     datum_name: Option<&str>,
-) -> anyhow::Result<Vec<EpsgCode>> {
+) -> Result<Vec<String>> {
+    Ok(query_utm_crs_info_list(lon, lat, datum_name)?
+        .iter()
+        .map(CrsInfo::authority_string)
+        .collect())
+}
+
+/// Query all EPSG projected CRSs whose area of use contains the lon/lat WGS84 coordinate.
+///
+/// # Returns
+/// The found CRSs, and the result count reported by PROJ.
+#[cfg(feature = "gdal-io")]
+fn query_projected_crs_info_list(lon: f64, lat: f64) -> Result<(Vec<CrsInfo>, usize)> {
     let mut results = Vec::new();
+    let mut out_result_count: c_int = 0;
     unsafe {
         let context = proj_sys::proj_context_create();
         let auth_name = CString::new("EPSG").unwrap();
@@ -45,13 +281,11 @@ pub fn query_utm_crs_info(
         (*query_params).east_lon_degree = lon;
         (*query_params).north_lat_degree = lat;
 
-        let out_result_count: *mut c_int = null_mut();
-
         let mut crs_info_list = proj_sys::proj_get_crs_info_list_from_database(
             context,
             auth_name.as_ptr(),
             query_params,
-            out_result_count,
+            &mut out_result_count,
         );
         // Store the pointer returned by proj_get_crs_info_list_from_database to destroy it later with proj_crs_info_list_destroy.
         let crs_info_list_original = crs_info_list;
@@ -60,44 +294,43 @@ pub fn query_utm_crs_info(
         proj_sys::proj_context_destroy(context);
 
         if crs_info_list.is_null() {
-            return Err(anyhow!("Failed to query UTM zones."));
+            return Err(Error::ProjError("Failed to query UTM zones.".into()));
         }
 
         while !(*crs_info_list).is_null() {
             let crs_info = **crs_info_list;
             crs_info_list = crs_info_list.offset(1);
 
-            let crs_name = i8_ptr_as_str(crs_info.name)?;
-            if !crs_name.contains("UTM zone") {
-                continue;
-            }
-            if let Some(datum_name) = datum_name {
-                // UTM zone names start with the datum name as e.g. "WGS 87 / UTM zone ..."
-                // Split out the datum name and remvove the spaces.
-                let crs_datum = crs_name
-                    .split("/")
-                    .nth(0)
-                    .ok_or_else(|| anyhow!("CRS '{}' does not have a datum specifier", crs_name))?;
-                let crs_datum = crs_datum.replace(" ", "");
-                if crs_datum != datum_name {
-                    continue;
+            let crs_info = (|| -> Result<CrsInfo> {
+                Ok(CrsInfo {
+                    auth_name: i8_ptr_as_str(crs_info.auth_name)?.to_string(),
+                    code: i8_ptr_as_str(crs_info.code)?.to_string(),
+                    name: i8_ptr_as_str(crs_info.name)?.to_string(),
+                })
+            })();
+            match crs_info {
+                Ok(crs_info) => results.push(crs_info),
+                Err(error) => {
+                    proj_sys::proj_crs_info_list_destroy(crs_info_list_original);
+                    return Err(error);
                 }
             }
-            let auth_code: EpsgCode = i8_ptr_as_str(crs_info.code)?.parse()?;
-            results.push(auth_code);
         }
         proj_sys::proj_crs_info_list_destroy(crs_info_list_original);
     }
-    Ok(results)
+    Ok((results, out_result_count as usize))
 }
 
-fn i8_ptr_as_str(c_string: *const c_char) -> anyhow::Result<&'static str> {
+/// Borrow a C string as str. The returned str is only valid as long as the C string is alive.
+#[cfg(feature = "gdal-io")]
+fn i8_ptr_as_str<'a>(c_string: *const c_char) -> Result<&'a str> {
     unsafe {
         let slice = slice::from_raw_parts(
             c_string as *const u8,
             libc::strlen(c_string as *const c_char),
         );
-        from_utf8(slice).or_else(|err| Err(anyhow!("Could not decode string {}", err)))
+        from_utf8(slice)
+            .map_err(|err| Error::invalid_data(format!("Could not decode string {}", err)))
     }
 }
 
@@ -109,12 +342,20 @@ pub fn epsg_code_to_authority_string(code: EpsgCode) -> String {
 mod tests {
     use rstest::rstest;
 
+    #[cfg(feature = "gdal-io")]
     use std::collections::HashSet;
 
+    #[cfg(feature = "gdal-io")]
     use crate::crs::crs_utils::query_utm_crs_info;
+    use crate::crs::Crs;
+
+    use approx::assert_abs_diff_eq;
 
-    use super::EpsgCode;
+    use super::{meters_to_degrees_at, CrsDefinition};
+    #[cfg(feature = "gdal-io")]
+    use super::{query_projected_crs_info_list, query_utm_crs_authority_strings, EpsgCode};
 
+    #[cfg(feature = "gdal-io")]
     #[rstest]
     #[case(139.813385, 35.707317999, Some("WGS84"), vec!(32654))] // WGS 84 UTM zone 54N for a coordinate in Tokyo.
     #[case(139.813385, 35.707317999, Some("Tokyo"), vec!(3095))] // UTM zone 54N in the "Tokyo" projection (because of course that exists).
@@ -131,4 +372,101 @@ mod tests {
         let expected_results_set: HashSet<EpsgCode> = expected_results.into_iter().collect();
         assert_eq!(results_set, expected_results_set);
     }
+
+    #[cfg(feature = "gdal-io")]
+    #[rstest]
+    #[case(139.813385, 35.707317999, Some("WGS84"), vec!("EPSG:32654"))]
+    #[case(139.813385, 35.707317999, Some("NAD83"), vec!())]
+    #[case(-98.261719, 35.581384, Some("NAD83"), vec!("EPSG:26914"))]
+    fn test_query_utm_crs_authority_strings(
+        #[case] lon: f64,
+        #[case] lat: f64,
+        #[case] datum_name: Option<&str>,
+        #[case] expected_results: Vec<&str>,
+    ) {
+        let results = query_utm_crs_authority_strings(lon, lat, datum_name).unwrap();
+        let results_set: HashSet<String> = results.into_iter().collect();
+        let expected_results_set: HashSet<String> =
+            expected_results.into_iter().map(String::from).collect();
+        assert_eq!(results_set, expected_results_set);
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[rstest]
+    #[case(139.813385, 35.707317999)]
+    #[case(-98.261719, 35.581384)]
+    fn test_query_projected_crs_info_list_result_count(#[case] lon: f64, #[case] lat: f64) {
+        let (crs_infos, result_count) = query_projected_crs_info_list(lon, lat).unwrap();
+        assert!(!crs_infos.is_empty());
+        assert_eq!(result_count, crs_infos.len());
+    }
+
+    #[rstest]
+    #[case(0.0, 111319.458, 110574.273)]
+    #[case(60.0, 55799.979, 111412.240)]
+    #[case(-60.0, 55799.979, 111412.240)]
+    fn test_meters_to_degrees_at(
+        #[case] lat: f64,
+        #[case] expected_meters_per_lon_degree: f64,
+        #[case] expected_meters_per_lat_degree: f64,
+    ) {
+        let (lon_degrees, lat_degrees) = meters_to_degrees_at(lat, 1000.0);
+        assert_abs_diff_eq!(
+            1000.0 / expected_meters_per_lon_degree,
+            lon_degrees,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            1000.0 / expected_meters_per_lat_degree,
+            lat_degrees,
+            epsilon = 1e-9
+        );
+    }
+
+    #[rstest]
+    #[case(CrsDefinition::EpsgCode(32654))]
+    #[case(CrsDefinition::Definition("EPSG:32654".to_string()))]
+    #[case(CrsDefinition::Definition("32654".to_string()))]
+    fn test_crs_definition_epsg(#[case] definition: CrsDefinition) {
+        assert_eq!(Crs::Epsg(32654), definition.to_crs().unwrap());
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[test]
+    fn test_crs_definition_wkt() {
+        let definition = CrsDefinition::Definition(
+            gdal::spatial_ref::SpatialRef::from_epsg(32654)
+                .unwrap()
+                .to_wkt()
+                .unwrap(),
+        );
+        let spatial_ref = definition.to_spatial_ref().unwrap();
+        assert_eq!(32654, spatial_ref.auth_code().unwrap());
+        assert_eq!(Crs::Epsg(32654), definition.to_crs().unwrap());
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[test]
+    fn test_crs_definition_proj_string() {
+        let definition = CrsDefinition::Definition(
+            "+proj=utm +zone=54 +datum=WGS84 +units=m +no_defs".to_string(),
+        );
+        let spatial_ref = definition.to_spatial_ref().unwrap();
+        assert!(spatial_ref.is_projected());
+        assert!(spatial_ref.to_proj4().unwrap().contains("+zone=54"));
+    }
+
+    #[test]
+    fn test_crs_definition_from_yaml() {
+        let definitions: Vec<CrsDefinition> =
+            serde_yaml::from_str("[32654, \"EPSG:32654\", \"+proj=longlat\"]").unwrap();
+        assert_eq!(
+            vec![
+                CrsDefinition::EpsgCode(32654),
+                CrsDefinition::Definition("EPSG:32654".to_string()),
+                CrsDefinition::Definition("+proj=longlat".to_string())
+            ],
+            definitions
+        );
+    }
 }
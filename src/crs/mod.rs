@@ -1 +1,4 @@
 pub mod crs_utils;
+pub mod reference;
+
+pub use reference::Crs;
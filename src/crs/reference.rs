@@ -0,0 +1,348 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use super::crs_utils::{epsg_code_to_authority_string, EpsgCode};
+
+/// The CRS of a graph or geofile.
+///
+/// With the `gdal-io` feature, its properties are looked up with GDAL, see `Crs::to_spatial_ref`. Without it, they
+/// are derived from the EPSG code or parsed from the WKT, which only covers common CRSs, e.g. the linear unit of a
+/// projected CRS by EPSG code is only known for UTM zones and a few other metric CRSs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Crs {
+    /// A CRS of the EPSG authority.
+    Epsg(EpsgCode),
+    /// A CRS without an EPSG code, e.g. one created from a PROJ string, as WKT.
+    Wkt(String),
+    Unknown,
+}
+
+/// EPSG codes of common geographic CRSs, by which they are told apart from projected CRSs without GDAL.
+#[cfg(not(feature = "gdal-io"))]
+const GEOGRAPHIC_EPSG_CODES: [EpsgCode; 18] = [
+    4019, 4148, 4152, 4167, 4171, 4230, 4258, 4267, 4269, 4277, 4283, 4301, 4326, 4612, 4617, 4674,
+    6668, 7844,
+];
+
+/// Whether a projected CRS by EPSG code is known to have meters as its linear unit without GDAL: the UTM zones of
+/// WGS84, ETRS89, NAD83 and GDA, Web Mercator, and the national grids of some countries.
+#[cfg(not(feature = "gdal-io"))]
+fn is_metric_epsg_code(code: EpsgCode) -> bool {
+    matches!(
+        code,
+        32601..=32660
+            | 32701..=32760
+            | 25828..=25838
+            | 26901..=26923
+            | 28348..=28358
+            | 7846..=7859
+            | 6669..=6687
+            | 2443..=2461
+            | 3857
+            | 3395
+            | 3035
+            | 2154
+            | 27700
+    )
+}
+
+impl Crs {
+    /// The CRS of an EPSG code or WKT, `Crs::Epsg` if the WKT is of a CRS with an EPSG code.
+    pub fn from_wkt(wkt: &str) -> Crs {
+        match wkt_authority(wkt) {
+            Some((auth_name, auth_code)) if auth_name == "EPSG" && auth_code >= 0 => {
+                Crs::Epsg(auth_code as EpsgCode)
+            }
+            _ => Crs::Wkt(wkt.to_string()),
+        }
+    }
+
+    /// The code of the CRS in its authority, e.g. 32654.
+    pub fn auth_code(&self) -> Result<i32> {
+        match self {
+            Crs::Epsg(code) => Ok(*code as i32),
+            Crs::Wkt(wkt) => wkt_authority(wkt)
+                .map(|(_, auth_code)| auth_code)
+                .ok_or_else(|| Error::CrsMismatch(format!("The CRS '{}' has no authority", self))),
+            Crs::Unknown => Err(unknown_crs_error()),
+        }
+    }
+
+    /// The name of the authority of the CRS, e.g. "EPSG".
+    pub fn auth_name(&self) -> Result<String> {
+        match self {
+            Crs::Epsg(_) => Ok("EPSG".to_string()),
+            Crs::Wkt(wkt) => wkt_authority(wkt)
+                .map(|(auth_name, _)| auth_name)
+                .ok_or_else(|| Error::CrsMismatch(format!("The CRS '{}' has no authority", self))),
+            Crs::Unknown => Err(unknown_crs_error()),
+        }
+    }
+
+    /// The name of the CRS, e.g. "WGS 84 / UTM zone 54N". Without the `gdal-io` feature, CRSs by EPSG code are named
+    /// after their authority string, e.g. "EPSG:32654".
+    pub fn name(&self) -> Result<String> {
+        match self {
+            #[cfg(feature = "gdal-io")]
+            Crs::Epsg(_) => Ok(self.to_spatial_ref()?.name()?),
+            #[cfg(not(feature = "gdal-io"))]
+            Crs::Epsg(code) => Ok(epsg_code_to_authority_string(*code)),
+            Crs::Wkt(wkt) => wkt_name(wkt)
+                .map(str::to_string)
+                .ok_or_else(|| Error::invalid_data(format!("The WKT '{}' has no name", wkt))),
+            Crs::Unknown => Err(unknown_crs_error()),
+        }
+    }
+
+    pub fn is_geographic(&self) -> bool {
+        #[cfg(feature = "gdal-io")]
+        {
+            self.to_spatial_ref()
+                .is_ok_and(|spatial_ref| spatial_ref.is_geographic())
+        }
+        #[cfg(not(feature = "gdal-io"))]
+        match self {
+            Crs::Epsg(code) => GEOGRAPHIC_EPSG_CODES.contains(code),
+            Crs::Wkt(wkt) => matches!(wkt_keyword(wkt), "GEOGCS" | "GEOGCRS" | "GEOGRAPHICCRS"),
+            Crs::Unknown => false,
+        }
+    }
+
+    pub fn is_projected(&self) -> bool {
+        #[cfg(feature = "gdal-io")]
+        {
+            self.to_spatial_ref()
+                .is_ok_and(|spatial_ref| spatial_ref.is_projected())
+        }
+        #[cfg(not(feature = "gdal-io"))]
+        match self {
+            Crs::Epsg(_) => !self.is_geographic(),
+            Crs::Wkt(wkt) => matches!(wkt_keyword(wkt), "PROJCS" | "PROJCRS" | "PROJECTEDCRS"),
+            Crs::Unknown => false,
+        }
+    }
+
+    /// The length of the linear unit of a projected CRS in meters, NaN if it is not known.
+    pub fn linear_units(&self) -> f64 {
+        #[cfg(feature = "gdal-io")]
+        {
+            self.to_spatial_ref()
+                .map_or(f64::NAN, |spatial_ref| spatial_ref.linear_units())
+        }
+        #[cfg(not(feature = "gdal-io"))]
+        match self {
+            Crs::Epsg(code) if is_metric_epsg_code(*code) => 1.0,
+            Crs::Wkt(wkt) => wkt_linear_unit(wkt).map_or(f64::NAN, |(_, meters)| meters),
+            _ => f64::NAN,
+        }
+    }
+
+    /// The name of the linear unit of a projected CRS, e.g. "metre" or "US survey foot".
+    pub fn linear_units_name(&self) -> Result<String> {
+        #[cfg(feature = "gdal-io")]
+        {
+            Ok(self.to_spatial_ref()?.linear_units_name()?)
+        }
+        #[cfg(not(feature = "gdal-io"))]
+        match self {
+            Crs::Epsg(code) if is_metric_epsg_code(*code) => Ok("metre".to_string()),
+            Crs::Wkt(wkt) => wkt_linear_unit(wkt)
+                .map(|(name, _)| name.to_string())
+                .ok_or_else(|| {
+                    Error::invalid_data(format!("The WKT '{}' has no linear unit", wkt))
+                }),
+            _ => Err(Error::CrsMismatch(format!(
+                "The linear unit of the CRS '{}' is not known",
+                self
+            ))),
+        }
+    }
+
+    /// The WKT of the CRS. Without the `gdal-io` feature, only CRSs given as WKT have one.
+    pub fn to_wkt(&self) -> Result<String> {
+        match self {
+            #[cfg(feature = "gdal-io")]
+            Crs::Epsg(_) => Ok(self.to_spatial_ref()?.to_wkt()?),
+            #[cfg(not(feature = "gdal-io"))]
+            Crs::Epsg(code) => Err(Error::ConfigError(format!(
+                "The WKT of EPSG:{} requires the gdal-io feature",
+                code
+            ))),
+            Crs::Wkt(wkt) => Ok(wkt.clone()),
+            Crs::Unknown => Err(unknown_crs_error()),
+        }
+    }
+
+    /// The PROJ string of the CRS.
+    #[cfg(feature = "gdal-io")]
+    pub fn to_proj4(&self) -> Result<String> {
+        Ok(self.to_spatial_ref()?.to_proj4()?)
+    }
+
+    /// The CRS of a spatial ref: `Crs::Epsg` if it has an EPSG code, otherwise its WKT.
+    #[cfg(feature = "gdal-io")]
+    pub fn from_spatial_ref(spatial_ref: &gdal::spatial_ref::SpatialRef) -> Result<Crs> {
+        match (spatial_ref.auth_name(), spatial_ref.auth_code()) {
+            (Ok(auth_name), Ok(auth_code)) if auth_name == "EPSG" => {
+                Ok(Crs::Epsg(auth_code as EpsgCode))
+            }
+            _ => Ok(Crs::Wkt(spatial_ref.to_wkt()?)),
+        }
+    }
+
+    /// A spatial ref of the CRS with traditional GIS axis order, see `set_traditional_gis_axis_order`.
+    #[cfg(feature = "gdal-io")]
+    pub fn to_spatial_ref(&self) -> Result<gdal::spatial_ref::SpatialRef> {
+        let spatial_ref = match self {
+            Crs::Epsg(code) => gdal::spatial_ref::SpatialRef::from_epsg(*code)?,
+            Crs::Wkt(wkt) => gdal::spatial_ref::SpatialRef::from_wkt(wkt)?,
+            Crs::Unknown => return Err(unknown_crs_error()),
+        };
+        super::crs_utils::set_traditional_gis_axis_order(&spatial_ref);
+        Ok(spatial_ref)
+    }
+}
+
+/// CRSs are equal if they are the same EPSG code or WKT. With the `gdal-io` feature, a CRS by EPSG code and one by
+/// WKT are also equal if GDAL considers them the same.
+impl PartialEq for Crs {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Crs::Epsg(code), Crs::Epsg(other_code)) => code == other_code,
+            (Crs::Wkt(wkt), Crs::Wkt(other_wkt)) if wkt == other_wkt => true,
+            (Crs::Unknown, Crs::Unknown) => true,
+            (Crs::Unknown, _) | (_, Crs::Unknown) => false,
+            #[cfg(feature = "gdal-io")]
+            _ => match (self.to_spatial_ref(), other.to_spatial_ref()) {
+                (Ok(spatial_ref), Ok(other_spatial_ref)) => spatial_ref == other_spatial_ref,
+                _ => false,
+            },
+            #[cfg(not(feature = "gdal-io"))]
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Crs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Crs::Epsg(code) => f.write_str(&epsg_code_to_authority_string(*code)),
+            Crs::Wkt(wkt) => f.write_str(wkt),
+            Crs::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+fn unknown_crs_error() -> Error {
+    Error::CrsMismatch("The CRS is unknown".to_string())
+}
+
+/// The keyword of the root node of a WKT, e.g. "PROJCS".
+#[cfg_attr(feature = "gdal-io", allow(dead_code))]
+fn wkt_keyword(wkt: &str) -> &str {
+    wkt.split('[').next().unwrap_or_default().trim()
+}
+
+/// The name of the root node of a WKT, its first quoted string.
+fn wkt_name(wkt: &str) -> Option<&str> {
+    let (_, rest) = wkt.split_once("[\"")?;
+    rest.split('"').next()
+}
+
+/// The authority name and code of the root node of a WKT, from its `AUTHORITY` (WKT1) or `ID` (WKT2) node, which is
+/// the last one of the root node.
+fn wkt_authority(wkt: &str) -> Option<(String, i32)> {
+    let wkt = wkt.trim().strip_suffix(']')?.trim_end();
+    let node = wkt.strip_suffix(']')?;
+    let start = ["AUTHORITY[", "ID["]
+        .iter()
+        .filter_map(|keyword| Some(node.rfind(keyword)? + keyword.len()))
+        .max()?;
+    let content = &node[start..];
+    if content.contains(['[', ']']) {
+        return None;
+    }
+    let mut parts = content.split(',').map(|part| part.trim().trim_matches('"'));
+    let auth_name = parts.next()?.to_string();
+    let auth_code = parts.next()?.parse().ok()?;
+    Some((auth_name, auth_code))
+}
+
+/// The name and length in meters of the linear unit of a projected CRS WKT, from its last `UNIT` or `LENGTHUNIT`
+/// node.
+#[cfg_attr(feature = "gdal-io", allow(dead_code))]
+fn wkt_linear_unit(wkt: &str) -> Option<(&str, f64)> {
+    let start = wkt.rfind("UNIT[")?;
+    if wkt[..start].ends_with("ANGLE") {
+        return None;
+    }
+    let mut parts = wkt[start + "UNIT[".len()..].split(',');
+    let name = parts.next()?.trim().trim_matches('"');
+    let meters = parts.next()?.trim().trim_end_matches(']').parse().ok()?;
+    Some((name, meters))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{wkt_authority, wkt_linear_unit, Crs};
+
+    const UTM_54N_WKT: &str = r#"PROJCS["WGS 84 / UTM zone 54N",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],PARAMETER["central_meridian",141],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",500000],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],AUTHORITY["EPSG","32654"]]"#;
+
+    const US_FEET_WKT: &str = r#"PROJCS["NAD83 / California zone 3 (ftUS)",GEOGCS["NAD83",DATUM["North_American_Datum_1983",SPHEROID["GRS 1980",6378137,298.257222101]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Lambert_Conformal_Conic_2SP"],PARAMETER["standard_parallel_1",38.4333333333333],PARAMETER["standard_parallel_2",37.0666666666667],PARAMETER["latitude_of_origin",36.5],PARAMETER["central_meridian",-120.5],PARAMETER["false_easting",6561666.667],PARAMETER["false_northing",1640416.667],UNIT["US survey foot",0.304800609601219],AXIS["Easting",EAST],AXIS["Northing",NORTH]]"#;
+
+    #[test]
+    fn test_from_wkt_with_epsg_authority() {
+        assert_eq!(Crs::Epsg(32654), Crs::from_wkt(UTM_54N_WKT));
+        assert!(matches!(Crs::from_wkt(US_FEET_WKT), Crs::Wkt(_)));
+    }
+
+    #[rstest]
+    #[case(UTM_54N_WKT, Some(("EPSG".to_string(), 32654)))]
+    #[case(US_FEET_WKT, None)]
+    #[case(r#"GEOGCRS["WGS 84",CS[ellipsoidal,2],ID["EPSG",4326]]"#, Some(("EPSG".to_string(), 4326)))]
+    fn test_wkt_authority(#[case] wkt: &str, #[case] expected: Option<(String, i32)>) {
+        assert_eq!(expected, wkt_authority(wkt));
+    }
+
+    #[test]
+    fn test_wkt_linear_unit() {
+        assert_eq!(Some(("metre", 1.0)), wkt_linear_unit(UTM_54N_WKT));
+        assert_eq!(
+            Some(("US survey foot", 0.304800609601219)),
+            wkt_linear_unit(US_FEET_WKT)
+        );
+    }
+
+    #[test]
+    fn test_crs_properties() {
+        let utm = Crs::Epsg(32654);
+        assert!(utm.is_projected());
+        assert!(!utm.is_geographic());
+        assert_eq!(1.0, utm.linear_units());
+        assert_eq!(32654, utm.auth_code().unwrap());
+        assert_eq!("EPSG", utm.auth_name().unwrap());
+        assert!(Crs::Epsg(4326).is_geographic());
+
+        let feet = Crs::from_wkt(US_FEET_WKT);
+        assert!(feet.is_projected());
+        assert_eq!("NAD83 / California zone 3 (ftUS)", feet.name().unwrap());
+        assert!((feet.linear_units() - 0.3048006).abs() < 1e-6);
+        assert!(feet.auth_code().is_err());
+
+        assert!(!Crs::Unknown.is_projected());
+        assert!(Crs::Unknown.auth_code().is_err());
+    }
+
+    #[test]
+    fn test_crs_display_and_serde() {
+        assert_eq!("EPSG:32654", Crs::Epsg(32654).to_string());
+        assert_eq!("unknown", Crs::Unknown.to_string());
+        let yaml = serde_yaml::to_string(&Crs::Epsg(32654)).unwrap();
+        assert_eq!(
+            Crs::Epsg(32654),
+            serde_yaml::from_str::<Crs>(&yaml).unwrap()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::error::{Error, Result};
+
+use crate::crs::crs_utils::{query_utm_crs_info, EpsgCode};
+
+/// GDAL drivers the binary reads or writes files with.
+const REQUIRED_GDAL_DRIVERS: [&str; 2] = ["GPKG", "GeoJSON"];
+
+/// UTM zone used to check projections, with a coordinate within it.
+const TEST_UTM_ZONE: EpsgCode = 32654;
+const TEST_LON_LAT: (f64, f64) = (139.813385, 35.707318);
+
+const PROJ_DATABASE_HINT: &str = "Check that PROJ's database (proj.db) is installed, and if it is not in a default \
+location, set the PROJ_DATA (PROJ_LIB before PROJ 9.1) environment variable to the directory containing it.";
+
+/// Versions and search paths of the geospatial libraries, as found by `check_environment`.
+#[derive(Debug)]
+pub struct EnvironmentReport {
+    pub gdal_version: String,
+    pub proj_version: String,
+    /// Directories PROJ searches for its database and grids.
+    pub proj_search_paths: Vec<String>,
+    /// The registered GDAL drivers out of the required ones.
+    pub gdal_drivers: Vec<String>,
+}
+
+impl std::fmt::Display for EnvironmentReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "GDAL version: {}", self.gdal_version)?;
+        writeln!(f, "GDAL drivers: {}", self.gdal_drivers.join(", "))?;
+        writeln!(f, "PROJ version: {}", self.proj_version)?;
+        write!(
+            f,
+            "PROJ search paths: {}",
+            self.proj_search_paths.join(", ")
+        )
+    }
+}
+
+/// Check that GDAL and PROJ are usable: the required GDAL drivers are registered, spatial refs can be constructed,
+/// a projection round-trips and the PROJ database answers UTM zone queries.
+///
+/// # Returns
+/// The report if all checks pass, otherwise an error listing each failed check with a hint on how to fix it.
+pub fn check_environment() -> Result<EnvironmentReport> {
+    let mut failures: Vec<String> = Vec::new();
+
+    let mut gdal_drivers = Vec::new();
+    for driver_name in REQUIRED_GDAL_DRIVERS {
+        match gdal::DriverManager::get_driver_by_name(driver_name) {
+            Ok(_) => gdal_drivers.push(driver_name.to_string()),
+            Err(error) => failures.push(format!(
+                "GDAL driver {} is not available ({}). Install a GDAL build which includes it.",
+                driver_name, error
+            )),
+        }
+    }
+
+    for epsg_code in [4326, TEST_UTM_ZONE] {
+        if let Err(error) = gdal::spatial_ref::SpatialRef::from_epsg(epsg_code) {
+            failures.push(format!(
+                "GDAL cannot construct the spatial ref EPSG:{} ({}). {}",
+                epsg_code, error, PROJ_DATABASE_HINT
+            ));
+        }
+    }
+
+    let (proj_version, proj_search_paths) = match check_projection_round_trip() {
+        Ok(proj_info) => (proj_info.version, split_search_paths(&proj_info.searchpath)),
+        Err(error) => {
+            failures.push(format!(
+                "PROJ cannot project between EPSG:4326 and EPSG:{} ({}). {}",
+                TEST_UTM_ZONE, error, PROJ_DATABASE_HINT
+            ));
+            ("unknown".to_string(), Vec::new())
+        }
+    };
+
+    match query_utm_crs_info(TEST_LON_LAT.0, TEST_LON_LAT.1, Some("WGS84")) {
+        Ok(utm_zones) if utm_zones.contains(&TEST_UTM_ZONE) => {}
+        Ok(utm_zones) => failures.push(format!(
+            "The PROJ database returned {:?} instead of EPSG:{} for a UTM zone query. The database may be \
+            outdated, install a newer PROJ.",
+            utm_zones, TEST_UTM_ZONE
+        )),
+        Err(error) => failures.push(format!(
+            "The PROJ database cannot be queried for UTM zones ({}). {}",
+            error, PROJ_DATABASE_HINT
+        )),
+    }
+
+    if !failures.is_empty() {
+        return Err(Error::invalid_data(format!(
+            "The geospatial environment is not usable:\n- {}",
+            failures.join("\n- ")
+        )));
+    }
+    Ok(EnvironmentReport {
+        gdal_version: gdal::version::version_info("RELEASE_NAME"),
+        proj_version,
+        proj_search_paths,
+        gdal_drivers,
+    })
+}
+
+/// Project a coordinate from WGS84 to the test UTM zone and back, and check that it arrives where it started.
+fn check_projection_round_trip() -> Result<proj::Info> {
+    let utm_zone = format!("EPSG:{}", TEST_UTM_ZONE);
+    let forward = proj::Proj::new_known_crs("EPSG:4326", &utm_zone, None)?;
+    let inverse = proj::Proj::new_known_crs(&utm_zone, "EPSG:4326", None)?;
+    let projected = forward.convert(TEST_LON_LAT)?;
+    let (lon, lat) = inverse.convert(projected)?;
+    if (lon - TEST_LON_LAT.0).abs() > 1e-6 || (lat - TEST_LON_LAT.1).abs() > 1e-6 {
+        return Err(Error::ProjError(
+            format!(
+                "{:?} was projected to {:?} and back to {:?}",
+                TEST_LON_LAT,
+                projected,
+                (lon, lat)
+            )
+            .into(),
+        ));
+    }
+    Ok(forward.lib_info()?)
+}
+
+fn split_search_paths(search_path: &str) -> Vec<String> {
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    search_path
+        .split(separator)
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_environment, REQUIRED_GDAL_DRIVERS};
+
+    #[test]
+    fn test_check_environment() {
+        let report = check_environment().unwrap();
+
+        assert!(!report.gdal_version.is_empty());
+        assert!(!report.proj_version.is_empty());
+        assert_eq!(
+            REQUIRED_GDAL_DRIVERS.to_vec(),
+            report
+                .gdal_drivers
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+        );
+        assert!(report.to_string().contains("PROJ search paths"));
+    }
+}
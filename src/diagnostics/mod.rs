@@ -0,0 +1,4 @@
+#[cfg(feature = "gdal-io")]
+pub mod environment;
+pub mod validation;
+pub mod warnings;
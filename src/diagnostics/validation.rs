@@ -0,0 +1,115 @@
+/// The outcome of one check of a `ValidationReport`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    /// The check found something which does not stop a run, but may be unintended or slow it down, e.g. OSM data
+    /// which is not cached yet.
+    Warn,
+    Fail,
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// One check of a `ValidationReport`, e.g. that an input file is readable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckItem {
+    /// What was checked, e.g. "Proposal".
+    pub name: String,
+    pub status: CheckStatus,
+    /// What the check found, or why it failed.
+    pub detail: String,
+}
+
+/// The outcomes of a list of checks, e.g. of the inputs of a config before a long run. Displayed as a checklist with
+/// one line per check.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub items: Vec<CheckItem>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.items.push(CheckItem {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// Add a passed check with the detail returned by `result`, or a failed check with its error.
+    ///
+    /// # Returns
+    /// Whether the check passed.
+    pub fn check<E: std::fmt::Display>(
+        &mut self,
+        name: impl Into<String>,
+        result: std::result::Result<String, E>,
+    ) -> bool {
+        match result {
+            Ok(detail) => {
+                self.add(name, CheckStatus::Pass, detail);
+                true
+            }
+            Err(error) => {
+                self.add(name, CheckStatus::Fail, error.to_string());
+                false
+            }
+        }
+    }
+
+    /// The number of checks with the given status.
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.status == status)
+            .count()
+    }
+}
+
+impl std::fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for item in &self.items {
+            if item.detail.is_empty() {
+                writeln!(f, "[{}] {}", item.status, item.name)?;
+            } else {
+                writeln!(f, "[{}] {}: {}", item.status, item.name, item.detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckStatus, ValidationReport};
+
+    #[test]
+    fn test_validation_report() {
+        let mut report = ValidationReport::new();
+
+        assert!(report.check::<std::io::Error>("Config file", Ok("run.yaml".to_string())));
+        report.add("OSM data", CheckStatus::Warn, "not cached");
+        assert!(!report.check(
+            "Proposal",
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"))
+        ));
+
+        assert_eq!(1, report.count(CheckStatus::Fail));
+        assert_eq!(
+            "[PASS] Config file: run.yaml\n[WARN] OSM data: not cached\n[FAIL] Proposal: missing\n",
+            report.to_string()
+        );
+    }
+}
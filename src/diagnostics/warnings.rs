@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// The kind of a warning recorded by a `WarningCollector`.
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCategory {
+    /// Features of an input which could not be decoded and were skipped.
+    SkippedFeature,
+    /// Lines with less than two (distinct) coordinates, which were dropped.
+    DegenerateGeometry,
+    /// Features of an input which are not LineStrings, and were dropped.
+    NonLineString,
+    /// A CRS declared by an input which was overridden by the config.
+    CrsOverride,
+    /// Coordinates which do not look plausible for the CRS of their input.
+    ImplausibleCrs,
+    /// Ground truth and proposal which barely overlap.
+    LowOverlap,
+    /// Line features whose coordinates are outliers by orders of magnitude, which were excluded.
+    CoordinateOutlier,
+}
+
+impl std::fmt::Display for WarningCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            WarningCategory::SkippedFeature => "skipped_feature",
+            WarningCategory::DegenerateGeometry => "degenerate_geometry",
+            WarningCategory::NonLineString => "non_line_string",
+            WarningCategory::CrsOverride => "crs_override",
+            WarningCategory::ImplausibleCrs => "implausible_crs",
+            WarningCategory::LowOverlap => "low_overlap",
+            WarningCategory::CoordinateOutlier => "coordinate_outlier",
+        })
+    }
+}
+
+/// The warnings of one category recorded by a `WarningCollector`.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct WarningSummary {
+    pub category: WarningCategory,
+    /// The number of affected items, e.g. skipped features, summed over all recorded warnings.
+    pub count: usize,
+    /// The message of the first recorded warning.
+    pub sample_message: String,
+}
+
+/// Collects the warnings of a run by category, so that they can be summarized at its end instead of being scattered
+/// across the log. Clones share the same warnings, so a collector can be handed to several threads.
+#[derive(Clone, Debug, Default)]
+pub struct WarningCollector {
+    warnings: Arc<Mutex<BTreeMap<WarningCategory, WarningSummary>>>,
+}
+
+impl WarningCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a warning affecting `count` items. The message is not logged, see `warn`.
+    pub fn record(&self, category: WarningCategory, count: usize, message: impl Into<String>) {
+        let mut warnings = self.warnings.lock().unwrap();
+        warnings
+            .entry(category)
+            .or_insert_with(|| WarningSummary {
+                category,
+                count: 0,
+                sample_message: message.into(),
+            })
+            .count += count;
+    }
+
+    /// The recorded warnings, one per category.
+    pub fn summary(&self) -> Vec<WarningSummary> {
+        self.warnings.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.lock().unwrap().is_empty()
+    }
+
+    /// Log the recorded warnings as a table with one line per category.
+    pub fn log_summary(&self) {
+        let summary = self.summary();
+        if summary.is_empty() {
+            return;
+        }
+        let category_width = summary
+            .iter()
+            .map(|warning| warning.category.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("category".len());
+        let mut table = format!(
+            "{:<category_width$}  {:>8}  first message",
+            "category", "count"
+        );
+        for warning in summary {
+            table.push_str(&format!(
+                "\n{:<category_width$}  {:>8}  {}",
+                warning.category.to_string(),
+                warning.count,
+                warning.sample_message
+            ));
+        }
+        log::warn!("Warning summary:\n{}", table);
+    }
+}
+
+/// Log a warning affecting `count` items, and record it in `warnings` if given.
+pub fn warn(
+    warnings: Option<&WarningCollector>,
+    category: WarningCategory,
+    count: usize,
+    message: impl Into<String>,
+) {
+    let message = message.into();
+    log::warn!("{}", message);
+    if let Some(warnings) = warnings {
+        warnings.record(category, count, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{warn, WarningCategory, WarningCollector};
+
+    #[test]
+    fn test_warning_collector_aggregates_by_category() {
+        let warnings = WarningCollector::new();
+        let handle = warnings.clone();
+        std::thread::spawn(move || {
+            warn(
+                Some(&handle),
+                WarningCategory::SkippedFeature,
+                2,
+                "Skipped 2 features",
+            )
+        })
+        .join()
+        .unwrap();
+        warn(
+            Some(&warnings),
+            WarningCategory::DegenerateGeometry,
+            1,
+            "Dropped 1 line",
+        );
+        warn(
+            Some(&warnings),
+            WarningCategory::SkippedFeature,
+            3,
+            "Skipped 3 features",
+        );
+        warn(None, WarningCategory::LowOverlap, 1, "Only logged");
+
+        let summary = warnings.summary();
+        assert_eq!(2, summary.len());
+        assert_eq!(WarningCategory::SkippedFeature, summary[0].category);
+        assert_eq!(5, summary[0].count);
+        assert_eq!("Skipped 2 features", summary[0].sample_message);
+        assert_eq!(WarningCategory::DegenerateGeometry, summary[1].category);
+        assert_eq!(1, summary[1].count);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::util::telemetry::StageMetrics;
+
+/// Boxed error of another library, kept as the source of an `Error`.
+pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Error of the library functions of this crate, e.g. `calculate_topo`, the graph builders, and the geofile and OSM
+/// readers and writers. The command line interface converts it to `anyhow::Error` for display.
+///
+/// Context added with `ResultExt` wraps the error in `Error::Context`, use `Error::root` to match on the kind of
+/// the underlying error.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// A configuration value or parameter is invalid.
+    #[error("Invalid config: {0}")]
+    ConfigError(String),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[cfg(feature = "gdal-io")]
+    #[error(transparent)]
+    GdalError(#[from] gdal::errors::GdalError),
+    /// Creating or applying a PROJ transformation failed.
+    #[error(transparent)]
+    ProjError(BoxedError),
+    /// Downloading OSM data failed, e.g. because the Overpass API is unreachable or returned an error.
+    #[error("{message}")]
+    OsmDownloadError {
+        message: String,
+        #[source]
+        source: Option<reqwest::Error>,
+    },
+    /// OSM data could not be parsed.
+    #[error("{message}")]
+    OsmParseError {
+        message: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+    /// A graph has no nodes or edges, but some are required.
+    #[error("{0}")]
+    EmptyGraph(String),
+    /// Graphs are not in the same CRS, or a graph is not in a CRS which the operation supports.
+    #[error("{0}")]
+    CrsMismatch(String),
+    /// The computation was cancelled via its CancellationToken.
+    #[error("TOPO calculation cancelled during stage '{stage}'")]
+    Cancelled {
+        /// The stage which was running when the cancellation was noticed.
+        stage: &'static str,
+        /// Metrics of the stages which completed before the cancellation.
+        completed_stages: Vec<StageMetrics>,
+    },
+    /// Input data which cannot be processed, e.g. a malformed file or an invalid geometry.
+    #[error("{message}")]
+    InvalidData {
+        message: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+    /// What was being done when the `source` error occurred.
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+/// Result of the library functions of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn invalid_data(message: impl Into<String>) -> Self {
+        Error::InvalidData {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// The messages of the error and of its sources, separated by ": ", like `{:#}` of `anyhow::Error`.
+    pub fn display_chain(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+
+    /// The error below all `Error::Context` layers.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::OsmDownloadError {
+            message: "Requesting the Overpass API failed".to_string(),
+            source: Some(error),
+        }
+    }
+}
+
+impl From<osm_xml::error::Error> for Error {
+    fn from(error: osm_xml::error::Error) -> Self {
+        Error::OsmParseError {
+            message: "Parsing the OSM XML failed".to_string(),
+            source: Some(Box::new(error)),
+        }
+    }
+}
+
+#[cfg(feature = "gdal-io")]
+impl From<proj::ProjError> for Error {
+    fn from(error: proj::ProjError) -> Self {
+        Error::ProjError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "gdal-io")]
+impl From<proj::ProjCreateError> for Error {
+    fn from(error: proj::ProjCreateError) -> Self {
+        Error::ProjError(Box::new(error))
+    }
+}
+
+/// Implement the conversion of errors of data formats to `Error::InvalidData`, with the given message.
+macro_rules! invalid_data_from {
+    ($($error:ty => $message:literal),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::InvalidData {
+                        message: $message.to_string(),
+                        source: Some(Box::new(error)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+invalid_data_from!(
+    std::num::ParseIntError => "Invalid integer",
+    std::num::ParseFloatError => "Invalid number",
+    chrono::ParseError => "Invalid date",
+    serde_json::Error => "Invalid JSON",
+    serde_yaml::Error => "Invalid YAML",
+    bincode::Error => "Invalid binary data",
+    csv::Error => "Invalid CSV",
+    geojson::Error => "Invalid GeoJSON",
+    geohash::GeohashError => "Invalid coordinate for a geohash",
+    kdtree::ErrorKind => "Invalid coordinate for the k-d tree",
+);
+
+/// Adding context to errors which convert to `Error`, like `anyhow::Context`.
+pub trait ResultExt<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|error| Error::Context {
+            context: context.into(),
+            source: Box::new(error.into()),
+        })
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|error| Error::Context {
+            context: context().into(),
+            source: Box::new(error.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ResultExt};
+
+    #[test]
+    fn test_context_preserves_source_chain() {
+        let result: std::result::Result<(), std::io::Error> =
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"));
+
+        let error = result
+            .context("Opening the file")
+            .with_context(|| "Reading the config")
+            .unwrap_err();
+
+        assert!(matches!(error.root(), Error::IoError(_)));
+        assert_eq!(
+            "Reading the config: Opening the file: missing",
+            error.display_chain()
+        );
+        let chain: Vec<String> = anyhow::Error::from(error)
+            .chain()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            vec!["Reading the config", "Opening the file", "missing"],
+            chain
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+
+use geo::{Area, BoundingRect, EuclideanLength, Simplify};
+
+use super::feature::Feature;
+
+/// Number of grid cells across the mean width of a polygon when rasterizing it.
+const CELLS_PER_MEAN_WIDTH: f64 = 8.0;
+/// Upper bound of the number of grid cells of one polygon, the cells are made larger for large polygons.
+const MAX_GRID_CELLS: f64 = 4_000_000.0;
+
+/// Approximate the centerlines of the Polygon and MultiPolygon features, e.g. of lane polygons. Other features are
+/// skipped.
+///
+/// See `polygon_centerlines` for how the centerlines are approximated.
+pub fn polygons_to_centerlines(features: &[Feature]) -> Vec<geo::LineString> {
+    features
+        .iter()
+        .flat_map(|feature| feature_polygons(&feature.geometry))
+        .flat_map(polygon_centerlines)
+        .collect()
+}
+
+/// Replace each Polygon and MultiPolygon feature by LineString features of its centerlines, which have the
+/// attributes of the polygon. Other features are kept as they are.
+pub fn replace_polygons_with_centerlines(features: Vec<Feature>) -> Vec<Feature> {
+    let mut num_polygon_features = 0;
+    let features: Vec<Feature> = features
+        .into_iter()
+        .flat_map(|feature| {
+            let polygons = feature_polygons(&feature.geometry);
+            if polygons.is_empty() {
+                return vec![feature];
+            }
+            num_polygon_features += 1;
+            polygons
+                .into_iter()
+                .flat_map(polygon_centerlines)
+                .map(|centerline| Feature {
+                    geometry: geo::Geometry::LineString(centerline),
+                    attributes: feature.attributes.clone(),
+                    null_fields: feature.null_fields.clone(),
+                })
+                .collect()
+        })
+        .collect();
+    log::info!(
+        "Replaced {} polygon features with their centerlines",
+        num_polygon_features
+    );
+    features
+}
+
+fn feature_polygons(geometry: &geo::Geometry) -> Vec<&geo::Polygon> {
+    match geometry {
+        geo::Geometry::Polygon(polygon) => vec![polygon],
+        geo::Geometry::MultiPolygon(multi_polygon) => multi_polygon.0.iter().collect(),
+        _ => vec![],
+    }
+}
+
+/// Approximate the centerlines of a polygon by the skeleton of its rasterization.
+///
+/// The polygon is rasterized into cells of an eighth of its mean width, and thinned to a skeleton one cell wide.
+/// Branches of the skeleton shorter than the mean width, which thinning creates towards the corners of the polygon,
+/// are pruned. The paths of the skeleton between its end points and junctions become the centerlines, so the
+/// centerlines of a polygon are connected, and lie inside it up to the simplification tolerance of half a cell.
+///
+/// Parts of the polygon narrower than about two cells may vanish, and a polygon which is too small to rasterize
+/// has no centerlines.
+pub fn polygon_centerlines(polygon: &geo::Polygon) -> Vec<geo::LineString> {
+    let bounding_rect = match polygon.bounding_rect() {
+        Some(bounding_rect) => bounding_rect,
+        None => return vec![],
+    };
+    let area = polygon.unsigned_area();
+    let perimeter = polygon.exterior().euclidean_length()
+        + polygon
+            .interiors()
+            .iter()
+            .map(|interior| interior.euclidean_length())
+            .sum::<f64>();
+    if !(area > 0.0 && perimeter > 0.0) {
+        return vec![];
+    }
+    // The width of a long thin polygon, e.g. area / length for a rectangle.
+    let mean_width = 2.0 * area / perimeter;
+    let mut cell_size = mean_width / CELLS_PER_MEAN_WIDTH;
+    let bounding_rect_area = bounding_rect.width() * bounding_rect.height();
+    if bounding_rect_area / cell_size.powi(2) > MAX_GRID_CELLS {
+        cell_size = (bounding_rect_area / MAX_GRID_CELLS).sqrt();
+    }
+
+    let mut grid = Grid::rasterize(polygon, bounding_rect, cell_size);
+    grid.thin();
+    grid.prune_spurs((mean_width / cell_size).ceil() as usize);
+    grid.trace_paths()
+        .into_iter()
+        .map(|path| {
+            let centerline: geo::LineString =
+                path.into_iter().map(|cell| grid.center(cell)).collect();
+            centerline.simplify(&(cell_size / 2.0))
+        })
+        .collect()
+}
+
+/// Column and row of a grid cell.
+type Cell = (usize, usize);
+
+/// A binary raster. The outermost cells are always unset, so every set cell has eight neighbors.
+struct Grid {
+    origin: geo::Coord,
+    cell_size: f64,
+    cols: usize,
+    rows: usize,
+    cells: Vec<bool>,
+}
+
+impl Grid {
+    /// Set the cells whose centers are inside the polygon.
+    fn rasterize(polygon: &geo::Polygon, bounding_rect: geo::Rect, cell_size: f64) -> Self {
+        let origin = geo::Coord {
+            x: bounding_rect.min().x - cell_size,
+            y: bounding_rect.min().y - cell_size,
+        };
+        let cols = (bounding_rect.width() / cell_size).ceil() as usize + 2;
+        let rows = (bounding_rect.height() / cell_size).ceil() as usize + 2;
+        let mut grid = Self {
+            origin,
+            cell_size,
+            cols,
+            rows,
+            cells: vec![false; cols * rows],
+        };
+        // Fill the cells between pairs of crossings of the boundary with the row centers.
+        let rings: Vec<&geo::LineString> = std::iter::once(polygon.exterior())
+            .chain(polygon.interiors())
+            .collect();
+        for row in 1..rows - 1 {
+            let y = grid.center((0, row)).y;
+            let mut crossings: Vec<f64> = rings
+                .iter()
+                .flat_map(|ring| ring.lines())
+                .filter(|line| (line.start.y <= y) != (line.end.y <= y))
+                .map(|line| {
+                    line.start.x
+                        + (y - line.start.y) * (line.end.x - line.start.x)
+                            / (line.end.y - line.start.y)
+                })
+                .collect();
+            crossings.sort_by(f64::total_cmp);
+            for pair in crossings.chunks_exact(2) {
+                let first_col = ((pair[0] - origin.x) / cell_size - 0.5).ceil().max(1.0) as usize;
+                let last_col = ((pair[1] - origin.x) / cell_size - 0.5).floor() as usize;
+                for col in first_col..=last_col.min(cols - 2) {
+                    grid.set((col, row), true);
+                }
+            }
+        }
+        grid
+    }
+
+    fn center(&self, (col, row): Cell) -> geo::Coord {
+        geo::Coord {
+            x: self.origin.x + (col as f64 + 0.5) * self.cell_size,
+            y: self.origin.y + (row as f64 + 0.5) * self.cell_size,
+        }
+    }
+
+    fn get(&self, (col, row): Cell) -> bool {
+        self.cells[row * self.cols + col]
+    }
+
+    fn set(&mut self, (col, row): Cell, value: bool) {
+        self.cells[row * self.cols + col] = value;
+    }
+
+    fn set_cells(&self) -> Vec<Cell> {
+        (1..self.rows - 1)
+            .flat_map(|row| (1..self.cols - 1).map(move |col| (col, row)))
+            .filter(|cell| self.get(*cell))
+            .collect()
+    }
+
+    /// The eight neighbors of a cell, clockwise starting from the one in the -y direction.
+    fn ring((col, row): Cell) -> [Cell; 8] {
+        [
+            (col, row - 1),
+            (col + 1, row - 1),
+            (col + 1, row),
+            (col + 1, row + 1),
+            (col, row + 1),
+            (col - 1, row + 1),
+            (col - 1, row),
+            (col - 1, row - 1),
+        ]
+    }
+
+    /// Thin the set cells to a skeleton one cell wide with the Zhang-Suen algorithm, which preserves connectivity.
+    fn thin(&mut self) {
+        loop {
+            let mut changed = false;
+            for first_pass in [true, false] {
+                let removed: Vec<Cell> = self
+                    .set_cells()
+                    .into_iter()
+                    .filter(|cell| {
+                        let ring = Self::ring(*cell).map(|cell| self.get(cell));
+                        let [p2, _, p4, _, p6, _, p8, _] = ring;
+                        let num_set = ring.iter().filter(|set| **set).count();
+                        let num_transitions = (0..8)
+                            .filter(|idx| !ring[*idx] && ring[(idx + 1) % 8])
+                            .count();
+                        let removable = if first_pass {
+                            !(p4 && p6 && (p2 || p8))
+                        } else {
+                            !(p2 && p8 && (p4 || p6))
+                        };
+                        (2..=6).contains(&num_set) && num_transitions == 1 && removable
+                    })
+                    .collect();
+                changed |= !removed.is_empty();
+                for cell in removed {
+                    self.set(cell, false);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// The neighbors of a set cell on the skeleton. A diagonal neighbor is left out if it is also reachable via a
+    /// set edge-adjacent neighbor, so that staircases of the skeleton do not form spurious junctions.
+    fn neighbors(&self, cell: Cell) -> Vec<Cell> {
+        let ring = Self::ring(cell);
+        (0..8)
+            .filter(|idx| {
+                self.get(ring[*idx])
+                    && (idx % 2 == 0 || !(self.get(ring[idx - 1]) || self.get(ring[(idx + 1) % 8])))
+            })
+            .map(|idx| ring[idx])
+            .collect()
+    }
+
+    /// The paths of the skeleton between cells which are not in the middle of a path, i.e. end points and
+    /// junctions. Cycles without such cells are split into two paths.
+    fn trace_paths(&self) -> Vec<Vec<Cell>> {
+        let neighbors: HashMap<Cell, Vec<Cell>> = self
+            .set_cells()
+            .into_iter()
+            .map(|cell| (cell, self.neighbors(cell)))
+            .collect();
+        let is_path_end = |cell: &Cell| neighbors[cell].len() != 2;
+        let mut visited_links: HashSet<(Cell, Cell)> = HashSet::new();
+        let mut visit_link = |a: Cell, b: Cell| visited_links.insert((a.min(b), a.max(b)));
+
+        // Start from the path ends in a deterministic order, then from the remaining cells, which are on cycles.
+        let mut start_cells: Vec<Cell> = neighbors.keys().copied().collect();
+        start_cells.sort_by_key(|(col, row)| (!is_path_end(&(*col, *row)), *row, *col));
+        let mut paths = Vec::new();
+        for start_cell in start_cells {
+            for next_cell in &neighbors[&start_cell] {
+                if !visit_link(start_cell, *next_cell) {
+                    continue;
+                }
+                let mut path = vec![start_cell, *next_cell];
+                while !is_path_end(path.last().unwrap()) {
+                    let (previous_cell, cell) = (path[path.len() - 2], path[path.len() - 1]);
+                    let next_cell = neighbors[&cell]
+                        .iter()
+                        .copied()
+                        .find(|neighbor| *neighbor != previous_cell)
+                        .unwrap();
+                    if !visit_link(cell, next_cell) {
+                        break;
+                    }
+                    path.push(next_cell);
+                }
+                if is_path_end(&start_cell) || path.len() < 4 {
+                    paths.push(path);
+                } else {
+                    // A cycle, which would be a self-loop edge.
+                    let middle = path.len() / 2;
+                    paths.push(path[middle..].to_vec());
+                    path.truncate(middle + 1);
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    /// Remove the branches of the skeleton from an end point to a junction which are at most `max_len` cells long.
+    /// At junctions where all branches are this short, the two longest are kept.
+    fn prune_spurs(&mut self, max_len: usize) {
+        loop {
+            let paths = self.trace_paths();
+            let degree = |cell: &Cell| self.neighbors(*cell).len();
+            // The spurs at each junction, as the cells to remove.
+            let mut spurs_by_junction: HashMap<Cell, Vec<Vec<Cell>>> = HashMap::new();
+            for mut path in paths {
+                if path.len() > max_len {
+                    continue;
+                }
+                let (first, last) = (degree(&path[0]), degree(path.last().unwrap()));
+                if first >= 3 && last == 1 {
+                    path.reverse();
+                } else if !(first == 1 && last >= 3) {
+                    continue;
+                }
+                let junction = path.pop().unwrap();
+                spurs_by_junction.entry(junction).or_default().push(path);
+            }
+
+            let pruned_cells: Vec<Cell> = spurs_by_junction
+                .into_iter()
+                .flat_map(|(junction, mut spurs)| {
+                    if spurs.len() >= degree(&junction) {
+                        spurs.sort_by_key(|spur| spur.len());
+                        spurs.truncate(spurs.len().saturating_sub(2));
+                    }
+                    spurs.into_iter().flatten()
+                })
+                .collect();
+            if pruned_cells.is_empty() {
+                break;
+            }
+            for cell in pruned_cells {
+                self.set(cell, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use geo::Contains;
+
+    use crate::geofile::feature::{Feature, FieldValue};
+
+    use super::{polygon_centerlines, polygons_to_centerlines, replace_polygons_with_centerlines};
+
+    fn polygon(exterior: Vec<(f64, f64)>) -> geo::Polygon {
+        geo::Polygon::new(exterior.into(), vec![])
+    }
+
+    fn assert_inside(polygon: &geo::Polygon, centerlines: &[geo::LineString]) {
+        for coord in centerlines
+            .iter()
+            .flat_map(|centerline| centerline.coords())
+        {
+            assert!(
+                polygon.contains(coord),
+                "{:?} is outside of the polygon",
+                coord
+            );
+        }
+    }
+
+    #[test]
+    fn test_rectangle_centerline_is_long_axis() {
+        let rectangle = polygon(vec![
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (100.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ]);
+
+        let centerlines = polygons_to_centerlines(&[Feature {
+            geometry: geo::Geometry::Polygon(rectangle.clone()),
+            attributes: None,
+            null_fields: Default::default(),
+        }]);
+
+        assert_eq!(1, centerlines.len());
+        assert_inside(&rectangle, &centerlines);
+        let coords: Vec<&geo::Coord> = centerlines[0].coords().collect();
+        assert!(
+            coords.iter().all(|coord| (coord.y - 5.0).abs() < 1.5),
+            "{:?}",
+            coords
+        );
+        let min_x = coords
+            .iter()
+            .map(|coord| coord.x)
+            .fold(f64::INFINITY, f64::min);
+        let max_x = coords
+            .iter()
+            .map(|coord| coord.x)
+            .fold(f64::NEG_INFINITY, f64::max);
+        assert!(min_x < 15.0 && max_x > 85.0, "{:?}", coords);
+    }
+
+    #[test]
+    fn test_l_shape_centerlines_are_connected() {
+        let l_shape = polygon(vec![
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (100.0, 10.0),
+            (10.0, 10.0),
+            (10.0, 100.0),
+            (0.0, 100.0),
+            (0.0, 0.0),
+        ]);
+
+        let centerlines = polygon_centerlines(&l_shape);
+
+        assert!(!centerlines.is_empty());
+        assert_inside(&l_shape, &centerlines);
+        // Each centerline shares an endpoint with another one, unless it is the only one.
+        let endpoints: Vec<[geo::Coord; 2]> = centerlines
+            .iter()
+            .map(|centerline| [centerline.0[0], *centerline.0.last().unwrap()])
+            .collect();
+        for (idx, [start, end]) in endpoints.iter().enumerate() {
+            assert!(
+                endpoints.len() == 1
+                    || endpoints.iter().enumerate().any(|(other_idx, other)| {
+                        other_idx != idx && (other.contains(start) || other.contains(end))
+                    })
+            );
+        }
+        // Both arms are reached.
+        let coords: Vec<&geo::Coord> = centerlines.iter().flat_map(|line| line.coords()).collect();
+        assert!(coords.iter().any(|coord| coord.x > 85.0 && coord.y < 10.0));
+        assert!(coords.iter().any(|coord| coord.y > 85.0 && coord.x < 10.0));
+    }
+
+    #[test]
+    fn test_replace_polygons_with_centerlines_keeps_attributes() {
+        let attributes = Some(HashMap::from([(
+            "lanes".to_string(),
+            FieldValue::IntegerValue(2),
+        )]));
+        let line = geo::Geometry::LineString(vec![(0.0, 20.0), (100.0, 20.0)].into());
+        let features = vec![
+            Feature {
+                geometry: geo::Geometry::Polygon(polygon(vec![
+                    (0.0, 0.0),
+                    (100.0, 0.0),
+                    (100.0, 10.0),
+                    (0.0, 10.0),
+                    (0.0, 0.0),
+                ])),
+                attributes: attributes.clone(),
+                null_fields: Default::default(),
+            },
+            Feature {
+                geometry: line.clone(),
+                attributes: None,
+                null_fields: Default::default(),
+            },
+        ];
+
+        let features = replace_polygons_with_centerlines(features);
+
+        assert_eq!(2, features.len());
+        assert!(matches!(features[0].geometry, geo::Geometry::LineString(_)));
+        assert_eq!(attributes, features[0].attributes);
+        assert_eq!(line, features[1].geometry);
+    }
+}
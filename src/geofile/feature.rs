@@ -1,11 +1,70 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-pub type FeatureMap = HashMap<String, gdal::vector::FieldValue>;
+pub type FeatureMap = HashMap<String, FieldValue>;
 
+/// The value of an attribute of a feature. Mirrors the field values of GDAL, to which it is converted when reading and
+/// writing geofiles with GDAL.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(deprecated)] // gdal represents dates with chrono's deprecated Date type.
+pub enum FieldValue {
+    IntegerValue(i32),
+    IntegerListValue(Vec<i32>),
+    Integer64Value(i64),
+    Integer64ListValue(Vec<i64>),
+    StringValue(String),
+    StringListValue(Vec<String>),
+    RealValue(f64),
+    RealListValue(Vec<f64>),
+    DateValue(chrono::Date<chrono::FixedOffset>),
+    DateTimeValue(chrono::DateTime<chrono::FixedOffset>),
+}
+
+impl FieldValue {
+    /// The value as `String`, `None` if it is not a string.
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            FieldValue::StringValue(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// A geometry with optional attributes, as read from or written to a geofile.
+///
+/// Coordinates follow the traditional GIS axis order regardless of the CRS authority's definition: x is the
+/// easting/longitude, y is the northing/latitude.
 #[derive(Debug, PartialEq)]
 pub struct Feature {
     pub geometry: geo::Geometry,
     pub attributes: Option<FeatureMap>,
+    /// Names of the attributes whose value is NULL. Unlike attributes which the feature does not have, they are
+    /// written as NULL values, e.g. as `null` properties in GeoJSON. See `Feature::attribute`.
+    pub null_fields: HashSet<String>,
+}
+
+/// The value of an attribute of a feature, which tells NULL values apart from attributes the feature does not have.
+#[derive(Debug, PartialEq)]
+pub enum AttributeValue<'a> {
+    Value(&'a FieldValue),
+    /// The feature has the attribute, but its value is NULL.
+    Null,
+    /// The feature does not have the attribute.
+    Absent,
+}
+
+impl Feature {
+    /// The value of the attribute `name`, for filters which must treat NULL values and absent attributes differently.
+    pub fn attribute(&self, name: &str) -> AttributeValue<'_> {
+        match self
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.get(name))
+        {
+            Some(value) => AttributeValue::Value(value),
+            None if self.null_fields.contains(name) => AttributeValue::Null,
+            None => AttributeValue::Absent,
+        }
+    }
 }
 
 impl From<geo::Geometry> for Feature {
@@ -13,6 +72,7 @@ impl From<geo::Geometry> for Feature {
         Self {
             geometry: value,
             attributes: None,
+            null_fields: HashSet::new(),
         }
     }
 }
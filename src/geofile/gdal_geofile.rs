@@ -1,14 +1,24 @@
-use anyhow::{anyhow, Context};
-use gdal::vector::FieldValue;
-use gdal::vector::LayerAccess;
-use indicatif::ProgressBar;
+use crate::error::{Error, Result, ResultExt};
+use gdal::vector::{LayerAccess, LayerCaps};
 use rayon::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
     path::Path,
 };
 
-use super::feature::Feature;
+use crate::{
+    crs::{
+        crs_utils::{epsg_4326, proj_definition},
+        Crs,
+    },
+    diagnostics::warnings::{warn, WarningCategory, WarningCollector},
+    util::progress::ProgressSink,
+};
+
+use super::{
+    feature::{Feature, FeatureMap, FieldValue},
+    geojson::is_gzip_path,
+};
 
 pub enum GdalDriverType {
     GeoPackage,
@@ -24,191 +34,1397 @@ impl GdalDriverType {
     }
 }
 
+/// Options for `write_features_to_geofile`.
+#[derive(Default)]
+pub struct WriteOptions<'a> {
+    strict: bool,
+    progress: Option<&'a dyn ProgressSink>,
+    dataset_creation_options: Vec<String>,
+    layer_creation_options: Vec<String>,
+    typed_fields: bool,
+}
+
+impl<'a> WriteOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail on any lossy conversion of the features, instead of warning and counting it in the `WriteReport`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Report the number of written features to `progress`.
+    pub fn progress(mut self, progress: &'a dyn ProgressSink) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Driver specific dataset creation options as "KEY=VALUE" strings.
+    pub fn dataset_creation_options(mut self, options: Vec<String>) -> Self {
+        self.dataset_creation_options = options;
+        self
+    }
+
+    /// Driver specific layer creation options as "KEY=VALUE" strings, e.g. "COORDINATE_PRECISION=7" for GeoJSON.
+    /// They override the defaults of `default_layer_creation_options` with the same key.
+    pub fn layer_creation_options(mut self, options: Vec<String>) -> Self {
+        self.layer_creation_options = options;
+        self
+    }
+
+    /// Create integer and real fields for attributes whose values are all integers or reals, instead of writing all
+    /// attributes to string fields, e.g. so that viewers can style features by them.
+    pub fn typed_fields(mut self, typed_fields: bool) -> Self {
+        self.typed_fields = typed_fields;
+        self
+    }
+}
+
+/// Layer creation options used for a driver unless overridden in `WriteOptions`, e.g. a spatial index for
+/// GeoPackage, without which viewers like QGIS are slow on large layers, and sequential feature ids for GeoJSON, so
+/// that features can be joined across dumps.
+pub fn default_layer_creation_options(driver_name: &str) -> &'static [&'static str] {
+    match driver_name {
+        "GPKG" => &["SPATIAL_INDEX=YES"],
+        "GeoJSON" => &["ID_GENERATE=YES"],
+        _ => &[],
+    }
+}
+
+/// Split "KEY=VALUE" creation options into keys and values, and merge them into the defaults. Keys are compared
+/// case-insensitively like GDAL does, later options override earlier ones.
+fn merge_creation_options(defaults: &[&str], options: &[String]) -> Result<Vec<(String, String)>> {
+    let mut merged: Vec<(String, String)> = Vec::new();
+    for option in defaults
+        .iter()
+        .copied()
+        .chain(options.iter().map(String::as_str))
+    {
+        let (key, value) = option.split_once('=').ok_or_else(|| {
+            Error::ConfigError(format!(
+                "Creation option '{}' is not of the form KEY=VALUE",
+                option
+            ))
+        })?;
+        merged.retain(|(merged_key, _)| !merged_key.eq_ignore_ascii_case(key));
+        merged.push((key.to_string(), value.to_string()));
+    }
+    Ok(merged)
+}
+
+/// What to do with features whose geometry or attributes cannot be decoded while reading a geofile.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnReadError {
+    /// Stop reading and return the error.
+    #[default]
+    Fail,
+    /// Skip the feature, record it in the `ReadReport` and warn once at the end.
+    SkipAndWarn,
+}
+
+/// What to do with line features whose coordinates are outliers by orders of magnitude, e.g. features appended to a
+/// file in another CRS than the one it declares, when loading a graph.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnCoordinateOutliers {
+    /// Load the features like all others.
+    Keep,
+    /// Leave the features out of the graph with a warning listing their FIDs.
+    #[default]
+    Exclude,
+    /// Fail with an error listing their FIDs.
+    Fail,
+}
+
+/// Options for `read_features_from_geofile_with_options` and `for_each_feature_in_geofile_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+    on_error: OnReadError,
+    lenient_coordinate_range: bool,
+    warnings: Option<WarningCollector>,
+    coordinate_precision: Option<f64>,
+    coordinate_outliers: OnCoordinateOutliers,
+}
+
+impl ReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_error(mut self, on_error: OnReadError) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    /// When loading a graph with `GeoFeatureGraph::load_from_geofile_with_options`, only warn instead of failing if
+    /// the coordinates exceed the longitude/latitude range of a geographic CRS.
+    pub fn lenient_coordinate_range(mut self, lenient_coordinate_range: bool) -> Self {
+        self.lenient_coordinate_range = lenient_coordinate_range;
+        self
+    }
+
+    pub fn is_lenient_coordinate_range(&self) -> bool {
+        self.lenient_coordinate_range
+    }
+
+    /// Record the warnings of reading, e.g. about skipped features, in `warnings` in addition to logging them.
+    pub fn warnings(mut self, warnings: WarningCollector) -> Self {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    pub fn warning_collector(&self) -> Option<&WarningCollector> {
+        self.warnings.as_ref()
+    }
+
+    /// When loading a graph with `GeoFeatureGraph::load_from_geofile_with_options`, quantize the coordinates to a
+    /// grid with this spacing, in units of the CRS of the graph, see `GeoGraphBuilder::coordinate_precision`.
+    pub fn coordinate_precision(mut self, coordinate_precision: Option<f64>) -> Self {
+        self.coordinate_precision = coordinate_precision;
+        self
+    }
+
+    pub fn get_coordinate_precision(&self) -> Option<f64> {
+        self.coordinate_precision
+    }
+
+    /// When loading a graph with `GeoFeatureGraph::load_from_geofile_with_options`, how to handle line features
+    /// whose coordinates are outliers by orders of magnitude. Excluded by default.
+    pub fn coordinate_outliers(mut self, coordinate_outliers: OnCoordinateOutliers) -> Self {
+        self.coordinate_outliers = coordinate_outliers;
+        self
+    }
+
+    pub fn get_coordinate_outliers(&self) -> OnCoordinateOutliers {
+        self.coordinate_outliers
+    }
+}
+
+/// A feature which was skipped because it could not be decoded.
+#[derive(Debug, PartialEq)]
+pub struct SkippedFeature {
+    /// The FID of the feature, if the driver assigns one.
+    pub fid: Option<u64>,
+    pub error: String,
+}
+
+/// The features skipped while reading a geofile.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReadReport {
+    pub skipped_features: Vec<SkippedFeature>,
+}
+
+impl ReadReport {
+    pub fn is_complete(&self) -> bool {
+        self.skipped_features.is_empty()
+    }
+}
+
+/// Counts of the lossy conversions made while writing features to a geofile.
+#[derive(Debug, Default, PartialEq)]
+pub struct WriteReport {
+    /// Non-string scalar values written to string fields.
+    pub type_fallbacks: usize,
+    /// List values flattened into string fields.
+    pub list_flattenings: usize,
+    /// Fields whose names were truncated or otherwise changed by the driver.
+    pub renamed_fields: usize,
+    /// Single geometries promoted to the multi geometry type of the layer.
+    pub promoted_geometries: usize,
+}
+
+impl WriteReport {
+    pub fn is_lossless(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Add the counts of another report.
+    fn add(&mut self, other: &WriteReport) {
+        self.type_fallbacks += other.type_fallbacks;
+        self.list_flattenings += other.list_flattenings;
+        self.renamed_fields += other.renamed_fields;
+        self.promoted_geometries += other.promoted_geometries;
+    }
+}
+
 /// Write features to a geofile.
 ///
+/// Lossy conversions are warned about and counted in the returned report, or are an error in strict mode:
+/// * All attributes are written as strings, other values fall back to their string representation, and lists are
+///   flattened into one string. With `WriteOptions::typed_fields`, numeric attributes get numeric fields instead.
+/// * Drivers may truncate or rename fields, e.g. shapefile field names are limited to 10 characters.
+/// * If features have both single and multi geometries of the same kind, the single ones are promoted to multi
+///   geometries. Features of different kinds of geometries cannot be written to one file.
+///
 /// # Arguments
-/// * features - The features to write. NOTE: all features will be written as string regardless of their type.
-/// * crs - The CRS to set for the geofile. Defaults to EPSG:4326 if None.
+/// * features - The features to write.
+/// * crs - The CRS to set for the geofile. Defaults to EPSG:4326 if None. Geometries are always interpreted in
+///   traditional GIS axis order (x=easting/longitude), regardless of the axis mapping strategy of `crs`.
 /// * driver - Name of the GDAL driver to use. GdalDriverType has some options.
+/// * options - Strictness, progress reporting and creation options, see `WriteOptions`.
 pub fn write_features_to_geofile(
     features: &Vec<Feature>,
     output_filepath: &Path,
-    crs: Option<&gdal::spatial_ref::SpatialRef>,
+    crs: Option<&Crs>,
     // TODO make driver optional and attempt to derive it from extension
     driver: &str,
-) -> anyhow::Result<()> {
+    options: &WriteOptions,
+) -> Result<WriteReport> {
+    write_layers_to_geofile(&[("", features)], output_filepath, crs, driver, options)
+}
+
+/// Write features to named layers of one geofile, for drivers which support several layers, e.g. GeoPackage. Like
+/// `write_features_to_geofile`, which writes a single unnamed layer. Layers without features are not created, and
+/// no file is created if no layer has features.
+///
+/// # Returns
+/// The lossy conversions of all layers.
+pub fn write_layers_to_geofile(
+    layers: &[(&str, &Vec<Feature>)],
+    output_filepath: &Path,
+    crs: Option<&Crs>,
+    driver: &str,
+    options: &WriteOptions,
+) -> Result<WriteReport> {
     let driver = gdal::DriverManager::get_driver_by_name(driver).context("Getting GDAL driver")?;
 
-    if features.is_empty() {
-        return Ok(());
+    let mut report = WriteReport::default();
+    if layers.iter().all(|(_, features)| features.is_empty()) {
+        return Ok(report);
     }
-    let layer_type = {
-        use gdal::vector::OGRwkbGeometryType::*;
-        let geometry = &features.iter().nth(0).unwrap().geometry;
-        // TODO verify that all features have the same geometry type up front.
-        match geometry {
-            geo::Geometry::Point(_) => wkbPoint,
-            geo::Geometry::LineString(_) => wkbLineString,
-            geo::Geometry::Polygon(_) => wkbPolygon,
-            geo::Geometry::MultiPoint(_) => wkbMultiPoint,
-            geo::Geometry::MultiLineString(_) => wkbMultiLineString,
-            geo::Geometry::MultiPolygon(_) => wkbMultiPolygon,
-            _ => {
-                return Err(anyhow!("Cannot write geometry type {:?} to file.", {
-                    geometry
-                }))
-            }
-        }
-    };
 
-    let crs = match crs {
-        Some(crs) => crs.clone(),
-        None => get_default_spatial_ref(),
-    };
+    let crs = crs.cloned().unwrap_or_else(epsg_4326).to_spatial_ref()?;
     let crs_name = crs.name()?;
     log::debug!("Using spatial ref {} for writing geofile", crs_name);
 
-    let mut dataset = driver.create_vector_only(output_filepath)?;
+    let dataset_creation_options = merge_creation_options(&[], &options.dataset_creation_options)?;
+    let layer_creation_options = merge_creation_options(
+        default_layer_creation_options(&driver.short_name()),
+        &options.layer_creation_options,
+    )?;
+    let dataset_creation_options: Vec<gdal::raster::RasterCreationOption> =
+        dataset_creation_options
+            .iter()
+            .map(|(key, value)| gdal::raster::RasterCreationOption { key, value })
+            .collect();
+    let layer_creation_options: Vec<String> = layer_creation_options
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let layer_creation_options: Vec<&str> =
+        layer_creation_options.iter().map(String::as_str).collect();
+
+    let mut dataset = driver.create_with_band_type_with_options::<u8, _>(
+        output_filepath,
+        0,
+        0,
+        0,
+        &dataset_creation_options,
+    )?;
+    for (layer_name, features) in layers {
+        if features.is_empty() {
+            continue;
+        }
+        log::info!(
+            "Writing {} features to {:?}",
+            features.len(),
+            output_filepath
+        );
+        write_layer(
+            &mut dataset,
+            &driver.short_name(),
+            layer_name,
+            features,
+            &crs,
+            &layer_creation_options,
+            options,
+            &mut report,
+        )?;
+    }
+    Ok(report)
+}
+
+/// Create a layer in `dataset` with fields for the attributes of the features, and write the features to it.
+#[allow(clippy::too_many_arguments)]
+fn write_layer(
+    dataset: &mut gdal::Dataset,
+    driver_name: &str,
+    layer_name: &str,
+    features: &Vec<Feature>,
+    crs: &gdal::spatial_ref::SpatialRef,
+    layer_creation_options: &[&str],
+    options: &WriteOptions,
+    report: &mut WriteReport,
+) -> Result<()> {
+    let (layer_type, multi_layer) = get_layer_geometry_type(features)?;
+    let field_names = get_field_names(features);
+    let field_types: HashMap<&str, gdal::vector::OGRFieldType::Type> = field_names
+        .iter()
+        .map(|name| {
+            let field_type = if options.typed_fields {
+                get_numeric_field_type(features, name)
+                    .unwrap_or(gdal::vector::OGRFieldType::OFTString)
+            } else {
+                gdal::vector::OGRFieldType::OFTString
+            };
+            (name as &str, field_type)
+        })
+        .collect();
+    let mut layer_report = WriteReport::default();
+    count_attribute_conversions(features, &field_types, options.strict, &mut layer_report)?;
+    count_geometry_promotions(features, multi_layer, options.strict, &mut layer_report)?;
+
     let layer_options = gdal::LayerOptions {
-        name: "",
-        srs: Some(&crs),
+        name: layer_name,
+        srs: Some(crs),
         ty: layer_type,
-        options: None,
+        options: Some(layer_creation_options),
     };
-
-    let mut layer = dataset.create_layer(layer_options)?;
+    let layer_idx = dataset.layer_count();
+    let layer = dataset.create_layer(layer_options)?;
 
     // Create the fields based on all attributes of all features.
     log::info!("Setting up fields");
-    let field_names = get_field_names(features);
     let field_definitions: Vec<(&str, gdal::vector::OGRFieldType::Type)> = field_names
         .iter()
-        .map(|field_name| (field_name as &str, gdal::vector::OGRFieldType::OFTString))
+        .map(|field_name| (field_name as &str, field_types[field_name as &str]))
         .collect();
     layer.create_defn_fields(&field_definitions)?;
+    let layer_field_names: HashMap<&str, String> = field_names
+        .iter()
+        .map(|name| name as &str)
+        .zip(layer.defn().fields().map(|field| field.name()))
+        .collect();
+    for field_name in &field_names {
+        let layer_field_name = layer_field_names.get(field_name as &str).ok_or_else(|| {
+            Error::invalid_data(format!(
+                "The {} driver did not create field '{}'",
+                driver_name, field_name
+            ))
+        })?;
+        if layer_field_name != field_name {
+            let feature_idx = features
+                .iter()
+                .position(|feature| {
+                    matches!(&feature.attributes, Some(attributes) if attributes.contains_key(field_name))
+                })
+                .unwrap_or(0);
+            if options.strict {
+                return Err(Error::invalid_data(format!(
+                    "Feature {}: field '{}' would be renamed to '{}' by the {} driver",
+                    feature_idx, field_name, layer_field_name, driver_name
+                )));
+            }
+            layer_report.renamed_fields += 1;
+        }
+    }
+    log_lossy_conversions(&layer_report);
+    report.add(&layer_report);
 
-    log::info!(
-        "Writing {} features to {:?}",
-        features.len(),
-        output_filepath
-    );
-    unsafe {
-        // Start a transaction in case the driver supports transactions, e.g. GeoPackage.
-        // Committing all features once as opposed to per-feature is a massive speedup for these drivers.
-        gdal_sys::OGR_L_StartTransaction(layer.c_layer());
-    };
-    let bar = ProgressBar::new(features.len() as u64);
+    // Committing all features at once as opposed to per feature is a massive speedup for drivers which support
+    // transactions, e.g. GeoPackage.
+    if layer.has_capability(LayerCaps::OLCTransactions) {
+        let transaction = dataset.start_transaction()?;
+        write_features_to_layer(
+            features,
+            &mut transaction.layer(layer_idx)?,
+            &layer_field_names,
+            multi_layer,
+            options,
+        )?;
+        transaction.commit()?;
+    } else {
+        log::info!(
+            "The {} driver does not support transactions, writing features one by one",
+            driver_name
+        );
+        write_features_to_layer(
+            features,
+            &mut dataset.layer(layer_idx)?,
+            &layer_field_names,
+            multi_layer,
+            options,
+        )?;
+    }
+    Ok(())
+}
+
+/// The numeric field type which holds all values of an attribute without conversion, `None` if some value is not
+/// numeric. Features without the attribute are left out, they get NULL values.
+fn get_numeric_field_type(
+    features: &[Feature],
+    name: &str,
+) -> Option<gdal::vector::OGRFieldType::Type> {
+    use gdal::vector::OGRFieldType::*;
+    // Integers are widened to 64 bit integers, and both to reals, so the widest type of the values is used.
+    const WIDENING_ORDER: [gdal::vector::OGRFieldType::Type; 3] =
+        [OFTInteger, OFTInteger64, OFTReal];
+    let mut widest = None;
+    for value in features
+        .iter()
+        .filter_map(|feature| feature.attributes.as_ref()?.get(name))
+    {
+        let rank = match value {
+            FieldValue::IntegerValue(_) => 0,
+            FieldValue::Integer64Value(_) => 1,
+            FieldValue::RealValue(_) => 2,
+            _ => return None,
+        };
+        widest = widest.max(Some(rank));
+    }
+    widest.map(|rank| WIDENING_ORDER[rank])
+}
+
+/// Write features to a layer whose fields were created from the attributes of the features.
+///
+/// # Arguments
+/// * layer_field_names - The names of the fields of the layer by the attribute names of the features.
+/// * multi_layer - Whether the layer has a multi geometry type, to which single geometries must be promoted.
+fn write_features_to_layer(
+    features: &[Feature],
+    layer: &mut gdal::vector::Layer,
+    layer_field_names: &HashMap<&str, String>,
+    multi_layer: bool,
+    options: &WriteOptions,
+) -> Result<()> {
+    if let Some(progress) = options.progress {
+        progress.begin("write_features", features.len() as u64);
+    }
     for feature in features {
-        let wkb = wkb::geom_to_wkb(&feature.geometry)
-            .or_else(|err| Err(anyhow!("Could not write geometry to WKB, {:?}", err)))?;
+        let geometry = if multi_layer {
+            promote_to_multi_geometry(&feature.geometry)
+        } else {
+            feature.geometry.clone()
+        };
+        let wkb = wkb::geom_to_wkb(&geometry).map_err(|err| {
+            Error::invalid_data(format!("Could not write geometry to WKB, {:?}", err))
+        })?;
         let geometry = gdal::vector::Geometry::from_wkb(&wkb)?;
 
         match &feature.attributes {
+            _ if !feature.null_fields.is_empty() => {
+                write_feature_with_nulls(feature, geometry, layer, layer_field_names)?;
+            }
             Some(attributes) => {
                 let mut field_names = Vec::new();
                 let mut values = Vec::new();
                 for (key, value) in attributes {
-                    field_names.push(key);
-                    values.push(value.to_owned())
+                    field_names.push(layer_field_names[key as &str].as_str());
+                    values.push(gdal::vector::FieldValue::from(value))
                 }
-                let field_names: Vec<&str> = field_names.iter().map(|name| name as &str).collect();
                 layer.create_feature_fields(geometry, &field_names, &values)?;
             }
             None => layer.create_feature(geometry)?,
         }
 
-        bar.inc(1);
+        if let Some(progress) = options.progress {
+            progress.advance(1);
+        }
+    }
+    if let Some(progress) = options.progress {
+        progress.end();
+    }
+    Ok(())
+}
+
+/// Write a feature with NULL attributes, which `Layer::create_feature_fields` cannot set, to a layer.
+fn write_feature_with_nulls(
+    feature: &Feature,
+    geometry: gdal::vector::Geometry,
+    layer: &gdal::vector::Layer,
+    layer_field_names: &HashMap<&str, String>,
+) -> Result<()> {
+    let defn = layer.defn();
+    let mut gdal_feature = gdal::vector::Feature::new(defn)?;
+    gdal_feature.set_geometry(geometry)?;
+    for (key, value) in feature.attributes.iter().flatten() {
+        gdal_feature.set_field(
+            &layer_field_names[key as &str],
+            &gdal::vector::FieldValue::from(value),
+        )?;
     }
-    unsafe {
-        // Start a transaction in case the driver supports transactions.
-        gdal_sys::OGR_L_CommitTransaction(layer.c_layer());
+    // Values take precedence over NULLs, like in `Feature::attribute`.
+    for name in feature.null_fields.iter().filter(
+        |name| !matches!(&feature.attributes, Some(attributes) if attributes.contains_key(*name)),
+    ) {
+        let layer_field_name = &layer_field_names[name as &str];
+        let field_idx = defn
+            .fields()
+            .position(|field| &field.name() == layer_field_name)
+            .ok_or_else(|| {
+                Error::invalid_data(format!("The layer has no field '{}'", layer_field_name))
+            })?;
+        unsafe { gdal_sys::OGR_F_SetFieldNull(gdal_feature.c_feature(), field_idx as i32) };
+    }
+    gdal_feature.create(layer)?;
+    Ok(())
+}
+
+/// Determine the geometry type of a layer holding all features.
+///
+/// # Returns
+/// The layer geometry type, and whether it is a multi geometry type to which single geometries must be promoted.
+fn get_layer_geometry_type(
+    features: &[Feature],
+) -> Result<(gdal::vector::OGRwkbGeometryType::Type, bool)> {
+    use gdal::vector::OGRwkbGeometryType::*;
+    let mut kinds = HashSet::new();
+    let mut has_single = false;
+    let mut has_multi = false;
+    for geometry in features.iter().map(|feature| &feature.geometry) {
+        let (kind, is_multi) = match geometry {
+            geo::Geometry::Point(_) => ("Point", false),
+            geo::Geometry::LineString(_) => ("LineString", false),
+            geo::Geometry::Polygon(_) => ("Polygon", false),
+            geo::Geometry::MultiPoint(_) => ("Point", true),
+            geo::Geometry::MultiLineString(_) => ("LineString", true),
+            geo::Geometry::MultiPolygon(_) => ("Polygon", true),
+            _ => {
+                return Err(Error::invalid_data(format!(
+                    "Cannot write geometry type {:?} to file.",
+                    geometry
+                )))
+            }
+        };
+        kinds.insert(kind);
+        has_single |= !is_multi;
+        has_multi |= is_multi;
+    }
+    if kinds.len() > 1 {
+        let mut kinds: Vec<&str> = kinds.into_iter().collect();
+        kinds.sort();
+        return Err(Error::invalid_data(format!(
+            "Cannot write features with different geometry types ({}) to one file.",
+            kinds.join(", ")
+        )));
+    }
+    let layer_type = match (kinds.into_iter().next().unwrap(), has_multi) {
+        ("Point", false) => wkbPoint,
+        ("LineString", false) => wkbLineString,
+        ("Polygon", false) => wkbPolygon,
+        ("Point", true) => wkbMultiPoint,
+        ("LineString", true) => wkbMultiLineString,
+        ("Polygon", true) => wkbMultiPolygon,
+        (kind, _) => unreachable!("Unexpected geometry kind {}", kind),
     };
+    Ok((layer_type, has_multi && has_single))
+}
+
+fn is_single_geometry(geometry: &geo::Geometry) -> bool {
+    matches!(
+        geometry,
+        geo::Geometry::Point(_) | geo::Geometry::LineString(_) | geo::Geometry::Polygon(_)
+    )
+}
+
+/// Wrap a single geometry into a multi geometry of one part. Other geometries are returned as they are.
+fn promote_to_multi_geometry(geometry: &geo::Geometry) -> geo::Geometry {
+    match geometry {
+        geo::Geometry::Point(point) => geo::MultiPoint(vec![*point]).into(),
+        geo::Geometry::LineString(line) => geo::MultiLineString(vec![line.clone()]).into(),
+        geo::Geometry::Polygon(polygon) => geo::MultiPolygon(vec![polygon.clone()]).into(),
+        _ => geometry.clone(),
+    }
+}
+
+/// Count the attribute values which are not written as they are, or fail on the first one in strict mode.
+///
+/// # Arguments
+/// * field_types - The types of the fields by attribute name. Values of attributes with numeric fields are written as
+///   they are.
+fn count_attribute_conversions(
+    features: &[Feature],
+    field_types: &HashMap<&str, gdal::vector::OGRFieldType::Type>,
+    strict: bool,
+    report: &mut WriteReport,
+) -> Result<()> {
+    for (feature_idx, feature) in features.iter().enumerate() {
+        let mut attributes: Vec<(&String, &FieldValue)> =
+            feature.attributes.iter().flatten().collect();
+        attributes.sort_by_key(|(name, _)| *name);
+        for (name, value) in attributes {
+            if field_types.get(name.as_str()) != Some(&gdal::vector::OGRFieldType::OFTString) {
+                continue;
+            }
+            let is_list = match value {
+                FieldValue::StringValue(_) => continue,
+                FieldValue::IntegerListValue(_)
+                | FieldValue::Integer64ListValue(_)
+                | FieldValue::StringListValue(_)
+                | FieldValue::RealListValue(_) => true,
+                _ => false,
+            };
+            if strict {
+                return Err(Error::invalid_data(format!(
+                    "Feature {}: field '{}' holds {:?}, which would be {} a string",
+                    feature_idx,
+                    name,
+                    value,
+                    if is_list {
+                        "flattened into"
+                    } else {
+                        "converted to"
+                    }
+                )));
+            }
+            if is_list {
+                report.list_flattenings += 1;
+            } else {
+                report.type_fallbacks += 1;
+            }
+        }
+    }
     Ok(())
 }
 
-pub fn read_features_from_geofile(
-    filepath: &Path,
-) -> anyhow::Result<(Vec<Feature>, gdal::spatial_ref::SpatialRef)> {
-    gdal::DriverManager::register_all();
-    let mut open_options = gdal::DatasetOptions::default();
-    open_options.open_flags = gdal::GdalOpenFlags::GDAL_OF_VECTOR;
-    let dataset = gdal::Dataset::open_ex(filepath, open_options)?;
-
-    let layer_count = dataset.layer_count();
-    if 0 == layer_count || 1 < layer_count {
-        // Note: in principle any amount of layers could be read in a loop, their features combined into one collection. Implement if necessary.
-        return Err(anyhow!(
-            "Found {} layers, only one layer is supported.",
-            layer_count
-        ));
+/// Count the single geometries which are promoted to multi geometries, or fail on the first one in strict mode.
+fn count_geometry_promotions(
+    features: &[Feature],
+    multi_layer: bool,
+    strict: bool,
+    report: &mut WriteReport,
+) -> Result<()> {
+    if !multi_layer {
+        return Ok(());
     }
-    let mut layer = dataset.layer(0)?;
+    for (feature_idx, feature) in features.iter().enumerate() {
+        if is_single_geometry(&feature.geometry) {
+            if strict {
+                return Err(Error::invalid_data(format!(
+                    "Feature {}: single geometry would be promoted to a multi geometry",
+                    feature_idx
+                )));
+            }
+            report.promoted_geometries += 1;
+        }
+    }
+    Ok(())
+}
 
+fn log_lossy_conversions(report: &WriteReport) {
+    if report.type_fallbacks > 0 {
+        log::warn!(
+            "Writing {} non-string attribute values as strings",
+            report.type_fallbacks
+        );
+    }
+    if report.list_flattenings > 0 {
+        log::warn!(
+            "Flattening {} list attribute values into strings",
+            report.list_flattenings
+        );
+    }
+    if report.renamed_fields > 0 {
+        log::warn!("The driver renamed {} fields", report.renamed_fields);
+    }
+    if report.promoted_geometries > 0 {
+        log::warn!(
+            "Promoting {} single geometries to multi geometries",
+            report.promoted_geometries
+        );
+    }
+}
+
+/// Read all features of a geofile into memory. See `for_each_feature_in_geofile` for reading large files.
+pub fn read_features_from_geofile(filepath: &Path) -> Result<(Vec<Feature>, Crs)> {
+    let (features, crs, _) =
+        read_features_from_geofile_with_options(filepath, &ReadOptions::default())?;
+    Ok((features, crs))
+}
+
+/// Read all features of a geofile into memory, handling undecodable features as set in `options`.
+///
+/// # Returns
+/// The features, the CRS of the geofile, and the features which were skipped.
+pub fn read_features_from_geofile_with_options(
+    filepath: &Path,
+    options: &ReadOptions,
+) -> Result<(Vec<Feature>, Crs, ReadReport)> {
     let mut features = Vec::new();
-    features.reserve(layer.feature_count() as usize);
+    let (crs, report) =
+        for_each_feature_in_geofile_with_options(filepath, None, options, |feature| {
+            features.push(feature);
+            Ok(())
+        })?;
+    Ok((features, crs.unwrap_or_else(epsg_4326), report))
+}
+
+/// Stream the features of a geofile layer into `callback` one by one, without collecting them into memory.
+///
+/// # Arguments
+/// * filepath - The geofile to read.
+/// * layer_name - Name of the layer to read. If None, the geofile must have exactly one layer, which is read.
+/// * callback - Called with each feature in order. Reading stops at the first error returned by the callback.
+///
+/// # Returns
+/// The CRS of the layer, None if the layer does not declare one.
+pub fn for_each_feature_in_geofile(
+    filepath: &Path,
+    layer_name: Option<&str>,
+    callback: impl FnMut(Feature) -> Result<()>,
+) -> Result<Option<Crs>> {
+    let (crs, _) = for_each_feature_in_geofile_with_options(
+        filepath,
+        layer_name,
+        &ReadOptions::default(),
+        callback,
+    )?;
+    Ok(crs)
+}
+
+/// Like `for_each_feature_in_geofile`, but features whose geometry or attributes cannot be decoded are handled as
+/// set in `options`. Errors returned by `callback` always stop reading.
+///
+/// # Returns
+/// The CRS of the layer, None if the layer does not declare one, and the features which were skipped.
+pub fn for_each_feature_in_geofile_with_options(
+    filepath: &Path,
+    layer_name: Option<&str>,
+    options: &ReadOptions,
+    mut callback: impl FnMut(Feature) -> Result<()>,
+) -> Result<(Option<Crs>, ReadReport)> {
+    for_each_feature_with_fid_in_geofile_with_options(
+        filepath,
+        layer_name,
+        options,
+        |_, feature| callback(feature),
+    )
+}
+
+/// Like `for_each_feature_in_geofile_with_options`, but `callback` is also given the FID of each feature, if the
+/// driver assigns one.
+pub fn for_each_feature_with_fid_in_geofile_with_options(
+    filepath: &Path,
+    layer_name: Option<&str>,
+    options: &ReadOptions,
+    mut callback: impl FnMut(Option<u64>, Feature) -> Result<()>,
+) -> Result<(Option<Crs>, ReadReport)> {
+    let dataset = open_vector_dataset(filepath)?;
+    let mut layer = get_layer(&dataset, layer_name)?;
+    let field_names: Vec<String> = layer.defn().fields().map(|field| field.name()).collect();
 
     log::info!("Reading {} features", layer.feature_count());
 
+    let mut report = ReadReport::default();
     for gdal_feature in layer.features() {
-        let attributes: HashMap<String, FieldValue> = gdal_feature
-            .fields()
-            .into_iter()
-            .filter_map(|(field_name, field_value)| {
-                if let Some(value) = field_value {
-                    return Some((field_name, value));
+        let feature = match decode_feature(&gdal_feature, &field_names) {
+            Ok(feature) => feature,
+            Err(err) => match options.on_error {
+                OnReadError::Fail => {
+                    return Err(err).context(format!(
+                        "Decoding feature {:?} of {:?}",
+                        gdal_feature.fid(),
+                        filepath
+                    ))
                 }
-                return None;
-            })
-            .collect();
-        let wkb = gdal_feature.geometry().wkb()?;
-        let geometry = wkb::wkb_to_geom(&mut wkb.as_slice())
-            .or_else(|err| Err(anyhow!("Could not parse geometry from WKB, {:?}", err)))?;
-        let attributes = if attributes.is_empty() {
-            None
-        } else {
-            Some(attributes)
+                OnReadError::SkipAndWarn => {
+                    report.skipped_features.push(SkippedFeature {
+                        fid: gdal_feature.fid(),
+                        error: err.display_chain(),
+                    });
+                    continue;
+                }
+            },
         };
+        callback(gdal_feature.fid(), feature)?;
+    }
 
-        features.push(Feature {
-            geometry: geometry,
-            attributes: attributes,
-        });
+    if let Some(first_skipped) = report.skipped_features.first() {
+        warn(
+            options.warning_collector(),
+            WarningCategory::SkippedFeature,
+            report.skipped_features.len(),
+            format!(
+                "Skipped {} features of {:?} which could not be decoded, the first one with FID {:?}: {}",
+                report.skipped_features.len(),
+                filepath,
+                first_skipped.fid,
+                first_skipped.error
+            ),
+        );
     }
+    Ok((get_layer_crs(&layer)?, report))
+}
 
-    let spatial_ref = layer.spatial_ref().unwrap_or(get_default_spatial_ref());
+/// Decode the geometry and attributes of a GDAL feature. The names of NULL attributes are collected in
+/// `Feature::null_fields`. Unset attributes, e.g. properties which a GeoJSON feature does not have, and attributes of
+/// types which are not supported by gdal are left out.
+///
+/// # Arguments
+/// * field_names - The names of the fields of the layer, in the order of their indices.
+fn decode_feature(gdal_feature: &gdal::vector::Feature, field_names: &[String]) -> Result<Feature> {
+    let (attributes, null_fields) = decode_attributes(gdal_feature, field_names)?;
+    let wkb = gdal_feature
+        .geometry()
+        .wkb()
+        .context("Exporting the geometry to WKB")?;
+    let geometry = wkb::wkb_to_geom(&mut wkb.as_slice()).map_err(|err| {
+        Error::invalid_data(format!("Could not parse geometry from WKB, {:?}", err))
+    })?;
+    let attributes = if attributes.is_empty() {
+        None
+    } else {
+        Some(attributes)
+    };
+
+    Ok(Feature {
+        geometry,
+        attributes,
+        null_fields,
+    })
+}
+
+/// Decode the attributes of a GDAL feature, see `decode_feature`.
+///
+/// # Returns
+/// The attributes which are set and not NULL, and the names of the NULL attributes.
+fn decode_attributes(
+    gdal_feature: &gdal::vector::Feature,
+    field_names: &[String],
+) -> Result<(FeatureMap, HashSet<String>)> {
+    let mut attributes: HashMap<String, FieldValue> = HashMap::new();
+    let mut null_fields = HashSet::new();
+    for (field_idx, field_name) in field_names.iter().enumerate() {
+        if unsafe { gdal_sys::OGR_F_IsFieldSet(gdal_feature.c_feature(), field_idx as i32) } == 0 {
+            continue;
+        }
+        match gdal_feature.field(field_name) {
+            Ok(Some(value)) => {
+                attributes.insert(field_name.clone(), value.into());
+            }
+            Ok(None) => {
+                null_fields.insert(field_name.clone());
+            }
+            Err(gdal::errors::GdalError::UnhandledFieldType { .. }) => {}
+            Err(err) => return Err(err).context(format!("Decoding attribute '{}'", field_name)),
+        }
+    }
+    Ok((attributes, null_fields))
+}
+
+/// The GDAL field type which holds an attribute value without conversion. Lists are not supported.
+fn get_field_type(name: &str, value: &FieldValue) -> Result<gdal::vector::OGRFieldType::Type> {
+    use gdal::vector::OGRFieldType::*;
+    Ok(match value {
+        FieldValue::IntegerValue(_) => OFTInteger,
+        FieldValue::Integer64Value(_) => OFTInteger64,
+        FieldValue::RealValue(_) => OFTReal,
+        FieldValue::StringValue(_) => OFTString,
+        _ => {
+            return Err(Error::invalid_data(format!(
+                "The attribute '{}' with the value {:?} cannot be written to an attribute table",
+                name, value
+            )))
+        }
+    })
+}
 
-    return Ok((features, spatial_ref));
+/// Add a layer without geometries, e.g. of metadata, to an existing geofile whose driver supports several layers and
+/// tables without geometries, e.g. a GeoPackage. Unlike the layers written by `write_layers_to_geofile`, the fields
+/// are typed after the attributes of the first row, and every row must have the same attributes.
+pub fn append_attribute_table(
+    filepath: &Path,
+    layer_name: &str,
+    rows: &[FeatureMap],
+) -> Result<()> {
+    let first_row = match rows.first() {
+        Some(first_row) => first_row,
+        None => return Ok(()),
+    };
+    let mut field_names: Vec<&String> = first_row.keys().collect();
+    field_names.sort();
+    let field_definitions = field_names
+        .iter()
+        .map(|name| Ok((name.as_str(), get_field_type(name, &first_row[*name])?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    gdal::DriverManager::register_all();
+    let open_options = gdal::DatasetOptions {
+        open_flags: gdal::GdalOpenFlags::GDAL_OF_UPDATE | gdal::GdalOpenFlags::GDAL_OF_VECTOR,
+        ..Default::default()
+    };
+    let mut dataset = gdal::Dataset::open_ex(filepath, open_options)
+        .with_context(|| format!("Opening {:?} for update", filepath))?;
+    let layer = dataset
+        .create_layer(gdal::LayerOptions {
+            name: layer_name,
+            ty: gdal::vector::OGRwkbGeometryType::wkbNone,
+            ..Default::default()
+        })
+        .with_context(|| format!("Creating layer '{}' in {:?}", layer_name, filepath))?;
+    layer.create_defn_fields(&field_definitions)?;
+    for row in rows {
+        let feature = gdal::vector::Feature::new(layer.defn())?;
+        for (name, value) in row {
+            feature.set_field(name, &gdal::vector::FieldValue::from(value))?;
+        }
+        feature.create(&layer)?;
+    }
+    Ok(())
 }
 
-fn get_default_spatial_ref() -> gdal::spatial_ref::SpatialRef {
-    gdal::spatial_ref::SpatialRef::from_epsg(4326).unwrap()
+/// Read the attributes of the rows of a layer, e.g. of a table without geometries written by
+/// `append_attribute_table`. NULL attributes are left out.
+pub fn read_attribute_table(filepath: &Path, layer_name: &str) -> Result<Vec<FeatureMap>> {
+    let dataset = open_vector_dataset(filepath)?;
+    let mut layer = get_layer(&dataset, Some(layer_name))?;
+    let field_names: Vec<String> = layer.defn().fields().map(|field| field.name()).collect();
+    let rows = layer
+        .features()
+        .map(|gdal_feature| Ok(decode_attributes(&gdal_feature, &field_names)?.0))
+        .collect::<Result<_>>()?;
+    Ok(rows)
 }
 
+/// Read the CRS of a geofile layer without reading its features.
+///
+/// # Arguments
+/// * filepath - The geofile to read.
+/// * layer_name - Name of the layer to read. If None, the geofile must have exactly one layer, which is read.
+///
+/// # Returns
+/// The CRS of the layer, None if the layer does not declare one.
+pub fn read_geofile_crs(filepath: &Path, layer_name: Option<&str>) -> Result<Option<Crs>> {
+    let dataset = open_vector_dataset(filepath)?;
+    let layer = get_layer(&dataset, layer_name)?;
+    get_layer_crs(&layer)
+}
+
+/// The name and type of an attribute field of a geofile layer.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct FieldInfo {
+    pub name: String,
+    /// The OGR field type, e.g. "String" or "Integer64".
+    pub field_type: String,
+}
+
+/// Metadata of a geofile layer, see `probe_geofile`.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct LayerInfo {
+    pub name: String,
+    /// `None` if the driver cannot count the features without reading them.
+    pub feature_count: Option<u64>,
+    /// The OGR geometry type, e.g. "Line String". `None` for tables without geometries.
+    pub geometry_type: Option<String>,
+    pub fields: Vec<FieldInfo>,
+    /// The CRS declared by the layer, see `proj_definition`.
+    pub crs: Option<String>,
+    /// The bounding box of the features as min x, min y, max x and max y. `None` if the driver cannot compute it
+    /// without reading the features, or the layer has no geometries.
+    pub extent: Option<[f64; 4]>,
+}
+
+impl LayerInfo {
+    pub fn extent_rect(&self) -> Option<geo::Rect> {
+        self.extent.map(|[min_x, min_y, max_x, max_y]| {
+            geo::Rect::new(
+                geo::coord! { x: min_x, y: min_y },
+                geo::coord! { x: max_x, y: max_y },
+            )
+        })
+    }
+}
+
+/// Read the metadata of all layers of a geofile from the layer definitions, without reading the features. The
+/// feature counts and extents are only included if the driver provides them cheaply, e.g. from a GeoPackage's
+/// metadata tables.
+pub fn probe_geofile(filepath: &Path) -> Result<Vec<LayerInfo>> {
+    let dataset = open_vector_dataset(filepath)?;
+    dataset
+        .layers()
+        .map(|layer| {
+            let defn = layer.defn();
+            let geometry_type = defn
+                .geom_fields()
+                .next()
+                .map(|geom_field| gdal::vector::geometry_type_to_name(geom_field.field_type()));
+            let fields = defn
+                .fields()
+                .map(|field| FieldInfo {
+                    name: field.name(),
+                    field_type: gdal::vector::field_type_to_name(field.field_type()),
+                })
+                .collect();
+            let crs = get_layer_crs(&layer)?
+                .map(|crs| proj_definition(&crs))
+                .transpose()?;
+            let extent = match geometry_type {
+                Some(_) => layer
+                    .try_get_extent()
+                    .with_context(|| format!("Getting the extent of layer '{}'", layer.name()))?
+                    .map(|envelope| [envelope.MinX, envelope.MinY, envelope.MaxX, envelope.MaxY]),
+                None => None,
+            };
+            Ok(LayerInfo {
+                name: layer.name(),
+                feature_count: layer.try_feature_count(),
+                geometry_type,
+                fields,
+                crs,
+                extent,
+            })
+        })
+        .collect()
+}
+
+/// The short name of the GDAL driver which opens a geofile, e.g. "GPKG", without reading its features. Fails if no
+/// driver can open it.
+pub fn read_geofile_driver_name(filepath: &Path) -> Result<String> {
+    Ok(open_vector_dataset(filepath)?.driver().short_name())
+}
+
+/// Open a vector dataset for reading. Gzip compressed files, e.g. `.geojson.gz`, are decompressed on the fly.
+fn open_vector_dataset(filepath: &Path) -> Result<gdal::Dataset> {
+    gdal::DriverManager::register_all();
+    let open_options = gdal::DatasetOptions {
+        open_flags: gdal::GdalOpenFlags::GDAL_OF_VECTOR,
+        ..Default::default()
+    };
+    if is_gzip_path(filepath) {
+        let mut vsi_filepath = std::ffi::OsString::from("/vsigzip/");
+        vsi_filepath.push(filepath);
+        return Ok(gdal::Dataset::open_ex(
+            Path::new(&vsi_filepath),
+            open_options,
+        )?);
+    }
+    Ok(gdal::Dataset::open_ex(filepath, open_options)?)
+}
+
+/// Get the layer with the given name, or the only layer of the dataset if no name is given. Of several layers, tables
+/// without geometries are skipped, e.g. the run info added to written GeoPackages.
+fn get_layer<'a>(
+    dataset: &'a gdal::Dataset,
+    layer_name: Option<&str>,
+) -> Result<gdal::vector::Layer<'a>> {
+    Ok(match layer_name {
+        Some(layer_name) => dataset
+            .layer_by_name(layer_name)
+            .with_context(|| format!("Getting layer '{}'", layer_name))?,
+        None => {
+            let mut layer_indices: Vec<isize> = (0..dataset.layer_count()).collect();
+            if 1 < layer_indices.len() {
+                layer_indices.retain(|idx| match dataset.layer(*idx) {
+                    Ok(layer) => layer.defn().geom_fields().next().is_some(),
+                    Err(_) => true,
+                });
+            }
+            if 1 != layer_indices.len() {
+                return Err(Error::invalid_data(format!(
+                    "Found {} layers, only one layer is supported unless a layer name is given.",
+                    layer_indices.len()
+                )));
+            }
+            dataset.layer(layer_indices[0])?
+        }
+    })
+}
+
+fn get_layer_crs(layer: &gdal::vector::Layer) -> Result<Option<Crs>> {
+    layer
+        .spatial_ref()
+        .ok()
+        .map(|spatial_ref| Crs::from_spatial_ref(&spatial_ref))
+        .transpose()
+}
+
+/// The names of all attributes of the features, including those which are only NULL.
 fn get_field_names(features: &Vec<Feature>) -> Vec<String> {
     let fields: HashSet<String> = features
         .par_iter()
-        .filter_map(|feature| match &feature.attributes {
-            Some(attributes) => Some(attributes.keys().cloned().collect::<Vec<String>>()),
-            None => None,
+        .flat_map_iter(|feature| {
+            feature
+                .attributes
+                .iter()
+                .flat_map(|attributes| attributes.keys())
+                .chain(&feature.null_fields)
+                .cloned()
         })
-        .flatten()
         .collect();
     fields.into_iter().collect()
 }
 
+#[allow(deprecated)] // gdal represents dates with chrono's deprecated Date type.
+impl From<gdal::vector::FieldValue> for FieldValue {
+    fn from(value: gdal::vector::FieldValue) -> Self {
+        use gdal::vector::FieldValue as GdalFieldValue;
+        match value {
+            GdalFieldValue::IntegerValue(value) => FieldValue::IntegerValue(value),
+            GdalFieldValue::IntegerListValue(value) => FieldValue::IntegerListValue(value),
+            GdalFieldValue::Integer64Value(value) => FieldValue::Integer64Value(value),
+            GdalFieldValue::Integer64ListValue(value) => FieldValue::Integer64ListValue(value),
+            GdalFieldValue::StringValue(value) => FieldValue::StringValue(value),
+            GdalFieldValue::StringListValue(value) => FieldValue::StringListValue(value),
+            GdalFieldValue::RealValue(value) => FieldValue::RealValue(value),
+            GdalFieldValue::RealListValue(value) => FieldValue::RealListValue(value),
+            GdalFieldValue::DateValue(value) => FieldValue::DateValue(value),
+            GdalFieldValue::DateTimeValue(value) => FieldValue::DateTimeValue(value),
+        }
+    }
+}
+
+#[allow(deprecated)] // gdal represents dates with chrono's deprecated Date type.
+impl From<&FieldValue> for gdal::vector::FieldValue {
+    fn from(value: &FieldValue) -> Self {
+        use gdal::vector::FieldValue as GdalFieldValue;
+        match value.clone() {
+            FieldValue::IntegerValue(value) => GdalFieldValue::IntegerValue(value),
+            FieldValue::IntegerListValue(value) => GdalFieldValue::IntegerListValue(value),
+            FieldValue::Integer64Value(value) => GdalFieldValue::Integer64Value(value),
+            FieldValue::Integer64ListValue(value) => GdalFieldValue::Integer64ListValue(value),
+            FieldValue::StringValue(value) => GdalFieldValue::StringValue(value),
+            FieldValue::StringListValue(value) => GdalFieldValue::StringListValue(value),
+            FieldValue::RealValue(value) => GdalFieldValue::RealValue(value),
+            FieldValue::RealListValue(value) => GdalFieldValue::RealListValue(value),
+            FieldValue::DateValue(value) => GdalFieldValue::DateValue(value),
+            FieldValue::DateTimeValue(value) => GdalFieldValue::DateTimeValue(value),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, iter::zip};
+    use std::{collections::HashMap, iter::zip, path::PathBuf};
 
-    use gdal::vector::FieldValue;
+    use approx::assert_abs_diff_eq;
+    use gdal::vector::LayerAccess;
     use rstest::rstest;
     use testdir::testdir;
 
-    use crate::geofile::{
-        feature::Feature,
-        gdal_geofile::{read_features_from_geofile, write_features_to_geofile, GdalDriverType},
+    use crate::{
+        crs::crs_utils::epsg_4326,
+        error::Error,
+        geofile::{
+            feature::{AttributeValue, Feature, FieldValue},
+            gdal_geofile::{
+                merge_creation_options, probe_geofile, read_features_from_geofile,
+                read_features_from_geofile_with_options, read_geofile_driver_name,
+                write_features_to_geofile, write_layers_to_geofile, FieldInfo, GdalDriverType,
+                OnReadError, ReadOptions, WriteOptions, WriteReport,
+            },
+        },
     };
 
+    /// Features with one of each lossy conversion when written to a shapefile.
+    fn lossy_features() -> Vec<Feature> {
+        vec![
+            Feature {
+                geometry: geo::LineString::from(vec![(0.0, 0.0), (1.0, 1.0)]).into(),
+                attributes: Some(HashMap::from([(
+                    "road_surface".to_string(),
+                    FieldValue::StringValue("asphalt".to_string()),
+                )])),
+                null_fields: Default::default(),
+            },
+            Feature {
+                geometry: geo::MultiLineString(vec![vec![(1.0, 1.0), (2.0, 1.0)].into()]).into(),
+                attributes: Some(HashMap::from([
+                    ("lanes".to_string(), FieldValue::IntegerValue(2)),
+                    (
+                        "refs".to_string(),
+                        FieldValue::StringListValue(vec!["A1".to_string(), "B2".to_string()]),
+                    ),
+                ])),
+                null_fields: Default::default(),
+            },
+        ]
+    }
+
+    /// A CSV geofile with WKT geometries, of which the second one is truncated and cannot be decoded.
+    fn write_corrupted_geometry_csv() -> PathBuf {
+        let filepath = testdir!().join("corrupted.csv");
+        std::fs::write(
+            &filepath,
+            "WKT,name\n\
+            \"LINESTRING (0 0,1 1)\",first\n\
+            \"LINESTRING (1 1,\",corrupted\n\
+            \"LINESTRING (1 1,2 1)\",last\n",
+        )
+        .unwrap();
+        filepath
+    }
+
+    #[test]
+    fn test_read_corrupted_geometry_fails_by_default() {
+        let filepath = write_corrupted_geometry_csv();
+
+        let error = read_features_from_geofile(&filepath).err().unwrap();
+
+        assert!(
+            matches!(error.root(), Error::GdalError(_)),
+            "{}",
+            error.display_chain()
+        );
+    }
+
+    #[test]
+    fn test_merge_creation_options() {
+        let merged = merge_creation_options(
+            &["SPATIAL_INDEX=YES", "FID=fid"],
+            &[
+                "spatial_index=NO".to_string(),
+                "GEOMETRY_NAME=geom".to_string(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ("FID".to_string(), "fid".to_string()),
+                ("spatial_index".to_string(), "NO".to_string()),
+                ("GEOMETRY_NAME".to_string(), "geom".to_string()),
+            ],
+            merged
+        );
+        assert!(matches!(
+            merge_creation_options(&[], &["SPATIAL_INDEX".to_string()]),
+            Err(Error::ConfigError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(Vec::new(), true)]
+    #[case(vec!["SPATIAL_INDEX=NO".to_string()], false)]
+    fn test_write_geopackage_spatial_index(
+        #[case] layer_creation_options: Vec<String>,
+        #[case] expect_spatial_index: bool,
+    ) {
+        let features = vec![Feature {
+            geometry: geo::LineString::from(vec![(0.0, 0.0), (1.0, 1.0)]).into(),
+            attributes: None,
+            null_fields: Default::default(),
+        }];
+        let geofile_filepath = testdir!().join("output.gpkg");
+
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::new().layer_creation_options(layer_creation_options),
+        )
+        .unwrap();
+
+        let dataset = gdal::Dataset::open(&geofile_filepath).unwrap();
+        let rtree_tables = dataset
+            .execute_sql(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'rtree_%'",
+                None,
+                gdal::vector::sql::Dialect::DEFAULT,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(expect_spatial_index, rtree_tables.feature_count() > 0);
+    }
+
+    #[test]
+    fn test_write_geojson_coordinate_precision() {
+        let features = vec![Feature {
+            geometry: geo::LineString::from(vec![(0.123456, 1.987654), (1.5, 2.5)]).into(),
+            attributes: None,
+            null_fields: Default::default(),
+        }];
+        let geofile_filepath = testdir!().join("output.geojson");
+
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoJson.name(),
+            &WriteOptions::new().layer_creation_options(vec!["COORDINATE_PRECISION=2".to_string()]),
+        )
+        .unwrap();
+
+        let contents = std::fs::read_to_string(&geofile_filepath).unwrap();
+        assert!(contents.contains("0.12"), "{}", contents);
+        assert!(!contents.contains("0.123"), "{}", contents);
+        assert!(!contents.contains("1.987"), "{}", contents);
+    }
+
+    #[test]
+    fn test_write_geojson_feature_ids() {
+        let features: Vec<Feature> = (0..3)
+            .map(|idx| Feature {
+                geometry: geo::LineString::from(vec![(idx as f64, 0.0), (idx as f64, 1.0)]).into(),
+                attributes: None,
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join("output.geojson");
+
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoJson.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&geofile_filepath).unwrap()).unwrap();
+        let ids: Vec<u64> = json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|feature| feature["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(3, ids.len());
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+    }
+
+    #[test]
+    fn test_write_with_missing_driver_is_gdal_error() {
+        let features = vec![Feature {
+            geometry: geo::LineString::from(vec![(0.0, 0.0), (1.0, 1.0)]).into(),
+            attributes: None,
+            null_fields: Default::default(),
+        }];
+
+        let error = write_features_to_geofile(
+            &features,
+            &testdir!().join("output.xyz"),
+            None,
+            "NoSuchDriver",
+            &WriteOptions::default(),
+        )
+        .unwrap_err();
+
+        assert!(matches!(error.root(), Error::GdalError(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn test_read_corrupted_geometry_skip_and_warn() {
+        let filepath = write_corrupted_geometry_csv();
+
+        let (features, _, report) = read_features_from_geofile_with_options(
+            &filepath,
+            &ReadOptions::new().on_error(OnReadError::SkipAndWarn),
+        )
+        .unwrap();
+
+        let names: Vec<&FieldValue> = features
+            .iter()
+            .map(|feature| feature.attributes.as_ref().unwrap().get("name").unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                &FieldValue::StringValue("first".to_string()),
+                &FieldValue::StringValue("last".to_string())
+            ],
+            names
+        );
+        assert!(!report.is_complete());
+        assert_eq!(1, report.skipped_features.len());
+        assert!(report.skipped_features[0].fid.is_some());
+        assert!(report.skipped_features[0]
+            .error
+            .starts_with("Exporting the geometry to WKB"));
+    }
+
     #[rstest]
     #[case(GdalDriverType::GeoJson)]
     #[case(GdalDriverType::GeoPackage)]
@@ -225,28 +1441,417 @@ mod tests {
                     FieldValue::StringValue("56.0".to_string()),
                 ),
             ])),
+            null_fields: Default::default(),
         }];
 
         let test_dir = testdir!();
         let geofile_filepath = test_dir.join("output.file");
 
-        let spatial_ref = gdal::spatial_ref::SpatialRef::from_epsg(4326).unwrap();
+        let crs = epsg_4326();
 
         write_features_to_geofile(
             &features,
             &geofile_filepath,
-            Some(&spatial_ref),
+            Some(&crs),
             driver.name(),
+            &WriteOptions::default(),
         )
         .unwrap();
-        let (read_features, read_spatial_ref) =
-            read_features_from_geofile(&geofile_filepath).unwrap();
+        let (read_features, read_crs) = read_features_from_geofile(&geofile_filepath).unwrap();
 
         for (feature, read_feature) in zip(features, read_features) {
             assert_eq!(feature, read_feature);
         }
-        let read_spatial_ref_name = read_spatial_ref.name().unwrap();
-        let spatial_ref_name = spatial_ref.name().unwrap();
-        assert_eq!(read_spatial_ref_name, spatial_ref_name);
+        let read_crs_name = read_crs.name().unwrap();
+        let crs_name = crs.name().unwrap();
+        assert_eq!(read_crs_name, crs_name);
+        assert_eq!(
+            driver.name(),
+            read_geofile_driver_name(&geofile_filepath).unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case("GPKG", "output.gpkg")]
+    #[case("ESRI Shapefile", "output.shp")]
+    fn test_write_with_and_without_transactions(#[case] driver_name: &str, #[case] filename: &str) {
+        let features: Vec<Feature> = (0..100)
+            .map(|idx| Feature {
+                geometry: geo::Geometry::Point(geo::Point::new(idx as f64, 45.0)),
+                attributes: Some(HashMap::from([(
+                    "key1".to_string(),
+                    FieldValue::StringValue(format!("value{}", idx)),
+                )])),
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join(filename);
+        let crs = epsg_4326();
+
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            Some(&crs),
+            driver_name,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        // All features are visible after reopening, i.e. the transaction was committed if the driver supports it.
+        let (read_features, _) = read_features_from_geofile(&geofile_filepath).unwrap();
+        assert_eq!(features, read_features);
+    }
+
+    #[rstest]
+    #[case(GdalDriverType::GeoJson)]
+    #[case(GdalDriverType::GeoPackage)]
+    fn test_geofile_write_read_axis_order(#[case] driver: GdalDriverType) {
+        let (lon, lat) = (139.8, 35.7);
+        let features = vec![Feature::from(geo::Geometry::Point(geo::Point::new(
+            lon, lat,
+        )))];
+
+        let test_dir = testdir!();
+        let geofile_filepath = test_dir.join("output.file");
+
+        // Deliberately not using epsg_4326(), which already has the traditional axis order set.
+        let crs = epsg_4326();
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            Some(&crs),
+            driver.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        // Read the point back with GDAL directly, honoring the axis mapping of the layer.
+        let dataset = gdal::Dataset::open(&geofile_filepath).unwrap();
+        let mut layer = dataset.layer(0).unwrap();
+        let gdal_feature = layer.features().next().unwrap();
+        let (x, y, _) = gdal_feature.geometry().get_point(0);
+        assert_abs_diff_eq!(x, lon);
+        assert_abs_diff_eq!(y, lat);
+
+        let (read_features, _) = read_features_from_geofile(&geofile_filepath).unwrap();
+        assert_eq!(features, read_features);
+    }
+
+    #[test]
+    fn test_write_lenient_reports_lossy_conversions() {
+        let geofile_filepath = testdir!().join("output.shp");
+        let report = write_features_to_geofile(
+            &lossy_features(),
+            &geofile_filepath,
+            None,
+            "ESRI Shapefile",
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let expected_report = WriteReport {
+            type_fallbacks: 1,
+            list_flattenings: 1,
+            renamed_fields: 1,
+            promoted_geometries: 1,
+        };
+        assert_eq!(expected_report, report);
+        let (read_features, _) = read_features_from_geofile(&geofile_filepath).unwrap();
+        assert_eq!(2, read_features.len());
+        assert!(matches!(
+            read_features[0].geometry,
+            geo::Geometry::MultiLineString(_)
+        ));
+    }
+
+    #[test]
+    fn test_write_typed_fields() {
+        let point_feature = |attributes: Vec<(&str, FieldValue)>| Feature {
+            geometry: geo::Point::new(0.0, 0.0).into(),
+            attributes: Some(
+                attributes
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect(),
+            ),
+            null_fields: Default::default(),
+        };
+        let features = vec![
+            point_feature(vec![
+                ("count", FieldValue::IntegerValue(1)),
+                ("fraction", FieldValue::IntegerValue(1)),
+                ("mixed", FieldValue::IntegerValue(1)),
+            ]),
+            point_feature(vec![
+                ("count", FieldValue::Integer64Value(2)),
+                ("fraction", FieldValue::RealValue(0.5)),
+                ("mixed", FieldValue::StringValue("a".to_string())),
+            ]),
+        ];
+        let geofile_filepath = testdir!().join("output.gpkg");
+
+        let report = write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::new().typed_fields(true),
+        )
+        .unwrap();
+
+        // Only the integer of the attribute with a string value falls back to a string.
+        assert_eq!(
+            WriteReport {
+                type_fallbacks: 1,
+                ..WriteReport::default()
+            },
+            report
+        );
+        let (read_features, _) = read_features_from_geofile(&geofile_filepath).unwrap();
+        let attributes = read_features[0].attributes.as_ref().unwrap();
+        assert_eq!(FieldValue::Integer64Value(1), attributes["count"]);
+        assert_eq!(FieldValue::RealValue(1.0), attributes["fraction"]);
+        assert_eq!(
+            FieldValue::StringValue("1".to_string()),
+            attributes["mixed"]
+        );
+    }
+
+    #[rstest]
+    // A GeoPackage has a value for each field of each row, so an absent attribute is read back as NULL.
+    #[case("output.gpkg", GdalDriverType::GeoPackage, AttributeValue::Null)]
+    #[case("output.geojson", GdalDriverType::GeoJson, AttributeValue::Absent)]
+    fn test_write_null_integer_field_round_trip(
+        #[case] filename: &str,
+        #[case] driver: GdalDriverType,
+        #[case] expected_absent_value: AttributeValue,
+    ) {
+        let point_feature = |lanes: Option<i32>, null_fields: &[&str]| Feature {
+            geometry: geo::Point::new(0.0, 0.0).into(),
+            attributes: Some(
+                lanes
+                    .map(|lanes| ("lanes".to_string(), FieldValue::IntegerValue(lanes)))
+                    .into_iter()
+                    .chain([(
+                        "name".to_string(),
+                        FieldValue::StringValue("Main Street".to_string()),
+                    )])
+                    .collect(),
+            ),
+            null_fields: null_fields.iter().map(|name| name.to_string()).collect(),
+        };
+        let features = vec![
+            point_feature(Some(2), &[]),
+            point_feature(None, &["lanes"]),
+            point_feature(None, &[]),
+        ];
+        assert_eq!(AttributeValue::Null, features[1].attribute("lanes"));
+        assert_eq!(AttributeValue::Absent, features[2].attribute("lanes"));
+        let geofile_filepath = testdir!().join(filename);
+
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            driver.name(),
+            &WriteOptions::new().typed_fields(true),
+        )
+        .unwrap();
+
+        let (read_features, _) = read_features_from_geofile(&geofile_filepath).unwrap();
+        assert_eq!(3, read_features.len());
+        assert_eq!(
+            AttributeValue::Value(&FieldValue::IntegerValue(2)),
+            read_features[0].attribute("lanes")
+        );
+        assert!(read_features[0].null_fields.is_empty());
+        assert_eq!(AttributeValue::Null, read_features[1].attribute("lanes"));
+        assert_eq!(
+            AttributeValue::Value(&FieldValue::StringValue("Main Street".to_string())),
+            read_features[1].attribute("name")
+        );
+        assert_eq!(expected_absent_value, read_features[2].attribute("lanes"));
+    }
+
+    #[test]
+    fn test_write_layers() {
+        let line_features = vec![Feature {
+            geometry: geo::LineString::from(vec![(0.0, 0.0), (1.0, 1.0)]).into(),
+            attributes: None,
+            null_fields: Default::default(),
+        }];
+        let point_features: Vec<Feature> = (0..2)
+            .map(|_| Feature {
+                geometry: geo::Point::new(0.0, 0.0).into(),
+                attributes: None,
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join("output.gpkg");
+
+        write_layers_to_geofile(
+            &[
+                ("lines", &line_features),
+                ("empty", &Vec::new()),
+                ("points", &point_features),
+            ],
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let dataset = gdal::Dataset::open(&geofile_filepath).unwrap();
+        assert_eq!(2, dataset.layer_count());
+        assert_eq!(1, dataset.layer_by_name("lines").unwrap().feature_count());
+        assert_eq!(2, dataset.layer_by_name("points").unwrap().feature_count());
+    }
+
+    #[test]
+    fn test_probe_geojson_fixture() {
+        let geofile_filepath = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/end_to_end/ground_truth.geojson");
+
+        let layers = probe_geofile(&geofile_filepath).unwrap();
+
+        assert_eq!(1, layers.len());
+        let layer = &layers[0];
+        assert_eq!(Some(12), layer.feature_count);
+        assert_eq!(Some("Line String"), layer.geometry_type.as_deref());
+        assert_eq!(
+            vec![FieldInfo {
+                name: "highway".to_string(),
+                field_type: "String".to_string()
+            }],
+            layer.fields
+        );
+        assert_eq!(Some("EPSG:4326"), layer.crs.as_deref());
+        assert_eq!(Some([139.78, 35.68, 139.784, 35.684]), layer.extent);
+    }
+
+    #[test]
+    fn test_probe_geopackage() {
+        let line_features = vec![Feature {
+            geometry: geo::LineString::from(vec![(0.0, 0.0), (10.0, 5.0)]).into(),
+            attributes: Some(HashMap::from([(
+                "lanes".to_string(),
+                FieldValue::IntegerValue(2),
+            )])),
+            null_fields: Default::default(),
+        }];
+        let point_features: Vec<Feature> = (0..2)
+            .map(|idx| Feature {
+                geometry: geo::Point::new(idx as f64, 0.0).into(),
+                attributes: None,
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join("output.gpkg");
+        write_layers_to_geofile(
+            &[("lines", &line_features), ("points", &point_features)],
+            &geofile_filepath,
+            Some(&crate::crs::Crs::Epsg(32654)),
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::new().typed_fields(true),
+        )
+        .unwrap();
+
+        let layers = probe_geofile(&geofile_filepath).unwrap();
+
+        let summary: Vec<(&str, Option<u64>, Option<&str>)> = layers
+            .iter()
+            .map(|layer| {
+                (
+                    layer.name.as_str(),
+                    layer.feature_count,
+                    layer.geometry_type.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("lines", Some(1), Some("Line String")),
+                ("points", Some(2), Some("Point"))
+            ],
+            summary
+        );
+        let field_names: Vec<&str> = layers[0]
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        assert_eq!(vec!["lanes"], field_names);
+        assert_eq!(Some("EPSG:32654"), layers[0].crs.as_deref());
+        assert_eq!(Some([0.0, 0.0, 10.0, 5.0]), layers[0].extent);
+    }
+
+    #[rstest]
+    #[case(vec!["lanes"], false, "Feature 1: field 'lanes'")]
+    #[case(vec!["refs"], false, "Feature 1: field 'refs'")]
+    #[case(vec![], true, "Feature 0: single geometry")]
+    #[case(vec!["road_surface"], false, "Feature 0: field 'road_surface' would be renamed")]
+    fn test_write_strict_fails_on_lossy_conversion(
+        #[case] kept_attributes: Vec<&str>,
+        #[case] keep_geometries: bool,
+        #[case] expected_error: &str,
+    ) {
+        // Keep only the lossy conversion under test.
+        let mut features = lossy_features();
+        for feature in &mut features {
+            if let Some(attributes) = &mut feature.attributes {
+                attributes.retain(|key, _| kept_attributes.contains(&key.as_str()));
+            }
+            if !keep_geometries {
+                feature.geometry = geo::LineString::from(vec![(0.0, 0.0), (1.0, 1.0)]).into();
+            }
+        }
+        let geofile_filepath = testdir!().join("output.shp");
+        let lenient_report = write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            "ESRI Shapefile",
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        assert!(!lenient_report.is_lossless());
+
+        let geofile_filepath = testdir!().join("strict_output.shp");
+        let error = write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            "ESRI Shapefile",
+            &WriteOptions::new().strict(true),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::InvalidData { .. }));
+        assert!(
+            error.to_string().starts_with(expected_error),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_write_strict_accepts_lossless_features() {
+        let features = vec![Feature {
+            geometry: geo::Geometry::Point(geo::Point::new(80.0, 45.0)),
+            attributes: Some(HashMap::from([(
+                "key".to_string(),
+                FieldValue::StringValue("value".to_string()),
+            )])),
+            null_fields: Default::default(),
+        }];
+        let report = write_features_to_geofile(
+            &features,
+            &testdir!().join("output.gpkg"),
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::new().strict(true),
+        )
+        .unwrap();
+        assert!(report.is_lossless());
     }
 }
@@ -1,29 +1,802 @@
 use std::{
-    fs::{self, read_to_string},
-    io,
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
-use anyhow::anyhow;
+use crate::crs::{crs_utils::epsg_4326, Crs};
+use crate::error::{Error, Result, ResultExt};
+use crate::geofile::feature::{Feature, FeatureMap, FieldValue};
+use crate::geograph::primitives::EdgeKey;
+use geo::BoundingRect;
 
-pub fn write_lines_to_geojson(
-    lines: &Vec<geo::LineString>,
+/// Options for writing GeoJSON files. The bounding box of the feature collection is always written.
+#[derive(Default)]
+pub struct GeoJsonWriteOptions<'a> {
+    /// Write the bounding box of each feature.
+    pub feature_bbox: bool,
+    /// If set, write the deprecated named `crs` member of the 2008 GeoJSON specification for this CRS, for consumers
+    /// which expect it. RFC 7946 requires WGS84 coordinates, so projected CRSes are refused unless
+    /// `force_projected_crs` is set.
+    pub crs: Option<&'a Crs>,
+    pub force_projected_crs: bool,
+}
+
+/// Whether a geofile is gzip compressed, i.e. its path ends in `.gz`, e.g. `ground_truth.geojson.gz`.
+pub fn is_gzip_path(filepath: &Path) -> bool {
+    filepath
+        .extension()
+        .is_some_and(|extension| extension == "gz")
+}
+
+/// The GeoJSON feature id of a graph edge, "start-end-parallel" from its start and end node index and its parallel edge
+/// index, e.g. "2-10-0".
+pub fn edge_feature_id(key: &EdgeKey) -> String {
+    format!("{}-{}-{}", key.0, key.1, key.2)
+}
+
+/// Write lines to a GeoJSON file, gzip compressed if the path ends in `.gz`. Each feature gets its index as its `id`
+/// member and `id` property.
+pub fn write_lines_to_geojson(lines: &[geo::LineString], output_filepath: &Path) -> Result<()> {
+    write_lines_to_geojson_with_options(lines, output_filepath, &GeoJsonWriteOptions::default())
+}
+
+/// Like `write_lines_to_geojson`, with the bounding boxes and CRS member configured by `options`.
+pub fn write_lines_to_geojson_with_options(
+    lines: &[geo::LineString],
+    output_filepath: &Path,
+    options: &GeoJsonWriteOptions,
+) -> Result<()> {
+    let crs_member = match options.crs {
+        Some(crs) => Some(named_crs_member(crs, options.force_projected_crs)?),
+        None => None,
+    };
+    write_lines(
+        lines,
+        &index_feature_ids(lines.len()),
+        output_filepath,
+        options.feature_bbox,
+        crs_member,
+    )
+}
+
+/// Like `write_lines_to_geojson_with_options`, for the edges of a graph with their keys, e.g. from
+/// `GeoGraph::keyed_edge_geometries`. Each feature gets the `edge_feature_id` of its edge as its `id` member and `id`
+/// property, and the features are written in the order of the edge keys, so that dumps of the same graph can be
+/// diffed and joined.
+pub fn write_edges_to_geojson_with_options(
+    edges: Vec<(EdgeKey, geo::LineString)>,
     output_filepath: &Path,
-) -> io::Result<()> {
-    let feature_collection: geojson::FeatureCollection = lines
-        .iter()
-        .map(|line| geojson::Feature::from(geojson::Geometry::from(line)))
-        .collect();
-    let geojson_contents: geojson::GeoJson = geojson::GeoJson::from(feature_collection);
-    fs::write(output_filepath, geojson_contents.to_string())
-}
-
-pub fn read_lines_from_geojson(filepath: &PathBuf) -> anyhow::Result<Vec<geo::LineString>> {
-    let geojson_contents = read_to_string(filepath)?;
-    let feature_collection = geojson_contents.parse::<geojson::FeatureCollection>()?;
-    let lines: Result<Vec<_>, _> = feature_collection
+    options: &GeoJsonWriteOptions,
+) -> Result<()> {
+    GeoJsonLinesWriter::from_edges(edges, output_filepath, options)?.write()
+}
+
+fn index_feature_ids(count: usize) -> Vec<geojson::feature::Id> {
+    (0..count as u64)
+        .map(|idx| geojson::feature::Id::Number(idx.into()))
+        .collect()
+}
+
+/// Lines to write to a GeoJSON file like `write_lines_to_geojson_with_options`, which can be sent to another thread
+/// to write them there. The CRS member is resolved on creation, as GDAL spatial references cannot be sent.
+pub struct GeoJsonLinesWriter {
+    lines: Vec<geo::LineString>,
+    ids: Vec<geojson::feature::Id>,
+    output_filepath: PathBuf,
+    feature_bbox: bool,
+    crs_member: Option<geojson::JsonObject>,
+}
+
+impl GeoJsonLinesWriter {
+    /// Lines whose features get their indices as ids, like `write_lines_to_geojson_with_options`.
+    pub fn new(
+        lines: Vec<geo::LineString>,
+        output_filepath: &Path,
+        options: &GeoJsonWriteOptions,
+    ) -> Result<Self> {
+        let ids = index_feature_ids(lines.len());
+        Self::with_ids(lines, ids, output_filepath, options)
+    }
+
+    /// Edges whose features get their edge keys as ids, like `write_edges_to_geojson_with_options`.
+    pub fn from_edges(
+        mut edges: Vec<(EdgeKey, geo::LineString)>,
+        output_filepath: &Path,
+        options: &GeoJsonWriteOptions,
+    ) -> Result<Self> {
+        edges.sort_by_key(|(key, _)| *key);
+        let (ids, lines) = edges
+            .into_iter()
+            .map(|(key, line)| (geojson::feature::Id::String(edge_feature_id(&key)), line))
+            .unzip();
+        Self::with_ids(lines, ids, output_filepath, options)
+    }
+
+    fn with_ids(
+        lines: Vec<geo::LineString>,
+        ids: Vec<geojson::feature::Id>,
+        output_filepath: &Path,
+        options: &GeoJsonWriteOptions,
+    ) -> Result<Self> {
+        let crs_member = match options.crs {
+            Some(crs) => Some(named_crs_member(crs, options.force_projected_crs)?),
+            None => None,
+        };
+        Ok(GeoJsonLinesWriter {
+            lines,
+            ids,
+            output_filepath: output_filepath.to_path_buf(),
+            feature_bbox: options.feature_bbox,
+            crs_member,
+        })
+    }
+
+    pub fn write(self) -> Result<()> {
+        write_lines(
+            &self.lines,
+            &self.ids,
+            &self.output_filepath,
+            self.feature_bbox,
+            self.crs_member,
+        )
+        .with_context(|| format!("Writing {:?}", self.output_filepath))
+    }
+}
+
+fn write_lines(
+    lines: &[geo::LineString],
+    ids: &[geojson::feature::Id],
+    output_filepath: &Path,
+    feature_bbox: bool,
+    crs_member: Option<geojson::JsonObject>,
+) -> Result<()> {
+    write_geojson_file(output_filepath, |mut writer| {
+        write_feature_collection(&mut writer, lines, ids, feature_bbox, crs_member)
+    })
+}
+
+/// Create the file at `output_filepath`, gzip compressed if its path ends in `.gz`, and write its content with
+/// `write`.
+fn write_geojson_file(
+    output_filepath: &Path,
+    write: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let file = BufWriter::new(
+        File::create(output_filepath).with_context(|| format!("Creating {:?}", output_filepath))?,
+    );
+    if is_gzip_path(output_filepath) {
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        write(&mut encoder)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut file = file;
+        write(&mut file)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+/// Serialize the lines as a GeoJSON feature collection to `writer`, one feature at a time, so that the document is
+/// never held in memory as a whole. Each feature gets the id at its index in `ids` as its `id` member and property.
+fn write_feature_collection(
+    writer: &mut impl Write,
+    lines: &[geo::LineString],
+    ids: &[geojson::feature::Id],
+    feature_bbox: bool,
+    crs_member: Option<geojson::JsonObject>,
+) -> Result<()> {
+    let bbox = union_bbox(lines.iter().filter_map(|line| line.bounding_rect()));
+    let features = lines.iter().zip(ids).map(|(line, id)| {
+        let mut feature = geojson::Feature::from(geojson::Geometry::from(line));
+        feature.id = Some(id.clone());
+        feature.set_property("id", serde_json::to_value(id)?);
+        if feature_bbox {
+            feature.bbox = line.bounding_rect().map(rect_to_bbox);
+        }
+        Ok(feature)
+    });
+    write_features(writer, bbox, crs_member, features)
+}
+
+/// Serialize a feature collection with the given bounding box and CRS member to `writer`, one feature at a time.
+fn write_features(
+    writer: &mut impl Write,
+    bbox: Option<geojson::Bbox>,
+    crs_member: Option<geojson::JsonObject>,
+    features: impl Iterator<Item = Result<geojson::Feature>>,
+) -> Result<()> {
+    writer.write_all(br#"{"type":"FeatureCollection""#)?;
+    if let Some(bbox) = bbox {
+        writer.write_all(br#","bbox":"#)?;
+        serde_json::to_writer(&mut *writer, &bbox)?;
+    }
+    for (name, value) in crs_member.iter().flatten() {
+        write!(writer, ",{}:", serde_json::Value::from(name.as_str()))?;
+        serde_json::to_writer(&mut *writer, value)?;
+    }
+    writer.write_all(br#","features":["#)?;
+    for (idx, feature) in features.enumerate() {
+        if idx > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *writer, &feature?)?;
+    }
+    writer.write_all(b"]}")?;
+    Ok(())
+}
+
+/// The bounding box of all `rects`, `None` if there are none.
+fn union_bbox(rects: impl Iterator<Item = geo::Rect>) -> Option<geojson::Bbox> {
+    rects
+        .reduce(|bbox, rect| {
+            geo::Rect::new(
+                (
+                    bbox.min().x.min(rect.min().x),
+                    bbox.min().y.min(rect.min().y),
+                ),
+                (
+                    bbox.max().x.max(rect.max().x),
+                    bbox.max().y.max(rect.max().y),
+                ),
+            )
+        })
+        .map(rect_to_bbox)
+}
+
+fn rect_to_bbox(rect: geo::Rect) -> geojson::Bbox {
+    vec![rect.min().x, rect.min().y, rect.max().x, rect.max().y]
+}
+
+/// Write features with their attributes to a GeoJSON file, gzip compressed if the path ends in `.gz`. Unlike
+/// `write_features_to_geofile`, this does not need GDAL. Each feature gets its index as its `id` member, its
+/// attributes become its properties and its NULL fields `null` properties. Dates and date times are written as ISO
+/// 8601 strings.
+pub fn write_features_to_geojson(
+    features: &[Feature],
+    output_filepath: &Path,
+    options: &GeoJsonWriteOptions,
+) -> Result<()> {
+    let crs_member = match options.crs {
+        Some(crs) => Some(named_crs_member(crs, options.force_projected_crs)?),
+        None => None,
+    };
+    let bbox = union_bbox(
+        features
+            .iter()
+            .filter_map(|feature| feature.geometry.bounding_rect()),
+    );
+    write_geojson_file(output_filepath, |mut writer| {
+        let geojson_features = features.iter().enumerate().map(|(idx, feature)| {
+            let mut geojson_feature = geojson::Feature::from(geojson::Geometry::new(
+                geojson::Value::from(&feature.geometry),
+            ));
+            geojson_feature.id = Some(geojson::feature::Id::Number((idx as u64).into()));
+            let mut properties = geojson::JsonObject::new();
+            for (name, value) in feature.attributes.iter().flatten() {
+                properties.insert(name.clone(), field_value_to_json(value));
+            }
+            for name in &feature.null_fields {
+                properties.insert(name.clone(), serde_json::Value::Null);
+            }
+            geojson_feature.properties = Some(properties);
+            if options.feature_bbox {
+                geojson_feature.bbox = feature.geometry.bounding_rect().map(rect_to_bbox);
+            }
+            Ok(geojson_feature)
+        });
+        write_features(&mut writer, bbox, crs_member, geojson_features)
+    })
+    .with_context(|| format!("Writing {:?}", output_filepath))
+}
+
+fn field_value_to_json(value: &FieldValue) -> serde_json::Value {
+    match value {
+        FieldValue::IntegerValue(value) => (*value).into(),
+        FieldValue::IntegerListValue(values) => values.clone().into(),
+        FieldValue::Integer64Value(value) => (*value).into(),
+        FieldValue::Integer64ListValue(values) => values.clone().into(),
+        FieldValue::StringValue(value) => value.clone().into(),
+        FieldValue::StringListValue(values) => values.clone().into(),
+        FieldValue::RealValue(value) => (*value).into(),
+        FieldValue::RealListValue(values) => values.clone().into(),
+        FieldValue::DateValue(value) => value.naive_local().to_string().into(),
+        FieldValue::DateTimeValue(value) => value.to_rfc3339().into(),
+    }
+}
+
+/// The named `crs` member for the given CRS, as a foreign member object.
+fn named_crs_member(crs: &Crs, force_projected_crs: bool) -> Result<geojson::JsonObject> {
+    if crs.is_projected() && !force_projected_crs {
+        return Err(Error::CrsMismatch(format!(
+            "Refusing to declare the projected CRS '{}' in GeoJSON, which requires WGS84 coordinates",
+            crs.name()?
+        )));
+    }
+    let crs_name = match (crs.auth_name()?, crs.auth_code()?) {
+        (auth_name, 4326) if auth_name == "EPSG" => "urn:ogc:def:crs:OGC:1.3:CRS84".to_string(),
+        (auth_name, auth_code) => format!("urn:ogc:def:crs:{}::{}", auth_name, auth_code),
+    };
+    let crs_member = serde_json::json!({
+        "type": "name",
+        "properties": { "name": crs_name },
+    });
+    Ok(geojson::JsonObject::from_iter([(
+        "crs".to_string(),
+        crs_member,
+    )]))
+}
+
+/// Parse the feature collection of a GeoJSON file, decompressing it if the path ends in `.gz`.
+fn read_feature_collection(filepath: &Path) -> Result<geojson::FeatureCollection> {
+    let file =
+        BufReader::new(File::open(filepath).with_context(|| format!("Opening {:?}", filepath))?);
+    let reader: Box<dyn Read> = if is_gzip_path(filepath) {
+        Box::new(flate2::read::MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    serde_json::from_reader(reader).with_context(|| format!("Parsing {:?}", filepath))
+}
+
+/// Read the lines of a GeoJSON file, decompressing it if the path ends in `.gz`.
+pub fn read_lines_from_geojson(filepath: &Path) -> Result<Vec<geo::LineString>> {
+    read_feature_collection(filepath)?
+        .into_iter()
+        .map(geo::LineString::try_from)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("Could not parse linestrings")
+}
+
+/// Read the features of a GeoJSON file with their properties as attributes, decompressing it if the path ends in
+/// `.gz`. Unlike `read_features_from_geofile`, this does not need GDAL. Returns the features and the CRS declared by
+/// the named `crs` member of the file, or EPSG:4326 if it has none, as required by RFC 7946.
+///
+/// Properties become attributes like GDAL reads them: integers are `IntegerValue`s, or `Integer64Value`s if they do
+/// not fit, booleans are `IntegerValue`s of 0 or 1, and `null` properties are NULL fields. Arrays of strings and
+/// numbers become list values, all other arrays and objects are kept as JSON strings.
+pub fn read_features_from_geojson(filepath: &Path) -> Result<(Vec<Feature>, Crs)> {
+    let feature_collection = read_feature_collection(filepath)?;
+    let crs = match feature_collection
+        .foreign_members
+        .as_ref()
+        .and_then(|members| members.get("crs"))
+    {
+        Some(crs_member) => crs_from_named_crs_member(crs_member)
+            .with_context(|| format!("Reading the CRS of {:?}", filepath))?,
+        None => epsg_4326(),
+    };
+    let features = feature_collection
+        .features
         .into_iter()
-        .map(|feature| geo::LineString::try_from(feature))
-        .collect();
-    lines.or_else(|error| Err(anyhow!("Could not parse linestrings, {}", error)))
+        .enumerate()
+        .map(|(idx, feature)| {
+            let geometry = feature
+                .geometry
+                .ok_or_else(|| Error::invalid_data(format!("Feature {} has no geometry", idx)))?;
+            let geometry = geo::Geometry::try_from(geometry)
+                .map_err(|err| Error::invalid_data(format!("Feature {}: {}", idx, err)))?;
+            let mut attributes = FeatureMap::new();
+            let mut null_fields = HashSet::new();
+            for (name, value) in feature.properties.into_iter().flatten() {
+                match json_to_field_value(value) {
+                    Some(value) => {
+                        attributes.insert(name, value);
+                    }
+                    None => {
+                        null_fields.insert(name);
+                    }
+                }
+            }
+            Ok(Feature {
+                geometry,
+                attributes: Some(attributes),
+                null_fields,
+            })
+        })
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("Reading {:?}", filepath))?;
+    Ok((features, crs))
+}
+
+/// The CRS named by a `crs` member, e.g. `urn:ogc:def:crs:EPSG::32654`.
+fn crs_from_named_crs_member(crs_member: &serde_json::Value) -> Result<Crs> {
+    let name = crs_member["properties"]["name"]
+        .as_str()
+        .ok_or_else(|| Error::invalid_data(format!("Unsupported crs member {}", crs_member)))?;
+    if matches!(
+        name,
+        "urn:ogc:def:crs:OGC:1.3:CRS84" | "urn:ogc:def:crs:OGC::CRS84" | "OGC:CRS84"
+    ) {
+        return Ok(epsg_4326());
+    }
+    name.strip_prefix("urn:ogc:def:crs:EPSG:")
+        .and_then(|code| code.rsplit(':').next())
+        .or_else(|| name.strip_prefix("EPSG:"))
+        .and_then(|code| code.parse().ok())
+        .map(Crs::Epsg)
+        .ok_or_else(|| Error::invalid_data(format!("Unsupported CRS name '{}'", name)))
+}
+
+/// The attribute value of a GeoJSON property, `None` for `null`.
+fn json_to_field_value(value: serde_json::Value) -> Option<FieldValue> {
+    Some(match value {
+        serde_json::Value::Null => return None,
+        serde_json::Value::Bool(value) => FieldValue::IntegerValue(value.into()),
+        serde_json::Value::Number(number) => number_to_field_value(&number),
+        serde_json::Value::String(value) => FieldValue::StringValue(value),
+        serde_json::Value::Array(values) => {
+            if let Some(values) = values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+            {
+                FieldValue::StringListValue(values)
+            } else if let Some(values) = values
+                .iter()
+                .map(|value| value.as_i64())
+                .collect::<Option<Vec<_>>>()
+            {
+                match values
+                    .iter()
+                    .map(|&value| i32::try_from(value).ok())
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(values) => FieldValue::IntegerListValue(values),
+                    None => FieldValue::Integer64ListValue(values),
+                }
+            } else if let Some(values) = values
+                .iter()
+                .map(|value| value.as_f64())
+                .collect::<Option<Vec<_>>>()
+            {
+                FieldValue::RealListValue(values)
+            } else {
+                FieldValue::StringValue(serde_json::Value::Array(values).to_string())
+            }
+        }
+        serde_json::Value::Object(_) => FieldValue::StringValue(value.to_string()),
+    })
+}
+
+fn number_to_field_value(number: &serde_json::Number) -> FieldValue {
+    match number.as_i64() {
+        Some(value) => match i32::try_from(value) {
+            Ok(value) => FieldValue::IntegerValue(value),
+            Err(_) => FieldValue::Integer64Value(value),
+        },
+        None => FieldValue::RealValue(number.as_f64().unwrap_or(f64::NAN)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use testdir::testdir;
+
+    use std::collections::HashSet;
+
+    use crate::{
+        crs::{crs_utils::epsg_4326, Crs},
+        error::Error,
+        geofile::feature::{Feature, FeatureMap, FieldValue},
+    };
+
+    use super::{
+        crs_from_named_crs_member, index_feature_ids, read_features_from_geojson,
+        read_lines_from_geojson, write_edges_to_geojson_with_options, write_feature_collection,
+        write_features_to_geojson, write_lines_to_geojson, write_lines_to_geojson_with_options,
+        GeoJsonLinesWriter, GeoJsonWriteOptions,
+    };
+
+    fn lines() -> Vec<geo::LineString> {
+        vec![
+            vec![(139.78, 35.68), (139.79, 35.69)].into(),
+            vec![(139.79, 35.69), (139.80, 35.67)].into(),
+        ]
+    }
+
+    fn read_json(filepath: &std::path::Path) -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(filepath).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_write_lines_to_geojson_bbox() {
+        let filepath = testdir!().join("lines.geojson");
+        write_lines_to_geojson(&lines(), &filepath).unwrap();
+
+        let json = read_json(&filepath);
+        assert_eq!(
+            serde_json::json!([139.78, 35.67, 139.80, 35.69]),
+            json["bbox"]
+        );
+        assert!(json["features"][0].get("bbox").is_none());
+        assert_eq!(1, json["features"][1]["id"]);
+        assert_eq!(1, json["features"][1]["properties"]["id"]);
+        assert!(json.get("crs").is_none());
+        assert_eq!(lines(), read_lines_from_geojson(&filepath).unwrap());
+    }
+
+    #[test]
+    fn test_write_lines_to_geojson_feature_bbox_and_crs() {
+        let filepath = testdir!().join("lines.geojson");
+        let crs = epsg_4326();
+        let options = GeoJsonWriteOptions {
+            feature_bbox: true,
+            crs: Some(&crs),
+            ..Default::default()
+        };
+        write_lines_to_geojson_with_options(&lines(), &filepath, &options).unwrap();
+
+        let json = read_json(&filepath);
+        assert_eq!(
+            serde_json::json!([139.78, 35.68, 139.79, 35.69]),
+            json["features"][0]["bbox"]
+        );
+        assert_eq!(
+            serde_json::json!([139.79, 35.67, 139.80, 35.69]),
+            json["features"][1]["bbox"]
+        );
+        assert_eq!(
+            "urn:ogc:def:crs:OGC:1.3:CRS84",
+            json["crs"]["properties"]["name"]
+        );
+    }
+
+    #[rstest]
+    #[case(false, None)]
+    #[case(true, Some("urn:ogc:def:crs:EPSG::32654"))]
+    fn test_write_lines_to_geojson_projected_crs(
+        #[case] force_projected_crs: bool,
+        #[case] expected_crs_name: Option<&str>,
+    ) {
+        let filepath = testdir!().join("lines.geojson");
+        let crs = crate::crs::Crs::Epsg(32654);
+        let options = GeoJsonWriteOptions {
+            crs: Some(&crs),
+            force_projected_crs,
+            ..Default::default()
+        };
+        let result = write_lines_to_geojson_with_options(&lines(), &filepath, &options);
+
+        match expected_crs_name {
+            Some(expected_crs_name) => {
+                result.unwrap();
+                assert_eq!(
+                    expected_crs_name,
+                    read_json(&filepath)["crs"]["properties"]["name"]
+                );
+            }
+            None => assert!(matches!(result, Err(Error::CrsMismatch(_)))),
+        }
+    }
+
+    #[test]
+    fn test_geojson_lines_writer_on_other_thread() {
+        let dir = testdir!();
+        let crs = crate::crs::Crs::Epsg(32654);
+        let options = GeoJsonWriteOptions {
+            feature_bbox: true,
+            crs: Some(&crs),
+            force_projected_crs: true,
+        };
+        write_lines_to_geojson_with_options(&lines(), &dir.join("direct.geojson"), &options)
+            .unwrap();
+        let writer =
+            GeoJsonLinesWriter::new(lines(), &dir.join("threaded.geojson"), &options).unwrap();
+
+        std::thread::spawn(move || writer.write())
+            .join()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            read_json(&dir.join("direct.geojson")),
+            read_json(&dir.join("threaded.geojson"))
+        );
+    }
+
+    #[test]
+    fn test_write_edges_to_geojson_sorted_by_edge_key() {
+        let filepath = testdir!().join("edges.geojson");
+        let line = |x: f64| -> geo::LineString { vec![(x, 0.0), (x, 1.0)].into() };
+        let edges = vec![
+            ((10, 1, 0), line(0.0)),
+            ((2, 10, 0), line(1.0)),
+            ((2, 3, 1), line(2.0)),
+            ((2, 3, 0), line(3.0)),
+        ];
+
+        write_edges_to_geojson_with_options(edges, &filepath, &GeoJsonWriteOptions::default())
+            .unwrap();
+
+        let json = read_json(&filepath);
+        let features = json["features"].as_array().unwrap();
+        let ids: Vec<&str> = features
+            .iter()
+            .map(|feature| feature["id"].as_str().unwrap())
+            .collect();
+        assert!(features
+            .iter()
+            .all(|feature| feature["id"] == feature["properties"]["id"]));
+        let keys: Vec<Vec<u64>> = ids
+            .iter()
+            .map(|id| id.split('-').map(|index| index.parse().unwrap()).collect())
+            .collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+        assert_eq!(vec!["2-3-0", "2-3-1", "2-10-0", "10-1-0"], ids);
+        assert_eq!(
+            vec![line(3.0), line(2.0), line(1.0), line(0.0)],
+            read_lines_from_geojson(&filepath).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_and_read_gzip_compressed_geojson() {
+        let filepath = testdir!().join("lines.geojson.gz");
+        let crs = epsg_4326();
+        let options = GeoJsonWriteOptions {
+            feature_bbox: true,
+            crs: Some(&crs),
+            ..Default::default()
+        };
+        write_lines_to_geojson_with_options(&lines(), &filepath, &options).unwrap();
+
+        assert!(std::fs::read(&filepath).unwrap().starts_with(&[0x1f, 0x8b]));
+        assert_eq!(lines(), read_lines_from_geojson(&filepath).unwrap());
+        #[cfg(feature = "gdal-io")]
+        {
+            let (features, _) =
+                crate::geofile::gdal_geofile::read_features_from_geofile(&filepath).unwrap();
+            assert_eq!(
+                lines(),
+                features
+                    .into_iter()
+                    .map(|feature| geo::LineString::try_from(feature.geometry).unwrap())
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    /// Counts the bytes written to it without keeping them, and fails on writes larger than `max_write_len`.
+    struct BoundedWriter {
+        max_write_len: usize,
+        written_len: usize,
+    }
+
+    impl std::io::Write for BoundedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.len() > self.max_write_len {
+                return Err(std::io::Error::other(format!(
+                    "Write of {} bytes",
+                    buf.len()
+                )));
+            }
+            self.written_len += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_feature_collection_streams_features() {
+        let lines: Vec<geo::LineString> = (0..100_000)
+            .map(|idx| vec![(idx as f64, 0.0), (idx as f64, 1.0)].into())
+            .collect();
+        let mut writer = BoundedWriter {
+            max_write_len: 1024,
+            written_len: 0,
+        };
+
+        write_feature_collection(
+            &mut writer,
+            &lines,
+            &index_feature_ids(lines.len()),
+            true,
+            None,
+        )
+        .unwrap();
+
+        // The document is far larger than any single write.
+        assert!(writer.written_len > 1000 * writer.max_write_len);
+    }
+
+    #[rstest]
+    #[case::plain("features.geojson")]
+    #[case::gzip("features.geojson.gz")]
+    fn test_write_and_read_features_with_attributes(#[case] filename: &str) {
+        let filepath = testdir!().join(filename);
+        let crs = Crs::Epsg(32654);
+        let features: Vec<Feature> = lines()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, line)| Feature {
+                geometry: line.into(),
+                attributes: Some(FeatureMap::from([
+                    (
+                        "name".to_string(),
+                        FieldValue::StringValue(format!("road {}", idx)),
+                    ),
+                    (
+                        "lanes".to_string(),
+                        FieldValue::IntegerValue(idx as i32 + 1),
+                    ),
+                    ("osm_id".to_string(), FieldValue::Integer64Value(1 << 40)),
+                    ("width".to_string(), FieldValue::RealValue(3.5)),
+                    (
+                        "tags".to_string(),
+                        FieldValue::StringListValue(vec!["a".to_string(), "b".to_string()]),
+                    ),
+                ])),
+                null_fields: HashSet::from(["ref".to_string()]),
+            })
+            .collect();
+        let options = GeoJsonWriteOptions {
+            crs: Some(&crs),
+            force_projected_crs: true,
+            ..Default::default()
+        };
+        write_features_to_geojson(&features, &filepath, &options).unwrap();
+
+        let (read_features, read_crs) = read_features_from_geojson(&filepath).unwrap();
+        assert_eq!(crs, read_crs);
+        assert_eq!(features, read_features);
+    }
+
+    #[test]
+    fn test_read_features_from_geojson_property_types() {
+        let filepath = testdir!().join("features.geojson");
+        std::fs::write(
+            &filepath,
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","geometry":{"type":"LineString",
+            "coordinates":[[0,0],[1,1]]},"properties":{"oneway":true,"maxspeed":[30,50],"factor":[0.5,2],
+            "nested":{"a":1},"mixed":[1,"a"]}}]}"#,
+        )
+        .unwrap();
+
+        let (features, crs) = read_features_from_geojson(&filepath).unwrap();
+        assert_eq!(epsg_4326(), crs);
+        let attributes = features[0].attributes.as_ref().unwrap();
+        assert_eq!(FieldValue::IntegerValue(1), attributes["oneway"]);
+        assert_eq!(
+            FieldValue::IntegerListValue(vec![30, 50]),
+            attributes["maxspeed"]
+        );
+        assert_eq!(
+            FieldValue::RealListValue(vec![0.5, 2.0]),
+            attributes["factor"]
+        );
+        assert_eq!(
+            FieldValue::StringValue(r#"{"a":1}"#.to_string()),
+            attributes["nested"]
+        );
+        assert_eq!(
+            FieldValue::StringValue(r#"[1,"a"]"#.to_string()),
+            attributes["mixed"]
+        );
+    }
+
+    #[rstest]
+    #[case::crs84("urn:ogc:def:crs:OGC:1.3:CRS84", Some(4326))]
+    #[case::urn("urn:ogc:def:crs:EPSG::32654", Some(32654))]
+    #[case::versioned_urn("urn:ogc:def:crs:EPSG:6.6:3857", Some(3857))]
+    #[case::short("EPSG:2056", Some(2056))]
+    #[case::unsupported("urn:ogc:def:crs:ESRI::102100", None)]
+    fn test_crs_from_named_crs_member(#[case] name: &str, #[case] expected: Option<u32>) {
+        let crs_member = serde_json::json!({"type": "name", "properties": {"name": name}});
+        match expected {
+            Some(code) => assert_eq!(
+                Crs::Epsg(code),
+                crs_from_named_crs_member(&crs_member).unwrap()
+            ),
+            None => assert!(matches!(
+                crs_from_named_crs_member(&crs_member),
+                Err(Error::InvalidData { .. })
+            )),
+        }
+    }
 }
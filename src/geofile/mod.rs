@@ -1,3 +1,5 @@
+pub mod centerline;
 pub mod feature;
+#[cfg(feature = "gdal-io")]
 pub mod gdal_geofile;
 pub mod geojson;
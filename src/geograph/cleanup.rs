@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+
+use geo::{EuclideanDistance, EuclideanLength};
+
+use crate::error::Result;
+
+use super::primitives::{GeoEdge, GeoGraph, NodeIdx};
+
+/// Remove nearly identical parallel edges, e.g. duplicate detections of the same road in a proposal.
+///
+/// The parallel edges between each pair of nodes are visited from longest to shortest. An edge is removed if its
+/// distance to a longer edge which is kept is at most `tolerance`. The distance of two edges is the largest distance
+/// of a vertex of either edge to the geometry of the other, a vertex-based Hausdorff distance. The data of removed
+/// edges is dropped.
+///
+/// Only edges between the same pair of nodes are compared, duplicates which end in distinct nodes are kept.
+///
+/// # Returns
+/// The number of removed edges.
+pub fn deduplicate_parallel_edges<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    graph: &mut GeoGraph<E, N, Ty>,
+    tolerance: f64,
+) -> usize {
+    let mut removed_count = 0;
+    for (_, _, par_edges) in graph.edge_graph_mut().all_edges_mut() {
+        if par_edges.len() < 2 {
+            continue;
+        }
+        let mut edges: Vec<GeoEdge<E>> = std::mem::take(par_edges);
+        // Stable sort to keep the order of equally long edges.
+        edges.sort_by(|a, b| {
+            b.geometry
+                .euclidean_length()
+                .total_cmp(&a.geometry.euclidean_length())
+        });
+        for edge in edges {
+            let is_duplicate = par_edges.iter().any(|kept_edge: &GeoEdge<E>| {
+                vertex_hausdorff_distance(&kept_edge.geometry, &edge.geometry) <= tolerance
+            });
+            if is_duplicate {
+                removed_count += 1;
+            } else {
+                par_edges.push(edge);
+            }
+        }
+    }
+    debug_assert!(graph.validate_invariants().is_ok());
+    removed_count
+}
+
+/// Find the reciprocal edge pairs of a directed graph, e.g. the two directions of a two-way street which are
+/// represented as two opposing edges with the same geometry. An edge from a node A to a node B and an edge from B to A
+/// are reciprocal if their distance is at most `tolerance`, like for `deduplicate_parallel_edges`.
+///
+/// Each edge is in at most one pair, and loops are never paired. Undirected graphs have no reciprocal edges, as their
+/// opposing edges are parallel edges.
+///
+/// # Returns
+/// The pairs as indices in the order of `GeoGraph::edges_sorted`, the smaller index first.
+pub fn reciprocal_edge_pairs<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    tolerance: f64,
+) -> Vec<(usize, usize)> {
+    if !graph.edge_graph().is_directed() {
+        return Vec::new();
+    }
+    let edges: Vec<(NodeIdx, NodeIdx, usize, &GeoEdge<E>)> = graph.edges_sorted().collect();
+    let mut edge_indices_by_nodes: HashMap<(NodeIdx, NodeIdx), Vec<usize>> = HashMap::new();
+    for (edge_idx, (start_node_idx, end_node_idx, _, _)) in edges.iter().enumerate() {
+        edge_indices_by_nodes
+            .entry((*start_node_idx, *end_node_idx))
+            .or_default()
+            .push(edge_idx);
+    }
+    let mut paired = vec![false; edges.len()];
+    let mut pairs = Vec::new();
+    for (edge_idx, (start_node_idx, end_node_idx, _, edge)) in edges.iter().enumerate() {
+        if start_node_idx == end_node_idx || paired[edge_idx] {
+            continue;
+        }
+        let Some(opposing_edge_indices) =
+            edge_indices_by_nodes.get(&(*end_node_idx, *start_node_idx))
+        else {
+            continue;
+        };
+        let reciprocal_edge_idx = opposing_edge_indices
+            .iter()
+            .copied()
+            .find(|opposing_edge_idx| {
+                !paired[*opposing_edge_idx]
+                    && vertex_hausdorff_distance(
+                        &edge.geometry,
+                        &edges[*opposing_edge_idx].3.geometry,
+                    ) <= tolerance
+            });
+        if let Some(reciprocal_edge_idx) = reciprocal_edge_idx {
+            paired[edge_idx] = true;
+            paired[reciprocal_edge_idx] = true;
+            pairs.push((edge_idx, reciprocal_edge_idx));
+        }
+    }
+    pairs
+}
+
+fn vertex_hausdorff_distance(a: &geo::LineString, b: &geo::LineString) -> f64 {
+    let directed_distance = |from: &geo::LineString, to: &geo::LineString| -> f64 {
+        from.points()
+            .map(|point| point.euclidean_distance(to))
+            .fold(0.0, f64::max)
+    };
+    directed_distance(a, b).max(directed_distance(b, a))
+}
+
+/// A segment of an edge geometry in an R-tree, with the index of the edge and of the segment within the geometry.
+type IndexedSegment =
+    rstar::primitives::GeomWithData<rstar::primitives::Line<[f64; 2]>, (usize, usize)>;
+
+/// A node in an R-tree, with its index.
+pub(crate) type IndexedNode = rstar::primitives::GeomWithData<[f64; 2], NodeIdx>;
+
+/// A point at which to split an edge: the index of the geometry segment it is on, its position along the segment as
+/// a fraction of the segment length, and its coordinate.
+type SplitPoint = (usize, f64, geo::Coord);
+
+/// Split the edges of `graph_a` where they pass within `tolerance` of a node of `graph_b`, e.g. where one graph models
+/// a T-junction with a node and the other runs a straight edge through it.
+///
+/// For each node of `graph_b`, each edge of `graph_a` within `tolerance` is split at the point of its geometry which
+/// is nearest to the node, unless that point is within `tolerance` of an end node of the edge. A node is inserted at
+/// the split point, or, if a node of `graph_a` is within `tolerance` of it, e.g. the dangling end of a side road, the
+/// edge is split at that node instead. Split points on the same edge within `tolerance` of each other are merged.
+///
+/// The parts of a split edge keep the orientation of its geometry and a copy of its data. Inserted nodes get default
+/// data and indices after the largest node index of `graph_a`.
+///
+/// # Returns
+/// The number of points at which edges were split.
+pub fn split_edges_at_near_nodes<E, N, E2, N2, Ty>(
+    graph_a: &mut GeoGraph<E, N, Ty>,
+    graph_b: &GeoGraph<E2, N2, Ty>,
+    tolerance: f64,
+) -> Result<usize>
+where
+    E: Default + Clone,
+    N: Default,
+    E2: Default,
+    N2: Default,
+    Ty: petgraph::EdgeType,
+{
+    let squared_tolerance = tolerance * tolerance;
+    let edges: Vec<(NodeIdx, NodeIdx, usize)> = graph_a
+        .edges_sorted()
+        .map(|(start_node_idx, end_node_idx, par_edge_idx, _)| {
+            (start_node_idx, end_node_idx, par_edge_idx)
+        })
+        .collect();
+    let segment_tree = rstar::RTree::bulk_load(
+        graph_a
+            .edges_sorted()
+            .enumerate()
+            .flat_map(|(edge_idx, (_, _, _, edge))| {
+                edge.geometry
+                    .lines()
+                    .enumerate()
+                    .map(move |(segment_idx, line)| {
+                        IndexedSegment::new(
+                            rstar::primitives::Line::new(
+                                [line.start.x, line.start.y],
+                                [line.end.x, line.end.y],
+                            ),
+                            (edge_idx, segment_idx),
+                        )
+                    })
+            })
+            .collect(),
+    );
+    let node_tree = rstar::RTree::bulk_load(
+        graph_a
+            .node_map()
+            .iter()
+            .map(|(idx, node)| IndexedNode::new([node.geometry.x(), node.geometry.y()], *idx))
+            .collect(),
+    );
+    let within_tolerance = |a: &geo::Coord, b: &geo::Coord| {
+        let delta = *a - *b;
+        delta.x * delta.x + delta.y * delta.y <= squared_tolerance
+    };
+
+    // The split points of each edge, by edge index.
+    let mut split_points: HashMap<usize, Vec<SplitPoint>> = HashMap::new();
+    for (_, node) in graph_b.nodes_sorted() {
+        // The nearest point of each edge within tolerance, and its distance.
+        let mut nearest_points: HashMap<usize, (f64, SplitPoint)> = HashMap::new();
+        let point = [node.geometry.x(), node.geometry.y()];
+        for segment in segment_tree.locate_within_distance(point, squared_tolerance) {
+            let (edge_idx, segment_idx) = segment.data;
+            let (fraction, coord) = nearest_point_on_segment(segment.geom(), &node.geometry.0);
+            let distance = coord.euclidean_distance(&node.geometry.0);
+            let nearest = nearest_points
+                .entry(edge_idx)
+                .or_insert((distance, (segment_idx, fraction, coord)));
+            if distance < nearest.0 {
+                *nearest = (distance, (segment_idx, fraction, coord));
+            }
+        }
+        for (edge_idx, (_, split_point)) in nearest_points {
+            let (start_node_idx, end_node_idx, _) = edges[edge_idx];
+            let is_near_end_node = [start_node_idx, end_node_idx]
+                .iter()
+                .any(|idx| within_tolerance(&graph_a.node_map()[idx].geometry.0, &split_point.2));
+            if !is_near_end_node {
+                split_points.entry(edge_idx).or_default().push(split_point);
+            }
+        }
+    }
+
+    // Split the edges in reverse order, so that removing a parallel edge does not shift the indices of the
+    // parallel edges which are split later.
+    let mut split_edge_indices: Vec<usize> = split_points.keys().copied().collect();
+    split_edge_indices.sort();
+    let mut next_node_idx = graph_a.node_map().keys().max().map_or(0, |idx| idx + 1);
+    let mut inserted_nodes: HashMap<(u64, u64), NodeIdx> = HashMap::new();
+    let mut split_count = 0;
+    for edge_idx in split_edge_indices.into_iter().rev() {
+        let (start_node_idx, end_node_idx, par_edge_idx) = edges[edge_idx];
+        let mut edge_split_points = split_points.remove(&edge_idx).unwrap();
+        edge_split_points.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        edge_split_points.dedup_by(|later, earlier| within_tolerance(&later.2, &earlier.2));
+
+        let par_edges = graph_a
+            .edge_graph_mut()
+            .edge_weight_mut(start_node_idx, end_node_idx)
+            .unwrap();
+        let edge = par_edges.remove(par_edge_idx);
+        if par_edges.is_empty() {
+            graph_a
+                .edge_graph_mut()
+                .remove_edge(start_node_idx, end_node_idx);
+        }
+        // The node at the first coordinate of the geometry, which may be the end node in undirected graphs.
+        let (first_node_idx, last_node_idx) =
+            if graph_a.node_map()[&start_node_idx].geometry.0 == edge.geometry.0[0] {
+                (start_node_idx, end_node_idx)
+            } else {
+                (end_node_idx, start_node_idx)
+            };
+
+        let mut part_start_node_idx = first_node_idx;
+        let mut part_coords = vec![edge.geometry.0[0]];
+        let mut next_coord_idx = 1;
+        for (segment_idx, _, coord) in edge_split_points {
+            let (split_node_idx, split_coord) = match node_tree
+                .nearest_neighbor(&[coord.x, coord.y])
+                .filter(|node| within_tolerance(&geo::Coord::from(*node.geom()), &coord))
+            {
+                Some(node) => (node.data, geo::Coord::from(*node.geom())),
+                None => {
+                    let idx = *inserted_nodes
+                        .entry((coord.x.to_bits(), coord.y.to_bits()))
+                        .or_insert_with(|| {
+                            next_node_idx += 1;
+                            next_node_idx - 1
+                        });
+                    (idx, coord)
+                }
+            };
+            extend_distinct(
+                &mut part_coords,
+                &edge.geometry.0[next_coord_idx..=segment_idx],
+            );
+            extend_distinct(&mut part_coords, &[split_coord]);
+            next_coord_idx = segment_idx + 1;
+            if part_coords.len() < 2 {
+                // Split points merged into the same existing node.
+                continue;
+            }
+            graph_a.insert_edge_with_data(
+                part_start_node_idx,
+                split_node_idx,
+                std::mem::replace(&mut part_coords, vec![split_coord]).into(),
+                edge.data.clone(),
+            )?;
+            part_start_node_idx = split_node_idx;
+            split_count += 1;
+        }
+        extend_distinct(&mut part_coords, &edge.geometry.0[next_coord_idx..]);
+        graph_a.insert_edge_with_data(
+            part_start_node_idx,
+            last_node_idx,
+            part_coords.into(),
+            edge.data,
+        )?;
+    }
+    debug_assert!(graph_a.validate_invariants().is_ok());
+    Ok(split_count)
+}
+
+/// The point of a segment nearest to `coord`, and its position along the segment as a fraction of its length.
+fn nearest_point_on_segment(
+    segment: &rstar::primitives::Line<[f64; 2]>,
+    coord: &geo::Coord,
+) -> (f64, geo::Coord) {
+    let start = geo::Coord::from(segment.from);
+    let direction = geo::Coord::from(segment.to) - start;
+    let squared_length = direction.x * direction.x + direction.y * direction.y;
+    let fraction = if squared_length > 0.0 {
+        let offset = *coord - start;
+        ((offset.x * direction.x + offset.y * direction.y) / squared_length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (fraction, start + direction * fraction)
+}
+
+/// Append the coordinates which differ from the previous one.
+pub(crate) fn extend_distinct(coords: &mut Vec<geo::Coord>, new_coords: &[geo::Coord]) {
+    for coord in new_coords {
+        if coords.last() != Some(coord) {
+            coords.push(*coord);
+        }
+    }
+}
+
+#[cfg(test)]
+#[generic_tests::define]
+mod tests {
+    use crate::{
+        crs::crs_utils::epsg_4326,
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_with_data},
+    };
+
+    use super::{deduplicate_parallel_edges, reciprocal_edge_pairs, split_edges_at_near_nodes};
+
+    /// Two edges between the same nodes, 0.5 apart in the middle. The second one is longer.
+    fn build_graph_with_parallel_edges<Ty: petgraph::EdgeType>() -> GeoGraph<String, (), Ty> {
+        let mut graph: GeoGraph<String, (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge_with_data(
+                0,
+                1,
+                vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)].into(),
+                "short".to_string(),
+            )
+            .unwrap();
+        graph
+            .insert_edge_with_data(
+                0,
+                1,
+                vec![(0.0, 0.0), (5.0, 0.5), (10.0, 0.0)].into(),
+                "long".to_string(),
+            )
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_deduplicate_parallel_edges_within_tolerance<Ty: petgraph::EdgeType>() {
+        let mut graph = build_graph_with_parallel_edges::<Ty>();
+
+        assert_eq!(1, deduplicate_parallel_edges(&mut graph, 1.0));
+
+        let par_edges = graph.edge_graph().edge_weight(0, 1).unwrap();
+        assert_eq!(1, par_edges.len());
+        // The longer edge survives with its data.
+        assert_eq!("long", par_edges[0].data);
+    }
+
+    #[test]
+    fn test_deduplicate_parallel_edges_beyond_tolerance<Ty: petgraph::EdgeType>() {
+        let mut graph = build_graph_with_parallel_edges::<Ty>();
+
+        assert_eq!(0, deduplicate_parallel_edges(&mut graph, 0.1));
+
+        assert_eq!(2, graph.edge_graph().edge_weight(0, 1).unwrap().len());
+    }
+
+    #[test]
+    fn test_reciprocal_edge_pairs<Ty: petgraph::EdgeType>() {
+        let graph = build_graph::<Ty>(vec![
+            // A two-way street as two opposing edges, the second one digitized slightly differently.
+            vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)],
+            vec![(10.0, 0.0), (5.0, 0.2), (0.0, 0.0)],
+            // A one-way street.
+            vec![(10.0, 0.0), (10.0, 10.0)],
+            // A detour back to the start of the two-way street, too far from it to be reciprocal.
+            vec![(10.0, 0.0), (5.0, 5.0), (0.0, 0.0)],
+        ]);
+
+        let pairs = reciprocal_edge_pairs(&graph, 1.0);
+
+        if Ty::is_directed() {
+            assert_eq!(1, pairs.len());
+            let (first_edge_idx, second_edge_idx) = pairs[0];
+            let edge_data: Vec<&String> = graph
+                .edges_sorted()
+                .map(|(_, _, _, edge)| &edge.data)
+                .collect();
+            assert_eq!("edge 0", edge_data[first_edge_idx]);
+            assert_eq!("edge 1", edge_data[second_edge_idx]);
+        } else {
+            assert!(pairs.is_empty());
+        }
+    }
+
+    fn build_graph<Ty: petgraph::EdgeType>(
+        lines: Vec<Vec<(f64, f64)>>,
+    ) -> GeoGraph<String, (), Ty> {
+        let data = (0..lines.len())
+            .map(|idx| format!("edge {}", idx))
+            .collect();
+        build_geograph_from_lines_with_data(
+            lines.into_iter().map(|line| line.into()).collect(),
+            data,
+        )
+        .unwrap()
+    }
+
+    fn edge_geometry<Ty: petgraph::EdgeType>(
+        graph: &GeoGraph<String, (), Ty>,
+        start_node_idx: u64,
+        end_node_idx: u64,
+    ) -> geo::LineString {
+        graph
+            .edge_graph()
+            .edge_weight(start_node_idx, end_node_idx)
+            .unwrap()[0]
+            .geometry
+            .clone()
+    }
+
+    #[test]
+    fn test_split_edges_at_near_nodes_inserts_node<Ty: petgraph::EdgeType>() {
+        let mut graph_a = build_graph::<Ty>(vec![vec![(0.0, 0.0), (40.0, 0.0), (100.0, 0.0)]]);
+        let graph_b = build_graph::<Ty>(vec![vec![(50.0, 0.5), (50.0, 30.0)]]);
+
+        assert_eq!(
+            1,
+            split_edges_at_near_nodes(&mut graph_a, &graph_b, 1.0).unwrap()
+        );
+
+        assert_eq!(3, graph_a.node_map().len());
+        assert_eq!(
+            geo::Point::new(50.0, 0.0),
+            graph_a.node_map().get(&2).unwrap().geometry
+        );
+        assert_eq!(2, graph_a.edge_count());
+        assert_eq!(
+            geo::LineString::from(vec![(0.0, 0.0), (40.0, 0.0), (50.0, 0.0)]),
+            edge_geometry(&graph_a, 0, 2)
+        );
+        assert_eq!(
+            geo::LineString::from(vec![(50.0, 0.0), (100.0, 0.0)]),
+            edge_geometry(&graph_a, 2, 1)
+        );
+        // Both parts keep the data of the split edge.
+        for (_, _, _, edge) in graph_a.edges_sorted() {
+            assert_eq!("edge 0", edge.data);
+        }
+        assert!(graph_a.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_split_edges_at_near_nodes_reuses_near_node<Ty: petgraph::EdgeType>() {
+        // A T-junction whose side road ends next to the main road, without a node on it.
+        let mut graph_a = build_graph::<Ty>(vec![
+            vec![(0.0, 0.0), (100.0, 0.0)],
+            vec![(50.0, 0.5), (50.0, 30.0)],
+        ]);
+        let graph_b = build_graph::<Ty>(vec![vec![(0.0, 0.0), (50.0, 0.0)]]);
+
+        assert_eq!(
+            1,
+            split_edges_at_near_nodes(&mut graph_a, &graph_b, 1.0).unwrap()
+        );
+
+        assert_eq!(4, graph_a.node_map().len());
+        assert_eq!(3, graph_a.edge_count());
+        assert_eq!(
+            geo::LineString::from(vec![(0.0, 0.0), (50.0, 0.5)]),
+            edge_geometry(&graph_a, 0, 2)
+        );
+        assert_eq!(
+            geo::LineString::from(vec![(50.0, 0.5), (100.0, 0.0)]),
+            edge_geometry(&graph_a, 2, 1)
+        );
+    }
+
+    #[test]
+    fn test_split_edges_at_near_nodes_not_near_end_node<Ty: petgraph::EdgeType>() {
+        let mut graph_a = build_graph::<Ty>(vec![vec![(0.0, 0.0), (100.0, 0.0)]]);
+        let graph_b = build_graph::<Ty>(vec![vec![(0.5, 0.5), (0.5, 30.0)]]);
+
+        assert_eq!(
+            0,
+            split_edges_at_near_nodes(&mut graph_a, &graph_b, 1.0).unwrap()
+        );
+
+        assert_eq!(1, graph_a.edge_count());
+    }
+
+    #[instantiate_tests(<petgraph::Directed>)]
+    mod directed {}
+
+    #[instantiate_tests(<petgraph::Undirected>)]
+    mod undirected {}
+}
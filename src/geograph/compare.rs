@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::geofile::feature::{Feature, FieldValue};
+
+use super::primitives::{GeoGraph, NodeIdx};
+
+/// Cell of a coordinate in a grid of `tolerance` sized cells. Coordinates within `tolerance` of each other are in the
+/// same or adjacent cells. With a tolerance of zero, each distinct coordinate is its own cell.
+type Cell = (i64, i64);
+
+fn coord_to_cell(coord: &geo::Coord, tolerance: f64) -> Cell {
+    if tolerance > 0.0 {
+        (
+            (coord.x / tolerance).floor() as i64,
+            (coord.y / tolerance).floor() as i64,
+        )
+    } else {
+        (coord.x.to_bits() as i64, coord.y.to_bits() as i64)
+    }
+}
+
+/// The geometry hash of an edge: the cells of its endpoints, in a canonical order so that reversed geometries have
+/// the same hash.
+fn endpoint_cells_key(start: Cell, end: Cell) -> (Cell, Cell) {
+    (start.min(end), start.max(end))
+}
+
+/// The cell and, if the tolerance is positive, its neighbors.
+fn neighborhood(cell: Cell, tolerance: f64) -> Vec<Cell> {
+    if tolerance > 0.0 {
+        (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (cell.0 + dx, cell.1 + dy)))
+            .collect()
+    } else {
+        vec![cell]
+    }
+}
+
+/// Whether two geometries have the same number of coordinates, and each pair of coordinates is within `tolerance`,
+/// in the same or in reversed orientation.
+fn geometries_equal_within(a: &geo::LineString, b: &geo::LineString, tolerance: f64) -> bool {
+    let coords_within = |a: &geo::Coord, b: &geo::Coord| {
+        (a.x - b.x).abs() <= tolerance && (a.y - b.y).abs() <= tolerance
+    };
+    a.0.len() == b.0.len()
+        && (a.0.iter().zip(b.0.iter()).all(|(a, b)| coords_within(a, b))
+            || a.0
+                .iter()
+                .zip(b.0.iter().rev())
+                .all(|(a, b)| coords_within(a, b)))
+}
+
+fn edge_to_feature(
+    start_node_idx: NodeIdx,
+    end_node_idx: NodeIdx,
+    geometry: &geo::LineString,
+) -> Feature {
+    Feature {
+        geometry: geo::Geometry::LineString(geometry.clone()),
+        attributes: Some(HashMap::from([
+            (
+                "start_node".to_string(),
+                FieldValue::Integer64Value(start_node_idx as i64),
+            ),
+            (
+                "end_node".to_string(),
+                FieldValue::Integer64Value(end_node_idx as i64),
+            ),
+        ])),
+        null_fields: Default::default(),
+    }
+}
+
+/// Find the edges of each graph which have no geometrically identical counterpart in the other graph, e.g. to check a
+/// data pipeline for regressions before running the full metric.
+///
+/// Two edges match if their geometries have the same number of coordinates, and each coordinate is within
+/// `tolerance` of the corresponding one along either orientation. Each edge matches at most one edge of the other
+/// graph, so duplicated edges need duplicated counterparts. Candidates are looked up by the grid cells of their
+/// endpoints instead of comparing all pairs of edges.
+///
+/// # Returns
+/// The unmatched edges of `a` and of `b`, as LineString features with their start and end node indices.
+pub fn edge_symmetric_difference<E1, N1, E2, N2, Ty>(
+    a: &GeoGraph<E1, N1, Ty>,
+    b: &GeoGraph<E2, N2, Ty>,
+    tolerance: f64,
+) -> (Vec<Feature>, Vec<Feature>)
+where
+    E1: Default,
+    N1: Default,
+    E2: Default,
+    N2: Default,
+    Ty: petgraph::EdgeType,
+{
+    let b_edges: Vec<(NodeIdx, NodeIdx, &geo::LineString)> = b
+        .edges_sorted()
+        .map(|(start_node_idx, end_node_idx, _, edge)| {
+            (start_node_idx, end_node_idx, &edge.geometry)
+        })
+        .collect();
+    let endpoint_cells = |geometry: &geo::LineString| -> (Cell, Cell) {
+        (
+            coord_to_cell(geometry.0.first().unwrap(), tolerance),
+            coord_to_cell(geometry.0.last().unwrap(), tolerance),
+        )
+    };
+    let mut b_edges_by_key: HashMap<(Cell, Cell), Vec<usize>> = HashMap::new();
+    for (b_edge_idx, (_, _, geometry)) in b_edges.iter().enumerate() {
+        let (start, end) = endpoint_cells(geometry);
+        b_edges_by_key
+            .entry(endpoint_cells_key(start, end))
+            .or_default()
+            .push(b_edge_idx);
+    }
+
+    let mut matched_b_edges = HashSet::new();
+    let mut only_in_a = Vec::new();
+    for (start_node_idx, end_node_idx, _, edge) in a.edges_sorted() {
+        let (start, end) = endpoint_cells(&edge.geometry);
+        let start_cells = neighborhood(start, tolerance);
+        let end_cells = neighborhood(end, tolerance);
+        let matched_b_edge = start_cells
+            .iter()
+            .flat_map(|start| end_cells.iter().map(|end| endpoint_cells_key(*start, *end)))
+            .filter_map(|key| b_edges_by_key.get(&key))
+            .flatten()
+            .find(|b_edge_idx| {
+                !matched_b_edges.contains(*b_edge_idx)
+                    && geometries_equal_within(&edge.geometry, b_edges[**b_edge_idx].2, tolerance)
+            })
+            .copied();
+        match matched_b_edge {
+            Some(b_edge_idx) => {
+                matched_b_edges.insert(b_edge_idx);
+            }
+            None => only_in_a.push(edge_to_feature(
+                start_node_idx,
+                end_node_idx,
+                &edge.geometry,
+            )),
+        }
+    }
+    let only_in_b = b_edges
+        .iter()
+        .enumerate()
+        .filter(|(b_edge_idx, _)| !matched_b_edges.contains(b_edge_idx))
+        .map(|(_, (start_node_idx, end_node_idx, geometry))| {
+            edge_to_feature(*start_node_idx, *end_node_idx, geometry)
+        })
+        .collect();
+    (only_in_a, only_in_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_in_crs};
+
+    use super::edge_symmetric_difference;
+
+    fn build_graph(lines: Vec<Vec<(f64, f64)>>) -> GeoGraph<(), (), petgraph::Undirected> {
+        build_geograph_from_lines_in_crs(
+            lines.into_iter().map(geo::LineString::from).collect(),
+            crate::crs::Crs::Epsg(32654),
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    // Identical graphs.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)], 0.0, 0, 0)]
+    // The same edge digitized in reverse.
+    #[case(vec![(10.0, 10.0), (10.0, 0.0), (0.0, 0.0)], 0.0, 0, 0)]
+    // Coordinates within the tolerance, across a cell boundary.
+    #[case(vec![(0.0, -0.05), (10.0, 0.05), (10.0, 10.0)], 0.1, 0, 0)]
+    // Coordinates outside the tolerance.
+    #[case(vec![(0.0, -0.05), (10.0, 0.05), (10.0, 10.0)], 0.01, 1, 1)]
+    // Different intermediate vertex.
+    #[case(vec![(0.0, 0.0), (0.0, 10.0), (10.0, 10.0)], 0.0, 1, 1)]
+    fn test_edge_symmetric_difference(
+        #[case] b_line: Vec<(f64, f64)>,
+        #[case] tolerance: f64,
+        #[case] expected_only_in_a: usize,
+        #[case] expected_only_in_b: usize,
+    ) {
+        let shared_line = vec![(20.0, 0.0), (30.0, 0.0)];
+        let a = build_graph(vec![
+            shared_line.clone(),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)],
+        ]);
+        let b = build_graph(vec![shared_line, b_line]);
+
+        let (only_in_a, only_in_b) = edge_symmetric_difference(&a, &b, tolerance);
+
+        assert_eq!(expected_only_in_a, only_in_a.len());
+        assert_eq!(expected_only_in_b, only_in_b.len());
+    }
+
+    #[test]
+    fn test_edge_symmetric_difference_extra_edge() {
+        let a = build_graph(vec![vec![(0.0, 0.0), (10.0, 0.0)]]);
+        let b = build_graph(vec![
+            vec![(0.0, 0.0), (10.0, 0.0)],
+            vec![(10.0, 0.0), (10.0, 10.0)],
+        ]);
+
+        let (only_in_a, only_in_b) = edge_symmetric_difference(&a, &b, 0.0);
+
+        assert!(only_in_a.is_empty());
+        assert_eq!(1, only_in_b.len());
+        assert_eq!(
+            geo::Geometry::LineString(vec![(10.0, 0.0), (10.0, 10.0)].into()),
+            only_in_b[0].geometry
+        );
+    }
+}
@@ -1,22 +1,53 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::collections::HashMap;
+#[cfg(feature = "gdal-io")]
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "gdal-io")]
+use geo::{BoundingRect, EuclideanLength};
 
 use crate::{
-    geofile::{
-        feature::{Feature, FeatureMap},
-        gdal_geofile::read_features_from_geofile,
-    },
+    crs::{crs_utils::epsg_4326, Crs},
+    error::{Error, Result},
+    geofile::feature::{Feature, FeatureMap},
     geograph,
 };
+#[cfg(feature = "gdal-io")]
+use crate::{
+    diagnostics::warnings::{warn, WarningCategory, WarningCollector},
+    geofile::gdal_geofile::{
+        for_each_feature_with_fid_in_geofile_with_options, read_geofile_crs, OnCoordinateOutliers,
+        ReadOptions, ReadReport,
+    },
+};
 
 use super::primitives::GeoGraph;
+#[cfg(feature = "gdal-io")]
+use super::utils::GeoGraphBuilder;
 
 /// A GeoGraph whose edge and node data type is a FeatureMap. Can be constructed from features read from a geofile.
 pub type GeoFeatureGraph<Ty> = GeoGraph<FeatureMap, FeatureMap, Ty>;
 
 impl<Ty: petgraph::EdgeType> TryFrom<Vec<Feature>> for GeoFeatureGraph<Ty> {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(features: Vec<Feature>) -> anyhow::Result<Self> {
+    /// Build a graph in EPSG:4326, use `GeoFeatureGraph::from_features_in_crs` for features in another CRS.
+    fn try_from(features: Vec<Feature>) -> Result<Self> {
+        Self::from_features_in_crs(features, epsg_4326(), None)
+    }
+}
+
+impl<Ty: petgraph::EdgeType> GeoFeatureGraph<Ty> {
+    /// Build a graph from the LineString features in the given CRS. Other features are skipped with a warning.
+    /// The coordinates are quantized to a grid of `coordinate_precision` if given, see
+    /// `GeoGraphBuilder::coordinate_precision`.
+    pub fn from_features_in_crs(
+        features: Vec<Feature>,
+        crs: Crs,
+        coordinate_precision: Option<f64>,
+    ) -> Result<Self> {
         let num_features = features.len();
         let (lines, data): (Vec<geo::LineString>, Vec<FeatureMap>) = features
             .into_iter()
@@ -34,15 +65,641 @@ impl<Ty: petgraph::EdgeType> TryFrom<Vec<Feature>> for GeoFeatureGraph<Ty> {
                 lines.len()
             )
         }
-        geograph::utils::build_geograph_from_lines_with_data(lines, data)
+        geograph::utils::build_geograph_from_lines_with_data_and_precision(
+            lines,
+            data,
+            crs,
+            coordinate_precision,
+        )
     }
-}
 
-impl<Ty: petgraph::EdgeType> GeoFeatureGraph<Ty> {
-    pub fn load_from_geofile(filepath: &PathBuf) -> anyhow::Result<Self> {
-        let (features, spatial_ref) = read_features_from_geofile(filepath)?;
-        let mut graph: GeoFeatureGraph<Ty> = features.try_into()?;
-        graph.crs = spatial_ref;
+    /// Load a graph from the LineString features of a single-layer geofile. Features are streamed into the graph
+    /// as they are read, so the whole feature collection is never held in memory.
+    ///
+    /// The CRS of the graph is `crs_override` if given, otherwise the CRS declared by the geofile, or EPSG:4326 if
+    /// the geofile does not declare one.
+    #[cfg(feature = "gdal-io")]
+    pub fn load_from_geofile(filepath: &PathBuf, crs_override: Option<&Crs>) -> Result<Self> {
+        let (graph, _) =
+            Self::load_from_geofile_with_options(filepath, crs_override, &ReadOptions::default())?;
         Ok(graph)
     }
+
+    /// Like `load_from_geofile`, but features which cannot be decoded are handled as set in `read_options`.
+    ///
+    /// # Returns
+    /// The graph, and the features which were skipped.
+    #[cfg(feature = "gdal-io")]
+    pub fn load_from_geofile_with_options(
+        filepath: &PathBuf,
+        crs_override: Option<&Crs>,
+        read_options: &ReadOptions,
+    ) -> Result<(Self, ReadReport)> {
+        let crs = match (crs_override, read_geofile_crs(filepath, None)?) {
+            (Some(crs_override), Some(declared_crs)) => {
+                if *crs_override != declared_crs {
+                    warn(
+                        read_options.warning_collector(),
+                        WarningCategory::CrsOverride,
+                        1,
+                        format!(
+                            "Overriding CRS '{}' declared by {:?} with '{}'",
+                            declared_crs.name()?,
+                            filepath,
+                            crs_override.name()?
+                        ),
+                    );
+                }
+                crs_override.clone()
+            }
+            (Some(crs_override), None) => crs_override.clone(),
+            (None, Some(declared_crs)) => declared_crs,
+            (None, None) => epsg_4326(),
+        };
+        let mut lines = read_lines(filepath, crs.clone(), read_options, &HashSet::new())?;
+        let outliers = match read_options.get_coordinate_outliers() {
+            OnCoordinateOutliers::Keep => Vec::new(),
+            OnCoordinateOutliers::Exclude | OnCoordinateOutliers::Fail => {
+                coordinate_outliers(&lines.extents)
+            }
+        };
+        if !outliers.is_empty() {
+            let message = format!(
+                "{} line features of {:?} have coordinates far outside the range of the others, likely in another \
+                CRS than the declared '{}', FIDs: {}",
+                outliers.len(),
+                filepath,
+                crs.name()?,
+                format_fids(&outliers)
+            );
+            if read_options.get_coordinate_outliers() == OnCoordinateOutliers::Fail {
+                return Err(Error::CrsMismatch(message));
+            }
+            warn(
+                read_options.warning_collector(),
+                WarningCategory::CoordinateOutlier,
+                outliers.len(),
+                format!("Excluding {}", message),
+            );
+            // The outliers are rare, so the file is read again without them instead of holding the features in
+            // memory on every load. The warnings of the first read were already recorded.
+            let excluded_line_indices = outliers.iter().map(|extent| extent.line_idx).collect();
+            lines = read_lines(
+                filepath,
+                crs,
+                &read_options.clone().warnings(WarningCollector::new()),
+                &excluded_line_indices,
+            )?;
+        }
+        let LinesRead {
+            builder,
+            num_features,
+            num_lines,
+            report,
+            ..
+        } = lines;
+        if num_lines != num_features {
+            warn(
+                read_options.warning_collector(),
+                WarningCategory::NonLineString,
+                num_features - num_lines,
+                format!(
+                    "Out of {} features read from {:?}, only {} were LineStrings.",
+                    num_features, filepath, num_lines
+                ),
+            );
+        }
+        if builder.degenerate_line_count() > 0 {
+            warn(
+                read_options.warning_collector(),
+                WarningCategory::DegenerateGeometry,
+                builder.degenerate_line_count(),
+                format!(
+                    "Dropped {} lines of {:?} with less than two points",
+                    builder.degenerate_line_count(),
+                    filepath
+                ),
+            );
+        }
+        let graph = builder.build();
+        check_geographic_coordinate_range(&graph, filepath, read_options)?;
+        Ok((graph, report))
+    }
+}
+
+/// Below this many lines, the statistics of `coordinate_outliers` are not meaningful, and no outliers are detected.
+#[cfg(feature = "gdal-io")]
+const MIN_OUTLIER_DETECTION_LINE_COUNT: usize = 5;
+
+/// Lines are coordinate outliers if they are further from the median line center than this factor times the typical
+/// scale of the lines, see `coordinate_outliers`. Large, so that long roads far from the others are kept.
+#[cfg(feature = "gdal-io")]
+const COORDINATE_OUTLIER_FACTOR: f64 = 1000.0;
+
+/// Number of FIDs listed in messages about coordinate outliers.
+#[cfg(feature = "gdal-io")]
+const MAX_LISTED_FIDS: usize = 20;
+
+/// The extent of a line feature, for the detection of coordinate outliers.
+#[cfg(feature = "gdal-io")]
+struct LineExtent {
+    /// Index of the line among the LineString features of the geofile.
+    line_idx: usize,
+    fid: Option<u64>,
+    bounding_rect: geo::Rect,
+    length: f64,
+}
+
+/// The result of `read_lines`.
+#[cfg(feature = "gdal-io")]
+struct LinesRead<Ty: petgraph::EdgeType> {
+    builder: GeoGraphBuilder<FeatureMap, FeatureMap, Ty>,
+    num_features: usize,
+    num_lines: usize,
+    /// The extents of the lines with at least two coordinates.
+    extents: Vec<LineExtent>,
+    report: ReadReport,
+}
+
+/// Stream the LineString features of a geofile into a graph builder, leaving out the lines whose index among the
+/// LineString features is in `excluded_line_indices`.
+#[cfg(feature = "gdal-io")]
+fn read_lines<Ty: petgraph::EdgeType>(
+    filepath: &Path,
+    crs: Crs,
+    read_options: &ReadOptions,
+    excluded_line_indices: &HashSet<usize>,
+) -> Result<LinesRead<Ty>> {
+    let mut builder =
+        GeoGraphBuilder::new(crs).coordinate_precision(read_options.get_coordinate_precision());
+    let mut num_features = 0;
+    let mut num_lines = 0;
+    let mut extents = Vec::new();
+    let (_, report) = for_each_feature_with_fid_in_geofile_with_options(
+        filepath,
+        None,
+        read_options,
+        |fid, feature| {
+            num_features += 1;
+            if let geo::Geometry::LineString(linestring) = feature.geometry {
+                let line_idx = num_lines;
+                num_lines += 1;
+                if excluded_line_indices.contains(&line_idx) {
+                    return Ok(());
+                }
+                let bounding_rect = linestring
+                    .bounding_rect()
+                    .filter(|_| linestring.0.len() >= 2);
+                if let Some(bounding_rect) = bounding_rect {
+                    extents.push(LineExtent {
+                        line_idx,
+                        fid,
+                        bounding_rect,
+                        length: linestring.euclidean_length(),
+                    });
+                }
+                builder.add_line_with_data(linestring, feature.attributes.unwrap_or_default())?;
+            }
+            Ok(())
+        },
+    )?;
+    Ok(LinesRead {
+        builder,
+        num_features,
+        num_lines,
+        extents,
+        report,
+    })
+}
+
+/// The lines whose coordinates are outliers by orders of magnitude, e.g. lines in another CRS than the others.
+///
+/// The center of the lines is the median of their bounding box centers, and their typical scale is the larger of
+/// the 90th percentile of the distances of the bounding box centers from it, and the median line length. Distances
+/// are Chebyshev distances, in CRS units. A line is an outlier if a corner of its bounding box is more than
+/// `COORDINATE_OUTLIER_FACTOR` times the scale from the center. Using the 90th percentile keeps lines far away from
+/// the others, as long as more than a tenth of the lines are there, e.g. a second city.
+#[cfg(feature = "gdal-io")]
+fn coordinate_outliers(extents: &[LineExtent]) -> Vec<&LineExtent> {
+    if extents.len() < MIN_OUTLIER_DETECTION_LINE_COUNT {
+        return Vec::new();
+    }
+    let quantile = |mut values: Vec<f64>, quantile: f64| {
+        values.sort_by(f64::total_cmp);
+        values[(quantile * (values.len() - 1) as f64) as usize]
+    };
+    let centers: Vec<geo::Coord> = extents
+        .iter()
+        .map(|extent| extent.bounding_rect.center())
+        .collect();
+    let center = geo::coord! {
+        x: quantile(centers.iter().map(|coord| coord.x).collect(), 0.5),
+        y: quantile(centers.iter().map(|coord| coord.y).collect(), 0.5),
+    };
+    let distance = |coord: geo::Coord| (coord.x - center.x).abs().max((coord.y - center.y).abs());
+    let scale = quantile(centers.iter().map(|coord| distance(*coord)).collect(), 0.9).max(
+        quantile(extents.iter().map(|extent| extent.length).collect(), 0.5),
+    );
+    if scale == 0.0 {
+        return Vec::new();
+    }
+    extents
+        .iter()
+        .filter(|extent| {
+            let rect = extent.bounding_rect;
+            distance(rect.min()).max(distance(rect.max())) > COORDINATE_OUTLIER_FACTOR * scale
+        })
+        .collect()
+}
+
+/// The FIDs of `extents` for a message, at most `MAX_LISTED_FIDS` of them.
+#[cfg(feature = "gdal-io")]
+fn format_fids(extents: &[&LineExtent]) -> String {
+    let mut fids: Vec<String> = extents
+        .iter()
+        .take(MAX_LISTED_FIDS)
+        .map(|extent| match extent.fid {
+            Some(fid) => fid.to_string(),
+            None => "unknown".to_string(),
+        })
+        .collect();
+    if extents.len() > MAX_LISTED_FIDS {
+        fids.push(format!("and {} more", extents.len() - MAX_LISTED_FIDS));
+    }
+    fids.join(", ")
+}
+
+/// Fail if the graph is in a geographic CRS, but its coordinates exceed the longitude/latitude range. This happens
+/// with files in a projected CRS, e.g. UTM, which do not declare it, like GeoJSON files against the spec. Only warn
+/// if `read_options` is lenient about the coordinate range.
+#[cfg(feature = "gdal-io")]
+fn check_geographic_coordinate_range<Ty: petgraph::EdgeType>(
+    graph: &GeoFeatureGraph<Ty>,
+    filepath: &PathBuf,
+    read_options: &ReadOptions,
+) -> Result<()> {
+    let bounding_box = match graph.bounding_box() {
+        Some(bounding_box) if graph.crs.is_geographic() => bounding_box,
+        _ => return Ok(()),
+    };
+    if bounding_box.min().x >= -180.0
+        && bounding_box.max().x <= 180.0
+        && bounding_box.min().y >= -90.0
+        && bounding_box.max().y <= 90.0
+    {
+        return Ok(());
+    }
+    let message = format!(
+        "The coordinates of {:?} range over x {} to {} and y {} to {}, which exceeds the longitude/latitude range of \
+        its geographic CRS '{}'. The file is likely in a projected CRS, e.g. UTM, which it does not declare. Set the \
+        CRS with the `proposal_crs_override` or `ground_truth_crs_override` option",
+        filepath,
+        bounding_box.min().x,
+        bounding_box.max().x,
+        bounding_box.min().y,
+        bounding_box.max().y,
+        graph.crs.name()?
+    );
+    if read_options.is_lenient_coordinate_range() {
+        warn(
+            read_options.warning_collector(),
+            WarningCategory::ImplausibleCrs,
+            1,
+            message,
+        );
+        return Ok(());
+    }
+    Err(Error::CrsMismatch(message))
+}
+
+#[cfg(all(test, feature = "gdal-io"))]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use testdir::testdir;
+
+    use crate::{
+        crs::crs_utils::{epsg_4326, CrsDefinition},
+        diagnostics::warnings::{WarningCategory, WarningCollector},
+        error::Error,
+        geofile::{
+            feature::{Feature, FieldValue},
+            gdal_geofile::{
+                read_features_from_geofile, write_features_to_geofile, GdalDriverType,
+                OnCoordinateOutliers, OnReadError, ReadOptions, WriteOptions,
+            },
+        },
+    };
+
+    use super::{coordinate_outliers, GeoFeatureGraph, LineExtent};
+
+    /// Write a few lines with attributes to a GeoPackage in EPSG:4326, and return its path.
+    fn write_test_geofile() -> PathBuf {
+        let lines: Vec<geo::LineString> = vec![
+            vec![(0.0, 0.0), (10.0, 0.0)].into(),
+            vec![(10.0, 0.0), (20.0, 0.0)].into(),
+            vec![(10.0, 0.0), (10.0, 10.0)].into(),
+            vec![(10.0, 0.0), (5.0, 5.0), (10.0, 10.0)].into(),
+        ];
+        let features: Vec<Feature> = lines
+            .into_iter()
+            .enumerate()
+            .map(|(idx, line)| Feature {
+                geometry: geo::Geometry::LineString(line),
+                attributes: Some(HashMap::from([(
+                    "name".to_string(),
+                    FieldValue::StringValue(format!("line_{}", idx)),
+                )])),
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join("lines.gpkg");
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        geofile_filepath
+    }
+
+    #[test]
+    fn test_load_from_geofile_matches_in_memory_construction() {
+        let geofile_filepath = write_test_geofile();
+        let streamed_graph: GeoFeatureGraph<petgraph::Directed> =
+            GeoFeatureGraph::load_from_geofile(&geofile_filepath, None).unwrap();
+        let (read_features, crs) = read_features_from_geofile(&geofile_filepath).unwrap();
+        let in_memory_graph: GeoFeatureGraph<petgraph::Directed> =
+            GeoFeatureGraph::from_features_in_crs(read_features, crs, None).unwrap();
+
+        assert_eq!(
+            in_memory_graph.edge_graph().edge_count(),
+            streamed_graph.edge_graph().edge_count()
+        );
+        assert_eq!(
+            in_memory_graph.edge_geometries(),
+            streamed_graph.edge_geometries()
+        );
+        assert_eq!(
+            in_memory_graph.node_map().len(),
+            streamed_graph.node_map().len()
+        );
+        for (node_idx, node) in in_memory_graph.node_map() {
+            assert_eq!(
+                node.geometry,
+                streamed_graph.node_map().get(node_idx).unwrap().geometry
+            );
+        }
+        assert_eq!(4326, streamed_graph.crs.auth_code().unwrap());
+        assert_eq!(4326, in_memory_graph.crs.auth_code().unwrap());
+    }
+
+    #[test]
+    fn test_try_from_features_defaults_to_epsg_4326() {
+        let features = vec![Feature {
+            geometry: geo::Geometry::LineString(vec![(0.0, 0.0), (1.0, 0.0)].into()),
+            attributes: None,
+            null_fields: Default::default(),
+        }];
+        let graph: GeoFeatureGraph<petgraph::Directed> = features.try_into().unwrap();
+        assert_eq!(4326, graph.crs.auth_code().unwrap());
+    }
+
+    #[test]
+    fn test_load_from_geofile_with_crs_override() {
+        let geofile_filepath = write_test_geofile();
+        let crs_override = CrsDefinition::EpsgCode(32654).to_crs().unwrap();
+        let graph: GeoFeatureGraph<petgraph::Directed> =
+            GeoFeatureGraph::load_from_geofile(&geofile_filepath, Some(&crs_override)).unwrap();
+        assert_eq!(32654, graph.crs.auth_code().unwrap());
+    }
+
+    #[test]
+    fn test_load_from_geofile_skips_corrupted_geometry() {
+        let geofile_filepath = testdir!().join("lines.csv");
+        std::fs::write(
+            &geofile_filepath,
+            "WKT\n\"LINESTRING (0 0,1 0)\"\n\"LINESTRING (1 0,\"\n\"LINESTRING (1 0,1 1)\"\n",
+        )
+        .unwrap();
+
+        assert!(
+            GeoFeatureGraph::<petgraph::Directed>::load_from_geofile(&geofile_filepath, None)
+                .is_err()
+        );
+        let (graph, report) =
+            GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+                &geofile_filepath,
+                None,
+                &ReadOptions::new().on_error(OnReadError::SkipAndWarn),
+            )
+            .unwrap();
+        assert_eq!(2, graph.edge_count());
+        assert_eq!(1, report.skipped_features.len());
+    }
+
+    #[test]
+    fn test_load_from_geofile_records_degenerate_lines() {
+        let geofile_filepath = testdir!().join("lines.csv");
+        std::fs::write(
+            &geofile_filepath,
+            "WKT\n\"LINESTRING (0 0,1 0)\"\n\"LINESTRING (1 0)\"\n\"LINESTRING (2 0)\"\n\"LINESTRING (3 0)\"\n",
+        )
+        .unwrap();
+        let warnings = WarningCollector::new();
+
+        let (graph, _) = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new().warnings(warnings.clone()),
+        )
+        .unwrap();
+
+        assert_eq!(1, graph.edge_count());
+        let summary = warnings.summary();
+        assert_eq!(1, summary.len());
+        assert_eq!(WarningCategory::DegenerateGeometry, summary[0].category);
+        assert_eq!(3, summary[0].count);
+    }
+
+    #[test]
+    fn test_load_from_geofile_with_coordinate_precision() {
+        let geofile_filepath = testdir!().join("lines.csv");
+        // The endpoints of the lines differ by less than 1e-8 degrees.
+        std::fs::write(
+            &geofile_filepath,
+            "WKT\n\"LINESTRING (10 50,10.001 50.000000004)\"\n\"LINESTRING (10.000999997 50,10.002 50)\"\n",
+        )
+        .unwrap();
+
+        let graph =
+            GeoFeatureGraph::<petgraph::Directed>::load_from_geofile(&geofile_filepath, None)
+                .unwrap();
+        assert_eq!(4, graph.node_map().len());
+
+        let (graph, _) = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new().coordinate_precision(Some(1e-8)),
+        )
+        .unwrap();
+        assert_eq!(3, graph.node_map().len());
+        assert_eq!(2, graph.edge_count());
+    }
+
+    /// Write a GeoJSON file with UTM zone 54N coordinates in Tokyo, which does not declare a CRS.
+    fn write_utm_geojson() -> PathBuf {
+        let geofile_filepath = testdir!().join("utm_lines.geojson");
+        std::fs::write(
+            &geofile_filepath,
+            r#"{"type": "FeatureCollection", "features": [{"type": "Feature", "properties": {}, "geometry":
+            {"type": "LineString", "coordinates": [[390000.0, 3950000.0], [390100.0, 3950050.0]]}}]}"#,
+        )
+        .unwrap();
+        geofile_filepath
+    }
+
+    #[test]
+    fn test_load_from_geofile_projected_coordinates_in_geographic_crs() {
+        let geofile_filepath = write_utm_geojson();
+
+        let error =
+            GeoFeatureGraph::<petgraph::Directed>::load_from_geofile(&geofile_filepath, None)
+                .err()
+                .unwrap();
+
+        assert!(matches!(error, Error::CrsMismatch(_)));
+        let message = error.to_string();
+        assert!(message.contains("proposal_crs_override"), "{}", message);
+        assert!(
+            message.contains("x 390000 to 390100 and y 3950000 to 3950050"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_load_from_geofile_projected_coordinates_lenient() {
+        let geofile_filepath = write_utm_geojson();
+
+        let (graph, _) = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new().lenient_coordinate_range(true),
+        )
+        .unwrap();
+        assert_eq!(1, graph.edge_count());
+
+        let crs_override = CrsDefinition::EpsgCode(32654).to_crs().unwrap();
+        let graph: GeoFeatureGraph<petgraph::Directed> =
+            GeoFeatureGraph::load_from_geofile(&geofile_filepath, Some(&crs_override)).unwrap();
+        assert_eq!(32654, graph.crs.auth_code().unwrap());
+    }
+
+    /// Write lines in EPSG:4326 to a GeoPackage which declares it, with a line in UTM zone 54N coordinates appended
+    /// as the fifth feature.
+    fn write_geofile_with_utm_feature() -> PathBuf {
+        let lines: Vec<geo::LineString> = vec![
+            vec![(139.0, 35.0), (139.01, 35.0)].into(),
+            vec![(139.01, 35.0), (139.02, 35.0)].into(),
+            vec![(139.01, 35.0), (139.01, 35.01)].into(),
+            vec![(139.01, 35.01), (139.02, 35.01)].into(),
+            vec![(390000.0, 3950000.0), (390100.0, 3950050.0)].into(),
+        ];
+        let features: Vec<Feature> = lines
+            .into_iter()
+            .map(|line| Feature {
+                geometry: geo::Geometry::LineString(line),
+                attributes: None,
+                null_fields: Default::default(),
+            })
+            .collect();
+        let geofile_filepath = testdir!().join("mixed_crs_lines.gpkg");
+        write_features_to_geofile(
+            &features,
+            &geofile_filepath,
+            Some(&epsg_4326()),
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        geofile_filepath
+    }
+
+    #[test]
+    fn test_load_from_geofile_excludes_coordinate_outliers() {
+        let geofile_filepath = write_geofile_with_utm_feature();
+        let warnings = WarningCollector::new();
+
+        let (graph, _) = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new().warnings(warnings.clone()),
+        )
+        .unwrap();
+
+        assert_eq!(4, graph.edge_count());
+        let bounding_box = graph.bounding_box().unwrap();
+        assert!(bounding_box.max().x < 180.0);
+        let summary = warnings.summary();
+        assert_eq!(1, summary.len());
+        assert_eq!(WarningCategory::CoordinateOutlier, summary[0].category);
+        assert_eq!(1, summary[0].count);
+        assert!(
+            summary[0].sample_message.ends_with("FIDs: 5"),
+            "{}",
+            summary[0].sample_message
+        );
+    }
+
+    #[test]
+    fn test_load_from_geofile_coordinate_outliers_strict() {
+        let geofile_filepath = write_geofile_with_utm_feature();
+
+        let error = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new().coordinate_outliers(OnCoordinateOutliers::Fail),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, Error::CrsMismatch(_)));
+        assert!(error.to_string().contains("FIDs: 5"), "{}", error);
+
+        let (graph, _) = GeoFeatureGraph::<petgraph::Directed>::load_from_geofile_with_options(
+            &geofile_filepath,
+            None,
+            &ReadOptions::new()
+                .coordinate_outliers(OnCoordinateOutliers::Keep)
+                .lenient_coordinate_range(true),
+        )
+        .unwrap();
+        assert_eq!(5, graph.edge_count());
+    }
+
+    #[test]
+    fn test_coordinate_outliers_keep_long_roads() {
+        let extent = |line_idx: usize, min: (f64, f64), max: (f64, f64)| LineExtent {
+            line_idx,
+            fid: Some(line_idx as u64),
+            bounding_rect: geo::Rect::new(min, max),
+            length: (max.0 - min.0).hypot(max.1 - min.1),
+        };
+        // Short streets within 0.1 degrees, and a highway leading 5 degrees away from them.
+        let mut extents: Vec<LineExtent> = (0..10)
+            .map(|idx| {
+                let x = 10.0 + idx as f64 * 0.01;
+                extent(idx, (x, 50.0), (x + 0.001, 50.001))
+            })
+            .collect();
+        extents.push(extent(10, (10.0, 50.0), (15.0, 50.0)));
+        assert!(coordinate_outliers(&extents).is_empty());
+
+        extents.push(extent(11, (390000.0, 3950000.0), (390100.0, 3950050.0)));
+        let outliers = coordinate_outliers(&extents);
+        assert_eq!(1, outliers.len());
+        assert_eq!(11, outliers[0].line_idx);
+    }
 }
@@ -0,0 +1,602 @@
+use std::{collections::HashMap, iter::zip, path::Path};
+
+use crate::crs::Crs;
+use crate::error::{Error, Result, ResultExt};
+use rstar::PointDistance;
+
+use crate::geofile::feature::{FeatureMap, FieldValue};
+
+use super::{geo_feature_graph::GeoFeatureGraph, primitives::NodeIdx};
+
+type IndexedNode = rstar::primitives::GeomWithData<[f64; 2], NodeIdx>;
+
+/// Columns of the node CSV which hold the node id and coordinates, all other columns are node attributes.
+const NODE_ID_COLUMN: &str = "id";
+const NODE_X_COLUMN: &str = "x";
+const NODE_Y_COLUMN: &str = "y";
+/// Columns of the edge CSV which hold the node ids and the optional WKT geometry, all other columns are edge
+/// attributes.
+const EDGE_START_ID_COLUMN: &str = "start_id";
+const EDGE_END_ID_COLUMN: &str = "end_id";
+const EDGE_GEOMETRY_COLUMN: &str = "geometry";
+
+/// Load a graph from a pair of node and edge CSV files, as used e.g. by the SpaceNet road challenge.
+///
+/// The node CSV has the columns `id`, `x` and `y`, the edge CSV the columns `start_id`, `end_id`, and optionally
+/// `geometry` with a WKT LineString. The node ids of the files are used as they are. Edges without a geometry get a
+/// straight line between their nodes, and the endpoints of WKT geometries are set to the coordinates of their nodes.
+/// All other columns are stored as string attributes of the nodes and edges.
+///
+/// # Arguments
+/// * nodes_path - The node CSV.
+/// * edges_path - The edge CSV.
+/// * crs - The CRS of the coordinates in both files.
+pub fn load_from_node_edge_csv<Ty: petgraph::EdgeType>(
+    nodes_path: &Path,
+    edges_path: &Path,
+    crs: Crs,
+) -> Result<GeoFeatureGraph<Ty>> {
+    let mut graph = GeoFeatureGraph::new(crs);
+
+    let mut reader =
+        csv::Reader::from_path(nodes_path).with_context(|| format!("Opening {:?}", nodes_path))?;
+    let columns = CsvColumns::new(
+        &mut reader,
+        &[NODE_ID_COLUMN, NODE_X_COLUMN, NODE_Y_COLUMN],
+        &[],
+    )
+    .with_context(|| format!("Reading the header of {:?}", nodes_path))?;
+    for (row, record) in reader.records().enumerate() {
+        let record =
+            record.with_context(|| format!("Reading row {} of {:?}", row + 1, nodes_path))?;
+        let parse_error = || format!("Parsing row {} of {:?}", row + 1, nodes_path);
+        let id: NodeIdx = columns
+            .get(&record, NODE_ID_COLUMN)
+            .parse()
+            .with_context(parse_error)?;
+        let x: f64 = columns
+            .get(&record, NODE_X_COLUMN)
+            .parse()
+            .with_context(parse_error)?;
+        let y: f64 = columns
+            .get(&record, NODE_Y_COLUMN)
+            .parse()
+            .with_context(parse_error)?;
+        if graph.node_map().contains_key(&id) {
+            return Err(Error::invalid_data(format!(
+                "Duplicate node id {} in row {} of {:?}",
+                id,
+                row + 1,
+                nodes_path
+            )));
+        }
+        graph.insert_node(id, geo::Point::new(x, y))?;
+        graph.node_map_mut().get_mut(&id).unwrap().data = columns.attributes(&record);
+    }
+
+    let mut reader =
+        csv::Reader::from_path(edges_path).with_context(|| format!("Opening {:?}", edges_path))?;
+    let columns = CsvColumns::new(
+        &mut reader,
+        &[EDGE_START_ID_COLUMN, EDGE_END_ID_COLUMN],
+        &[EDGE_GEOMETRY_COLUMN],
+    )
+    .with_context(|| format!("Reading the header of {:?}", edges_path))?;
+    for (row, record) in reader.records().enumerate() {
+        let record =
+            record.with_context(|| format!("Reading row {} of {:?}", row + 1, edges_path))?;
+        let parse_error = || format!("Parsing row {} of {:?}", row + 1, edges_path);
+        let node_coord = |column: &str| -> Result<(NodeIdx, geo::Coord)> {
+            let id: NodeIdx = columns
+                .get(&record, column)
+                .parse()
+                .with_context(parse_error)?;
+            let node = graph.node_map().get(&id).ok_or_else(|| {
+                Error::invalid_data(format!(
+                    "Edge in row {} of {:?} references missing node id {}",
+                    row + 1,
+                    edges_path,
+                    id
+                ))
+            })?;
+            Ok((id, node.geometry.0))
+        };
+        let (start_id, start_coord) = node_coord(EDGE_START_ID_COLUMN)?;
+        let (end_id, end_coord) = node_coord(EDGE_END_ID_COLUMN)?;
+        let wkt = columns
+            .get_optional(&record, EDGE_GEOMETRY_COLUMN)
+            .unwrap_or("");
+        let geometry = if wkt.trim().is_empty() {
+            geo::LineString::new(vec![start_coord, end_coord])
+        } else {
+            let mut geometry = parse_wkt_linestring(wkt).with_context(parse_error)?;
+            *geometry.0.first_mut().unwrap() = start_coord;
+            *geometry.0.last_mut().unwrap() = end_coord;
+            geometry
+        };
+        graph.insert_edge_with_data(start_id, end_id, geometry, columns.attributes(&record))?;
+    }
+    Ok(graph)
+}
+
+/// How the rows of a CSV are matched to the nodes of a graph by `GeoFeatureGraph::join_node_attributes_from_csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinKey {
+    /// The column holds the index of the node.
+    NodeIdx { column: String },
+    /// The columns hold coordinates in the CRS of the graph, and the row is matched to the nearest node within
+    /// `max_distance` of them.
+    NearestCoordinate {
+        x_col: String,
+        y_col: String,
+        max_distance: f64,
+    },
+}
+
+/// A CSV row which was not joined to a node.
+#[derive(Debug, PartialEq)]
+pub struct UnjoinedRow {
+    /// The number of the row, the first row after the header is 1.
+    pub row: usize,
+    pub reason: String,
+}
+
+/// The result of `GeoFeatureGraph::join_node_attributes_from_csv`.
+#[derive(Debug, Default, PartialEq)]
+pub struct NodeJoinReport {
+    pub joined_row_count: usize,
+    pub unjoined_rows: Vec<UnjoinedRow>,
+}
+
+impl NodeJoinReport {
+    pub fn is_complete(&self) -> bool {
+        self.unjoined_rows.is_empty()
+    }
+}
+
+impl<Ty: petgraph::EdgeType> GeoFeatureGraph<Ty> {
+    /// Add the columns of a CSV file as string attributes to the nodes which its rows are matched to by `join`.
+    ///
+    /// All columns except the ones of the join key are added, empty values are left out. Attributes of the nodes
+    /// with the same name are overwritten, also if several rows are matched to the same node. Rows which do not match
+    /// a node, or whose key cannot be parsed, are left out and listed in the report.
+    pub fn join_node_attributes_from_csv(
+        &mut self,
+        path: &Path,
+        join: &JoinKey,
+    ) -> Result<NodeJoinReport> {
+        let mut reader =
+            csv::Reader::from_path(path).with_context(|| format!("Opening {:?}", path))?;
+        let key_columns: Vec<&str> = match join {
+            JoinKey::NodeIdx { column } => vec![column],
+            JoinKey::NearestCoordinate { x_col, y_col, .. } => vec![x_col, y_col],
+        };
+        let columns = CsvColumns::new(&mut reader, &key_columns, &[])
+            .with_context(|| format!("Reading the header of {:?}", path))?;
+        let records = reader
+            .records()
+            .enumerate()
+            .map(|(row, record)| {
+                record.with_context(|| format!("Reading row {} of {:?}", row + 1, path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // The node of each row, or why it has none.
+        let node_indices: Vec<std::result::Result<NodeIdx, String>> = match join {
+            JoinKey::NodeIdx { column } => records
+                .iter()
+                .map(|record| {
+                    let value = columns.get(record, column);
+                    let idx: NodeIdx = value
+                        .parse()
+                        .map_err(|_| format!("Invalid node index '{}'", value))?;
+                    if self.node_map().contains_key(&idx) {
+                        Ok(idx)
+                    } else {
+                        Err(format!("No node with index {}", idx))
+                    }
+                })
+                .collect(),
+            JoinKey::NearestCoordinate {
+                x_col,
+                y_col,
+                max_distance,
+            } => {
+                let node_tree = rstar::RTree::bulk_load(
+                    self.node_map()
+                        .iter()
+                        .map(|(idx, node)| {
+                            IndexedNode::new([node.geometry.x(), node.geometry.y()], *idx)
+                        })
+                        .collect(),
+                );
+                records
+                    .iter()
+                    .map(|record| {
+                        let coord = parse_coordinate(&columns, record, x_col, y_col)?;
+                        node_tree
+                            .nearest_neighbor(&coord)
+                            .filter(|nearest| nearest.distance_2(&coord) <= max_distance.powi(2))
+                            .map(|nearest| nearest.data)
+                            .ok_or_else(|| {
+                                format!("No node within {} of {:?}", max_distance, coord)
+                            })
+                    })
+                    .collect()
+            }
+        };
+
+        let mut report = NodeJoinReport::default();
+        for (row, (record, node_idx)) in zip(records, node_indices).enumerate() {
+            match node_idx {
+                Ok(node_idx) => {
+                    let data = &mut self.node_map_mut().get_mut(&node_idx).unwrap().data;
+                    data.extend(columns.attributes(&record));
+                    report.joined_row_count += 1;
+                }
+                Err(reason) => report.unjoined_rows.push(UnjoinedRow {
+                    row: row + 1,
+                    reason,
+                }),
+            }
+        }
+        if !report.is_complete() {
+            log::warn!(
+                "{} rows of {:?} could not be joined to a node",
+                report.unjoined_rows.len(),
+                path
+            );
+        }
+        Ok(report)
+    }
+}
+
+/// Parse the coordinate in the given columns of a CSV row.
+fn parse_coordinate(
+    columns: &CsvColumns,
+    record: &csv::StringRecord,
+    x_col: &str,
+    y_col: &str,
+) -> std::result::Result<[f64; 2], String> {
+    let parse = |column: &str| {
+        let value = columns.get(record, column);
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid coordinate '{}' in column '{}'", value, column))
+    };
+    Ok([parse(x_col)?, parse(y_col)?])
+}
+
+/// Parse a WKT LineString with at least two points.
+fn parse_wkt_linestring(wkt: &str) -> Result<geo::LineString> {
+    match parse_wkt_geometry(wkt)? {
+        geo::Geometry::LineString(linestring) if linestring.0.len() >= 2 => Ok(linestring),
+        geometry => Err(Error::invalid_data(format!(
+            "Expected a LineString with at least two points, got {:?}",
+            geometry
+        ))),
+    }
+}
+
+#[cfg(feature = "gdal-io")]
+fn parse_wkt_geometry(wkt: &str) -> Result<geo::Geometry> {
+    let wkb = gdal::vector::Geometry::from_wkt(wkt)?.wkb()?;
+    wkb::wkb_to_geom(&mut wkb.as_slice())
+        .map_err(|err| Error::invalid_data(format!("Could not parse geometry from WKB, {:?}", err)))
+}
+
+#[cfg(not(feature = "gdal-io"))]
+fn parse_wkt_geometry(wkt: &str) -> Result<geo::Geometry> {
+    use wkt::TryFromWkt;
+
+    geo::Geometry::try_from_wkt_str(wkt)
+        .map_err(|err| Error::invalid_data(format!("Could not parse geometry from WKT, {}", err)))
+}
+
+/// The positions of the columns of a CSV file by their names.
+struct CsvColumns {
+    positions: HashMap<String, usize>,
+    /// Names and positions of the columns which are not in the required or optional columns.
+    attribute_columns: Vec<(String, usize)>,
+}
+
+impl CsvColumns {
+    /// Read the header of a CSV file, which must contain the `required` columns and may contain the `optional` ones.
+    fn new<R: std::io::Read>(
+        reader: &mut csv::Reader<R>,
+        required: &[&str],
+        optional: &[&str],
+    ) -> Result<Self> {
+        let positions: HashMap<String, usize> = reader
+            .headers()?
+            .iter()
+            .enumerate()
+            .map(|(position, name)| (name.trim().to_string(), position))
+            .collect();
+        for column in required {
+            if !positions.contains_key(*column) {
+                return Err(Error::invalid_data(format!("Missing column '{}'", column)));
+            }
+        }
+        let mut attribute_columns: Vec<(String, usize)> = positions
+            .iter()
+            .filter(|(name, _)| {
+                !required.contains(&name.as_str()) && !optional.contains(&name.as_str())
+            })
+            .map(|(name, position)| (name.clone(), *position))
+            .collect();
+        attribute_columns.sort_by_key(|(_, position)| *position);
+        Ok(Self {
+            positions,
+            attribute_columns,
+        })
+    }
+
+    /// The value of a required column.
+    fn get<'r>(&self, record: &'r csv::StringRecord, column: &str) -> &'r str {
+        self.get_optional(record, column).unwrap_or("").trim()
+    }
+
+    fn get_optional<'r>(&self, record: &'r csv::StringRecord, column: &str) -> Option<&'r str> {
+        record.get(*self.positions.get(column)?)
+    }
+
+    /// The values of the attribute columns. Empty values are left out.
+    fn attributes(&self, record: &csv::StringRecord) -> FeatureMap {
+        self.attribute_columns
+            .iter()
+            .filter_map(|(name, position)| {
+                let value = record.get(*position).filter(|value| !value.is_empty())?;
+                Some((name.clone(), FieldValue::StringValue(value.to_string())))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::geofile::feature::FieldValue;
+    use testdir::testdir;
+
+    use crate::{crs::crs_utils::epsg_4326, geograph::geo_feature_graph::GeoFeatureGraph};
+
+    use super::{load_from_node_edge_csv, JoinKey, UnjoinedRow};
+
+    /// Write the node and edge CSVs to the test dir.
+    fn write_csvs(test_dir: &Path, nodes: &str, edges: &str) -> (PathBuf, PathBuf) {
+        let nodes_path = test_dir.join("nodes.csv");
+        let edges_path = test_dir.join("edges.csv");
+        std::fs::write(&nodes_path, nodes).unwrap();
+        std::fs::write(&edges_path, edges).unwrap();
+        (nodes_path, edges_path)
+    }
+
+    const NODES_CSV: &str = "id,x,y,kind\n\
+        10,139.78,35.68,junction\n\
+        20,139.79,35.68,\n\
+        30,139.79,35.69,end\n";
+
+    #[test]
+    fn test_load_from_node_edge_csv_with_wkt() {
+        let (nodes_path, edges_path) = write_csvs(
+            &testdir!(),
+            NODES_CSV,
+            "start_id,end_id,geometry,lanes\n\
+            10,20,\"LINESTRING (139.78 35.68, 139.785 35.679, 139.79 35.68)\",2\n\
+            20,30,,1\n",
+        );
+
+        let graph: GeoFeatureGraph<petgraph::Undirected> =
+            load_from_node_edge_csv(&nodes_path, &edges_path, epsg_4326()).unwrap();
+
+        assert_eq!(3, graph.node_map().len());
+        assert_eq!(2, graph.edge_count());
+        let edge = &graph.edge_graph().edge_weight(10, 20).unwrap()[0];
+        assert_eq!(3, edge.geometry.0.len());
+        assert_eq!(
+            Some(&FieldValue::StringValue("2".to_string())),
+            edge.data.get("lanes")
+        );
+        assert!(!edge.data.contains_key("geometry"));
+        // The missing geometry is a straight line between the nodes.
+        let edge = &graph.edge_graph().edge_weight(20, 30).unwrap()[0];
+        assert_eq!(
+            geo::LineString::from(vec![(139.79, 35.68), (139.79, 35.69)]),
+            edge.geometry
+        );
+        let node_data = |id| &graph.node_map().get(&id).unwrap().data;
+        assert_eq!(
+            Some(&FieldValue::StringValue("junction".to_string())),
+            node_data(10).get("kind")
+        );
+        assert!(node_data(20).is_empty());
+        assert!(graph.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_load_from_node_edge_csv_straight_lines() {
+        let (nodes_path, edges_path) = write_csvs(
+            &testdir!(),
+            NODES_CSV,
+            "start_id,end_id\n\
+            10,20\n\
+            20,30\n\
+            30,10\n",
+        );
+
+        let graph: GeoFeatureGraph<petgraph::Directed> =
+            load_from_node_edge_csv(&nodes_path, &edges_path, epsg_4326()).unwrap();
+
+        assert_eq!(3, graph.edge_count());
+        let edge = &graph.edge_graph().edge_weight(30, 10).unwrap()[0];
+        assert_eq!(
+            geo::LineString::from(vec![(139.79, 35.69), (139.78, 35.68)]),
+            edge.geometry
+        );
+    }
+
+    #[test]
+    fn test_load_from_node_edge_csv_missing_node_id() {
+        let (nodes_path, edges_path) = write_csvs(
+            &testdir!(),
+            NODES_CSV,
+            "start_id,end_id\n\
+            10,20\n\
+            20,40\n",
+        );
+
+        let error =
+            load_from_node_edge_csv::<petgraph::Undirected>(&nodes_path, &edges_path, epsg_4326())
+                .err()
+                .unwrap()
+                .to_string();
+
+        assert!(
+            error.starts_with("Edge in row 2 of")
+                && error.ends_with("references missing node id 40"),
+            "{}",
+            error
+        );
+    }
+
+    /// The graph of `NODES_CSV` with straight edges.
+    fn load_test_graph(test_dir: &Path) -> GeoFeatureGraph<petgraph::Undirected> {
+        let (nodes_path, edges_path) =
+            write_csvs(test_dir, NODES_CSV, "start_id,end_id\n10,20\n20,30\n");
+        load_from_node_edge_csv(&nodes_path, &edges_path, epsg_4326()).unwrap()
+    }
+
+    #[test]
+    fn test_join_node_attributes_by_node_idx() {
+        let test_dir = testdir!();
+        let mut graph = load_test_graph(&test_dir);
+        let join_path = test_dir.join("join.csv");
+        std::fs::write(
+            &join_path,
+            "node,verified,kind\n\
+            20,true,junction\n\
+            30,false,\n\
+            40,true,end\n\
+            abc,true,end\n",
+        )
+        .unwrap();
+
+        let report = graph
+            .join_node_attributes_from_csv(
+                &join_path,
+                &JoinKey::NodeIdx {
+                    column: "node".to_string(),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(2, report.joined_row_count);
+        assert_eq!(
+            vec![
+                UnjoinedRow {
+                    row: 3,
+                    reason: "No node with index 40".to_string()
+                },
+                UnjoinedRow {
+                    row: 4,
+                    reason: "Invalid node index 'abc'".to_string()
+                },
+            ],
+            report.unjoined_rows
+        );
+        let node_data = |id| &graph.node_map().get(&id).unwrap().data;
+        assert_eq!(
+            Some(&FieldValue::StringValue("true".to_string())),
+            node_data(20).get("verified")
+        );
+        assert_eq!(
+            Some(&FieldValue::StringValue("junction".to_string())),
+            node_data(20).get("kind")
+        );
+        // Empty values do not overwrite the existing attributes.
+        assert_eq!(
+            Some(&FieldValue::StringValue("end".to_string())),
+            node_data(30).get("kind")
+        );
+        assert!(!node_data(30).contains_key("node"));
+        assert_eq!(
+            Some(&FieldValue::StringValue("junction".to_string())),
+            node_data(10).get("kind")
+        );
+        assert!(!node_data(10).contains_key("verified"));
+    }
+
+    #[test]
+    fn test_join_node_attributes_by_nearest_coordinate() {
+        let test_dir = testdir!();
+        let mut graph = load_test_graph(&test_dir);
+        let join_path = test_dir.join("join.csv");
+        std::fs::write(
+            &join_path,
+            "lon,lat,verified\n\
+            139.7801,35.6799,true\n\
+            139.7899,35.6901,false\n\
+            139.785,35.685,true\n\
+            ,35.68,true\n",
+        )
+        .unwrap();
+
+        let report = graph
+            .join_node_attributes_from_csv(
+                &join_path,
+                &JoinKey::NearestCoordinate {
+                    x_col: "lon".to_string(),
+                    y_col: "lat".to_string(),
+                    max_distance: 0.001,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(2, report.joined_row_count);
+        assert_eq!(
+            vec![3, 4],
+            report
+                .unjoined_rows
+                .iter()
+                .map(|unjoined| unjoined.row)
+                .collect::<Vec<_>>()
+        );
+        assert!(report.unjoined_rows[0].reason.starts_with("No node within"));
+        assert_eq!(
+            "Invalid coordinate '' in column 'lon'",
+            report.unjoined_rows[1].reason
+        );
+        let verified = |id| graph.node_map().get(&id).unwrap().data.get("verified");
+        assert_eq!(
+            Some(&FieldValue::StringValue("true".to_string())),
+            verified(10)
+        );
+        assert_eq!(None, verified(20));
+        assert_eq!(
+            Some(&FieldValue::StringValue("false".to_string())),
+            verified(30)
+        );
+        assert!(!graph.node_map().get(&10).unwrap().data.contains_key("lon"));
+    }
+
+    #[test]
+    fn test_join_node_attributes_missing_key_column() {
+        let test_dir = testdir!();
+        let mut graph = load_test_graph(&test_dir);
+        let join_path = test_dir.join("join.csv");
+        std::fs::write(&join_path, "id,verified\n10,true\n").unwrap();
+
+        let error = graph
+            .join_node_attributes_from_csv(
+                &join_path,
+                &JoinKey::NodeIdx {
+                    column: "node".to_string(),
+                },
+            )
+            .unwrap_err();
+
+        assert!(error.display_chain().ends_with("Missing column 'node'"));
+    }
+}
@@ -1,3 +1,7 @@
+pub mod cleanup;
+pub mod compare;
 pub mod geo_feature_graph;
+pub mod io;
 pub mod primitives;
+pub mod serialization;
 pub mod utils;
@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 
-use anyhow::anyhow;
+use crate::crs::Crs;
+use crate::error::{Error, Result};
+use geo::{BoundingRect, EuclideanDistance, Intersects};
+use rstar::PointDistance;
 
 /// Edge of a geospatial graph.
 /// Parameters:
 /// - `D`: type of associated data.
+#[derive(Clone)]
 pub struct GeoEdge<D: Default> {
     pub geometry: geo::LineString,
     pub data: D,
@@ -25,12 +29,25 @@ impl<D: Default> GeoEdge<D> {
     }
 }
 
+/// Default of `GeoGraph::node_tolerance`, in CRS units.
+pub const DEFAULT_NODE_TOLERANCE: f64 = 1e-9;
+
+/// A node in an R-tree, with its index.
+type IndexedNode = rstar::primitives::GeomWithData<[f64; 2], NodeIdx>;
+
 /// Index type used for nodes of a geospatial graph.
 pub type NodeIdx = u64;
 
+/// Key of an edge of a geospatial graph: start node index, end node index, and parallel edge index.
+pub type EdgeKey = (NodeIdx, NodeIdx, usize);
+
+/// The bounding box of an edge in an R-tree, with the key of the edge.
+type IndexedEdge = rstar::primitives::GeomWithData<rstar::primitives::Rectangle<[f64; 2]>, EdgeKey>;
+
 /// Node of a geospatial graph.
 /// /// Parameters:
 /// - `D`: type of associated data.
+#[derive(Clone)]
 pub struct GeoNode<D: Default> {
     pub geometry: geo::Point,
     pub data: D,
@@ -52,6 +69,12 @@ impl<D: Default> GeoNode<D> {
 }
 
 /// Graph of geospatial edges. Parallel edges are supported because the edge weight is a vector of GeoEdge.
+///
+/// A graphmap edge between two nodes holds all edges between them, e.g. both carriageways of a dual carriageway road.
+/// The graphmap's own edge count is thus the number of connected node pairs, not the number of edges. Code which
+/// visits edges must visit every GeoEdge of each vector, e.g. via `GeoGraph::edges_sorted`, and count them with
+/// `GeoGraph::edge_count`.
+///
 /// Parameters:
 /// - `E`: the data type associated with edges.
 /// - `Ty`: whether the graph is directed or undirected, see petgraph documentation for details.
@@ -69,27 +92,50 @@ pub type NodeMap<N> = HashMap<NodeIdx, GeoNode<N>>;
 /// - `E`: the data type associated with edges.
 /// - `N`: the data type associated with nodes.
 /// - `Ty`: whether the graph is directed or undirected, see petgraph documentation for details.
+#[derive(Clone)]
 pub struct GeoGraph<E: Default, N: Default, Ty: petgraph::EdgeType> {
     edge_graph: EdgeGraph<E, Ty>,
     node_map: NodeMap<N>,
-    pub crs: gdal::spatial_ref::SpatialRef,
+    pub crs: Crs,
+    node_tolerance: f64,
+    /// Spatial index of the edges, see `build_edge_index`.
+    edge_index: Option<rstar::RTree<IndexedEdge>>,
+    /// Whether the edges may have changed since the edge index was built.
+    edge_index_dirty: bool,
 }
 
 impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
     /// Create an empty graph.
-    pub fn new(crs: gdal::spatial_ref::SpatialRef) -> Self {
+    pub fn new(crs: Crs) -> Self {
         Self {
             edge_graph: EdgeGraph::new(),
             node_map: HashMap::new(),
             crs: crs,
+            node_tolerance: DEFAULT_NODE_TOLERANCE,
+            edge_index: None,
+            edge_index_dirty: false,
         }
     }
 
+    /// Set the distance, in CRS units, up to which a node inserted again with an existing index may differ from the
+    /// existing node, see `insert_node`.
+    pub fn with_node_tolerance(mut self, node_tolerance: f64) -> Self {
+        self.node_tolerance = node_tolerance;
+        self
+    }
+
+    pub fn node_tolerance(&self) -> f64 {
+        self.node_tolerance
+    }
+
     pub fn edge_graph(&self) -> &EdgeGraph<E, Ty> {
         &self.edge_graph
     }
 
+    /// Mutable access to the edges. Callers must keep the invariants checked by `validate_invariants`, e.g. by using
+    /// `transform_geometries` to change coordinates.
     pub fn edge_graph_mut(&mut self) -> &mut EdgeGraph<E, Ty> {
+        self.edge_index_dirty = true;
         &mut self.edge_graph
     }
 
@@ -97,6 +143,8 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
         &self.node_map
     }
 
+    /// Mutable access to the nodes. Callers must keep the invariants checked by `validate_invariants`, e.g. by using
+    /// `transform_geometries` to change coordinates.
     pub fn node_map_mut(&mut self) -> &mut NodeMap<N> {
         &mut self.node_map
     }
@@ -106,7 +154,7 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
         start_node_idx: NodeIdx,
         end_node_idx: NodeIdx,
         geometry: geo::LineString,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         self.insert_edge_with_data(start_node_idx, end_node_idx, geometry, E::default())
     }
 
@@ -116,9 +164,11 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
         end_node_idx: NodeIdx,
         geometry: geo::LineString,
         data: E,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         if 2 > geometry.coords().count() {
-            return Err(anyhow!("Cannot insert edge with less than two points"));
+            return Err(Error::invalid_data(
+                "Cannot insert edge with less than two points",
+            ));
         }
 
         let line_start_point = geometry.coords().nth(0).unwrap();
@@ -126,7 +176,14 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
 
         self.insert_node(start_node_idx, (*line_start_point).into())?;
         self.insert_node(end_node_idx, (*line_end_point).into())?;
+        // The endpoints may differ from existing nodes within the node tolerance. Snap them to the nodes, so that
+        // the edge starts and ends exactly at its nodes.
+        let mut geometry = geometry;
+        geometry.0[0] = self.node_map[&start_node_idx].geometry.0;
+        let last_idx = geometry.0.len() - 1;
+        geometry.0[last_idx] = self.node_map[&end_node_idx].geometry.0;
 
+        self.edge_index_dirty = true;
         if let Some(edge_vec) = self
             .edge_graph
             .edge_weight_mut(start_node_idx, end_node_idx)
@@ -144,13 +201,21 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
         Ok(())
     }
 
-    pub fn insert_node(&mut self, idx: NodeIdx, geometry: geo::Point) -> anyhow::Result<()> {
+    /// Insert a node, unless a node with the same index exists. Fails if the existing node is further than the node
+    /// tolerance from `geometry`, see `with_node_tolerance`.
+    pub fn insert_node(&mut self, idx: NodeIdx, geometry: geo::Point) -> Result<()> {
         if let Some(node) = self.node_map.get(&idx) {
-            if node.geometry != geometry {
-                return Err(anyhow!(
-                    "Node with the same index ({}) but different geometry already exists",
-                    idx
-                ));
+            let distance = node.geometry.euclidean_distance(&geometry);
+            if distance.is_nan() || distance > self.node_tolerance {
+                return Err(Error::invalid_data(format!(
+                    "Node with the same index ({}) but different geometry already exists: {:?} differs from {:?} by \
+                    {} (tolerance {})",
+                    idx,
+                    geometry.x_y(),
+                    node.geometry.x_y(),
+                    distance,
+                    self.node_tolerance
+                )));
             }
         } else {
             self.node_map.insert(idx, GeoNode::new(geometry));
@@ -158,13 +223,768 @@ impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraph<E, N, Ty> {
         Ok(())
     }
 
-    pub fn edge_geometries(&self) -> Vec<geo::LineString> {
-        self.edge_graph()
+    /// Reverse edge geometries as necessary, so that each edge geometry starts at its start node. The start node is
+    /// the designated start node for directed graphs, and the lower-indexed endpoint node for undirected graphs.
+    /// Nodes, edge data, and the edge keys of the graph are not changed.
+    pub fn normalize_edge_orientation(&mut self) {
+        let node_map = &self.node_map;
+        for (start_node_idx, end_node_idx, par_edges) in self.edge_graph.all_edges_mut() {
+            let start_node_idx = if Ty::is_directed() {
+                start_node_idx
+            } else {
+                start_node_idx.min(end_node_idx)
+            };
+            let start_coord = match node_map.get(&start_node_idx) {
+                Some(start_node) => start_node.geometry.0,
+                None => continue,
+            };
+            for edge in par_edges.iter_mut() {
+                if edge.geometry.0.first() != Some(&start_coord)
+                    && edge.geometry.0.last() == Some(&start_coord)
+                {
+                    edge.geometry.0.reverse();
+                }
+            }
+        }
+        debug_assert!(self.validate_invariants().is_ok());
+    }
+
+    /// Apply a coordinate transformation to all node and edge geometries. Either all geometries are transformed, or,
+    /// if `transform` fails for any coordinate, none are.
+    ///
+    /// `transform` must map equal coordinates to equal coordinates, so that edge endpoints stay equal to their nodes.
+    pub fn transform_geometries(
+        &mut self,
+        transform: impl Fn(&mut geo::Coord) -> Result<()>,
+    ) -> Result<()> {
+        let mut node_geometries = Vec::with_capacity(self.node_map.len());
+        for (idx, node) in &self.node_map {
+            let mut coord = node.geometry.0;
+            transform(&mut coord)?;
+            node_geometries.push((*idx, geo::Point(coord)));
+        }
+        let mut edge_geometries = Vec::new();
+        for (_, _, par_edges) in self.edge_graph.all_edges() {
+            for edge in par_edges {
+                let mut geometry = edge.geometry.clone();
+                for coord in geometry.0.iter_mut() {
+                    transform(coord)?;
+                }
+                edge_geometries.push(geometry);
+            }
+        }
+
+        for (idx, geometry) in node_geometries {
+            self.node_map.get_mut(&idx).unwrap().geometry = geometry;
+        }
+        let mut edge_geometries = edge_geometries.into_iter();
+        for (_, _, par_edges) in self.edge_graph.all_edges_mut() {
+            for edge in par_edges.iter_mut() {
+                edge.geometry = edge_geometries.next().unwrap();
+            }
+        }
+        self.edge_index_dirty = true;
+        if cfg!(debug_assertions) {
+            self.validate_invariants()?;
+        }
+        Ok(())
+    }
+
+    /// Check the invariants of the graph:
+    /// - Every edge has a node entry for both of its endpoints.
+    /// - The first and last coordinates of every edge geometry are the geometries of its start and end nodes. For
+    ///   undirected graphs, the geometry may point from the end node to the start node.
+    /// - No two nodes have the same geometry.
+    pub fn validate_invariants(&self) -> Result<()> {
+        for (start_node_idx, end_node_idx, par_edges) in self.edge_graph.all_edges() {
+            let node_coord = |idx: NodeIdx| -> Result<geo::Coord> {
+                self.node_map
+                    .get(&idx)
+                    .map(|node| node.geometry.0)
+                    .ok_or_else(|| {
+                        Error::invalid_data(format!(
+                            "Node {} of an edge is missing from the node map",
+                            idx
+                        ))
+                    })
+            };
+            let start_coord = node_coord(start_node_idx)?;
+            let end_coord = node_coord(end_node_idx)?;
+            for edge in par_edges {
+                let first_coord = edge.geometry.0.first();
+                let last_coord = edge.geometry.0.last();
+                let is_forward =
+                    first_coord == Some(&start_coord) && last_coord == Some(&end_coord);
+                let is_backward =
+                    first_coord == Some(&end_coord) && last_coord == Some(&start_coord);
+                if !(is_forward || (!Ty::is_directed() && is_backward)) {
+                    return Err(Error::invalid_data(format!(
+                        "Geometry of an edge between nodes {} and {} runs from {:?} to {:?}, but the nodes are at \
+                        {:?} and {:?}",
+                        start_node_idx,
+                        end_node_idx,
+                        first_coord,
+                        last_coord,
+                        start_coord,
+                        end_coord
+                    )));
+                }
+            }
+        }
+
+        let mut node_indices_by_coord = HashMap::new();
+        for (idx, node) in self.nodes_sorted() {
+            let coord_key = (node.geometry.x().to_bits(), node.geometry.y().to_bits());
+            if let Some(other_idx) = node_indices_by_coord.insert(coord_key, idx) {
+                return Err(Error::invalid_data(format!(
+                    "Nodes {} and {} have the same geometry {:?}",
+                    other_idx, idx, node.geometry
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterate over all edges as (start node index, end node index, parallel edge index, edge), ordered by these
+    /// indices. Unlike the iteration order of the edge graph, the order only depends on the graph contents.
+    pub fn edges_sorted(&self) -> impl Iterator<Item = (NodeIdx, NodeIdx, usize, &GeoEdge<E>)> {
+        let mut edges: Vec<(NodeIdx, NodeIdx, &Vec<GeoEdge<E>>)> =
+            self.edge_graph.all_edges().collect();
+        edges.sort_by_key(|(start_node_idx, end_node_idx, _)| (*start_node_idx, *end_node_idx));
+        edges
+            .into_iter()
+            .flat_map(|(start_node_idx, end_node_idx, par_edges)| {
+                par_edges
+                    .iter()
+                    .enumerate()
+                    .map(move |(par_edge_idx, edge)| {
+                        (start_node_idx, end_node_idx, par_edge_idx, edge)
+                    })
+            })
+    }
+
+    /// All nodes ordered by node index.
+    pub fn nodes_sorted(&self) -> Vec<(NodeIdx, &GeoNode<N>)> {
+        let mut nodes: Vec<(NodeIdx, &GeoNode<N>)> = self
+            .node_map
+            .iter()
+            .map(|(idx, node)| (*idx, node))
+            .collect();
+        nodes.sort_by_key(|(idx, _)| *idx);
+        nodes
+    }
+
+    /// The bounding box of all edge geometries, None if the graph has no edges.
+    pub fn bounding_box(&self) -> Option<geo::Rect> {
+        self.edge_graph
             .all_edges()
-            .flat_map(|(_, _, par_edges)| par_edges.iter().map(|edge| edge.geometry.clone()))
+            .flat_map(|(_, _, par_edges)| par_edges.iter())
+            .filter_map(|edge| edge.geometry.bounding_rect())
+            .reduce(|bounding_box, edge_bounding_box| {
+                geo::Rect::new(
+                    (
+                        bounding_box.min().x.min(edge_bounding_box.min().x),
+                        bounding_box.min().y.min(edge_bounding_box.min().y),
+                    ),
+                    (
+                        bounding_box.max().x.max(edge_bounding_box.max().x),
+                        bounding_box.max().y.max(edge_bounding_box.max().y),
+                    ),
+                )
+            })
+    }
+
+    /// The number of edges, counting each parallel edge.
+    pub fn edge_count(&self) -> usize {
+        self.edge_graph
+            .all_edges()
+            .map(|(_, _, par_edges)| par_edges.len())
+            .sum()
+    }
+
+    /// The geometries of all edges including parallel ones, in the order of `edges_sorted`.
+    pub fn edge_geometries(&self) -> Vec<geo::LineString> {
+        self.edges_sorted()
+            .map(|(_, _, _, edge)| edge.geometry.clone())
+            .collect()
+    }
+
+    /// The geometries of all edges including parallel ones with their keys, in the order of `edges_sorted`.
+    pub fn keyed_edge_geometries(&self) -> Vec<(EdgeKey, geo::LineString)> {
+        self.edges_sorted()
+            .map(|(start_node_idx, end_node_idx, par_edge_idx, edge)| {
+                (
+                    (start_node_idx, end_node_idx, par_edge_idx),
+                    edge.geometry.clone(),
+                )
+            })
             .collect()
     }
+
+    /// Build a spatial index of the bounding boxes of all edges, used by `edges_intersecting` and
+    /// `edges_within_distance`. Inserting edges or mutating the edge graph invalidates the index, it must be built
+    /// again afterwards.
+    pub fn build_edge_index(&mut self) {
+        let indexed_edges = self
+            .edges_sorted()
+            .filter_map(|(start_node_idx, end_node_idx, par_edge_idx, edge)| {
+                let bounding_rect = edge.geometry.bounding_rect()?;
+                Some(IndexedEdge::new(
+                    rstar::primitives::Rectangle::from_corners(
+                        bounding_rect.min().x_y().into(),
+                        bounding_rect.max().x_y().into(),
+                    ),
+                    (start_node_idx, end_node_idx, par_edge_idx),
+                ))
+            })
+            .collect();
+        self.edge_index = Some(rstar::RTree::bulk_load(indexed_edges));
+        self.edge_index_dirty = false;
+    }
+
+    /// Whether the edge index is built and the edges have not changed since.
+    pub fn has_current_edge_index(&self) -> bool {
+        self.edge_index.is_some() && !self.edge_index_dirty
+    }
+
+    /// All edges whose geometry intersects `rect`, ordered by their keys.
+    pub fn edges_intersecting(&self, rect: geo::Rect) -> Vec<(EdgeKey, &GeoEdge<E>)> {
+        self.edges_in_envelope(rect, |edge| edge.geometry.intersects(&rect))
+    }
+
+    /// All edges whose geometry is at most `distance` from `point`, ordered by their keys.
+    pub fn edges_within_distance(
+        &self,
+        point: geo::Point,
+        distance: f64,
+    ) -> Vec<(EdgeKey, &GeoEdge<E>)> {
+        let rect = geo::Rect::new(
+            (point.x() - distance, point.y() - distance),
+            (point.x() + distance, point.y() + distance),
+        );
+        self.edges_in_envelope(rect, |edge| {
+            point.euclidean_distance(&edge.geometry) <= distance
+        })
+    }
+
+    /// The edges whose bounding box intersects `rect` and which satisfy `predicate`, ordered by their keys. Uses the
+    /// edge index if it is current, otherwise scans all edges.
+    fn edges_in_envelope(
+        &self,
+        rect: geo::Rect,
+        predicate: impl Fn(&GeoEdge<E>) -> bool,
+    ) -> Vec<(EdgeKey, &GeoEdge<E>)> {
+        match &self.edge_index {
+            Some(edge_index) if !self.edge_index_dirty => {
+                let envelope =
+                    rstar::AABB::from_corners(rect.min().x_y().into(), rect.max().x_y().into());
+                let mut edges: Vec<(EdgeKey, &GeoEdge<E>)> = edge_index
+                    .locate_in_envelope_intersecting(&envelope)
+                    .map(|indexed_edge| {
+                        let (start_node_idx, end_node_idx, par_edge_idx) = indexed_edge.data;
+                        let edge = &self.edge_graph[(start_node_idx, end_node_idx)][par_edge_idx];
+                        (indexed_edge.data, edge)
+                    })
+                    .filter(|(_, edge)| predicate(edge))
+                    .collect();
+                edges.sort_by_key(|(key, _)| *key);
+                edges
+            }
+            _ => {
+                log::warn!(
+                    "The edge index is {}, scanning all edges, see GeoGraph::build_edge_index",
+                    if self.edge_index.is_some() {
+                        "out of date"
+                    } else {
+                        "not built"
+                    }
+                );
+                self.edges_sorted()
+                    .filter(|(_, _, _, edge)| predicate(edge))
+                    .map(|(start_node_idx, end_node_idx, par_edge_idx, edge)| {
+                        ((start_node_idx, end_node_idx, par_edge_idx), edge)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Move the nodes and edges of `other` into this graph, e.g. to combine the tiles of a proposal.
+    ///
+    /// Each node of `other` within `snap_tolerance` of a node of this graph is unified with the nearest such node,
+    /// keeping the data of the node of this graph. The other nodes are added with their indices offset past the
+    /// largest node index of this graph. Edges keep their data, and become parallel edges if this graph already has
+    /// edges between their nodes. The endpoints of the edge geometries are moved to their unified nodes.
+    ///
+    /// Fails if the graphs are not in the same CRS.
+    pub fn merge(&mut self, other: GeoGraph<E, N, Ty>, snap_tolerance: f64) -> Result<MergeReport> {
+        if self.crs != other.crs {
+            return Err(Error::CrsMismatch(format!(
+                "Cannot merge a graph in {} into a graph in {}",
+                other.crs.name()?,
+                self.crs.name()?
+            )));
+        }
+        let node_tree = rstar::RTree::bulk_load(
+            self.node_map
+                .iter()
+                .map(|(idx, node)| IndexedNode::new([node.geometry.x(), node.geometry.y()], *idx))
+                .collect(),
+        );
+        let index_offset = self.node_map.keys().max().map_or(0, |max_idx| max_idx + 1);
+        let GeoGraph {
+            mut edge_graph,
+            node_map: other_node_map,
+            ..
+        } = other;
+
+        let mut other_node_coords = HashMap::new();
+        let mut new_node_indices = HashMap::new();
+        let mut report = MergeReport::default();
+        let mut other_nodes: Vec<(NodeIdx, GeoNode<N>)> = other_node_map.into_iter().collect();
+        other_nodes.sort_by_key(|(idx, _)| *idx);
+        for (other_idx, node) in other_nodes {
+            other_node_coords.insert(other_idx, node.geometry.0);
+            let coord = [node.geometry.x(), node.geometry.y()];
+            let nearest_idx = node_tree
+                .nearest_neighbor(&coord)
+                .filter(|nearest| nearest.distance_2(&coord) <= snap_tolerance.powi(2))
+                .map(|nearest| nearest.data);
+            let new_idx = match nearest_idx {
+                Some(nearest_idx) => {
+                    report.unified_node_count += 1;
+                    nearest_idx
+                }
+                None => {
+                    report.added_node_count += 1;
+                    self.node_map.insert(other_idx + index_offset, node);
+                    other_idx + index_offset
+                }
+            };
+            new_node_indices.insert(other_idx, new_idx);
+        }
+
+        for (start_node_idx, end_node_idx, par_edges) in edge_graph.all_edges_mut() {
+            for edge in std::mem::take(par_edges) {
+                // Undirected edge geometries may run from the end node to the start node.
+                let (start_node_idx, end_node_idx) =
+                    if edge.geometry.0.first() == Some(&other_node_coords[&start_node_idx]) {
+                        (start_node_idx, end_node_idx)
+                    } else {
+                        (end_node_idx, start_node_idx)
+                    };
+                let new_start_node_idx = new_node_indices[&start_node_idx];
+                let new_end_node_idx = new_node_indices[&end_node_idx];
+                let mut geometry = edge.geometry;
+                geometry.0[0] = self.node_map[&new_start_node_idx].geometry.0;
+                let last_idx = geometry.0.len() - 1;
+                geometry.0[last_idx] = self.node_map[&new_end_node_idx].geometry.0;
+                self.insert_edge_with_data(
+                    new_start_node_idx,
+                    new_end_node_idx,
+                    geometry,
+                    edge.data,
+                )?;
+                report.added_edge_count += 1;
+            }
+        }
+        debug_assert!(self.validate_invariants().is_ok());
+        Ok(report)
+    }
+}
+
+/// The nodes and edges moved into a graph by `GeoGraph::merge`.
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Nodes of the other graph which were unified with an existing node.
+    pub unified_node_count: usize,
+    /// Nodes of the other graph which were added as new nodes.
+    pub added_node_count: usize,
+    pub added_edge_count: usize,
 }
 
 pub type UnGeoGraph<E, N> = GeoGraph<E, N, petgraph::Undirected>;
 pub type DiGeoGraph<E, N> = GeoGraph<E, N, petgraph::Directed>;
+
+#[cfg(test)]
+#[generic_tests::define]
+mod tests {
+    use testdir::testdir;
+
+    use super::{EdgeKey, GeoEdge, GeoGraph, MergeReport};
+    use crate::{
+        crs::crs_utils::epsg_4326, error::Error, geofile::geojson::write_lines_to_geojson,
+    };
+
+    #[test]
+    fn test_normalize_edge_orientation<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+        graph
+            .insert_edge(2, 1, vec![(1.0, 1.0), (0.5, 0.5), (1.0, 0.0)].into())
+            .unwrap();
+        // Parallel edge between nodes 1 and 2.
+        graph
+            .insert_edge(2, 1, vec![(1.0, 1.0), (1.0, 0.0)].into())
+            .unwrap();
+
+        graph.normalize_edge_orientation();
+
+        for (start_node_idx, end_node_idx, par_edges) in graph.edge_graph().all_edges() {
+            let start_node_idx = if Ty::is_directed() {
+                start_node_idx
+            } else {
+                start_node_idx.min(end_node_idx)
+            };
+            let end_node_idx = if Ty::is_directed() {
+                end_node_idx
+            } else {
+                start_node_idx.max(end_node_idx)
+            };
+            for edge in par_edges {
+                assert_eq!(
+                    graph.node_map()[&start_node_idx].geometry.0,
+                    edge.geometry.0[0]
+                );
+                assert_eq!(
+                    graph.node_map()[&end_node_idx].geometry.0,
+                    *edge.geometry.0.last().unwrap()
+                );
+            }
+        }
+        // The middle vertex of the reversed edge is kept.
+        let reversed_edges = graph.edge_graph().edge_weight(2, 1).unwrap();
+        if Ty::is_directed() {
+            assert_eq!(
+                geo::Coord::from((1.0, 1.0)),
+                reversed_edges[0].geometry.0[0]
+            );
+        } else {
+            assert_eq!(
+                geo::Coord::from((1.0, 0.0)),
+                reversed_edges[0].geometry.0[0]
+            );
+            assert_eq!(
+                geo::Coord::from((0.5, 0.5)),
+                reversed_edges[0].geometry.0[1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_edge_geometries_independent_of_insertion_order<Ty: petgraph::EdgeType>() {
+        let edges: Vec<(u64, u64, geo::LineString)> = vec![
+            (0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into()),
+            (1, 2, vec![(1.0, 0.0), (1.0, 1.0)].into()),
+            (1, 2, vec![(1.0, 0.0), (0.5, 0.5), (1.0, 1.0)].into()),
+            (3, 0, vec![(0.0, 1.0), (0.0, 0.0)].into()),
+        ];
+        let build_graph = |edge_order: &[usize]| -> GeoGraph<(), (), Ty> {
+            let mut graph = GeoGraph::new(epsg_4326());
+            for edge_idx in edge_order {
+                let (start_node_idx, end_node_idx, geometry) = &edges[*edge_idx];
+                graph
+                    .insert_edge(*start_node_idx, *end_node_idx, geometry.clone())
+                    .unwrap();
+            }
+            graph
+        };
+        // Parallel edges keep their relative order.
+        let graph = build_graph(&[0, 1, 2, 3]);
+        let shuffled_graph = build_graph(&[3, 1, 0, 2]);
+
+        let test_dir = testdir!();
+        let dump_filepath = test_dir.join("edges.geojson");
+        let shuffled_dump_filepath = test_dir.join("shuffled_edges.geojson");
+        write_lines_to_geojson(&graph.edge_geometries(), &dump_filepath).unwrap();
+        write_lines_to_geojson(&shuffled_graph.edge_geometries(), &shuffled_dump_filepath).unwrap();
+
+        assert_eq!(
+            std::fs::read(dump_filepath).unwrap(),
+            std::fs::read(shuffled_dump_filepath).unwrap()
+        );
+        let node_indices: Vec<u64> = shuffled_graph
+            .nodes_sorted()
+            .iter()
+            .map(|(idx, _)| *idx)
+            .collect();
+        assert_eq!(vec![0, 1, 2, 3], node_indices);
+    }
+
+    #[test]
+    fn test_parallel_edges_are_all_visited<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        let first_carriageway: geo::LineString = vec![(0.0, 0.0), (0.5, 0.1), (1.0, 0.0)].into();
+        let second_carriageway: geo::LineString = vec![(0.0, 0.0), (0.5, -0.1), (1.0, 0.0)].into();
+        graph.insert_edge(0, 1, first_carriageway.clone()).unwrap();
+        graph.insert_edge(0, 1, second_carriageway.clone()).unwrap();
+
+        assert_eq!(1, graph.edge_graph().edge_count());
+        assert_eq!(2, graph.edge_count());
+        assert_eq!(
+            vec![first_carriageway, second_carriageway],
+            graph.edge_geometries()
+        );
+        let par_edge_indices: Vec<usize> = graph
+            .edges_sorted()
+            .map(|(_, _, par_edge_idx, _)| par_edge_idx)
+            .collect();
+        assert_eq!(vec![0, 1], par_edge_indices);
+    }
+
+    #[test]
+    fn test_bounding_box<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        assert_eq!(None, graph.bounding_box());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, -2.0)].into())
+            .unwrap();
+        graph
+            .insert_edge(1, 2, vec![(1.0, -2.0), (-3.0, 4.0)].into())
+            .unwrap();
+
+        assert_eq!(
+            Some(geo::Rect::new((-3.0, -2.0), (1.0, 4.0))),
+            graph.bounding_box()
+        );
+    }
+
+    #[test]
+    fn test_transform_geometries<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (0.5, 0.5), (1.0, 0.0)].into())
+            .unwrap();
+        graph
+            .insert_edge(1, 2, vec![(1.0, 0.0), (1.0, 1.0)].into())
+            .unwrap();
+
+        graph
+            .transform_geometries(|coord| {
+                coord.x += 10.0;
+                coord.y *= 2.0;
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(graph.validate_invariants().is_ok());
+        assert_eq!(geo::Point::new(11.0, 0.0), graph.node_map()[&1].geometry);
+        assert_eq!(
+            geo::LineString::from(vec![(10.0, 0.0), (10.5, 1.0), (11.0, 0.0)]),
+            graph.edge_graph().edge_weight(0, 1).unwrap()[0].geometry
+        );
+
+        // A failing transformation leaves the graph unchanged.
+        let result = graph.transform_geometries(|coord| {
+            if coord.y > 1.5 {
+                return Err(crate::error::Error::invalid_data("Out of range"));
+            }
+            coord.x = 0.0;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(geo::Point::new(11.0, 0.0), graph.node_map()[&1].geometry);
+    }
+
+    #[test]
+    fn test_validate_invariants_catches_malformed_mutation<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+        assert!(graph.validate_invariants().is_ok());
+
+        // Moving a node without its edges.
+        graph.node_map_mut().get_mut(&1).unwrap().geometry = geo::Point::new(2.0, 0.0);
+        assert!(graph.validate_invariants().is_err());
+
+        // Moving it back, but adding another node at the same place.
+        graph.node_map_mut().get_mut(&1).unwrap().geometry = geo::Point::new(1.0, 0.0);
+        graph.insert_node(2, geo::Point::new(1.0, 0.0)).unwrap();
+        assert!(graph.validate_invariants().is_err());
+    }
+
+    #[test]
+    fn test_insert_node_within_tolerance<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+
+        // The end node of the second edge differs from node 1 by less than the default tolerance.
+        graph
+            .insert_edge(1, 2, vec![(1.0 + 1e-12, 0.0), (2.0, 0.0)].into())
+            .unwrap();
+
+        assert_eq!(geo::Point::new(1.0, 0.0), graph.node_map()[&1].geometry);
+        assert!(graph.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_insert_node_conflict<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326()).with_node_tolerance(1e-6);
+        graph.insert_node(0, geo::Point::new(1.0, 2.0)).unwrap();
+
+        graph
+            .insert_node(0, geo::Point::new(1.0, 2.0 + 1e-7))
+            .unwrap();
+        let error = graph
+            .insert_node(0, geo::Point::new(1.0, 2.5))
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("(1.0, 2.5)"), "{}", error);
+        assert!(error.contains("(1.0, 2.0)"), "{}", error);
+        assert!(error.contains("by 0.5"), "{}", error);
+        assert_eq!(geo::Point::new(1.0, 2.0), graph.node_map()[&0].geometry);
+    }
+
+    #[test]
+    fn test_merge_unifies_shared_node<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+        let mut other: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        other
+            .insert_edge(0, 1, vec![(1.0, 0.0), (2.0, 0.0)].into())
+            .unwrap();
+
+        let report = graph.merge(other, 1e-6).unwrap();
+
+        assert_eq!(
+            MergeReport {
+                unified_node_count: 1,
+                added_node_count: 1,
+                added_edge_count: 1,
+            },
+            report
+        );
+        assert_eq!(3, graph.node_map().len());
+        assert_eq!(2, graph.edge_count());
+        assert_eq!(geo::Point::new(2.0, 0.0), graph.node_map()[&3].geometry);
+        assert!(graph.edge_graph().contains_edge(1, 3));
+        assert!(graph.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_merge_keeps_parallel_edges<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+        // The same nodes, up to the snap tolerance, connected by another geometry.
+        let mut other: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        other
+            .insert_edge(5, 6, vec![(0.0, 0.0), (0.5, 0.5), (1.0 + 1e-4, 0.0)].into())
+            .unwrap();
+
+        let report = graph.merge(other, 1e-3).unwrap();
+
+        assert_eq!(2, report.unified_node_count);
+        assert_eq!(0, report.added_node_count);
+        assert_eq!(2, graph.node_map().len());
+        assert_eq!(2, graph.edge_count());
+        assert_eq!(2, graph.edge_graph().edge_weight(0, 1).unwrap().len());
+        assert!(graph
+            .edge_geometries()
+            .contains(&vec![(0.0, 0.0), (0.5, 0.5), (1.0, 0.0)].into()));
+        assert!(graph.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_merge_different_crs<Ty: petgraph::EdgeType>() {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        let other: GeoGraph<(), (), Ty> = GeoGraph::new(crate::crs::Crs::Epsg(32654));
+
+        let result = graph.merge(other, 1e-6);
+
+        assert!(matches!(result, Err(Error::CrsMismatch(_))));
+    }
+
+    fn build_edge_index_test_graph<Ty: petgraph::EdgeType>() -> GeoGraph<(), (), Ty> {
+        let mut graph: GeoGraph<(), (), Ty> = GeoGraph::new(epsg_4326());
+        graph
+            .insert_edge(0, 1, vec![(0.0, 0.0), (1.0, 0.0)].into())
+            .unwrap();
+        graph
+            .insert_edge(1, 2, vec![(1.0, 0.0), (1.0, 1.0)].into())
+            .unwrap();
+        // Parallel edge, whose bounding box but not geometry contains (1.5, 0.5).
+        graph
+            .insert_edge(
+                1,
+                2,
+                vec![(1.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0)].into(),
+            )
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_edge_index_queries<Ty: petgraph::EdgeType>() {
+        let mut graph = build_edge_index_test_graph::<Ty>();
+        graph.build_edge_index();
+        assert!(graph.has_current_edge_index());
+
+        let keys = |edges: Vec<(EdgeKey, &GeoEdge<()>)>| {
+            edges.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(0, 1, 0)],
+            keys(graph.edges_intersecting(geo::Rect::new((0.2, -0.1), (0.4, 0.1))))
+        );
+        assert_eq!(
+            vec![(0, 1, 0), (1, 2, 0), (1, 2, 1)],
+            keys(graph.edges_intersecting(geo::Rect::new((0.9, -0.1), (1.1, 0.1))))
+        );
+        assert!(graph
+            .edges_intersecting(geo::Rect::new((1.4, 0.4), (1.6, 0.6)))
+            .is_empty());
+        assert_eq!(
+            vec![(1, 2, 0)],
+            keys(graph.edges_within_distance(geo::Point::new(1.3, 0.5), 0.35))
+        );
+        assert_eq!(
+            vec![(1, 2, 0), (1, 2, 1)],
+            keys(graph.edges_within_distance(geo::Point::new(1.3, 0.5), 0.5))
+        );
+        let edges = graph.edges_within_distance(geo::Point::new(2.1, 0.5), 0.2);
+        assert_eq!(1, edges.len());
+        assert_eq!(4, edges[0].1.geometry.0.len());
+    }
+
+    #[test]
+    fn test_edge_index_invalidated_by_insert<Ty: petgraph::EdgeType>() {
+        let mut graph = build_edge_index_test_graph::<Ty>();
+        graph.build_edge_index();
+
+        graph
+            .insert_edge(2, 3, vec![(1.0, 1.0), (1.5, 0.5)].into())
+            .unwrap();
+
+        assert!(!graph.has_current_edge_index());
+        // The new edge is found by scanning all edges.
+        let edges = graph.edges_within_distance(geo::Point::new(1.5, 0.5), 0.1);
+        assert_eq!(
+            vec![(2, 3, 0)],
+            edges.iter().map(|(key, _)| *key).collect::<Vec<_>>()
+        );
+
+        graph.build_edge_index();
+        assert!(graph.has_current_edge_index());
+        let edges = graph.edges_within_distance(geo::Point::new(1.5, 0.5), 0.1);
+        assert_eq!(
+            vec![(2, 3, 0)],
+            edges.iter().map(|(key, _)| *key).collect::<Vec<_>>()
+        );
+    }
+
+    #[instantiate_tests(<petgraph::Directed>)]
+    mod directed {}
+
+    #[instantiate_tests(<petgraph::Undirected>)]
+    mod undirected {}
+}
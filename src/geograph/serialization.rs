@@ -0,0 +1,308 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use crate::error::{Error, Result, ResultExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    crs::crs_utils::{proj_definition, CrsDefinition},
+    geofile::feature::{FeatureMap, FieldValue},
+};
+
+use super::primitives::{GeoGraph, NodeIdx};
+
+/// Conversion of data associated with graph edges and nodes to and from a serde-friendly representation.
+pub trait SerializableData: Sized {
+    type Repr: Serialize + DeserializeOwned;
+
+    fn to_repr(&self) -> Self::Repr;
+    fn from_repr(repr: Self::Repr) -> Result<Self>;
+}
+
+impl SerializableData for () {
+    type Repr = ();
+
+    fn to_repr(&self) -> Self::Repr {}
+
+    fn from_repr(_: Self::Repr) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl SerializableData for String {
+    type Repr = String;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.clone()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(repr)
+    }
+}
+
+/// Serialize `SerializableData` in a serde field, via `#[serde(serialize_with = "serialize_data")]`.
+pub fn serialize_data<T: SerializableData, S: serde::Serializer>(
+    data: &T,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    data.to_repr().serialize(serializer)
+}
+
+/// Deserialize `SerializableData` in a serde field, via `#[serde(deserialize_with = "deserialize_data")]`.
+pub fn deserialize_data<'de, T: SerializableData, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<T, D::Error> {
+    T::from_repr(T::Repr::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Serde-friendly mirror of gdal's FieldValue.
+#[derive(Serialize, Deserialize)]
+pub enum FieldValueRepr {
+    IntegerValue(i32),
+    IntegerListValue(Vec<i32>),
+    Integer64Value(i64),
+    Integer64ListValue(Vec<i64>),
+    StringValue(String),
+    StringListValue(Vec<String>),
+    RealValue(f64),
+    RealListValue(Vec<f64>),
+    /// Naive date as YYYY-MM-DD and the UTC offset in seconds.
+    DateValue(String, i32),
+    /// RFC 3339 date and time.
+    DateTimeValue(String),
+}
+
+impl SerializableData for FeatureMap {
+    type Repr = Vec<(String, FieldValueRepr)>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter()
+            .map(|(key, value)| (key.clone(), field_value_to_repr(value)))
+            .collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        repr.into_iter()
+            .map(|(key, value)| Ok((key, field_value_from_repr(value)?)))
+            .collect()
+    }
+}
+
+#[allow(deprecated)] // gdal represents dates with chrono's deprecated Date type.
+fn field_value_to_repr(value: &FieldValue) -> FieldValueRepr {
+    match value {
+        FieldValue::IntegerValue(value) => FieldValueRepr::IntegerValue(*value),
+        FieldValue::IntegerListValue(value) => FieldValueRepr::IntegerListValue(value.clone()),
+        FieldValue::Integer64Value(value) => FieldValueRepr::Integer64Value(*value),
+        FieldValue::Integer64ListValue(value) => FieldValueRepr::Integer64ListValue(value.clone()),
+        FieldValue::StringValue(value) => FieldValueRepr::StringValue(value.clone()),
+        FieldValue::StringListValue(value) => FieldValueRepr::StringListValue(value.clone()),
+        FieldValue::RealValue(value) => FieldValueRepr::RealValue(*value),
+        FieldValue::RealListValue(value) => FieldValueRepr::RealListValue(value.clone()),
+        FieldValue::DateValue(value) => FieldValueRepr::DateValue(
+            value.naive_local().to_string(),
+            value.offset().local_minus_utc(),
+        ),
+        FieldValue::DateTimeValue(value) => FieldValueRepr::DateTimeValue(value.to_rfc3339()),
+    }
+}
+
+#[allow(deprecated)] // gdal represents dates with chrono's deprecated Date type.
+fn field_value_from_repr(repr: FieldValueRepr) -> Result<FieldValue> {
+    Ok(match repr {
+        FieldValueRepr::IntegerValue(value) => FieldValue::IntegerValue(value),
+        FieldValueRepr::IntegerListValue(value) => FieldValue::IntegerListValue(value),
+        FieldValueRepr::Integer64Value(value) => FieldValue::Integer64Value(value),
+        FieldValueRepr::Integer64ListValue(value) => FieldValue::Integer64ListValue(value),
+        FieldValueRepr::StringValue(value) => FieldValue::StringValue(value),
+        FieldValueRepr::StringListValue(value) => FieldValue::StringListValue(value),
+        FieldValueRepr::RealValue(value) => FieldValue::RealValue(value),
+        FieldValueRepr::RealListValue(value) => FieldValue::RealListValue(value),
+        FieldValueRepr::DateValue(date, utc_offset_seconds) => {
+            let date: chrono::NaiveDate = date.parse()?;
+            let offset = chrono::FixedOffset::east_opt(utc_offset_seconds).ok_or_else(|| {
+                Error::invalid_data(format!("Invalid UTC offset {}", utc_offset_seconds))
+            })?;
+            FieldValue::DateValue(chrono::Date::from_utc(date, offset))
+        }
+        FieldValueRepr::DateTimeValue(date_time) => {
+            FieldValue::DateTimeValue(chrono::DateTime::parse_from_rfc3339(&date_time)?)
+        }
+    })
+}
+
+/// Serialized form of a GeoEdge: the coordinates of its geometry and its data.
+type GeoEdgeRepr<E> = (Vec<[f64; 2]>, E);
+
+/// Serialized form of a GeoGraph. Geometries are stored as coordinate arrays, the CRS as a `CrsDefinition` string:
+/// its EPSG authority string if it has an EPSG code, otherwise its WKT, see `proj_definition`.
+#[derive(Serialize, Deserialize)]
+struct GeoGraphRepr<E, N> {
+    directed: bool,
+    crs_definition: String,
+    nodes: Vec<(NodeIdx, [f64; 2], N)>,
+    /// Start node, end node and the parallel edges between them.
+    edges: Vec<(NodeIdx, NodeIdx, Vec<GeoEdgeRepr<E>>)>,
+}
+
+impl<E, N, Ty> GeoGraph<E, N, Ty>
+where
+    E: Default + SerializableData,
+    N: Default + SerializableData,
+    Ty: petgraph::EdgeType,
+{
+    /// Write the graph to a file in a compact binary format, which can be read with `deserialize_from`.
+    pub fn serialize_to(&self, filepath: &Path) -> Result<()> {
+        let mut repr = GeoGraphRepr {
+            directed: Ty::is_directed(),
+            crs_definition: proj_definition(&self.crs)?,
+            nodes: self
+                .nodes_sorted()
+                .into_iter()
+                .map(|(idx, node)| {
+                    (
+                        idx,
+                        [node.geometry.x(), node.geometry.y()],
+                        node.data.to_repr(),
+                    )
+                })
+                .collect(),
+            edges: Vec::new(),
+        };
+        for (start_node_idx, end_node_idx, par_edge_idx, edge) in self.edges_sorted() {
+            if par_edge_idx == 0 {
+                repr.edges.push((start_node_idx, end_node_idx, Vec::new()));
+            }
+            let coords = edge
+                .geometry
+                .coords()
+                .map(|coord| [coord.x, coord.y])
+                .collect();
+            repr.edges
+                .last_mut()
+                .unwrap()
+                .2
+                .push((coords, edge.data.to_repr()));
+        }
+        let writer = BufWriter::new(
+            File::create(filepath).with_context(|| format!("Creating {:?}", filepath))?,
+        );
+        bincode::serialize_into(writer, &repr)
+            .with_context(|| format!("Serializing graph to {:?}", filepath))
+    }
+
+    /// Read a graph written by `serialize_to`. The directedness of the stored graph must match `Ty`.
+    pub fn deserialize_from(filepath: &Path) -> Result<Self> {
+        let reader = BufReader::new(
+            File::open(filepath).with_context(|| format!("Opening {:?}", filepath))?,
+        );
+        let repr: GeoGraphRepr<E::Repr, N::Repr> = bincode::deserialize_from(reader)
+            .with_context(|| format!("Deserializing graph from {:?}", filepath))?;
+        if repr.directed != Ty::is_directed() {
+            return Err(Error::invalid_data(format!(
+                "Graph in {:?} has a different directedness than requested",
+                filepath
+            )));
+        }
+
+        let crs = CrsDefinition::Definition(repr.crs_definition).to_crs()?;
+        let mut graph = GeoGraph::new(crs);
+        for (start_node_idx, end_node_idx, par_edges) in repr.edges {
+            for (coords, data) in par_edges {
+                let geometry: geo::LineString = coords.into_iter().map(geo::Coord::from).collect();
+                graph.insert_edge_with_data(
+                    start_node_idx,
+                    end_node_idx,
+                    geometry,
+                    E::from_repr(data)?,
+                )?;
+            }
+        }
+        for (idx, [x, y], data) in repr.nodes {
+            let geometry = geo::Point::new(x, y);
+            graph.insert_node(idx, geometry)?;
+            graph.node_map_mut().get_mut(&idx).unwrap().data = N::from_repr(data)?;
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+#[generic_tests::define]
+mod tests {
+    use std::collections::HashMap;
+
+    use testdir::testdir;
+
+    use crate::{
+        geofile::feature::{FeatureMap, FieldValue},
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_with_data_in_crs},
+    };
+
+    #[test]
+    fn test_serialization_round_trip<Ty: petgraph::EdgeType>() {
+        let lines: Vec<geo::LineString> = vec![
+            vec![(139.78, 35.68), (139.79, 35.68)].into(),
+            vec![(139.79, 35.68), (139.79, 35.69), (139.80, 35.69)].into(),
+            // Parallel edge.
+            vec![(139.79, 35.68), (139.80, 35.69)].into(),
+        ];
+        let data: Vec<FeatureMap> = (0..lines.len())
+            .map(|idx| {
+                HashMap::from([
+                    (
+                        "name".to_string(),
+                        FieldValue::StringValue(format!("road {}", idx)),
+                    ),
+                    ("lanes".to_string(), FieldValue::IntegerValue(idx as i32)),
+                    ("width".to_string(), FieldValue::RealValue(3.5)),
+                ])
+            })
+            .collect();
+        let mut graph: GeoGraph<FeatureMap, FeatureMap, Ty> =
+            build_geograph_from_lines_with_data_in_crs(lines, data, crate::crs::Crs::Epsg(32654))
+                .unwrap();
+        graph.node_map_mut().get_mut(&0).unwrap().data.insert(
+            "degree".to_string(),
+            FieldValue::Integer64ListValue(vec![1, 2]),
+        );
+
+        let filepath = testdir!().join("graph.bin");
+        graph.serialize_to(&filepath).unwrap();
+        let read_graph: GeoGraph<FeatureMap, FeatureMap, Ty> =
+            GeoGraph::deserialize_from(&filepath).unwrap();
+
+        assert_eq!(graph.node_map().len(), read_graph.node_map().len());
+        for (idx, node) in graph.node_map() {
+            let read_node = read_graph.node_map().get(idx).unwrap();
+            assert_eq!(node.geometry, read_node.geometry);
+            assert_eq!(node.data, read_node.data);
+        }
+        assert_eq!(
+            graph.edge_graph().edge_count(),
+            read_graph.edge_graph().edge_count()
+        );
+        for (start_node_idx, end_node_idx, par_edges) in graph.edge_graph().all_edges() {
+            let read_par_edges = read_graph
+                .edge_graph()
+                .edge_weight(start_node_idx, end_node_idx)
+                .unwrap();
+            assert_eq!(par_edges.len(), read_par_edges.len());
+            for (edge, read_edge) in par_edges.iter().zip(read_par_edges) {
+                assert_eq!(edge.geometry, read_edge.geometry);
+                assert_eq!(edge.data, read_edge.data);
+            }
+        }
+        assert_eq!(32654, read_graph.crs.auth_code().unwrap());
+    }
+
+    #[instantiate_tests(<petgraph::Directed>)]
+    mod directed {}
+
+    #[instantiate_tests(<petgraph::Undirected>)]
+    mod undirected {}
+}
@@ -1,8 +1,13 @@
 use std::iter::zip;
 
-use crate::crs::crs_utils::{epsg_4326, epsg_code_to_authority_string, query_utm_crs_info};
-
-use anyhow::anyhow;
+#[cfg(feature = "gdal-io")]
+use crate::crs::crs_utils::{proj_definition, query_utm_crs_info, Projection};
+use crate::crs::{crs_utils::epsg_4326, Crs};
+
+#[cfg(feature = "gdal-io")]
+use crate::error::ResultExt;
+use crate::error::{Error, Result};
+#[cfg(feature = "gdal-io")]
 use proj::Transform;
 
 use super::primitives::{GeoGraph, NodeIdx};
@@ -41,10 +46,12 @@ impl NodeIndexer {
 ///
 /// Nodes are indexed from zero, in the order of appearance. As an example, this code:
 /// ```
+/// # use topo_rust::geograph::{primitives::GeoGraph, utils::build_geograph_from_lines};
 /// let my_graph: GeoGraph<(), (), petgraph::Directed> = build_geograph_from_lines(vec![
 ///     vec![(0.0, 0.0), (1.0, 0.0)].into(),
 ///     vec![(1.0, 0.0), (2.0, 0.0)].into(),
-/// ]);
+/// ])
+/// .unwrap();
 /// ```
 ///
 /// will create a graph with nodes like this:
@@ -56,23 +63,34 @@ impl NodeIndexer {
 /// - `E`: the data type associeted with edges of the resulting graph.
 /// - `N`: the data type associated with nodes of the resulting graph.
 /// - `Ty`: the directedness of the resulting graph, e.g. petgraph::Directed.
+///
+/// The graph is in EPSG:4326, use `build_geograph_from_lines_in_crs` for lines in another CRS.
 pub fn build_geograph_from_lines<E: Default, D: Default, Ty: petgraph::EdgeType>(
     lines: Vec<geo::LineString>,
-) -> anyhow::Result<GeoGraph<E, D, Ty>> {
-    let mut node_indexer = NodeIndexer::new();
-    let mut geograph = GeoGraph::new(epsg_4326());
-    for (index, line) in lines.into_iter().enumerate() {
-        if 2 > line.coords().count() {
-            continue;
-        }
-        let start_point = line.points().nth(0).unwrap();
-        let start_node_idx = node_indexer.get_index_for_coordinate(&start_point.into());
-        let end_point = line.points().last().unwrap();
-        let end_node_idx = node_indexer.get_index_for_coordinate(&end_point.into());
-        geograph.insert_edge(start_node_idx, end_node_idx, line)?;
-    }
+) -> Result<GeoGraph<E, D, Ty>> {
+    build_geograph_from_lines_in_crs(lines, epsg_4326())
+}
 
-    Ok(geograph)
+/// Like `build_geograph_from_lines`, for lines in the given CRS.
+pub fn build_geograph_from_lines_in_crs<E: Default, D: Default, Ty: petgraph::EdgeType>(
+    lines: Vec<geo::LineString>,
+    crs: Crs,
+) -> Result<GeoGraph<E, D, Ty>> {
+    build_geograph_from_lines_with_precision(lines, crs, None)
+}
+
+/// Like `build_geograph_from_lines_in_crs`, with the coordinates quantized to a grid of `coordinate_precision` if
+/// given, see `GeoGraphBuilder::coordinate_precision`.
+pub fn build_geograph_from_lines_with_precision<E: Default, D: Default, Ty: petgraph::EdgeType>(
+    lines: Vec<geo::LineString>,
+    crs: Crs,
+    coordinate_precision: Option<f64>,
+) -> Result<GeoGraph<E, D, Ty>> {
+    let mut builder = GeoGraphBuilder::new(crs).coordinate_precision(coordinate_precision);
+    for line in lines.into_iter() {
+        builder.add_line(line)?;
+    }
+    Ok(builder.build())
 }
 
 /// Like `build_geograph_from_lines`, with the addition of also initializing the edges with data.
@@ -81,89 +99,282 @@ pub fn build_geograph_from_lines<E: Default, D: Default, Ty: petgraph::EdgeType>
 pub fn build_geograph_from_lines_with_data<E: Default, D: Default, Ty: petgraph::EdgeType>(
     lines: Vec<geo::LineString>,
     data: Vec<E>,
-) -> anyhow::Result<GeoGraph<E, D, Ty>> {
+) -> Result<GeoGraph<E, D, Ty>> {
+    build_geograph_from_lines_with_data_in_crs(lines, data, epsg_4326())
+}
+
+/// Like `build_geograph_from_lines_with_data`, for lines in the given CRS.
+pub fn build_geograph_from_lines_with_data_in_crs<
+    E: Default,
+    D: Default,
+    Ty: petgraph::EdgeType,
+>(
+    lines: Vec<geo::LineString>,
+    data: Vec<E>,
+    crs: Crs,
+) -> Result<GeoGraph<E, D, Ty>> {
+    build_geograph_from_lines_with_data_and_precision(lines, data, crs, None)
+}
+
+/// Like `build_geograph_from_lines_with_data_in_crs`, with the coordinates quantized to a grid of
+/// `coordinate_precision` if given, see `GeoGraphBuilder::coordinate_precision`.
+pub fn build_geograph_from_lines_with_data_and_precision<
+    E: Default,
+    D: Default,
+    Ty: petgraph::EdgeType,
+>(
+    lines: Vec<geo::LineString>,
+    data: Vec<E>,
+    crs: Crs,
+    coordinate_precision: Option<f64>,
+) -> Result<GeoGraph<E, D, Ty>> {
     if lines.len() != data.len() {
-        return Err(anyhow!(
+        return Err(Error::invalid_data(format!(
             "Number of lines ({}) must match number of data ({})",
             lines.len(),
             data.len()
-        ));
+        )));
     }
 
-    let mut node_indexer = NodeIndexer::new();
-    let mut geograph = GeoGraph::new(epsg_4326());
+    let mut builder = GeoGraphBuilder::new(crs).coordinate_precision(coordinate_precision);
     for (line, data_item) in zip(lines.into_iter(), data.into_iter()) {
+        builder.add_line_with_data(line, data_item)?;
+    }
+    Ok(builder.build())
+}
+
+/// Builds a topologically correct GeoGraph incrementally, one linestring at a time. Useful when the lines are not
+/// available all at once, e.g. when streamed from a file. See `build_geograph_from_lines` for the node indexing.
+pub struct GeoGraphBuilder<E: Default, N: Default, Ty: petgraph::EdgeType> {
+    node_indexer: NodeIndexer,
+    geograph: GeoGraph<E, N, Ty>,
+    degenerate_line_count: usize,
+    coordinate_precision: Option<f64>,
+}
+
+impl<E: Default, N: Default, Ty: petgraph::EdgeType> GeoGraphBuilder<E, N, Ty> {
+    pub fn new(crs: Crs) -> Self {
+        Self {
+            node_indexer: NodeIndexer::new(),
+            geograph: GeoGraph::new(crs),
+            degenerate_line_count: 0,
+            coordinate_precision: None,
+        }
+    }
+
+    /// Quantize the coordinates of the added lines to a grid with this spacing, in units of the CRS, before their
+    /// endpoints are indexed. Endpoints which differ only by noise below the spacing then share a node. Consecutive
+    /// coordinates which fall onto the same grid point are merged.
+    pub fn coordinate_precision(mut self, coordinate_precision: Option<f64>) -> Self {
+        self.coordinate_precision = coordinate_precision;
+        self
+    }
+
+    /// Add an edge with default data. Lines with less than two points are skipped.
+    pub fn add_line(&mut self, line: geo::LineString) -> Result<()> {
+        self.add_line_with_data(line, E::default())
+    }
+
+    /// Add an edge with the given data. Lines with less than two points are skipped.
+    pub fn add_line_with_data(&mut self, line: geo::LineString, data: E) -> Result<()> {
+        let line = match self.coordinate_precision {
+            Some(coordinate_precision) => quantize_line(line, coordinate_precision)?,
+            None => line,
+        };
         if 2 > line.coords().count() {
-            continue;
+            self.degenerate_line_count += 1;
+            return Ok(());
         }
         let start_point = line.points().nth(0).unwrap();
-        let start_node_idx = node_indexer.get_index_for_coordinate(&start_point.into());
+        let start_node_idx = self
+            .node_indexer
+            .get_index_for_coordinate(&start_point.into());
         let end_point = line.points().last().unwrap();
-        let end_node_idx = node_indexer.get_index_for_coordinate(&end_point.into());
-        geograph.insert_edge_with_data(start_node_idx, end_node_idx, line, data_item)?;
+        let end_node_idx = self
+            .node_indexer
+            .get_index_for_coordinate(&end_point.into());
+        self.geograph
+            .insert_edge_with_data(start_node_idx, end_node_idx, line, data)
+    }
+
+    /// The number of lines skipped so far because they have less than two points.
+    pub fn degenerate_line_count(&self) -> usize {
+        self.degenerate_line_count
     }
 
-    Ok(geograph)
+    pub fn build(self) -> GeoGraph<E, N, Ty> {
+        self.geograph
+    }
+}
+
+/// Round the coordinates of `line` to the nearest multiples of `coordinate_precision`, merging consecutive
+/// coordinates which are rounded to the same point.
+pub fn quantize_line(line: geo::LineString, coordinate_precision: f64) -> Result<geo::LineString> {
+    if !(coordinate_precision.is_finite() && coordinate_precision > 0.0) {
+        return Err(Error::ConfigError(format!(
+            "The coordinate precision must be positive, got {}",
+            coordinate_precision
+        )));
+    }
+    let quantize = |value: f64| (value / coordinate_precision).round() * coordinate_precision;
+    let mut coords: Vec<geo::Coord> = line
+        .into_inner()
+        .into_iter()
+        .map(|coord| geo::coord! { x: quantize(coord.x), y: quantize(coord.y) })
+        .collect();
+    coords.dedup();
+    Ok(coords.into())
 }
 
+#[cfg(feature = "gdal-io")]
 pub fn get_utm_zone_for_graph<E: Default, N: Default, Ty: petgraph::EdgeType>(
     geograph: &GeoGraph<E, N, Ty>,
-) -> anyhow::Result<gdal::spatial_ref::SpatialRef> {
+) -> Result<Crs> {
     if !geograph.crs.is_geographic() {
-        return Err(anyhow!("The lines are not in a geographic CRS."));
+        return Err(Error::CrsMismatch(
+            "The lines are not in a geographic CRS.".to_string(),
+        ));
     }
-    match geograph.node_map().values().nth(0) {
-        Some(node) => {
+    match geograph.nodes_sorted().first() {
+        Some((_, node)) => {
             let utm_zone_codes =
                 query_utm_crs_info(node.geometry.x(), node.geometry.y(), Some("WGS84"))?;
             let utm_zone_code = utm_zone_codes
                 .get(0)
-                .ok_or_else(|| (anyhow!("No UTM zones found for graph")))?;
-            gdal::spatial_ref::SpatialRef::from_epsg(*utm_zone_code)
-                .map_err(|err| anyhow!("Could not create SpatialRef from EPSG code. {}", err))
+                .ok_or_else(|| Error::CrsMismatch("No UTM zones found for graph".to_string()))?;
+            Ok(Crs::Epsg(*utm_zone_code))
         }
         None => {
-            return Err(anyhow!(
-                "Could not determine UTM zone for graph because it has no nodes."
+            return Err(Error::EmptyGraph(
+                "Could not determine UTM zone for graph because it has no nodes.".to_string(),
             ))
         }
     }
 }
 
+/// The projected CRS of the kind `projection` for a graph in a geographic CRS. Local projections are centered on the
+/// center of the bounding box of the graph.
+#[cfg(feature = "gdal-io")]
+pub fn get_projected_crs_for_graph<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    geograph: &GeoGraph<E, N, Ty>,
+    projection: Projection,
+) -> Result<Crs> {
+    if !geograph.crs.is_geographic() {
+        return Err(Error::CrsMismatch(
+            "The lines are not in a geographic CRS.".to_string(),
+        ));
+    }
+    if projection == Projection::Utm {
+        return get_utm_zone_for_graph(geograph);
+    }
+    let bounding_box = geograph.bounding_box().ok_or_else(|| {
+        Error::EmptyGraph(
+            "Could not center a local projection on the graph because it has no edges.".to_string(),
+        )
+    })?;
+    let proj_string = projection
+        .local_proj_string(bounding_box.center())
+        .ok_or_else(|| Error::ConfigError(format!("{:?} is not a local projection", projection)))?;
+    let spatial_ref = gdal::spatial_ref::SpatialRef::from_proj4(&proj_string)
+        .with_context(|| format!("Parsing PROJ string '{}'", proj_string))?;
+    Crs::from_spatial_ref(&spatial_ref)
+}
+
+/// Projection from the CRS of `geograph` to `to_crs`. Either CRS may lack an EPSG code, see `proj_definition`.
+#[cfg(feature = "gdal-io")]
+fn geograph_projection<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    geograph: &GeoGraph<E, N, Ty>,
+    to_crs: &Crs,
+) -> Result<proj::Proj> {
+    Ok(proj::Proj::new_known_crs(
+        &proj_definition(&geograph.crs)?,
+        &proj_definition(to_crs)?,
+        None,
+    )?)
+}
+
 /// Project a geograph into the CRS indicated by `to_crs`.
+#[cfg(feature = "gdal-io")]
 pub fn project_geograph<E: Default, N: Default, Ty: petgraph::EdgeType>(
     geograph: &mut GeoGraph<E, N, Ty>,
-    to_crs: &gdal::spatial_ref::SpatialRef,
-) -> anyhow::Result<()> {
-    let projection = proj::Proj::new_known_crs(
-        &epsg_code_to_authority_string(geograph.crs.auth_code()? as u32),
-        &epsg_code_to_authority_string(to_crs.auth_code()? as u32),
-        None,
-    )?;
-    for (_, _, par_edges) in geograph.edge_graph_mut().all_edges_mut() {
-        for edge in par_edges.iter_mut() {
-            edge.geometry.transform(&projection)?;
-        }
-    }
-    for node in geograph.node_map_mut().values_mut() {
-        node.geometry.transform(&projection)?;
-    }
+    to_crs: &Crs,
+) -> Result<()> {
+    let projection = geograph_projection(geograph, to_crs)?;
+    geograph.transform_geometries(|coord| {
+        coord.transform(&projection)?;
+        Ok(())
+    })?;
 
     geograph.crs = to_crs.clone();
     Ok(())
 }
 
+/// Create a copy of a geograph projected into the CRS indicated by `to_crs`, leaving `geograph` unchanged. Node
+/// indices and the data of edges and nodes are kept.
+#[cfg(feature = "gdal-io")]
+pub fn project_geograph_copy<E, N, Ty>(
+    geograph: &GeoGraph<E, N, Ty>,
+    to_crs: &Crs,
+) -> Result<GeoGraph<E, N, Ty>>
+where
+    E: Default + Clone,
+    N: Default + Clone,
+    Ty: petgraph::EdgeType,
+{
+    let projection = geograph_projection(geograph, to_crs)?;
+    let project_coord = |coord: &geo::Coord| -> Result<geo::Coord> {
+        let mut coord = *coord;
+        coord.transform(&projection)?;
+        Ok(coord)
+    };
+
+    let mut projected = GeoGraph::new(to_crs.clone());
+    for (start_node_idx, end_node_idx, _, edge) in geograph.edges_sorted() {
+        let geometry = edge
+            .geometry
+            .coords()
+            .map(project_coord)
+            .collect::<Result<geo::LineString>>()?;
+        projected.insert_edge_with_data(
+            start_node_idx,
+            end_node_idx,
+            geometry,
+            edge.data.clone(),
+        )?;
+    }
+    for (idx, node) in geograph.nodes_sorted() {
+        projected.insert_node(idx, project_coord(&node.geometry.0)?.into())?;
+        projected.node_map_mut().get_mut(&idx).unwrap().data = node.data.clone();
+    }
+    Ok(projected)
+}
+
 #[cfg(test)]
 #[generic_tests::define]
 mod tests {
 
     use std::iter::zip;
 
+    #[cfg(feature = "gdal-io")]
     use approx::assert_abs_diff_eq;
 
-    use crate::geograph::{primitives::GeoGraph, utils::build_geograph_from_lines};
+    #[cfg(feature = "gdal-io")]
+    use geo::{EuclideanLength, GeodesicLength};
+
+    #[cfg(feature = "gdal-io")]
+    use crate::crs::crs_utils::Projection;
+    use crate::{
+        crs::crs_utils::epsg_4326,
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines},
+    };
 
-    use super::{build_geograph_from_lines_with_data, project_geograph};
+    use super::{
+        build_geograph_from_lines_in_crs, build_geograph_from_lines_with_data,
+        build_geograph_from_lines_with_data_in_crs, build_geograph_from_lines_with_precision,
+        GeoGraphBuilder,
+    };
+    #[cfg(feature = "gdal-io")]
+    use super::{get_projected_crs_for_graph, project_geograph, project_geograph_copy};
 
     /// Graph type used in tests, holds no extra data for edges or nodes.
     type TestGraph<Ty> = GeoGraph<(), (), Ty>;
@@ -205,6 +416,73 @@ mod tests {
             let node = graph.node_map().get(&(node_index as u64)).unwrap();
             assert_eq!(*expected_coord, (node.geometry.x(), node.geometry.y()));
         }
+        assert_eq!(4326, graph.crs.auth_code().unwrap());
+    }
+
+    #[test]
+    fn test_build_geograph_from_lines_in_crs<Ty: petgraph::EdgeType>() {
+        let lines: Vec<geo::LineString> =
+            vec![vec![(390000.0, 3950000.0), (390010.0, 3950000.0)].into()];
+        let crs = crate::crs::Crs::Epsg(32654);
+
+        let graph: TestGraph<Ty> =
+            build_geograph_from_lines_in_crs(lines.clone(), crs.clone()).unwrap();
+        assert_eq!(32654, graph.crs.auth_code().unwrap());
+
+        let graph: GeoGraph<String, String, Ty> =
+            build_geograph_from_lines_with_data_in_crs(lines, vec!["a".to_string()], crs).unwrap();
+        assert_eq!(32654, graph.crs.auth_code().unwrap());
+        assert_eq!(1, graph.edge_graph().edge_count());
+    }
+
+    #[test]
+    fn test_build_geograph_from_lines_with_precision<Ty: petgraph::EdgeType>() {
+        // The second line starts within a millimeter of the end of the first one.
+        let lines: Vec<geo::LineString> = vec![
+            vec![(0.0, 0.0), (10.0004, 0.0)].into(),
+            vec![(9.9998, 0.0003), (20.0, 0.0)].into(),
+        ];
+        let crs = crate::crs::Crs::Epsg(32654);
+
+        let graph: TestGraph<Ty> =
+            build_geograph_from_lines_with_precision(lines.clone(), crs.clone(), None).unwrap();
+        assert_eq!(4, graph.node_map().len());
+
+        let graph: TestGraph<Ty> =
+            build_geograph_from_lines_with_precision(lines, crs, Some(1e-2)).unwrap();
+        assert_eq!(3, graph.node_map().len());
+        let shared_node = graph.node_map().get(&1).unwrap();
+        assert_eq!(
+            (10.0, 0.0),
+            (shared_node.geometry.x(), shared_node.geometry.y())
+        );
+        assert_eq!(2, graph.edge_graph().edge_count());
+    }
+
+    #[test]
+    fn test_graph_builder_drops_lines_collapsed_by_precision<Ty: petgraph::EdgeType>() {
+        let mut builder: GeoGraphBuilder<(), (), Ty> =
+            GeoGraphBuilder::new(epsg_4326()).coordinate_precision(Some(1e-6));
+        builder
+            .add_line(vec![(10.0, 50.0), (10.0000001, 50.0000001)].into())
+            .unwrap();
+        builder
+            .add_line(vec![(10.0, 50.0), (10.0000001, 50.0), (10.001, 50.0)].into())
+            .unwrap();
+        assert_eq!(1, builder.degenerate_line_count());
+
+        let graph = builder.build();
+        let (_, _, _, edge) = graph.edges_sorted().next().unwrap();
+        assert_eq!(2, edge.geometry.coords().count());
+    }
+
+    #[test]
+    fn test_graph_builder_rejects_non_positive_precision<Ty: petgraph::EdgeType>() {
+        let mut builder: GeoGraphBuilder<(), (), Ty> =
+            GeoGraphBuilder::new(epsg_4326()).coordinate_precision(Some(0.0));
+        assert!(builder
+            .add_line(vec![(10.0, 50.0), (10.001, 50.0)].into())
+            .is_err());
     }
 
     #[test]
@@ -235,6 +513,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "gdal-io")]
     #[test]
     fn test_project_geograph<Ty: petgraph::EdgeType>() {
         // EPSG 4326 coordinates.
@@ -247,10 +526,10 @@ mod tests {
             vec![node_2_coord, node_3_coord].into(),
         ];
 
-        let mut graph: TestGraph<Ty> = build_geograph_from_lines(lines).unwrap();
-        graph.crs = crate::crs::crs_utils::epsg_4326();
+        let mut graph: TestGraph<Ty> =
+            build_geograph_from_lines_in_crs(lines, epsg_4326()).unwrap();
 
-        let target_crs = gdal::spatial_ref::SpatialRef::from_epsg(32654).unwrap(); // UTM zone 54N
+        let target_crs = crate::crs::Crs::Epsg(32654); // UTM zone 54N
         project_geograph(&mut graph, &target_crs).unwrap();
 
         // Computed using https://coordinates-converter.com/
@@ -290,6 +569,100 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "gdal-io")]
+    #[test]
+    fn test_project_geograph_to_local_crs<Ty: petgraph::EdgeType>() {
+        let lines: Vec<geo::LineString> = vec![
+            vec![(139.7895073, 35.6862101), (139.7912979, 35.6870132)].into(),
+            vec![(139.7912979, 35.6870132), (139.7919128, 35.6862357)].into(),
+        ];
+        let geographic_graph: TestGraph<Ty> =
+            build_geograph_from_lines_in_crs(lines.clone(), epsg_4326()).unwrap();
+        let edge_lengths = |graph: &TestGraph<Ty>| -> Vec<f64> {
+            graph
+                .edge_geometries()
+                .iter()
+                .map(|geometry| geometry.euclidean_length())
+                .collect()
+        };
+        let geodesic_lengths: Vec<f64> = lines.iter().map(|line| line.geodesic_length()).collect();
+        let mut utm_graph: TestGraph<Ty> =
+            build_geograph_from_lines_in_crs(lines.clone(), epsg_4326()).unwrap();
+        let utm_crs = get_projected_crs_for_graph(&utm_graph, Projection::Utm).unwrap();
+        project_geograph(&mut utm_graph, &utm_crs).unwrap();
+        assert_eq!(32654, utm_graph.crs.auth_code().unwrap());
+
+        for projection in [
+            Projection::LocalTransverseMercator,
+            Projection::LocalAzimuthalEqualArea,
+        ] {
+            let mut graph: TestGraph<Ty> =
+                build_geograph_from_lines_in_crs(lines.clone(), epsg_4326()).unwrap();
+            let local_crs = get_projected_crs_for_graph(&geographic_graph, projection).unwrap();
+            project_geograph(&mut graph, &local_crs).unwrap();
+
+            assert!(graph.crs.is_projected());
+            // The local projection is centered on the graph.
+            let center = graph.bounding_box().unwrap().center();
+            assert_abs_diff_eq!(0.0, center.x, epsilon = 1.0);
+            assert_abs_diff_eq!(0.0, center.y, epsilon = 1.0);
+            for ((length, geodesic_length), utm_length) in zip(
+                zip(edge_lengths(&graph), &geodesic_lengths),
+                edge_lengths(&utm_graph),
+            ) {
+                // Millimeter tolerance.
+                assert_abs_diff_eq!(*geodesic_length, length, epsilon = 1e-3);
+                // The UTM zone 54N distorts the scale by about 0.025% this far west of its central meridian.
+                assert_abs_diff_eq!(1.0, utm_length / length, epsilon = 5e-4);
+            }
+        }
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[test]
+    fn test_project_geograph_copy<Ty: petgraph::EdgeType>() {
+        let lines: Vec<geo::LineString> = vec![
+            vec![(139.7895073, 35.6862101), (139.7912979, 35.6870132)].into(),
+            vec![(139.7912979, 35.6870132), (139.7919128, 35.6862357)].into(),
+        ];
+        let data = vec!["a".to_string(), "b".to_string()];
+        let graph: GeoGraph<String, String, Ty> =
+            build_geograph_from_lines_with_data_in_crs(lines.clone(), data, epsg_4326()).unwrap();
+        let target_crs = crate::crs::Crs::Epsg(32654);
+
+        let copy = project_geograph_copy(&graph, &target_crs).unwrap();
+
+        // The original is unchanged.
+        assert_eq!(4326, graph.crs.auth_code().unwrap());
+        assert_eq!(
+            lines[0],
+            graph.edge_graph().edge_weight(0, 1).unwrap()[0].geometry
+        );
+        // The copy equals the graph projected in place.
+        let mut projected: GeoGraph<String, String, Ty> =
+            build_geograph_from_lines_with_data_in_crs(
+                lines,
+                vec!["a".to_string(), "b".to_string()],
+                epsg_4326(),
+            )
+            .unwrap();
+        project_geograph(&mut projected, &target_crs).unwrap();
+        assert_eq!(32654, copy.crs.auth_code().unwrap());
+        assert_eq!(projected.node_map().len(), copy.node_map().len());
+        for (idx, node) in projected.node_map() {
+            assert_eq!(node.geometry, copy.node_map().get(idx).unwrap().geometry);
+        }
+        for (start_node_idx, end_node_idx, _, edge) in projected.edges_sorted() {
+            let copied_edge = &copy
+                .edge_graph()
+                .edge_weight(start_node_idx, end_node_idx)
+                .unwrap()[0];
+            assert_eq!(edge.geometry, copied_edge.geometry);
+            assert_eq!(edge.data, copied_edge.data);
+        }
+        assert!(copy.validate_invariants().is_ok());
+    }
+
     #[instantiate_tests(<petgraph::Directed>)]
     mod directed {}
 
@@ -0,0 +1,17 @@
+extern crate log;
+
+#[cfg(not(any(feature = "gdal-io", feature = "pure")))]
+compile_error!("Either the `gdal-io` or the `pure` feature must be enabled.");
+
+pub mod crs;
+pub mod diagnostics;
+pub mod error;
+pub mod geofile;
+pub mod geograph;
+pub mod osm;
+#[cfg(feature = "gdal-io")]
+pub mod provenance;
+#[cfg(feature = "python")]
+mod python;
+pub mod topo;
+pub mod util;
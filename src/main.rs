@@ -1,54 +1,1090 @@
-extern crate log;
-pub mod crs;
-pub mod geofile;
-pub mod geograph;
-pub mod osm;
-pub mod topo;
-use crate::crs::crs_utils::epsg_4326;
-use crate::geofile::feature::Feature;
-use crate::geofile::gdal_geofile::{write_features_to_geofile, GdalDriverType};
-use crate::geograph::geo_feature_graph::GeoFeatureGraph;
-use crate::geograph::utils::build_geograph_from_lines;
-use crate::osm::download::{sync_osm_data_to_file, WgsBoundingBox};
-use crate::topo::topo::{calculate_topo, TopoParams};
-use anyhow::anyhow;
-use clap::Parser;
+use anyhow::{anyhow, Context};
+use clap::{Parser, Subcommand};
+use proj::Transform;
 use rayon::prelude::*;
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, PathBuf};
+use std::time::Instant;
 use std::{fs::read_to_string, path::Path};
+use topo_rust::crs::crs_utils::{
+    epsg_4326, epsg_code_to_authority_string, proj_definition, CrsDefinition, EpsgCode, Projection,
+};
+use topo_rust::crs::Crs;
+use topo_rust::diagnostics::environment::check_environment;
+use topo_rust::diagnostics::validation::{CheckStatus, ValidationReport};
+use topo_rust::diagnostics::warnings::WarningCollector;
+use topo_rust::error::Error;
+use topo_rust::geofile::centerline::replace_polygons_with_centerlines;
+use topo_rust::geofile::feature::Feature;
+use topo_rust::geofile::gdal_geofile::{
+    probe_geofile, read_features_from_geofile_with_options, read_geofile_crs,
+    read_geofile_driver_name, write_features_to_geofile, GdalDriverType, LayerInfo,
+    OnCoordinateOutliers, OnReadError, ReadOptions, WriteOptions,
+};
+use topo_rust::geofile::geojson::{GeoJsonLinesWriter, GeoJsonWriteOptions};
+use topo_rust::geograph::cleanup::deduplicate_parallel_edges;
+use topo_rust::geograph::compare::edge_symmetric_difference;
+use topo_rust::geograph::geo_feature_graph::GeoFeatureGraph;
+use topo_rust::geograph::io::load_from_node_edge_csv;
+use topo_rust::geograph::utils::{
+    build_geograph_from_lines_with_precision, get_utm_zone_for_graph, project_geograph,
+};
+use topo_rust::osm::download::{
+    check_overpass_status, find_cached_osm_data, sync_osm_data_to_file, OsmEndpoint, WgsBoundingBox,
+};
+use topo_rust::osm::nominatim::resolve_place_bbox;
+use topo_rust::provenance::{InputFile, RunInfo};
+use topo_rust::topo::analysis::{
+    best_f1_threshold_point, match_distance_histogram, matched_hole_radius,
+    missing_coverage_clusters, missing_coverage_features, parameter_sweep, score_threshold_sweep,
+    write_match_distance_histogram_to_csv, write_parameter_sweep_points_to_csv,
+    write_threshold_points_to_csv, MissingCoverageCluster, MissingCoverageConfig, MissingScore,
+    ParameterCombination, ParameterSweepConfig, ScoreThresholdSweepConfig,
+    COARSE_MATCH_DISTANCE_BIN_COUNT,
+};
+use topo_rust::topo::approximate::{
+    approximate_scores, subsample_graphs, ApproximateConfig, ApproximateScores,
+    DEFAULT_APPROXIMATE_TILE_SIZE, DEFAULT_BOOTSTRAP_SAMPLES,
+};
+use topo_rust::topo::cancellation::CancellationToken;
+use topo_rust::topo::heatmap::{grid_metrics, HeatmapConfig, DEFAULT_HEATMAP_CELL_SIZE};
+use topo_rust::topo::matching::{CsvCandidateWriter, MatchingMode};
+use topo_rust::topo::report::write_edge_report_gpkg;
+use topo_rust::topo::topo::{
+    calculate_topo, calculate_topo_with_candidate_sink, EvaluationMask, F1ScoreResult, GtIndex,
+    NodeExportSchema, TopoNode, TopoParams, TopoResult, DEFAULT_EVALUATOR_CACHE_CAPACITY,
+};
+use topo_rust::topo::trace::{
+    trace_features, trace_proposal_edge, EdgeSelector, TraceEdgeConfig,
+    DEFAULT_TRACE_CANDIDATE_COUNT,
+};
+use topo_rust::util::progress::{IndicatifProgress, JsonlProgressSink, ProgressSink, TeeProgress};
+use topo_rust::util::telemetry::{log_stage_summary, StageMetrics, StageTimer};
+use topo_rust::{osm, topo};
 
 /// Calculate the TOPO metric over a ground truth and a proposal road map.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
     /// Path to the input config file.
-    #[arg(short, long)]
-    config_filepath: String,
+    #[arg(short, long, required = true)]
+    config_filepath: Option<String>,
+    /// Print the effective configuration, with defaults resolved, and exit.
+    #[arg(long)]
+    print_config: bool,
+    /// Run the evaluation without writing any outputs, e.g. to smoke test a config.
+    #[arg(long)]
+    check: bool,
+    /// Trace the matching of one proposal edge, given by its index or as ATTRIBUTE=VALUE, like `trace_edge` of the
+    /// config.
+    #[arg(long)]
+    trace_edge: Option<EdgeSelector>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that GDAL and PROJ are usable, and print their versions and search paths.
+    Doctor,
+    /// Print the ground truth points nearest to a location, with their distances and hole radii.
+    Query {
+        /// The ground truth geofile.
+        #[arg(long)]
+        gt: PathBuf,
+        /// Longitude of the location in EPSG:4326.
+        #[arg(long, allow_negative_numbers = true)]
+        lon: f64,
+        /// Latitude of the location in EPSG:4326.
+        #[arg(long, allow_negative_numbers = true)]
+        lat: f64,
+        /// Number of ground truth points to print.
+        #[arg(long, default_value_t = 5)]
+        k: usize,
+        /// Distance between the sampled ground truth points, in units of the projected CRS.
+        #[arg(long, default_value_t = 11.0)]
+        resampling_distance: f64,
+        /// Hole radius of the ground truth points, in units of the projected CRS.
+        #[arg(long, default_value_t = 6.0)]
+        hole_radius: f64,
+    },
+    /// Compare the scores of a candidate run to a baseline, and fail if the F1 score regressed by more than
+    /// `max_regression`.
+    CompareScores {
+        /// The `result.json` of the baseline run, or a JSON file with just its scores.
+        #[arg(long)]
+        baseline: PathBuf,
+        /// The `result.json` of the candidate run, or a JSON file with just its scores.
+        #[arg(long)]
+        candidate: PathBuf,
+        /// The largest allowed decrease of the F1 score.
+        #[arg(long, default_value_t = 0.0)]
+        max_regression: f64,
+    },
+    /// Write the edges of each of two geofiles which have no geometrically identical counterpart in the other.
+    DiffGraphs {
+        /// The first geofile.
+        #[arg(long)]
+        a: PathBuf,
+        /// The second geofile, in the same CRS as the first.
+        #[arg(long)]
+        b: PathBuf,
+        /// Maximum difference of corresponding coordinates of matching edges, in CRS units.
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+        /// Directory to write `only_in_a.gpkg` and `only_in_b.gpkg` to. Files without edges are not written.
+        #[arg(long)]
+        output_dir: PathBuf,
+    },
+    /// Print the metadata of the layers of a geofile as JSON, i.e. their feature counts, geometry types, fields, CRSs
+    /// and extents, without reading the features.
+    Inspect {
+        /// The geofile to inspect.
+        #[arg(long)]
+        path: PathBuf,
+    },
+    /// Check a config and its inputs as far as possible without loading the graphs, e.g. before a long run, and
+    /// print a report with the outcome of each check. Fails if any check fails.
+    Validate {
+        /// The config file to check.
+        #[arg(long)]
+        config: PathBuf,
+        /// Also check that the Overpass API is reachable, if OSM data is downloaded.
+        #[arg(long)]
+        allow_network: bool,
+    },
+    /// Write an example config with every entry, commented, and commented-out alternatives of the entries, e.g. the
+    /// other ground truth sources.
+    InitConfig {
+        /// The file to write the example config to.
+        #[arg(long)]
+        output: PathBuf,
+        /// Overwrite the file if it exists.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Source of the proposal or the ground truth graph.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+enum GraphSource {
+    Geofile {
+        filepath: PathBuf,
+    },
+    Osm {
+        bounding_box: WgsBoundingBox,
+        /// The Overpass API endpoint to download the OSM data from.
+        #[serde(default)]
+        endpoint: OsmEndpoint,
+        /// The values of the highway tag to use as ground truth, e.g. "primary". If empty, all highways are used.
+        #[serde(default)]
+        highway_classes: Vec<String>,
+        /// Merge ways which share an endpoint and have identical relevant tags into longer linestrings.
+        #[serde(default)]
+        merge_ways: bool,
+        /// Store the downloaded OSM data gzip compressed.
+        #[serde(default)]
+        compress_cache: bool,
+    },
+    /// Like `Osm`, for the bounding box of a named place, e.g. "Shibuya, Tokyo", looked up with Nominatim.
+    OsmPlace {
+        query: String,
+        /// The bounding box of the place. Looked up if not set, and recorded in the effective config, so that the
+        /// effective config reproduces the run.
+        bounding_box: Option<WgsBoundingBox>,
+        #[serde(default)]
+        endpoint: OsmEndpoint,
+        #[serde(default)]
+        highway_classes: Vec<String>,
+        #[serde(default)]
+        merge_ways: bool,
+        #[serde(default)]
+        compress_cache: bool,
+    },
+    /// The roads of an OSM XML file, optionally gzip or bzip2 compressed, e.g. a Geofabrik extract of a past year.
+    OsmFile {
+        filepath: PathBuf,
+        /// The values of the highway tag to use. If empty, all highways are used.
+        #[serde(default)]
+        highway_classes: Vec<String>,
+        #[serde(default)]
+        merge_ways: bool,
+    },
+    /// Node and edge CSV files, e.g. from SpaceNet, see `load_from_node_edge_csv`. Their CRS is the CRS override of
+    /// the graph, or EPSG:4326 if not set.
+    NodeEdgeCsv {
+        nodes_path: PathBuf,
+        edges_path: PathBuf,
+    },
+}
+
+/// A ground truth of `GroundTruthConfig::Named`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+struct NamedGraphSource {
+    /// Keys the scores against this ground truth in the result file, and is appended to the names of its outputs.
+    name: String,
+    source: GraphSource,
+}
+
+/// Name of the union of the named ground truths, see `Config::ground_truth_union_snap_tolerance`.
+const UNION_GROUND_TRUTH_NAME: &str = "union";
+
+/// The ground truth of a run, either a single source, or a list of named sources, e.g. of different map providers,
+/// which the proposal is evaluated against separately.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
 enum GroundTruthConfig {
-    Geofile { filepath: PathBuf },
-    Osm { bounding_box: WgsBoundingBox },
+    Single(GraphSource),
+    Named(Vec<NamedGraphSource>),
+}
+
+// Deserialized via a YAML value, as untagged enums do not support the YAML tags of `GraphSource`.
+impl<'de> Deserialize<'de> for GroundTruthConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+        let ground_truth = if value.is_sequence() {
+            serde_yaml::from_value(value).map(GroundTruthConfig::Named)
+        } else {
+            serde_yaml::from_value(value).map(GroundTruthConfig::Single)
+        };
+        ground_truth.map_err(serde::de::Error::custom)
+    }
+}
+
+impl GroundTruthConfig {
+    /// The sources of the ground truths, with their names if they are named.
+    fn sources(&self) -> Vec<(Option<&str>, &GraphSource)> {
+        match self {
+            GroundTruthConfig::Single(source) => vec![(None, source)],
+            GroundTruthConfig::Named(sources) => sources
+                .iter()
+                .map(|named| (Some(named.name.as_str()), &named.source))
+                .collect(),
+        }
+    }
+
+    fn sources_mut(&mut self) -> Vec<&mut GraphSource> {
+        match self {
+            GroundTruthConfig::Single(source) => vec![source],
+            GroundTruthConfig::Named(sources) => {
+                sources.iter_mut().map(|named| &mut named.source).collect()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+struct GeoJsonOutputConfig {
+    /// Write the bounding box of each feature, in addition to the one of the feature collection.
+    #[serde(default)]
+    feature_bbox: bool,
+    /// Declare the CRS in the deprecated `crs` member if it is not EPSG:4326, even if it is projected.
+    #[serde(default)]
+    crs_member: bool,
+    /// Write `ground_truth.geojson.gz`, gzip compressed, instead of `ground_truth.geojson`.
+    #[serde(default)]
+    compress: bool,
+}
+
+/// Which of the sampled TOPO nodes are written to the data dir.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum NodeDetail {
+    #[default]
+    All,
+    /// Only the unmatched nodes, to files with an `_unmatched` suffix.
+    UnmatchedOnly,
+    None,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+struct OutputsConfig {
+    #[serde(default)]
+    node_detail: NodeDetail,
+    /// GDAL dataset creation options of the output GeoPackages, as "KEY=VALUE" strings.
+    #[serde(default)]
+    dataset_creation_options: Vec<String>,
+    /// GDAL layer creation options of the output GeoPackages, as "KEY=VALUE" strings.
+    #[serde(default)]
+    layer_creation_options: Vec<String>,
+    /// If set, the ground truth nodes within hole radius of each proposal node are written to this CSV file, before
+    /// any nodes are matched.
+    dump_candidates: Option<PathBuf>,
+    /// Names and types of the attributes of the node outputs.
+    #[serde(default)]
+    node_schema: NodeExportSchema,
+    /// Write the edges of both graphs with the fraction of their matched nodes to `edge_report.gpkg`, see
+    /// `write_edge_report_gpkg`.
+    #[serde(default)]
+    edge_report: bool,
+    /// Write outputs for calibrating the TOPO parameters: the histogram of the match distances of the proposal nodes
+    /// to `match_distance_histogram.csv`, see `match_distance_histogram`.
+    #[serde(default)]
+    debug_outputs: bool,
+    /// The bin width of `match_distance_histogram.csv`, in units of the projected CRS. Defaults to
+    /// `DEFAULT_MATCH_DISTANCE_BIN_WIDTH`.
+    match_distance_bin_width: Option<f64>,
+    /// If set, the progress of the TOPO computation is written to this file as JSON lines, next to the progress bars
+    /// on the terminal, see `JsonlProgressSink`.
+    progress_file: Option<PathBuf>,
+}
+
+/// Default of `OutputsConfig::match_distance_bin_width`.
+const DEFAULT_MATCH_DISTANCE_BIN_WIDTH: f64 = 0.25;
+
+/// Conversions of the ground truth features before the ground truth graph is built from them.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+struct GroundTruthPreprocessingConfig {
+    /// Replace Polygon and MultiPolygon features of a geofile ground truth, e.g. road or lane polygons, with their
+    /// approximate centerlines.
+    #[serde(default)]
+    polygons_to_centerlines: bool,
+}
+
+/// A graph given as a pair of node and edge CSV files, see `GraphSource::NodeEdgeCsv`.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct NodeEdgeCsvConfig {
+    nodes_path: PathBuf,
+    edges_path: PathBuf,
+}
+
+fn default_detect_coordinate_outliers() -> bool {
+    true
+}
+
+fn default_min_bounding_box_overlap() -> f64 {
+    topo::preprocessing::DEFAULT_MIN_BOUNDING_BOX_OVERLAP
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
 struct Config {
-    proposal_geofile_path: PathBuf,
+    /// The proposal. Exactly one of this, `proposal_geofile_path` and `proposal_node_edge_csv` must be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proposal: Option<GraphSource>,
+    /// Deprecated, the proposal geofile, like `proposal: !Geofile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proposal_geofile_path: Option<PathBuf>,
+    /// Deprecated, the proposal as node and edge CSV files, like `proposal: !NodeEdgeCsv`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proposal_node_edge_csv: Option<NodeEdgeCsvConfig>,
+    /// CRS to use for the proposal instead of the one declared by its geofile, if any.
+    proposal_crs_override: Option<CrsDefinition>,
     ground_truth: GroundTruthConfig,
+    /// If set and the ground truths are named, the proposal is also evaluated against their union, named `union`.
+    /// Nodes of the ground truths within this distance of each other, in units of the projected CRS, are unified, see
+    /// `GeoGraph::merge`.
+    ground_truth_union_snap_tolerance: Option<f64>,
+    /// CRS to use for a geofile ground truth instead of the one declared by its geofile, if any.
+    ground_truth_crs_override: Option<CrsDefinition>,
+    #[serde(default)]
+    ground_truth_preprocessing: GroundTruthPreprocessingConfig,
+    /// If set, the preprocessed ground truth graph is cached in this file and reused by subsequent runs with the
+    /// same ground truth config.
+    ground_truth_cache: Option<PathBuf>,
+    /// Whether to orient all edge geometries away from their start node before sampling. Defaults to on for
+    /// undirected graphs.
+    normalize_edge_orientation: Option<bool>,
+    /// If set, parallel proposal edges whose geometries are at most this far apart are considered duplicate
+    /// detections and only the longest one is kept. Like the other preprocessing distances below, it is in the units
+    /// of `topo_params`, i.e. in meters unless `topo_params.units` is `CrsUnits`.
+    proposal_duplicate_edge_tolerance: Option<f64>,
+    /// If set, proposal edges longer than this are split into chained parts of at most this length, see
+    /// `topo::preprocessing::split_long_edges`.
+    split_long_edges: Option<f64>,
+    /// If set, the edges of each graph which pass within this distance of a node of the other graph are split there,
+    /// e.g. at T-junctions which only one of the graphs models with a node.
+    split_edges_at_near_nodes: Option<f64>,
+    /// If set, the dangling endpoints of proposal edges are extended to the nearest ground truth node within this
+    /// distance, e.g. for proposals which stop roads short of junctions.
+    snap_proposal_endpoints_to_gt: Option<f64>,
+    /// Whether to fail on features of the input geofiles whose geometry or attributes cannot be decoded, or to skip
+    /// them with a warning.
+    #[serde(default)]
+    on_read_error: OnReadError,
+    /// The projected CRS to project the graphs to if the ground truth is in a geographic CRS. The local projections
+    /// are centered on the ground truth, which avoids the scale distortion of UTM for areas near or across UTM zone
+    /// boundaries.
+    #[serde(default)]
+    projection: Projection,
+    /// Fail instead of warning if the coordinates of an input do not look plausible for its CRS.
+    #[serde(default)]
+    strict_crs_checks: bool,
+    /// Minimum fraction of the smaller of the bounding boxes of the ground truth and the proposal which must be
+    /// covered by the other one, below which they likely cover different areas.
+    #[serde(default = "default_min_bounding_box_overlap")]
+    min_bounding_box_overlap: f64,
+    /// Fail instead of warning if the bounding boxes of the ground truth and the proposal overlap by less than
+    /// `min_bounding_box_overlap`.
+    #[serde(default)]
+    strict_overlap_check: bool,
+    /// Only warn instead of failing if the coordinates of an input geofile exceed the longitude/latitude range of its
+    /// geographic CRS.
+    #[serde(default)]
+    lenient_coordinate_range: bool,
+    /// Exclude line features of the input geofiles whose coordinates are outliers by orders of magnitude, e.g.
+    /// features appended in another CRS than the declared one, with a warning listing their FIDs. Fails instead if
+    /// `strict_crs_checks` is set.
+    #[serde(default = "default_detect_coordinate_outliers")]
+    detect_coordinate_outliers: bool,
+    /// If set, the coordinates of the line inputs are quantized to a grid with this spacing, in units of their CRS,
+    /// before their endpoints are joined into nodes, e.g. 1e-8 for EPSG:4326. Endpoints which differ only by noise
+    /// below the spacing then share a node.
+    coordinate_precision: Option<f64>,
+    /// Number of threads of the parallel computations. Defaults to the number of logical CPUs. The scores do not
+    /// depend on it.
+    threads: Option<usize>,
+    /// Parameters of the TOPO computation, defaults for omitted ones, see `TopoParams`.
+    #[serde(default)]
     topo_params: TopoParams,
+    /// Directory of the outputs of the run, and of the downloaded OSM data.
     data_dir: PathBuf,
+    /// If set, the outputs of the run are written to `data_dir/runs/<run_name>` instead of the data dir.
+    run_name: Option<String>,
+    /// Write the outputs of the run to `data_dir/runs/<timestamp>` if `run_name` is not set.
+    #[serde(default)]
+    create_run_subdir: bool,
+    /// Allow writing the outputs of a run to a run directory which is not empty.
+    #[serde(default)]
+    overwrite: bool,
+    /// Options of the ground truth GeoJSON written to the data dir.
+    #[serde(default)]
+    ground_truth_geojson: GeoJsonOutputConfig,
+    /// Options of the node outputs written to the data dir.
+    #[serde(default)]
+    outputs: OutputsConfig,
+    /// If set, a grid of local recall and precision values is written to the data dir.
+    heatmap: Option<HeatmapConfig>,
+    /// If set, the unmatched ground truth nodes are clustered into areas of missing coverage, whose convex hulls are
+    /// written to the data dir, see `missing_coverage_clusters`.
+    missing_coverage: Option<MissingCoverageConfig>,
+    /// If set, TOPO is additionally calculated for thresholds of a proposal edge score, and the resulting
+    /// precision-recall curve is written to the data dir.
+    score_threshold_sweep: Option<ScoreThresholdSweepConfig>,
+    /// If set, TOPO is additionally calculated for combinations of the resampling distance and the hole radius, and
+    /// the scores of each combination are written to the data dir, see `parameter_sweep`.
+    parameter_sweep: Option<ParameterSweepConfig>,
+    /// If set, the matching of the nodes of this proposal edge is traced after the evaluation, and the trace is
+    /// written to the data dir, see `trace_proposal_edge`.
+    trace_edge: Option<TraceEdgeConfig>,
+    /// If set, only a random fraction of tiles of both graphs is evaluated, and the scores in the result file are
+    /// estimates, see `subsample_graphs`.
+    approximate: Option<ApproximateConfig>,
 }
 
-fn get_ground_truth_ways_from_osm(
+/// Write the TOPO nodes selected by `outputs.node_detail` to a GeoPackage named after `name` in `output_dir`, with
+/// the run info.
+fn write_node_outputs(
+    nodes: &[TopoNode],
+    name: &str,
+    crs: &Crs,
+    config: &Config,
+    output_dir: &Path,
+    write_options: &WriteOptions,
+    run_info: &RunInfo,
+) -> anyhow::Result<()> {
+    let (features, filename): (Vec<Feature>, String) = match config.outputs.node_detail {
+        NodeDetail::All => (
+            nodes
+                .par_iter()
+                .map(|node| node.to_feature(&config.outputs.node_schema))
+                .collect(),
+            format!("{}.gpkg", name),
+        ),
+        NodeDetail::UnmatchedOnly => (
+            nodes
+                .par_iter()
+                .filter(|node| !node.matched())
+                .map(|node| node.to_feature(&config.outputs.node_schema))
+                .collect(),
+            format!("{}_unmatched.gpkg", name),
+        ),
+        NodeDetail::None => return Ok(()),
+    };
+    let filepath = output_dir.join(filename);
+    write_features_to_geofile(
+        &features,
+        &filepath,
+        Some(crs),
+        GdalDriverType::GeoPackage.name(),
+        write_options,
+    )?;
+    run_info.append_to_geofile(&filepath)?;
+    Ok(())
+}
+
+fn get_ways_from_osm(
     bounding_box: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    merge_ways: bool,
+    compress_cache: bool,
     data_dir: &PathBuf,
+    timer: &mut StageTimer,
+    warnings: &WarningCollector,
 ) -> anyhow::Result<Vec<geo::LineString>> {
     log::info!("Syncing OSM data for bounding box {:?}", bounding_box);
-    let osm_filepath = sync_osm_data_to_file(&bounding_box, &data_dir)?;
+    timer.start_stage("download_osm");
+    let osm_filepath = sync_osm_data_to_file(
+        bounding_box,
+        endpoint,
+        highway_classes,
+        data_dir,
+        compress_cache,
+    )?;
     log::info!("Reading OSM ways");
-    osm::conversion::read_osm_roads_from_file(&osm_filepath)
+    timer.start_stage("parse_osm");
+    let ways = osm::conversion::read_osm_roads_from_file(
+        &osm_filepath,
+        merge_ways,
+        highway_classes,
+        Some(warnings),
+    )?;
+    if ways.is_empty() {
+        return Err(anyhow!(
+            "No ways were read from the OSM data in {:?}, try a larger bounding box or fewer restrictions in \
+            highway_classes",
+            osm_filepath
+        ));
+    }
+    Ok(ways)
+}
+
+impl Config {
+    /// The source of the proposal, from `proposal` or from one of the deprecated proposal fields.
+    fn proposal_source(&self) -> anyhow::Result<GraphSource> {
+        match (
+            &self.proposal,
+            &self.proposal_geofile_path,
+            &self.proposal_node_edge_csv,
+        ) {
+            (Some(source), None, None) => Ok(source.clone()),
+            (None, Some(filepath), None) => Ok(GraphSource::Geofile {
+                filepath: filepath.clone(),
+            }),
+            (None, None, Some(csv_config)) => Ok(GraphSource::NodeEdgeCsv {
+                nodes_path: csv_config.nodes_path.clone(),
+                edges_path: csv_config.edges_path.clone(),
+            }),
+            _ => Err(anyhow!(
+                "Exactly one of proposal, proposal_geofile_path and proposal_node_edge_csv must be set"
+            )),
+        }
+    }
+}
+
+/// Replace the deprecated proposal fields of `config` with the equivalent `proposal`, so that the effective config
+/// uses the current format.
+fn migrate_deprecated_proposal_fields(config: &mut Config) -> anyhow::Result<()> {
+    config.proposal = Some(config.proposal_source()?);
+    config.proposal_geofile_path = None;
+    config.proposal_node_edge_csv = None;
+    Ok(())
+}
+
+/// Look up the bounding box of an `OsmPlace` source, unless it is set, and set it in the source.
+fn resolve_place(source: &mut GraphSource, data_dir: &Path) -> anyhow::Result<()> {
+    if let GraphSource::OsmPlace {
+        query,
+        bounding_box: bounding_box @ None,
+        ..
+    } = source
+    {
+        let resolved_bounding_box = resolve_place_bbox(query, data_dir)?;
+        log::info!(
+            "Resolved place '{}' to bounding box {:?}",
+            query,
+            resolved_bounding_box
+        );
+        *bounding_box = Some(resolved_bounding_box);
+    }
+    Ok(())
+}
+
+fn read_options(config: &Config, warnings: &WarningCollector) -> ReadOptions {
+    ReadOptions::new()
+        .on_error(config.on_read_error)
+        .lenient_coordinate_range(config.lenient_coordinate_range)
+        .coordinate_outliers(
+            match (config.detect_coordinate_outliers, config.strict_crs_checks) {
+                (false, _) => OnCoordinateOutliers::Keep,
+                (true, false) => OnCoordinateOutliers::Exclude,
+                (true, true) => OnCoordinateOutliers::Fail,
+            },
+        )
+        .coordinate_precision(config.coordinate_precision)
+        .warnings(warnings.clone())
+}
+
+/// Load a graph from its source. OSM data is downloaded to the data dir of `config`.
+///
+/// # Arguments
+/// * crs_override - CRS to use instead of the one declared by a geofile, and the CRS of node and edge CSV files.
+fn load_graph(
+    source: &GraphSource,
+    crs_override: &Option<CrsDefinition>,
+    config: &Config,
+    timer: &mut StageTimer,
+    warnings: &WarningCollector,
+) -> anyhow::Result<GeoFeatureGraph<petgraph::Undirected>> {
+    let crs_override = crs_override_to_crs(crs_override)?;
+    Ok(match source {
+        GraphSource::Geofile { filepath } => {
+            GeoFeatureGraph::load_from_geofile_with_options(
+                filepath,
+                crs_override.as_ref(),
+                &read_options(config, warnings),
+            )?
+            .0
+        }
+        GraphSource::Osm {
+            bounding_box,
+            endpoint,
+            highway_classes,
+            merge_ways,
+            compress_cache,
+        }
+        | GraphSource::OsmPlace {
+            bounding_box: Some(bounding_box),
+            endpoint,
+            highway_classes,
+            merge_ways,
+            compress_cache,
+            ..
+        } => {
+            let ways = get_ways_from_osm(
+                bounding_box,
+                *endpoint,
+                highway_classes,
+                *merge_ways,
+                *compress_cache,
+                &config.data_dir,
+                timer,
+                warnings,
+            )?;
+            timer.start_stage("build_osm_graph");
+            build_geograph_from_lines_with_precision(
+                ways,
+                epsg_4326(),
+                config.coordinate_precision,
+            )?
+        }
+        GraphSource::OsmPlace {
+            query,
+            bounding_box: None,
+            ..
+        } => {
+            return Err(anyhow!(
+                "The bounding box of the place '{}' was not looked up",
+                query
+            ))
+        }
+        GraphSource::OsmFile {
+            filepath,
+            highway_classes,
+            merge_ways,
+        } => {
+            log::info!("Reading OSM ways from {:?}", filepath);
+            timer.start_stage("parse_osm");
+            let ways = osm::conversion::read_osm_roads_from_file(
+                filepath,
+                *merge_ways,
+                highway_classes,
+                Some(warnings),
+            )?;
+            timer.start_stage("build_osm_graph");
+            build_geograph_from_lines_with_precision(
+                ways,
+                epsg_4326(),
+                config.coordinate_precision,
+            )?
+        }
+        GraphSource::NodeEdgeCsv {
+            nodes_path,
+            edges_path,
+        } => load_from_node_edge_csv(
+            nodes_path,
+            edges_path,
+            crs_override.unwrap_or_else(epsg_4326),
+        )?,
+    })
+}
+
+fn load_ground_truth_graph(
+    source: &GraphSource,
+    config: &Config,
+    timer: &mut StageTimer,
+    warnings: &WarningCollector,
+) -> anyhow::Result<GeoFeatureGraph<petgraph::Undirected>> {
+    timer.start_stage("load_ground_truth");
+    match source {
+        GraphSource::Geofile { filepath }
+            if config.ground_truth_preprocessing.polygons_to_centerlines =>
+        {
+            let (features, declared_crs, _) =
+                read_features_from_geofile_with_options(filepath, &read_options(config, warnings))?;
+            let crs =
+                crs_override_to_crs(&config.ground_truth_crs_override)?.unwrap_or(declared_crs);
+            timer.start_stage("extract_ground_truth_centerlines");
+            Ok(GeoFeatureGraph::from_features_in_crs(
+                replace_polygons_with_centerlines(features),
+                crs,
+                config.coordinate_precision,
+            )?)
+        }
+        source => load_graph(
+            source,
+            &config.ground_truth_crs_override,
+            config,
+            timer,
+            warnings,
+        ),
+    }
+}
+
+/// Name of the input of a graph for messages.
+///
+/// # Arguments
+/// * graph_name - Name of the graph, e.g. "ground truth".
+fn graph_source_name(source: &GraphSource, graph_name: &str) -> String {
+    match source {
+        GraphSource::Geofile { filepath } | GraphSource::OsmFile { filepath, .. } => {
+            format!("{:?}", filepath)
+        }
+        GraphSource::Osm { .. } => format!("the OSM {}", graph_name),
+        GraphSource::OsmPlace { query, .. } => format!("the OSM {} of '{}'", graph_name, query),
+        GraphSource::NodeEdgeCsv { edges_path, .. } => format!("{:?}", edges_path),
+    }
+}
+
+/// The input files of the proposal and the ground truths, with their hashes, for the run info of the outputs. OSM
+/// data which is downloaded by the run is not listed.
+fn run_input_files(
+    proposal_source: &GraphSource,
+    ground_truth_sources: &[(Option<&str>, &GraphSource)],
+) -> anyhow::Result<Vec<InputFile>> {
+    let ground_truth_roles = ground_truth_sources.iter().map(|(name, source)| {
+        let role = match name {
+            Some(name) => format!("ground truth '{}'", name),
+            None => "ground truth".to_string(),
+        };
+        (role, *source)
+    });
+    let mut input_files = Vec::new();
+    for (role, source) in
+        std::iter::once(("proposal".to_string(), proposal_source)).chain(ground_truth_roles)
+    {
+        let filepaths = match source {
+            GraphSource::Geofile { filepath } | GraphSource::OsmFile { filepath, .. } => {
+                vec![filepath]
+            }
+            GraphSource::NodeEdgeCsv {
+                nodes_path,
+                edges_path,
+            } => vec![nodes_path, edges_path],
+            GraphSource::Osm { .. } | GraphSource::OsmPlace { .. } => vec![],
+        };
+        for filepath in filepaths {
+            input_files.push(InputFile::new(&role, filepath)?);
+        }
+    }
+    Ok(input_files)
+}
+
+/// Hash of the config entries which determine the preprocessed ground truth graph. Stored alongside the ground
+/// truth cache to invalidate it when the ground truth config changes.
+fn ground_truth_cache_key(config: &Config) -> anyhow::Result<String> {
+    let ground_truth_config = serde_yaml::to_string(&(
+        &config.ground_truth,
+        &config.ground_truth_crs_override,
+        &config.ground_truth_preprocessing,
+        &config.projection,
+        &config.on_read_error,
+        &config.coordinate_precision,
+        &config.detect_coordinate_outliers,
+    ))?;
+    let mut hasher = DefaultHasher::new();
+    ground_truth_config.hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+fn ground_truth_cache_key_filepath(cache_filepath: &Path) -> PathBuf {
+    let mut key_filepath = cache_filepath.as_os_str().to_owned();
+    key_filepath.push(".key");
+    PathBuf::from(key_filepath)
+}
+
+/// Read the cached ground truth graph, if the cache exists and was written with the same cache key.
+fn read_ground_truth_cache(
+    cache_filepath: &Path,
+    cache_key: &str,
+) -> anyhow::Result<Option<GeoFeatureGraph<petgraph::Undirected>>> {
+    let key_filepath = ground_truth_cache_key_filepath(cache_filepath);
+    if !cache_filepath.exists() || !key_filepath.exists() {
+        return Ok(None);
+    }
+    if read_to_string(&key_filepath)?.trim() != cache_key {
+        log::info!(
+            "Ground truth cache {:?} is outdated, ignoring it",
+            cache_filepath
+        );
+        return Ok(None);
+    }
+    log::info!("Reading cached ground truth from {:?}", cache_filepath);
+    Ok(Some(GeoFeatureGraph::deserialize_from(cache_filepath)?))
+}
+
+fn write_ground_truth_cache(
+    ground_truth_graph: &GeoFeatureGraph<petgraph::Undirected>,
+    cache_filepath: &Path,
+    cache_key: &str,
+) -> anyhow::Result<()> {
+    log::info!("Caching preprocessed ground truth to {:?}", cache_filepath);
+    ground_truth_graph.serialize_to(cache_filepath)?;
+    std::fs::write(ground_truth_cache_key_filepath(cache_filepath), cache_key)?;
+    Ok(())
+}
+
+fn crs_override_to_crs(crs_override: &Option<CrsDefinition>) -> anyhow::Result<Option<Crs>> {
+    crs_override
+        .as_ref()
+        .map(|crs_override| crs_override.to_crs())
+        .transpose()
+        .map_err(anyhow::Error::from)
+}
+
+/// Write the diagnostics of a cancelled TOPO calculation to a JSON file in the output dir.
+fn write_partial_result(
+    output_dir: &Path,
+    cancelled_stage: &str,
+    completed_stages: &[StageMetrics],
+) -> anyhow::Result<()> {
+    let partial_result_filepath = output_dir.join("partial_result.json");
+    log::info!(
+        "Writing partial result of cancelled run to {:?}",
+        &partial_result_filepath
+    );
+    let partial_result = serde_json::json!({
+        "cancelled_stage": cancelled_stage,
+        "completed_stages": completed_stages,
+    });
+    std::fs::write(
+        partial_result_filepath,
+        serde_json::to_string_pretty(&partial_result)?,
+    )?;
+    Ok(())
+}
+
+/// Print a table of the baseline and candidate scores, and fail if the candidate F1 score regressed by more than
+/// `max_regression`.
+fn compare_scores(
+    baseline_filepath: &Path,
+    candidate_filepath: &Path,
+    max_regression: f64,
+) -> anyhow::Result<()> {
+    let read_scores = |filepath: &Path| -> anyhow::Result<F1ScoreResult> {
+        F1ScoreResult::from_json(&read_to_string(filepath)?)
+            .with_context(|| format!("Reading scores from {:?}", filepath))
+    };
+    let baseline = read_scores(baseline_filepath)?;
+    let candidate = read_scores(candidate_filepath)?;
+    print!("{}", format_score_comparison(&baseline, &candidate));
+    if !candidate.meets_threshold(&baseline, max_regression) {
+        return Err(anyhow!(
+            "F1 score regressed from {:.4} to {:.4}, by more than {}",
+            baseline.f1_score(),
+            candidate.f1_score(),
+            max_regression
+        ));
+    }
+    Ok(())
+}
+
+fn format_score_comparison(baseline: &F1ScoreResult, candidate: &F1ScoreResult) -> String {
+    let delta = candidate.diff(baseline);
+    let mut table = format!(
+        "{:<10} {:>10} {:>10} {:>10}\n",
+        "", "baseline", "candidate", "change"
+    );
+    for (name, baseline, candidate, change) in [
+        (
+            "precision",
+            baseline.precision(),
+            candidate.precision(),
+            delta.precision,
+        ),
+        (
+            "recall",
+            baseline.recall(),
+            candidate.recall(),
+            delta.recall,
+        ),
+        (
+            "f1_score",
+            baseline.f1_score(),
+            candidate.f1_score(),
+            delta.f1_score,
+        ),
+    ] {
+        table.push_str(&format!(
+            "{:<10} {:>10.4} {:>10.4} {:>+10.4}\n",
+            name, baseline, candidate, change
+        ));
+    }
+    table
+}
+
+/// Write the edges of each geofile without a counterpart in the other to the output dir.
+fn diff_graphs(
+    a_filepath: &PathBuf,
+    b_filepath: &PathBuf,
+    tolerance: f64,
+    output_dir: &Path,
+) -> anyhow::Result<()> {
+    let a: GeoFeatureGraph<petgraph::Undirected> =
+        GeoFeatureGraph::load_from_geofile(a_filepath, None)?;
+    let b: GeoFeatureGraph<petgraph::Undirected> =
+        GeoFeatureGraph::load_from_geofile(b_filepath, None)?;
+    if a.crs != b.crs {
+        return Err(anyhow!(
+            "{:?} and {:?} have different CRSs, '{}' and '{}'",
+            a_filepath,
+            b_filepath,
+            a.crs.name()?,
+            b.crs.name()?
+        ));
+    }
+    let (only_in_a, only_in_b) = edge_symmetric_difference(&a, &b, tolerance);
+    log::info!(
+        "{} of {} edges are only in {:?}, {} of {} edges are only in {:?}",
+        only_in_a.len(),
+        a.edge_count(),
+        a_filepath,
+        only_in_b.len(),
+        b.edge_count(),
+        b_filepath
+    );
+    std::fs::create_dir_all(output_dir)?;
+    for (features, filename) in [(only_in_a, "only_in_a.gpkg"), (only_in_b, "only_in_b.gpkg")] {
+        write_features_to_geofile(
+            &features,
+            &output_dir.join(filename),
+            Some(&a.crs),
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Describe the `k` ground truth points nearest to a location in EPSG:4326, sampled as for TOPO. A ground truth in a
+/// geographic CRS is projected to its UTM zone first.
+fn query_ground_truth(
+    ground_truth_filepath: &PathBuf,
+    lon: f64,
+    lat: f64,
+    k: usize,
+    params: &TopoParams,
+) -> anyhow::Result<String> {
+    let mut ground_truth_graph: GeoFeatureGraph<petgraph::Undirected> =
+        GeoFeatureGraph::load_from_geofile(ground_truth_filepath, None)?;
+    if ground_truth_graph.crs.is_geographic() {
+        let utm_zone = get_utm_zone_for_graph(&ground_truth_graph)?;
+        project_geograph(&mut ground_truth_graph, &utm_zone)?;
+    }
+    let crs_authority_string =
+        epsg_code_to_authority_string(ground_truth_graph.crs.auth_code()? as EpsgCode);
+    let projection = proj::Proj::new_known_crs(
+        &epsg_code_to_authority_string(4326),
+        &crs_authority_string,
+        None,
+    )?;
+    let mut coord = geo::Coord { x: lon, y: lat };
+    coord.transform(&projection)?;
+
+    let gt_index = GtIndex::from_graph(&ground_truth_graph, params)?;
+    let mut description = format!(
+        "Ground truth points nearest to ({:.3}, {:.3}) in {}:\n",
+        coord.x, coord.y, crs_authority_string
+    );
+    for (rank, (distance, node)) in gt_index.nearest(coord, k)?.into_iter().enumerate() {
+        let hole_radius = node.hole_radius().unwrap_or(params.hole_radius);
+        description.push_str(&format!(
+            "{}. node {} at ({:.3}, {:.3}), distance {:.3}, hole radius {:.3}, {}\n",
+            rank + 1,
+            node.id(),
+            node.coord().x,
+            node.coord().y,
+            distance,
+            hole_radius,
+            if distance <= hole_radius {
+                "within hole radius"
+            } else {
+                "outside hole radius"
+            }
+        ));
+    }
+    Ok(description)
+}
+
+/// Write the scores and the metrics of the stages of a completed run to a JSON file in the output dir.
+///
+/// The scores against a single ground truth are written at the top level, the scores against named ground truths in
+/// the `ground_truths` object, keyed by their names.
+///
+/// # Arguments
+/// * outcomes - The outcomes of the evaluations against each ground truth, with the names of named ground truths.
+/// * projected_crs - The definition of the CRS the graphs were evaluated in, see `proj_definition`.
+fn write_result(
+    output_dir: &Path,
+    outcomes: &[(Option<&str>, EvaluationOutcome)],
+    stage_metrics: &[StageMetrics],
+    projected_crs: &str,
+    run_info: &RunInfo,
+    warnings: &WarningCollector,
+) -> anyhow::Result<()> {
+    let scores = |outcome: &EvaluationOutcome| {
+        let topo_result = &outcome.topo_result;
+        let match_distance_histogram = matched_hole_radius(topo_result).map(|hole_radius| {
+            let bin_width = hole_radius / COARSE_MATCH_DISTANCE_BIN_COUNT as f64;
+            serde_json::json!({
+                "bin_width": bin_width,
+                "counts": match_distance_histogram(topo_result, bin_width)
+                    .into_iter()
+                    .map(|(_, count)| count)
+                    .collect::<Vec<_>>(),
+            })
+        });
+        serde_json::json!({
+            "f1_score_result": topo_result.f1_score_result,
+            "unmasked_f1_score_result": topo_result.unmasked_f1_score_result,
+            "class_breakdown": topo_result.class_breakdown,
+            "match_distance_histogram": match_distance_histogram,
+            "phase_offset_scores": topo_result.phase_offset_scores,
+            "missing_coverage": outcome.missing_coverage,
+            "approximate": outcome.approximate,
+        })
+    };
+    let mut result = match outcomes {
+        [(None, outcome)] => scores(outcome),
+        _ => serde_json::json!({
+            "ground_truths": outcomes
+                .iter()
+                .map(|(name, outcome)| (name.unwrap_or_default().to_string(), scores(outcome)))
+                .collect::<serde_json::Map<_, _>>(),
+        }),
+    };
+    result["projected_crs"] = serde_json::json!(projected_crs);
+    result["stages"] = serde_json::to_value(stage_metrics)?;
+    result["run_info"] = run_info.to_json();
+    result["warnings"] = serde_json::to_value(warnings.summary())?;
+    std::fs::write(
+        output_dir.join("result.json"),
+        serde_json::to_string_pretty(&result)?,
+    )?;
+    Ok(())
 }
 
 fn try_main() -> anyhow::Result<()> {
@@ -57,72 +1093,1876 @@ fn try_main() -> anyhow::Result<()> {
     }
 
     let args = Args::try_parse()?;
-    if !Path::new(&args.config_filepath).exists() {
-        return Err(anyhow!("Config file {} not found", &args.config_filepath));
+    match args.command {
+        Some(Command::Doctor) => {
+            println!("{}", check_environment()?);
+            return Ok(());
+        }
+        Some(Command::Query {
+            gt,
+            lon,
+            lat,
+            k,
+            resampling_distance,
+            hole_radius,
+        }) => {
+            let params = TopoParams::new_in_crs_units(resampling_distance, hole_radius);
+            print!("{}", query_ground_truth(&gt, lon, lat, k, &params)?);
+            return Ok(());
+        }
+        Some(Command::CompareScores {
+            baseline,
+            candidate,
+            max_regression,
+        }) => {
+            return compare_scores(&baseline, &candidate, max_regression);
+        }
+        Some(Command::DiffGraphs {
+            a,
+            b,
+            tolerance,
+            output_dir,
+        }) => {
+            diff_graphs(&a, &b, tolerance, &output_dir)?;
+            return Ok(());
+        }
+        Some(Command::Inspect { path }) => {
+            println!("{}", serde_json::to_string_pretty(&probe_geofile(&path)?)?);
+            return Ok(());
+        }
+        Some(Command::Validate {
+            config,
+            allow_network,
+        }) => {
+            let report = validate_config_file(&config, allow_network);
+            print!("{}", report);
+            let failed_count = report.count(CheckStatus::Fail);
+            if failed_count > 0 {
+                return Err(anyhow!(
+                    "{} of {} checks failed",
+                    failed_count,
+                    report.items.len()
+                ));
+            }
+            return Ok(());
+        }
+        Some(Command::InitConfig { output, force }) => {
+            write_example_config(&output, force)?;
+            println!("Wrote an example config to {:?}", output);
+            return Ok(());
+        }
+        None => {}
+    }
+    let config_filepath = args
+        .config_filepath
+        .ok_or_else(|| anyhow!("No config file given"))?;
+    let mut config = read_config(Path::new(&config_filepath))?;
+    if let Some(edge) = args.trace_edge {
+        config.trace_edge = Some(TraceEdgeConfig {
+            edge,
+            nearest_candidates: config
+                .trace_edge
+                .as_ref()
+                .map_or(DEFAULT_TRACE_CANDIDATE_COUNT, |trace_config| {
+                    trace_config.nearest_candidates
+                }),
+        });
+    }
+    if args.print_config {
+        print!("{}", serde_yaml::to_string(&config)?);
+        return Ok(());
+    }
+
+    for source in config.ground_truth.sources_mut() {
+        resolve_place(source, &config.data_dir)?;
+    }
+    if let Some(proposal) = &mut config.proposal {
+        resolve_place(proposal, &config.data_dir)?;
+    }
+
+    let environment_report = check_environment()?;
+    log::debug!("Environment:\n{}", environment_report);
+
+    let cancellation_token = CancellationToken::new();
+    {
+        let cancellation_token = cancellation_token.clone();
+        ctrlc::set_handler(move || {
+            log::warn!("Termination requested, cancelling TOPO calculation");
+            cancellation_token.cancel();
+        })?;
+    }
+    match config.threads {
+        Some(threads) => {
+            let thread_pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?;
+            thread_pool.install(|| run(&config, &cancellation_token, args.check))?;
+        }
+        None => {
+            run(&config, &cancellation_token, args.check)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a config file, with the deprecated proposal fields migrated, see `migrate_deprecated_proposal_fields`.
+fn read_config(config_filepath: &Path) -> anyhow::Result<Config> {
+    if !config_filepath.exists() {
+        return Err(anyhow!("Config file {:?} not found", config_filepath));
+    }
+    let config_contents = read_to_string(config_filepath)?;
+    let mut config: Config = serde_yaml::from_str(&config_contents)?;
+    migrate_deprecated_proposal_fields(&mut config)?;
+    Ok(config)
+}
+
+/// Maximum length of the comment lines of the example config written by `init-config`.
+const EXAMPLE_CONFIG_LINE_WIDTH: usize = 120;
+
+/// Comments of the entries of the example config written by `init-config`, by their dotted paths. Every top-level
+/// entry of `example_config`, and every entry of its sections, has one, see `test_example_config_comments`.
+const EXAMPLE_CONFIG_COMMENTS: &[(&str, &str)] = &[
+    ("proposal", "The proposal road map to evaluate."),
+    (
+        "proposal_crs_override",
+        "CRS to use for the proposal instead of the one declared by its geofile, as an EPSG code, an \"EPSG:<code>\" \
+        string, a PROJ string or WKT.",
+    ),
+    (
+        "ground_truth",
+        "The ground truth road map. A list of named sources evaluates the proposal against each of them separately.",
+    ),
+    (
+        "ground_truth_union_snap_tolerance",
+        "If set and the ground truths are named, the proposal is also evaluated against their union. Nodes of the \
+        ground truths within this distance of each other, in units of the projected CRS, are unified.",
+    ),
+    (
+        "ground_truth_crs_override",
+        "CRS to use for a geofile ground truth instead of the one declared by its geofile.",
+    ),
+    (
+        "ground_truth_preprocessing",
+        "Conversions of the ground truth features before the ground truth graph is built from them.",
+    ),
+    (
+        "ground_truth_preprocessing.polygons_to_centerlines",
+        "Replace road or lane polygons of a geofile ground truth with their approximate centerlines.",
+    ),
+    (
+        "ground_truth_cache",
+        "If set, the preprocessed ground truth graph is cached in this file and reused by runs with the same ground \
+        truth config.",
+    ),
+    (
+        "normalize_edge_orientation",
+        "Whether to orient all edge geometries away from their start node before sampling. Defaults to on for \
+        undirected graphs.",
+    ),
+    (
+        "proposal_duplicate_edge_tolerance",
+        "If set, of parallel proposal edges at most this far apart, in the units of topo_params, only the longest \
+        one is kept.",
+    ),
+    (
+        "split_long_edges",
+        "If set, proposal edges longer than this, in the units of topo_params, are split into chained parts.",
+    ),
+    (
+        "split_edges_at_near_nodes",
+        "If set, the edges of each graph passing within this distance of a node of the other graph, in the units of \
+        topo_params, are split there.",
+    ),
+    (
+        "snap_proposal_endpoints_to_gt",
+        "If set, dangling proposal edge endpoints are extended to the nearest ground truth node within this distance, \
+        in the units of topo_params.",
+    ),
+    (
+        "on_read_error",
+        "Whether to fail on features of the input geofiles which cannot be decoded, or to skip them with a warning.",
+    ),
+    (
+        "projection",
+        "The projected CRS to project the graphs to if the ground truth is in a geographic CRS.",
+    ),
+    (
+        "strict_crs_checks",
+        "Fail instead of warning if the coordinates of an input do not look plausible for its CRS.",
+    ),
+    (
+        "min_bounding_box_overlap",
+        "Minimum fraction of the smaller of the bounding boxes of the inputs which the other one must cover.",
+    ),
+    (
+        "strict_overlap_check",
+        "Fail instead of warning if the bounding boxes of the inputs overlap by less than min_bounding_box_overlap.",
+    ),
+    (
+        "lenient_coordinate_range",
+        "Only warn instead of failing if the coordinates of an input exceed the range of its geographic CRS.",
+    ),
+    (
+        "detect_coordinate_outliers",
+        "Exclude line features whose coordinates are outliers by orders of magnitude, with a warning.",
+    ),
+    (
+        "coordinate_precision",
+        "If set, the coordinates of the line inputs are quantized to a grid with this spacing, in units of their CRS, \
+        before their endpoints are joined into nodes.",
+    ),
+    (
+        "threads",
+        "Number of threads of the parallel computations. Defaults to the number of logical CPUs.",
+    ),
+    ("topo_params", "Parameters of the TOPO computation."),
+    (
+        "topo_params.resampling_distance",
+        "Distance between the points sampled on the edges.",
+    ),
+    (
+        "topo_params.hole_radius",
+        "Distance within which a proposal point matches a ground truth point, unless set by hole_radius_by_class.",
+    ),
+    (
+        "topo_params.matching_mode",
+        "Greedy, or OptimalAssignment to maximize the number of matches.",
+    ),
+    (
+        "topo_params.road_class_attribute",
+        "Name of the ground truth edge attribute holding the road class, e.g. highway for OSM.",
+    ),
+    (
+        "topo_params.proposal_road_class_attribute",
+        "Name of the proposal edge attribute holding the road class, if it differs from road_class_attribute.",
+    ),
+    (
+        "topo_params.hole_radius_by_class",
+        "Hole radius of ground truth points by the road class of their edge.",
+    ),
+    (
+        "topo_params.evaluation_mask",
+        "If set, only ground truth points within the mask count for recall, e.g. !ProposalBuffer with a distance.",
+    ),
+    (
+        "topo_params.units",
+        "Units of the distances, Meters or CrsUnits.",
+    ),
+    (
+        "topo_params.anisotropy_warning_latitude",
+        "Warn when converting meters to degrees above this absolute latitude.",
+    ),
+    (
+        "topo_params.propagate_edge_attributes",
+        "Attributes of proposal edges copied to the proposal points sampled on them.",
+    ),
+    (
+        "topo_params.include_original_vertices",
+        "Also sample every vertex of the edges.",
+    ),
+    (
+        "topo_params.max_network_detour_factor",
+        "If set, only match points whose edges are compatible, i.e. of the same road class and similar azimuth.",
+    ),
+    (
+        "topo_params.deduplicate_proposal_points",
+        "Merge proposal points of different edges which run in nearly the same direction within \
+        proposal_dedup_radius.",
+    ),
+    (
+        "topo_params.proposal_dedup_radius",
+        "Radius within which proposal points are merged. Defaults to a quarter of the resampling distance.",
+    ),
+    (
+        "topo_params.exclude_near_junction_distance",
+        "If set, the points of both graphs within this distance of a junction are dropped.",
+    ),
+    (
+        "topo_params.collapse_reciprocal_edges",
+        "Sample the reciprocal edges of directed graphs, e.g. of two-way streets, only once.",
+    ),
+    (
+        "topo_params.sampling_phase_offsets",
+        "If set, the proposal is also sampled and matched with its points shifted by each of these fractions of the \
+        resampling distance.",
+    ),
+    (
+        "topo_params.directional_matching",
+        "Only match points whose edges head in the same direction. Rejected for the undirected graphs of the \
+        executable.",
+    ),
+    (
+        "topo_params.low_memory",
+        "Spill the sampled ground truth points to a memory-mapped temporary file while matching.",
+    ),
+    (
+        "data_dir",
+        "Directory of the outputs of the run, and of the downloaded OSM data.",
+    ),
+    (
+        "run_name",
+        "If set, the outputs are written to data_dir/runs/<run_name> instead of the data dir.",
+    ),
+    (
+        "create_run_subdir",
+        "Write the outputs to data_dir/runs/<timestamp> if run_name is not set.",
+    ),
+    (
+        "overwrite",
+        "Allow writing the outputs to a run directory which is not empty.",
+    ),
+    (
+        "ground_truth_geojson",
+        "Options of the ground truth GeoJSON written to the data dir.",
+    ),
+    (
+        "ground_truth_geojson.feature_bbox",
+        "Write the bounding box of each feature.",
+    ),
+    (
+        "ground_truth_geojson.crs_member",
+        "Declare a CRS other than EPSG:4326 in the deprecated crs member.",
+    ),
+    (
+        "ground_truth_geojson.compress",
+        "Write ground_truth.geojson.gz, gzip compressed.",
+    ),
+    ("outputs", "Options of the outputs written to the data dir."),
+    (
+        "outputs.node_detail",
+        "Which sampled points are written: all, unmatched_only or none.",
+    ),
+    (
+        "outputs.dataset_creation_options",
+        "GDAL dataset creation options of the output GeoPackages, as KEY=VALUE strings.",
+    ),
+    (
+        "outputs.layer_creation_options",
+        "GDAL layer creation options of the output GeoPackages, as KEY=VALUE strings.",
+    ),
+    (
+        "outputs.dump_candidates",
+        "If set, the ground truth points within hole radius of each proposal point are written to this CSV file.",
+    ),
+    (
+        "outputs.node_schema",
+        "Names and types of the attributes of the point outputs.",
+    ),
+    (
+        "outputs.edge_report",
+        "Write the edges of both graphs with the fraction of their matched points to edge_report.gpkg.",
+    ),
+    (
+        "outputs.debug_outputs",
+        "Write the histogram of the match distances to match_distance_histogram.csv.",
+    ),
+    (
+        "outputs.match_distance_bin_width",
+        "The bin width of match_distance_histogram.csv, in units of the projected CRS.",
+    ),
+    (
+        "outputs.progress_file",
+        "If set, the progress of the TOPO computation is written to this file as JSON lines.",
+    ),
+    (
+        "heatmap",
+        "If set, a grid of local recall and precision values is written to the data dir.",
+    ),
+    (
+        "missing_coverage",
+        "If set, the unmatched ground truth points are clustered into areas of missing coverage.",
+    ),
+    (
+        "score_threshold_sweep",
+        "If set, TOPO is also calculated for thresholds of a proposal edge score, for a precision-recall curve.",
+    ),
+    (
+        "parameter_sweep",
+        "If set, TOPO is also calculated for combinations of the resampling distance and the hole radius, reusing \
+        the points sampled for each resampling distance.",
+    ),
+    (
+        "trace_edge",
+        "If set, the matching of the points of this proposal edge is traced.",
+    ),
+    (
+        "approximate",
+        "If set, only a random fraction of tiles of both graphs is evaluated, and the scores are estimates.",
+    ),
+];
+
+/// The config written by `init-config`: a geofile proposal and ground truth, and the defaults of all other entries.
+/// All fields are listed, so that a new entry has to be added here.
+fn example_config() -> Config {
+    Config {
+        proposal: Some(GraphSource::Geofile {
+            filepath: PathBuf::from("proposal.gpkg"),
+        }),
+        proposal_geofile_path: None,
+        proposal_node_edge_csv: None,
+        proposal_crs_override: None,
+        ground_truth: GroundTruthConfig::Single(GraphSource::Geofile {
+            filepath: PathBuf::from("ground_truth.gpkg"),
+        }),
+        ground_truth_union_snap_tolerance: None,
+        ground_truth_crs_override: None,
+        ground_truth_preprocessing: GroundTruthPreprocessingConfig::default(),
+        ground_truth_cache: None,
+        normalize_edge_orientation: None,
+        proposal_duplicate_edge_tolerance: None,
+        split_long_edges: None,
+        split_edges_at_near_nodes: None,
+        snap_proposal_endpoints_to_gt: None,
+        on_read_error: OnReadError::default(),
+        projection: Projection::default(),
+        strict_crs_checks: false,
+        min_bounding_box_overlap: default_min_bounding_box_overlap(),
+        strict_overlap_check: false,
+        lenient_coordinate_range: false,
+        detect_coordinate_outliers: default_detect_coordinate_outliers(),
+        coordinate_precision: None,
+        threads: None,
+        topo_params: TopoParams::default(),
+        data_dir: PathBuf::from("data"),
+        run_name: None,
+        create_run_subdir: false,
+        overwrite: false,
+        ground_truth_geojson: GeoJsonOutputConfig::default(),
+        outputs: OutputsConfig::default(),
+        heatmap: None,
+        missing_coverage: None,
+        score_threshold_sweep: None,
+        parameter_sweep: None,
+        trace_edge: None,
+        approximate: None,
     }
-    let config_contents = read_to_string(args.config_filepath)?;
-    let config: Config = serde_yaml::from_str(&config_contents)?;
+}
+
+/// An alternative value of a top-level entry of the example config, which is written commented out after the entry.
+struct ConfigAlternative {
+    key: &'static str,
+    description: &'static str,
+    value: serde_yaml::Value,
+}
+
+impl ConfigAlternative {
+    fn new(
+        key: &'static str,
+        description: &'static str,
+        value: impl Serialize,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            key,
+            description,
+            value: serde_yaml::to_value(value)?,
+        })
+    }
+}
 
-    let mut ground_truth_graph: GeoFeatureGraph<petgraph::Undirected> = match config.ground_truth {
-        GroundTruthConfig::Osm { bounding_box } => {
-            let ground_truth_ways =
-                get_ground_truth_ways_from_osm(&bounding_box, &config.data_dir)?;
-            let mut graph = build_geograph_from_lines(ground_truth_ways)?;
-            graph.crs = epsg_4326();
-            graph
+/// The alternatives of the entries of the example config. They are built from the config types, so that they stay
+/// valid as the types change.
+fn example_config_alternatives() -> anyhow::Result<Vec<ConfigAlternative>> {
+    let tokyo_station = WgsBoundingBox {
+        left_lon: 139.763,
+        right_lon: 139.771,
+        bottom_lat: 35.677,
+        top_lat: 35.685,
+    };
+    Ok(vec![
+        ConfigAlternative::new(
+            "proposal",
+            "node and edge CSV files, in EPSG:4326 unless proposal_crs_override is set",
+            GraphSource::NodeEdgeCsv {
+                nodes_path: PathBuf::from("proposal_nodes.csv"),
+                edges_path: PathBuf::from("proposal_edges.csv"),
+            },
+        )?,
+        ConfigAlternative::new(
+            "proposal_crs_override",
+            "an EPSG code",
+            Some(CrsDefinition::EpsgCode(32654)),
+        )?,
+        ConfigAlternative::new(
+            "ground_truth",
+            "the roads in a bounding box, downloaded from OpenStreetMap",
+            GraphSource::Osm {
+                bounding_box: tokyo_station.clone(),
+                endpoint: OsmEndpoint::default(),
+                highway_classes: vec!["primary".to_string(), "secondary".to_string()],
+                merge_ways: false,
+                compress_cache: false,
+            },
+        )?,
+        ConfigAlternative::new(
+            "ground_truth",
+            "the roads of a named place, looked up with Nominatim and downloaded from OpenStreetMap",
+            GraphSource::OsmPlace {
+                query: "Shibuya, Tokyo".to_string(),
+                bounding_box: None,
+                endpoint: OsmEndpoint::default(),
+                highway_classes: Vec::new(),
+                merge_ways: true,
+                compress_cache: true,
+            },
+        )?,
+        ConfigAlternative::new(
+            "ground_truth",
+            "the roads of an OSM XML file, optionally gzip or bzip2 compressed",
+            GraphSource::OsmFile {
+                filepath: PathBuf::from("extract.osm.bz2"),
+                highway_classes: Vec::new(),
+                merge_ways: false,
+            },
+        )?,
+        ConfigAlternative::new(
+            "ground_truth",
+            "node and edge CSV files",
+            GraphSource::NodeEdgeCsv {
+                nodes_path: PathBuf::from("ground_truth_nodes.csv"),
+                edges_path: PathBuf::from("ground_truth_edges.csv"),
+            },
+        )?,
+        ConfigAlternative::new(
+            "ground_truth",
+            "several named ground truths, which the proposal is evaluated against separately",
+            GroundTruthConfig::Named(vec![
+                NamedGraphSource {
+                    name: "osm".to_string(),
+                    source: GraphSource::Osm {
+                        bounding_box: tokyo_station,
+                        endpoint: OsmEndpoint::default(),
+                        highway_classes: Vec::new(),
+                        merge_ways: false,
+                        compress_cache: false,
+                    },
+                },
+                NamedGraphSource {
+                    name: "survey".to_string(),
+                    source: GraphSource::Geofile {
+                        filepath: PathBuf::from("survey.gpkg"),
+                    },
+                },
+            ]),
+        )?,
+        ConfigAlternative::new(
+            "ground_truth_union_snap_tolerance",
+            "also evaluate against the union of named ground truths",
+            Some(1.0),
+        )?,
+        ConfigAlternative::new(
+            "ground_truth_crs_override",
+            "an authority string",
+            Some(CrsDefinition::Definition("EPSG:6677".to_string())),
+        )?,
+        ConfigAlternative::new(
+            "ground_truth_preprocessing",
+            "a ground truth of road polygons",
+            GroundTruthPreprocessingConfig {
+                polygons_to_centerlines: true,
+            },
+        )?,
+        ConfigAlternative::new(
+            "ground_truth_cache",
+            "cache the ground truth graph",
+            Some(PathBuf::from("data/ground_truth_cache.bin")),
+        )?,
+        ConfigAlternative::new(
+            "normalize_edge_orientation",
+            "keep the digitization direction of the edges",
+            Some(false),
+        )?,
+        ConfigAlternative::new(
+            "proposal_duplicate_edge_tolerance",
+            "drop duplicate detections",
+            Some(2.0),
+        )?,
+        ConfigAlternative::new("split_long_edges", "split long edges", Some(1000.0))?,
+        ConfigAlternative::new(
+            "split_edges_at_near_nodes",
+            "split edges at T-junctions",
+            Some(0.5),
+        )?,
+        ConfigAlternative::new(
+            "snap_proposal_endpoints_to_gt",
+            "close gaps at junctions",
+            Some(5.0),
+        )?,
+        ConfigAlternative::new(
+            "on_read_error",
+            "skip broken features",
+            OnReadError::SkipAndWarn,
+        )?,
+        ConfigAlternative::new(
+            "projection",
+            "a projection centered on the area of interest",
+            Projection::LocalTransverseMercator,
+        )?,
+        ConfigAlternative::new(
+            "coordinate_precision",
+            "join endpoints which differ by noise, for EPSG:4326 inputs",
+            Some(1e-8),
+        )?,
+        ConfigAlternative::new("threads", "a fixed number of threads", Some(4))?,
+        ConfigAlternative::new(
+            "topo_params",
+            "road classes with their own hole radii, optimal matching and an evaluation mask",
+            TopoParams {
+                matching_mode: MatchingMode::OptimalAssignment,
+                road_class_attribute: Some("highway".to_string()),
+                hole_radius_by_class: Some(HashMap::from([("motorway".to_string(), 8.0)])),
+                evaluation_mask: Some(EvaluationMask::ProposalBuffer { distance: 50.0 }),
+                propagate_edge_attributes: vec!["confidence".to_string()],
+                deduplicate_proposal_points: true,
+                exclude_near_junction_distance: Some(2.5),
+                sampling_phase_offsets: Some(vec![0.0, 0.25, 0.5, 0.75]),
+                ..TopoParams::default()
+            },
+        )?,
+        ConfigAlternative::new("run_name", "a named run", Some("baseline"))?,
+        ConfigAlternative::new(
+            "outputs",
+            "only the unmatched points, and all diagnostic outputs",
+            OutputsConfig {
+                node_detail: NodeDetail::UnmatchedOnly,
+                dataset_creation_options: Vec::new(),
+                layer_creation_options: vec!["SPATIAL_INDEX=NO".to_string()],
+                dump_candidates: Some(PathBuf::from("data/candidates.csv")),
+                node_schema: NodeExportSchema::default(),
+                edge_report: true,
+                debug_outputs: true,
+                match_distance_bin_width: Some(DEFAULT_MATCH_DISTANCE_BIN_WIDTH),
+                progress_file: Some(PathBuf::from("data/progress.jsonl")),
+            },
+        )?,
+        ConfigAlternative::new(
+            "heatmap",
+            "a heatmap",
+            Some(HeatmapConfig {
+                cell_size: DEFAULT_HEATMAP_CELL_SIZE,
+            }),
+        )?,
+        ConfigAlternative::new(
+            "missing_coverage",
+            "clusters of missing coverage",
+            Some(MissingCoverageConfig::default()),
+        )?,
+        ConfigAlternative::new(
+            "score_threshold_sweep",
+            "a sweep over a confidence score",
+            Some(ScoreThresholdSweepConfig {
+                attribute: "confidence".to_string(),
+                missing_score: MissingScore::default(),
+            }),
+        )?,
+        ConfigAlternative::new(
+            "parameter_sweep",
+            "a sweep over two resampling distances and hole radii",
+            Some(ParameterSweepConfig {
+                combinations: [5.0, 10.0]
+                    .into_iter()
+                    .flat_map(|resampling_distance| {
+                        [5.0, 8.0].map(|hole_radius| ParameterCombination {
+                            resampling_distance: Some(resampling_distance),
+                            hole_radius: Some(hole_radius),
+                        })
+                    })
+                    .collect(),
+                cache_capacity: DEFAULT_EVALUATOR_CACHE_CAPACITY,
+            }),
+        )?,
+        ConfigAlternative::new(
+            "trace_edge",
+            "trace the edge of an OSM way",
+            Some(TraceEdgeConfig {
+                edge: EdgeSelector::Attribute {
+                    name: "osm_id".to_string(),
+                    value: "4711".to_string(),
+                },
+                nearest_candidates: DEFAULT_TRACE_CANDIDATE_COUNT,
+            }),
+        )?,
+        ConfigAlternative::new(
+            "approximate",
+            "estimate the scores on a tenth of the tiles",
+            Some(ApproximateConfig {
+                fraction: 0.1,
+                seed: 42,
+                tile_size: DEFAULT_APPROXIMATE_TILE_SIZE,
+                bootstrap_samples: DEFAULT_BOOTSTRAP_SAMPLES,
+            }),
+        )?,
+    ])
+}
+
+/// The lines of a comment wrapped at `EXAMPLE_CONFIG_LINE_WIDTH`, with the given indentation.
+fn comment_lines(text: &str, indent: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = format!("{}#", indent);
+    for word in text.split_whitespace() {
+        if line.len() + 1 + word.len() > EXAMPLE_CONFIG_LINE_WIDTH && line.len() > indent.len() + 1
+        {
+            lines.push(std::mem::replace(&mut line, format!("{}#", indent)));
         }
-        GroundTruthConfig::Geofile { filepath } => GeoFeatureGraph::load_from_geofile(&filepath)?,
+        line.push(' ');
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// The example config as YAML, with the comments of `EXAMPLE_CONFIG_COMMENTS` above its entries, and the alternatives
+/// of `example_config_alternatives` commented out after them. The lines of the alternatives start with `#` directly
+/// followed by YAML, so that they can be uncommented by removing it.
+fn example_config_yaml() -> anyhow::Result<String> {
+    let comments: HashMap<&str, &str> = EXAMPLE_CONFIG_COMMENTS.iter().copied().collect();
+    let alternatives = example_config_alternatives()?;
+    let alternative_lines = |key: &str| -> anyhow::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for alternative in alternatives
+            .iter()
+            .filter(|alternative| alternative.key == key)
+        {
+            lines.extend(comment_lines(
+                &format!("Alternative: {}.", alternative.description),
+                "",
+            ));
+            let entry = serde_yaml::Mapping::from_iter([(key.into(), alternative.value.clone())]);
+            lines.extend(
+                serde_yaml::to_string(&entry)?
+                    .lines()
+                    .map(|line| format!("#{}", line)),
+            );
+        }
+        Ok(lines)
     };
-    log::info!(
-        "Read ground truth graph with {}  edges",
-        ground_truth_graph.edge_graph().edge_count()
+
+    let mut lines = comment_lines(
+        "Example config of topo_rust, written by `topo_rust init-config`. Entries are set to their defaults. Lines \
+        starting with # directly followed by YAML are alternatives of the entry above them: to use one, remove the \
+        entry and uncomment the alternative.",
+        "",
     );
+    let mut section: Option<&str> = None;
+    let serialized = serde_yaml::to_string(&example_config())?;
+    for line in serialized.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let key = line
+            .trim_start()
+            .split_once(':')
+            .map(|(key, _)| key)
+            .filter(|key| key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        match (indent, key) {
+            (0, Some(key)) => {
+                if let Some(previous_section) = section {
+                    lines.extend(alternative_lines(previous_section)?);
+                }
+                lines.push(String::new());
+                if let Some(comment) = comments.get(key) {
+                    lines.extend(comment_lines(comment, ""));
+                }
+                section = Some(key);
+            }
+            (2, Some(key)) => {
+                let path = format!("{}.{}", section.unwrap_or_default(), key);
+                if let Some(comment) = comments.get(path.as_str()) {
+                    lines.extend(comment_lines(comment, "  "));
+                }
+            }
+            _ => {}
+        }
+        lines.push(line.to_string());
+    }
+    if let Some(section) = section {
+        lines.extend(alternative_lines(section)?);
+    }
+    Ok(lines.join("\n") + "\n")
+}
 
-    let mut proposal_graph = GeoFeatureGraph::load_from_geofile(&config.proposal_geofile_path)?;
-    log::info!(
-        "Read proposal graph with {} edges",
-        proposal_graph.edge_graph().edge_count()
+/// Write the example config to `output`. Fails if the file exists, unless `force` is set.
+fn write_example_config(output: &Path, force: bool) -> anyhow::Result<()> {
+    if output.exists() && !force {
+        return Err(anyhow!(
+            "{:?} already exists, pass --force to overwrite it",
+            output
+        ));
+    }
+    std::fs::write(output, example_config_yaml()?)
+        .with_context(|| format!("Could not write the example config to {:?}", output))
+}
+
+/// The checks of the config entries which need no inputs, by name. Shared by `run` and `validate_config_file`, so
+/// that the validation does not drift from the checks of the run.
+fn config_checks(config: &Config) -> Vec<(&'static str, anyhow::Result<()>)> {
+    vec![
+        (
+            "TOPO parameters",
+            config.topo_params.validate().map_err(anyhow::Error::from),
+        ),
+        (
+            "Node schema",
+            config
+                .outputs
+                .node_schema
+                .validate()
+                .map_err(anyhow::Error::from),
+        ),
+        ("Ground truth names", validate_ground_truth_names(config)),
+        (
+            "Parameter sweep",
+            config
+                .parameter_sweep
+                .as_ref()
+                .map_or(Ok(()), |sweep_config| {
+                    sweep_config.validate(&config.topo_params)
+                })
+                .map_err(anyhow::Error::from),
+        ),
+        (
+            "Approximate evaluation",
+            config
+                .approximate
+                .as_ref()
+                .map_or(Ok(()), ApproximateConfig::validate)
+                .map_err(anyhow::Error::from),
+        ),
+    ]
+}
+
+/// Check a config file and its inputs as far as possible without loading the graphs or computing anything: the
+/// config itself, the environment, that the inputs are readable and their CRSs can be constructed, whether OSM data
+/// is cached, and that the outputs can be written. If `allow_network` is set, and OSM data is downloaded, the
+/// Overpass API is asked for its status.
+fn validate_config_file(config_filepath: &Path, allow_network: bool) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    let config = match read_config(config_filepath) {
+        Ok(config) => {
+            report.add(
+                "Config file",
+                CheckStatus::Pass,
+                format!("{:?}", config_filepath),
+            );
+            config
+        }
+        Err(error) => {
+            report.add("Config file", CheckStatus::Fail, format!("{:#}", error));
+            return report;
+        }
+    };
+    for (name, result) in config_checks(&config) {
+        report.check(name, result.map(|_| String::new()));
+    }
+    report.check(
+        "Environment",
+        check_environment().map(|environment| {
+            format!(
+                "GDAL {}, PROJ {}",
+                environment.gdal_version, environment.proj_version
+            )
+        }),
+    );
+
+    let mut sources = Vec::new();
+    if let Some(proposal) = &config.proposal {
+        sources.push((
+            "Proposal".to_string(),
+            proposal,
+            &config.proposal_crs_override,
+        ));
+    }
+    for (name, source) in config.ground_truth.sources() {
+        let label = match name {
+            Some(name) => format!("Ground truth '{}'", name),
+            None => "Ground truth".to_string(),
+        };
+        sources.push((label, source, &config.ground_truth_crs_override));
+    }
+    let mut downloads_osm_data = false;
+    // The geofile inputs which passed their check, whose extents can be compared.
+    let mut readable_geofiles = Vec::new();
+    for (label, source, crs_override) in sources {
+        let (status, detail) = match check_graph_source(source, crs_override, &config) {
+            Ok((status, detail)) => (status, detail),
+            Err(error) => (CheckStatus::Fail, format!("{:#}", error)),
+        };
+        if let (GraphSource::Geofile { filepath }, false) = (source, status == CheckStatus::Fail) {
+            readable_geofiles.push(filepath);
+        }
+        report.add(label.as_str(), status, detail);
+        if let Some(crs_override) = crs_override {
+            report.check(
+                format!("{} CRS override", label),
+                crs_override
+                    .to_crs()
+                    .and_then(|crs| topo::preprocessing::crs_label(&crs)),
+            );
+        }
+        downloads_osm_data |= matches!(
+            source,
+            GraphSource::Osm { .. } | GraphSource::OsmPlace { .. }
+        );
+    }
+    if let Some(GraphSource::Geofile {
+        filepath: proposal_filepath,
+    }) = &config.proposal
+    {
+        for (name, source) in config.ground_truth.sources() {
+            if let GraphSource::Geofile { filepath } = source {
+                if !readable_geofiles.contains(&proposal_filepath)
+                    || !readable_geofiles.contains(&filepath)
+                {
+                    continue;
+                }
+                let label = match name {
+                    Some(name) => format!("Overlap with ground truth '{}'", name),
+                    None => "Overlap with ground truth".to_string(),
+                };
+                let (status, detail) =
+                    match check_input_overlap(filepath, proposal_filepath, &config) {
+                        Ok((status, detail)) => (status, detail),
+                        Err(error) => (CheckStatus::Fail, format!("{:#}", error)),
+                    };
+                report.add(label.as_str(), status, detail);
+            }
+        }
+    }
+    if allow_network && downloads_osm_data {
+        report.check(
+            "Overpass API",
+            check_overpass_status().map(|status| format!("reachable. {}", status)),
+        );
+    }
+    report.check("Data dir", check_data_dir_writable(&config));
+    report
+}
+
+/// Check that the input of a graph can be read, without reading it. Geofiles must be readable by a GDAL driver and
+/// have a readable CRS, OSM data is checked for a cache file in the data dir.
+///
+/// # Returns
+/// The status, `CheckStatus::Warn` e.g. if OSM data is downloaded, and a description of the input.
+fn check_graph_source(
+    source: &GraphSource,
+    crs_override: &Option<CrsDefinition>,
+    config: &Config,
+) -> anyhow::Result<(CheckStatus, String)> {
+    let ensure_readable = |filepath: &Path| -> anyhow::Result<()> {
+        std::fs::File::open(filepath).with_context(|| format!("Cannot read {:?}", filepath))?;
+        Ok(())
+    };
+    Ok(match source {
+        GraphSource::Geofile { filepath } => {
+            ensure_readable(filepath)?;
+            let driver_name = read_geofile_driver_name(filepath)?;
+            let layers = describe_layers(&probe_geofile(filepath)?);
+            match read_geofile_crs(filepath, None)? {
+                Some(declared_crs) => (
+                    CheckStatus::Pass,
+                    format!(
+                        "{:?}, {} driver, CRS {}, {}",
+                        filepath,
+                        driver_name,
+                        topo::preprocessing::crs_label(&declared_crs)?,
+                        layers
+                    ),
+                ),
+                None if crs_override.is_some() => (
+                    CheckStatus::Pass,
+                    format!("{:?}, {} driver, {}", filepath, driver_name, layers),
+                ),
+                None => (
+                    CheckStatus::Warn,
+                    format!(
+                        "{:?}, {} driver, {}, declares no CRS, EPSG:4326 is assumed",
+                        filepath, driver_name, layers
+                    ),
+                ),
+            }
+        }
+        GraphSource::OsmFile { filepath, .. } => {
+            ensure_readable(filepath)?;
+            (CheckStatus::Pass, format!("{:?}", filepath))
+        }
+        GraphSource::NodeEdgeCsv {
+            nodes_path,
+            edges_path,
+        } => {
+            ensure_readable(nodes_path)?;
+            ensure_readable(edges_path)?;
+            (
+                CheckStatus::Pass,
+                format!("{:?} and {:?}", nodes_path, edges_path),
+            )
+        }
+        GraphSource::Osm {
+            bounding_box,
+            endpoint,
+            highway_classes,
+            compress_cache,
+            ..
+        }
+        | GraphSource::OsmPlace {
+            bounding_box: Some(bounding_box),
+            endpoint,
+            highway_classes,
+            compress_cache,
+            ..
+        } => match find_cached_osm_data(
+            bounding_box,
+            *endpoint,
+            highway_classes,
+            &config.data_dir,
+            *compress_cache,
+        )? {
+            Some(cache_filepath) => (
+                CheckStatus::Pass,
+                format!("OSM data cached in {:?}", cache_filepath),
+            ),
+            None => (
+                CheckStatus::Warn,
+                "OSM data is not cached, it is downloaded from the Overpass API".to_string(),
+            ),
+        },
+        GraphSource::OsmPlace {
+            query,
+            bounding_box: None,
+            ..
+        } => (
+            CheckStatus::Warn,
+            format!(
+                "The bounding box of '{}' is looked up with Nominatim, the OSM data may be downloaded",
+                query
+            ),
+        ),
+    })
+}
+
+/// A short description of the layers of a geofile with geometries, e.g. "layer 'roads' with 12 Line String
+/// features".
+fn describe_layers(layers: &[LayerInfo]) -> String {
+    layers
+        .iter()
+        .filter_map(|layer| {
+            let geometry_type = layer.geometry_type.as_ref()?;
+            Some(match layer.feature_count {
+                Some(feature_count) => format!(
+                    "layer '{}' with {} {} features",
+                    layer.name, feature_count, geometry_type
+                ),
+                None => format!("layer '{}' with {} features", layer.name, geometry_type),
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Check the overlap of the extents of a ground truth and a proposal geofile, read from their metadata, like
+/// `ensure_gt_proposal_overlap` does for the loaded graphs. The proposal extent is transformed to the CRS of the
+/// ground truth if they differ. Skipped if a driver cannot provide the extent without reading the features.
+///
+/// # Returns
+/// The status, `CheckStatus::Warn` if the extents overlap by less than `min_bounding_box_overlap`, or
+/// `CheckStatus::Fail` with `strict_overlap_check`, and a description of the overlap.
+fn check_input_overlap(
+    ground_truth_filepath: &Path,
+    proposal_filepath: &Path,
+    config: &Config,
+) -> anyhow::Result<(CheckStatus, String)> {
+    let extent = |filepath: &Path,
+                  crs_override: &Option<CrsDefinition>|
+     -> anyhow::Result<Option<(geo::Rect, Crs)>> {
+        let layers = probe_geofile(filepath)?;
+        let geometry_layers: Vec<&LayerInfo> = layers
+            .iter()
+            .filter(|layer| layer.geometry_type.is_some())
+            .collect();
+        let Some(extent) = geometry_layers
+            .first()
+            .filter(|_| geometry_layers.len() == 1)
+            .and_then(|layer| layer.extent_rect())
+        else {
+            return Ok(None);
+        };
+        let crs = match crs_override_to_crs(crs_override)? {
+            Some(crs) => crs,
+            None => read_geofile_crs(filepath, None)?.unwrap_or_else(epsg_4326),
+        };
+        Ok(Some((extent, crs)))
+    };
+    let (Some((ground_truth_extent, ground_truth_crs)), Some((proposal_extent, proposal_crs))) = (
+        extent(ground_truth_filepath, &config.ground_truth_crs_override)?,
+        extent(proposal_filepath, &config.proposal_crs_override)?,
+    ) else {
+        return Ok((
+            CheckStatus::Pass,
+            "Skipped, the extents are not available without reading the features".to_string(),
+        ));
+    };
+    let proposal_extent = if proposal_crs == ground_truth_crs {
+        proposal_extent
+    } else {
+        let projection = proj::Proj::new_known_crs(
+            &proj_definition(&proposal_crs)?,
+            &proj_definition(&ground_truth_crs)?,
+            None,
+        )?;
+        let mut corners = proposal_extent.to_polygon().exterior().clone();
+        for coord in corners.coords_mut() {
+            coord.transform(&projection)?;
+        }
+        geo::BoundingRect::bounding_rect(&corners)
+            .ok_or_else(|| anyhow!("The proposal extent has no corners"))?
+    };
+    let overlap = topo::preprocessing::rect_overlap(&ground_truth_extent, &proposal_extent);
+    let detail = format!(
+        "The extents overlap by {:.2}% of the smaller one",
+        overlap * 100.0
     );
-    let geojson_dump_filepath = config.data_dir.join("ground_truth.geojson");
+    Ok(if overlap >= config.min_bounding_box_overlap {
+        (CheckStatus::Pass, detail)
+    } else if config.strict_overlap_check {
+        (CheckStatus::Fail, detail)
+    } else {
+        (CheckStatus::Warn, detail)
+    })
+}
+
+/// Check that the outputs of a run can be written: a file can be created in the data dir, or, if the run creates a
+/// run directory in it, in the data dir or its closest existing ancestor. See `prepare_output_dir`.
+fn check_data_dir_writable(config: &Config) -> anyhow::Result<String> {
+    let creates_output_dir = config.run_name.is_some() || config.create_run_subdir;
+    let existing_dir = if creates_output_dir {
+        config
+            .data_dir
+            .ancestors()
+            .map(|dir| {
+                if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir
+                }
+            })
+            .find(|dir| dir.exists())
+            .unwrap_or(Path::new("."))
+    } else {
+        config.data_dir.as_path()
+    };
+    if !existing_dir.exists() {
+        return Err(anyhow!("{:?} does not exist", existing_dir));
+    }
+    if !existing_dir.is_dir() {
+        return Err(anyhow!("{:?} is not a directory", existing_dir));
+    }
+    let probe_filepath =
+        existing_dir.join(format!(".topo_rust_write_check_{}", std::process::id()));
+    std::fs::File::create(&probe_filepath)
+        .with_context(|| format!("Cannot create files in {:?}", existing_dir))?;
+    std::fs::remove_file(&probe_filepath)?;
+    Ok(if existing_dir == config.data_dir {
+        format!("{:?} is writable", existing_dir)
+    } else {
+        format!("{:?} is created in {:?}", config.data_dir, existing_dir)
+    })
+}
+
+/// Create the directory to write the outputs of a run to, see `Config::run_name`, and write the effective config to
+/// it. Fails if a run directory is not empty, unless `overwrite` is set.
+fn prepare_output_dir(config: &Config) -> anyhow::Result<PathBuf> {
+    let run_name = match (&config.run_name, config.create_run_subdir) {
+        (Some(run_name), _) => Some(run_name.clone()),
+        (None, true) => Some(chrono::Local::now().format("%Y%m%dT%H%M%S").to_string()),
+        (None, false) => None,
+    };
+    let output_dir = match run_name {
+        Some(run_name) => {
+            let mut components = Path::new(&run_name).components();
+            if !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            ) {
+                return Err(anyhow!(
+                    "Invalid run name '{}', it must be a plain directory name",
+                    run_name
+                ));
+            }
+            let run_dir = config.data_dir.join("runs").join(run_name);
+            let is_empty = match run_dir.read_dir() {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(_) => true,
+            };
+            if !is_empty && !config.overwrite {
+                return Err(anyhow!(
+                    "Run directory {:?} is not empty, set `overwrite: true` to overwrite its outputs",
+                    run_dir
+                ));
+            }
+            std::fs::create_dir_all(&run_dir)?;
+            run_dir
+        }
+        None => config.data_dir.clone(),
+    };
+    log::info!("Writing outputs to {:?}", output_dir);
+    std::fs::write(
+        output_dir.join("effective_config.yaml"),
+        serde_yaml::to_string(config)?,
+    )?;
+    Ok(output_dir)
+}
+
+/// Check the names of `GroundTruthConfig::Named` ground truths, which are used in output filenames, and that the
+/// config entries which only support a single ground truth are not set.
+fn validate_ground_truth_names(config: &Config) -> anyhow::Result<()> {
+    let sources = match &config.ground_truth {
+        GroundTruthConfig::Single(_) => return Ok(()),
+        GroundTruthConfig::Named(sources) => sources,
+    };
+    if sources.is_empty() {
+        return Err(anyhow!("The list of ground truths is empty"));
+    }
+    if config.ground_truth_cache.is_some() {
+        return Err(anyhow!(
+            "ground_truth_cache is not supported with several ground truths"
+        ));
+    }
+    let mut names = std::collections::HashSet::new();
+    for named in sources {
+        if named.name.is_empty()
+            || !named
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(anyhow!(
+                "Invalid ground truth name '{}', it may only contain letters, digits, '_' and '-'",
+                named.name
+            ));
+        }
+        if config.ground_truth_union_snap_tolerance.is_some()
+            && named.name == UNION_GROUND_TRUTH_NAME
+        {
+            return Err(anyhow!(
+                "The ground truth name '{}' is reserved for the union of the ground truths",
+                UNION_GROUND_TRUTH_NAME
+            ));
+        }
+        if !names.insert(&named.name) {
+            return Err(anyhow!("Duplicate ground truth name '{}'", named.name));
+        }
+    }
+    Ok(())
+}
 
-    // Write the ground truth to file for reference.
+/// Suffix of the names of the outputs of a named ground truth, empty for a single ground truth.
+fn output_name_suffix(ground_truth_name: Option<&str>) -> String {
+    ground_truth_name
+        .map(|name| format!("_{}", name))
+        .unwrap_or_default()
+}
+
+/// The file path with `suffix` appended to its file stem, e.g. `candidates_osm.csv` for `candidates.csv`.
+fn with_file_stem_suffix(filepath: &Path, suffix: &str) -> PathBuf {
+    let mut filename = filepath.file_stem().unwrap_or_default().to_owned();
+    filename.push(suffix);
+    if let Some(extension) = filepath.extension() {
+        filename.push(".");
+        filename.push(extension);
+    }
+    filepath.with_file_name(filename)
+}
+
+/// Evaluate the proposal against the ground truth of `config`, and write the outputs to its output dir, see
+/// `prepare_output_dir`.
+///
+/// With named ground truths, the proposal is evaluated against each of them, and optionally against their union, in
+/// the projected CRS of the first one. The scores are keyed by the names in the result file, and the names are
+/// appended to the names of the outputs of each ground truth, e.g. `proposal_nodes_osm.gpkg`.
+///
+/// # Arguments
+/// * check - Only run the evaluation, without writing any outputs or the ground truth cache. Downloaded OSM data is
+///   still stored in the data dir.
+///
+/// # Returns
+/// The metrics of the stages of the run, which are also written to the result file.
+fn run(
+    config: &Config,
+    cancellation_token: &CancellationToken,
+    check: bool,
+) -> anyhow::Result<Vec<StageMetrics>> {
+    for (_, result) in config_checks(config) {
+        result?;
+    }
+    let output_dir = if check {
+        log::info!("Checking the config, no outputs are written");
+        None
+    } else {
+        Some(prepare_output_dir(config)?)
+    };
+    let mut timer = StageTimer::new();
+    let warnings = WarningCollector::new();
+    let ground_truth_sources = config.ground_truth.sources();
+    let run_info = match output_dir {
+        Some(_) => Some(RunInfo::new(
+            &config.topo_params,
+            run_input_files(&config.proposal_source()?, &ground_truth_sources)?,
+        )),
+        None => None,
+    };
+    let ground_truth_cache_key = ground_truth_cache_key(config)?;
+    let cached_ground_truth_graph = match &config.ground_truth_cache {
+        Some(cache_filepath) => {
+            timer.start_stage("read_ground_truth_cache");
+            read_ground_truth_cache(cache_filepath, &ground_truth_cache_key)?
+        }
+        None => None,
+    };
+    let ground_truth_is_cached = cached_ground_truth_graph.is_some();
+    let mut ground_truths: Vec<(Option<&str>, GeoFeatureGraph<petgraph::Undirected>)> =
+        match cached_ground_truth_graph {
+            Some(graph) => vec![(None, graph)],
+            None => ground_truth_sources
+                .iter()
+                .map(|(name, source)| {
+                    Ok((
+                        *name,
+                        load_ground_truth_graph(source, config, &mut timer, &warnings)?,
+                    ))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+    for (name, ground_truth_graph) in &ground_truths {
+        log::info!(
+            "Read ground truth graph{} with {}  edges",
+            name.map(|name| format!(" '{}'", name)).unwrap_or_default(),
+            ground_truth_graph.edge_count()
+        );
+    }
+
+    let proposal_source = config.proposal_source()?;
+    timer.start_stage("load_proposal");
+    let mut proposal_graph = load_graph(
+        &proposal_source,
+        &config.proposal_crs_override,
+        config,
+        &mut timer,
+        &warnings,
+    )?;
     log::info!(
-        "Writing ground truth edges to GeoJSON to {:?}",
-        &geojson_dump_filepath
+        "Read proposal graph with {} edges",
+        proposal_graph.edge_count()
     );
-    geofile::geojson::write_lines_to_geojson(
-        &ground_truth_graph.edge_geometries(),
-        &geojson_dump_filepath,
+    timer.finish_stage();
+
+    for ((_, ground_truth_graph), (_, source)) in ground_truths.iter().zip(&ground_truth_sources) {
+        topo::preprocessing::ensure_crs_plausible(
+            ground_truth_graph,
+            &graph_source_name(source, "ground truth"),
+            config.strict_crs_checks,
+            Some(&warnings),
+        )?;
+    }
+    topo::preprocessing::ensure_crs_plausible(
+        &proposal_graph,
+        &graph_source_name(&proposal_source, "proposal"),
+        config.strict_crs_checks,
+        Some(&warnings),
     )?;
 
+    // Write the ground truths to file for reference, on other threads while the evaluation runs. The geometries are
+    // copied before they are projected. The cached ground truth is already projected, so its CRS is always declared.
+    let mut ground_truth_geojson_writers = Vec::new();
+    if let Some(output_dir) = &output_dir {
+        for (name, ground_truth_graph) in &ground_truths {
+            let geojson_dump_filepath = output_dir.join(format!(
+                "ground_truth{}.geojson{}",
+                output_name_suffix(*name),
+                if config.ground_truth_geojson.compress {
+                    ".gz"
+                } else {
+                    ""
+                }
+            ));
+            log::info!(
+                "Writing ground truth edges to GeoJSON to {:?}",
+                &geojson_dump_filepath
+            );
+            let declares_crs = (config.ground_truth_geojson.crs_member || ground_truth_is_cached)
+                && ground_truth_graph.crs.auth_code().ok() != Some(4326);
+            let writer = GeoJsonLinesWriter::from_edges(
+                ground_truth_graph.keyed_edge_geometries(),
+                &geojson_dump_filepath,
+                &GeoJsonWriteOptions {
+                    feature_bbox: config.ground_truth_geojson.feature_bbox,
+                    crs: declares_crs.then_some(&ground_truth_graph.crs),
+                    force_projected_crs: true,
+                },
+            )?;
+            ground_truth_geojson_writers.push(std::thread::spawn(move || {
+                let start = Instant::now();
+                let result = writer.write();
+                log::info!(
+                    "Wrote the ground truth GeoJSON in {:.3} s",
+                    start.elapsed().as_secs_f64()
+                );
+                result
+            }));
+        }
+    }
+
+    timer.start_stage("project");
+    let (first_ground_truth, other_ground_truths) = ground_truths.split_first_mut().unwrap();
+    let first_ground_truth_graph = &mut first_ground_truth.1;
     topo::preprocessing::ensure_gt_proposal_in_same_projected_crs(
-        &mut ground_truth_graph,
+        first_ground_truth_graph,
         &mut proposal_graph,
+        config.projection,
     )?;
+    let projected_crs = proj_definition(&first_ground_truth_graph.crs)?;
+    for (_, ground_truth_graph) in other_ground_truths.iter_mut() {
+        if proj_definition(&ground_truth_graph.crs)? != projected_crs {
+            project_geograph(ground_truth_graph, &first_ground_truth_graph.crs)?;
+        }
+    }
+    for (_, ground_truth_graph) in &ground_truths {
+        topo::preprocessing::ensure_gt_proposal_overlap(
+            ground_truth_graph,
+            &proposal_graph,
+            config.min_bounding_box_overlap,
+            config.strict_overlap_check,
+            Some(&warnings),
+        )?;
+    }
 
-    let topo_result = calculate_topo(&proposal_graph, &ground_truth_graph, &config.topo_params)?;
-    log::info!("{:?}", topo_result.f1_score_result);
-    write_features_to_geofile(
-        &topo_result
-            .proposal_nodes
-            .par_iter()
-            .map(|node| Feature::from(node))
-            .collect(),
-        &config.data_dir.join("proposal_nodes.gpkg"),
-        Some(&proposal_graph.crs),
-        GdalDriverType::GeoPackage.name(),
-    )?;
-    write_features_to_geofile(
-        &topo_result
-            .ground_truth_nodes
-            .par_iter()
-            .map(|node| Feature::from(node))
-            .collect(),
-        &config.data_dir.join("ground_truth_nodes.gpkg"),
-        Some(&ground_truth_graph.crs),
-        GdalDriverType::GeoPackage.name(),
-    )?;
-    Ok(())
+    // The preprocessing distances are in the units of the TOPO parameters, and are converted alike.
+    let distance_factor = config.topo_params.crs_units_factor(&ground_truths[0].1)?;
+    if let Some(tolerance) = config.proposal_duplicate_edge_tolerance {
+        timer.start_stage("deduplicate_proposal_edges");
+        let removed_count =
+            deduplicate_parallel_edges(&mut proposal_graph, tolerance * distance_factor);
+        log::info!("Removed {} duplicate proposal edges", removed_count);
+    }
+    if let Some(max_length) = config
+        .split_long_edges
+        .map(|max_length| max_length * distance_factor)
+    {
+        timer.start_stage("split_long_proposal_edges");
+        let split_count = topo::preprocessing::split_long_edges(&mut proposal_graph, max_length)?;
+        log::info!(
+            "Split {} proposal edges longer than {}",
+            split_count,
+            max_length
+        );
+    }
+
+    if let (Some(cache_filepath), false, false) =
+        (&config.ground_truth_cache, ground_truth_is_cached, check)
+    {
+        timer.start_stage("write_ground_truth_cache");
+        write_ground_truth_cache(&ground_truths[0].1, cache_filepath, &ground_truth_cache_key)?;
+    }
+
+    if let (GroundTruthConfig::Named(_), Some(snap_tolerance)) = (
+        &config.ground_truth,
+        config.ground_truth_union_snap_tolerance,
+    ) {
+        timer.start_stage("merge_ground_truths");
+        let mut union_graph = ground_truths[0].1.clone();
+        for (_, ground_truth_graph) in &ground_truths[1..] {
+            let report = union_graph.merge(ground_truth_graph.clone(), snap_tolerance)?;
+            log::info!("Merged ground truths into their union: {:?}", report);
+        }
+        ground_truths.push((Some(UNION_GROUND_TRUTH_NAME), union_graph));
+    }
+
+    let progress_bars = IndicatifProgress::new();
+    let progress_file = match config.outputs.progress_file.as_ref().filter(|_| !check) {
+        Some(progress_filepath) => Some(JsonlProgressSink::create(progress_filepath)?),
+        None => None,
+    };
+    let mut progress_sinks: Vec<&dyn ProgressSink> = vec![&progress_bars];
+    if let Some(progress_file) = &progress_file {
+        progress_sinks.push(progress_file);
+    }
+    let progress = TeeProgress::new(progress_sinks);
+    let evaluation_count = ground_truths.len();
+    let mut outcomes = Vec::new();
+    for (name, mut ground_truth_graph) in ground_truths {
+        let evaluation = GroundTruthEvaluation {
+            config,
+            name,
+            output_dir: output_dir.as_deref(),
+            run_info: run_info.as_ref(),
+            progress: &progress,
+            cancellation_token,
+            check,
+        };
+        // The proposal is split and reoriented for each ground truth, so each is evaluated against a copy of it.
+        let outcome = if evaluation_count > 1 {
+            evaluation.run(
+                &mut proposal_graph.clone(),
+                &mut ground_truth_graph,
+                &mut timer,
+            )?
+        } else {
+            evaluation.run(&mut proposal_graph, &mut ground_truth_graph, &mut timer)?
+        };
+        outcomes.push((name, outcome));
+    }
+
+    if !ground_truth_geojson_writers.is_empty() {
+        // The time the evaluation waits for the writers, which is only the part not overlapping with it.
+        timer.start_stage("write_ground_truth_geojson");
+    }
+    for ground_truth_geojson_writer in ground_truth_geojson_writers {
+        ground_truth_geojson_writer
+            .join()
+            .map_err(|_| anyhow!("Writing the ground truth GeoJSON panicked"))??;
+    }
+
+    let stage_metrics = timer.into_metrics();
+    if let (Some(output_dir), Some(run_info)) = (&output_dir, &run_info) {
+        write_result(
+            output_dir,
+            &outcomes,
+            &stage_metrics,
+            &projected_crs,
+            run_info,
+            &warnings,
+        )?;
+    }
+    log_stage_summary(&stage_metrics);
+    warnings.log_summary();
+    Ok(stage_metrics)
+}
+
+/// The outcome of the evaluation against one of the ground truths of a run, see `GroundTruthEvaluation::run`.
+struct EvaluationOutcome {
+    topo_result: TopoResult,
+    /// The `missing_coverage.top_n` largest areas of missing coverage, if configured.
+    missing_coverage: Option<Vec<MissingCoverageCluster>>,
+    /// The estimated scores with their sampling parameters, if the evaluation is approximate.
+    approximate: Option<ApproximateScores>,
+}
+
+/// The evaluation of the proposal against one of the ground truths of a run, see `run`.
+struct GroundTruthEvaluation<'a> {
+    config: &'a Config,
+    /// The name of a named ground truth, appended to the names of the outputs.
+    name: Option<&'a str>,
+    output_dir: Option<&'a Path>,
+    /// Added to the written GeoPackages, set if `output_dir` is.
+    run_info: Option<&'a RunInfo>,
+    progress: &'a dyn ProgressSink,
+    cancellation_token: &'a CancellationToken,
+    check: bool,
+}
+
+impl GroundTruthEvaluation<'_> {
+    /// Preprocess the graphs for the evaluation, calculate TOPO and the optional analyses, and write their outputs.
+    fn run(
+        &self,
+        proposal_graph: &mut GeoFeatureGraph<petgraph::Undirected>,
+        ground_truth_graph: &mut GeoFeatureGraph<petgraph::Undirected>,
+        timer: &mut StageTimer,
+    ) -> anyhow::Result<EvaluationOutcome> {
+        let config = self.config;
+        let suffix = output_name_suffix(self.name);
+        if let Some(name) = self.name {
+            log::info!(
+                "Evaluating the proposal against the ground truth '{}'",
+                name
+            );
+        }
+        let distance_factor = config.topo_params.crs_units_factor(ground_truth_graph)?;
+        if let Some(tolerance) = config.split_edges_at_near_nodes {
+            timer.start_stage("split_edges_at_near_nodes");
+            topo::preprocessing::split_gt_proposal_edges_at_near_nodes(
+                ground_truth_graph,
+                proposal_graph,
+                tolerance * distance_factor,
+            )?;
+        }
+        if let Some(distance) = config.snap_proposal_endpoints_to_gt {
+            timer.start_stage("snap_proposal_endpoints_to_gt");
+            topo::preprocessing::snap_proposal_endpoints_to_gt_nodes(
+                ground_truth_graph,
+                proposal_graph,
+                distance * distance_factor,
+            )?;
+        }
+
+        if config
+            .normalize_edge_orientation
+            .unwrap_or(!ground_truth_graph.edge_graph().is_directed())
+        {
+            timer.start_stage("normalize_edge_orientation");
+            log::info!("Normalizing edge orientation");
+            topo::preprocessing::normalize_gt_proposal_edge_orientation(
+                ground_truth_graph,
+                proposal_graph,
+            );
+        }
+        let approximate_tiles = match &config.approximate {
+            Some(approximate_config) => {
+                timer.start_stage("approximate_subsample");
+                let subsampled = subsample_graphs(
+                    proposal_graph,
+                    ground_truth_graph,
+                    &config.topo_params,
+                    approximate_config,
+                )?;
+                log::warn!(
+                    "Approximate evaluation on {} of {} tiles, the scores are estimates",
+                    subsampled.tiles.sampled_tile_count(),
+                    subsampled.tiles.tile_count()
+                );
+                *proposal_graph = subsampled.proposal_graph;
+                *ground_truth_graph = subsampled.ground_truth_graph;
+                Some(subsampled.tiles)
+            }
+            None => None,
+        };
+        timer.finish_stage();
+
+        let topo_result = match config
+            .outputs
+            .dump_candidates
+            .as_ref()
+            .filter(|_| !self.check)
+        {
+            Some(candidates_filepath) => calculate_topo_with_candidate_sink(
+                proposal_graph,
+                ground_truth_graph,
+                &config.topo_params,
+                Some(self.cancellation_token),
+                Some(self.progress),
+                &mut CsvCandidateWriter::create(&with_file_stem_suffix(
+                    candidates_filepath,
+                    &suffix,
+                ))?,
+            ),
+            None => calculate_topo(
+                proposal_graph,
+                ground_truth_graph,
+                &config.topo_params,
+                Some(self.cancellation_token),
+                Some(self.progress),
+            ),
+        };
+        let mut topo_result = match topo_result {
+            Ok(topo_result) => topo_result,
+            Err(error) => {
+                if let (
+                    Error::Cancelled {
+                        stage,
+                        completed_stages,
+                    },
+                    Some(output_dir),
+                ) = (error.root(), self.output_dir)
+                {
+                    write_partial_result(
+                        output_dir,
+                        stage,
+                        &[timer.completed_stages(), completed_stages].concat(),
+                    )?;
+                }
+                return Err(error.into());
+            }
+        };
+        timer.extend(topo_result.stage_metrics.iter().cloned());
+        let approximate = config
+            .approximate
+            .as_ref()
+            .zip(approximate_tiles.as_ref())
+            .map(|(approximate_config, tiles)| {
+                let scores = approximate_scores(&topo_result, tiles, approximate_config);
+                log::warn!(
+                    "Approximate F1 score {} with interval [{}, {}]",
+                    scores.f1_score_result.f1_score(),
+                    scores.f1_score_interval[0],
+                    scores.f1_score_interval[1]
+                );
+                topo_result.f1_score_result = scores.f1_score_result.clone();
+                scores
+            });
+        log::info!("{:?}", topo_result.f1_score_result);
+        if let Some(unmasked_f1_score_result) = &topo_result.unmasked_f1_score_result {
+            log::info!("Without evaluation mask: {:?}", unmasked_f1_score_result);
+        }
+
+        let write_options = WriteOptions::new()
+            .progress(self.progress)
+            .dataset_creation_options(config.outputs.dataset_creation_options.clone())
+            .layer_creation_options(config.outputs.layer_creation_options.clone());
+        if let (Some(output_dir), Some(run_info)) = (self.output_dir, self.run_info) {
+            timer.start_stage("write_output");
+            write_node_outputs(
+                &topo_result.proposal_nodes,
+                &format!("proposal_nodes{}", suffix),
+                &proposal_graph.crs,
+                config,
+                output_dir,
+                &write_options,
+                run_info,
+            )?;
+            write_node_outputs(
+                &topo_result.ground_truth_nodes,
+                &format!("ground_truth_nodes{}", suffix),
+                &ground_truth_graph.crs,
+                config,
+                output_dir,
+                &write_options,
+                run_info,
+            )?;
+            if config.outputs.edge_report {
+                let edge_report_filepath = output_dir.join(format!("edge_report{}.gpkg", suffix));
+                write_edge_report_gpkg(
+                    proposal_graph,
+                    ground_truth_graph,
+                    &topo_result,
+                    &edge_report_filepath,
+                )?;
+                run_info.append_to_geofile(&edge_report_filepath)?;
+            }
+            if config.outputs.debug_outputs {
+                write_match_distance_histogram_to_csv(
+                    &match_distance_histogram(
+                        &topo_result,
+                        config
+                            .outputs
+                            .match_distance_bin_width
+                            .unwrap_or(DEFAULT_MATCH_DISTANCE_BIN_WIDTH),
+                    ),
+                    &output_dir.join(format!("match_distance_histogram{}.csv", suffix)),
+                )?;
+            }
+        }
+        if let Some(heatmap_config) = &config.heatmap {
+            timer.start_stage("heatmap");
+            log::info!(
+                "Calculating heatmap with cell size {}",
+                heatmap_config.cell_size
+            );
+            let heatmap = grid_metrics(&topo_result, heatmap_config.cell_size);
+            if let (Some(output_dir), Some(run_info)) = (self.output_dir, self.run_info) {
+                let heatmap_filepath = output_dir.join(format!("heatmap{}.gpkg", suffix));
+                write_features_to_geofile(
+                    &heatmap,
+                    &heatmap_filepath,
+                    Some(&ground_truth_graph.crs),
+                    GdalDriverType::GeoPackage.name(),
+                    &write_options,
+                )?;
+                run_info.append_to_geofile(&heatmap_filepath)?;
+            }
+        }
+        let missing_coverage = match &config.missing_coverage {
+            Some(missing_coverage_config) => {
+                timer.start_stage("missing_coverage");
+                let mut clusters = missing_coverage_clusters(
+                    &topo_result,
+                    &config.topo_params.in_crs_units(ground_truth_graph)?,
+                    missing_coverage_config.min_points,
+                )?;
+                log::info!("Found {} areas of missing coverage", clusters.len());
+                if let (Some(output_dir), Some(run_info)) = (self.output_dir, self.run_info) {
+                    let missing_coverage_filepath =
+                        output_dir.join(format!("missing_coverage{}.gpkg", suffix));
+                    write_features_to_geofile(
+                        &missing_coverage_features(&clusters),
+                        &missing_coverage_filepath,
+                        Some(&ground_truth_graph.crs),
+                        GdalDriverType::GeoPackage.name(),
+                        &write_options,
+                    )?;
+                    run_info.append_to_geofile(&missing_coverage_filepath)?;
+                }
+                clusters.truncate(missing_coverage_config.top_n);
+                Some(clusters)
+            }
+            None => None,
+        };
+        if let Some(trace_config) = &config.trace_edge {
+            timer.start_stage("trace_edge");
+            let trace = trace_proposal_edge(
+                proposal_graph,
+                &topo_result,
+                &config.topo_params.in_crs_units(ground_truth_graph)?,
+                trace_config,
+            )?;
+            if let Some(output_dir) = self.output_dir {
+                std::fs::write(
+                    output_dir.join(format!("trace_edge{}.json", suffix)),
+                    serde_json::to_string_pretty(&trace)?,
+                )?;
+                write_features_to_geofile(
+                    &trace_features(&trace),
+                    &output_dir.join(format!("trace_edge{}.geojson", suffix)),
+                    Some(&proposal_graph.crs),
+                    GdalDriverType::GeoJson.name(),
+                    &write_options,
+                )?;
+            }
+        }
+        if let Some(sweep_config) = &config.score_threshold_sweep {
+            timer.start_stage("score_threshold_sweep");
+            let points = score_threshold_sweep(
+                proposal_graph,
+                ground_truth_graph,
+                &sweep_config.attribute,
+                sweep_config.missing_score,
+                &config.topo_params,
+            )?;
+            if let Some(output_dir) = self.output_dir {
+                write_threshold_points_to_csv(
+                    &points,
+                    &output_dir.join(format!("score_threshold_sweep{}.csv", suffix)),
+                )?;
+            }
+            match best_f1_threshold_point(&points) {
+                Some(best_point) => log::info!(
+                    "Best F1 score {} at {} threshold {}",
+                    best_point.f1_score,
+                    sweep_config.attribute,
+                    best_point.threshold
+                ),
+                None => log::warn!(
+                    "No proposal edge has a numeric '{}' attribute",
+                    sweep_config.attribute
+                ),
+            }
+        }
+        if let Some(sweep_config) = &config.parameter_sweep {
+            timer.start_stage("parameter_sweep");
+            let points = parameter_sweep(
+                proposal_graph,
+                ground_truth_graph,
+                sweep_config,
+                &config.topo_params,
+            )?;
+            if let Some(output_dir) = self.output_dir {
+                write_parameter_sweep_points_to_csv(
+                    &points,
+                    &output_dir.join(format!("parameter_sweep{}.csv", suffix)),
+                )?;
+            }
+            if let Some(best_point) = points
+                .iter()
+                .filter(|point| !point.f1_score.is_nan())
+                .max_by(|a, b| a.f1_score.total_cmp(&b.f1_score))
+            {
+                log::info!(
+                    "Best F1 score {} at resampling distance {} and hole radius {}",
+                    best_point.f1_score,
+                    best_point.resampling_distance,
+                    best_point.hole_radius
+                );
+            }
+        }
+        Ok(EvaluationOutcome {
+            topo_result,
+            missing_coverage,
+            approximate,
+        })
+    }
 }
 
 fn main() {
@@ -132,3 +2972,861 @@ fn main() {
         std::process::exit(1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use testdir::testdir;
+
+    use topo_rust::{
+        crs::Crs,
+        diagnostics::warnings::WarningCollector,
+        geofile::{
+            feature::Feature,
+            gdal_geofile::{
+                read_features_from_geofile, write_features_to_geofile, GdalDriverType, WriteOptions,
+            },
+        },
+        topo::cancellation::CancellationToken,
+    };
+
+    use super::{
+        compare_scores, example_config, example_config_alternatives, example_config_yaml,
+        ground_truth_cache_key, load_graph, migrate_deprecated_proposal_fields, read_config, run,
+        validate_ground_truth_names, write_example_config, ApproximateConfig, Config, EdgeSelector,
+        GraphSource, MissingCoverageConfig, OnReadError, TopoParams, TraceEdgeConfig,
+        EXAMPLE_CONFIG_COMMENTS, EXAMPLE_CONFIG_LINE_WIDTH,
+    };
+    use topo_rust::util::telemetry::StageTimer;
+
+    #[test]
+    fn test_config_serialization_round_trip() {
+        let minimal_config = r#"
+proposal_geofile_path: ./proposal.geojson
+ground_truth:
+  !Osm
+    bounding_box:
+      left_lon: 139.788745
+      right_lon: 139.792244
+      bottom_lat: 35.683695
+      top_lat: 35.685717
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: ./data
+"#;
+        let config: Config = serde_yaml::from_str(minimal_config).unwrap();
+        let serialized_config = serde_yaml::to_string(&config).unwrap();
+        // Defaulted fields are written out explicitly.
+        assert!(serialized_config.contains("matching_mode: Greedy"));
+        let reparsed_config: Config = serde_yaml::from_str(&serialized_config).unwrap();
+        assert_eq!(config, reparsed_config);
+    }
+
+    #[test]
+    fn test_config_without_topo_params() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+proposal_geofile_path: ./proposal.gpkg
+ground_truth:
+  !Geofile
+    filepath: ./ground_truth.gpkg
+data_dir: ./data
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(TopoParams::default(), config.topo_params);
+        assert_eq!(5.0, config.topo_params.resampling_distance);
+        assert_eq!(5.0, config.topo_params.hole_radius);
+        let partial_params: TopoParams = serde_yaml::from_str("hole_radius: 8.0").unwrap();
+        assert_eq!(
+            TopoParams {
+                hole_radius: 8.0,
+                ..TopoParams::default()
+            },
+            partial_params
+        );
+    }
+
+    #[test]
+    fn test_ground_truth_cache_key() {
+        let key = ground_truth_cache_key(&example_config()).unwrap();
+        // Entries which change the loaded ground truth graph invalidate the cache.
+        let ground_truth_changes: Vec<(&str, fn(&mut Config))> = vec![
+            ("on_read_error", |config| {
+                config.on_read_error = OnReadError::SkipAndWarn
+            }),
+            ("coordinate_precision", |config| {
+                config.coordinate_precision = Some(1e-8)
+            }),
+            ("detect_coordinate_outliers", |config| {
+                config.detect_coordinate_outliers = !config.detect_coordinate_outliers
+            }),
+        ];
+        for (name, change) in ground_truth_changes {
+            let mut config = example_config();
+            change(&mut config);
+            assert_ne!(key, ground_truth_cache_key(&config).unwrap(), "{}", name);
+        }
+
+        let mut config = example_config();
+        config.topo_params.hole_radius = 8.0;
+        assert_eq!(key, ground_truth_cache_key(&config).unwrap());
+    }
+
+    #[test]
+    fn test_init_config_round_trip() {
+        let filepath = testdir!().join("example.yaml");
+
+        write_example_config(&filepath, false).unwrap();
+
+        // The commented-out alternatives are YAML comments, which the parser skips.
+        let config = read_config(&filepath).unwrap();
+        assert_eq!(example_config(), config);
+        let minimal_config: Config = serde_yaml::from_str(
+            r#"
+proposal: !Geofile
+  filepath: proposal.gpkg
+ground_truth: !Geofile
+  filepath: ground_truth.gpkg
+data_dir: data
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            minimal_config, config,
+            "The example config must hold the defaults of the omitted entries"
+        );
+
+        let error = write_example_config(&filepath, false).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        std::fs::write(&filepath, "").unwrap();
+        write_example_config(&filepath, true).unwrap();
+        assert_eq!(example_config(), read_config(&filepath).unwrap());
+    }
+
+    #[test]
+    fn test_example_config_comments() {
+        let example = serde_yaml::to_value(example_config()).unwrap();
+        let mut paths = Vec::new();
+        for (key, entry) in example.as_mapping().unwrap() {
+            let key = key.as_str().unwrap();
+            paths.push(key.to_string());
+            // Tagged values are variants of enums like `GraphSource`, whose fields need no comments.
+            if let serde_yaml::Value::Mapping(section) = entry {
+                paths.extend(
+                    section
+                        .keys()
+                        .map(|field| format!("{}.{}", key, field.as_str().unwrap())),
+                );
+            }
+        }
+        let commented_paths: Vec<&str> = EXAMPLE_CONFIG_COMMENTS
+            .iter()
+            .map(|(path, _)| *path)
+            .collect();
+        for path in &paths {
+            assert!(
+                commented_paths.contains(&path.as_str()),
+                "No comment for {}",
+                path
+            );
+        }
+        for path in commented_paths {
+            assert!(
+                paths.iter().any(|other| other == path),
+                "Comment for unknown entry {}",
+                path
+            );
+        }
+        let yaml = example_config_yaml().unwrap();
+        assert!(
+            yaml.lines()
+                .all(|line| line.len() <= EXAMPLE_CONFIG_LINE_WIDTH
+                    || !line.trim_start().starts_with("# ")),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_example_config_alternatives() {
+        let yaml = example_config_yaml().unwrap();
+        let example = serde_yaml::to_value(example_config()).unwrap();
+        // The blocks of consecutive lines starting with # directly followed by YAML, which is indented by two spaces
+        // if nested, unlike the comments, which start with a single space.
+        let mut blocks: Vec<String> = Vec::new();
+        let mut in_block = false;
+        for line in yaml.lines() {
+            match line.strip_prefix('#') {
+                Some(uncommented)
+                    if !uncommented.starts_with(' ') || uncommented.starts_with("  ") =>
+                {
+                    if !in_block {
+                        blocks.push(String::new());
+                    }
+                    let block = blocks.last_mut().unwrap();
+                    block.push_str(uncommented);
+                    block.push('\n');
+                    in_block = true;
+                }
+                _ => in_block = false,
+            }
+        }
+
+        assert_eq!(example_config_alternatives().unwrap().len(), blocks.len());
+        for block in blocks {
+            let alternative: serde_yaml::Mapping = serde_yaml::from_str(&block).unwrap();
+            let mut config = example.clone();
+            for (key, value) in alternative {
+                assert!(
+                    config.as_mapping().unwrap().contains_key(&key),
+                    "Alternative of unknown entry {:?}",
+                    key
+                );
+                config.as_mapping_mut().unwrap().insert(key, value);
+            }
+            let config: Config = serde_yaml::from_value(config)
+                .unwrap_or_else(|error| panic!("Invalid alternative {}: {}", block, error));
+            assert_ne!(example_config(), config, "{}", block);
+        }
+    }
+
+    #[test]
+    fn test_deprecated_proposal_fields() {
+        let config_with_source = |proposal: &str| -> Config {
+            serde_yaml::from_str(&format!(
+                r#"
+{}
+ground_truth:
+  !Geofile
+    filepath: ./ground_truth.gpkg
+data_dir: ./data
+"#,
+                proposal
+            ))
+            .unwrap()
+        };
+        let new_style_config = config_with_source(
+            r#"
+proposal:
+  !Geofile
+    filepath: ./proposal.gpkg"#,
+        );
+        let mut old_style_config = config_with_source("proposal_geofile_path: ./proposal.gpkg");
+        let old_style_csv_config = config_with_source(
+            r#"
+proposal_node_edge_csv:
+  nodes_path: ./nodes.csv
+  edges_path: ./edges.csv"#,
+        );
+
+        assert_eq!(
+            new_style_config.proposal_source().unwrap(),
+            old_style_config.proposal_source().unwrap()
+        );
+        assert_eq!(
+            GraphSource::NodeEdgeCsv {
+                nodes_path: "./nodes.csv".into(),
+                edges_path: "./edges.csv".into(),
+            },
+            old_style_csv_config.proposal_source().unwrap()
+        );
+        migrate_deprecated_proposal_fields(&mut old_style_config).unwrap();
+        assert_eq!(new_style_config, old_style_config);
+        assert!(!serde_yaml::to_string(&old_style_config)
+            .unwrap()
+            .contains("proposal_geofile_path"));
+        let config_without_proposal: Config = serde_yaml::from_str(
+            r#"
+ground_truth:
+  !Geofile
+    filepath: ./ground_truth.gpkg
+data_dir: ./data
+"#,
+        )
+        .unwrap();
+        assert!(config_without_proposal.proposal_source().is_err());
+    }
+
+    #[test]
+    fn test_load_osm_file_proposal() {
+        let data_dir = testdir!();
+        let osm_filepath = data_dir.join("proposal.osm");
+        std::fs::write(
+            &osm_filepath,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="35.68" lon="139.78"/>
+  <node id="2" lat="35.68" lon="139.781"/>
+  <node id="3" lat="35.681" lon="139.781"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <way id="11">
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="primary"/>
+  </way>
+</osm>
+"#,
+        )
+        .unwrap();
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+proposal:
+  !OsmFile
+    filepath: {data_dir}/proposal.osm
+    highway_classes: [primary]
+ground_truth:
+  !Geofile
+    filepath: {data_dir}/ground_truth.gpkg
+data_dir: {data_dir}
+"#,
+            data_dir = data_dir.display()
+        ))
+        .unwrap();
+
+        let graph = load_graph(
+            &config.proposal_source().unwrap(),
+            &config.proposal_crs_override,
+            &config,
+            &mut StageTimer::new(),
+            &WarningCollector::new(),
+        )
+        .unwrap();
+
+        assert_eq!(1, graph.edge_count());
+        assert_eq!(Some(4326), graph.crs.auth_code().ok());
+    }
+
+    /// Write a ground truth and a proposal line 1 m apart to `data_dir`, and return a config evaluating them.
+    fn write_parallel_line_inputs(data_dir: &std::path::Path) -> Config {
+        let crs = Crs::Epsg(32654);
+        for (filename, y) in [
+            ("ground_truth.gpkg", 3950000.0),
+            ("proposal.gpkg", 3950001.0),
+        ] {
+            let features = vec![Feature {
+                geometry: geo::Geometry::LineString(vec![(390000.0, y), (390100.0, y)].into()),
+                attributes: None,
+                null_fields: Default::default(),
+            }];
+            write_features_to_geofile(
+                &features,
+                &data_dir.join(filename),
+                Some(&crs),
+                GdalDriverType::GeoPackage.name(),
+                &WriteOptions::default(),
+            )
+            .unwrap();
+        }
+        serde_yaml::from_str(&format!(
+            r#"
+proposal_geofile_path: {data_dir}/proposal.gpkg
+ground_truth:
+  !Geofile
+    filepath: {data_dir}/ground_truth.gpkg
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: {data_dir}
+"#,
+            data_dir = data_dir.display()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run_records_stage_metrics() {
+        let data_dir = testdir!();
+        let config = write_parallel_line_inputs(&data_dir);
+
+        let stages = run(&config, &CancellationToken::new(), false).unwrap();
+
+        let stage_names: Vec<&str> = stages.iter().map(|metrics| metrics.stage).collect();
+        assert_eq!(
+            vec![
+                "load_ground_truth",
+                "load_proposal",
+                "project",
+                "normalize_edge_orientation",
+                "sample_proposal",
+                "sample_ground_truth",
+                "build_lookup_tree",
+                "lookup_within_hole_radius",
+                "match",
+                "write_output",
+                "write_ground_truth_geojson",
+            ],
+            stage_names
+        );
+        assert!(stages.iter().all(|metrics| metrics.elapsed_seconds > 0.0));
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        assert_eq!(1.0, result["f1_score_result"]["recall"]);
+        assert_eq!(stages.len(), result["stages"].as_array().unwrap().len());
+        assert_eq!(
+            serde_json::to_value(&config.topo_params).unwrap(),
+            result["run_info"]["topo_params"]
+        );
+        assert_eq!(
+            vec!["proposal", "ground truth"],
+            result["run_info"]["inputs"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|input| input["role"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        // The inputs are clean.
+        assert_eq!(serde_json::json!([]), result["warnings"]);
+    }
+
+    #[test]
+    fn test_run_writes_ground_truth_geojson() {
+        let data_dir = testdir!();
+        let config = write_parallel_line_inputs(&data_dir);
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let lines = topo_rust::geofile::geojson::read_lines_from_geojson(
+            &data_dir.join("ground_truth.geojson"),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![geo::LineString::from(vec![
+                (390000.0, 3950000.0),
+                (390100.0, 3950000.0)
+            ])],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_run_writes_ground_truth_geojson_from_cache() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        config.ground_truth_cache = Some(data_dir.join("ground_truth_cache.bin"));
+        let geojson_filepath = data_dir.join("ground_truth.geojson");
+        run(&config, &CancellationToken::new(), false).unwrap();
+        std::fs::remove_file(&geojson_filepath).unwrap();
+
+        let stages = run(&config, &CancellationToken::new(), false).unwrap();
+
+        assert!(stages
+            .iter()
+            .all(|metrics| metrics.stage != "load_ground_truth"));
+        let lines =
+            topo_rust::geofile::geojson::read_lines_from_geojson(&geojson_filepath).unwrap();
+        assert_eq!(
+            vec![geo::LineString::from(vec![
+                (390000.0, 3950000.0),
+                (390100.0, 3950000.0)
+            ])],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_run_writes_compressed_ground_truth_geojson() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        config.ground_truth_geojson.compress = true;
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        assert!(!data_dir.join("ground_truth.geojson").exists());
+        let lines = topo_rust::geofile::geojson::read_lines_from_geojson(
+            &data_dir.join("ground_truth.geojson.gz"),
+        )
+        .unwrap();
+        assert_eq!(1, lines.len());
+    }
+
+    #[test]
+    fn test_run_writes_match_distance_histograms() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        config.outputs.debug_outputs = true;
+        config.outputs.match_distance_bin_width = Some(2.0);
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        // The proposal is 1 m from the ground truth, so all matches are in the first bin of [0, 6].
+        let csv_contents =
+            std::fs::read_to_string(data_dir.join("match_distance_histogram.csv")).unwrap();
+        let rows: Vec<&str> = csv_contents.lines().collect();
+        assert_eq!(4, rows.len());
+        assert_eq!("bin_start,count", rows[0]);
+        assert!(!rows[1].ends_with(",0"));
+        assert_eq!(vec!["2.0,0", "4.0,0"], rows[2..]);
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        let coarse_counts: Vec<u64> = result["match_distance_histogram"]["counts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|count| count.as_u64().unwrap())
+            .collect();
+        // With 0.6 m bins, the distance of 1 m is in the second bin.
+        assert_eq!(10, coarse_counts.len());
+        assert_eq!(coarse_counts.iter().sum::<u64>(), coarse_counts[1]);
+        assert!(coarse_counts[1] > 0);
+    }
+
+    #[test]
+    fn test_run_writes_missing_coverage() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        // The proposal is 1 m from the ground truth, outside the hole radius, so the whole road is missed.
+        config.topo_params.resampling_distance = 1.0;
+        config.topo_params.hole_radius = 0.9;
+        config.missing_coverage = Some(MissingCoverageConfig::default());
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        let clusters = result["missing_coverage"].as_array().unwrap();
+        assert_eq!(1, clusters.len());
+        assert_eq!(1, clusters[0]["rank"]);
+        assert_eq!(101, clusters[0]["node_count"]);
+        assert_eq!(101.0, clusters[0]["missing_length"]);
+        let (features, _) =
+            read_features_from_geofile(&data_dir.join("missing_coverage.gpkg")).unwrap();
+        assert_eq!(1, features.len());
+    }
+
+    #[test]
+    fn test_run_writes_approximate_scores() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        // The 100 m line spans three 50 m tiles, which are all sampled.
+        config.approximate = Some(ApproximateConfig {
+            fraction: 1.0,
+            seed: 7,
+            tile_size: 50.0,
+            bootstrap_samples: 10,
+        });
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        let approximate = &result["approximate"];
+        assert_eq!(3, approximate["tile_count"]);
+        assert_eq!(3, approximate["sampled_tile_count"]);
+        assert_eq!(7, approximate["seed"]);
+        assert_eq!(1.0, approximate["f1_score_result"]["f1_score"]);
+        assert_eq!(
+            serde_json::json!([1.0, 1.0]),
+            approximate["f1_score_interval"]
+        );
+        assert_eq!(1.0, result["f1_score_result"]["f1_score"]);
+    }
+
+    #[test]
+    fn test_run_writes_edge_trace() {
+        let data_dir = testdir!();
+        let mut config = write_parallel_line_inputs(&data_dir);
+        config.trace_edge = Some(TraceEdgeConfig {
+            edge: EdgeSelector::Index(0),
+            nearest_candidates: 3,
+        });
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let trace: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(data_dir.join("trace_edge.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(0, trace["edge_idx"]);
+        let nodes = trace["nodes"].as_array().unwrap();
+        assert!(!nodes.is_empty());
+        for node in nodes {
+            // Each node is matched to the ground truth node 1 m away, the next ones are outside the hole radius.
+            let candidates = node["candidates"].as_array().unwrap();
+            assert_eq!(true, node["matched"]);
+            assert_eq!(true, candidates[0]["chosen"]);
+            assert!(candidates[1..]
+                .iter()
+                .all(|candidate| candidate["rejection"] == "out_of_radius"));
+        }
+        let (features, _) =
+            read_features_from_geofile(&data_dir.join("trace_edge.geojson")).unwrap();
+        assert_eq!(nodes.len() * 3, features.len());
+    }
+
+    #[test]
+    fn test_run_fails_if_ground_truth_geojson_cannot_be_written() {
+        let data_dir = testdir!();
+        let config = write_parallel_line_inputs(&data_dir);
+        // Writing the file fails, as a directory is in its place.
+        std::fs::create_dir(data_dir.join("ground_truth.geojson")).unwrap();
+
+        let error = run(&config, &CancellationToken::new(), false).unwrap_err();
+
+        assert!(
+            format!("{:#}", error).contains("ground_truth.geojson"),
+            "{:#}",
+            error
+        );
+        assert!(!data_dir.join("result.json").exists());
+    }
+
+    #[test]
+    fn test_run_named_ground_truths_and_union() {
+        let data_dir = testdir!();
+        write_parallel_line_inputs(&data_dir);
+        // A second ground truth, which only covers the first half of the proposal.
+        write_features_to_geofile(
+            &vec![Feature {
+                geometry: geo::Geometry::LineString(
+                    vec![(390000.0, 3950000.0), (390050.0, 3950000.0)].into(),
+                ),
+                attributes: None,
+                null_fields: Default::default(),
+            }],
+            &data_dir.join("ground_truth_b.gpkg"),
+            Some(&Crs::Epsg(32654)),
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+proposal_geofile_path: {data_dir}/proposal.gpkg
+ground_truth:
+  - name: a
+    source:
+      !Geofile
+        filepath: {data_dir}/ground_truth.gpkg
+  - name: b
+    source:
+      !Geofile
+        filepath: {data_dir}/ground_truth_b.gpkg
+ground_truth_union_snap_tolerance: 1.0
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: {data_dir}
+"#,
+            data_dir = data_dir.display()
+        ))
+        .unwrap();
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        let ground_truths = result["ground_truths"].as_object().unwrap();
+        assert_eq!(
+            vec!["a", "b", "union"],
+            ground_truths.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(1.0, ground_truths["a"]["f1_score_result"]["recall"]);
+        assert_eq!(1.0, ground_truths["b"]["f1_score_result"]["recall"]);
+        assert!(
+            ground_truths["b"]["f1_score_result"]["precision"]
+                .as_f64()
+                .unwrap()
+                < 1.0
+        );
+        assert!(ground_truths["union"]["f1_score_result"]["f1_score"].is_number());
+        for filename in [
+            "ground_truth_a.geojson",
+            "ground_truth_b.geojson",
+            "proposal_nodes_a.gpkg",
+            "ground_truth_nodes_b.gpkg",
+            "proposal_nodes_union.gpkg",
+        ] {
+            assert!(data_dir.join(filename).exists(), "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_validate_ground_truth_names_fails() {
+        for ground_truth in [
+            "ground_truth: []",
+            "ground_truth: [{name: a, source: !Geofile {filepath: a.gpkg}}, {name: a, source: !Geofile {filepath: b.gpkg}}]",
+            "ground_truth: [{name: a/b, source: !Geofile {filepath: a.gpkg}}]",
+            "ground_truth: [{name: union, source: !Geofile {filepath: a.gpkg}}]\nground_truth_union_snap_tolerance: 1.0",
+            "ground_truth: [{name: a, source: !Geofile {filepath: a.gpkg}}]\nground_truth_cache: cache.bin",
+        ] {
+            let config: Config =
+                serde_yaml::from_str(&format!("{}\ndata_dir: .\n", ground_truth)).unwrap();
+
+            assert!(
+                validate_ground_truth_names(&config).is_err(),
+                "{}",
+                ground_truth
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_check_writes_nothing() {
+        let data_dir = testdir!();
+        let config = write_parallel_line_inputs(&data_dir);
+
+        let stages = run(&config, &CancellationToken::new(), true).unwrap();
+
+        assert!(!stages.is_empty());
+        let mut filenames: Vec<String> = std::fs::read_dir(&data_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        filenames.sort();
+        assert_eq!(vec!["ground_truth.gpkg", "proposal.gpkg"], filenames);
+    }
+
+    #[test]
+    fn test_compare_scores() {
+        let data_dir = testdir!();
+        let baseline_filepath = data_dir.join("baseline.json");
+        std::fs::write(
+            &baseline_filepath,
+            r#"{"f1_score_result": {"precision": 0.9, "recall": 0.8, "f1_score": 0.85}, "stages": []}"#,
+        )
+        .unwrap();
+        let candidate_filepath = data_dir.join("candidate.json");
+        std::fs::write(
+            &candidate_filepath,
+            r#"{"precision": 0.9, "recall": 0.75, "f1_score": 0.82}"#,
+        )
+        .unwrap();
+
+        assert!(compare_scores(&baseline_filepath, &candidate_filepath, 0.05).is_ok());
+        assert!(compare_scores(&baseline_filepath, &candidate_filepath, 0.01).is_err());
+        assert!(compare_scores(&candidate_filepath, &baseline_filepath, 0.0).is_ok());
+    }
+
+    #[test]
+    fn test_run_writes_unmatched_nodes_only() {
+        let data_dir = testdir!();
+        let crs = Crs::Epsg(32654);
+        let line_feature = |start_x: f64, end_x: f64, y: f64| Feature {
+            geometry: geo::Geometry::LineString(vec![(start_x, y), (end_x, y)].into()),
+            attributes: None,
+            null_fields: Default::default(),
+        };
+        // Ground truth nodes at x = 0, 11, ..., 99 and 100. The proposal matches the first 5 of them with its first
+        // line, and its second line with 3 nodes is far away.
+        for (filename, features) in [
+            (
+                "ground_truth.gpkg",
+                vec![line_feature(390000.0, 390100.0, 3950000.0)],
+            ),
+            (
+                "proposal.gpkg",
+                vec![
+                    line_feature(390000.0, 390044.0, 3950001.0),
+                    line_feature(390000.0, 390022.0, 3950100.0),
+                ],
+            ),
+        ] {
+            write_features_to_geofile(
+                &features,
+                &data_dir.join(filename),
+                Some(&crs),
+                GdalDriverType::GeoPackage.name(),
+                &WriteOptions::default(),
+            )
+            .unwrap();
+        }
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+proposal_geofile_path: {data_dir}/proposal.gpkg
+ground_truth:
+  !Geofile
+    filepath: {data_dir}/ground_truth.gpkg
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: {data_dir}
+outputs:
+  node_detail: unmatched_only
+"#,
+            data_dir = data_dir.display()
+        ))
+        .unwrap();
+
+        run(&config, &CancellationToken::new(), false).unwrap();
+
+        let (proposal_nodes, _) =
+            read_features_from_geofile(&data_dir.join("proposal_nodes_unmatched.gpkg")).unwrap();
+        assert_eq!(3, proposal_nodes.len());
+        let (ground_truth_nodes, _) =
+            read_features_from_geofile(&data_dir.join("ground_truth_nodes_unmatched.gpkg"))
+                .unwrap();
+        assert_eq!(6, ground_truth_nodes.len());
+        assert!(!data_dir.join("proposal_nodes.gpkg").exists());
+        // The scores still count all nodes.
+        let result: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+                .unwrap();
+        assert_eq!(5.0 / 8.0, result["f1_score_result"]["precision"]);
+        assert_eq!(5.0 / 11.0, result["f1_score_result"]["recall"]);
+    }
+
+    #[test]
+    fn test_run_name_overwrite_protection() {
+        let data_dir = testdir!();
+        let crs = Crs::Epsg(32654);
+        for (filename, y) in [
+            ("ground_truth.gpkg", 3950000.0),
+            ("proposal.gpkg", 3950001.0),
+        ] {
+            let features = vec![Feature {
+                geometry: geo::Geometry::LineString(vec![(390000.0, y), (390100.0, y)].into()),
+                attributes: None,
+                null_fields: Default::default(),
+            }];
+            write_features_to_geofile(
+                &features,
+                &data_dir.join(filename),
+                Some(&crs),
+                GdalDriverType::GeoPackage.name(),
+                &WriteOptions::default(),
+            )
+            .unwrap();
+        }
+        let config = |overwrite: bool| -> Config {
+            serde_yaml::from_str(&format!(
+                r#"
+proposal_geofile_path: {data_dir}/proposal.gpkg
+ground_truth:
+  !Geofile
+    filepath: {data_dir}/ground_truth.gpkg
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: {data_dir}
+run_name: baseline
+overwrite: {overwrite}
+"#,
+                data_dir = data_dir.display()
+            ))
+            .unwrap()
+        };
+
+        run(&config(false), &CancellationToken::new(), false).unwrap();
+
+        let run_dir = data_dir.join("runs").join("baseline");
+        assert!(run_dir.join("result.json").exists());
+        assert!(run_dir.join("effective_config.yaml").exists());
+        assert!(run_dir.join("proposal_nodes.gpkg").exists());
+        assert!(!data_dir.join("result.json").exists());
+        let error = run(&config(false), &CancellationToken::new(), false).unwrap_err();
+        assert!(error.to_string().contains("overwrite"), "{}", error);
+        run(&config(true), &CancellationToken::new(), false).unwrap();
+    }
+}
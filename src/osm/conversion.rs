@@ -1,26 +1,693 @@
 extern crate osm_xml as osm;
-use anyhow::anyhow;
-use std::{borrow::Borrow, path::Path};
+use crate::diagnostics::warnings::{warn, WarningCategory, WarningCollector};
+use crate::error::{Error, Result, ResultExt};
+use crate::geofile::feature::{FeatureMap, FieldValue};
+use crate::osm::download::WgsBoundingBox;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
 
-pub fn read_osm_roads_from_file(filepath: &Path) -> anyhow::Result<Vec<geo::LineString>> {
-    let infile = std::fs::File::open(filepath)?;
-    let data = osm::OSM::parse(infile)?;
-    data.ways
+/// The magic bytes at the start of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// The magic bytes at the start of a bzip2 stream.
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+/// Tags which must be identical for two ways to be merged into one linestring.
+const MERGE_RELEVANT_TAGS: [&str; 5] = ["highway", "name", "ref", "oneway", "lanes"];
+
+/// A road way converted to coordinates, with the IDs of its nodes and the tags relevant for merging.
+struct RoadWay {
+    node_ids: Vec<osm::Id>,
+    coords: Vec<geo::Coord>,
+    merge_tags: Vec<(String, String)>,
+}
+
+/// Open an OSM XML file for reading, decompressing it if it is gzip or bzip2 compressed, e.g. a `.osm.gz` or
+/// `.osm.bz2` file. The compression is detected from the magic bytes at the start of the file.
+fn open_osm_file(filepath: &Path) -> Result<Box<dyn Read>> {
+    let mut reader =
+        BufReader::new(File::open(filepath).with_context(|| format!("Opening {:?}", filepath))?);
+    let header = reader
+        .fill_buf()
+        .with_context(|| format!("Reading {:?}", filepath))?;
+    Ok(if header.starts_with(GZIP_MAGIC) {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else if header.starts_with(BZIP2_MAGIC) {
+        Box::new(bzip2::read::MultiBzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Read the roads of an OSM XML file as linestrings.
+///
+/// # Arguments
+/// * filepath - The OSM XML file, optionally gzip or bzip2 compressed.
+/// * merge_ways - Whether to merge ways which share an endpoint and have identical relevant tags into longer
+///   linestrings, see `merge_road_ways`.
+/// * highway_classes - The values of the highway tag to keep, e.g. "primary". If empty, all highways are kept.
+/// * warnings - Records the dropped ways, which are only logged if `None`.
+pub fn read_osm_roads_from_file(
+    filepath: &Path,
+    merge_ways: bool,
+    highway_classes: &[String],
+    warnings: Option<&WarningCollector>,
+) -> Result<Vec<geo::LineString>> {
+    let data = osm::OSM::parse(open_osm_file(filepath)?)
+        .with_context(|| format!("Reading OSM data from {:?}", filepath))?;
+    let mut ways: Vec<&osm::Way> = data
+        .ways
         .borrow()
+        .values()
+        .filter(|way| {
+            way.tags.iter().any(|tag| {
+                tag.key == "highway"
+                    && (highway_classes.is_empty() || highway_classes.contains(&tag.val))
+            })
+        })
+        .collect();
+    // Sort the ways to make the output independent of the hash map order.
+    ways.sort_by_key(|way| way.id);
+
+    let mut road_ways = Vec::new();
+    let mut collapsed_way_count = 0;
+    for way in ways {
+        let road_way = osm_way_to_road_way(&data, way)?;
+        if road_way.coords.len() < 2 {
+            collapsed_way_count += 1;
+        } else {
+            road_ways.push(road_way);
+        }
+    }
+    if collapsed_way_count > 0 {
+        warn(
+            warnings,
+            WarningCategory::DegenerateGeometry,
+            collapsed_way_count,
+            format!(
+                "Dropped {} ways with less than two distinct coordinates",
+                collapsed_way_count
+            ),
+        );
+    }
+
+    if merge_ways {
+        let way_count = road_ways.len();
+        road_ways = merge_road_ways(road_ways);
+        log::info!("Merged {} ways into {}", way_count, road_ways.len());
+    }
+    Ok(road_ways
         .into_iter()
-        .filter(|(_, way)| way.tags.iter().any(|tag| tag.key == "highway"))
-        .map(|(_, way)| osm_way_to_linestring(&data, &way))
-        .collect()
+        .map(|road_way| road_way.coords.into())
+        .collect())
 }
 
-fn osm_way_to_linestring(osm_data: &osm::OSM, way: &osm::Way) -> anyhow::Result<geo::LineString> {
-    let mut points: Vec<geo::Point> = Vec::new();
+/// Read the bounding box declared by the `bounds` element of an OSM XML file, if it has one.
+pub fn read_osm_bounds(filepath: &Path) -> Result<Option<WgsBoundingBox>> {
+    let data = osm::OSM::parse(open_osm_file(filepath)?)
+        .with_context(|| format!("Reading OSM data from {:?}", filepath))?;
+    Ok(data.bounds.map(|bounds| WgsBoundingBox {
+        left_lon: bounds.minlon,
+        right_lon: bounds.maxlon,
+        bottom_lat: bounds.minlat,
+        top_lat: bounds.maxlat,
+    }))
+}
+
+/// Read the remark of an OSM XML file which has a `remark` element but no nodes, ways or relations. The Overpass API
+/// responds like this with status 200 when a query fails at runtime, e.g. "runtime error: Query timed out".
+///
+/// # Returns
+/// The trimmed text of the remark, `None` if the file has an element or no remark.
+pub fn read_overpass_error_remark(filepath: &Path) -> Result<Option<String>> {
+    let mut text = String::new();
+    for line in BufReader::new(open_osm_file(filepath)?).lines() {
+        let line = line.with_context(|| format!("Reading {:?}", filepath))?;
+        if ["<node", "<way", "<relation"]
+            .iter()
+            .any(|element| line.contains(element))
+        {
+            return Ok(None);
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    Ok(text.split_once("<remark>").and_then(|(_, rest)| {
+        rest.split_once("</remark>")
+            .map(|(remark, _)| remark.trim().to_string())
+    }))
+}
+
+/// Resolve the nodes of a way, skipping nodes which repeat the coordinate of the previous node.
+fn osm_way_to_road_way(osm_data: &osm::OSM, way: &osm::Way) -> Result<RoadWay> {
+    let mut node_ids = Vec::new();
+    let mut coords: Vec<geo::Coord> = Vec::new();
     for node in &way.nodes {
-        if let osm::Reference::Node(node) = osm_data.resolve_reference(&node) {
-            points.push(geo::Point::new(node.lon, node.lat));
+        if let osm::Reference::Node(node) = osm_data.resolve_reference(node) {
+            let coord = geo::Coord::from((node.lon, node.lat));
+            if coords.last() != Some(&coord) {
+                node_ids.push(node.id);
+                coords.push(coord);
+            }
         } else {
-            return Err(anyhow!("Expected a node"));
+            return Err(Error::OsmParseError {
+                message: "Expected a node".to_string(),
+                source: None,
+            });
         }
     }
-    Ok(points.into_iter().collect())
+    let mut merge_tags: Vec<(String, String)> = way
+        .tags
+        .iter()
+        .filter(|tag| MERGE_RELEVANT_TAGS.contains(&tag.key.as_str()))
+        .map(|tag| (tag.key.clone(), tag.val.clone()))
+        .collect();
+    merge_tags.sort();
+    Ok(RoadWay {
+        node_ids,
+        coords,
+        merge_tags,
+    })
+}
+
+/// Merge chains of ways into single ways. Two ways are merged at a node if the node is an endpoint of both, is not
+/// referenced by any other way, and the two ways have identical relevant tags. Junctions are thus preserved.
+fn merge_road_ways(road_ways: Vec<RoadWay>) -> Vec<RoadWay> {
+    let mut node_reference_counts: HashMap<osm::Id, usize> = HashMap::new();
+    let mut ways_by_endpoint: HashMap<osm::Id, Vec<usize>> = HashMap::new();
+    for (way_idx, road_way) in road_ways.iter().enumerate() {
+        for node_id in &road_way.node_ids {
+            *node_reference_counts.entry(*node_id).or_default() += 1;
+        }
+        ways_by_endpoint
+            .entry(road_way.node_ids[0])
+            .or_default()
+            .push(way_idx);
+        ways_by_endpoint
+            .entry(*road_way.node_ids.last().unwrap())
+            .or_default()
+            .push(way_idx);
+    }
+    // Find the way to continue with at the endpoint of a way, if any.
+    let next_way = |way_idx: usize, node_id: osm::Id, merged: &[bool]| -> Option<usize> {
+        if node_reference_counts[&node_id] != 2 {
+            return None;
+        }
+        let endpoint_ways = &ways_by_endpoint[&node_id];
+        if endpoint_ways.len() != 2 {
+            return None;
+        }
+        let other_way_idx = if endpoint_ways[0] == way_idx {
+            endpoint_ways[1]
+        } else {
+            endpoint_ways[0]
+        };
+        if other_way_idx == way_idx
+            || merged[other_way_idx]
+            || road_ways[other_way_idx].merge_tags != road_ways[way_idx].merge_tags
+        {
+            return None;
+        }
+        Some(other_way_idx)
+    };
+
+    let mut merged = vec![false; road_ways.len()];
+    let mut merged_ways = Vec::new();
+    for way_idx in 0..road_ways.len() {
+        if merged[way_idx] {
+            continue;
+        }
+        merged[way_idx] = true;
+        let mut node_ids = road_ways[way_idx].node_ids.clone();
+        let mut coords = road_ways[way_idx].coords.clone();
+        // Extend the chain forward from the end, then reverse it and extend forward from the original start.
+        for _ in 0..2 {
+            let mut last_way_idx = way_idx;
+            while let Some(other_way_idx) =
+                next_way(last_way_idx, *node_ids.last().unwrap(), &merged)
+            {
+                merged[other_way_idx] = true;
+                let other_way = &road_ways[other_way_idx];
+                let mut other_node_ids = other_way.node_ids.clone();
+                let mut other_coords = other_way.coords.clone();
+                if other_node_ids[0] != *node_ids.last().unwrap() {
+                    other_node_ids.reverse();
+                    other_coords.reverse();
+                }
+                node_ids.extend(other_node_ids.into_iter().skip(1));
+                coords.extend(other_coords.into_iter().skip(1));
+                last_way_idx = other_way_idx;
+            }
+            node_ids.reverse();
+            coords.reverse();
+        }
+        merged_ways.push(RoadWay {
+            node_ids,
+            coords,
+            merge_tags: road_ways[way_idx].merge_tags.clone(),
+        });
+    }
+    merged_ways
+}
+
+/// Kilometers per hour in one mile per hour.
+const KMH_PER_MPH: f64 = 1.609344;
+/// Kilometers per hour in one knot.
+const KMH_PER_KNOT: f64 = 1.852;
+/// Meters in one foot.
+const METERS_PER_FOOT: f64 = 0.3048;
+/// Meters in one inch.
+const METERS_PER_INCH: f64 = 0.0254;
+/// Meters in one mile.
+const METERS_PER_MILE: f64 = 1609.344;
+
+/// The attribute type an OSM tag value is converted to by `TagConverter`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagValueType {
+    /// The value is kept as it is.
+    String,
+    /// An integer, e.g. `lanes`.
+    Integer,
+    /// A real number without a unit.
+    Real,
+    /// A speed converted to km/h, e.g. `maxspeed`. Values without a unit are in km/h, see `parse_speed_kmh`.
+    SpeedKmh,
+    /// A length converted to meters, e.g. `width`. Values without a unit are in meters, see `parse_length_meters`.
+    LengthMeters,
+}
+
+/// The default types of the numeric tags of roads. Tags which are not listed are kept as strings.
+pub fn default_tag_value_types() -> HashMap<String, TagValueType> {
+    [
+        ("lanes", TagValueType::Integer),
+        ("lanes:forward", TagValueType::Integer),
+        ("lanes:backward", TagValueType::Integer),
+        ("layer", TagValueType::Integer),
+        ("maxspeed", TagValueType::SpeedKmh),
+        ("maxspeed:forward", TagValueType::SpeedKmh),
+        ("maxspeed:backward", TagValueType::SpeedKmh),
+        ("width", TagValueType::LengthMeters),
+        ("est_width", TagValueType::LengthMeters),
+        ("maxwidth", TagValueType::LengthMeters),
+        ("maxheight", TagValueType::LengthMeters),
+    ]
+    .into_iter()
+    .map(|(key, value_type)| (key.to_string(), value_type))
+    .collect()
+}
+
+/// Split a value into its number and its unit, e.g. "50 mph" into 50 and "mph". The unit may follow the number
+/// without a space. Returns `None` if the value does not start with a finite number.
+fn split_number_and_unit(value: &str) -> Option<(f64, &str)> {
+    let value = value.trim();
+    let number_len = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(value.len());
+    let number: f64 = value[..number_len].parse().ok()?;
+    number
+        .is_finite()
+        .then(|| (number, value[number_len..].trim()))
+}
+
+/// Parse an integer tag value, e.g. `lanes=2`. Returns `None` for anything else, including "2.5" and "2 lanes".
+pub fn parse_integer(value: &str) -> Option<i32> {
+    value.trim().parse().ok()
+}
+
+/// Parse a real tag value without a unit. Returns `None` for anything else, including "NaN" and "inf".
+pub fn parse_real(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Parse a speed tag value, e.g. `maxspeed`, to km/h. Values without a unit are in km/h, like in OSM, and "mph" and
+/// "knots" are converted. Returns `None` for symbolic values like "none", "walk" or "DE:urban".
+pub fn parse_speed_kmh(value: &str) -> Option<f64> {
+    let (number, unit) = split_number_and_unit(value)?;
+    match unit {
+        "" | "km/h" | "kmh" | "kph" => Some(number),
+        "mph" => Some(number * KMH_PER_MPH),
+        "knots" => Some(number * KMH_PER_KNOT),
+        _ => None,
+    }
+}
+
+/// Parse a length tag value, e.g. `width`, to meters. Values without a unit are in meters, like in OSM, and "km",
+/// "mi", "ft" and feet and inches like `12'6"` are converted. Returns `None` for anything else, e.g. "3,5" or
+/// "narrow".
+pub fn parse_length_meters(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if let Some((feet, inches)) = value.split_once('\'') {
+        let feet = parse_integer(feet)?;
+        let inches = match inches.trim() {
+            "" => 0.0,
+            inches => parse_real(inches.strip_suffix('"')?)?,
+        };
+        return Some(feet as f64 * METERS_PER_FOOT + inches * METERS_PER_INCH);
+    }
+    let (number, unit) = split_number_and_unit(value)?;
+    match unit {
+        "" | "m" => Some(number),
+        "km" => Some(number * 1000.0),
+        "mi" => Some(number * METERS_PER_MILE),
+        "ft" => Some(number * METERS_PER_FOOT),
+        _ => None,
+    }
+}
+
+/// Convert a tag value to an attribute of the given type. Multiple values separated by ";", e.g. "30; 40", are
+/// converted to a list attribute.
+///
+/// # Returns
+/// The attribute, or `None` if the value or one of the multiple values cannot be parsed.
+pub fn convert_tag_value(value: &str, value_type: TagValueType) -> Option<FieldValue> {
+    fn convert_values<T, F: Fn(&str) -> Option<T>>(value: &str, parse: F) -> Option<Vec<T>> {
+        value.split(';').map(parse).collect()
+    }
+    let parse_real_with_type = match value_type {
+        TagValueType::String => return Some(FieldValue::StringValue(value.to_string())),
+        TagValueType::Integer => {
+            let mut values = convert_values(value, parse_integer)?;
+            return Some(if values.len() == 1 {
+                FieldValue::IntegerValue(values.remove(0))
+            } else {
+                FieldValue::IntegerListValue(values)
+            });
+        }
+        TagValueType::Real => parse_real,
+        TagValueType::SpeedKmh => parse_speed_kmh,
+        TagValueType::LengthMeters => parse_length_meters,
+    };
+    let mut values = convert_values(value, parse_real_with_type)?;
+    Some(if values.len() == 1 {
+        FieldValue::RealValue(values.remove(0))
+    } else {
+        FieldValue::RealListValue(values)
+    })
+}
+
+/// Converts OSM tags to attributes with the types of a configurable mapping from tag keys. Values which cannot be
+/// parsed are kept as strings and counted per key, see `log_failures`.
+#[derive(Debug, Clone)]
+pub struct TagConverter {
+    value_types: HashMap<String, TagValueType>,
+    failure_counts: BTreeMap<String, usize>,
+}
+
+impl Default for TagConverter {
+    fn default() -> Self {
+        Self::new(default_tag_value_types())
+    }
+}
+
+impl TagConverter {
+    /// # Arguments
+    /// * value_types - The type of each tag key. Tags which are not listed are kept as strings.
+    pub fn new(value_types: HashMap<String, TagValueType>) -> Self {
+        Self {
+            value_types,
+            failure_counts: BTreeMap::new(),
+        }
+    }
+
+    /// Convert one tag to an attribute, falling back to a string attribute if its value cannot be parsed.
+    pub fn convert(&mut self, key: &str, value: &str) -> FieldValue {
+        let value_type = self
+            .value_types
+            .get(key)
+            .copied()
+            .unwrap_or(TagValueType::String);
+        convert_tag_value(value, value_type).unwrap_or_else(|| {
+            *self.failure_counts.entry(key.to_string()).or_default() += 1;
+            FieldValue::StringValue(value.to_string())
+        })
+    }
+
+    /// Convert the tags of one OSM element to attributes, see `convert`.
+    pub fn convert_tags<'a>(
+        &mut self,
+        tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> FeatureMap {
+        tags.into_iter()
+            .map(|(key, value)| (key.to_string(), self.convert(key, value)))
+            .collect()
+    }
+
+    /// The number of values which could not be parsed and were kept as strings, by tag key.
+    pub fn failure_counts(&self) -> &BTreeMap<String, usize> {
+        &self.failure_counts
+    }
+
+    /// Log a warning with the number of values of each tag key which could not be parsed.
+    pub fn log_failures(&self) {
+        for (key, count) in &self.failure_counts {
+            log::warn!(
+                "{} values of the OSM tag '{}' could not be parsed as {:?} and were kept as strings",
+                count,
+                key,
+                self.value_types[key]
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write, path::Path};
+
+    use crate::geofile::feature::FieldValue;
+    use rstest::rstest;
+    use testdir::testdir;
+
+    use super::{
+        convert_tag_value, parse_integer, parse_length_meters, parse_speed_kmh,
+        read_osm_roads_from_file, read_overpass_error_remark, TagConverter, TagValueType,
+    };
+
+    const TEST_OSM_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="35.0" lon="139.0"/>
+  <node id="2" lat="35.0" lon="139.001"/>
+  <node id="3" lat="35.0" lon="139.002"/>
+  <node id="4" lat="35.001" lon="139.002"/>
+  <node id="5" lat="35.001" lon="139.003"/>
+  <node id="6" lat="35.002" lon="139.003"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="First Street"/>
+  </way>
+  <way id="11">
+    <nd ref="3"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="First Street"/>
+    <tag k="surface" v="asphalt"/>
+  </way>
+  <way id="12">
+    <nd ref="3"/>
+    <nd ref="4"/>
+    <tag k="highway" v="primary"/>
+  </way>
+  <way id="13">
+    <nd ref="5"/>
+    <nd ref="5"/>
+    <tag k="highway" v="service"/>
+  </way>
+  <way id="14">
+    <nd ref="5"/>
+    <nd ref="6"/>
+    <tag k="building" v="yes"/>
+  </way>
+</osm>
+"#;
+
+    /// Write `contents` to `filepath`, gzip or bzip2 compressed if its extension is "gz" or "bz2".
+    fn write_osm_file(filepath: &Path, contents: &str) {
+        let file = File::create(filepath).unwrap();
+        match filepath
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("gz") => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                encoder.write_all(contents.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
+            Some("bz2") => {
+                let mut encoder = bzip2::write::BzEncoder::new(file, bzip2::Compression::default());
+                encoder.write_all(contents.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
+            _ => {
+                let mut file = file;
+                file.write_all(contents.as_bytes()).unwrap();
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(false, vec![], vec![
+        vec![(139.0, 35.0), (139.001, 35.0)],
+        vec![(139.002, 35.0), (139.001, 35.0)],
+        vec![(139.002, 35.0), (139.002, 35.001)],
+    ])]
+    #[case(true, vec![], vec![
+        vec![(139.0, 35.0), (139.001, 35.0), (139.002, 35.0)],
+        vec![(139.002, 35.0), (139.002, 35.001)],
+    ])]
+    #[case(false, vec!["primary".to_string()], vec![
+        vec![(139.002, 35.0), (139.002, 35.001)],
+    ])]
+    fn test_read_osm_roads_from_file(
+        #[case] merge_ways: bool,
+        #[case] highway_classes: Vec<String>,
+        #[case] expected_lines: Vec<Vec<(f64, f64)>>,
+        #[values("roads.osm", "roads.osm.gz", "roads.osm.bz2")] filename: &str,
+    ) {
+        let filepath = testdir!().join(filename);
+        write_osm_file(&filepath, TEST_OSM_XML);
+
+        let lines =
+            read_osm_roads_from_file(&filepath, merge_ways, &highway_classes, None).unwrap();
+
+        let expected_lines: Vec<geo::LineString> =
+            expected_lines.into_iter().map(|line| line.into()).collect();
+        assert_eq!(expected_lines, lines);
+    }
+
+    #[rstest]
+    #[case("roads.osm.gz")]
+    #[case("roads.osm.bz2")]
+    fn test_read_truncated_compressed_osm_file(#[case] filename: &str) {
+        let filepath = testdir!().join(filename);
+        write_osm_file(&filepath, TEST_OSM_XML);
+        let contents = std::fs::read(&filepath).unwrap();
+        std::fs::write(&filepath, &contents[..contents.len() / 2]).unwrap();
+
+        let error = read_osm_roads_from_file(&filepath, false, &[], None).unwrap_err();
+
+        assert!(
+            error.display_chain().contains(filename),
+            "{}",
+            error.display_chain()
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<osm version=\"0.6\">\n<remark> runtime error: Query timed out in \"query\" at line 1 after 26 seconds. </remark>\n</osm>\n",
+        Some("runtime error: Query timed out in \"query\" at line 1 after 26 seconds.")
+    )]
+    #[case(TEST_OSM_XML, None)]
+    #[case("<osm version=\"0.6\">\n</osm>\n", None)]
+    fn test_read_overpass_error_remark(#[case] contents: &str, #[case] expected: Option<&str>) {
+        let filepath = testdir!().join("osm.xml");
+        std::fs::write(&filepath, contents).unwrap();
+
+        let remark = read_overpass_error_remark(&filepath).unwrap();
+
+        assert_eq!(expected.map(str::to_string), remark);
+    }
+
+    #[rstest]
+    #[case("50", Some(50.0))]
+    #[case(" 50 km/h", Some(50.0))]
+    #[case("30 mph", Some(48.28032))]
+    #[case("30mph", Some(48.28032))]
+    #[case("10 knots", Some(18.52))]
+    #[case("none", None)]
+    #[case("DE:urban", None)]
+    #[case("50 furlongs", None)]
+    fn test_parse_speed_kmh(#[case] value: &str, #[case] expected: Option<f64>) {
+        let actual = parse_speed_kmh(value);
+        assert_eq!(expected.is_some(), actual.is_some(), "{:?}", actual);
+        if let (Some(expected), Some(actual)) = (expected, actual) {
+            assert!((expected - actual).abs() < 1e-9, "{}", actual);
+        }
+    }
+
+    #[rstest]
+    #[case("2", Some(2))]
+    #[case(" 3 ", Some(3))]
+    #[case("2.5", None)]
+    #[case("two", None)]
+    fn test_parse_integer(#[case] value: &str, #[case] expected: Option<i32>) {
+        assert_eq!(expected, parse_integer(value));
+    }
+
+    #[rstest]
+    #[case("3.5", Some(3.5))]
+    #[case("3.5 m", Some(3.5))]
+    #[case("10 ft", Some(3.048))]
+    #[case("12'6\"", Some(3.81))]
+    #[case("3,5", None)]
+    #[case("narrow", None)]
+    fn test_parse_length_meters(#[case] value: &str, #[case] expected: Option<f64>) {
+        let actual = parse_length_meters(value);
+        assert_eq!(expected.is_some(), actual.is_some(), "{:?}", actual);
+        if let (Some(expected), Some(actual)) = (expected, actual) {
+            assert!((expected - actual).abs() < 1e-9, "{}", actual);
+        }
+    }
+
+    #[rstest]
+    #[case("2", TagValueType::Integer, Some(FieldValue::IntegerValue(2)))]
+    #[case("2;3", TagValueType::Integer, Some(FieldValue::IntegerListValue(vec![2, 3])))]
+    #[case("30; 40", TagValueType::SpeedKmh, Some(FieldValue::RealListValue(vec![30.0, 40.0])))]
+    #[case("30; walk", TagValueType::SpeedKmh, None)]
+    #[case("wide", TagValueType::String, Some(FieldValue::StringValue("wide".to_string())))]
+    fn test_convert_tag_value(
+        #[case] value: &str,
+        #[case] value_type: TagValueType,
+        #[case] expected: Option<FieldValue>,
+    ) {
+        assert_eq!(expected, convert_tag_value(value, value_type));
+    }
+
+    #[rstest]
+    fn test_tag_converter_keeps_malformed_values_as_strings() {
+        let mut converter = TagConverter::default();
+
+        let attributes = converter.convert_tags([
+            ("highway", "primary"),
+            ("lanes", "2"),
+            ("maxspeed", "50 mph"),
+            ("width", "3,5 m"),
+        ]);
+
+        assert_eq!(
+            Some(&FieldValue::StringValue("primary".to_string())),
+            attributes.get("highway")
+        );
+        assert_eq!(Some(&FieldValue::IntegerValue(2)), attributes.get("lanes"));
+        assert_eq!(
+            Some(&FieldValue::RealValue(50.0 * 1.609344)),
+            attributes.get("maxspeed")
+        );
+        assert_eq!(
+            Some(&FieldValue::StringValue("3,5 m".to_string())),
+            attributes.get("width")
+        );
+        assert_eq!(
+            vec![("width".to_string(), 1)],
+            converter
+                .failure_counts()
+                .clone()
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+    }
 }
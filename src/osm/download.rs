@@ -1,13 +1,35 @@
 extern crate osm_xml as osm;
-use anyhow::{anyhow, Ok};
+use crate::crs::crs_utils::meters_to_degrees_at;
+use crate::error::{Error, Result, ResultExt};
+use crate::osm::conversion::{read_osm_bounds, read_overpass_error_remark};
+use flate2::{write::GzEncoder, Compression};
 use geohash::{encode, Coord};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs,
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
-#[derive(Deserialize, Debug)]
+const OVERPASS_API_URL: &str = "https://overpass-api.de/api";
+/// Timeout of `check_overpass_status`.
+const OVERPASS_STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// The user agent identifying the requests to the OSM APIs, as required by their usage policies.
+pub(crate) const USER_AGENT: &str = "osm-geo-mapper";
+
+/// The Overpass API endpoint to download OSM data from.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OsmEndpoint {
+    /// `/api/interpreter` with an Overpass QL query, which only returns highway ways and their nodes.
+    #[default]
+    Interpreter,
+    /// `/api/map`, which returns every element in the bounding box.
+    Map,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct WgsBoundingBox {
     pub left_lon: f64,
     pub right_lon: f64,
@@ -15,7 +37,129 @@ pub struct WgsBoundingBox {
     pub top_lat: f64,
 }
 
-pub fn get_filename_for_bbox(bbox: &WgsBoundingBox) -> anyhow::Result<String> {
+impl WgsBoundingBox {
+    /// A bounding box extending half_width_m meters west and east and half_height_m meters south and north of a
+    /// center. The meters are converted to degrees at the latitude of the center.
+    pub fn from_center(lon: f64, lat: f64, half_width_m: f64, half_height_m: f64) -> Self {
+        let (half_width_deg, _) = meters_to_degrees_at(lat, half_width_m);
+        let (_, half_height_deg) = meters_to_degrees_at(lat, half_height_m);
+        WgsBoundingBox {
+            left_lon: lon - half_width_deg,
+            right_lon: lon + half_width_deg,
+            bottom_lat: lat - half_height_deg,
+            top_lat: lat + half_height_deg,
+        }
+    }
+
+    /// The bounding box grown by the given meters on each side.
+    ///
+    /// The meters are converted to degrees of longitude at the edge closer to a pole, where a degree is shortest, so
+    /// the buffer is at least as wide as requested along the whole bounding box.
+    pub fn buffer(&self, meters: f64) -> Self {
+        let polar_lat = self.bottom_lat.abs().max(self.top_lat.abs());
+        let (lon_deg, _) = meters_to_degrees_at(polar_lat, meters);
+        let (_, lat_deg) = meters_to_degrees_at((self.bottom_lat + self.top_lat) / 2.0, meters);
+        WgsBoundingBox {
+            left_lon: self.left_lon - lon_deg,
+            right_lon: self.right_lon + lon_deg,
+            bottom_lat: self.bottom_lat - lat_deg,
+            top_lat: self.top_lat + lat_deg,
+        }
+    }
+
+    /// The overlap of two bounding boxes, `None` if they are disjoint or only touch.
+    pub fn intersection(&self, other: &WgsBoundingBox) -> Option<Self> {
+        let intersection = WgsBoundingBox {
+            left_lon: self.left_lon.max(other.left_lon),
+            right_lon: self.right_lon.min(other.right_lon),
+            bottom_lat: self.bottom_lat.max(other.bottom_lat),
+            top_lat: self.top_lat.min(other.top_lat),
+        };
+        (intersection.width_deg() > 0.0 && intersection.height_deg() > 0.0).then_some(intersection)
+    }
+
+    /// Whether the other bounding box is inside this one, including on its edges.
+    pub fn contains(&self, other: &WgsBoundingBox) -> bool {
+        self.left_lon <= other.left_lon
+            && other.right_lon <= self.right_lon
+            && self.bottom_lat <= other.bottom_lat
+            && other.top_lat <= self.top_lat
+    }
+
+    pub fn width_deg(&self) -> f64 {
+        self.right_lon - self.left_lon
+    }
+
+    pub fn height_deg(&self) -> f64 {
+        self.top_lat - self.bottom_lat
+    }
+}
+
+/// Build the Overpass QL query for the highway ways in a bounding box and the nodes they reference.
+///
+/// # Arguments
+/// * highway_classes - The values of the highway tag to keep, e.g. "primary". If empty, all highways are kept.
+pub fn build_overpass_ql_query(bbox: &WgsBoundingBox, highway_classes: &[String]) -> String {
+    let highway_filter = if highway_classes.is_empty() {
+        "[highway]".to_string()
+    } else {
+        format!("[highway~\"^({})$\"]", highway_classes.join("|"))
+    };
+    // Overpass bounding boxes are ordered (south, west, north, east).
+    format!(
+        "(way{}({},{},{},{}); >;); out;",
+        highway_filter, bbox.bottom_lat, bbox.left_lon, bbox.top_lat, bbox.right_lon
+    )
+}
+
+/// Maximum difference of the bounds of a legacy cache file from the requested bounding box. The Overpass API writes
+/// the bounds with 7 decimals.
+const LEGACY_BOUNDS_TOLERANCE: f64 = 1e-7;
+
+/// FNV-1a hash, which unlike the hasher of the standard library is stable across Rust versions, so that cache
+/// filenames stay valid.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Get the name of the file which caches the OSM data of a bounding box.
+///
+/// The name contains the corners of the bounding box with 7 decimals, and a hash of the exact request, i.e. of the
+/// exact coordinates for the map endpoint and of the query for the interpreter endpoint. Bounding boxes which only
+/// differ below the printed precision, or requests with different highway classes, get different names.
+pub fn get_filename_for_bbox(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+) -> String {
+    // The `Display` of f64 is the shortest string which parses back to the same value, so the key is exact.
+    let request_key = match endpoint {
+        OsmEndpoint::Interpreter => build_overpass_ql_query(bbox, highway_classes),
+        OsmEndpoint::Map => format!(
+            "map({},{},{},{})",
+            bbox.left_lon, bbox.bottom_lat, bbox.right_lon, bbox.top_lat
+        ),
+    };
+    format!(
+        "{:.7}_{:.7}_{:.7}_{:.7}_{:016x}_osm.xml",
+        bbox.left_lon,
+        bbox.bottom_lat,
+        bbox.right_lon,
+        bbox.top_lat,
+        stable_hash(request_key.as_bytes())
+    )
+}
+
+/// The name of the cache file of a bounding box used by earlier versions, from geohashes of the corners. Bounding
+/// boxes in the same geohash cells share a name, so these files are only reused after checking their bounds, see
+/// `migrate_legacy_cache_file`.
+fn get_legacy_filename_for_bbox(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+) -> Result<String> {
     const GEOHASH_LENGTH: usize = 8;
     let top_left_coord = Coord {
         x: bbox.left_lon,
@@ -27,36 +171,941 @@ pub fn get_filename_for_bbox(bbox: &WgsBoundingBox) -> anyhow::Result<String> {
     };
     let top_left_geohash = encode(top_left_coord, GEOHASH_LENGTH)?;
     let bottom_right_geohash = encode(bottom_right_coord, GEOHASH_LENGTH)?;
-    Ok(format!("{top_left_geohash}_{bottom_right_geohash}_osm.xml"))
+    Ok(match endpoint {
+        OsmEndpoint::Interpreter => {
+            let mut hasher = DefaultHasher::new();
+            build_overpass_ql_query(bbox, highway_classes).hash(&mut hasher);
+            format!(
+                "{top_left_geohash}_{bottom_right_geohash}_{:016x}_osm.xml",
+                hasher.finish()
+            )
+        }
+        OsmEndpoint::Map => format!("{top_left_geohash}_{bottom_right_geohash}_osm.xml"),
+    })
+}
+
+/// The request which an OSM cache file was downloaded for, stored in a JSON file next to it.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct OsmCacheMetadata {
+    pub bbox: WgsBoundingBox,
+    pub endpoint: OsmEndpoint,
+    /// The requested highway classes, always empty for the map endpoint.
+    pub highway_classes: Vec<String>,
+    /// When the data was downloaded, in RFC 3339 format.
+    pub downloaded_at: String,
+}
+
+impl OsmCacheMetadata {
+    fn new(
+        bbox: &WgsBoundingBox,
+        endpoint: OsmEndpoint,
+        highway_classes: &[String],
+        downloaded_at: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            bbox: bbox.clone(),
+            endpoint,
+            highway_classes: requested_highway_classes(endpoint, highway_classes),
+            downloaded_at: downloaded_at.to_rfc3339(),
+        }
+    }
+
+    /// The path of the metadata file of a cache file.
+    pub fn filepath(cache_filepath: &Path) -> PathBuf {
+        let mut filename = cache_filepath.as_os_str().to_os_string();
+        filename.push(".json");
+        PathBuf::from(filename)
+    }
+
+    /// Read the metadata of a cache file, `None` if it has no metadata file.
+    pub fn read(cache_filepath: &Path) -> Result<Option<Self>> {
+        let filepath = Self::filepath(cache_filepath);
+        if !filepath.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read_to_string(&filepath).with_context(|| format!("Reading {:?}", filepath))?;
+        Ok(Some(
+            serde_json::from_str(&contents).with_context(|| format!("Parsing {:?}", filepath))?,
+        ))
+    }
+
+    pub fn write(&self, cache_filepath: &Path) -> Result<()> {
+        let filepath = Self::filepath(cache_filepath);
+        fs::write(&filepath, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Writing {:?}", filepath))?;
+        Ok(())
+    }
+
+    /// Whether the cache file holds the data of exactly this request.
+    pub fn matches(
+        &self,
+        bbox: &WgsBoundingBox,
+        endpoint: OsmEndpoint,
+        highway_classes: &[String],
+    ) -> bool {
+        self.bbox == *bbox
+            && self.endpoint == endpoint
+            && self.highway_classes == requested_highway_classes(endpoint, highway_classes)
+    }
+}
+
+/// The highway classes which a request to the endpoint filters by.
+fn requested_highway_classes(endpoint: OsmEndpoint, highway_classes: &[String]) -> Vec<String> {
+    match endpoint {
+        OsmEndpoint::Interpreter => highway_classes.to_vec(),
+        OsmEndpoint::Map => Vec::new(),
+    }
 }
 
-pub fn download_osm_data_by_bbox(bbox: &WgsBoundingBox) -> anyhow::Result<String> {
-    let query = format!(
-        "https://overpass-api.de/api/map?bbox={},{},{},{}",
-        bbox.left_lon, bbox.bottom_lat, bbox.right_lon, bbox.top_lat
-    );
+/// Download the OSM data of a bounding box to `output_filepath`.
+///
+/// The response is streamed to a temporary file next to `output_filepath`, which is renamed to `output_filepath`
+/// once the download is complete, so the full response is never held in memory and an interrupted download never
+/// leaves a truncated file behind.
+///
+/// If `compress` is set, the file is gzip compressed.
+///
+/// # Returns
+/// The path of the written file, and the number of bytes downloaded.
+pub fn download_osm_data_by_bbox(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_filepath: &Path,
+    compress: bool,
+) -> Result<(PathBuf, u64)> {
+    download_osm_data_from(
+        OVERPASS_API_URL,
+        bbox,
+        endpoint,
+        highway_classes,
+        output_filepath,
+        compress,
+    )
+}
+
+fn download_osm_data_from(
+    api_url: &str,
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_filepath: &Path,
+    compress: bool,
+) -> Result<(PathBuf, u64)> {
     let client = reqwest::blocking::Client::builder()
-        .user_agent("osm-geo-mapper")
+        .user_agent(USER_AGENT)
         .build()?;
-    let response = client.get(&query).send()?;
-    response.text().or(Err(anyhow!("No response text")))
+    let request = match endpoint {
+        OsmEndpoint::Interpreter => client
+            .get(format!("{api_url}/interpreter"))
+            .query(&[("data", build_overpass_ql_query(bbox, highway_classes))]),
+        OsmEndpoint::Map => client.get(format!(
+            "{api_url}/map?bbox={},{},{},{}",
+            bbox.left_lon, bbox.bottom_lat, bbox.right_lon, bbox.top_lat
+        )),
+    };
+    let response = request.send()?.error_for_status()?;
+    let expected_len = response.content_length();
+    let written_len = stream_to_file(
+        response,
+        expected_len,
+        output_filepath,
+        compress,
+        check_overpass_response,
+    )?;
+    Ok((output_filepath.to_path_buf(), written_len))
 }
 
-pub fn sync_osm_data_to_file(bbox: &WgsBoundingBox, output_dir: &Path) -> anyhow::Result<PathBuf> {
-    let filename = get_filename_for_bbox(bbox)?;
-    let output_filepath = output_dir.join(filename);
-    if output_filepath.exists() {
+/// Fail if a downloaded OSM file is an Overpass error response, i.e. has no elements but a remark like "runtime error:
+/// Query timed out". The Overpass API returns these with status 200, so they would otherwise be cached as empty data.
+fn check_overpass_response(filepath: &Path) -> Result<()> {
+    match read_overpass_error_remark(filepath)? {
+        Some(remark) => Err(Error::OsmDownloadError {
+            message: format!(
+                "The Overpass API returned no data, but the remark: {}",
+                remark
+            ),
+            source: None,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Copy `reader` to `output_filepath` in bounded chunks, via a temporary file which is renamed into place only if the
+/// copy succeeded and, if given, exactly `expected_len` bytes were read. On failure, the temporary file is removed.
+/// If `compress` is set, the file is gzip compressed. `validate` is called with the complete temporary file, and if it
+/// fails, the file is removed as well.
+///
+/// # Returns
+/// The number of bytes read from `reader`.
+fn stream_to_file(
+    mut reader: impl Read,
+    expected_len: Option<u64>,
+    output_filepath: &Path,
+    compress: bool,
+    validate: impl FnOnce(&Path) -> Result<()>,
+) -> Result<u64> {
+    let mut temp_filename = output_filepath
+        .file_name()
+        .ok_or_else(|| Error::ConfigError(format!("{:?} is not a file path", output_filepath)))?
+        .to_os_string();
+    temp_filename.push(".part");
+    let temp_filepath = output_filepath.with_file_name(temp_filename);
+
+    let copy_result = (|| {
+        let mut writer = BufWriter::new(
+            File::create(&temp_filepath)
+                .with_context(|| format!("Creating {:?}", temp_filepath))?,
+        );
+        let written_len = if compress {
+            let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+            let written_len = io::copy(&mut reader, &mut encoder)
+                .with_context(|| format!("Writing OSM data to {:?}", temp_filepath))?;
+            encoder.finish()?;
+            written_len
+        } else {
+            io::copy(&mut reader, &mut writer)
+                .with_context(|| format!("Writing OSM data to {:?}", temp_filepath))?
+        };
+        writer.flush()?;
+        match expected_len {
+            Some(expected_len) if expected_len != written_len => {
+                return Err(Error::OsmDownloadError {
+                    message: format!(
+                        "Received {} bytes of OSM data, but {} were announced",
+                        written_len, expected_len
+                    ),
+                    source: None,
+                })
+            }
+            _ if written_len == 0 => {
+                return Err(Error::OsmDownloadError {
+                    message: "Received no OSM data".to_string(),
+                    source: None,
+                })
+            }
+            _ => {}
+        }
+        drop(writer);
+        validate(&temp_filepath)?;
+        Ok(written_len)
+    })();
+    match copy_result {
+        Ok(written_len) => {
+            fs::rename(&temp_filepath, output_filepath)
+                .with_context(|| format!("Moving {:?} to {:?}", temp_filepath, output_filepath))?;
+            Ok(written_len)
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp_filepath);
+            Err(error)
+        }
+    }
+}
+
+/// Download the OSM data of a bounding box to a file in `output_dir`, unless it was downloaded before.
+///
+/// # Arguments
+/// * endpoint - The Overpass API endpoint to use.
+/// * highway_classes - The highway classes to request from the interpreter endpoint, all if empty. The map endpoint
+///   returns all elements regardless.
+/// * compress_cache - Whether to store the download gzip compressed, in a file with the additional extension ".gz".
+pub fn sync_osm_data_to_file(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_dir: &Path,
+    compress_cache: bool,
+) -> Result<PathBuf> {
+    let output_filepath =
+        cache_filepath_for_bbox(bbox, endpoint, highway_classes, output_dir, compress_cache);
+    if let Some(cache_filepath) = find_cache_file(
+        bbox,
+        endpoint,
+        highway_classes,
+        output_dir,
+        &output_filepath,
+        compress_cache,
+    )? {
         log::info!(
             "Local file exists for OSM data: {:?}",
-            output_filepath.canonicalize()
+            cache_filepath.canonicalize()
         );
-        return Ok(output_filepath);
+        return Ok(cache_filepath);
     }
 
-    log::info!("Downloading OSM data");
-    let osm_data = download_osm_data_by_bbox(bbox)?;
-    fs::write(&output_filepath, osm_data).or(Err(anyhow!("Could not write OSM data to file")))?;
+    log::info!("Downloading OSM data from the {:?} endpoint", endpoint);
+    let (output_filepath, written_len) = download_osm_data_by_bbox(
+        bbox,
+        endpoint,
+        highway_classes,
+        &output_filepath,
+        compress_cache,
+    )?;
+    OsmCacheMetadata::new(bbox, endpoint, highway_classes, chrono::Utc::now())
+        .write(&output_filepath)?;
+    log::info!("Downloaded {} bytes of OSM data", written_len);
     Ok(output_filepath)
 }
 
+/// The path of the file in `output_dir` which caches the OSM data of a request, see `get_filename_for_bbox`.
+fn cache_filepath_for_bbox(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_dir: &Path,
+    compress_cache: bool,
+) -> PathBuf {
+    let mut filename = get_filename_for_bbox(bbox, endpoint, highway_classes);
+    if compress_cache {
+        filename.push_str(".gz");
+    }
+    output_dir.join(filename)
+}
+
+/// The file in `output_dir` which caches the OSM data of a request, if there is one which `sync_osm_data_to_file`
+/// would use without downloading the data. Unlike `sync_osm_data_to_file`, nothing is written, so cache files of
+/// earlier versions, which it migrates, are not found.
+pub fn find_cached_osm_data(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_dir: &Path,
+    compress_cache: bool,
+) -> Result<Option<PathBuf>> {
+    let cache_filepath =
+        cache_filepath_for_bbox(bbox, endpoint, highway_classes, output_dir, compress_cache);
+    if !cache_filepath.exists() {
+        return Ok(None);
+    }
+    Ok(OsmCacheMetadata::read(&cache_filepath)?
+        .filter(|metadata| metadata.matches(bbox, endpoint, highway_classes))
+        .map(|_| cache_filepath))
+}
+
+/// Check that the Overpass API is reachable with a request to its cheap `/status` endpoint.
+///
+/// # Returns
+/// The status reported by the API, e.g. the number of available query slots.
+pub fn check_overpass_status() -> Result<String> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(OVERPASS_STATUS_TIMEOUT)
+        .build()?;
+    let status = client
+        .get(format!("{OVERPASS_API_URL}/status"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    Ok(status
+        .lines()
+        .find(|line| line.contains("available"))
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+/// Get the cache file of a request at `output_filepath`, if its metadata shows that it holds the data of exactly this
+/// request. Otherwise, migrate the cache file of the request from earlier versions, if there is one.
+fn find_cache_file(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    output_dir: &Path,
+    output_filepath: &Path,
+    compress_cache: bool,
+) -> Result<Option<PathBuf>> {
+    if output_filepath.exists() {
+        match OsmCacheMetadata::read(output_filepath) {
+            Ok(Some(metadata)) if metadata.matches(bbox, endpoint, highway_classes) => {
+                return Ok(Some(output_filepath.to_path_buf()))
+            }
+            Ok(Some(metadata)) => log::warn!(
+                "Cached OSM data {:?} was downloaded for {:?}, downloading it again",
+                output_filepath,
+                metadata
+            ),
+            Ok(None) => log::warn!(
+                "Cached OSM data {:?} has no metadata file, downloading it again",
+                output_filepath
+            ),
+            Err(error) => log::warn!(
+                "Cannot read the metadata of cached OSM data {:?}, downloading it again: {}",
+                output_filepath,
+                error.display_chain()
+            ),
+        }
+        return Ok(None);
+    }
+    let mut legacy_filename = get_legacy_filename_for_bbox(bbox, endpoint, highway_classes)?;
+    if compress_cache {
+        legacy_filename.push_str(".gz");
+    }
+    migrate_legacy_cache_file(
+        bbox,
+        endpoint,
+        highway_classes,
+        &output_dir.join(legacy_filename),
+        output_filepath,
+    )
+}
+
+/// Move a cache file named by an earlier version to `output_filepath` and write its metadata, if the bounds declared
+/// by the file match the bounding box. Files without bounds, e.g. most responses of the interpreter endpoint, cannot
+/// be validated and are left in place.
+///
+/// # Returns
+/// `output_filepath` if the file was migrated.
+fn migrate_legacy_cache_file(
+    bbox: &WgsBoundingBox,
+    endpoint: OsmEndpoint,
+    highway_classes: &[String],
+    legacy_filepath: &Path,
+    output_filepath: &Path,
+) -> Result<Option<PathBuf>> {
+    if !legacy_filepath.exists() {
+        return Ok(None);
+    }
+    let bounds = match read_osm_bounds(legacy_filepath) {
+        Ok(bounds) => bounds,
+        Err(error) => {
+            log::warn!(
+                "Cannot read the legacy OSM cache file {:?}, ignoring it: {}",
+                legacy_filepath,
+                error.display_chain()
+            );
+            return Ok(None);
+        }
+    };
+    let matches_bbox = |bounds: &WgsBoundingBox| {
+        [
+            (bounds.left_lon, bbox.left_lon),
+            (bounds.right_lon, bbox.right_lon),
+            (bounds.bottom_lat, bbox.bottom_lat),
+            (bounds.top_lat, bbox.top_lat),
+        ]
+        .iter()
+        .all(|(a, b)| (a - b).abs() <= LEGACY_BOUNDS_TOLERANCE)
+    };
+    match bounds {
+        Some(bounds) if matches_bbox(&bounds) => {
+            let downloaded_at = fs::metadata(legacy_filepath)
+                .and_then(|metadata| metadata.modified())
+                .map(chrono::DateTime::<chrono::Utc>::from)
+                .unwrap_or_else(|_| chrono::Utc::now());
+            fs::rename(legacy_filepath, output_filepath).with_context(|| {
+                format!("Moving {:?} to {:?}", legacy_filepath, output_filepath)
+            })?;
+            OsmCacheMetadata::new(bbox, endpoint, highway_classes, downloaded_at)
+                .write(output_filepath)?;
+            log::info!(
+                "Migrated the legacy OSM cache file {:?} to {:?}",
+                legacy_filepath,
+                output_filepath
+            );
+            Ok(Some(output_filepath.to_path_buf()))
+        }
+        Some(bounds) => {
+            log::warn!(
+                "The legacy OSM cache file {:?} has the bounds {:?}, which differ from the requested {:?}, ignoring it",
+                legacy_filepath,
+                bounds,
+                bbox
+            );
+            Ok(None)
+        }
+        None => {
+            log::warn!(
+                "The legacy OSM cache file {:?} declares no bounds to validate it by, ignoring it",
+                legacy_filepath
+            );
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{BufRead, BufReader, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::JoinHandle,
+    };
+
+    use approx::assert_abs_diff_eq;
+    use flate2::read::GzDecoder;
+    use rstest::{fixture, rstest};
+    use testdir::testdir;
+
+    use crate::error::Error;
+
+    use super::{
+        build_overpass_ql_query, download_osm_data_from, find_cache_file, find_cached_osm_data,
+        get_filename_for_bbox, get_legacy_filename_for_bbox, stream_to_file, OsmCacheMetadata,
+        OsmEndpoint, WgsBoundingBox,
+    };
+
+    #[fixture]
+    fn bbox() -> WgsBoundingBox {
+        WgsBoundingBox {
+            left_lon: 139.788745,
+            right_lon: 139.792244,
+            bottom_lat: 35.683695,
+            top_lat: 35.685717,
+        }
+    }
 
+    // The meters per degree of longitude and latitude at the equator and at 60 degrees, see `meters_to_degrees_at`.
+    #[rstest]
+    #[case(0.0, 111319.458, 110574.273)]
+    #[case(60.0, 55799.979, 111412.240)]
+    #[case(-60.0, 55799.979, 111412.240)]
+    fn test_bbox_from_center(
+        #[case] lat: f64,
+        #[case] meters_per_lon_degree: f64,
+        #[case] meters_per_lat_degree: f64,
+    ) {
+        let bbox = WgsBoundingBox::from_center(139.79, lat, 1000.0, 500.0);
+
+        assert_abs_diff_eq!(
+            2000.0 / meters_per_lon_degree,
+            bbox.width_deg(),
+            epsilon = 1e-8
+        );
+        assert_abs_diff_eq!(
+            1000.0 / meters_per_lat_degree,
+            bbox.height_deg(),
+            epsilon = 1e-8
+        );
+        assert_abs_diff_eq!(
+            139.79,
+            (bbox.left_lon + bbox.right_lon) / 2.0,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(lat, (bbox.bottom_lat + bbox.top_lat) / 2.0, epsilon = 1e-12);
+    }
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(60.0)]
+    fn test_bbox_buffer(#[case] lat: f64) {
+        let bbox = WgsBoundingBox::from_center(10.0, lat, 1000.0, 1000.0);
+
+        let buffered = bbox.buffer(1000.0);
+
+        assert!(buffered.contains(&bbox));
+        assert!(!bbox.contains(&buffered));
+        // A degree of longitude is shorter at the polar edge, so the width grows by more than at the center.
+        let center_lon_deg = bbox.width_deg() / 2.0;
+        assert!(buffered.width_deg() - bbox.width_deg() >= 2.0 * center_lon_deg);
+        assert_abs_diff_eq!(
+            2.0 * bbox.height_deg(),
+            buffered.height_deg(),
+            epsilon = 1e-6
+        );
+    }
+
+    #[rstest]
+    fn test_bbox_buffer_width_at_high_latitude() {
+        let low = WgsBoundingBox::from_center(10.0, 0.0, 1000.0, 1000.0).buffer(1000.0);
+        let high = WgsBoundingBox::from_center(10.0, 60.0, 1000.0, 1000.0).buffer(1000.0);
+
+        // A degree of longitude at 60 degrees is about half as long as at the equator, a degree of latitude is about
+        // as long.
+        assert!(high.width_deg() > 1.9 * low.width_deg());
+        assert_abs_diff_eq!(low.height_deg(), high.height_deg(), epsilon = 1e-3);
+    }
+
+    #[rstest]
+    #[case((0.0, 2.0, 0.0, 2.0), (1.0, 3.0, 1.0, 3.0), Some((1.0, 2.0, 1.0, 2.0)))] // Overlapping.
+    #[case((0.0, 2.0, 0.0, 2.0), (0.5, 1.0, 0.5, 1.0), Some((0.5, 1.0, 0.5, 1.0)))] // Contained.
+    #[case((0.0, 2.0, 0.0, 2.0), (2.0, 3.0, 0.0, 2.0), None)] // Touching edges.
+    #[case((0.0, 2.0, 0.0, 2.0), (2.0, 3.0, 2.0, 3.0), None)] // Touching corners.
+    #[case((0.0, 2.0, 0.0, 2.0), (3.0, 4.0, 0.0, 2.0), None)] // Disjoint in longitude.
+    #[case((0.0, 2.0, 0.0, 2.0), (0.0, 2.0, -2.0, -1.0), None)] // Disjoint in latitude.
+    fn test_bbox_intersection(
+        #[case] a: (f64, f64, f64, f64),
+        #[case] b: (f64, f64, f64, f64),
+        #[case] expected: Option<(f64, f64, f64, f64)>,
+    ) {
+        let to_bbox = |(left_lon, right_lon, bottom_lat, top_lat)| WgsBoundingBox {
+            left_lon,
+            right_lon,
+            bottom_lat,
+            top_lat,
+        };
+        let a = to_bbox(a);
+        let b = to_bbox(b);
+        let expected = expected.map(to_bbox);
+
+        assert_eq!(expected, a.intersection(&b));
+        assert_eq!(expected, b.intersection(&a));
+        if let Some(intersection) = a.intersection(&b) {
+            assert!(a.contains(&intersection));
+            assert!(b.contains(&intersection));
+        }
+    }
+
+    #[rstest]
+    fn test_bbox_serializes_corners(bbox: WgsBoundingBox) {
+        let json = serde_json::to_value(&bbox).unwrap();
+
+        assert_eq!(139.788745, json["left_lon"]);
+        assert_eq!(35.685717, json["top_lat"]);
+    }
+
+    #[rstest]
+    #[case(vec![], "(way[highway](35.683695,139.788745,35.685717,139.792244); >;); out;")]
+    #[case(
+        vec!["primary".to_string(), "secondary".to_string()],
+        "(way[highway~\"^(primary|secondary)$\"](35.683695,139.788745,35.685717,139.792244); >;); out;"
+    )]
+    fn test_build_overpass_ql_query(
+        bbox: WgsBoundingBox,
+        #[case] highway_classes: Vec<String>,
+        #[case] expected_query: &str,
+    ) {
+        assert_eq!(
+            expected_query,
+            build_overpass_ql_query(&bbox, &highway_classes)
+        );
+    }
+
+    #[rstest]
+    fn test_filename_depends_on_query(bbox: WgsBoundingBox) {
+        let all_highways = get_filename_for_bbox(&bbox, OsmEndpoint::Interpreter, &[]);
+        let primary_highways =
+            get_filename_for_bbox(&bbox, OsmEndpoint::Interpreter, &["primary".to_string()]);
+        assert_ne!(all_highways, primary_highways);
+        assert_eq!(
+            all_highways,
+            get_filename_for_bbox(&bbox, OsmEndpoint::Interpreter, &[])
+        );
+
+        let map = get_filename_for_bbox(&bbox, OsmEndpoint::Map, &["primary".to_string()]);
+        assert_ne!(all_highways, map);
+        assert_eq!(map, get_filename_for_bbox(&bbox, OsmEndpoint::Map, &[]));
+        assert!(map.ends_with("_osm.xml"));
+    }
+
+    #[rstest]
+    fn test_filename_contains_bbox(bbox: WgsBoundingBox) {
+        let filename = get_filename_for_bbox(&bbox, OsmEndpoint::Map, &[]);
+
+        assert!(
+            filename.starts_with("139.7887450_35.6836950_139.7922440_35.6857170_"),
+            "{}",
+            filename
+        );
+    }
+
+    #[rstest]
+    #[case(1e-9)]
+    #[case(1e-6)]
+    fn test_filename_differs_for_nearby_bboxes(bbox: WgsBoundingBox, #[case] offset: f64) {
+        let nearby_bbox = WgsBoundingBox {
+            left_lon: bbox.left_lon + offset,
+            ..bbox.clone()
+        };
+
+        // The geohashes of the legacy names do not tell the bounding boxes apart.
+        assert_eq!(
+            get_legacy_filename_for_bbox(&bbox, OsmEndpoint::Map, &[]).unwrap(),
+            get_legacy_filename_for_bbox(&nearby_bbox, OsmEndpoint::Map, &[]).unwrap()
+        );
+        for endpoint in [OsmEndpoint::Interpreter, OsmEndpoint::Map] {
+            assert_ne!(
+                get_filename_for_bbox(&bbox, endpoint, &[]),
+                get_filename_for_bbox(&nearby_bbox, endpoint, &[])
+            );
+        }
+    }
+
+    /// Write a cache file of `bbox` to `output_dir`, with metadata of `metadata_bbox` if given.
+    fn write_cache_file(
+        output_dir: &Path,
+        bbox: &WgsBoundingBox,
+        metadata_bbox: Option<&WgsBoundingBox>,
+    ) -> PathBuf {
+        let filepath = output_dir.join(get_filename_for_bbox(bbox, OsmEndpoint::Map, &[]));
+        std::fs::write(&filepath, "<osm></osm>").unwrap();
+        if let Some(metadata_bbox) = metadata_bbox {
+            OsmCacheMetadata::new(metadata_bbox, OsmEndpoint::Map, &[], chrono::Utc::now())
+                .write(&filepath)
+                .unwrap();
+        }
+        filepath
+    }
+
+    #[rstest]
+    fn test_find_cache_file_validates_metadata(bbox: WgsBoundingBox) {
+        let output_dir = testdir!();
+        let other_bbox = WgsBoundingBox {
+            top_lat: bbox.top_lat + 1e-9,
+            ..bbox.clone()
+        };
+        let find = |bbox: &WgsBoundingBox, filepath: &Path| {
+            find_cache_file(bbox, OsmEndpoint::Map, &[], &output_dir, filepath, false).unwrap()
+        };
+
+        let filepath = write_cache_file(&output_dir, &bbox, Some(&bbox));
+        assert_eq!(Some(filepath.clone()), find(&bbox, &filepath));
+        let metadata = OsmCacheMetadata::read(&filepath).unwrap().unwrap();
+        assert!(!metadata.matches(&bbox, OsmEndpoint::Interpreter, &[]));
+        // The highway classes are not part of requests to the map endpoint.
+        assert!(metadata.matches(&bbox, OsmEndpoint::Map, &["primary".to_string()]));
+
+        let filepath = write_cache_file(&output_dir, &bbox, Some(&other_bbox));
+        assert_eq!(None, find(&bbox, &filepath));
+
+        std::fs::remove_file(OsmCacheMetadata::filepath(&filepath)).unwrap();
+        assert_eq!(None, find(&bbox, &filepath));
+    }
+
+    #[rstest]
+    fn test_find_cached_osm_data(bbox: WgsBoundingBox) {
+        let output_dir = testdir!();
+        let find = |compress_cache: bool| {
+            find_cached_osm_data(&bbox, OsmEndpoint::Map, &[], &output_dir, compress_cache).unwrap()
+        };
+        assert_eq!(None, find(false));
+
+        let filepath = write_cache_file(&output_dir, &bbox, None);
+        // Without metadata, the data would be downloaded again.
+        assert_eq!(None, find(false));
+
+        OsmCacheMetadata::new(&bbox, OsmEndpoint::Map, &[], chrono::Utc::now())
+            .write(&filepath)
+            .unwrap();
+        assert_eq!(Some(filepath), find(false));
+        assert_eq!(None, find(true));
+    }
+
+    #[rstest]
+    #[case(0.0, true)]
+    #[case(1e-4, false)]
+    fn test_migrate_legacy_cache_file(
+        bbox: WgsBoundingBox,
+        #[case] bounds_offset: f64,
+        #[case] expect_migrated: bool,
+    ) {
+        let output_dir = testdir!();
+        let legacy_filepath =
+            output_dir.join(get_legacy_filename_for_bbox(&bbox, OsmEndpoint::Map, &[]).unwrap());
+        std::fs::write(
+            &legacy_filepath,
+            format!(
+                r#"<osm version="0.6"><bounds minlat="{:.7}" minlon="{:.7}" maxlat="{:.7}" maxlon="{:.7}"/></osm>"#,
+                bbox.bottom_lat,
+                bbox.left_lon + bounds_offset,
+                bbox.top_lat,
+                bbox.right_lon
+            ),
+        )
+        .unwrap();
+        let output_filepath = output_dir.join(get_filename_for_bbox(&bbox, OsmEndpoint::Map, &[]));
+
+        let cache_filepath = find_cache_file(
+            &bbox,
+            OsmEndpoint::Map,
+            &[],
+            &output_dir,
+            &output_filepath,
+            false,
+        )
+        .unwrap();
+
+        if expect_migrated {
+            assert_eq!(Some(output_filepath.clone()), cache_filepath);
+            assert!(!legacy_filepath.exists());
+            let metadata = OsmCacheMetadata::read(&output_filepath).unwrap().unwrap();
+            assert!(metadata.matches(&bbox, OsmEndpoint::Map, &[]));
+        } else {
+            assert_eq!(None, cache_filepath);
+            assert!(legacy_filepath.exists());
+            assert!(!output_filepath.exists());
+        }
+    }
+
+    /// Reader of `len` bytes which records the largest buffer it was asked to fill.
+    struct RecordingReader<'a> {
+        remaining: usize,
+        max_buffer_len: &'a AtomicUsize,
+    }
+
+    impl Read for RecordingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.max_buffer_len.fetch_max(buf.len(), Ordering::Relaxed);
+            let len = buf.len().min(self.remaining).min(1000);
+            buf[..len].fill(b'x');
+            self.remaining -= len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_stream_to_file_reads_bounded_chunks() {
+        let output_filepath = testdir!().join("osm.xml");
+        let max_buffer_len = AtomicUsize::new(0);
+        let len = 10_000_000;
+        let reader = RecordingReader {
+            remaining: len,
+            max_buffer_len: &max_buffer_len,
+        };
+
+        let written_len =
+            stream_to_file(
+                reader,
+                Some(len as u64),
+                &output_filepath,
+                false,
+                |_| Ok(()),
+            )
+            .unwrap();
+
+        assert_eq!(len as u64, written_len);
+        assert_eq!(len as u64, output_filepath.metadata().unwrap().len());
+        assert!(max_buffer_len.load(Ordering::Relaxed) <= 64 * 1024);
+    }
+
+    #[test]
+    fn test_stream_to_file_length_mismatch() {
+        let test_dir = testdir!();
+        let output_filepath = test_dir.join("osm.xml");
+
+        let result = stream_to_file(
+            &b"<osm></osm>"[..],
+            Some(100),
+            &output_filepath,
+            false,
+            |_| Ok(()),
+        );
+
+        assert!(matches!(result, Err(Error::OsmDownloadError { .. })));
+        assert!(!output_filepath.exists());
+        assert!(!test_dir.join("osm.xml.part").exists());
+    }
+
+    #[test]
+    fn test_stream_to_file_compressed() {
+        let output_filepath = testdir!().join("osm.xml.gz");
+        let contents = b"<osm><node id=\"1\"/></osm>";
+
+        let written_len =
+            stream_to_file(&contents[..], None, &output_filepath, true, |_| Ok(())).unwrap();
+
+        assert_eq!(contents.len() as u64, written_len);
+        let mut decompressed = Vec::new();
+        GzDecoder::new(File::open(&output_filepath).unwrap())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(&contents[..], &decompressed[..]);
+    }
+
+    /// Serve one HTTP request on a local port, responding with `respond`.
+    ///
+    /// # Returns
+    /// The address of the server, and the handle of its thread, which returns the request line.
+    fn serve_one_request(
+        respond: impl FnOnce(&mut TcpStream) + Send + 'static,
+    ) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header_line = String::new();
+            while header_line != "\r\n" {
+                header_line.clear();
+                reader.read_line(&mut header_line).unwrap();
+            }
+            let mut stream = stream;
+            respond(&mut stream);
+            request_line
+        });
+        (address, server)
+    }
+
+    #[rstest]
+    fn test_download_streams_chunked_response(bbox: WgsBoundingBox) {
+        let chunks = ["<osm>", "<node id=\"1\"/>", "<node id=\"2\"/>", "</osm>"];
+        let (address, server) = serve_one_request(move |stream| {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            for chunk in chunks {
+                write!(stream, "{:x}\r\n{}\r\n", chunk.len(), chunk).unwrap();
+                stream.flush().unwrap();
+            }
+            write!(stream, "0\r\n\r\n").unwrap();
+        });
+        let output_filepath = testdir!().join("osm.xml");
+
+        let (filepath, written_len) = download_osm_data_from(
+            &format!("http://{}", address),
+            &bbox,
+            OsmEndpoint::Map,
+            &[],
+            &output_filepath,
+            false,
+        )
+        .unwrap();
+
+        let request_line = server.join().unwrap();
+        assert!(
+            request_line.starts_with("GET /map?bbox="),
+            "{}",
+            request_line
+        );
+        assert_eq!(output_filepath, filepath);
+        let expected_contents = chunks.concat();
+        assert_eq!(expected_contents.len() as u64, written_len);
+        assert_eq!(
+            expected_contents,
+            std::fs::read_to_string(&output_filepath).unwrap()
+        );
+    }
+
+    #[rstest]
+    fn test_download_remark_only_response_fails(bbox: WgsBoundingBox) {
+        let body = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<osm version=\"0.6\">\n<remark> runtime error: Query timed out in \"query\" at line 1 after 26 seconds. </remark>\n</osm>\n";
+        let (address, server) = serve_one_request(move |stream| {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        let output_dir = testdir!();
+        let output_filepath = output_dir.join("osm.xml");
+
+        let result = download_osm_data_from(
+            &format!("http://{}", address),
+            &bbox,
+            OsmEndpoint::Interpreter,
+            &[],
+            &output_filepath,
+            false,
+        );
+
+        server.join().unwrap();
+        match result {
+            Err(Error::OsmDownloadError { message, .. }) => {
+                assert!(message.contains("Query timed out"), "{}", message)
+            }
+            result => panic!("Expected a download error, got {:?}", result),
+        }
+        // Neither the cache file nor its temporary file are left behind.
+        assert_eq!(0, std::fs::read_dir(&output_dir).unwrap().count());
+    }
+}
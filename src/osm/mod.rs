@@ -1,2 +1,3 @@
 pub mod conversion;
-pub mod download;
\ No newline at end of file
+pub mod download;
+pub mod nominatim;
@@ -0,0 +1,179 @@
+use crate::error::{Error, Result, ResultExt};
+use serde::Deserialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use super::download::{WgsBoundingBox, USER_AGENT};
+
+const NOMINATIM_API_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// The number of places to request, to report alternatives to the first one.
+const MAX_PLACE_COUNT: usize = 5;
+
+/// A search result of the Nominatim API, in the `jsonv2` format.
+#[derive(Deserialize, Debug)]
+struct NominatimPlace {
+    display_name: String,
+    /// The bounding box as strings of (south latitude, north latitude, west longitude, east longitude).
+    boundingbox: [String; 4],
+}
+
+/// Get the name of the file which caches the Nominatim response for a place query.
+fn get_filename_for_query(query: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    query.hash(&mut hasher);
+    format!("nominatim_{:016x}.json", hasher.finish())
+}
+
+/// Look up the bounding box of a named place, e.g. "Shibuya, Tokyo", with the Nominatim search API.
+///
+/// The response is cached in `cache_dir`, so a query is only sent once. If several places match, the first one is
+/// used and the others are logged.
+pub fn resolve_place_bbox(query: &str, cache_dir: &Path) -> Result<WgsBoundingBox> {
+    resolve_place_bbox_from(NOMINATIM_API_URL, query, cache_dir)
+}
+
+fn resolve_place_bbox_from(api_url: &str, query: &str, cache_dir: &Path) -> Result<WgsBoundingBox> {
+    let cache_filepath = cache_dir.join(get_filename_for_query(query));
+    let response = if cache_filepath.exists() {
+        log::info!(
+            "Local file exists for place '{}': {:?}",
+            query,
+            cache_filepath
+        );
+        fs::read_to_string(&cache_filepath)
+            .with_context(|| format!("Reading {:?}", cache_filepath))?
+    } else {
+        log::info!("Looking up place '{}' with Nominatim", query);
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()?;
+        let response = client
+            .get(format!("{api_url}/search"))
+            .query(&[
+                ("q", query),
+                ("format", "jsonv2"),
+                ("limit", &MAX_PLACE_COUNT.to_string()),
+            ])
+            .send()?
+            .error_for_status()?
+            .text()?;
+        // Parse before caching, so that an unusable response is not cached.
+        parse_place_bbox(&response, query)?;
+        fs::write(&cache_filepath, &response)
+            .with_context(|| format!("Writing {:?}", cache_filepath))?;
+        response
+    };
+    parse_place_bbox(&response, query)
+}
+
+/// Parse the bounding box of the first place of a Nominatim search response.
+fn parse_place_bbox(response: &str, query: &str) -> Result<WgsBoundingBox> {
+    let places: Vec<NominatimPlace> =
+        serde_json::from_str(response).context("Parsing the Nominatim response")?;
+    let (place, alternatives) = places
+        .split_first()
+        .ok_or_else(|| Error::OsmDownloadError {
+            message: format!("Nominatim found no place for '{}'", query),
+            source: None,
+        })?;
+    if !alternatives.is_empty() {
+        log::warn!(
+            "Nominatim found {} places for '{}', using '{}'. Alternatives: {}",
+            places.len(),
+            query,
+            place.display_name,
+            alternatives
+                .iter()
+                .map(|alternative| format!("'{}'", alternative.display_name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let [bottom_lat, top_lat, left_lon, right_lon] = &place.boundingbox;
+    Ok(WgsBoundingBox {
+        left_lon: left_lon.parse()?,
+        right_lon: right_lon.parse()?,
+        bottom_lat: bottom_lat.parse()?,
+        top_lat: top_lat.parse()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use testdir::testdir;
+
+    use crate::{error::Error, osm::download::WgsBoundingBox};
+
+    use super::{parse_place_bbox, resolve_place_bbox_from};
+
+    const SHIBUYA_RESPONSE: &str = r#"[
+  {"place_id": 1, "display_name": "Shibuya, Tokyo, Japan", "boundingbox": ["35.6432", "35.6920", "139.6612", "139.7234"]},
+  {"place_id": 2, "display_name": "Shibuya Station, Tokyo, Japan", "boundingbox": ["35.6570", "35.6600", "139.6990", "139.7030"]}
+]"#;
+
+    #[test]
+    fn test_resolve_place_bbox_caches_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // Serve a single request, so that a second request fails.
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header_line = String::new();
+            while header_line != "\r\n" {
+                header_line.clear();
+                reader.read_line(&mut header_line).unwrap();
+            }
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                SHIBUYA_RESPONSE.len(),
+                SHIBUYA_RESPONSE
+            )
+            .unwrap();
+            request_line
+        });
+        let cache_dir = testdir!();
+        let api_url = format!("http://{}", address);
+
+        let bbox = resolve_place_bbox_from(&api_url, "Shibuya, Tokyo", &cache_dir).unwrap();
+        let request_line = server.join().unwrap();
+        let cached_bbox = resolve_place_bbox_from(&api_url, "Shibuya, Tokyo", &cache_dir).unwrap();
+
+        let expected_bbox = WgsBoundingBox {
+            left_lon: 139.6612,
+            right_lon: 139.7234,
+            bottom_lat: 35.6432,
+            top_lat: 35.6920,
+        };
+        assert_eq!(expected_bbox, bbox);
+        assert_eq!(expected_bbox, cached_bbox);
+        assert!(
+            request_line.starts_with("GET /search?q=Shibuya%2C+Tokyo&format=jsonv2"),
+            "{}",
+            request_line
+        );
+        // Another query is not answered from the cache.
+        assert!(resolve_place_bbox_from(&api_url, "Shinjuku, Tokyo", &cache_dir).is_err());
+    }
+
+    #[test]
+    fn test_parse_place_bbox_without_places() {
+        let result = parse_place_bbox("[]", "Atlantis");
+
+        assert!(matches!(result, Err(Error::OsmDownloadError { .. })));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{Result, ResultExt},
+    geofile::{
+        feature::{FeatureMap, FieldValue},
+        gdal_geofile::append_attribute_table,
+    },
+    topo::topo::TopoParams,
+};
+
+/// Name of the table of the run info added to written GeoPackages, see `RunInfo::append_to_geofile`.
+pub const RUN_INFO_LAYER: &str = "run_info";
+
+/// An input file of a run, with the SHA-256 hash of its contents.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct InputFile {
+    /// What the file is read as, e.g. "proposal".
+    pub role: String,
+    pub path: PathBuf,
+    /// Hex encoded, `None` if the path is not a regular file, e.g. a directory of shapefiles.
+    pub sha256: Option<String>,
+}
+
+impl InputFile {
+    /// Hash the contents of the file at `path`.
+    pub fn new(role: impl Into<String>, path: &Path) -> Result<Self> {
+        let sha256 = if path.is_file() {
+            Some(sha256_of_file(path)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            role: role.into(),
+            path: path.to_path_buf(),
+            sha256,
+        })
+    }
+}
+
+/// Which tool version, parameters and inputs produced the outputs of a run, e.g. for audits of the written files.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct RunInfo {
+    pub crate_version: String,
+    /// The `git describe` output of the source tree the executable was built from, if it was built in a git checkout.
+    pub git_describe: Option<String>,
+    pub topo_params: TopoParams,
+    pub inputs: Vec<InputFile>,
+    /// Start of the run, in RFC 3339 format.
+    pub timestamp: String,
+}
+
+impl RunInfo {
+    /// The run info of a run starting now, with the version of this build.
+    pub fn new(topo_params: &TopoParams, inputs: Vec<InputFile>) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_describe: option_env!("TOPO_GIT_DESCRIBE").map(str::to_string),
+            topo_params: topo_params.clone(),
+            inputs,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("RunInfo is serializable to JSON")
+    }
+
+    /// The run info as the attributes of one row of a table. The parameters and the inputs are JSON strings.
+    pub fn to_attributes(&self) -> FeatureMap {
+        let json = self.to_json();
+        let mut attributes = FeatureMap::from([
+            (
+                "crate_version".to_string(),
+                FieldValue::StringValue(self.crate_version.clone()),
+            ),
+            (
+                "topo_params".to_string(),
+                FieldValue::StringValue(json["topo_params"].to_string()),
+            ),
+            (
+                "inputs".to_string(),
+                FieldValue::StringValue(json["inputs"].to_string()),
+            ),
+            (
+                "timestamp".to_string(),
+                FieldValue::StringValue(self.timestamp.clone()),
+            ),
+        ]);
+        if let Some(git_describe) = &self.git_describe {
+            attributes.insert(
+                "git_describe".to_string(),
+                FieldValue::StringValue(git_describe.clone()),
+            );
+        }
+        attributes
+    }
+
+    /// Add the run info as the single row table `run_info` to a written geofile which supports several layers, e.g.
+    /// a GeoPackage. Geofiles which were not written, e.g. as there were no features, are left out.
+    pub fn append_to_geofile(&self, filepath: &Path) -> Result<()> {
+        if !filepath.exists() {
+            return Ok(());
+        }
+        append_attribute_table(filepath, RUN_INFO_LAYER, &[self.to_attributes()])
+            .with_context(|| format!("Adding the run info to {:?}", filepath))
+    }
+}
+
+/// The hex encoded SHA-256 hash of the contents of a file.
+pub fn sha256_of_file(path: &Path) -> Result<String> {
+    let mut reader =
+        BufReader::new(File::open(path).with_context(|| format!("Opening {:?}", path))?);
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher).with_context(|| format!("Hashing {:?}", path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use testdir::testdir;
+
+    use crate::{
+        geofile::{
+            feature::Feature,
+            gdal_geofile::{
+                read_attribute_table, read_features_from_geofile, write_features_to_geofile,
+                GdalDriverType, WriteOptions,
+            },
+        },
+        topo::topo::TopoParams,
+    };
+
+    use super::{sha256_of_file, InputFile, RunInfo, RUN_INFO_LAYER};
+
+    #[rstest]
+    fn test_sha256_of_file() {
+        let path = testdir!().join("input.txt");
+        std::fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_of_file(&path).unwrap()
+        );
+    }
+
+    #[rstest]
+    fn test_run_info_round_trips_through_geopackage() {
+        let dir = testdir!();
+        let input_filepath = dir.join("proposal.geojson");
+        std::fs::write(&input_filepath, "{}").unwrap();
+        let topo_params = TopoParams {
+            proposal_dedup_radius: Some(0.5),
+            ..TopoParams::new_in_crs_units(11.0, 6.0)
+        };
+        let run_info = RunInfo::new(
+            &topo_params,
+            vec![InputFile::new("proposal", &input_filepath).unwrap()],
+        );
+        let gpkg_filepath = dir.join("nodes.gpkg");
+        write_features_to_geofile(
+            &vec![Feature::from(geo::Geometry::Point(geo::Point::new(
+                1.0, 2.0,
+            )))],
+            &gpkg_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        run_info.append_to_geofile(&gpkg_filepath).unwrap();
+
+        let rows = read_attribute_table(&gpkg_filepath, RUN_INFO_LAYER).unwrap();
+        assert_eq!(1, rows.len());
+        let value = |name: &str| rows[0][name].clone().into_string().unwrap();
+        let read_topo_params: TopoParams = serde_json::from_str(&value("topo_params")).unwrap();
+        assert_eq!(topo_params, read_topo_params);
+        let read_inputs: Vec<InputFile> = serde_json::from_str(&value("inputs")).unwrap();
+        assert_eq!(run_info.inputs, read_inputs);
+        assert_eq!(env!("CARGO_PKG_VERSION"), value("crate_version"));
+        assert_eq!(run_info.timestamp, value("timestamp"));
+        assert_eq!(
+            Some("44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"),
+            read_inputs[0].sha256.as_deref()
+        );
+        // The features of the geofile are kept.
+        let (features, _) = read_features_from_geofile(&gpkg_filepath).unwrap();
+        assert_eq!(1, features.len());
+    }
+}
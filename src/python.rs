@@ -0,0 +1,90 @@
+//! Python bindings of the lines-based TOPO functions, built as the `topo_rust` extension module with maturin.
+//!
+//! Geometries are passed as WKT in a projected CRS, whose units are those of the distances. Only the code paths
+//! which work on `geo` geometries are used, no GDAL datasets or spatial references.
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use wkt::TryFromWkt;
+
+use crate::topo::topo::{calculate_topo_from_lines, sample_line_coords, TopoParams};
+
+/// Parse a WKT LINESTRING.
+fn parse_wkt_line(wkt: &str) -> PyResult<geo::LineString> {
+    geo::LineString::try_from_wkt_str(wkt)
+        .map_err(|error| PyValueError::new_err(format!("Parsing WKT '{}': {}", wkt, error)))
+}
+
+fn parse_wkt_lines(wkts: Vec<String>) -> PyResult<Vec<geo::LineString>> {
+    wkts.iter().map(|wkt| parse_wkt_line(wkt)).collect()
+}
+
+/// Calculate the TOPO metric of two collections of WKT linestrings.
+///
+/// Returns a dict with the `precision`, `recall` and `f1_score`, and the node counts `proposal_count`,
+/// `ground_truth_count`, `matched_proposal_count` and `matched_ground_truth_count`.
+#[pyfunction]
+#[pyo3(name = "calculate_topo")]
+fn py_calculate_topo(
+    py: Python<'_>,
+    proposal_wkt_lines: Vec<String>,
+    gt_wkt_lines: Vec<String>,
+    resampling_distance: f64,
+    hole_radius: f64,
+) -> PyResult<PyObject> {
+    let proposal = parse_wkt_lines(proposal_wkt_lines)?;
+    let ground_truth = parse_wkt_lines(gt_wkt_lines)?;
+    let params = TopoParams {
+        resampling_distance,
+        hole_radius,
+        ..TopoParams::default()
+    };
+    params
+        .validate()
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+    let result = py
+        .allow_threads(|| calculate_topo_from_lines(&proposal, &ground_truth, &params))
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+
+    let ground_truth_nodes = result
+        .ground_truth_nodes
+        .iter()
+        .filter(|node| !node.excluded());
+    let scores = PyDict::new(py);
+    scores.set_item("precision", result.f1_score_result.precision())?;
+    scores.set_item("recall", result.f1_score_result.recall())?;
+    scores.set_item("f1_score", result.f1_score_result.f1_score())?;
+    scores.set_item("proposal_count", result.proposal_nodes.len())?;
+    scores.set_item("ground_truth_count", ground_truth_nodes.clone().count())?;
+    scores.set_item(
+        "matched_proposal_count",
+        result
+            .proposal_nodes
+            .iter()
+            .filter(|node| node.matched())
+            .count(),
+    )?;
+    scores.set_item(
+        "matched_ground_truth_count",
+        ground_truth_nodes.filter(|node| node.matched()).count(),
+    )?;
+    Ok(scores.into())
+}
+
+/// Sample points every `distance` on a WKT linestring, like the TOPO nodes of one edge.
+///
+/// Returns the sampled points as (x, y) tuples.
+#[pyfunction]
+fn sample_points(wkt: &str, distance: f64) -> PyResult<Vec<(f64, f64)>> {
+    let line = parse_wkt_line(wkt)?;
+    Ok(sample_line_coords(&line, distance)
+        .into_iter()
+        .map(|coord| (coord.x, coord.y))
+        .collect())
+}
+
+#[pymodule]
+fn topo_rust(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(py_calculate_topo, module)?)?;
+    module.add_function(wrap_pyfunction!(sample_points, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,898 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::error::{Error, Result, ResultExt};
+use geo::{BoundingRect, Centroid, ConvexHull};
+use kdtree::distance::squared_euclidean;
+
+use crate::{
+    geofile::feature::{Feature, FieldValue},
+    geograph::primitives::GeoGraph,
+};
+
+use super::topo::{
+    calculate_topo, EdgeAttributeData, TopoEvaluator, TopoParams, TopoResult,
+    DEFAULT_EVALUATOR_CACHE_CAPACITY,
+};
+
+/// Above this many distinct scores, the thresholds of a sweep are a subset of the scores at evenly spaced ranks.
+const MAX_SWEEP_THRESHOLDS: usize = 100;
+
+/// Number of bins of the coarse match distance histogram, e.g. of the result file of the executable.
+pub const COARSE_MATCH_DISTANCE_BIN_COUNT: usize = 10;
+
+/// Tolerance of the number of bins of a histogram, so that a hole radius which is a multiple of the bin width does
+/// not get an extra bin due to floating point errors.
+const BIN_COUNT_EPSILON: f64 = 1e-9;
+
+/// How to treat proposal edges which do not have a numeric score.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MissingScore {
+    /// Keep the edges at every threshold.
+    #[default]
+    Include,
+    /// Drop the edges at every threshold.
+    Exclude,
+}
+
+/// Configuration of a sweep over thresholds of a proposal edge score, see `score_threshold_sweep`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct ScoreThresholdSweepConfig {
+    /// The proposal edge attribute holding the score, e.g. "confidence".
+    pub attribute: String,
+    #[serde(default)]
+    pub missing_score: MissingScore,
+}
+
+/// The TOPO scores when only keeping proposal edges with a score of at least `threshold`.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ThresholdPoint {
+    pub threshold: f64,
+    pub proposal_edge_count: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1_score: f64,
+}
+
+/// Calculate the TOPO scores for a range of thresholds of a numeric proposal edge attribute. For each threshold, the
+/// proposal edges with a lower score are removed, and the proposal is matched to the ground truth again.
+///
+/// # Arguments
+/// * attribute - The name of the score attribute of the proposal edges.
+/// * missing_score - How to treat edges without a numeric score.
+///
+/// # Returns
+/// One point per threshold, in order of increasing threshold. The thresholds are the distinct scores of the edges.
+pub fn score_threshold_sweep<E, N, Ty>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    attribute: &str,
+    missing_score: MissingScore,
+    params: &TopoParams,
+) -> Result<Vec<ThresholdPoint>>
+where
+    E: Default + Clone + EdgeAttributeData,
+    N: Default,
+    Ty: petgraph::EdgeType,
+{
+    let edge_scores: Vec<Option<f64>> = proposal_graph
+        .edges_sorted()
+        .map(|(_, _, _, edge)| numeric_value(edge.data.attribute(attribute)?))
+        .collect();
+    let mut scores: Vec<f64> = edge_scores.iter().flatten().copied().collect();
+    scores.sort_by(f64::total_cmp);
+    scores.dedup();
+    let thresholds: Vec<f64> = if scores.len() > MAX_SWEEP_THRESHOLDS {
+        (0..MAX_SWEEP_THRESHOLDS)
+            .map(|idx| scores[idx * (scores.len() - 1) / (MAX_SWEEP_THRESHOLDS - 1)])
+            .collect()
+    } else {
+        scores
+    };
+
+    let mut points = Vec::new();
+    for threshold in thresholds {
+        log::info!("Calculating TOPO for {} threshold {}", attribute, threshold);
+        let mut filtered_graph = GeoGraph::new(proposal_graph.crs.clone());
+        for ((start_node_idx, end_node_idx, _, edge), score) in
+            proposal_graph.edges_sorted().zip(&edge_scores)
+        {
+            let keep = match score {
+                Some(score) => *score >= threshold,
+                None => missing_score == MissingScore::Include,
+            };
+            if keep {
+                filtered_graph.insert_edge_with_data(
+                    start_node_idx,
+                    end_node_idx,
+                    edge.geometry.clone(),
+                    edge.data.clone(),
+                )?;
+            }
+        }
+        let result = calculate_topo(&filtered_graph, ground_truth_graph, params, None, None)?;
+        points.push(ThresholdPoint {
+            threshold,
+            proposal_edge_count: filtered_graph.edge_count(),
+            precision: result.f1_score_result.precision(),
+            recall: result.f1_score_result.recall(),
+            f1_score: result.f1_score_result.f1_score(),
+        });
+    }
+    Ok(points)
+}
+
+/// The point with the best F1 score, if any.
+pub fn best_f1_threshold_point(points: &[ThresholdPoint]) -> Option<&ThresholdPoint> {
+    points
+        .iter()
+        .filter(|point| !point.f1_score.is_nan())
+        .max_by(|a, b| a.f1_score.total_cmp(&b.f1_score))
+}
+
+/// Write the points of a threshold sweep to a CSV file, one row per point.
+pub fn write_threshold_points_to_csv(
+    points: &[ThresholdPoint],
+    output_filepath: &Path,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_path(output_filepath)
+        .with_context(|| format!("Creating {:?}", output_filepath))?;
+    for point in points {
+        writer.serialize(point)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn default_parameter_sweep_cache_capacity() -> usize {
+    DEFAULT_EVALUATOR_CACHE_CAPACITY
+}
+
+/// Parameters of one evaluation of a parameter sweep, which replace those of the base parameters. Omitted ones keep
+/// their base values.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ParameterCombination {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resampling_distance: Option<f64>,
+    /// The hole radius of ground truth points whose road class has no entry in `TopoParams::hole_radius_by_class`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hole_radius: Option<f64>,
+}
+
+impl ParameterCombination {
+    /// The base parameters with those of the combination replaced.
+    pub fn apply(&self, params: &TopoParams) -> TopoParams {
+        TopoParams {
+            resampling_distance: self
+                .resampling_distance
+                .unwrap_or(params.resampling_distance),
+            hole_radius: self.hole_radius.unwrap_or(params.hole_radius),
+            ..params.clone()
+        }
+    }
+}
+
+/// Configuration of a sweep over combinations of TOPO parameters, see `parameter_sweep`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct ParameterSweepConfig {
+    /// The combinations to evaluate, in the order of the rows of the results.
+    pub combinations: Vec<ParameterCombination>,
+    /// Number of resampling distances whose sampled points are kept for the following combinations, see
+    /// `TopoEvaluator`.
+    #[serde(default = "default_parameter_sweep_cache_capacity")]
+    pub cache_capacity: usize,
+}
+
+impl ParameterSweepConfig {
+    /// Check that there are combinations, and that the base parameters with each of them are valid.
+    pub fn validate(&self, params: &TopoParams) -> Result<()> {
+        if self.combinations.is_empty() {
+            return Err(Error::ConfigError(
+                "The parameter sweep has no combinations".to_string(),
+            ));
+        }
+        for combination in &self.combinations {
+            combination
+                .apply(params)
+                .validate()
+                .with_context(|| format!("Parameter sweep combination {:?}", combination))?;
+        }
+        Ok(())
+    }
+}
+
+/// The TOPO scores with one combination of parameters of a sweep, whose distances are in the units of the base
+/// parameters.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ParameterSweepPoint {
+    pub resampling_distance: f64,
+    pub hole_radius: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1_score: f64,
+}
+
+/// Calculate the TOPO scores for each combination of the config, applied to `params`. The graphs are sampled once
+/// per resampling distance as long as its sampled nodes are cached, see `TopoEvaluator`, so that sweeping the hole
+/// radius costs only the matching.
+///
+/// # Returns
+/// One point per combination, in the order of the combinations.
+pub fn parameter_sweep<E, N, Ty>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    config: &ParameterSweepConfig,
+    params: &TopoParams,
+) -> Result<Vec<ParameterSweepPoint>>
+where
+    E: Default + EdgeAttributeData,
+    N: Default,
+    Ty: petgraph::EdgeType,
+{
+    config.validate(params)?;
+    let mut evaluator =
+        TopoEvaluator::new(proposal_graph, ground_truth_graph, config.cache_capacity);
+    let mut points = Vec::with_capacity(config.combinations.len());
+    for combination in &config.combinations {
+        let combination_params = combination.apply(params);
+        log::info!(
+            "Calculating TOPO with resampling distance {} and hole radius {}",
+            combination_params.resampling_distance,
+            combination_params.hole_radius
+        );
+        let result = evaluator.evaluate(&combination_params)?;
+        points.push(ParameterSweepPoint {
+            resampling_distance: combination_params.resampling_distance,
+            hole_radius: combination_params.hole_radius,
+            precision: result.f1_score_result.precision(),
+            recall: result.f1_score_result.recall(),
+            f1_score: result.f1_score_result.f1_score(),
+        });
+    }
+    Ok(points)
+}
+
+/// Write the points of a parameter sweep to a CSV file, one row per point.
+pub fn write_parameter_sweep_points_to_csv(
+    points: &[ParameterSweepPoint],
+    output_filepath: &Path,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_path(output_filepath)
+        .with_context(|| format!("Creating {:?}", output_filepath))?;
+    for point in points {
+        writer.serialize(point)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The largest hole radius of the matched proposal nodes, which bounds their match distances. `None` if no proposal
+/// node was matched.
+pub fn matched_hole_radius(result: &TopoResult) -> Option<f64> {
+    result
+        .proposal_nodes
+        .iter()
+        .filter(|node| node.matched() && !node.excluded())
+        .filter_map(|node| node.hole_radius())
+        .max_by(f64::total_cmp)
+}
+
+/// Histogram of the match distances of the matched proposal nodes, e.g. to calibrate the hole radius. Each node is
+/// counted once regardless of its weight, like for the scores, so that the counts add up to the true positives.
+///
+/// The bins cover [0, hole radius], where the hole radius is `matched_hole_radius`. Each bin includes its start, the
+/// last bin also includes the hole radius, and is narrower than `bin_width` if the hole radius is not a multiple of it.
+///
+/// # Returns
+/// The start and the count of each bin, in order of increasing start. Empty if no proposal node was matched or
+/// `bin_width` is not positive.
+pub fn match_distance_histogram(result: &TopoResult, bin_width: f64) -> Vec<(f64, usize)> {
+    let Some(hole_radius) = matched_hole_radius(result) else {
+        return Vec::new();
+    };
+    if bin_width.is_nan() || bin_width <= 0.0 {
+        return Vec::new();
+    }
+    let bin_count = ((hole_radius / bin_width - BIN_COUNT_EPSILON).ceil() as usize).max(1);
+    let mut counts = vec![0; bin_count];
+    for node in result
+        .proposal_nodes
+        .iter()
+        .filter(|node| node.matched() && !node.excluded())
+    {
+        let Some(distance) = node.match_distance() else {
+            continue;
+        };
+        let bin_idx = ((distance / bin_width).floor().max(0.0) as usize).min(bin_count - 1);
+        counts[bin_idx] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(bin_idx, count)| (bin_idx as f64 * bin_width, count))
+        .collect()
+}
+
+/// Write a histogram of `match_distance_histogram` to a CSV file with the columns `bin_start` and `count`.
+pub fn write_match_distance_histogram_to_csv(
+    histogram: &[(f64, usize)],
+    output_filepath: &Path,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_path(output_filepath)
+        .with_context(|| format!("Creating {:?}", output_filepath))?;
+    writer.write_record(["bin_start", "count"])?;
+    for bin in histogram {
+        writer.serialize(bin)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn default_missing_coverage_min_points() -> usize {
+    3
+}
+
+fn default_missing_coverage_top_n() -> usize {
+    10
+}
+
+/// Configuration of the areas of missing coverage, see `missing_coverage_clusters`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct MissingCoverageConfig {
+    /// Minimum number of unmatched ground truth nodes within twice the hole radius of a node, including itself, for
+    /// it to start or extend a cluster.
+    #[serde(default = "default_missing_coverage_min_points")]
+    pub min_points: usize,
+    /// Number of the largest clusters listed in the result file. All clusters are written to the polygon layer.
+    #[serde(default = "default_missing_coverage_top_n")]
+    pub top_n: usize,
+}
+
+impl Default for MissingCoverageConfig {
+    fn default() -> Self {
+        Self {
+            min_points: default_missing_coverage_min_points(),
+            top_n: default_missing_coverage_top_n(),
+        }
+    }
+}
+
+/// A cluster of unmatched ground truth nodes, i.e. an area where the proposal misses roads.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct MissingCoverageCluster {
+    /// The rank of the cluster by node count, starting at 1 for the largest one.
+    pub rank: usize,
+    pub node_count: usize,
+    /// Estimate of the length of the missed roads, the node count times the resampling distance.
+    pub missing_length: f64,
+    /// The centroid of the nodes.
+    pub centroid: (f64, f64),
+    /// The bounding box of the nodes as min x, min y, max x and max y.
+    pub bounding_box: [f64; 4],
+    /// The convex hull of the nodes. Degenerate for clusters of collinear nodes, e.g. along a straight road.
+    #[serde(skip)]
+    pub hull: geo::Polygon,
+}
+
+/// Cluster the unmatched ground truth nodes of a TOPO result into areas of missing coverage with DBSCAN. Nodes are
+/// neighbors within twice the largest hole radius, and nodes with at least `min_points` unmatched neighbors,
+/// including themselves, are the core nodes of clusters. Excluded nodes are ignored.
+///
+/// # Arguments
+/// * params - The parameters the result was calculated with, in the units of the CRS of the nodes, see
+///   `TopoParams::in_crs_units`.
+///
+/// # Returns
+/// The clusters in order of decreasing node count, ranked accordingly.
+pub fn missing_coverage_clusters(
+    result: &TopoResult,
+    params: &TopoParams,
+    min_points: usize,
+) -> Result<Vec<MissingCoverageCluster>> {
+    let coords: Vec<geo::Coord> = result
+        .ground_truth_nodes
+        .iter()
+        .filter(|node| !node.matched() && !node.excluded())
+        .map(|node| node.coord())
+        .collect();
+    let mut kdtree = kdtree::KdTree::with_capacity(2, coords.len());
+    for (idx, coord) in coords.iter().enumerate() {
+        kdtree.add(<[f64; 2]>::from(*coord), idx)?;
+    }
+    let squared_eps = (2.0 * params.max_hole_radius()).powi(2);
+    let neighbors = |idx: usize| -> Result<Vec<usize>> {
+        Ok(kdtree
+            .within(
+                &<[f64; 2]>::from(coords[idx]),
+                squared_eps,
+                &squared_euclidean,
+            )?
+            .into_iter()
+            .map(|(_, neighbor_idx)| *neighbor_idx)
+            .collect())
+    };
+
+    let mut cluster_ids: Vec<Option<usize>> = vec![None; coords.len()];
+    let mut visited = vec![false; coords.len()];
+    let mut cluster_count = 0;
+    for idx in 0..coords.len() {
+        if visited[idx] {
+            continue;
+        }
+        visited[idx] = true;
+        let mut queue = neighbors(idx)?;
+        if queue.len() < min_points {
+            // Noise, unless it is later reached from a core node.
+            continue;
+        }
+        let cluster_id = cluster_count;
+        cluster_count += 1;
+        cluster_ids[idx] = Some(cluster_id);
+        while let Some(neighbor_idx) = queue.pop() {
+            cluster_ids[neighbor_idx].get_or_insert(cluster_id);
+            if visited[neighbor_idx] {
+                continue;
+            }
+            visited[neighbor_idx] = true;
+            let neighbor_neighbors = neighbors(neighbor_idx)?;
+            if neighbor_neighbors.len() >= min_points {
+                queue.extend(neighbor_neighbors);
+            }
+        }
+    }
+
+    let mut cluster_coords: Vec<Vec<geo::Coord>> = vec![Vec::new(); cluster_count];
+    for (coord, cluster_id) in coords.iter().zip(cluster_ids) {
+        if let Some(cluster_id) = cluster_id {
+            cluster_coords[cluster_id].push(*coord);
+        }
+    }
+    // The sort is stable, so clusters of equal size stay in the order of their first node.
+    cluster_coords.sort_by_key(|coords| std::cmp::Reverse(coords.len()));
+    Ok(cluster_coords
+        .into_iter()
+        .enumerate()
+        .map(|(idx, coords)| {
+            let points = geo::MultiPoint::from(coords);
+            let centroid = points.centroid().unwrap();
+            let bounding_box = points.bounding_rect().unwrap();
+            MissingCoverageCluster {
+                rank: idx + 1,
+                node_count: points.0.len(),
+                missing_length: points.0.len() as f64 * params.resampling_distance,
+                centroid: centroid.x_y(),
+                bounding_box: [
+                    bounding_box.min().x,
+                    bounding_box.min().y,
+                    bounding_box.max().x,
+                    bounding_box.max().y,
+                ],
+                hull: points.convex_hull(),
+            }
+        })
+        .collect())
+}
+
+/// One polygon feature per cluster of `missing_coverage_clusters`, its convex hull with the attributes `rank`,
+/// `node_count` and `missing_length`.
+pub fn missing_coverage_features(clusters: &[MissingCoverageCluster]) -> Vec<Feature> {
+    clusters
+        .iter()
+        .map(|cluster| Feature {
+            geometry: geo::Geometry::Polygon(cluster.hull.clone()),
+            attributes: Some(HashMap::from([
+                (
+                    "rank".to_string(),
+                    FieldValue::Integer64Value(cluster.rank as i64),
+                ),
+                (
+                    "node_count".to_string(),
+                    FieldValue::Integer64Value(cluster.node_count as i64),
+                ),
+                (
+                    "missing_length".to_string(),
+                    FieldValue::RealValue(cluster.missing_length),
+                ),
+            ])),
+            null_fields: Default::default(),
+        })
+        .collect()
+}
+
+fn numeric_value(value: FieldValue) -> Option<f64> {
+    match value {
+        FieldValue::RealValue(value) => Some(value),
+        FieldValue::IntegerValue(value) => Some(value as f64),
+        FieldValue::Integer64Value(value) => Some(value as f64),
+        FieldValue::StringValue(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use testdir::testdir;
+
+    use crate::{
+        geofile::feature::{FeatureMap, FieldValue},
+        geograph::{
+            primitives::GeoGraph,
+            utils::{build_geograph_from_lines_in_crs, build_geograph_from_lines_with_data_in_crs},
+        },
+        topo::topo::{
+            calculate_topo, DistanceUnits, F1ScoreResult, TopoNode, TopoParams, TopoResult,
+        },
+    };
+
+    use super::{
+        best_f1_threshold_point, match_distance_histogram, missing_coverage_clusters,
+        missing_coverage_features, parameter_sweep, score_threshold_sweep,
+        write_match_distance_histogram_to_csv, write_parameter_sweep_points_to_csv,
+        write_threshold_points_to_csv, MissingScore, ParameterCombination, ParameterSweepConfig,
+    };
+
+    /// UTM zone 54N, a projected CRS in meters.
+    fn projected_crs() -> crate::crs::Crs {
+        crate::crs::Crs::Epsg(32654)
+    }
+
+    fn confidence_data(confidence: Option<f64>) -> FeatureMap {
+        confidence
+            .into_iter()
+            .map(|confidence| ("confidence".to_string(), FieldValue::RealValue(confidence)))
+            .collect()
+    }
+
+    fn two_edge_proposal(
+        good_confidence: Option<f64>,
+    ) -> GeoGraph<FeatureMap, (), petgraph::Undirected> {
+        // A good edge along the ground truth, and a hallucinated one far away.
+        build_geograph_from_lines_with_data_in_crs(
+            vec![
+                vec![(0.0, 1.0), (22.0, 1.0)].into(),
+                vec![(0.0, 100.0), (22.0, 100.0)].into(),
+            ],
+            vec![confidence_data(good_confidence), confidence_data(Some(0.1))],
+            projected_crs(),
+        )
+        .unwrap()
+    }
+
+    fn sweep_params() -> TopoParams {
+        TopoParams {
+            resampling_distance: 11.0,
+            hole_radius: 6.0,
+            matching_mode: Default::default(),
+            road_class_attribute: None,
+            proposal_road_class_attribute: None,
+            hole_radius_by_class: None,
+            evaluation_mask: None,
+            units: DistanceUnits::CrsUnits,
+            anisotropy_warning_latitude: 60.0,
+            propagate_edge_attributes: Vec::new(),
+            include_original_vertices: false,
+            max_network_detour_factor: None,
+            deduplicate_proposal_points: false,
+            proposal_dedup_radius: None,
+            exclude_near_junction_distance: None,
+            collapse_reciprocal_edges: false,
+            sampling_phase_offsets: None,
+            directional_matching: false,
+            low_memory: false,
+        }
+    }
+
+    #[test]
+    fn test_score_threshold_sweep() {
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+
+        let points = score_threshold_sweep(
+            &two_edge_proposal(Some(0.9)),
+            &ground_truth_graph,
+            "confidence",
+            MissingScore::Include,
+            &sweep_params(),
+        )
+        .unwrap();
+
+        let actual_points: Vec<(f64, f64, f64)> = points
+            .iter()
+            .map(|point| (point.threshold, point.precision, point.recall))
+            .collect();
+        // Without the hallucinated edge, the precision is perfect.
+        assert_eq!(vec![(0.1, 0.5, 1.0), (0.9, 1.0, 1.0)], actual_points);
+        assert_eq!(0.9, best_f1_threshold_point(&points).unwrap().threshold);
+
+        let csv_filepath = testdir!().join("sweep.csv");
+        write_threshold_points_to_csv(&points, &csv_filepath).unwrap();
+        let csv_contents = std::fs::read_to_string(csv_filepath).unwrap();
+        assert!(csv_contents.starts_with("threshold,proposal_edge_count,precision,recall,f1_score"));
+        assert_eq!(3, csv_contents.lines().count());
+    }
+
+    #[rstest]
+    #[case(MissingScore::Include, 1.0)]
+    #[case(MissingScore::Exclude, 0.0)]
+    fn test_score_threshold_sweep_missing_score(
+        #[case] missing_score: MissingScore,
+        #[case] expected_recall: f64,
+    ) {
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+
+        let points = score_threshold_sweep(
+            &two_edge_proposal(None),
+            &ground_truth_graph,
+            "confidence",
+            missing_score,
+            &sweep_params(),
+        )
+        .unwrap();
+
+        assert_eq!(1, points.len());
+        assert_eq!(expected_recall, points[0].recall);
+    }
+
+    #[test]
+    fn test_parameter_sweep() {
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (44.0, 0.0)].into(),
+                    vec![(0.0, 50.0), (44.0, 50.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        // One edge close to the ground truth, and one farther away.
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 1.0), (44.0, 1.0)].into(),
+                    vec![(0.0, 54.0), (44.0, 54.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let config = ParameterSweepConfig {
+            combinations: [5.5, 11.0]
+                .into_iter()
+                .flat_map(|resampling_distance| {
+                    [2.0, 6.0].map(|hole_radius| ParameterCombination {
+                        resampling_distance: Some(resampling_distance),
+                        hole_radius: Some(hole_radius),
+                    })
+                })
+                .collect(),
+            cache_capacity: 1,
+        };
+
+        let points = parameter_sweep(
+            &proposal_graph,
+            &ground_truth_graph,
+            &config,
+            &sweep_params(),
+        )
+        .unwrap();
+
+        assert_eq!(4, points.len());
+        for (point, combination) in points.iter().zip(&config.combinations) {
+            let params = combination.apply(&sweep_params());
+            let result =
+                calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+            assert_eq!(
+                (params.resampling_distance, params.hole_radius),
+                (point.resampling_distance, point.hole_radius)
+            );
+            assert_eq!(
+                (
+                    result.f1_score_result.precision(),
+                    result.f1_score_result.recall(),
+                    result.f1_score_result.f1_score()
+                ),
+                (point.precision, point.recall, point.f1_score)
+            );
+        }
+        // Only the larger hole radius reaches the farther proposal edge.
+        assert_eq!(
+            vec![0.5, 1.0, 0.5, 1.0],
+            points.iter().map(|point| point.recall).collect::<Vec<_>>()
+        );
+
+        let csv_filepath = testdir!().join("parameter_sweep.csv");
+        write_parameter_sweep_points_to_csv(&points, &csv_filepath).unwrap();
+        let csv_contents = std::fs::read_to_string(csv_filepath).unwrap();
+        assert!(
+            csv_contents.starts_with("resampling_distance,hole_radius,precision,recall,f1_score")
+        );
+        assert_eq!(5, csv_contents.lines().count());
+    }
+
+    #[rstest]
+    #[case(Vec::new())]
+    #[case(vec![ParameterCombination { resampling_distance: Some(0.0), hole_radius: None }])]
+    fn test_parameter_sweep_config_validate_fails(#[case] combinations: Vec<ParameterCombination>) {
+        let config = ParameterSweepConfig {
+            combinations,
+            cache_capacity: 1,
+        };
+
+        assert!(config.validate(&sweep_params()).is_err());
+    }
+
+    /// A matched proposal node with the given match distance and hole radius, via its serialized form.
+    fn matched_proposal_node(id: u64, match_distance: f64, hole_radius: f64) -> TopoNode {
+        serde_json::from_value(serde_json::json!({
+            "road_point": {"coord": {"x": 0.0, "y": 0.0}, "azimuth": 0.0, "edge_idx": 0},
+            "id": id,
+            "matched": true,
+            "match_distance": match_distance,
+            "hole_radius": hole_radius,
+            "excluded": false,
+            "edge_attributes": [],
+        }))
+        .unwrap()
+    }
+
+    fn result_with_match_distances(match_distances: &[f64], hole_radius: f64) -> TopoResult {
+        TopoResult {
+            f1_score_result: F1ScoreResult::from_json(
+                r#"{"precision": 1.0, "recall": 1.0, "f1_score": 1.0}"#,
+            )
+            .unwrap(),
+            unmasked_f1_score_result: None,
+            class_breakdown: None,
+            ground_truth_nodes: Vec::new(),
+            proposal_nodes: match_distances
+                .iter()
+                .enumerate()
+                .map(|(id, distance)| matched_proposal_node(id as u64, *distance, hole_radius))
+                .collect(),
+            stage_metrics: Vec::new(),
+            phase_offset_scores: None,
+        }
+    }
+
+    #[rstest]
+    #[case(&[0.4, 1.6, 1.7], 2.0, 1.0, vec![(0.0, 1), (1.0, 2)])]
+    // A distance equal to the hole radius is in the last bin.
+    #[case(&[0.0, 2.0], 2.0, 1.0, vec![(0.0, 1), (1.0, 1)])]
+    // The last bin is narrower than the bin width.
+    #[case(&[2.4, 2.5], 2.5, 1.0, vec![(0.0, 0), (1.0, 0), (2.0, 2)])]
+    #[case(&[0.3], 0.3, 0.1, vec![(0.0, 0), (0.1, 0), (0.2, 1)])]
+    #[case(&[], 2.0, 1.0, vec![])]
+    fn test_match_distance_histogram(
+        #[case] match_distances: &[f64],
+        #[case] hole_radius: f64,
+        #[case] bin_width: f64,
+        #[case] expected_histogram: Vec<(f64, usize)>,
+    ) {
+        let result = result_with_match_distances(match_distances, hole_radius);
+
+        let histogram = match_distance_histogram(&result, bin_width);
+
+        let counts: Vec<usize> = histogram.iter().map(|(_, count)| *count).collect();
+        let expected_counts: Vec<usize> =
+            expected_histogram.iter().map(|(_, count)| *count).collect();
+        assert_eq!(expected_counts, counts);
+        for ((bin_start, _), (expected_bin_start, _)) in histogram.iter().zip(&expected_histogram) {
+            assert!((bin_start - expected_bin_start).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_match_distance_histogram_counts_true_positives() {
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (110.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        // The same road drawn twice, so that the deduplicated proposal nodes have a weight of 2.
+        let proposal_graph = build_geograph_from_lines_in_crs(
+            vec![
+                vec![(0.0, 0.2), (110.0, 0.2)].into(),
+                vec![(0.0, 0.4), (110.0, 0.4)].into(),
+            ],
+            projected_crs(),
+        )
+        .unwrap();
+        let params = TopoParams {
+            deduplicate_proposal_points: true,
+            ..sweep_params()
+        };
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        assert!(result.proposal_nodes.iter().all(|node| node.weight() == 2));
+
+        let histogram = match_distance_histogram(&result, 1.0);
+
+        let true_positive_count = (result.f1_score_result.precision()
+            * result.proposal_nodes.len() as f64)
+            .round() as usize;
+        assert!(true_positive_count > 0);
+        assert_eq!(
+            true_positive_count,
+            histogram.iter().map(|(_, count)| count).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_write_match_distance_histogram_to_csv() {
+        let csv_filepath = testdir!().join("histogram.csv");
+
+        write_match_distance_histogram_to_csv(&[(0.0, 1), (1.0, 2)], &csv_filepath).unwrap();
+
+        assert_eq!(
+            "bin_start,count\n0.0,1\n1.0,2\n",
+            std::fs::read_to_string(csv_filepath).unwrap()
+        );
+    }
+
+    /// A ground truth node at the given location, via its serialized form.
+    fn ground_truth_node(id: u64, x: f64, y: f64, matched: bool) -> TopoNode {
+        serde_json::from_value(serde_json::json!({
+            "road_point": {"coord": {"x": x, "y": y}, "azimuth": 0.0, "edge_idx": 0},
+            "id": id,
+            "matched": matched,
+            "match_distance": null,
+            "hole_radius": 6.0,
+            "excluded": false,
+            "edge_attributes": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_missing_coverage_clusters() {
+        let locations = [
+            // A missed road of five nodes, and a matched node next to it.
+            (0.0, 0.0, false),
+            (11.0, 0.0, false),
+            (22.0, 0.0, false),
+            (33.0, 0.0, false),
+            (44.0, 0.0, false),
+            (55.0, 0.0, true),
+            // A single unmatched node, which is noise.
+            (500.0, 0.0, false),
+            // A missed square of four nodes.
+            (1000.0, 0.0, false),
+            (1010.0, 0.0, false),
+            (1000.0, 10.0, false),
+            (1010.0, 10.0, false),
+        ];
+        let mut result = result_with_match_distances(&[], 6.0);
+        result.ground_truth_nodes = locations
+            .iter()
+            .enumerate()
+            .map(|(id, (x, y, matched))| ground_truth_node(id as u64, *x, *y, *matched))
+            .collect();
+
+        let clusters = missing_coverage_clusters(&result, &sweep_params(), 3).unwrap();
+
+        let summary: Vec<(usize, usize, f64)> = clusters
+            .iter()
+            .map(|cluster| (cluster.rank, cluster.node_count, cluster.missing_length))
+            .collect();
+        assert_eq!(vec![(1, 5, 55.0), (2, 4, 44.0)], summary);
+        assert_eq!((22.0, 0.0), clusters[0].centroid);
+        assert_eq!([1000.0, 0.0, 1010.0, 10.0], clusters[1].bounding_box);
+        assert_eq!(100.0, geo::Area::unsigned_area(&clusters[1].hull));
+
+        let features = missing_coverage_features(&clusters);
+        assert_eq!(2, features.len());
+        assert_eq!(
+            Some(&FieldValue::Integer64Value(2)),
+            features[1].attributes.as_ref().unwrap().get("rank")
+        );
+    }
+}
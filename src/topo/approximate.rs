@@ -0,0 +1,519 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use geo::BoundingRect;
+
+use crate::{
+    error::{Error, Result},
+    geograph::primitives::GeoGraph,
+};
+
+use super::topo::{F1ScoreResult, TopoParams, TopoResult};
+
+/// Default edge length of the square tiles of an approximate evaluation, in CRS units, e.g. meters.
+pub const DEFAULT_APPROXIMATE_TILE_SIZE: f64 = 1000.0;
+
+/// Default number of bootstrap resamples of the sampled tiles for the interval of the approximate F1 score.
+pub const DEFAULT_BOOTSTRAP_SAMPLES: usize = 200;
+
+/// Upper bound of the number of tiles covering the graphs, to fail fast on a tile size far too small for the area.
+const MAX_TILE_COUNT: u64 = 1 << 24;
+
+fn default_approximate_tile_size() -> f64 {
+    DEFAULT_APPROXIMATE_TILE_SIZE
+}
+
+fn default_bootstrap_samples() -> usize {
+    DEFAULT_BOOTSTRAP_SAMPLES
+}
+
+/// Configuration of an approximate evaluation on a random subset of square tiles of both graphs, to get an estimate
+/// of the scores of large inputs quickly.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct ApproximateConfig {
+    /// The fraction of tiles to evaluate, in (0, 1].
+    pub fraction: f64,
+    /// Seed of the tile selection and of the bootstrap. The same seed selects the same tiles for the same inputs.
+    pub seed: u64,
+    /// Edge length of the square tiles, in the units of the distances of the `TopoParams`, i.e. in meters by
+    /// default.
+    #[serde(default = "default_approximate_tile_size")]
+    pub tile_size: f64,
+    /// Number of bootstrap resamples of the sampled tiles for the interval of the F1 score.
+    #[serde(default = "default_bootstrap_samples")]
+    pub bootstrap_samples: usize,
+}
+
+impl ApproximateConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.fraction > 0.0 && self.fraction <= 1.0) {
+            return Err(Error::ConfigError(format!(
+                "The approximate fraction must be in (0, 1], but is {}",
+                self.fraction
+            )));
+        }
+        if !(self.tile_size.is_finite() && self.tile_size > 0.0) {
+            return Err(Error::ConfigError(format!(
+                "The approximate tile_size must be positive, but is {}",
+                self.tile_size
+            )));
+        }
+        if self.bootstrap_samples == 0 {
+            return Err(Error::ConfigError(
+                "The approximate bootstrap_samples must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The tiles selected for an approximate evaluation, see `subsample_graphs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileSample {
+    tile_size: f64,
+    /// The number of tiles covering the bounding box of both graphs.
+    tile_count: usize,
+    /// The indices of the selected tiles, as in `floor(coord / tile_size)`.
+    tiles: BTreeSet<(i64, i64)>,
+}
+
+impl TileSample {
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+
+    pub fn sampled_tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    fn tile_index(&self, coord: geo::Coord) -> (i64, i64) {
+        (
+            (coord.x / self.tile_size).floor() as i64,
+            (coord.y / self.tile_size).floor() as i64,
+        )
+    }
+
+    /// Whether any selected tile intersects `rect` grown by `margin` on each side.
+    fn intersects(&self, rect: geo::Rect, margin: f64) -> bool {
+        let (min_x, min_y) = self.tile_index(geo::coord! {
+            x: rect.min().x - margin,
+            y: rect.min().y - margin,
+        });
+        let (max_x, max_y) = self.tile_index(geo::coord! {
+            x: rect.max().x + margin,
+            y: rect.max().y + margin,
+        });
+        self.tiles
+            .range((min_x, min_y)..=(max_x, max_y))
+            .any(|(_, y_idx)| (min_y..=max_y).contains(y_idx))
+    }
+}
+
+/// The graphs of an approximate evaluation, with the tiles they were subsampled to.
+pub struct SubsampledGraphs<E: Default, N: Default, Ty: petgraph::EdgeType> {
+    pub proposal_graph: GeoGraph<E, N, Ty>,
+    pub ground_truth_graph: GeoGraph<E, N, Ty>,
+    pub tiles: TileSample,
+}
+
+/// The scores of an approximate evaluation, with the sampling parameters they were estimated with.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct ApproximateScores {
+    pub fraction: f64,
+    pub seed: u64,
+    pub tile_size: f64,
+    /// The number of tiles covering the bounding box of both graphs.
+    pub tile_count: usize,
+    pub sampled_tile_count: usize,
+    /// The scores of the nodes within the sampled tiles.
+    pub f1_score_result: F1ScoreResult,
+    /// The 2.5th and 97.5th percentile of the F1 score over bootstrap resamples of the sampled tiles.
+    pub f1_score_interval: [f64; 2],
+    pub bootstrap_samples: usize,
+}
+
+/// Mix the bits of `state` after advancing it, see the SplitMix64 generator. Implemented here instead of taking a
+/// random number crate, so that the tile selection of a seed does not change with a dependency update.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut value = *state;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Map random bits to a uniform value in [0, 1).
+fn unit_interval(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A uniform value in [0, 1) which only depends on the seed and the tile index.
+fn tile_value(seed: u64, (x_idx, y_idx): (i64, i64)) -> f64 {
+    let mut state = seed;
+    state ^= splitmix64(&mut (x_idx as u64));
+    state ^= splitmix64(&mut (y_idx as u64)).rotate_left(32);
+    unit_interval(splitmix64(&mut state))
+}
+
+/// Select a random `config.fraction` of the square tiles of `tile_size` CRS units covering the bounding box of both
+/// graphs. At least one tile is selected.
+fn sample_tiles<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    config: &ApproximateConfig,
+    tile_size: f64,
+) -> Result<TileSample> {
+    let bounding_box = match (
+        proposal_graph.bounding_box(),
+        ground_truth_graph.bounding_box(),
+    ) {
+        (Some(proposal), Some(ground_truth)) => geo::Rect::new(
+            geo::coord! {
+                x: proposal.min().x.min(ground_truth.min().x),
+                y: proposal.min().y.min(ground_truth.min().y),
+            },
+            geo::coord! {
+                x: proposal.max().x.max(ground_truth.max().x),
+                y: proposal.max().y.max(ground_truth.max().y),
+            },
+        ),
+        (Some(rect), None) | (None, Some(rect)) => rect,
+        (None, None) => {
+            return Err(Error::invalid_data(
+                "Cannot sample tiles of two empty graphs",
+            ))
+        }
+    };
+    let mut sample = TileSample {
+        tile_size,
+        tile_count: 0,
+        tiles: BTreeSet::new(),
+    };
+    let (min_x, min_y) = sample.tile_index(bounding_box.min());
+    let (max_x, max_y) = sample.tile_index(bounding_box.max());
+    let tile_count = (max_x - min_x + 1) as u64 * (max_y - min_y + 1) as u64;
+    if tile_count > MAX_TILE_COUNT {
+        return Err(Error::ConfigError(format!(
+            "The approximate tile_size {} results in {} tiles, more than the maximum of {}",
+            config.tile_size, tile_count, MAX_TILE_COUNT
+        )));
+    }
+    sample.tile_count = tile_count as usize;
+
+    let mut lowest_tile = None;
+    for x_idx in min_x..=max_x {
+        for y_idx in min_y..=max_y {
+            let value = tile_value(config.seed, (x_idx, y_idx));
+            if value < config.fraction {
+                sample.tiles.insert((x_idx, y_idx));
+            }
+            if lowest_tile.is_none_or(|(lowest_value, _)| value < lowest_value) {
+                lowest_tile = Some((value, (x_idx, y_idx)));
+            }
+        }
+    }
+    if sample.tiles.is_empty() {
+        sample.tiles.extend(lowest_tile.map(|(_, tile)| tile));
+    }
+    Ok(sample)
+}
+
+/// The edges of `graph` which come within `margin` of a selected tile.
+fn subsample_graph<E: Default + Clone, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    tiles: &TileSample,
+    margin: f64,
+) -> Result<GeoGraph<E, N, Ty>> {
+    let mut subsampled_graph = GeoGraph::new(graph.crs.clone());
+    for (start_node_idx, end_node_idx, _, edge) in graph.edges_sorted() {
+        let keep = edge
+            .geometry
+            .bounding_rect()
+            .is_some_and(|rect| tiles.intersects(rect, margin));
+        if keep {
+            subsampled_graph.insert_edge_with_data(
+                start_node_idx,
+                end_node_idx,
+                edge.geometry.clone(),
+                edge.data.clone(),
+            )?;
+        }
+    }
+    Ok(subsampled_graph)
+}
+
+/// Subsample both graphs to the same random subset of square tiles, see `ApproximateConfig`.
+///
+/// The graphs keep the edges within the largest hole radius of a selected tile, so that the nodes within the tiles
+/// find the same matches as in the full graphs. Evaluate the subsampled graphs with `calculate_topo`, and estimate the
+/// scores from the nodes within the tiles with `approximate_scores`. The tile size is converted to the units of the
+/// CRS like the distances of `params`, see `TopoParams::crs_units_factor`.
+pub fn subsample_graphs<E: Default + Clone, N: Default, Ty: petgraph::EdgeType>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    config: &ApproximateConfig,
+) -> Result<SubsampledGraphs<E, N, Ty>> {
+    config.validate()?;
+    let margin = params.in_crs_units(ground_truth_graph)?.max_hole_radius();
+    let tile_size = config.tile_size * params.crs_units_factor(ground_truth_graph)?;
+    let tiles = sample_tiles(proposal_graph, ground_truth_graph, config, tile_size)?;
+    Ok(SubsampledGraphs {
+        proposal_graph: subsample_graph(proposal_graph, &tiles, margin)?,
+        ground_truth_graph: subsample_graph(ground_truth_graph, &tiles, margin)?,
+        tiles,
+    })
+}
+
+/// Matched and total node counts within one sampled tile.
+#[derive(Clone, Copy, Default)]
+struct TileCounts {
+    ground_truth_matched: usize,
+    ground_truth_total: usize,
+    proposal_matched: usize,
+    proposal_total: usize,
+}
+
+impl TileCounts {
+    fn add(&mut self, other: &Self) {
+        self.ground_truth_matched += other.ground_truth_matched;
+        self.ground_truth_total += other.ground_truth_total;
+        self.proposal_matched += other.proposal_matched;
+        self.proposal_total += other.proposal_total;
+    }
+
+    fn f1_score_result(&self) -> F1ScoreResult {
+        F1ScoreResult::from_matched_counts(
+            self.proposal_matched,
+            self.proposal_total,
+            self.ground_truth_matched,
+            self.ground_truth_total,
+        )
+    }
+}
+
+/// Estimate the scores of the full graphs from the result of `calculate_topo` on graphs subsampled to `tiles`.
+///
+/// Only the nodes within the tiles are counted. The interval of the F1 score is a percentile bootstrap over the
+/// tiles, so it reflects how much the score varies between areas, but not any bias of the tile selection.
+pub fn approximate_scores(
+    result: &TopoResult,
+    tiles: &TileSample,
+    config: &ApproximateConfig,
+) -> ApproximateScores {
+    let mut tile_counts: BTreeMap<(i64, i64), TileCounts> = tiles
+        .tiles
+        .iter()
+        .map(|tile| (*tile, TileCounts::default()))
+        .collect();
+    for node in result
+        .ground_truth_nodes
+        .iter()
+        .filter(|node| !node.excluded())
+    {
+        if let Some(counts) = tile_counts.get_mut(&tiles.tile_index(node.coord())) {
+            counts.ground_truth_total += 1;
+            counts.ground_truth_matched += node.matched() as usize;
+        }
+    }
+    for node in &result.proposal_nodes {
+        if let Some(counts) = tile_counts.get_mut(&tiles.tile_index(node.coord())) {
+            counts.proposal_total += 1;
+            counts.proposal_matched += node.matched() as usize;
+        }
+    }
+    let tile_counts: Vec<TileCounts> = tile_counts.into_values().collect();
+
+    let mut total_counts = TileCounts::default();
+    for counts in &tile_counts {
+        total_counts.add(counts);
+    }
+
+    let mut state = config.seed;
+    let mut bootstrap_f1_scores: Vec<f64> = (0..config.bootstrap_samples)
+        .map(|_| {
+            let mut resample_counts = TileCounts::default();
+            for _ in 0..tile_counts.len() {
+                let position =
+                    (unit_interval(splitmix64(&mut state)) * tile_counts.len() as f64) as usize;
+                resample_counts.add(&tile_counts[position]);
+            }
+            resample_counts.f1_score_result().f1_score()
+        })
+        .filter(|f1_score| !f1_score.is_nan())
+        .collect();
+    bootstrap_f1_scores.sort_by(f64::total_cmp);
+    let percentile = |fraction: f64| {
+        if bootstrap_f1_scores.is_empty() {
+            f64::NAN
+        } else {
+            bootstrap_f1_scores
+                [(fraction * (bootstrap_f1_scores.len() - 1) as f64).round() as usize]
+        }
+    };
+
+    ApproximateScores {
+        fraction: config.fraction,
+        seed: config.seed,
+        tile_size: tiles.tile_size,
+        tile_count: tiles.tile_count,
+        sampled_tile_count: tiles.tiles.len(),
+        f1_score_result: total_counts.f1_score_result(),
+        f1_score_interval: [percentile(0.025), percentile(0.975)],
+        bootstrap_samples: config.bootstrap_samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        geograph::{
+            primitives::GeoGraph,
+            utils::{build_geograph_from_lines, build_geograph_from_lines_in_crs},
+        },
+        topo::topo::{calculate_topo, DistanceUnits, TopoParams},
+    };
+
+    use super::{approximate_scores, subsample_graphs, ApproximateConfig, ApproximateScores};
+
+    /// UTM zone 54N, a projected CRS in meters.
+    fn projected_crs() -> crate::crs::Crs {
+        crate::crs::Crs::Epsg(32654)
+    }
+
+    /// A 1 km square grid of roads every 50 m, offset by `offset`, without every `skip_every`th east-west road.
+    fn grid_graph(
+        skip_every: Option<usize>,
+        offset: f64,
+    ) -> GeoGraph<(), (), petgraph::Undirected> {
+        let mut lines = Vec::new();
+        for idx in 0..=20 {
+            let position = idx as f64 * 50.0 + offset;
+            if skip_every.is_none_or(|skip_every| idx % skip_every != skip_every - 1) {
+                lines.push(vec![(0.0, position), (1000.0, position)].into());
+            }
+            lines.push(vec![(position, 0.0), (position, 1000.0)].into());
+        }
+        build_geograph_from_lines_in_crs(lines, projected_crs()).unwrap()
+    }
+
+    fn evaluate_approximately(
+        proposal_graph: &GeoGraph<(), (), petgraph::Undirected>,
+        ground_truth_graph: &GeoGraph<(), (), petgraph::Undirected>,
+        params: &TopoParams,
+        config: &ApproximateConfig,
+    ) -> ApproximateScores {
+        let subsampled =
+            subsample_graphs(proposal_graph, ground_truth_graph, params, config).unwrap();
+        let result = calculate_topo(
+            &subsampled.proposal_graph,
+            &subsampled.ground_truth_graph,
+            params,
+            None,
+            None,
+        )
+        .unwrap();
+        approximate_scores(&result, &subsampled.tiles, config)
+    }
+
+    #[test]
+    fn test_approximate_scores_close_to_exact_scores() {
+        let ground_truth_graph = grid_graph(None, 0.0);
+        // Every 5th east-west road is missing, which is one road per tile row.
+        let proposal_graph = grid_graph(Some(5), 2.0);
+        let params = TopoParams::new_in_crs_units(11.0, 6.0);
+        let config = ApproximateConfig {
+            fraction: 0.3,
+            seed: 1,
+            tile_size: 250.0,
+            bootstrap_samples: 200,
+        };
+
+        let exact_f1_score =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None)
+                .unwrap()
+                .f1_score_result
+                .f1_score();
+        let scores = evaluate_approximately(&proposal_graph, &ground_truth_graph, &params, &config);
+
+        assert_eq!(25, scores.tile_count);
+        assert!(scores.sampled_tile_count < scores.tile_count);
+        let f1_score = scores.f1_score_result.f1_score();
+        assert!(
+            (f1_score - exact_f1_score).abs() < 0.02,
+            "Approximate F1 score {} too far from {}",
+            f1_score,
+            exact_f1_score
+        );
+        assert!(scores.f1_score_interval[0] <= f1_score);
+        assert!(f1_score <= scores.f1_score_interval[1]);
+        // The same seed selects the same tiles and bootstrap resamples.
+        assert_eq!(
+            scores,
+            evaluate_approximately(&proposal_graph, &ground_truth_graph, &params, &config)
+        );
+    }
+
+    #[test]
+    fn test_subsample_graphs_converts_tile_size() {
+        // A grid of about 1 km in Tokyo, in EPSG:4326.
+        let graph: GeoGraph<(), (), petgraph::Undirected> = build_geograph_from_lines(
+            (0..=10)
+                .flat_map(|idx| {
+                    let position = idx as f64 * 0.001;
+                    [
+                        vec![(139.78, 35.68 + position), (139.79, 35.68 + position)].into(),
+                        vec![(139.78 + position, 35.68), (139.78 + position, 35.69)].into(),
+                    ]
+                })
+                .collect(),
+        )
+        .unwrap();
+        let params = TopoParams {
+            units: DistanceUnits::Meters,
+            ..TopoParams::new_in_crs_units(11.0, 6.0)
+        };
+        let config = ApproximateConfig {
+            fraction: 0.5,
+            seed: 1,
+            tile_size: 250.0,
+            bootstrap_samples: 10,
+        };
+
+        let tiles = subsample_graphs(&graph, &graph, &params, &config)
+            .unwrap()
+            .tiles;
+
+        // The tile size in meters is converted to degrees like the hole radius.
+        let crs_units_tiles = subsample_graphs(
+            &graph,
+            &graph,
+            &TopoParams {
+                units: DistanceUnits::CrsUnits,
+                ..params.in_crs_units(&graph).unwrap()
+            },
+            &ApproximateConfig {
+                tile_size: 250.0 * params.crs_units_factor(&graph).unwrap(),
+                ..config
+            },
+        )
+        .unwrap()
+        .tiles;
+        assert_eq!(crs_units_tiles, tiles);
+        assert!(tiles.tile_count() >= 16);
+    }
+
+    #[test]
+    fn test_approximate_config_validate() {
+        let config = |fraction: f64, tile_size: f64| ApproximateConfig {
+            fraction,
+            seed: 0,
+            tile_size,
+            bootstrap_samples: 10,
+        };
+        assert!(config(1.0, 100.0).validate().is_ok());
+        assert!(config(0.0, 100.0).validate().is_err());
+        assert!(config(1.5, 100.0).validate().is_err());
+        assert!(config(0.5, 0.0).validate().is_err());
+        assert!(config(0.5, f64::NAN).validate().is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Token for cooperatively cancelling a long-running computation, e.g. `calculate_topo`.
+///
+/// Clones share the same flag, so the token can be handed to another thread or a signal handler, which can then
+/// cancel the computation by calling `cancel`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. The computation holding the token will stop at its next check.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Check if an optional token is cancelled. A missing token is never cancelled.
+pub fn is_cancelled(token: Option<&CancellationToken>) -> bool {
+    matches!(token, Some(token) if token.is_cancelled())
+}
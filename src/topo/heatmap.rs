@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::geofile::feature::{Feature, FieldValue};
+
+use super::topo::{TopoNode, TopoResult};
+
+/// Default edge length of heatmap grid cells, in CRS units, e.g. meters.
+pub const DEFAULT_HEATMAP_CELL_SIZE: f64 = 250.0;
+
+fn default_heatmap_cell_size() -> f64 {
+    DEFAULT_HEATMAP_CELL_SIZE
+}
+
+/// Configuration of the heatmap of local TOPO metrics.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct HeatmapConfig {
+    /// Edge length of the square grid cells, in CRS units.
+    #[serde(default = "default_heatmap_cell_size")]
+    pub cell_size: f64,
+}
+
+/// Matched and total node counts within one grid cell.
+#[derive(Default)]
+struct CellCounts {
+    ground_truth_matched: usize,
+    ground_truth_total: usize,
+    proposal_matched: usize,
+    proposal_total: usize,
+}
+
+/// Bin the nodes of a TOPO result into a regular grid of square cells, and calculate the recall and precision of each
+/// cell.
+///
+/// # Returns
+/// One square polygon feature per cell which contains at least one node. The attributes are the node counts, the
+/// recall if the cell has ground truth nodes, and the precision if it has proposal nodes.
+pub fn grid_metrics(result: &TopoResult, cell_size: f64) -> Vec<Feature> {
+    // Ordered by cell index to get a deterministic feature order.
+    let mut cells: BTreeMap<(i64, i64), CellCounts> = BTreeMap::new();
+    let cell_index = |node: &TopoNode| -> (i64, i64) {
+        let coord = node.coord();
+        (
+            (coord.x / cell_size).floor() as i64,
+            (coord.y / cell_size).floor() as i64,
+        )
+    };
+    for node in result
+        .ground_truth_nodes
+        .iter()
+        .filter(|node| !node.excluded())
+    {
+        let counts = cells.entry(cell_index(node)).or_default();
+        counts.ground_truth_total += 1;
+        counts.ground_truth_matched += node.matched() as usize;
+    }
+    for node in &result.proposal_nodes {
+        let counts = cells.entry(cell_index(node)).or_default();
+        counts.proposal_total += 1;
+        counts.proposal_matched += node.matched() as usize;
+    }
+
+    cells
+        .into_iter()
+        .map(|((x_idx, y_idx), counts)| {
+            let min_x = x_idx as f64 * cell_size;
+            let min_y = y_idx as f64 * cell_size;
+            let geometry =
+                geo::Rect::new((min_x, min_y), (min_x + cell_size, min_y + cell_size)).to_polygon();
+
+            let mut attributes = HashMap::from([
+                (
+                    "ground_truth_matched".to_string(),
+                    FieldValue::Integer64Value(counts.ground_truth_matched as i64),
+                ),
+                (
+                    "ground_truth_total".to_string(),
+                    FieldValue::Integer64Value(counts.ground_truth_total as i64),
+                ),
+                (
+                    "proposal_matched".to_string(),
+                    FieldValue::Integer64Value(counts.proposal_matched as i64),
+                ),
+                (
+                    "proposal_total".to_string(),
+                    FieldValue::Integer64Value(counts.proposal_total as i64),
+                ),
+            ]);
+            if counts.ground_truth_total > 0 {
+                attributes.insert(
+                    "recall".to_string(),
+                    FieldValue::RealValue(
+                        counts.ground_truth_matched as f64 / counts.ground_truth_total as f64,
+                    ),
+                );
+            }
+            if counts.proposal_total > 0 {
+                attributes.insert(
+                    "precision".to_string(),
+                    FieldValue::RealValue(
+                        counts.proposal_matched as f64 / counts.proposal_total as f64,
+                    ),
+                );
+            }
+            Feature {
+                geometry: geo::Geometry::Polygon(geometry),
+                attributes: Some(attributes),
+                null_fields: Default::default(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        geofile::feature::{Feature, FieldValue},
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_in_crs},
+        topo::{
+            matching::MatchingMode,
+            topo::{calculate_topo, DistanceUnits, TopoParams},
+        },
+    };
+
+    use super::grid_metrics;
+
+    fn real_attribute(feature: &Feature, name: &str) -> Option<f64> {
+        match feature.attributes.as_ref().unwrap().get(name) {
+            Some(FieldValue::RealValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_grid_metrics() {
+        let crs = crate::crs::Crs::Epsg(32654);
+        // Two ground truth roads in separate cells, only the first one is in the proposal.
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(10.0, 10.0), (90.0, 10.0)].into(),
+                    vec![(310.0, 10.0), (390.0, 10.0)].into(),
+                ],
+                crs.clone(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(vec![vec![(10.0, 11.0), (90.0, 11.0)].into()], crs)
+                .unwrap();
+        let params = TopoParams {
+            resampling_distance: 10.0,
+            hole_radius: 5.0,
+            matching_mode: MatchingMode::Greedy,
+            road_class_attribute: None,
+            proposal_road_class_attribute: None,
+            hole_radius_by_class: None,
+            evaluation_mask: None,
+            units: DistanceUnits::CrsUnits,
+            anisotropy_warning_latitude: 60.0,
+            propagate_edge_attributes: Vec::new(),
+            include_original_vertices: false,
+            max_network_detour_factor: None,
+            deduplicate_proposal_points: false,
+            proposal_dedup_radius: None,
+            exclude_near_junction_distance: None,
+            collapse_reciprocal_edges: false,
+            sampling_phase_offsets: None,
+            directional_matching: false,
+            low_memory: false,
+        };
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        let cells = grid_metrics(&result, 250.0);
+
+        assert_eq!(2, cells.len());
+        let geo::Geometry::Polygon(first_cell) = &cells[0].geometry else {
+            panic!("Cell is not a polygon");
+        };
+        assert_eq!(
+            geo::Rect::new((0.0, 0.0), (250.0, 250.0)).to_polygon(),
+            *first_cell
+        );
+        assert_eq!(Some(1.0), real_attribute(&cells[0], "recall"));
+        assert_eq!(Some(1.0), real_attribute(&cells[0], "precision"));
+        assert_eq!(Some(0.0), real_attribute(&cells[1], "recall"));
+        // The second cell has no proposal nodes.
+        assert_eq!(None, real_attribute(&cells[1], "precision"));
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::Path,
+};
+
+use crate::error::{Result, ResultExt};
+
+use super::topo::StageTracker;
+
+/// Above this many proposal and ground truth nodes in one connected component of the candidate graph, optimal
+/// assignment is considered too expensive and the component is matched greedily instead. See
+/// `solve_component_assignment` for the cost of a component.
+const MAX_OPTIMAL_ASSIGNMENT_COMPONENT_SIZE: usize = 2000;
+
+/// Strategy for matching proposal nodes to ground truth nodes within hole radius.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MatchingMode {
+    /// Match each proposal node in order to the closest ground truth node which is not matched yet.
+    #[default]
+    Greedy,
+    /// Find the one-to-one assignment which maximizes the number of matches, and among those minimizes the total
+    /// match distance.
+    OptimalAssignment,
+}
+
+/// Ground truth nodes within hole radius of one proposal node, as (squared distance, ground truth node index) pairs
+/// in order of increasing distance.
+pub type MatchCandidates = Vec<(f64, usize)>;
+
+/// A ground truth node within hole radius of a proposal node, which the proposal node may be matched to.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct CandidatePair {
+    /// The id of the proposal node, see `TopoNode::id`.
+    pub proposal_node_id: u64,
+    /// The id of the ground truth node, see `TopoNode::id`.
+    pub gt_node_id: u64,
+    pub distance: f64,
+    /// The angle between the edges of the nodes at the nodes, between 0 and PI/2.
+    pub azimuth_diff: f64,
+}
+
+/// Why a ground truth node near a proposal node was not matched to it.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// The ground truth node is farther than its hole radius.
+    OutOfRadius,
+    /// The ground truth node is outside the evaluation mask.
+    Excluded,
+    /// The edges of the nodes differ in azimuth by more than `MAX_COMPATIBLE_AZIMUTH_DIFFERENCE`, if
+    /// `TopoParams::max_network_detour_factor` is set.
+    AzimuthGate,
+    /// The match compatibility rejected the pair for another reason, e.g. different road classes.
+    Incompatible,
+    /// The edges of the nodes head in different directions, by more than `MAX_DIRECTIONAL_HEADING_DIFFERENCE`, if
+    /// `TopoParams::directional_matching` is set.
+    DirectionMismatch,
+    /// The ground truth node was matched to another proposal node.
+    AlreadyMatched,
+    /// The proposal node was matched to another ground truth node.
+    OtherCandidateChosen,
+}
+
+impl std::fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RejectionReason::OutOfRadius => "out_of_radius",
+            RejectionReason::Excluded => "excluded",
+            RejectionReason::AzimuthGate => "azimuth_gate",
+            RejectionReason::Incompatible => "incompatible",
+            RejectionReason::DirectionMismatch => "direction_mismatch",
+            RejectionReason::AlreadyMatched => "already_matched",
+            RejectionReason::OtherCandidateChosen => "other_candidate_chosen",
+        })
+    }
+}
+
+/// Receiver of the match candidates of all proposal nodes, before any of them are matched, e.g. to run other
+/// assignment algorithms on them.
+pub trait CandidateSink {
+    fn write(&mut self, candidate: &CandidatePair) -> Result<()>;
+    /// Called after all candidates were written.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Writes match candidates as CSV rows, as they are received.
+pub struct CsvCandidateWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl CsvCandidateWriter<File> {
+    pub fn create(output_filepath: &Path) -> Result<Self> {
+        let writer = csv::Writer::from_path(output_filepath)
+            .with_context(|| format!("Creating {:?}", output_filepath))?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> CsvCandidateWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+}
+
+impl<W: Write> CandidateSink for CsvCandidateWriter<W> {
+    fn write(&mut self, candidate: &CandidatePair) -> Result<()> {
+        self.writer.serialize(candidate)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A matched pair of a proposal and a ground truth node.
+#[derive(Debug, PartialEq)]
+pub struct NodeMatch {
+    pub proposal_idx: usize,
+    pub ground_truth_idx: usize,
+    pub distance: f64,
+}
+
+/// Match proposal nodes to ground truth nodes.
+///
+/// # Arguments
+/// * candidates - The match candidates of each proposal node, indexed by proposal node index.
+/// * mode - The matching strategy.
+pub(crate) fn match_nodes(
+    candidates: &[MatchCandidates],
+    mode: MatchingMode,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<NodeMatch>> {
+    let progress = stage_tracker.progress();
+    let matches = match mode {
+        MatchingMode::Greedy => {
+            progress.begin("match", candidates.len() as u64);
+            match_greedy(candidates, 0..candidates.len(), stage_tracker, true)
+        }
+        MatchingMode::OptimalAssignment => match_optimal(candidates, stage_tracker),
+    };
+    progress.end();
+    matches
+}
+
+/// Greedily match the given proposal nodes in order, each to its closest unmatched candidate.
+///
+/// If `report_progress` is set, each proposal node is reported to the progress sink of `stage_tracker`.
+fn match_greedy(
+    candidates: &[MatchCandidates],
+    proposal_indices: impl Iterator<Item = usize>,
+    stage_tracker: &StageTracker,
+    report_progress: bool,
+) -> Result<Vec<NodeMatch>> {
+    let mut matched_gt_ids = HashSet::new();
+    let mut matches = Vec::new();
+    for proposal_idx in proposal_indices {
+        stage_tracker.check_cancelled()?;
+        for (squared_distance, gt_idx) in &candidates[proposal_idx] {
+            if matched_gt_ids.insert(*gt_idx) {
+                matches.push(NodeMatch {
+                    proposal_idx,
+                    ground_truth_idx: *gt_idx,
+                    distance: squared_distance.sqrt(),
+                });
+                break;
+            }
+        }
+        if report_progress {
+            stage_tracker.progress().advance(1);
+        }
+    }
+    Ok(matches)
+}
+
+/// Solve the assignment problem separately for each connected component of the bipartite candidate graph.
+fn match_optimal(
+    candidates: &[MatchCandidates],
+    stage_tracker: &StageTracker,
+) -> Result<Vec<NodeMatch>> {
+    let components = find_candidate_components(candidates);
+    let mut matches = Vec::new();
+    stage_tracker
+        .progress()
+        .begin("match", components.len() as u64);
+    for (proposal_indices, gt_indices) in components {
+        stage_tracker.check_cancelled()?;
+        if proposal_indices.len() + gt_indices.len() > MAX_OPTIMAL_ASSIGNMENT_COMPONENT_SIZE {
+            log::warn!(
+                "Candidate component with {} proposal and {} ground truth nodes is too large for optimal \
+                assignment, matching it greedily",
+                proposal_indices.len(),
+                gt_indices.len()
+            );
+            matches.extend(match_greedy(
+                candidates,
+                proposal_indices.into_iter(),
+                stage_tracker,
+                false,
+            )?);
+        } else {
+            matches.extend(solve_component_assignment(
+                candidates,
+                &proposal_indices,
+                &gt_indices,
+            ));
+        }
+        stage_tracker.progress().advance(1);
+    }
+    Ok(matches)
+}
+
+/// Find the connected components of the bipartite graph formed by proposal nodes, ground truth nodes and the match
+/// candidate relations between them. Proposal nodes without candidates are left out.
+///
+/// # Returns
+/// The sorted proposal and ground truth node indices of each component.
+fn find_candidate_components(candidates: &[MatchCandidates]) -> Vec<(Vec<usize>, Vec<usize>)> {
+    // Union-find over proposal nodes, which are indexed first, and ground truth nodes, indexed after them.
+    let num_proposals = candidates.len();
+    let mut parents: HashMap<usize, usize> = HashMap::new();
+    fn find(parents: &mut HashMap<usize, usize>, idx: usize) -> usize {
+        let parent = *parents.entry(idx).or_insert(idx);
+        if parent == idx {
+            return idx;
+        }
+        let root = find(parents, parent);
+        parents.insert(idx, root);
+        root
+    }
+    for (proposal_idx, proposal_candidates) in candidates.iter().enumerate() {
+        for (_, gt_idx) in proposal_candidates {
+            let proposal_root = find(&mut parents, proposal_idx);
+            let gt_root = find(&mut parents, num_proposals + gt_idx);
+            if proposal_root != gt_root {
+                parents.insert(gt_root, proposal_root);
+            }
+        }
+    }
+
+    let mut components: HashMap<usize, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    let mut members: Vec<usize> = parents.keys().copied().collect();
+    members.sort();
+    for member in members {
+        let root = find(&mut parents, member);
+        let component = components.entry(root).or_default();
+        if member < num_proposals {
+            component.0.push(member);
+        } else {
+            component.1.push(member - num_proposals);
+        }
+    }
+    let mut components: Vec<_> = components.into_values().collect();
+    components.sort();
+    components
+}
+
+/// Solve the min-cost assignment for one connected component by successive shortest augmenting paths over the
+/// candidate pairs. Each augmentation adds a match along the path which increases the total match distance the least,
+/// so that the final assignment has the maximum number of matches, and among those the minimum total match distance.
+///
+/// Only the candidate pairs are visited, so a component with `m` matches and `c` candidate pairs of `n` nodes costs
+/// O(m c log n).
+fn solve_component_assignment(
+    candidates: &[MatchCandidates],
+    proposal_indices: &[usize],
+    gt_indices: &[usize],
+) -> Vec<NodeMatch> {
+    let gt_positions: HashMap<usize, usize> = gt_indices
+        .iter()
+        .enumerate()
+        .map(|(position, gt_idx)| (*gt_idx, position))
+        .collect();
+    // The candidate pairs of each proposal row, as (distance, ground truth column) pairs.
+    let edges: Vec<Vec<(f64, usize)>> = proposal_indices
+        .iter()
+        .map(|proposal_idx| {
+            candidates[*proposal_idx]
+                .iter()
+                .map(|(squared_distance, gt_idx)| (squared_distance.sqrt(), gt_positions[gt_idx]))
+                .collect()
+        })
+        .collect();
+    let num_rows = proposal_indices.len();
+    let num_cols = gt_indices.len();
+    // The column and distance of the match of each row, and the row of the match of each column.
+    let mut row_matches: Vec<Option<(usize, f64)>> = vec![None; num_rows];
+    let mut col_matches: Vec<Option<usize>> = vec![None; num_cols];
+    // Potentials of the rows and the columns, which keep the reduced costs of the residual edges non-negative, so that
+    // Dijkstra's algorithm finds the shortest augmenting paths. Nodes are indexed rows first, columns after them.
+    let mut potentials = vec![0.0; num_rows + num_cols];
+    loop {
+        let mut distances = vec![f64::INFINITY; num_rows + num_cols];
+        // The row from which each column was reached.
+        let mut previous_rows = vec![0; num_cols];
+        let mut queue = BinaryHeap::new();
+        for row in (0..num_rows).filter(|row| row_matches[*row].is_none()) {
+            distances[row] = 0.0;
+            queue.push(QueueEntry {
+                distance: 0.0,
+                node: row,
+            });
+        }
+        let mut free_col = None;
+        while let Some(QueueEntry { distance, node }) = queue.pop() {
+            if distance > distances[node] {
+                continue;
+            }
+            let mut relax = |node: usize, reduced_cost: f64, queue: &mut BinaryHeap<QueueEntry>| {
+                // Clamped to absorb rounding errors of the potentials.
+                let next_distance = distance + reduced_cost.max(0.0);
+                if next_distance < distances[node] {
+                    distances[node] = next_distance;
+                    queue.push(QueueEntry {
+                        distance: next_distance,
+                        node,
+                    });
+                    true
+                } else {
+                    false
+                }
+            };
+            if node < num_rows {
+                let row = node;
+                for (cost, col) in &edges[row] {
+                    if row_matches[row].map(|(matched_col, _)| matched_col) == Some(*col) {
+                        continue;
+                    }
+                    let reduced_cost = cost + potentials[row] - potentials[num_rows + col];
+                    if relax(num_rows + col, reduced_cost, &mut queue) {
+                        previous_rows[*col] = row;
+                    }
+                }
+            } else {
+                let col = node - num_rows;
+                match col_matches[col] {
+                    None => {
+                        free_col = Some((col, distance));
+                        break;
+                    }
+                    Some(row) => {
+                        // The matched pair can be undone, which gains back its distance.
+                        let (_, cost) = row_matches[row].unwrap();
+                        let reduced_cost = -cost + potentials[node] - potentials[row];
+                        relax(row, reduced_cost, &mut queue);
+                    }
+                }
+            }
+        }
+        let Some((mut col, path_distance)) = free_col else {
+            break;
+        };
+        for (potential, distance) in potentials.iter_mut().zip(&distances) {
+            *potential += distance.min(path_distance);
+        }
+        // Augment along the path, from the free column back to the free row it starts at.
+        loop {
+            let row = previous_rows[col];
+            let cost = edges[row]
+                .iter()
+                .find(|(_, edge_col)| *edge_col == col)
+                .map(|(cost, _)| *cost)
+                .unwrap();
+            let previous_match = row_matches[row].replace((col, cost));
+            col_matches[col] = Some(row);
+            match previous_match {
+                Some((previous_col, _)) => col = previous_col,
+                None => break,
+            }
+        }
+    }
+
+    row_matches
+        .into_iter()
+        .enumerate()
+        .filter_map(|(row, row_match)| {
+            row_match.map(|(col, distance)| NodeMatch {
+                proposal_idx: proposal_indices[row],
+                ground_truth_idx: gt_indices[col],
+                distance,
+            })
+        })
+        .collect()
+}
+
+/// A node of the candidate graph in the queue of Dijkstra's algorithm, ordered by increasing distance and ties by
+/// node index, so that the assignment is deterministic.
+#[derive(PartialEq)]
+struct QueueEntry {
+    distance: f64,
+    node: usize,
+}
+
+impl Eq for QueueEntry {}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, as BinaryHeap is a max-heap.
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection, prelude::*};
+    use rstest::rstest;
+
+    use crate::topo::topo::StageTracker;
+
+    use super::{match_nodes, MatchCandidates, MatchingMode};
+
+    /// Candidates where greedy matching in proposal order finds two matches, but three are possible.
+    fn three_by_three_candidates() -> Vec<MatchCandidates> {
+        vec![
+            vec![(1.0, 0), (4.0, 1)],
+            vec![(1.0, 0)],
+            vec![(1.0, 1), (9.0, 2)],
+        ]
+    }
+
+    #[rstest]
+    #[case(MatchingMode::Greedy, vec![(0, 0), (2, 1)])]
+    #[case(MatchingMode::OptimalAssignment, vec![(0, 1), (1, 0), (2, 2)])]
+    fn test_match_nodes_three_by_three(
+        #[case] mode: MatchingMode,
+        #[case] expected_pairs: Vec<(usize, usize)>,
+    ) {
+        let matches = match_nodes(
+            &three_by_three_candidates(),
+            mode,
+            &StageTracker::new(None, None),
+        )
+        .unwrap();
+        let mut pairs: Vec<(usize, usize)> = matches
+            .iter()
+            .map(|node_match| (node_match.proposal_idx, node_match.ground_truth_idx))
+            .collect();
+        pairs.sort();
+        assert_eq!(expected_pairs, pairs);
+    }
+
+    #[test]
+    fn test_optimal_assignment_minimizes_distance() {
+        // Both assignments match two pairs, the crossed one has a lower total distance.
+        let candidates = vec![vec![(1.0, 0), (4.0, 1)], vec![(1.0, 0), (16.0, 1)]];
+        let matches = match_nodes(
+            &candidates,
+            MatchingMode::OptimalAssignment,
+            &StageTracker::new(None, None),
+        )
+        .unwrap();
+        let mut pairs: Vec<(usize, usize)> = matches
+            .iter()
+            .map(|node_match| (node_match.proposal_idx, node_match.ground_truth_idx))
+            .collect();
+        pairs.sort();
+        assert_eq!(vec![(0, 1), (1, 0)], pairs);
+    }
+
+    #[test]
+    fn test_optimal_assignment_prefers_the_closer_competitor() {
+        // Both proposal nodes only have the same ground truth node as candidate, the second one is closer.
+        let candidates = vec![vec![(25.0, 0)], vec![(1.0, 0)]];
+        let matches = match_nodes(
+            &candidates,
+            MatchingMode::OptimalAssignment,
+            &StageTracker::new(None, None),
+        )
+        .unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!(
+            (1, 0),
+            (matches[0].proposal_idx, matches[0].ground_truth_idx)
+        );
+        assert_eq!(1.0, matches[0].distance);
+    }
+
+    /// The largest number of matches, and the smallest total distance of the assignments with that many matches, by
+    /// trying every assignment.
+    fn brute_force_assignment(
+        candidates: &[MatchCandidates],
+        proposal_idx: usize,
+        used: &mut Vec<usize>,
+    ) -> (usize, f64) {
+        if proposal_idx == candidates.len() {
+            return (0, 0.0);
+        }
+        let mut best = brute_force_assignment(candidates, proposal_idx + 1, used);
+        for (squared_distance, gt_idx) in &candidates[proposal_idx] {
+            if used.contains(gt_idx) {
+                continue;
+            }
+            used.push(*gt_idx);
+            let (count, distance) = brute_force_assignment(candidates, proposal_idx + 1, used);
+            used.pop();
+            let (count, distance) = (count + 1, distance + squared_distance.sqrt());
+            if count > best.0 || (count == best.0 && distance < best.1) {
+                best = (count, distance);
+            }
+        }
+        best
+    }
+
+    fn small_candidates() -> impl Strategy<Value = Vec<MatchCandidates>> {
+        collection::vec(collection::btree_map(0..6usize, 0.0..100.0f64, 0..4), 0..7).prop_map(
+            |candidates| {
+                candidates
+                    .into_iter()
+                    .map(|candidates| {
+                        let mut candidates: MatchCandidates = candidates
+                            .into_iter()
+                            .map(|(gt_idx, squared_distance)| (squared_distance, gt_idx))
+                            .collect();
+                        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+                        candidates
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn test_optimal_assignment_matches_brute_force(candidates in small_candidates()) {
+            let matches = match_nodes(
+                &candidates,
+                MatchingMode::OptimalAssignment,
+                &StageTracker::new(None, None),
+            )
+            .unwrap();
+
+            let (expected_count, expected_distance) =
+                brute_force_assignment(&candidates, 0, &mut Vec::new());
+            let mut matched_gt_indices: Vec<usize> =
+                matches.iter().map(|node_match| node_match.ground_truth_idx).collect();
+            matched_gt_indices.sort();
+            matched_gt_indices.dedup();
+            prop_assert_eq!(matches.len(), matched_gt_indices.len());
+            prop_assert_eq!(expected_count, matches.len());
+            let distance: f64 = matches.iter().map(|node_match| node_match.distance).sum();
+            prop_assert!((expected_distance - distance).abs() < 1e-9, "{} != {}", expected_distance, distance);
+        }
+    }
+}
@@ -1,2 +1,11 @@
+pub mod analysis;
+pub mod approximate;
+pub mod cancellation;
+pub mod heatmap;
+pub mod matching;
 pub mod preprocessing;
+#[cfg(feature = "gdal-io")]
+pub mod report;
+mod spill;
 pub mod topo;
+pub mod trace;
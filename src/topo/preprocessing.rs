@@ -1,37 +1,729 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+use geo::{EuclideanDistance, EuclideanLength, Intersects};
+
+#[cfg(feature = "gdal-io")]
+use crate::geograph::utils::{get_projected_crs_for_graph, project_geograph};
 use crate::{
-    crs::crs_utils::{epsg_code_to_authority_string, EpsgCode},
+    crs::{
+        crs_utils::{epsg_code_to_authority_string, proj_definition, EpsgCode, Projection},
+        Crs,
+    },
+    diagnostics::warnings::{warn, WarningCategory, WarningCollector},
+    geofile::feature::FieldValue,
     geograph::{
-        primitives::GeoGraph,
-        utils::{get_utm_zone_for_graph, project_geograph},
+        cleanup::{extend_distinct, split_edges_at_near_nodes, IndexedNode},
+        primitives::{GeoGraph, NodeIdx},
     },
+    topo::topo::{sample_line_coords, EdgeAttributeData},
 };
 
-pub fn ensure_gt_proposal_in_same_projected_crs<
-    E: Default,
-    N: Default,
-    Ty: petgraph::EdgeType,
->(
+/// Project both graphs to the same projected CRS: to the CRS of the ground truth if it is projected, otherwise to the
+/// CRS of the kind `projection` for the ground truth, see `get_projected_crs_for_graph`.
+#[cfg(feature = "gdal-io")]
+pub fn ensure_gt_proposal_in_same_projected_crs<E: Default, N: Default, Ty: petgraph::EdgeType>(
     gt_graph: &mut GeoGraph<E, N, Ty>,
     proposal_graph: &mut GeoGraph<E, N, Ty>,
-) -> anyhow::Result<()> {
+    projection: Projection,
+) -> Result<()> {
     if gt_graph.crs.is_projected() {
-        if gt_graph.crs.auth_code()? != proposal_graph.crs.auth_code()? {
-            log::info!(
-                "Projecting proposal graph to {}",
-                epsg_code_to_authority_string(gt_graph.crs.auth_code()? as EpsgCode)
-            );
+        if proj_definition(&gt_graph.crs)? != proj_definition(&proposal_graph.crs)? {
+            log::info!("Projecting proposal graph to {}", crs_label(&gt_graph.crs)?);
             project_geograph(proposal_graph, &gt_graph.crs)?;
         }
     } else {
-        let utm_zone = get_utm_zone_for_graph(&gt_graph)?;
+        let projected_crs = get_projected_crs_for_graph(gt_graph, projection)?;
 
         log::info!(
             "Projecting ground truth and proposal lines to {}",
-            epsg_code_to_authority_string(utm_zone.auth_code()? as EpsgCode)
+            crs_label(&projected_crs)?
         );
 
-        project_geograph(gt_graph, &utm_zone)?;
-        project_geograph(proposal_graph, &utm_zone)?;
+        project_geograph(gt_graph, &projected_crs)?;
+        project_geograph(proposal_graph, &projected_crs)?;
+    }
+    Ok(())
+}
+
+/// Without the `gdal-io` feature graphs cannot be projected, so both graphs must already be in the same projected
+/// CRS, and `projection` is not used.
+#[cfg(not(feature = "gdal-io"))]
+pub fn ensure_gt_proposal_in_same_projected_crs<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    gt_graph: &mut GeoGraph<E, N, Ty>,
+    proposal_graph: &mut GeoGraph<E, N, Ty>,
+    _projection: Projection,
+) -> Result<()> {
+    if gt_graph.crs.is_projected()
+        && proj_definition(&gt_graph.crs)? == proj_definition(&proposal_graph.crs)?
+    {
+        return Ok(());
+    }
+    Err(Error::CrsMismatch(format!(
+        "The ground truth in {} and the proposal in {} must be in the same projected CRS, projecting them requires \
+        the gdal-io feature",
+        crs_label(&gt_graph.crs)?,
+        crs_label(&proposal_graph.crs)?
+    )))
+}
+
+/// A short label of a CRS for log messages, its EPSG authority string or otherwise its PROJ string. Without the
+/// `gdal-io` feature, CRSs without an EPSG code are labelled with their name.
+pub fn crs_label(crs: &Crs) -> Result<String> {
+    match crs.auth_code() {
+        Ok(auth_code) => Ok(epsg_code_to_authority_string(auth_code as EpsgCode)),
+        #[cfg(feature = "gdal-io")]
+        Err(_) => Ok(crs.to_proj4()?),
+        #[cfg(not(feature = "gdal-io"))]
+        Err(_) => crs.name(),
     }
+}
+
+/// Normalize the edge orientation of both graphs with `GeoGraph::normalize_edge_orientation`, so that the points
+/// sampled on an edge do not depend on the direction its geometry was digitized in.
+pub fn normalize_gt_proposal_edge_orientation<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    gt_graph: &mut GeoGraph<E, N, Ty>,
+    proposal_graph: &mut GeoGraph<E, N, Ty>,
+) {
+    gt_graph.normalize_edge_orientation();
+    proposal_graph.normalize_edge_orientation();
+}
+
+/// Split the edges of each graph where they pass within `tolerance` of a node of the other one, with
+/// `split_edges_at_near_nodes`. Junctions which only one of the graphs models with a node then get a node in both, so
+/// that the points sampled from the junction in one graph have counterparts in the other.
+pub fn split_gt_proposal_edges_at_near_nodes<E, N, Ty>(
+    gt_graph: &mut GeoGraph<E, N, Ty>,
+    proposal_graph: &mut GeoGraph<E, N, Ty>,
+    tolerance: f64,
+) -> Result<()>
+where
+    E: Default + Clone,
+    N: Default,
+    Ty: petgraph::EdgeType,
+{
+    let proposal_split_count = split_edges_at_near_nodes(proposal_graph, gt_graph, tolerance)?;
+    let gt_split_count = split_edges_at_near_nodes(gt_graph, proposal_graph, tolerance)?;
+    log::info!(
+        "Split proposal edges at {} and ground truth edges at {} points near nodes of the other graph",
+        proposal_split_count,
+        gt_split_count
+    );
     Ok(())
 }
+
+/// Snap the dangling endpoints of the proposal, i.e. its nodes of degree 1, to the nearest ground truth node within
+/// `distance`, e.g. for proposals which stop roads a few meters short of junctions. The incident proposal edge is
+/// extended with a straight segment to the ground truth node, which becomes the position of the proposal node.
+/// Returns the number of snapped endpoints.
+pub fn snap_proposal_endpoints_to_gt_nodes<E, N, Ty>(
+    gt_graph: &GeoGraph<E, N, Ty>,
+    proposal_graph: &mut GeoGraph<E, N, Ty>,
+    distance: f64,
+) -> Result<usize>
+where
+    E: Default,
+    N: Default,
+    Ty: petgraph::EdgeType,
+{
+    let gt_node_tree = rstar::RTree::bulk_load(
+        gt_graph
+            .node_map()
+            .iter()
+            .map(|(idx, node)| IndexedNode::new([node.geometry.x(), node.geometry.y()], *idx))
+            .collect(),
+    );
+
+    // The degree of each proposal node, and the last edge incident to it as its start and end node and parallel edge
+    // index.
+    let mut incident_edges: HashMap<NodeIdx, (usize, (NodeIdx, NodeIdx, usize))> = HashMap::new();
+    for (start_node_idx, end_node_idx, par_edge_idx, _) in proposal_graph.edges_sorted() {
+        for node_idx in [start_node_idx, end_node_idx] {
+            let entry = incident_edges
+                .entry(node_idx)
+                .or_insert((0, (start_node_idx, end_node_idx, par_edge_idx)));
+            entry.0 += 1;
+            entry.1 = (start_node_idx, end_node_idx, par_edge_idx);
+        }
+    }
+    let mut dangling_nodes: Vec<(NodeIdx, (NodeIdx, NodeIdx, usize))> = incident_edges
+        .into_iter()
+        .filter(|(_, (degree, _))| *degree == 1)
+        .map(|(node_idx, (_, edge))| (node_idx, edge))
+        .collect();
+    dangling_nodes.sort_by_key(|(node_idx, _)| *node_idx);
+
+    let mut snapped_count = 0;
+    for (node_idx, (start_node_idx, end_node_idx, par_edge_idx)) in dangling_nodes {
+        let node_coord = proposal_graph.node_map()[&node_idx].geometry.0;
+        let Some(gt_node) = gt_node_tree
+            .nearest_neighbor(&[node_coord.x, node_coord.y])
+            .map(|gt_node| geo::Coord::from(*gt_node.geom()))
+            .filter(|gt_coord| {
+                let gap =
+                    geo::Point::from(*gt_coord).euclidean_distance(&geo::Point::from(node_coord));
+                gap > 0.0 && gap <= distance
+            })
+        else {
+            continue;
+        };
+
+        let edge = &mut proposal_graph
+            .edge_graph_mut()
+            .edge_weight_mut(start_node_idx, end_node_idx)
+            .unwrap()[par_edge_idx];
+        if edge.geometry.0[0] == node_coord {
+            edge.geometry.0.insert(0, gt_node);
+        } else {
+            edge.geometry.0.push(gt_node);
+        }
+        proposal_graph
+            .node_map_mut()
+            .get_mut(&node_idx)
+            .unwrap()
+            .geometry = gt_node.into();
+        snapped_count += 1;
+    }
+    debug_assert!(proposal_graph.validate_invariants().is_ok());
+    log::info!(
+        "Snapped {} dangling proposal endpoints to ground truth nodes",
+        snapped_count
+    );
+    Ok(snapped_count)
+}
+
+/// The attribute in which `split_long_edges` stores the index of each part along the geometry of the split edge.
+pub const PART_INDEX_ATTRIBUTE: &str = "part_index";
+
+/// Split the edges of `graph` whose geometry is longer than `max_length` into chained parts, e.g. proposals which
+/// model an entire highway with a single linestring, which would dominate the per-edge reports.
+///
+/// The split points are interpolated every `max_length` along the geometry like the points sampled by
+/// `sample_line_coords`, so all parts but the last are `max_length` long. Nodes with default data are inserted at the
+/// split points, with indices after the largest node index of the graph. The parts keep the orientation of the
+/// geometry and a copy of the edge data, with the index of the part along the geometry set as the `part_index`
+/// attribute, see `EdgeAttributeData::set_attribute`.
+///
+/// # Returns
+/// The number of split edges.
+pub fn split_long_edges<E, N, Ty>(graph: &mut GeoGraph<E, N, Ty>, max_length: f64) -> Result<usize>
+where
+    E: EdgeAttributeData + Default + Clone,
+    N: Default,
+    Ty: petgraph::EdgeType,
+{
+    if !(max_length.is_finite() && max_length > 0.0) {
+        return Err(Error::ConfigError(format!(
+            "The split_long_edges length must be positive, but is {}",
+            max_length
+        )));
+    }
+    let long_edges: Vec<(NodeIdx, NodeIdx, usize)> = graph
+        .edges_sorted()
+        .filter(|(_, _, _, edge)| edge.geometry.euclidean_length() > max_length)
+        .map(|(start_node_idx, end_node_idx, par_edge_idx, _)| {
+            (start_node_idx, end_node_idx, par_edge_idx)
+        })
+        .collect();
+
+    // Split the edges in reverse order, so that removing a parallel edge does not shift the indices of the parallel
+    // edges which are split later.
+    let mut next_node_idx = graph.node_map().keys().max().map_or(0, |idx| idx + 1);
+    for &(start_node_idx, end_node_idx, par_edge_idx) in long_edges.iter().rev() {
+        let par_edges = graph
+            .edge_graph_mut()
+            .edge_weight_mut(start_node_idx, end_node_idx)
+            .unwrap();
+        let edge = par_edges.remove(par_edge_idx);
+        if par_edges.is_empty() {
+            graph
+                .edge_graph_mut()
+                .remove_edge(start_node_idx, end_node_idx);
+        }
+        // The node at the first coordinate of the geometry, which may be the end node in undirected graphs.
+        let (first_node_idx, last_node_idx) =
+            if graph.node_map()[&start_node_idx].geometry.0 == edge.geometry.0[0] {
+                (start_node_idx, end_node_idx)
+            } else {
+                (end_node_idx, start_node_idx)
+            };
+
+        // The split points without the endpoints of the geometry, each on the segment where the distance along the
+        // geometry reaches its offset.
+        let sampled_coords = sample_line_coords(&edge.geometry, max_length);
+        let split_coords = &sampled_coords[1..sampled_coords.len() - 1];
+        let mut segment_end_offsets = edge.geometry.lines().scan(0.0, |offset, line| {
+            *offset += line.euclidean_length();
+            Some(*offset)
+        });
+        let mut segment_idx = 0;
+        let mut segment_end_offset = segment_end_offsets.next().unwrap_or(0.0);
+
+        let mut part_start_node_idx = first_node_idx;
+        let mut part_coords = vec![edge.geometry.0[0]];
+        let mut next_coord_idx = 1;
+        for (split_idx, split_coord) in split_coords.iter().enumerate() {
+            let split_offset = (split_idx + 1) as f64 * max_length;
+            while split_offset > segment_end_offset {
+                match segment_end_offsets.next() {
+                    Some(offset) => {
+                        segment_idx += 1;
+                        segment_end_offset = offset;
+                    }
+                    None => break,
+                }
+            }
+            extend_distinct(
+                &mut part_coords,
+                &edge.geometry.0[next_coord_idx..=segment_idx],
+            );
+            extend_distinct(&mut part_coords, &[*split_coord]);
+            next_coord_idx = segment_idx + 1;
+            let mut part_data = edge.data.clone();
+            part_data.set_attribute(
+                PART_INDEX_ATTRIBUTE,
+                FieldValue::IntegerValue(split_idx as i32),
+            );
+            graph.insert_edge_with_data(
+                part_start_node_idx,
+                next_node_idx,
+                std::mem::replace(&mut part_coords, vec![*split_coord]).into(),
+                part_data,
+            )?;
+            part_start_node_idx = next_node_idx;
+            next_node_idx += 1;
+        }
+        extend_distinct(&mut part_coords, &edge.geometry.0[next_coord_idx..]);
+        let mut part_data = edge.data;
+        part_data.set_attribute(
+            PART_INDEX_ATTRIBUTE,
+            FieldValue::IntegerValue(split_coords.len() as i32),
+        );
+        graph.insert_edge_with_data(
+            part_start_node_idx,
+            last_node_idx,
+            part_coords.into(),
+            part_data,
+        )?;
+    }
+    debug_assert!(graph.validate_invariants().is_ok());
+    Ok(long_edges.len())
+}
+
+/// Above this edge length in degrees, the edges of a graph in a geographic CRS are implausibly long.
+const MAX_PLAUSIBLE_GEOGRAPHIC_EDGE_LENGTH: f64 = 1.0;
+
+/// Check whether the coordinates of a graph are plausible for the kind of its CRS. Catches graphs in a projected CRS
+/// which are labeled as geographic, and vice versa.
+///
+/// A graph in a geographic CRS is implausible if its bounding box exceeds the valid longitude/latitude range, or its
+/// median edge length exceeds one degree. A graph in a projected CRS is implausible if its bounding box fits in the
+/// longitude/latitude range, and all of its edges are shorter than one CRS unit.
+///
+/// # Returns
+/// A description of the problem if the coordinates are implausible.
+pub fn check_crs_plausibility<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+) -> Option<String> {
+    let bounding_box = graph.bounding_box()?;
+    let mut edge_lengths: Vec<f64> = graph
+        .edges_sorted()
+        .map(|(_, _, _, edge)| edge.geometry.euclidean_length())
+        .collect();
+    edge_lengths.sort_by(f64::total_cmp);
+    let median_edge_length = edge_lengths[edge_lengths.len() / 2];
+    let max_edge_length = *edge_lengths.last().unwrap();
+    let fits_lon_lat_range = bounding_box.min().x >= -180.0
+        && bounding_box.max().x <= 180.0
+        && bounding_box.min().y >= -90.0
+        && bounding_box.max().y <= 90.0;
+
+    if graph.crs.is_geographic() {
+        if !fits_lon_lat_range {
+            return Some(format!(
+                "the CRS is geographic, but the bounding box {:?} exceeds the longitude/latitude range",
+                bounding_box
+            ));
+        }
+        if median_edge_length > MAX_PLAUSIBLE_GEOGRAPHIC_EDGE_LENGTH {
+            return Some(format!(
+                "the CRS is geographic, but the median edge length is {} degrees",
+                median_edge_length
+            ));
+        }
+    } else if graph.crs.is_projected() && fits_lon_lat_range && max_edge_length < 1.0 {
+        return Some(format!(
+            "the CRS is projected, but the bounding box {:?} fits in the longitude/latitude range and all edges are \
+            shorter than one unit. The coordinates may be longitude/latitude",
+            bounding_box
+        ));
+    }
+    None
+}
+
+/// Check the plausibility of the coordinates of a graph with `check_crs_plausibility`. If they are implausible, warn,
+/// or fail in `strict` mode.
+///
+/// # Arguments
+/// * input_name - The name of the input the graph was loaded from, e.g. its file path, for the message.
+/// * warnings - Records the warning, which is only logged if `None`.
+pub fn ensure_crs_plausible<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    input_name: &str,
+    strict: bool,
+    warnings: Option<&WarningCollector>,
+) -> Result<()> {
+    if let Some(problem) = check_crs_plausibility(graph) {
+        let message = format!(
+            "The CRS of {} is likely wrong: {}. Check the CRS declared by the input, or set a CRS override",
+            input_name, problem
+        );
+        if strict {
+            return Err(Error::CrsMismatch(message));
+        }
+        warn(warnings, WarningCategory::ImplausibleCrs, 1, message);
+    }
+    Ok(())
+}
+
+/// Default minimum overlap of the bounding boxes of the ground truth and the proposal, see `ensure_gt_proposal_overlap`.
+pub const DEFAULT_MIN_BOUNDING_BOX_OVERLAP: f64 = 0.01;
+
+/// The fraction of the smaller of the bounding boxes of two graphs in the same CRS which is covered by the larger
+/// one. 0 for disjoint graphs, 1 if one bounding box contains the other. A bounding box without area, e.g. of a
+/// single straight road, counts as covered if it intersects the other one.
+///
+/// # Returns
+/// `None` if a graph has no edges.
+pub fn bounding_box_overlap<E1, N1, E2, N2, Ty>(
+    gt_graph: &GeoGraph<E1, N1, Ty>,
+    proposal_graph: &GeoGraph<E2, N2, Ty>,
+) -> Option<f64>
+where
+    E1: Default,
+    N1: Default,
+    E2: Default,
+    N2: Default,
+    Ty: petgraph::EdgeType,
+{
+    Some(rect_overlap(
+        &gt_graph.bounding_box()?,
+        &proposal_graph.bounding_box()?,
+    ))
+}
+
+/// The fraction of the smaller of two rectangles in the same CRS which is covered by the larger one, see
+/// `bounding_box_overlap`. Useful to check the overlap of inputs from their extents, without loading them.
+pub fn rect_overlap(a: &geo::Rect, b: &geo::Rect) -> f64 {
+    let area = |rect: &geo::Rect| rect.width() * rect.height();
+    let smaller_area = area(a).min(area(b));
+    if smaller_area == 0.0 {
+        return a.intersects(b) as u8 as f64;
+    }
+    let intersection_width = a.max().x.min(b.max().x) - a.min().x.max(b.min().x);
+    let intersection_height = a.max().y.min(b.max().y) - a.min().y.max(b.min().y);
+    intersection_width.max(0.0) * intersection_height.max(0.0) / smaller_area
+}
+
+/// Check that the bounding boxes of the ground truth and the proposal overlap by at least `min_overlap`, see
+/// `bounding_box_overlap`. Catches a proposal of a different area than the ground truth, which would otherwise give
+/// an F1 score of about 0 after the whole computation. If they barely overlap, warn, or fail in `strict` mode.
+pub fn ensure_gt_proposal_overlap<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    gt_graph: &GeoGraph<E, N, Ty>,
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    min_overlap: f64,
+    strict: bool,
+    warnings: Option<&WarningCollector>,
+) -> Result<()> {
+    let overlap = match bounding_box_overlap(gt_graph, proposal_graph) {
+        Some(overlap) => overlap,
+        None => return Ok(()),
+    };
+    if overlap < min_overlap {
+        let message = format!(
+            "The bounding boxes of the ground truth {:?} and of the proposal {:?} overlap by only {:.2}% of the \
+            smaller one. Check that both cover the same area",
+            gt_graph.bounding_box().unwrap(),
+            proposal_graph.bounding_box().unwrap(),
+            overlap * 100.0
+        );
+        if strict {
+            return Err(Error::invalid_data(message));
+        }
+        warn(warnings, WarningCategory::LowOverlap, 1, message);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use geo::EuclideanLength;
+    use rstest::rstest;
+
+    use crate::{
+        crs::{crs_utils::epsg_4326, Crs},
+        error::Error,
+        geofile::feature::{FeatureMap, FieldValue},
+        geograph::{
+            primitives::GeoGraph,
+            utils::{build_geograph_from_lines_in_crs, build_geograph_from_lines_with_data_in_crs},
+        },
+        topo::topo::{calculate_topo, TopoParams},
+    };
+
+    use super::{
+        bounding_box_overlap, check_crs_plausibility, ensure_crs_plausible,
+        ensure_gt_proposal_overlap, snap_proposal_endpoints_to_gt_nodes,
+        split_gt_proposal_edges_at_near_nodes, split_long_edges, DEFAULT_MIN_BOUNDING_BOX_OVERLAP,
+        PART_INDEX_ATTRIBUTE,
+    };
+
+    fn build_graph(
+        lines: Vec<Vec<(f64, f64)>>,
+        crs: Crs,
+    ) -> GeoGraph<(), (), petgraph::Undirected> {
+        build_geograph_from_lines_in_crs(lines.into_iter().map(|line| line.into()).collect(), crs)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_crs_plausibility_meters_labeled_geographic() {
+        // UTM zone 54N coordinates in Tokyo.
+        let graph = build_graph(
+            vec![vec![(390000.0, 3950000.0), (390100.0, 3950000.0)]],
+            epsg_4326(),
+        );
+
+        assert!(check_crs_plausibility(&graph).is_some());
+        assert!(ensure_crs_plausible(&graph, "proposal.geojson", false, None).is_ok());
+        let error = ensure_crs_plausible(&graph, "proposal.geojson", true, None).unwrap_err();
+        assert!(matches!(error, Error::CrsMismatch(_)));
+        assert!(error.to_string().contains("proposal.geojson"));
+    }
+
+    #[test]
+    fn test_check_crs_plausibility_degrees_labeled_projected() {
+        let graph = build_graph(
+            vec![vec![(139.78, 35.68), (139.79, 35.68)]],
+            crate::crs::Crs::Epsg(32654),
+        );
+
+        assert!(check_crs_plausibility(&graph).is_some());
+    }
+
+    #[test]
+    fn test_check_crs_plausibility_consistent() {
+        let geographic_graph =
+            build_graph(vec![vec![(139.78, 35.68), (139.79, 35.68)]], epsg_4326());
+        let projected_graph = build_graph(
+            vec![vec![(390000.0, 3950000.0), (390100.0, 3950000.0)]],
+            crate::crs::Crs::Epsg(32654),
+        );
+
+        assert_eq!(None, check_crs_plausibility(&geographic_graph));
+        assert_eq!(None, check_crs_plausibility(&projected_graph));
+    }
+
+    #[test]
+    fn test_split_gt_proposal_edges_at_near_nodes_improves_matching() {
+        let crs = crate::crs::Crs::Epsg(32654);
+        // The proposal models the T-junction with a node, the ground truth runs the main road through it.
+        let mut proposal_graph = build_graph(
+            vec![
+                vec![(0.0, 0.0), (50.0, 0.0)],
+                vec![(50.0, 0.0), (100.0, 0.0)],
+                vec![(50.0, 0.0), (50.0, 30.0)],
+            ],
+            crs.clone(),
+        );
+        let mut gt_graph = build_graph(
+            vec![
+                vec![(0.0, 0.0), (100.0, 0.0)],
+                vec![(50.0, 0.0), (50.0, 30.0)],
+            ],
+            crs,
+        );
+        let params = TopoParams::new_in_crs_units(11.0, 3.0);
+        let matched_count =
+            |proposal_graph: &GeoGraph<(), (), petgraph::Undirected>,
+             gt_graph: &GeoGraph<(), (), petgraph::Undirected>| {
+                calculate_topo(proposal_graph, gt_graph, &params, None, None)
+                    .unwrap()
+                    .proposal_nodes
+                    .iter()
+                    .filter(|node| node.matched())
+                    .count()
+            };
+        let matched_count_before = matched_count(&proposal_graph, &gt_graph);
+
+        split_gt_proposal_edges_at_near_nodes(&mut gt_graph, &mut proposal_graph, 1.0).unwrap();
+
+        // The main road of the ground truth is split at the end of the side road.
+        assert_eq!(3, gt_graph.edge_count());
+        assert_eq!(4, gt_graph.node_map().len());
+        assert_eq!(3, proposal_graph.edge_count());
+        let matched_count_after = matched_count(&proposal_graph, &gt_graph);
+        assert!(
+            matched_count_after > matched_count_before,
+            "{} <= {}",
+            matched_count_after,
+            matched_count_before
+        );
+    }
+
+    #[rstest]
+    #[case(None, 0.8)]
+    #[case(Some(5.0), 1.0)]
+    fn test_snap_proposal_endpoints_to_gt_nodes(
+        #[case] snap_distance: Option<f64>,
+        #[case] expected_recall: f64,
+    ) {
+        let crs = crate::crs::Crs::Epsg(32654);
+        // The side road of the proposal stops 3 m short of the T-junction.
+        let mut proposal_graph = build_graph(
+            vec![
+                vec![(0.0, 0.0), (100.0, 0.0)],
+                vec![(50.0, 3.0), (50.0, 30.0)],
+            ],
+            crs.clone(),
+        );
+        let gt_graph = build_graph(
+            vec![
+                vec![(0.0, 0.0), (100.0, 0.0)],
+                vec![(50.0, 0.0), (50.0, 30.0)],
+            ],
+            crs,
+        );
+
+        if let Some(distance) = snap_distance {
+            // The other dangling endpoints are already at ground truth nodes.
+            assert_eq!(
+                1,
+                snap_proposal_endpoints_to_gt_nodes(&gt_graph, &mut proposal_graph, distance)
+                    .unwrap()
+            );
+            assert!(proposal_graph.validate_invariants().is_ok());
+        }
+
+        let result = calculate_topo(
+            &proposal_graph,
+            &gt_graph,
+            &TopoParams::new_in_crs_units(11.0, 2.0),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(
+            expected_recall,
+            result.f1_score_result.recall(),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_split_long_edges() {
+        let highway = || {
+            FeatureMap::from([(
+                "highway".to_string(),
+                FieldValue::StringValue("motorway".to_string()),
+            )])
+        };
+        // A straight 25 km edge with an interior vertex, and a short edge which is not split.
+        let mut graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (15000.0, 0.0), (25000.0, 0.0)].into(),
+                    vec![(25000.0, 0.0), (25000.0, 100.0)].into(),
+                ],
+                vec![highway(), highway()],
+                Crs::Epsg(32654),
+            )
+            .unwrap();
+
+        assert_eq!(1, split_long_edges(&mut graph, 10000.0).unwrap());
+
+        assert!(graph.validate_invariants().is_ok());
+        assert_eq!(5, graph.node_map().len());
+        let mut parts: Vec<(i32, geo::LineString)> = graph
+            .edges_sorted()
+            .filter_map(|(_, _, _, edge)| {
+                assert_eq!(
+                    Some(&FieldValue::StringValue("motorway".to_string())),
+                    edge.data.get("highway")
+                );
+                match edge.data.get(PART_INDEX_ATTRIBUTE) {
+                    Some(FieldValue::IntegerValue(part_index)) => {
+                        Some((*part_index, edge.geometry.clone()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        parts.sort_by_key(|(part_index, _)| *part_index);
+        assert_eq!(
+            vec![
+                (0, vec![(0.0, 0.0), (10000.0, 0.0)].into()),
+                (
+                    1,
+                    vec![(10000.0, 0.0), (15000.0, 0.0), (20000.0, 0.0)].into()
+                ),
+                (2, vec![(20000.0, 0.0), (25000.0, 0.0)].into()),
+            ],
+            parts
+        );
+        assert_abs_diff_eq!(
+            25000.0,
+            parts
+                .iter()
+                .map(|(_, part)| part.euclidean_length())
+                .sum::<f64>(),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_split_long_edges_invalid_max_length() {
+        let mut graph = build_graph(vec![vec![(0.0, 0.0), (1.0, 0.0)]], Crs::Epsg(32654));
+        assert!(matches!(
+            split_long_edges(&mut graph, 0.0),
+            Err(Error::ConfigError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(vec![(1000.0, 1000.0), (1100.0, 1100.0)], 0.0)] // Disjoint.
+    #[case(vec![(50.0, 50.0), (150.0, 150.0)], 0.25)] // Partially overlapping.
+    #[case(vec![(20.0, 20.0), (40.0, 60.0)], 1.0)] // Nested in the ground truth.
+    #[case(vec![(-100.0, -100.0), (200.0, 200.0)], 1.0)] // Containing the ground truth.
+    #[case(vec![(-10.0, 50.0), (10.0, 50.0)], 1.0)] // Without area, intersecting.
+    #[case(vec![(-10.0, 150.0), (10.0, 150.0)], 0.0)] // Without area, disjoint.
+    fn test_bounding_box_overlap(
+        #[case] proposal_line: Vec<(f64, f64)>,
+        #[case] expected_overlap: f64,
+    ) {
+        let crs = crate::crs::Crs::Epsg(32654);
+        let gt_graph = build_graph(vec![vec![(0.0, 0.0), (100.0, 100.0)]], crs.clone());
+        let proposal_graph = build_graph(vec![proposal_line], crs);
+
+        let overlap = bounding_box_overlap(&gt_graph, &proposal_graph).unwrap();
+
+        assert_abs_diff_eq!(expected_overlap, overlap, epsilon = 1e-12);
+        let strict_result = ensure_gt_proposal_overlap(
+            &gt_graph,
+            &proposal_graph,
+            DEFAULT_MIN_BOUNDING_BOX_OVERLAP,
+            true,
+            None,
+        );
+        assert_eq!(expected_overlap > 0.0, strict_result.is_ok());
+        assert!(ensure_gt_proposal_overlap(
+            &gt_graph,
+            &proposal_graph,
+            DEFAULT_MIN_BOUNDING_BOX_OVERLAP,
+            false,
+            None
+        )
+        .is_ok());
+    }
+}
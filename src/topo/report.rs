@@ -0,0 +1,238 @@
+use std::{collections::HashMap, path::Path};
+
+use geo::EuclideanLength;
+
+use crate::{
+    error::Result,
+    geofile::{
+        feature::{Feature, FieldValue},
+        gdal_geofile::{write_layers_to_geofile, GdalDriverType, WriteOptions, WriteReport},
+    },
+    geograph::primitives::GeoGraph,
+};
+
+use super::topo::{TopoNode, TopoResult};
+
+/// Name of the layer of the ground truth edges written by `write_edge_report_gpkg`.
+pub const GROUND_TRUTH_EDGES_LAYER: &str = "gt_edges";
+/// Name of the layer of the proposal edges written by `write_edge_report_gpkg`.
+pub const PROPOSAL_EDGES_LAYER: &str = "proposal_edges";
+
+/// Sampled and matched node counts of one edge.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EdgeStats {
+    pub sampled_count: usize,
+    pub matched_count: usize,
+}
+
+impl EdgeStats {
+    /// The fraction of the sampled nodes which were matched, `None` if the edge has no sampled nodes.
+    pub fn matched_fraction(&self) -> Option<f64> {
+        if self.sampled_count == 0 {
+            return None;
+        }
+        Some(self.matched_count as f64 / self.sampled_count as f64)
+    }
+}
+
+/// Count the sampled and matched nodes of each edge of a graph. Nodes outside the evaluation mask are not counted,
+/// like for the scores.
+///
+/// # Arguments
+/// * nodes - The TOPO nodes sampled on the graph, e.g. `TopoResult::ground_truth_nodes`.
+/// * edge_count - The number of edges of the graph.
+///
+/// # Returns
+/// The counts of each edge, in the order of `GeoGraph::edges_sorted`.
+pub fn edge_stats(nodes: &[TopoNode], edge_count: usize) -> Vec<EdgeStats> {
+    let mut stats = vec![EdgeStats::default(); edge_count];
+    for node in nodes.iter().filter(|node| !node.excluded()) {
+        let edge_stats = &mut stats[node.edge_idx()];
+        edge_stats.sampled_count += 1;
+        edge_stats.matched_count += node.matched() as usize;
+    }
+    stats
+}
+
+/// One linestring feature per edge of the graph, with the node counts, the edge length in CRS units, and the matched
+/// fraction as `fraction_attribute`. Edges without sampled nodes have no fraction, which is written as NULL.
+fn edge_features<E: Default, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    nodes: &[TopoNode],
+    fraction_attribute: &str,
+) -> Vec<Feature> {
+    let stats = edge_stats(nodes, graph.edge_count());
+    graph
+        .edges_sorted()
+        .zip(stats)
+        .map(|((_, _, _, edge), stats)| {
+            let mut attributes = HashMap::from([
+                (
+                    "sampled_count".to_string(),
+                    FieldValue::Integer64Value(stats.sampled_count as i64),
+                ),
+                (
+                    "matched_count".to_string(),
+                    FieldValue::Integer64Value(stats.matched_count as i64),
+                ),
+                (
+                    "length".to_string(),
+                    FieldValue::RealValue(edge.geometry.euclidean_length()),
+                ),
+            ]);
+            if let Some(fraction) = stats.matched_fraction() {
+                attributes.insert(
+                    fraction_attribute.to_string(),
+                    FieldValue::RealValue(fraction),
+                );
+            }
+            Feature {
+                geometry: geo::Geometry::LineString(edge.geometry.clone()),
+                attributes: Some(attributes),
+                null_fields: Default::default(),
+            }
+        })
+        .collect()
+}
+
+/// Write the edges of both graphs to one GeoPackage, colored by how much of them was matched, e.g. for reviews in
+/// QGIS with a graduated style.
+///
+/// The ground truth edges are written to the layer `gt_edges` with a `recall_fraction`, the proposal edges to the
+/// layer `proposal_edges` with a `precision_fraction`. Both have the attributes `sampled_count`, `matched_count` and
+/// `length`, see `edge_stats`. The attributes are written to numeric fields.
+///
+/// # Arguments
+/// * proposal_graph, gt_graph - The graphs `result` was calculated for.
+pub fn write_edge_report_gpkg<
+    E1: Default,
+    N1: Default,
+    E2: Default,
+    N2: Default,
+    Ty: petgraph::EdgeType,
+>(
+    proposal_graph: &GeoGraph<E1, N1, Ty>,
+    gt_graph: &GeoGraph<E2, N2, Ty>,
+    result: &TopoResult,
+    path: &Path,
+) -> Result<WriteReport> {
+    let gt_features = edge_features(gt_graph, &result.ground_truth_nodes, "recall_fraction");
+    let proposal_features =
+        edge_features(proposal_graph, &result.proposal_nodes, "precision_fraction");
+    write_layers_to_geofile(
+        &[
+            (GROUND_TRUTH_EDGES_LAYER, &gt_features),
+            (PROPOSAL_EDGES_LAYER, &proposal_features),
+        ],
+        path,
+        Some(&gt_graph.crs),
+        GdalDriverType::GeoPackage.name(),
+        &WriteOptions::new().typed_fields(true),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use testdir::testdir;
+
+    use crate::{
+        geofile::{
+            feature::{FeatureMap, FieldValue},
+            gdal_geofile::for_each_feature_in_geofile,
+        },
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_in_crs},
+        topo::{
+            matching::MatchingMode,
+            topo::{calculate_topo, TopoParams},
+        },
+    };
+
+    use super::{write_edge_report_gpkg, GROUND_TRUTH_EDGES_LAYER, PROPOSAL_EDGES_LAYER};
+
+    fn read_layer_attributes(path: &std::path::Path, layer_name: &str) -> Vec<FeatureMap> {
+        let mut attributes = Vec::new();
+        for_each_feature_in_geofile(path, Some(layer_name), |feature| {
+            attributes.push(feature.attributes.unwrap_or_default());
+            Ok(())
+        })
+        .unwrap();
+        attributes
+    }
+
+    #[rstest]
+    fn test_write_edge_report_gpkg() {
+        let crs = crate::crs::Crs::Epsg(32654);
+        // The first edge is matched by the proposal, the second is not.
+        let gt_graph: GeoGraph<(), (), petgraph::Undirected> = build_geograph_from_lines_in_crs(
+            vec![
+                vec![(0.0, 0.0), (22.0, 0.0)].into(),
+                vec![(0.0, 100.0), (22.0, 100.0)].into(),
+            ],
+            crs.clone(),
+        )
+        .unwrap();
+        // The points of the second edge are merged into the coincident points of the first.
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 1.0), (22.0, 1.0)].into(),
+                    vec![(0.0, 1.2), (22.0, 1.2)].into(),
+                ],
+                crs,
+            )
+            .unwrap();
+        let params = TopoParams {
+            resampling_distance: 11.0,
+            hole_radius: 6.0,
+            matching_mode: MatchingMode::Greedy,
+            deduplicate_proposal_points: true,
+            ..TopoParams::default()
+        };
+        let result = calculate_topo(&proposal_graph, &gt_graph, &params, None, None).unwrap();
+        let path = testdir!().join("edge_report.gpkg");
+
+        let report = write_edge_report_gpkg(&proposal_graph, &gt_graph, &result, &path).unwrap();
+
+        assert!(report.is_lossless());
+        let gt_edges = read_layer_attributes(&path, GROUND_TRUTH_EDGES_LAYER);
+        assert_eq!(2, gt_edges.len());
+        assert_eq!(
+            Some(&FieldValue::RealValue(1.0)),
+            gt_edges[0].get("recall_fraction")
+        );
+        assert_eq!(
+            Some(&FieldValue::Integer64Value(2)),
+            gt_edges[0].get("matched_count")
+        );
+        assert_eq!(
+            Some(&FieldValue::RealValue(0.0)),
+            gt_edges[1].get("recall_fraction")
+        );
+        assert_eq!(
+            Some(&FieldValue::Integer64Value(2)),
+            gt_edges[1].get("sampled_count")
+        );
+        assert_eq!(
+            Some(&FieldValue::Integer64Value(0)),
+            gt_edges[1].get("matched_count")
+        );
+        assert_eq!(
+            Some(&FieldValue::RealValue(22.0)),
+            gt_edges[1].get("length")
+        );
+
+        let proposal_edges = read_layer_attributes(&path, PROPOSAL_EDGES_LAYER);
+        assert_eq!(2, proposal_edges.len());
+        assert_eq!(
+            Some(&FieldValue::RealValue(1.0)),
+            proposal_edges[0].get("precision_fraction")
+        );
+        // An edge without sampled nodes has a NULL fraction rather than 0.
+        assert_eq!(
+            Some(&FieldValue::Integer64Value(0)),
+            proposal_edges[1].get("sampled_count")
+        );
+        assert_eq!(None, proposal_edges[1].get("precision_fraction"));
+    }
+}
@@ -0,0 +1,183 @@
+//! Storage of sampled ground truth nodes in a memory-mapped temporary file, so that they do not take up memory while
+//! matching, see `TopoParams::low_memory`.
+
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+};
+
+use memmap2::Mmap;
+
+use crate::error::{Error, Result};
+
+/// Size of a record in bytes: 5 f64 fields, 3 u64 fields, the road class index and the flags, padded to a multiple
+/// of 8 bytes.
+const RECORD_SIZE: usize = 72;
+/// Road class index of nodes without a road class.
+const NO_ROAD_CLASS: u32 = u32::MAX;
+const EXCLUDED_FLAG: u8 = 1;
+const HAS_HOLE_RADIUS_FLAG: u8 = 2;
+
+/// The data of a sampled node which is read while matching. Match results are kept separately.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NodeRecord<'a> {
+    pub coord: geo::Coord,
+    pub azimuth: f64,
+    pub heading: f64,
+    pub hole_radius: Option<f64>,
+    pub id: u64,
+    pub edge_idx: usize,
+    pub weight: usize,
+    pub road_class: Option<&'a str>,
+    pub excluded: bool,
+}
+
+/// Nodes stored as a flat array of fixed-size little-endian records in an anonymous temporary file, which is mapped
+/// into memory. The operating system pages the records in and out as they are accessed, so that only the road
+/// classes, of which there are few, are held in memory.
+pub(crate) struct SpilledNodes {
+    map: Mmap,
+    len: usize,
+    road_classes: Vec<String>,
+}
+
+impl SpilledNodes {
+    /// Write the records to a new temporary file, which is deleted once the returned nodes are dropped.
+    pub fn write<'a>(records: impl IntoIterator<Item = NodeRecord<'a>>) -> Result<Self> {
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        let mut road_classes = Vec::new();
+        let mut road_class_indices: HashMap<&str, u32> = HashMap::new();
+        let mut len = 0;
+        for record in records {
+            let road_class_idx = match record.road_class {
+                Some(road_class) => *road_class_indices.entry(road_class).or_insert_with(|| {
+                    road_classes.push(road_class.to_string());
+                    (road_classes.len() - 1) as u32
+                }),
+                None => NO_ROAD_CLASS,
+            };
+            let mut flags = 0;
+            if record.excluded {
+                flags |= EXCLUDED_FLAG;
+            }
+            if record.hole_radius.is_some() {
+                flags |= HAS_HOLE_RADIUS_FLAG;
+            }
+            for value in [
+                record.coord.x,
+                record.coord.y,
+                record.azimuth,
+                record.heading,
+                record.hole_radius.unwrap_or_default(),
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            for value in [record.id, record.edge_idx as u64, record.weight as u64] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&road_class_idx.to_le_bytes())?;
+            writer.write_all(&[flags, 0, 0, 0])?;
+            len += 1;
+        }
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        // Safety: the file is an anonymous temporary file, which no other process can open, and which is not
+        // modified once mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() != len * RECORD_SIZE {
+            return Err(Error::invalid_data(format!(
+                "Spilled {} nodes to {} bytes instead of {}",
+                len,
+                map.len(),
+                len * RECORD_SIZE
+            )));
+        }
+        Ok(Self {
+            map,
+            len,
+            road_classes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The record at `idx`. Panics if it is out of bounds, like indexing a slice.
+    pub fn get(&self, idx: usize) -> NodeRecord<'_> {
+        assert!(
+            idx < self.len,
+            "Spilled node index {} out of bounds for {} nodes",
+            idx,
+            self.len
+        );
+        let record = &self.map[idx * RECORD_SIZE..(idx + 1) * RECORD_SIZE];
+        let f64_at =
+            |field: usize| f64::from_le_bytes(record[field * 8..][..8].try_into().unwrap());
+        let u64_at =
+            |field: usize| u64::from_le_bytes(record[field * 8..][..8].try_into().unwrap());
+        let road_class_idx = u32::from_le_bytes(record[64..68].try_into().unwrap());
+        let flags = record[68];
+        NodeRecord {
+            coord: geo::Coord {
+                x: f64_at(0),
+                y: f64_at(1),
+            },
+            azimuth: f64_at(2),
+            heading: f64_at(3),
+            hole_radius: (flags & HAS_HOLE_RADIUS_FLAG != 0).then(|| f64_at(4)),
+            id: u64_at(5),
+            edge_idx: u64_at(6) as usize,
+            weight: u64_at(7) as usize,
+            road_class: (road_class_idx != NO_ROAD_CLASS)
+                .then(|| self.road_classes[road_class_idx as usize].as_str()),
+            excluded: flags & EXCLUDED_FLAG != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeRecord, SpilledNodes};
+
+    fn record(id: u64, road_class: Option<&str>) -> NodeRecord<'_> {
+        NodeRecord {
+            coord: geo::Coord {
+                x: 388_000.5 + id as f64,
+                y: -3_949_000.25,
+            },
+            azimuth: 0.5,
+            heading: -2.5,
+            hole_radius: road_class.map(|_| 6.0),
+            id,
+            edge_idx: id as usize / 2,
+            weight: 1,
+            road_class,
+            excluded: id == 1,
+        }
+    }
+
+    #[test]
+    fn test_spilled_nodes_roundtrip() {
+        let records = vec![
+            record(0, Some("primary")),
+            record(1, None),
+            record(2, Some("residential")),
+            record(u64::MAX - 1, Some("primary")),
+        ];
+
+        let spilled = SpilledNodes::write(records.clone()).unwrap();
+
+        assert_eq!(records.len(), spilled.len());
+        for (idx, record) in records.iter().enumerate() {
+            assert_eq!(*record, spilled.get(idx));
+        }
+        assert_eq!(2, spilled.road_classes.len());
+    }
+
+    #[test]
+    fn test_spilled_nodes_empty() {
+        let spilled = SpilledNodes::write(Vec::new()).unwrap();
+
+        assert_eq!(0, spilled.len());
+    }
+}
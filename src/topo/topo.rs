@@ -1,362 +1,4520 @@
 use std::{
-    collections::{HashMap, HashSet},
-    f64::consts::FRAC_PI_2,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    f64::consts::{FRAC_PI_2, FRAC_PI_4, PI},
 };
 
-use anyhow::anyhow;
-use gdal::vector::FieldValue;
+use crate::error::{Error, Result, ResultExt};
 use geo::{CoordsIter, EuclideanLength};
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use kdtree::distance::squared_euclidean;
 use rayon::prelude::*;
 
 use crate::{
-    geofile::feature::Feature,
-    geograph::{primitives::GeoGraph, utils::NodeIndexer},
+    crs::crs_utils::meters_to_degrees_at,
+    geofile::feature::{Feature, FeatureMap, FieldValue},
+    geograph::{
+        cleanup::reciprocal_edge_pairs,
+        primitives::GeoGraph,
+        serialization::{deserialize_data, serialize_data},
+        utils::NodeIndexer,
+    },
+    util::{
+        progress::{NoopProgress, ProgressSink},
+        telemetry::{StageMetrics, StageTimer},
+    },
 };
 
-#[derive(PartialEq, Debug)]
+use super::{
+    cancellation::{is_cancelled, CancellationToken},
+    matching::{
+        self, CandidatePair, CandidateSink, MatchCandidates, MatchingMode, RejectionReason,
+    },
+    spill::{NodeRecord, SpilledNodes},
+};
+
+#[derive(Clone, PartialEq, Debug, serde::Deserialize, serde::Serialize)]
 pub struct F1ScoreResult {
     precision: f64,
     recall: f64,
     f1_score: f64,
 }
 
-pub struct TopoResult {
-    pub f1_score_result: F1ScoreResult,
-    pub ground_truth_nodes: Vec<TopoNode>,
-    pub proposal_nodes: Vec<TopoNode>,
-}
+impl F1ScoreResult {
+    fn from_counts(
+        true_positive_count: usize,
+        proposal_count: usize,
+        ground_truth_count: usize,
+    ) -> Self {
+        Self::from_matched_counts(
+            true_positive_count,
+            proposal_count,
+            true_positive_count,
+            ground_truth_count,
+        )
+    }
 
-#[derive(serde::Deserialize, Debug)]
-pub struct TopoParams {
-    pub resampling_distance: f64,
-    pub hole_radius: f64,
-}
+    /// The scores from separately counted matched proposal and ground truth nodes, which differ when only part of
+    /// the nodes are counted, and some of the counted nodes matched nodes which are not.
+    pub(crate) fn from_matched_counts(
+        proposal_matched_count: usize,
+        proposal_count: usize,
+        ground_truth_matched_count: usize,
+        ground_truth_count: usize,
+    ) -> Self {
+        let precision = proposal_matched_count as f64 / proposal_count as f64;
+        let recall = ground_truth_matched_count as f64 / ground_truth_count as f64;
+        let f1_score = 2.0 * precision * recall / (precision + recall);
+        Self {
+            precision,
+            recall,
+            f1_score,
+        }
+    }
 
-pub fn calculate_topo<E: Default, N: Default, Ty: petgraph::EdgeType>(
-    proposal_graph: &GeoGraph<E, N, Ty>,
-    ground_truth_graph: &GeoGraph<E, N, Ty>,
-    params: &TopoParams,
-) -> anyhow::Result<TopoResult> {
-    let proposal_edges = proposal_graph.edge_geometries();
-    let ground_truth = ground_truth_graph.edge_geometries();
+    pub fn precision(&self) -> f64 {
+        self.precision
+    }
 
-    // TODO ensure that all edge linestrings of both graphs point outward from the same geospatial coordinate.
+    pub fn recall(&self) -> f64 {
+        self.recall
+    }
 
-    // Interpolate the edges.
+    pub fn f1_score(&self) -> f64 {
+        self.f1_score
+    }
 
-    log::info!("Sampling points on proposal lines");
-    let proposal_points = sample_points_on_lines(&proposal_edges, params.resampling_distance);
-    let mut proposal_nodes = road_points_to_topo_nodes(proposal_points);
-    log::info!("Sampling points on ground truth lines");
-    let ground_truth_points: Vec<RoadPoint> =
-        sample_points_on_lines(&ground_truth, params.resampling_distance);
-    let mut ground_truth_nodes = road_points_to_topo_nodes(ground_truth_points);
-    log::info!("Building ground truth point lookup tree");
-    let ground_truth_kdtree = build_kdtree_from_nodes(&ground_truth_nodes)?;
+    /// The change of each score from `other` to `self`.
+    pub fn diff(&self, other: &Self) -> F1ScoreDelta {
+        F1ScoreDelta {
+            precision: self.precision - other.precision,
+            recall: self.recall - other.recall,
+            f1_score: self.f1_score - other.f1_score,
+        }
+    }
 
-    log::info!(
-        "Matching {} proposal points to {} ground truth points",
-        proposal_nodes.len(),
-        ground_truth_nodes.len()
-    );
-    // Get the squared distances and indices of the GT nodes within range, if there are any within hole radius.
-    let squared_hole_radius = params.hole_radius.powi(2);
-    let progress_style = ProgressStyle::with_template(
-        "{wide_bar} {pos}/{len} {percent}% elapsed: {elapsed_precise}",
-    )
-    .unwrap();
-    log::info!("Looking up ground truth nodes within hole radius");
-    let prop_node_and_gt_nodes_result: Result<Vec<_>, anyhow::Error> = proposal_nodes
-        .par_iter_mut()
-        .progress_with_style(progress_style)
-        .map(|proposal_node| {
-            let gt_distances_and_indices = ground_truth_kdtree
-                .within(
-                    &<[f64; 2]>::from(proposal_node.road_point.coord),
-                    squared_hole_radius,
-                    &squared_euclidean,
-                )
-                .or_else(|error| Err(anyhow!("Could not get nearest GT node, {}", error)))?;
-            Ok((proposal_node, gt_distances_and_indices))
-        })
-        .collect();
-    let mut matched_gt_distance_and_idx = prop_node_and_gt_nodes_result?;
+    /// Whether the F1 score is at most `max_regression` below the one of `baseline`. Differences within floating
+    /// point noise of the threshold pass, and a NaN score never does.
+    pub fn meets_threshold(&self, baseline: &Self, max_regression: f64) -> bool {
+        self.diff(baseline).f1_score >= -max_regression - F1_SCORE_THRESHOLD_EPSILON
+    }
 
-    log::info!("Determining matches for proposal nodes");
-    let mut matched_gt_ids = HashSet::new();
-    let progress_bar = ProgressBar::new(matched_gt_distance_and_idx.len() as u64);
-    for (proposal_node, gt_distances_and_indices) in matched_gt_distance_and_idx.iter_mut() {
-        for (squared_distance, gt_idx) in gt_distances_and_indices {
-            if !matched_gt_ids.contains(gt_idx) {
-                let match_distance = squared_distance.sqrt();
-
-                proposal_node.matched = true;
-                proposal_node.match_distance = Some(match_distance);
-
-                let mut gt_node = ground_truth_nodes
-                    .get_mut(**gt_idx as usize)
-                    .ok_or_else(|| anyhow!("No such GT node"))?;
-                gt_node.matched = true;
-                gt_node.match_distance = Some(match_distance);
-
-                matched_gt_ids.insert(gt_idx);
-                break;
-            }
+    /// Read the scores from JSON, either the scores themselves or a `result.json` holding them in its
+    /// `f1_score_result` member.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        if let Some(f1_score_result) = value.get_mut("f1_score_result") {
+            value = f1_score_result.take();
         }
-        progress_bar.inc(1);
+        Ok(serde_json::from_value(value)?)
     }
+}
 
-    let true_positive_count = matched_gt_ids.len();
-    let false_positive_count = proposal_nodes.len() - true_positive_count;
-    let false_negative_count = ground_truth_nodes.len() - true_positive_count;
-    let precision =
-        true_positive_count as f64 / (true_positive_count + false_positive_count) as f64;
-    let recall = true_positive_count as f64 / (true_positive_count + false_negative_count) as f64;
-    let f1_score = 2.0 * precision * recall / (precision + recall);
-    Ok(TopoResult {
-        f1_score_result: F1ScoreResult {
-            precision,
-            recall,
-            f1_score,
-        },
-        ground_truth_nodes,
-        proposal_nodes,
-    })
+/// Orders scores by their F1 score. Scores with the same F1 score but different precision and recall are unordered.
+impl PartialOrd for F1ScoreResult {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match self.f1_score.partial_cmp(&other.f1_score)? {
+            std::cmp::Ordering::Equal if self != other => None,
+            ordering => Some(ordering),
+        }
+    }
 }
 
-struct RoadPoint {
-    coord: geo::Coord,
-    azimuth: f64,
+/// Tolerance of `F1ScoreResult::meets_threshold`, so that a regression of exactly the threshold passes.
+const F1_SCORE_THRESHOLD_EPSILON: f64 = 1e-9;
+
+/// The change of the scores between two F1ScoreResults.
+#[derive(PartialEq, Debug, serde::Serialize)]
+pub struct F1ScoreDelta {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1_score: f64,
 }
 
-pub struct TopoNode {
-    road_point: RoadPoint,
-    id: i32,
-    matched: bool,
-    match_distance: Option<f64>,
+/// The bucket of `ClassBreakdown` of nodes whose edge has no road class.
+pub const UNCLASSIFIED_ROAD_CLASS: &str = "__unclassified";
+
+/// The matched nodes of one road class, out of all nodes of the class.
+#[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ClassScore {
+    pub matched_count: usize,
+    pub total_count: usize,
+    /// `matched_count` divided by `total_count`.
+    pub ratio: f64,
 }
 
-impl From<&TopoNode> for Feature {
-    fn from(node: &TopoNode) -> Self {
-        let mut attributes = HashMap::new();
-        attributes.insert("id".to_string(), FieldValue::IntegerValue(node.id));
-        attributes.insert(
-            "matched".to_string(),
-            FieldValue::StringValue(node.matched.to_string()),
-        );
-        if let Some(distance) = node.match_distance {
-            attributes.insert(
-                "match_distance".to_string(),
-                FieldValue::RealValue(distance),
-            );
-        }
+/// The scores of the nodes of each road class, see `TopoParams::road_class_attribute`.
+#[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ClassBreakdown {
+    /// The recall of the ground truth nodes of each class. If an evaluation mask is used, only the nodes within the
+    /// mask are counted.
+    pub recall_by_ground_truth_class: BTreeMap<String, ClassScore>,
+    /// The precision of the proposal nodes of each class.
+    pub precision_by_proposal_class: BTreeMap<String, ClassScore>,
+}
+
+impl ClassBreakdown {
+    fn from_nodes(proposal_nodes: &[TopoNode], ground_truth_nodes: &[TopoNode]) -> Self {
         Self {
-            geometry: geo::Geometry::Point(geo::Point::from(node.road_point.coord)),
-            attributes: Some(attributes),
+            recall_by_ground_truth_class: class_scores(
+                ground_truth_nodes.iter().filter(|node| !node.excluded),
+            ),
+            precision_by_proposal_class: class_scores(proposal_nodes.iter()),
         }
     }
 }
 
-impl TopoNode {
-    fn new(point: RoadPoint, id: i32) -> Self {
-        TopoNode {
-            road_point: point,
-            id: id,
-            matched: false,
-            match_distance: None,
-        }
+/// The share of matched nodes by the road class of the nodes.
+fn class_scores<'a>(nodes: impl Iterator<Item = &'a TopoNode>) -> BTreeMap<String, ClassScore> {
+    let mut counts: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for node in nodes {
+        let class = node
+            .road_class
+            .clone()
+            .unwrap_or_else(|| UNCLASSIFIED_ROAD_CLASS.to_string());
+        let (matched_count, total_count) = counts.entry(class).or_default();
+        *matched_count += node.matched as usize;
+        *total_count += 1;
     }
+    counts
+        .into_iter()
+        .map(|(class, (matched_count, total_count))| {
+            let score = ClassScore {
+                matched_count,
+                total_count,
+                ratio: matched_count as f64 / total_count as f64,
+            };
+            (class, score)
+        })
+        .collect()
 }
 
-fn build_kdtree_from_nodes(
-    topo_nodes: &Vec<TopoNode>,
-) -> anyhow::Result<kdtree::KdTree<f64, i32, [f64; 2]>> {
-    let mut kdtree = kdtree::KdTree::with_capacity(2, topo_nodes.len());
-    for node in topo_nodes {
-        kdtree.add(<[f64; 2]>::from(node.road_point.coord), node.id)?;
-    }
-    Ok(kdtree)
+pub struct TopoResult {
+    /// The scores. If an evaluation mask is used, recall only counts the ground truth nodes within the mask.
+    pub f1_score_result: F1ScoreResult,
+    /// The scores counting all ground truth nodes, if an evaluation mask is used.
+    pub unmasked_f1_score_result: Option<F1ScoreResult>,
+    /// The scores by road class, if a road class attribute is set for either graph.
+    pub class_breakdown: Option<ClassBreakdown>,
+    pub ground_truth_nodes: Vec<TopoNode>,
+    pub proposal_nodes: Vec<TopoNode>,
+    pub stage_metrics: Vec<StageMetrics>,
+    /// The scores of the proposal sampled with each of `TopoParams::sampling_phase_offsets`, if set. The other fields
+    /// are those of the proposal sampled without offset.
+    pub phase_offset_scores: Option<PhaseOffsetScores>,
 }
 
-/// Deduplicate RoadPoints by coordinate, and create TopoNodes from them.
-/// The created TopoNodes will have the same id as the index of the first RoadPoint with that coordinate.
-fn road_points_to_topo_nodes(road_points: Vec<RoadPoint>) -> Vec<TopoNode> {
-    let mut node_indexer = NodeIndexer::new();
+/// The scores of a proposal sampled with one phase offset, see `TopoParams::sampling_phase_offsets`.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct PhaseOffsetScore {
+    pub phase_offset: f64,
+    pub f1_score_result: F1ScoreResult,
+}
 
-    let mut nodes = Vec::new();
+/// The scores of a proposal sampled with several phase offsets, and the spread of their F1 scores.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct PhaseOffsetScores {
+    pub scores: Vec<PhaseOffsetScore>,
+    pub mean_f1_score: f64,
+    pub min_f1_score: f64,
+    pub max_f1_score: f64,
+}
 
-    for point in road_points.into_iter() {
-        let node_idx = node_indexer.get_index_for_coordinate(&point.coord);
-        if node_idx as usize == nodes.len() {
-            nodes.push(TopoNode::new(point, node_idx as i32));
+impl PhaseOffsetScores {
+    /// Aggregate the scores of the offsets. The mean, min and max are NaN if there are no scores.
+    pub fn new(scores: Vec<PhaseOffsetScore>) -> Self {
+        let f1_scores: Vec<f64> = scores
+            .iter()
+            .map(|score| score.f1_score_result.f1_score())
+            .collect();
+        let (mean_f1_score, min_f1_score, max_f1_score) = if f1_scores.is_empty() {
+            (f64::NAN, f64::NAN, f64::NAN)
+        } else {
+            (
+                f1_scores.iter().sum::<f64>() / f1_scores.len() as f64,
+                f1_scores.iter().copied().fold(f64::INFINITY, f64::min),
+                f1_scores.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            )
+        };
+        Self {
+            scores,
+            mean_f1_score,
+            min_f1_score,
+            max_f1_score,
         }
     }
-    nodes
 }
 
-fn sample_points_on_lines(
-    lines: &Vec<geo::LineString>,
-    resampling_distance: f64,
-) -> Vec<RoadPoint> {
-    lines
-        .par_iter()
-        .map(|linestr| sample_points_on_line(linestr, resampling_distance))
-        .flatten()
-        .collect()
+/// Units of the distances in `TopoParams`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DistanceUnits {
+    /// The units of the CRS of the graphs.
+    CrsUnits,
+    /// Meters. If the graphs are in a geographic CRS, distances are converted to degrees at the central latitude of
+    /// the ground truth, if they are in a projected CRS in other units, e.g. US survey feet, to those units.
+    #[default]
+    Meters,
 }
 
-/// Sample points on a linestring every resampling_distance, starting from the first coordinate of the linestring.
-fn sample_points_on_line(linestr: &geo::LineString, resampling_distance: f64) -> Vec<RoadPoint> {
-    if 2 > linestr.coords_count() {
-        return vec![];
+fn default_anisotropy_warning_latitude() -> f64 {
+    60.0
+}
+
+/// Default of `TopoParams::resampling_distance`.
+pub const DEFAULT_RESAMPLING_DISTANCE: f64 = 5.0;
+/// Default of `TopoParams::hole_radius`.
+pub const DEFAULT_HOLE_RADIUS: f64 = 5.0;
+
+/// Parameters of the TOPO computation. Omitted fields take their default values, see `TopoParams::default`: a
+/// resampling distance and hole radius of 5, in the units of `units`, i.e. 5 m in the projected CRS the executable
+/// evaluates in, greedy matching, no road classes, no evaluation mask and no propagated attributes.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TopoParams {
+    /// Distance between the points sampled on the edges. Defaults to `DEFAULT_RESAMPLING_DISTANCE`.
+    pub resampling_distance: f64,
+    /// Hole radius for ground truth points whose road class has no entry in `hole_radius_by_class`. Defaults to
+    /// `DEFAULT_HOLE_RADIUS`.
+    pub hole_radius: f64,
+    #[serde(default)]
+    pub matching_mode: MatchingMode,
+    /// Name of the ground truth edge attribute which holds the road class, e.g. "highway" for OSM.
+    pub road_class_attribute: Option<String>,
+    /// Name of the proposal edge attribute which holds the road class, if it differs from `road_class_attribute`.
+    #[serde(default)]
+    pub proposal_road_class_attribute: Option<String>,
+    /// Hole radius for ground truth points by the road class of their edge.
+    pub hole_radius_by_class: Option<HashMap<String, f64>>,
+    /// If set, only ground truth points within the mask are considered for recall.
+    #[serde(default)]
+    pub evaluation_mask: Option<EvaluationMask>,
+    /// Units of the distances, see `in_crs_units`. Defaults to meters.
+    #[serde(default, alias = "param_units")]
+    pub units: DistanceUnits,
+    /// When converting meters to degrees, warn about the distortion of distances if the absolute central latitude is
+    /// above this.
+    #[serde(default = "default_anisotropy_warning_latitude")]
+    pub anisotropy_warning_latitude: f64,
+    /// Attributes of proposal edges to copy to the proposal nodes sampled on them, e.g. a confidence score.
+    #[serde(default)]
+    pub propagate_edge_attributes: Vec<String>,
+    /// Also sample every original vertex of the edges, in addition to the points every `resampling_distance`.
+    #[serde(default)]
+    pub include_original_vertices: bool,
+    /// If set, a ground truth node within hole radius of a proposal node is only matched if the match is plausible
+    /// along the road network, as decided by a `MatchCompatibility`. The factor is the largest accepted ratio of the
+    /// distance along the network to the straight-line distance between the matched locations.
+    #[serde(default)]
+    pub max_network_detour_factor: Option<f64>,
+    /// Merge proposal points of different edges within `proposal_dedup_radius` of each other whose edges have
+    /// near-identical azimuths, e.g. of a road segment which is drawn twice, so that they do not compete for the same
+    /// ground truth points. See `TopoNode::weight`.
+    #[serde(default)]
+    pub deduplicate_proposal_points: bool,
+    /// Radius within which proposal points are merged if `deduplicate_proposal_points` is set. Defaults to a quarter
+    /// of the resampling distance.
+    #[serde(default)]
+    pub proposal_dedup_radius: Option<f64>,
+    /// If set, the points of both graphs within this distance of a junction, a node of degree 3 or more in either
+    /// graph, are dropped after sampling. Near junctions, the points of the incident edges crowd together, and how the
+    /// one-to-one matching resolves them would otherwise depend on how each graph models the junction.
+    #[serde(default)]
+    pub exclude_near_junction_distance: Option<f64>,
+    /// Sample reciprocal edges of directed graphs only once, e.g. the two opposing edges of a two-way street, so that
+    /// two-way streets do not weigh twice as much as one-way streets. The points are sampled on the first edge of each
+    /// pair, and stand for both. Edges are reciprocal if their geometries are within `dedup_radius` of each other,
+    /// see `reciprocal_edge_pairs`.
+    #[serde(default)]
+    pub collapse_reciprocal_edges: bool,
+    /// If set, the proposal is additionally sampled and matched once for each of these phase offsets, to quantify how
+    /// sensitive the score is to where sampling starts along each edge. An offset is a fraction of the resampling
+    /// distance in [0, 1), by which the interpolated points of each proposal edge are shifted along it. The ground
+    /// truth is sampled once, and its index is reused for all offsets. See `TopoResult::phase_offset_scores`.
+    #[serde(default)]
+    pub sampling_phase_offsets: Option<Vec<f64>>,
+    /// Only match nodes whose edges head in the same direction, by at most `MAX_DIRECTIONAL_HEADING_DIFFERENCE`, e.g.
+    /// to penalize a proposal edge which is drawn against the direction of a one-way street. Requires directed graphs,
+    /// whose edge geometries run from their start to their end node.
+    #[serde(default)]
+    pub directional_matching: bool,
+    /// Spill the sampled ground truth nodes to a memory-mapped temporary file while matching, so that evaluations
+    /// whose ground truth does not fit into memory along with the proposal and the match candidates still run. Only
+    /// the lookup tree and the match state of the ground truth nodes stay in memory. The scores and nodes are the same
+    /// as without it. Only used by `calculate_topo`, `calculate_topo_from_lines` and `TopoEvaluator`, which keeps the
+    /// ground truth nodes of its cache in memory regardless.
+    #[serde(default)]
+    pub low_memory: bool,
+}
+
+/// Area to which the evaluation of recall is restricted, e.g. when the proposal deliberately covers only a part of
+/// the ground truth.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub enum EvaluationMask {
+    /// Ground truth points farther than `distance` from every proposal point are excluded.
+    ProposalBuffer { distance: f64 },
+}
+
+impl Default for TopoParams {
+    /// The default distances, in meters, unlike the CRS units of `TopoParams::new_in_crs_units`.
+    fn default() -> Self {
+        TopoParams {
+            units: DistanceUnits::Meters,
+            ..Self::new_in_crs_units(DEFAULT_RESAMPLING_DISTANCE, DEFAULT_HOLE_RADIUS)
+        }
     }
-    if resampling_distance <= 0.0 {
-        return vec![];
+}
+
+impl TopoParams {
+    /// Parameters with the given distances in CRS units, and defaults for everything else.
+    ///
+    /// Unlike `TopoParams::default`, whose distances are in meters, the units are `DistanceUnits::CrsUnits`, so the
+    /// distances are used as given, e.g. for `calculate_topo_from_lines`. Set `units` to `DistanceUnits::Meters` for
+    /// distances in meters, which `calculate_topo` converts to the units of the CRS of the graphs.
+    pub fn new_in_crs_units(resampling_distance: f64, hole_radius: f64) -> Self {
+        TopoParams {
+            resampling_distance,
+            hole_radius,
+            matching_mode: MatchingMode::default(),
+            road_class_attribute: None,
+            proposal_road_class_attribute: None,
+            hole_radius_by_class: None,
+            evaluation_mask: None,
+            units: DistanceUnits::CrsUnits,
+            anisotropy_warning_latitude: default_anisotropy_warning_latitude(),
+            propagate_edge_attributes: Vec::new(),
+            include_original_vertices: false,
+            max_network_detour_factor: None,
+            deduplicate_proposal_points: false,
+            proposal_dedup_radius: None,
+            exclude_near_junction_distance: None,
+            collapse_reciprocal_edges: false,
+            sampling_phase_offsets: None,
+            directional_matching: false,
+            low_memory: false,
+        }
     }
 
-    let mut output_points = vec![RoadPoint {
-        coord: *linestr.coords().nth(0).unwrap(),
-        azimuth: get_normalized_line_azimuth(&linestr.lines().nth(0).unwrap()),
-    }];
+    /// The radius within which proposal points are merged, see `deduplicate_proposal_points`.
+    pub fn dedup_radius(&self) -> f64 {
+        self.proposal_dedup_radius
+            .unwrap_or(self.resampling_distance / 4.0)
+    }
 
-    let mut prev_inserted_dist = 0.0;
-    let mut prev_original_vertex_dist = 0.0;
-    let mut next_original_vert_dist = 0.0;
-    for line in linestr.lines() {
-        let line_len = line.euclidean_length();
-        next_original_vert_dist += line_len;
-        let mut azimuth: Option<f64> = None;
-        while (next_original_vert_dist - prev_inserted_dist) > resampling_distance {
-            let azimuth = azimuth.get_or_insert_with(|| get_normalized_line_azimuth(&line));
-            let new_insert_dist = prev_inserted_dist + resampling_distance;
-            let new_coord = line.start * (next_original_vert_dist - new_insert_dist) / line_len
-                + line.end * (new_insert_dist - prev_original_vertex_dist) / line_len;
-            output_points.push(RoadPoint {
-                coord: new_coord,
-                azimuth: *azimuth,
-            });
-            prev_inserted_dist = new_insert_dist;
+    /// The hole radius of ground truth points of the given road class.
+    pub fn hole_radius_for_class(&self, road_class: Option<&str>) -> f64 {
+        match (road_class, &self.hole_radius_by_class) {
+            (Some(road_class), Some(hole_radius_by_class)) => hole_radius_by_class
+                .get(road_class)
+                .copied()
+                .unwrap_or(self.hole_radius),
+            _ => self.hole_radius,
         }
-        prev_original_vertex_dist = next_original_vert_dist;
     }
-    output_points.push(RoadPoint {
-        coord: *linestr.coords().last().unwrap(),
-        azimuth: get_normalized_line_azimuth(&linestr.lines().last().unwrap()), // TODO create the line in a different way, iterating through the lines() is very wasteful
-    });
-    output_points
+
+    /// Copy of the parameters with all distances multiplied by `factor`.
+    pub fn with_distances_scaled(&self, factor: f64) -> Self {
+        TopoParams {
+            resampling_distance: self.resampling_distance * factor,
+            hole_radius: self.hole_radius * factor,
+            hole_radius_by_class: self
+                .hole_radius_by_class
+                .as_ref()
+                .map(|hole_radius_by_class| {
+                    hole_radius_by_class
+                        .iter()
+                        .map(|(road_class, radius)| (road_class.clone(), radius * factor))
+                        .collect()
+                }),
+            evaluation_mask: self.evaluation_mask.as_ref().map(|evaluation_mask| {
+                match evaluation_mask {
+                    EvaluationMask::ProposalBuffer { distance } => EvaluationMask::ProposalBuffer {
+                        distance: distance * factor,
+                    },
+                }
+            }),
+            proposal_dedup_radius: self.proposal_dedup_radius.map(|radius| radius * factor),
+            exclude_near_junction_distance: self
+                .exclude_near_junction_distance
+                .map(|distance| distance * factor),
+            ..self.clone()
+        }
+    }
+
+    /// The parameters in the units of the CRS of `graph`. Distances in meters are converted to degrees for graphs in
+    /// a geographic CRS, using the conversion at the central latitude of the graph. Of the longitude and latitude
+    /// conversions, the one yielding fewer degrees is used, so that hole radii are never larger than intended. For
+    /// graphs in a projected CRS, they are converted to its linear unit, e.g. US survey feet.
+    ///
+    /// Fails for distances in meters if the linear unit of a projected CRS has no known length in meters.
+    pub fn in_crs_units<E: Default, N: Default, Ty: petgraph::EdgeType>(
+        &self,
+        graph: &GeoGraph<E, N, Ty>,
+    ) -> Result<Self> {
+        Ok(self.with_distances_scaled(self.crs_units_factor(graph)?))
+    }
+
+    /// The factor by which `in_crs_units` scales the distances for `graph`, to convert other distances given in the
+    /// same units alike. 1 if the distances are in CRS units already. Fails like `in_crs_units`.
+    pub fn crs_units_factor<E: Default, N: Default, Ty: petgraph::EdgeType>(
+        &self,
+        graph: &GeoGraph<E, N, Ty>,
+    ) -> Result<f64> {
+        if self.units != DistanceUnits::Meters {
+            return Ok(1.0);
+        }
+        if !graph.crs.is_geographic() {
+            let meters_per_unit = graph.crs.linear_units();
+            if !(meters_per_unit.is_finite() && meters_per_unit > 0.0) {
+                return Err(Error::CrsMismatch(format!(
+                    "Cannot convert distances from meters to the units of the CRS '{}', whose linear unit has no \
+                    known length in meters. Set units: CrsUnits to give the distances in its units",
+                    graph.crs.name().unwrap_or_default()
+                )));
+            }
+            if meters_per_unit == 1.0 {
+                return Ok(1.0);
+            }
+            let factor = 1.0 / meters_per_unit;
+            log::info!(
+                "Converting distances from meters to {} by a factor of {}",
+                graph
+                    .crs
+                    .linear_units_name()
+                    .unwrap_or_else(|_| "CRS units".to_string()),
+                factor
+            );
+            return Ok(factor);
+        }
+        let central_lat = match graph.bounding_box() {
+            Some(bounding_box) => bounding_box.center().y,
+            None => return Ok(1.0),
+        };
+        if central_lat.abs() > self.anisotropy_warning_latitude {
+            log::warn!(
+                "Converting distances from meters to degrees at latitude {:.3}, where degrees of longitude and \
+                latitude differ greatly in length. Consider using a projected CRS",
+                central_lat
+            );
+        }
+        let (lon_degrees, lat_degrees) = meters_to_degrees_at(central_lat, 1.0);
+        let factor = lon_degrees.min(lat_degrees);
+        log::info!(
+            "Converting distances from meters to degrees at latitude {:.3} by a factor of {:e}",
+            central_lat,
+            factor
+        );
+        Ok(factor)
+    }
+
+    /// Check that the distances and factors are in their valid ranges, e.g. that the resampling distance and the
+    /// hole radii are positive and finite.
+    pub fn validate(&self) -> Result<()> {
+        let ensure = |valid: bool, name: &str, requirement: &str, value: f64| {
+            if valid {
+                Ok(())
+            } else {
+                Err(Error::ConfigError(format!(
+                    "{} must be {}, but is {}",
+                    name, requirement, value
+                )))
+            }
+        };
+        let is_positive = |value: f64| value.is_finite() && value > 0.0;
+        let is_non_negative = |value: f64| value.is_finite() && value >= 0.0;
+        ensure(
+            is_positive(self.resampling_distance),
+            "resampling_distance",
+            "positive",
+            self.resampling_distance,
+        )?;
+        ensure(
+            is_positive(self.hole_radius),
+            "hole_radius",
+            "positive",
+            self.hole_radius,
+        )?;
+        for (road_class, hole_radius) in self.hole_radius_by_class.iter().flatten() {
+            ensure(
+                is_positive(*hole_radius),
+                &format!("The hole radius of road class '{}'", road_class),
+                "positive",
+                *hole_radius,
+            )?;
+        }
+        if let Some(EvaluationMask::ProposalBuffer { distance }) = &self.evaluation_mask {
+            ensure(
+                is_non_negative(*distance),
+                "The distance of the evaluation mask",
+                "non-negative",
+                *distance,
+            )?;
+        }
+        if let Some(factor) = self.max_network_detour_factor {
+            ensure(
+                factor.is_finite() && factor >= 1.0,
+                "max_network_detour_factor",
+                "at least 1",
+                factor,
+            )?;
+        }
+        if let Some(radius) = self.proposal_dedup_radius {
+            ensure(
+                is_non_negative(radius),
+                "proposal_dedup_radius",
+                "non-negative",
+                radius,
+            )?;
+        }
+        if let Some(distance) = self.exclude_near_junction_distance {
+            ensure(
+                is_non_negative(distance),
+                "exclude_near_junction_distance",
+                "non-negative",
+                distance,
+            )?;
+        }
+        for offset in self.sampling_phase_offsets.iter().flatten() {
+            ensure(
+                (0.0..1.0).contains(offset),
+                "A sampling phase offset",
+                "in [0, 1)",
+                *offset,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The largest hole radius of any road class.
+    pub fn max_hole_radius(&self) -> f64 {
+        self.hole_radius_by_class
+            .iter()
+            .flat_map(|hole_radius_by_class| hole_radius_by_class.values())
+            .fold(self.hole_radius, |max_radius, radius| {
+                max_radius.max(*radius)
+            })
+    }
 }
 
-fn get_normalized_line_azimuth(line: &geo::Line) -> f64 {
-    let mut delta = line.delta();
+/// Edge data from which attributes can be read, e.g. the road class for class-dependent hole radii.
+pub trait EdgeAttributeData {
+    /// The value of the attribute `name`, if any.
+    fn attribute(&self, name: &str) -> Option<FieldValue>;
 
-    // Normalize the delta so the X component is always positive.
-    if delta.x < 0.0 {
-        delta = -delta;
+    /// Set the attribute `name`. Edge data without attributes ignores it.
+    fn set_attribute(&mut self, _name: &str, _value: FieldValue) {}
+
+    /// The road class stored under `attribute`, if any.
+    fn road_class(&self, attribute: &str) -> Option<String> {
+        match self.attribute(attribute)? {
+            FieldValue::StringValue(value) => Some(value),
+            FieldValue::IntegerValue(value) => Some(value.to_string()),
+            FieldValue::Integer64Value(value) => Some(value.to_string()),
+            _ => None,
+        }
     }
-    let azimuth = delta.y.atan2(delta.x);
-    if azimuth == -FRAC_PI_2 {
-        // Treat a vertical upwards line the same as a vertical downwards line.
-        return FRAC_PI_2;
+}
+
+impl EdgeAttributeData for () {
+    fn attribute(&self, _: &str) -> Option<FieldValue> {
+        None
     }
-    azimuth
 }
 
-#[cfg(test)]
-mod tests {
-    extern crate approx;
-    use approx::assert_abs_diff_eq;
-    use rstest::{fixture, rstest};
-    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+impl EdgeAttributeData for FeatureMap {
+    fn attribute(&self, name: &str) -> Option<FieldValue> {
+        self.get(name).cloned()
+    }
 
-    use crate::geograph::{primitives::GeoGraph, utils::build_geograph_from_lines};
+    fn set_attribute(&mut self, name: &str, value: FieldValue) {
+        self.insert(name.to_string(), value);
+    }
+}
 
-    use super::{
-        calculate_topo, get_normalized_line_azimuth, sample_points_on_line, F1ScoreResult,
-        TopoParams,
-    };
+/// Records the metrics of the stages of the TOPO computation, and checks for cancellation between and within them.
+pub(crate) struct StageTracker<'a> {
+    cancellation_token: Option<&'a CancellationToken>,
+    progress: &'a dyn ProgressSink,
+    timer: StageTimer,
+}
 
-    #[rstest]
-    #[case((0.0, 0.0), (1.0, 0.0), 0.0)]
-    #[case((0.0, 0.0), (-1.0, 0.0), 0.0)]
-    #[case((0.0, 0.0), (0.0, 1.0), FRAC_PI_2)]
-    #[case((0.0, 0.0), (0.0, -1.0), FRAC_PI_2)]
-    #[case((0.0, 0.0), (1.0, 1.0), FRAC_PI_4)]
-    #[case((0.0, 0.0), (-1.0, -1.0), FRAC_PI_4)]
-    #[case((0.0, 0.0), (1.0, -1.0), -FRAC_PI_4)]
-    fn test_get_normalized_line_azimuth(
-        #[case] line_start: (f64, f64),
-        #[case] line_end: (f64, f64),
-        #[case] expected_aximuth: f64,
-    ) {
-        let line = geo::Line::new(geo::Coord::from(line_start), geo::Coord::from(line_end));
-        let azimuth = get_normalized_line_azimuth(&line);
-        assert_abs_diff_eq!(expected_aximuth, azimuth);
+impl<'a> StageTracker<'a> {
+    pub(crate) fn new(
+        cancellation_token: Option<&'a CancellationToken>,
+        progress: Option<&'a dyn ProgressSink>,
+    ) -> Self {
+        Self {
+            cancellation_token,
+            progress: progress.unwrap_or(&NoopProgress),
+            timer: StageTimer::new(),
+        }
     }
 
-    #[rstest]
-    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 5.0, vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)])] // Split exactly in two.
-    #[case(vec![(0.0, 0.0), (9.0, 0.0)], 4.5, vec![(0.0, 0.0), (4.5, 0.0), (9.0, 0.0)])] // Split exactly in two, float.
-    #[case(vec![(0.0, 0.0), (9.0, 0.0)], 3.0, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Split exactly in three.
-    #[case(vec![(0.0, 0.0), (12.0, 0.0)], 5.0, vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0), (12.0, 0.0)])] // Split in three with leeway.
-    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 10.0, vec![(0.0, 0.0), (10.0, 0.0)])] // Split by length.
-    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 11.0, vec![(0.0, 0.0), (10.0, 0.0)])] // Split by more than length.
-    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 0.0, vec![])] // Split by zero.
-    #[case(vec![(0.0, 0.0), (10.0, 0.0)], -1.0, vec![])] // Split by negative.
-    #[case(vec![(0.0, 0.0), (5.0, 0.0), (9.0, 0.0)], 3.0, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Split linestr with multiple vertices.
-    #[case(vec![(0.0, 0.0), (4.5, 0.0), (4.5, 4.5)], 3.0, vec![(0.0, 0.0), (3.0, 0.0), (4.5, 1.5), (4.5, 4.5)])] // Split curving linestr with multiple vertices.
-    fn test_sample_points_on_line(
-        #[case] input_linestr: Vec<(f64, f64)>,
-        #[case] resampling_distance: f64,
-        #[case] expected_coordinates: Vec<(f64, f64)>,
-    ) {
-        let input_linestr: geo::LineString = input_linestr.into();
-        let result = sample_points_on_line(&input_linestr, resampling_distance);
+    /// Finish the current stage, if any, and start a new one. Fails if the computation was cancelled.
+    fn start_stage(&mut self, stage: &'static str) -> Result<()> {
+        self.timer.start_stage(stage);
+        self.check_cancelled()
+    }
 
-        let expected_coords_linestr: geo::LineString = expected_coordinates.into();
-        let actual_coords_linestr: geo::LineString =
-            result.iter().map(|point| point.coord).collect();
-        assert_abs_diff_eq!(
-            expected_coords_linestr,
-            actual_coords_linestr,
-            epsilon = 1e-6
-        );
+    /// The sink to report the progress within stages to.
+    pub(crate) fn progress(&self) -> &dyn ProgressSink {
+        self.progress
     }
 
-    #[fixture]
-    fn default_topo_params() -> TopoParams {
-        TopoParams {
-            resampling_distance: 11.0,
-            hole_radius: 6.0,
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        if is_cancelled(self.cancellation_token) {
+            return Err(self.cancelled_error());
         }
+        Ok(())
     }
 
-    #[rstest]
-    #[case(vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)], vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)], F1ScoreResult {
-        f1_score: 1.0,
-        precision: 1.0,
-        recall: 1.0
-    })] // Perfectly matching lines.
-    #[case(vec![(0.0, 0.0), (6.0, 0.0)], vec![(0.0, 0.0), (6.0, 0.0), (12.0, 0.0)], F1ScoreResult {
-        f1_score: 4.0 / 5.0,
-        precision: 1.0,
-        recall: 2.0 / 3.0
-    })] // Two points match, one GT point is unmatched.
-    fn test_calculate_topo_two_lines(
-        #[case] proposal_line_coords: Vec<(f64, f64)>,
-        #[case] ground_truth_line_coods: Vec<(f64, f64)>,
-        #[case] expected_result: F1ScoreResult,
-        default_topo_params: TopoParams,
-    ) {
-        let proposal_line: geo::LineString = proposal_line_coords.into();
-        let ground_truth_line: geo::LineString = ground_truth_line_coods.into();
-        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
-            build_geograph_from_lines(vec![proposal_line]).unwrap();
-        let ground_truth_graph = build_geograph_from_lines(vec![ground_truth_line]).unwrap();
+    fn cancelled_error(&self) -> Error {
+        Error::Cancelled {
+            stage: self.timer.current_stage(),
+            completed_stages: self.timer.completed_stages().to_vec(),
+        }
+    }
+}
 
-        let result = calculate_topo(&proposal_graph, &ground_truth_graph, &default_topo_params);
-        assert!(result.is_ok());
-        assert_eq!(expected_result, result.unwrap().f1_score_result)
+/// Calculate the TOPO metric.
+///
+/// The computation is the composition of its phases `sample_graph`, `build_gt_index` and `match_nodes`, which can
+/// also be run separately, e.g. to cache their results or to experiment with alternative matchers.
+///
+/// If a `cancellation_token` is given, it is checked periodically during the computation. Once it is cancelled,
+/// the computation stops and an `Error::Cancelled` error is returned.
+///
+/// If a `progress` sink is given, the progress of the longer stages is reported to it.
+pub fn calculate_topo<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    cancellation_token: Option<&CancellationToken>,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<TopoResult> {
+    calculate_topo_tracked(
+        proposal_graph,
+        ground_truth_graph,
+        params,
+        cancellation_token,
+        progress,
+        None,
+    )
+}
+
+/// Like `calculate_topo`, but additionally writes the match candidates of all proposal nodes to `candidate_sink`,
+/// right after they are looked up and before any nodes are matched.
+pub fn calculate_topo_with_candidate_sink<
+    E: Default + EdgeAttributeData,
+    N: Default,
+    Ty: petgraph::EdgeType,
+>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    cancellation_token: Option<&CancellationToken>,
+    progress: Option<&dyn ProgressSink>,
+    candidate_sink: &mut dyn CandidateSink,
+) -> Result<TopoResult> {
+    calculate_topo_tracked(
+        proposal_graph,
+        ground_truth_graph,
+        params,
+        cancellation_token,
+        progress,
+        Some(candidate_sink),
+    )
+}
+
+fn calculate_topo_tracked<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    ground_truth_graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    cancellation_token: Option<&CancellationToken>,
+    progress: Option<&dyn ProgressSink>,
+    candidate_sink: Option<&mut dyn CandidateSink>,
+) -> Result<TopoResult> {
+    ensure_directional_matching_supported::<Ty>(params)?;
+    let params = &params.in_crs_units(ground_truth_graph)?;
+    calculate_topo_from_lines_tracked(
+        &proposal_graph.edge_geometries(),
+        &EdgeNodeData::from_graph(proposal_graph, params, GraphRole::Proposal),
+        &ground_truth_graph.edge_geometries(),
+        &EdgeNodeData::from_graph(ground_truth_graph, params, GraphRole::GroundTruth),
+        params,
+        cancellation_token,
+        progress,
+        candidate_sink,
+    )
+}
+
+fn ensure_directional_matching_supported<Ty: petgraph::EdgeType>(
+    params: &TopoParams,
+) -> Result<()> {
+    if params.directional_matching && !Ty::is_directed() {
+        return Err(Error::ConfigError(
+            "directional_matching requires directed graphs".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Calculate the TOPO metric of two collections of lines, e.g. the edge geometries of graphs which are not available
+/// as GeoGraphs. Like `calculate_topo`, which extracts the edge geometries of its graphs and shares all of the
+/// computation with this function.
+///
+/// The lines must be in the same projected CRS, whose units are those of the distances in `params`. Unlike for
+/// `calculate_topo`, distances are never converted from meters, and `params.units` is ignored. As lines carry no
+/// attributes, nodes have no road classes and no propagated attributes.
+pub fn calculate_topo_from_lines(
+    proposal: &[geo::LineString],
+    ground_truth: &[geo::LineString],
+    params: &TopoParams,
+) -> Result<TopoResult> {
+    calculate_topo_from_lines_tracked(
+        proposal,
+        &EdgeNodeData::default(),
+        ground_truth,
+        &EdgeNodeData::default(),
+        params,
+        None,
+        None,
+        None,
+    )
+}
+
+/// Calculate TOPO on the lines with the data of their edges, see `calculate_topo_from_lines`. `params` must be in
+/// the units of the CRS of the lines.
+#[allow(clippy::too_many_arguments)]
+fn calculate_topo_from_lines_tracked(
+    proposal_lines: &[geo::LineString],
+    proposal_edge_data: &EdgeNodeData,
+    ground_truth_lines: &[geo::LineString],
+    ground_truth_edge_data: &EdgeNodeData,
+    params: &TopoParams,
+    cancellation_token: Option<&CancellationToken>,
+    progress: Option<&dyn ProgressSink>,
+    candidate_sink: Option<&mut dyn CandidateSink>,
+) -> Result<TopoResult> {
+    let mut stage_tracker = StageTracker::new(cancellation_token, progress);
+    log::info!("Calculating TOPO with parameters in CRS units {:?}", params);
+    let SampledGraphs {
+        proposal_nodes,
+        ground_truth_index: GtIndex { nodes, kdtree },
+        junctions,
+    } = sample_graphs_tracked(
+        proposal_lines,
+        proposal_edge_data,
+        ground_truth_lines,
+        ground_truth_edge_data,
+        params,
+        &mut stage_tracker,
+    )?;
+    match_sampled_graphs_tracked(
+        proposal_nodes,
+        nodes,
+        &kdtree,
+        junctions.as_deref(),
+        proposal_lines,
+        proposal_edge_data,
+        params,
+        candidate_sink,
+        stage_tracker,
+    )
+}
+
+/// The nodes sampled on both graphs and the lookup tree of the ground truth nodes, which only depend on the
+/// parameters of the sampling, see `sampling_params`.
+struct SampledGraphs {
+    proposal_nodes: Vec<TopoNode>,
+    ground_truth_index: GtIndex,
+    /// The junctions of both graphs, if `TopoParams::exclude_near_junction_distance` is set.
+    junctions: Option<Vec<geo::Coord>>,
+}
+
+/// Sample the nodes of both graphs, exclude the nodes near junctions, and build the lookup tree of the ground truth
+/// nodes.
+fn sample_graphs_tracked(
+    proposal_lines: &[geo::LineString],
+    proposal_edge_data: &EdgeNodeData,
+    ground_truth_lines: &[geo::LineString],
+    ground_truth_edge_data: &EdgeNodeData,
+    params: &TopoParams,
+    stage_tracker: &mut StageTracker,
+) -> Result<SampledGraphs> {
+    // Points are sampled starting at the first coordinate of each edge geometry. Normalize the edge orientation of
+    // both graphs beforehand to make the result independent of the digitization direction of the edges, see
+    // `GeoGraph::normalize_edge_orientation`.
+    let proposal_nodes = sample_lines_tracked(
+        proposal_lines,
+        proposal_edge_data,
+        params,
+        GraphRole::Proposal,
+        0.0,
+        stage_tracker,
+    )?;
+    let ground_truth_nodes = sample_lines_tracked(
+        ground_truth_lines,
+        ground_truth_edge_data,
+        params,
+        GraphRole::GroundTruth,
+        0.0,
+        stage_tracker,
+    )?;
+    let junctions: Option<Vec<geo::Coord>> = params.exclude_near_junction_distance.map(|_| {
+        junction_coords(proposal_lines)
+            .into_iter()
+            .chain(junction_coords(ground_truth_lines))
+            .collect()
+    });
+    let (proposal_nodes, ground_truth_nodes) =
+        match (params.exclude_near_junction_distance, &junctions) {
+            (Some(distance), Some(junctions)) => {
+                stage_tracker.start_stage("exclude_near_junctions")?;
+                (
+                    exclude_nodes_near_junctions(
+                        proposal_nodes,
+                        junctions,
+                        distance,
+                        "proposal",
+                        stage_tracker,
+                    )?,
+                    exclude_nodes_near_junctions(
+                        ground_truth_nodes,
+                        junctions,
+                        distance,
+                        "ground truth",
+                        stage_tracker,
+                    )?,
+                )
+            }
+            _ => (proposal_nodes, ground_truth_nodes),
+        };
+    Ok(SampledGraphs {
+        proposal_nodes,
+        ground_truth_index: GtIndex::build(ground_truth_nodes, stage_tracker)?,
+        junctions,
+    })
+}
+
+/// Match the sampled nodes, and additionally those of the proposal sampled with each phase offset of `params`.
+/// `ground_truth_kdtree` holds the indices of `ground_truth_nodes`.
+#[allow(clippy::too_many_arguments)]
+fn match_sampled_graphs_tracked(
+    proposal_nodes: Vec<TopoNode>,
+    ground_truth_nodes: Vec<TopoNode>,
+    ground_truth_kdtree: &kdtree::KdTree<f64, usize, [f64; 2]>,
+    junctions: Option<&[geo::Coord]>,
+    proposal_lines: &[geo::LineString],
+    proposal_edge_data: &EdgeNodeData,
+    params: &TopoParams,
+    candidate_sink: Option<&mut dyn CandidateSink>,
+    mut stage_tracker: StageTracker,
+) -> Result<TopoResult> {
+    let (nodes, spilled_nodes) = if params.low_memory {
+        log::info!(
+            "Spilling {} ground truth points to disk",
+            ground_truth_nodes.len()
+        );
+        stage_tracker.start_stage("spill_ground_truth")?;
+        let spilled_nodes =
+            SpilledNodes::write(ground_truth_nodes.iter().map(TopoNode::to_record))?;
+        // Free the nodes right away, as they would otherwise be held in memory as well while matching.
+        drop(ground_truth_nodes);
+        (Vec::new(), Some(spilled_nodes))
+    } else {
+        (ground_truth_nodes, None)
+    };
+    let phase_offset_scores = match &params.sampling_phase_offsets {
+        Some(phase_offsets) => {
+            let mut scores = Vec::with_capacity(phase_offsets.len());
+            for phase_offset in phase_offsets {
+                log::info!("Sampling the proposal with phase offset {}", phase_offset);
+                let mut offset_proposal_nodes = sample_lines_tracked(
+                    proposal_lines,
+                    proposal_edge_data,
+                    params,
+                    GraphRole::Proposal,
+                    *phase_offset,
+                    &mut stage_tracker,
+                )?;
+                if let (Some(distance), Some(junctions)) =
+                    (params.exclude_near_junction_distance, junctions)
+                {
+                    stage_tracker.start_stage("exclude_near_junctions")?;
+                    offset_proposal_nodes = exclude_nodes_near_junctions(
+                        offset_proposal_nodes,
+                        junctions,
+                        distance,
+                        "proposal",
+                        &stage_tracker,
+                    )?;
+                }
+                let offset_ground_truth_nodes = match &spilled_nodes {
+                    Some(spilled_nodes) => GroundTruthNodes::Spilled(spilled_nodes),
+                    None => GroundTruthNodes::InMemory(nodes.clone()),
+                };
+                let outcome = match_nodes_tracked(
+                    offset_proposal_nodes,
+                    offset_ground_truth_nodes,
+                    ground_truth_kdtree,
+                    params,
+                    &EdgeCompatibility,
+                    None,
+                    &mut stage_tracker,
+                )?;
+                log::info!(
+                    "With phase offset {}: {:?}",
+                    phase_offset,
+                    outcome.f1_score_result
+                );
+                scores.push(PhaseOffsetScore {
+                    phase_offset: *phase_offset,
+                    f1_score_result: outcome.f1_score_result,
+                });
+            }
+            Some(PhaseOffsetScores::new(scores))
+        }
+        None => None,
+    };
+    let ground_truth_nodes = match &spilled_nodes {
+        Some(spilled_nodes) => GroundTruthNodes::Spilled(spilled_nodes),
+        None => GroundTruthNodes::InMemory(nodes),
+    };
+    let match_outcome = match_nodes_tracked(
+        proposal_nodes,
+        ground_truth_nodes,
+        ground_truth_kdtree,
+        params,
+        &EdgeCompatibility,
+        candidate_sink,
+        &mut stage_tracker,
+    )?;
+    Ok(TopoResult {
+        f1_score_result: match_outcome.f1_score_result,
+        unmasked_f1_score_result: match_outcome.unmasked_f1_score_result,
+        class_breakdown: match_outcome.class_breakdown,
+        ground_truth_nodes: match_outcome.ground_truth_nodes,
+        proposal_nodes: match_outcome.proposal_nodes,
+        stage_metrics: stage_tracker.timer.into_metrics(),
+        phase_offset_scores,
+    })
+}
+
+/// Default number of sets of sampled nodes which a `TopoEvaluator` keeps.
+pub const DEFAULT_EVALUATOR_CACHE_CAPACITY: usize = 4;
+
+/// The parameters which the nodes sampled on both graphs depend on: `params` with the parameters which only affect
+/// the matching reset to their defaults. Sampled nodes can be reused for all parameters with the same sampling
+/// parameters, once the hole radii of the ground truth nodes are assigned again with `assign_hole_radii`.
+fn sampling_params(params: &TopoParams) -> TopoParams {
+    TopoParams {
+        hole_radius: DEFAULT_HOLE_RADIUS,
+        matching_mode: MatchingMode::default(),
+        hole_radius_by_class: None,
+        evaluation_mask: None,
+        anisotropy_warning_latitude: default_anisotropy_warning_latitude(),
+        max_network_detour_factor: None,
+        sampling_phase_offsets: None,
+        directional_matching: false,
+        low_memory: false,
+        ..params.clone()
+    }
+}
+
+/// The nodes sampled with one set of sampling parameters, see `TopoEvaluator`.
+struct SamplingCacheEntry {
+    sampling_params: TopoParams,
+    proposal_edge_data: EdgeNodeData,
+    sampled_graphs: SampledGraphs,
+}
+
+/// Calculates TOPO of two graphs for several sets of parameters, e.g. for a sweep over the resampling distance and
+/// the hole radius, loading and projecting the graphs only once.
+///
+/// The nodes sampled on both graphs and the lookup tree of the ground truth nodes are cached by the parameters they
+/// depend on, see `sampling_params`, so that evaluations which only differ in the hole radii or other matching
+/// parameters skip the sampling. As each cached set of nodes takes up about as much memory as the nodes of an
+/// evaluation, the cache holds the nodes of at most `cache_capacity` resampling distances, and evicts the least
+/// recently used ones.
+///
+/// The results are the same as those of `calculate_topo` with the same parameters, except for the metrics of the
+/// skipped stages.
+pub struct TopoEvaluator<'a, E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType> {
+    proposal_graph: &'a GeoGraph<E, N, Ty>,
+    ground_truth_graph: &'a GeoGraph<E, N, Ty>,
+    proposal_lines: Vec<geo::LineString>,
+    ground_truth_lines: Vec<geo::LineString>,
+    cache_capacity: usize,
+    /// In the order of their last use, the most recently used last.
+    cache: VecDeque<SamplingCacheEntry>,
+}
+
+impl<'a, E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>
+    TopoEvaluator<'a, E, N, Ty>
+{
+    /// An evaluator of graphs in the same projected CRS, like for `calculate_topo`. A `cache_capacity` of 0 disables
+    /// the cache.
+    pub fn new(
+        proposal_graph: &'a GeoGraph<E, N, Ty>,
+        ground_truth_graph: &'a GeoGraph<E, N, Ty>,
+        cache_capacity: usize,
+    ) -> Self {
+        Self {
+            proposal_graph,
+            ground_truth_graph,
+            proposal_lines: proposal_graph.edge_geometries(),
+            ground_truth_lines: ground_truth_graph.edge_geometries(),
+            cache_capacity,
+            cache: VecDeque::with_capacity(cache_capacity),
+        }
+    }
+
+    /// Calculate TOPO with `params`, like `calculate_topo`, reusing the sampled nodes of an earlier evaluation with
+    /// the same sampling parameters.
+    pub fn evaluate(&mut self, params: &TopoParams) -> Result<TopoResult> {
+        ensure_directional_matching_supported::<Ty>(params)?;
+        let params = &params.in_crs_units(self.ground_truth_graph)?;
+        let mut stage_tracker = StageTracker::new(None, None);
+        log::info!("Calculating TOPO with parameters in CRS units {:?}", params);
+        let sampling_params = sampling_params(params);
+        let cached_entry = self
+            .cache
+            .iter()
+            .position(|entry| entry.sampling_params == sampling_params)
+            .and_then(|idx| self.cache.remove(idx));
+        let entry = match cached_entry {
+            Some(entry) => {
+                log::info!(
+                    "Reusing the points sampled with resampling distance {}",
+                    params.resampling_distance
+                );
+                entry
+            }
+            None => {
+                let proposal_edge_data =
+                    EdgeNodeData::from_graph(self.proposal_graph, params, GraphRole::Proposal);
+                let sampled_graphs = sample_graphs_tracked(
+                    &self.proposal_lines,
+                    &proposal_edge_data,
+                    &self.ground_truth_lines,
+                    &EdgeNodeData::from_graph(
+                        self.ground_truth_graph,
+                        params,
+                        GraphRole::GroundTruth,
+                    ),
+                    params,
+                    &mut stage_tracker,
+                )?;
+                SamplingCacheEntry {
+                    sampling_params,
+                    proposal_edge_data,
+                    sampled_graphs,
+                }
+            }
+        };
+        let mut ground_truth_nodes = entry.sampled_graphs.ground_truth_index.nodes.clone();
+        assign_hole_radii(&mut ground_truth_nodes, params);
+        let result = match_sampled_graphs_tracked(
+            entry.sampled_graphs.proposal_nodes.clone(),
+            ground_truth_nodes,
+            &entry.sampled_graphs.ground_truth_index.kdtree,
+            entry.sampled_graphs.junctions.as_deref(),
+            &self.proposal_lines,
+            &entry.proposal_edge_data,
+            params,
+            None,
+            stage_tracker,
+        );
+        if self.cache_capacity > 0 {
+            if self.cache.len() == self.cache_capacity {
+                self.cache.pop_front();
+            }
+            self.cache.push_back(entry);
+        }
+        result
+    }
+}
+
+/// The graph a set of TOPO nodes is sampled from, which determines the data the nodes carry.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum GraphRole {
+    /// Nodes carry the edge attributes listed in `TopoParams::propagate_edge_attributes`.
+    Proposal,
+    /// Nodes carry the hole radius of the road class of their edge.
+    GroundTruth,
+}
+
+/// Sample TOPO nodes on the edges of a graph, every `params.resampling_distance`. Coincident points are merged into
+/// one node, and node ids are their indices in the returned nodes.
+///
+/// `params` must be in the units of the CRS of the graph, see `TopoParams::in_crs_units`. `calculate_topo` converts
+/// the parameters for both graphs at the central latitude of the ground truth.
+pub fn sample_graph<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    role: GraphRole,
+) -> Result<Vec<TopoNode>> {
+    sample_graph_tracked(graph, params, role, &mut StageTracker::new(None, None))
+}
+
+fn sample_graph_tracked<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    params: &TopoParams,
+    role: GraphRole,
+    stage_tracker: &mut StageTracker,
+) -> Result<Vec<TopoNode>> {
+    sample_lines_tracked(
+        &graph.edge_geometries(),
+        &EdgeNodeData::from_graph(graph, params, role),
+        params,
+        role,
+        0.0,
+        stage_tracker,
+    )
+}
+
+/// The data of edges which the nodes sampled on them carry, by edge index.
+#[derive(Default)]
+struct EdgeNodeData {
+    road_classes: Option<Vec<Option<String>>>,
+    propagated_attributes: Option<Vec<FeatureMap>>,
+    /// The edges which are not sampled, as the points sampled on their reciprocal edge stand for them, see
+    /// `TopoParams::collapse_reciprocal_edges`.
+    collapsed_edges: HashSet<usize>,
+}
+
+impl EdgeNodeData {
+    /// The data of the edges of a graph, in the order of `GeoGraph::edge_geometries`: the road classes if a road class
+    /// attribute is set for the role, and for proposals the propagated attributes.
+    fn from_graph<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+        graph: &GeoGraph<E, N, Ty>,
+        params: &TopoParams,
+        role: GraphRole,
+    ) -> Self {
+        let road_class_attribute = match role {
+            GraphRole::Proposal => params
+                .proposal_road_class_attribute
+                .as_ref()
+                .or(params.road_class_attribute.as_ref()),
+            GraphRole::GroundTruth => params.road_class_attribute.as_ref(),
+        };
+        let road_classes = road_class_attribute.map(|road_class_attribute| {
+            graph
+                .edges_sorted()
+                .map(|(_, _, _, edge)| edge.data.road_class(road_class_attribute))
+                .collect()
+        });
+        let propagated_attributes = match role {
+            GraphRole::Proposal if !params.propagate_edge_attributes.is_empty() => Some(
+                graph
+                    .edges_sorted()
+                    .map(|(_, _, _, edge)| {
+                        params
+                            .propagate_edge_attributes
+                            .iter()
+                            .filter_map(|name| Some((name.clone(), edge.data.attribute(name)?)))
+                            .collect()
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let collapsed_edges = if params.collapse_reciprocal_edges {
+            reciprocal_edge_pairs(graph, params.dedup_radius())
+                .into_iter()
+                .map(|(_, reciprocal_edge_idx)| reciprocal_edge_idx)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        Self {
+            road_classes,
+            propagated_attributes,
+            collapsed_edges,
+        }
+    }
+}
+
+/// Sample TOPO nodes on lines, like `sample_graph` on the edges of a graph.
+///
+/// # Arguments
+/// * edge_data - The data of the lines which their nodes carry.
+/// * phase_offset - Fraction of the resampling distance by which the interpolated points are shifted, see
+///   `TopoParams::sampling_phase_offsets`.
+fn sample_lines_tracked(
+    lines: &[geo::LineString],
+    edge_data: &EdgeNodeData,
+    params: &TopoParams,
+    role: GraphRole,
+    phase_offset: f64,
+    stage_tracker: &mut StageTracker,
+) -> Result<Vec<TopoNode>> {
+    let (graph_name, stage) = match role {
+        GraphRole::Proposal => ("proposal", "sample_proposal"),
+        GraphRole::GroundTruth => ("ground truth", "sample_ground_truth"),
+    };
+    log::info!("Sampling points on {} lines", graph_name);
+    stage_tracker.start_stage(stage)?;
+    let mut points = sample_points_on_lines(
+        lines,
+        params.resampling_distance,
+        phase_offset,
+        params.include_original_vertices,
+        stage,
+        stage_tracker,
+    )?;
+    if !edge_data.collapsed_edges.is_empty() {
+        log::info!(
+            "Sampling {} pairs of reciprocal {} edges only once",
+            edge_data.collapsed_edges.len(),
+            graph_name
+        );
+        points.retain(|point| !edge_data.collapsed_edges.contains(&point.edge_idx));
+    }
+    ensure_road_points_finite(&points, lines, graph_name)?;
+    let mut nodes = road_points_to_topo_nodes(points, stage_tracker)?;
+    if let Some(road_classes) = &edge_data.road_classes {
+        for node in nodes.iter_mut() {
+            node.road_class = road_classes[node.road_point.edge_idx].clone();
+        }
+    }
+    if let Some(propagated_attributes) = &edge_data.propagated_attributes {
+        for node in nodes.iter_mut() {
+            node.edge_attributes = propagated_attributes[node.road_point.edge_idx].clone();
+        }
+    }
+    if role == GraphRole::GroundTruth {
+        assign_hole_radii(&mut nodes, params);
+    }
+    if role == GraphRole::Proposal && params.deduplicate_proposal_points {
+        stage_tracker.start_stage("deduplicate_proposal_points")?;
+        let sampled_count = nodes.len();
+        nodes = deduplicate_nodes(nodes, params.dedup_radius(), stage_tracker)?;
+        log::info!(
+            "Merged {} of {} proposal points into coincident points of other edges",
+            sampled_count - nodes.len(),
+            sampled_count
+        );
+    }
+    Ok(nodes)
+}
+
+/// Set the hole radii of ground truth nodes by their road classes.
+fn assign_hole_radii(nodes: &mut [TopoNode], params: &TopoParams) {
+    for node in nodes.iter_mut() {
+        node.hole_radius = Some(params.hole_radius_for_class(node.road_class.as_deref()));
+    }
+}
+
+/// The coordinates where the ends of at least three lines meet, i.e. the nodes of degree 3 or more of a graph whose
+/// edge geometries are `lines`. A line which starts and ends at the same coordinate counts twice, like a self-loop.
+fn junction_coords(lines: &[geo::LineString]) -> Vec<geo::Coord> {
+    let mut degrees: HashMap<(u64, u64), (geo::Coord, usize)> = HashMap::new();
+    for line in lines {
+        if let (Some(first), Some(last)) = (line.0.first(), line.0.last()) {
+            for coord in [first, last] {
+                degrees
+                    .entry((coord.x.to_bits(), coord.y.to_bits()))
+                    .or_insert((*coord, 0))
+                    .1 += 1;
+            }
+        }
+    }
+    degrees
+        .into_values()
+        .filter(|(_, degree)| *degree >= 3)
+        .map(|(coord, _)| coord)
+        .collect()
+}
+
+/// Drop the nodes within `distance` of any of the `junctions`, see `TopoParams::exclude_near_junction_distance`. The
+/// kept nodes get their indices in the returned nodes as their ids.
+fn exclude_nodes_near_junctions(
+    nodes: Vec<TopoNode>,
+    junctions: &[geo::Coord],
+    distance: f64,
+    graph_name: &str,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<TopoNode>> {
+    let mut kdtree = kdtree::KdTree::with_capacity(2, junctions.len());
+    for (idx, junction) in junctions.iter().enumerate() {
+        kdtree.add(<[f64; 2]>::from(*junction), idx)?;
+    }
+    let sampled_count = nodes.len();
+    let mut kept_nodes = Vec::with_capacity(sampled_count);
+    for node in nodes {
+        stage_tracker.check_cancelled()?;
+        let nearby_junctions = kdtree
+            .within(
+                &<[f64; 2]>::from(node.road_point.coord),
+                distance.powi(2),
+                &squared_euclidean,
+            )
+            .with_context(|| {
+                format!(
+                    "Could not get junctions near {} node {} at {:?}",
+                    graph_name, node.id, node.road_point.coord
+                )
+            })?;
+        if nearby_junctions.is_empty() {
+            kept_nodes.push(node);
+        }
+    }
+    log::info!(
+        "Excluded {} of {} {} points within {} of a junction",
+        sampled_count - kept_nodes.len(),
+        sampled_count,
+        graph_name,
+        distance
+    );
+    for (idx, node) in kept_nodes.iter_mut().enumerate() {
+        node.id = idx as u64;
+    }
+    Ok(kept_nodes)
+}
+
+/// Largest difference of the azimuths of the edges of two proposal points which are merged, see
+/// `TopoParams::deduplicate_proposal_points`.
+pub const MAX_DUPLICATE_AZIMUTH_DIFFERENCE: f64 = PI / 36.0;
+
+/// Merge nodes of different edges which are within `radius` of each other, and whose edges differ in azimuth by at
+/// most `MAX_DUPLICATE_AZIMUTH_DIFFERENCE`. In the order of the nodes, each node which is not merged yet is kept,
+/// and the nodes near it are merged into it. The kept nodes have the number of nodes merged into them, including
+/// themselves, as their weight, and their indices in the returned nodes as their ids.
+fn deduplicate_nodes(
+    nodes: Vec<TopoNode>,
+    radius: f64,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<TopoNode>> {
+    let kdtree = build_kdtree_from_nodes(&nodes, stage_tracker)?;
+    let mut merged = vec![false; nodes.len()];
+    let mut weights = vec![0; nodes.len()];
+    for (idx, node) in nodes.iter().enumerate() {
+        stage_tracker.check_cancelled()?;
+        if merged[idx] {
+            continue;
+        }
+        weights[idx] = 1;
+        let nearby_nodes = kdtree
+            .within(
+                &<[f64; 2]>::from(node.road_point.coord),
+                radius.powi(2),
+                &squared_euclidean,
+            )
+            .with_context(|| {
+                format!(
+                    "Could not get proposal nodes near proposal node {} at {:?}",
+                    node.id, node.road_point.coord
+                )
+            })?;
+        for (_, other_idx) in nearby_nodes {
+            let other_idx = *other_idx;
+            let other_node = &nodes[other_idx];
+            // Nodes before this one are either merged or kept themselves.
+            if other_idx > idx
+                && !merged[other_idx]
+                && other_node.road_point.edge_idx != node.road_point.edge_idx
+                && azimuth_difference(node.road_point.azimuth, other_node.road_point.azimuth)
+                    <= MAX_DUPLICATE_AZIMUTH_DIFFERENCE
+            {
+                merged[other_idx] = true;
+                weights[idx] += 1;
+            }
+        }
+    }
+    Ok(nodes
+        .into_iter()
+        .zip(weights)
+        .filter(|(_, weight)| *weight > 0)
+        .enumerate()
+        .map(|(new_idx, (mut node, weight))| {
+            node.id = new_idx as u64;
+            node.weight = weight;
+            node
+        })
+        .collect())
+}
+
+/// Build the lookup index of ground truth nodes sampled with `sample_graph`.
+pub fn build_gt_index(ground_truth_nodes: Vec<TopoNode>) -> Result<GtIndex> {
+    GtIndex::build(ground_truth_nodes, &mut StageTracker::new(None, None))
+}
+
+/// The result of matching proposal nodes to ground truth nodes.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct MatchOutcome {
+    /// The scores. If an evaluation mask is used, recall only counts the ground truth nodes within the mask.
+    pub f1_score_result: F1ScoreResult,
+    /// The scores counting all ground truth nodes, if an evaluation mask is used.
+    pub unmasked_f1_score_result: Option<F1ScoreResult>,
+    /// The scores by road class, if a road class attribute is set for either graph.
+    pub class_breakdown: Option<ClassBreakdown>,
+    pub ground_truth_nodes: Vec<TopoNode>,
+    pub proposal_nodes: Vec<TopoNode>,
+}
+
+/// Match proposal nodes to the ground truth nodes of `gt_index`, applying the evaluation mask and matching mode of
+/// `params`, and score the result.
+///
+/// `params` must be the same, in the same units, as for sampling the nodes. The index is left unchanged, so it can
+/// be reused for several proposals.
+pub fn match_nodes(
+    proposal_nodes: Vec<TopoNode>,
+    gt_index: &GtIndex,
+    params: &TopoParams,
+) -> Result<MatchOutcome> {
+    match_nodes_with_compatibility(proposal_nodes, gt_index, params, &EdgeCompatibility)
+}
+
+/// Like `match_nodes`, but with a custom check of the plausibility of matches along the road network, which is
+/// applied if `params.max_network_detour_factor` is set.
+pub fn match_nodes_with_compatibility(
+    proposal_nodes: Vec<TopoNode>,
+    gt_index: &GtIndex,
+    params: &TopoParams,
+    compatibility: &dyn MatchCompatibility,
+) -> Result<MatchOutcome> {
+    match_nodes_tracked(
+        proposal_nodes,
+        GroundTruthNodes::InMemory(gt_index.nodes.clone()),
+        &gt_index.kdtree,
+        params,
+        compatibility,
+        None,
+        &mut StageTracker::new(None, None),
+    )
+}
+
+/// Decides whether a proposal node may be matched to a ground truth node within its hole radius, if
+/// `TopoParams::max_network_detour_factor` is set. This is the hook for checks along the road network, e.g. of the
+/// shortest path in the ground truth graph between the locations, rejecting matches which imply a detour by more than
+/// `max_detour_factor`.
+pub trait MatchCompatibility: Sync {
+    fn is_compatible(
+        &self,
+        proposal_node: &TopoNode,
+        gt_node: &TopoNode,
+        max_detour_factor: f64,
+    ) -> bool;
+}
+
+/// Largest difference of the headings of the edges of two nodes which are matched if
+/// `TopoParams::directional_matching` is set. Anything up to perpendicular counts as the same direction, so that only
+/// edges running against each other are rejected, not matches on curves.
+pub const MAX_DIRECTIONAL_HEADING_DIFFERENCE: f64 = FRAC_PI_2;
+
+/// Largest difference of the azimuths of the edges of two nodes for `EdgeCompatibility`.
+pub const MAX_COMPATIBLE_AZIMUTH_DIFFERENCE: f64 = FRAC_PI_4;
+
+/// Accepts matches of nodes whose edges differ in azimuth by at most `MAX_COMPATIBLE_AZIMUTH_DIFFERENCE`, and have
+/// the same road class if both have one. This rejects matches to crossing roads and to parallel roads of another
+/// class, but not to parallel roads of the same class. The detour factor is not checked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EdgeCompatibility;
+
+impl MatchCompatibility for EdgeCompatibility {
+    fn is_compatible(&self, proposal_node: &TopoNode, gt_node: &TopoNode, _: f64) -> bool {
+        let classes_match = match (&proposal_node.road_class, &gt_node.road_class) {
+            (Some(proposal_class), Some(gt_class)) => proposal_class == gt_class,
+            _ => true,
+        };
+        classes_match
+            && azimuth_difference(proposal_node.road_point.azimuth, gt_node.road_point.azimuth)
+                <= MAX_COMPATIBLE_AZIMUTH_DIFFERENCE
+    }
+}
+
+/// Why `gt_node` cannot be matched to `proposal_node` at all, regardless of the other matches, or `None` if it is a
+/// match candidate of the proposal node.
+pub(crate) fn candidate_rejection(
+    proposal_node: &TopoNode,
+    gt_node: &TopoNode,
+    squared_distance: f64,
+    params: &TopoParams,
+    compatibility: &dyn MatchCompatibility,
+) -> Option<RejectionReason> {
+    if gt_node.excluded {
+        return Some(RejectionReason::Excluded);
+    }
+    if squared_distance > gt_node.hole_radius.unwrap().powi(2) {
+        return Some(RejectionReason::OutOfRadius);
+    }
+    if params.directional_matching
+        && heading_difference(proposal_node.road_point.heading, gt_node.road_point.heading)
+            > MAX_DIRECTIONAL_HEADING_DIFFERENCE
+    {
+        return Some(RejectionReason::DirectionMismatch);
+    }
+    match params.max_network_detour_factor {
+        Some(factor) if !compatibility.is_compatible(proposal_node, gt_node, factor) => {
+            if azimuth_difference(proposal_node.road_point.azimuth, gt_node.road_point.azimuth)
+                > MAX_COMPATIBLE_AZIMUTH_DIFFERENCE
+            {
+                Some(RejectionReason::AzimuthGate)
+            } else {
+                Some(RejectionReason::Incompatible)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The angle between two headings, see `get_line_heading`, between 0 and PI.
+pub(crate) fn heading_difference(heading: f64, other_heading: f64) -> f64 {
+    let difference = (heading - other_heading).abs() % (2.0 * PI);
+    difference.min(2.0 * PI - difference)
+}
+
+/// The angle between two lines with normalized azimuths, see `get_normalized_line_azimuth`, between 0 and PI/2.
+pub(crate) fn azimuth_difference(azimuth: f64, other_azimuth: f64) -> f64 {
+    let difference = (azimuth - other_azimuth).abs() % PI;
+    difference.min(PI - difference)
+}
+
+/// Write the match candidates of each proposal node, by node id, to `candidate_sink`.
+fn write_candidates(
+    candidates: &[MatchCandidates],
+    proposal_nodes: &[TopoNode],
+    ground_truth_nodes: &GroundTruthNodes,
+    candidate_sink: &mut dyn CandidateSink,
+) -> Result<()> {
+    for (proposal_node, proposal_candidates) in proposal_nodes.iter().zip(candidates) {
+        for (squared_distance, gt_idx) in proposal_candidates {
+            let gt_node = ground_truth_nodes.node(*gt_idx);
+            candidate_sink.write(&CandidatePair {
+                proposal_node_id: proposal_node.id,
+                gt_node_id: gt_node.id,
+                distance: squared_distance.sqrt(),
+                azimuth_diff: azimuth_difference(
+                    proposal_node.road_point.azimuth,
+                    gt_node.road_point.azimuth,
+                ),
+            })?;
+        }
+    }
+    candidate_sink.finish()
+}
+
+/// The ground truth nodes to match, which are only read while matching. Their match state is kept aside, and only
+/// applied once they are materialized by `into_nodes`.
+enum GroundTruthNodes<'a> {
+    InMemory(Vec<TopoNode>),
+    /// Nodes spilled to disk, see `TopoParams::low_memory`.
+    Spilled(&'a SpilledNodes),
+}
+
+impl GroundTruthNodes<'_> {
+    fn len(&self) -> usize {
+        match self {
+            GroundTruthNodes::InMemory(nodes) => nodes.len(),
+            GroundTruthNodes::Spilled(spilled_nodes) => spilled_nodes.len(),
+        }
+    }
+
+    fn id(&self, idx: usize) -> u64 {
+        match self {
+            GroundTruthNodes::InMemory(nodes) => nodes[idx].id,
+            GroundTruthNodes::Spilled(spilled_nodes) => spilled_nodes.get(idx).id,
+        }
+    }
+
+    /// The node at `idx`, which is read back from disk for spilled nodes.
+    fn node(&self, idx: usize) -> Cow<'_, TopoNode> {
+        match self {
+            GroundTruthNodes::InMemory(nodes) => Cow::Borrowed(&nodes[idx]),
+            GroundTruthNodes::Spilled(spilled_nodes) => {
+                Cow::Owned(TopoNode::from_record(spilled_nodes.get(idx)))
+            }
+        }
+    }
+
+    /// All nodes in memory, in the order of their indices.
+    fn into_nodes(self) -> Vec<TopoNode> {
+        match self {
+            GroundTruthNodes::InMemory(nodes) => nodes,
+            GroundTruthNodes::Spilled(spilled_nodes) => (0..spilled_nodes.len())
+                .into_par_iter()
+                .map(|idx| TopoNode::from_record(spilled_nodes.get(idx)))
+                .collect(),
+        }
+    }
+}
+
+/// Match proposal nodes to ground truth nodes. `ground_truth_kdtree` holds the indices of `ground_truth_nodes`.
+fn match_nodes_tracked(
+    mut proposal_nodes: Vec<TopoNode>,
+    ground_truth_nodes: GroundTruthNodes,
+    ground_truth_kdtree: &kdtree::KdTree<f64, usize, [f64; 2]>,
+    params: &TopoParams,
+    compatibility: &dyn MatchCompatibility,
+    candidate_sink: Option<&mut dyn CandidateSink>,
+    stage_tracker: &mut StageTracker,
+) -> Result<MatchOutcome> {
+    let masked_out = match &params.evaluation_mask {
+        Some(evaluation_mask) => {
+            log::info!("Applying evaluation mask to ground truth points");
+            stage_tracker.start_stage("evaluation_mask")?;
+            Some(evaluation_mask_exclusions(
+                evaluation_mask,
+                &proposal_nodes,
+                &ground_truth_nodes,
+                stage_tracker,
+            )?)
+        }
+        None => None,
+    };
+    // The ground truth node at an index, excluded if it is outside the evaluation mask.
+    let ground_truth_node = |gt_idx: usize| -> Cow<'_, TopoNode> {
+        let mut gt_node = ground_truth_nodes.node(gt_idx);
+        if let Some(masked_out) = &masked_out {
+            if gt_node.excluded != masked_out[gt_idx] {
+                gt_node.to_mut().excluded = masked_out[gt_idx];
+            }
+        }
+        gt_node
+    };
+
+    log::info!(
+        "Matching {} proposal points to {} ground truth points",
+        proposal_nodes.len(),
+        ground_truth_nodes.len()
+    );
+    // Get the squared distances and indices of the GT nodes within range, if there are any within hole radius.
+    // Look up candidates within the largest hole radius first, then filter them by the hole radius of each GT node.
+    let squared_hole_radius = params.max_hole_radius().powi(2);
+    log::info!("Looking up ground truth nodes within hole radius");
+    stage_tracker.start_stage("lookup_within_hole_radius")?;
+    stage_tracker
+        .progress()
+        .begin("lookup_within_hole_radius", proposal_nodes.len() as u64);
+    let candidates_result: Result<Vec<MatchCandidates>> = proposal_nodes
+        .par_iter()
+        .map(|proposal_node| {
+            stage_tracker.check_cancelled()?;
+            let gt_distances_and_indices = ground_truth_kdtree
+                .within(
+                    &<[f64; 2]>::from(proposal_node.road_point.coord),
+                    squared_hole_radius,
+                    &squared_euclidean,
+                )
+                .with_context(|| {
+                    format!(
+                        "Could not get GT nodes near proposal node {} at {:?}",
+                        proposal_node.id, proposal_node.road_point.coord
+                    )
+                })?;
+            let mut candidates = gt_distances_and_indices
+                .into_iter()
+                .map(|(squared_distance, gt_idx)| (squared_distance, *gt_idx))
+                .filter(|(squared_distance, gt_idx)| {
+                    candidate_rejection(
+                        proposal_node,
+                        &ground_truth_node(*gt_idx),
+                        *squared_distance,
+                        params,
+                        compatibility,
+                    )
+                    .is_none()
+                })
+                .collect::<MatchCandidates>();
+            // Break ties of the distance by node id, then by index, so that the matches of coincident GT nodes do
+            // neither depend on the k-d tree traversal nor on the order of the nodes.
+            candidates.sort_by(
+                |(squared_distance, gt_idx), (other_squared_distance, other_gt_idx)| {
+                    squared_distance
+                        .total_cmp(other_squared_distance)
+                        .then(
+                            ground_truth_nodes
+                                .id(*gt_idx)
+                                .cmp(&ground_truth_nodes.id(*other_gt_idx)),
+                        )
+                        .then(gt_idx.cmp(other_gt_idx))
+                },
+            );
+            stage_tracker.progress().advance(1);
+            Ok(candidates)
+        })
+        .collect();
+    stage_tracker.progress().end();
+    let candidates = candidates_result?;
+    if let Some(candidate_sink) = candidate_sink {
+        log::info!("Writing match candidates");
+        stage_tracker.start_stage("write_candidates")?;
+        write_candidates(
+            &candidates,
+            &proposal_nodes,
+            &ground_truth_nodes,
+            candidate_sink,
+        )?;
+    }
+
+    log::info!("Determining matches for proposal nodes");
+    stage_tracker.start_stage("match")?;
+    let node_matches = matching::match_nodes(&candidates, params.matching_mode, stage_tracker)?;
+    let mut gt_matched = vec![false; ground_truth_nodes.len()];
+    for node_match in &node_matches {
+        let proposal_node = proposal_nodes
+            .get_mut(node_match.proposal_idx)
+            .ok_or_else(|| Error::invalid_data("No such proposal node"))?;
+        proposal_node.matched = true;
+        proposal_node.match_distance = Some(node_match.distance);
+
+        let gt_matched = gt_matched
+            .get_mut(node_match.ground_truth_idx)
+            .ok_or_else(|| Error::invalid_data("No such GT node"))?;
+        *gt_matched = true;
+        let gt_node = ground_truth_nodes.node(node_match.ground_truth_idx);
+        proposal_node.matched_node_id = Some(gt_node.id);
+        proposal_node.hole_radius = gt_node.hole_radius;
+    }
+    debug_assert!(
+        unmatched_candidate_pair(&candidates, &proposal_nodes, &gt_matched).is_none(),
+        "Unmatched proposal node has an unmatched GT candidate: {:?}",
+        unmatched_candidate_pair(&candidates, &proposal_nodes, &gt_matched)
+    );
+    drop(candidates);
+
+    let mut ground_truth_nodes = ground_truth_nodes.into_nodes();
+    if let Some(masked_out) = masked_out {
+        for (gt_node, masked_out) in ground_truth_nodes.iter_mut().zip(masked_out) {
+            gt_node.excluded = masked_out;
+        }
+    }
+    for node_match in &node_matches {
+        let gt_node = &mut ground_truth_nodes[node_match.ground_truth_idx];
+        gt_node.matched = true;
+        gt_node.match_distance = Some(node_match.distance);
+        gt_node.matched_node_id = Some(proposal_nodes[node_match.proposal_idx].id);
+    }
+    stage_tracker.timer.finish_stage();
+
+    let true_positive_count = node_matches.len();
+    let f1_score_result = F1ScoreResult::from_counts(
+        true_positive_count,
+        proposal_nodes.len(),
+        ground_truth_nodes
+            .iter()
+            .filter(|node| !node.excluded)
+            .count(),
+    );
+    let unmasked_f1_score_result = params.evaluation_mask.as_ref().map(|_| {
+        F1ScoreResult::from_counts(
+            true_positive_count,
+            proposal_nodes.len(),
+            ground_truth_nodes.len(),
+        )
+    });
+    let class_breakdown = (params.road_class_attribute.is_some()
+        || params.proposal_road_class_attribute.is_some())
+    .then(|| ClassBreakdown::from_nodes(&proposal_nodes, &ground_truth_nodes));
+    Ok(MatchOutcome {
+        f1_score_result,
+        unmasked_f1_score_result,
+        class_breakdown,
+        ground_truth_nodes,
+        proposal_nodes,
+    })
+}
+
+/// Find an unmatched proposal node with an unmatched ground truth node among its candidates, as a pair of their
+/// indices. Both matching modes match as many nodes as possible, so there is none after matching, in particular no
+/// GT node left unmatched next to a coincident duplicate which was matched.
+fn unmatched_candidate_pair(
+    candidates: &[MatchCandidates],
+    proposal_nodes: &[TopoNode],
+    gt_matched: &[bool],
+) -> Option<(usize, usize)> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|(proposal_idx, _)| !proposal_nodes[*proposal_idx].matched)
+        .find_map(|(proposal_idx, proposal_candidates)| {
+            proposal_candidates
+                .iter()
+                .find(|(_, gt_idx)| !gt_matched[*gt_idx])
+                .map(|(_, gt_idx)| (proposal_idx, *gt_idx))
+        })
+}
+
+/// The ground truth nodes sampled for TOPO, with a spatial index to look them up by location. Useful to inspect why
+/// a location is or is not matched.
+///
+/// Only the nodes are serialized, the spatial index is rebuilt when deserializing.
+pub struct GtIndex {
+    nodes: Vec<TopoNode>,
+    /// Index of the nodes by their coordinates, holding their indices in `nodes`.
+    kdtree: kdtree::KdTree<f64, usize, [f64; 2]>,
+}
+
+impl GtIndex {
+    /// Sample the ground truth graph and build its index the same way `calculate_topo` does. The evaluation mask of
+    /// `params` is not applied, as it depends on the proposal.
+    pub fn from_graph<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+        ground_truth_graph: &GeoGraph<E, N, Ty>,
+        params: &TopoParams,
+    ) -> Result<Self> {
+        let params = &params.in_crs_units(ground_truth_graph)?;
+        build_gt_index(sample_graph(
+            ground_truth_graph,
+            params,
+            GraphRole::GroundTruth,
+        )?)
+    }
+
+    fn build(nodes: Vec<TopoNode>, stage_tracker: &mut StageTracker) -> Result<Self> {
+        log::info!("Building ground truth point lookup tree");
+        stage_tracker.start_stage("build_lookup_tree")?;
+        let kdtree = build_kdtree_from_nodes(&nodes, stage_tracker)?;
+        Ok(Self { nodes, kdtree })
+    }
+
+    /// All sampled ground truth nodes.
+    pub fn nodes(&self) -> &[TopoNode] {
+        &self.nodes
+    }
+
+    /// The `k` ground truth nodes nearest to `coord`, with their distances, in order of increasing distance.
+    pub fn nearest(&self, coord: geo::Coord, k: usize) -> Result<Vec<(f64, &TopoNode)>> {
+        let nearest = self
+            .kdtree
+            .nearest(&<[f64; 2]>::from(coord), k, &squared_euclidean)
+            .with_context(|| format!("Could not get GT nodes near {:?}", coord))?;
+        Ok(self.with_distances(nearest))
+    }
+
+    /// The ground truth nodes within `radius` of `coord`, with their distances, in order of increasing distance.
+    pub fn within(&self, coord: geo::Coord, radius: f64) -> Result<Vec<(f64, &TopoNode)>> {
+        let within = self
+            .kdtree
+            .within(&<[f64; 2]>::from(coord), radius.powi(2), &squared_euclidean)
+            .with_context(|| format!("Could not get GT nodes near {:?}", coord))?;
+        Ok(self.with_distances(within))
+    }
+
+    fn with_distances(
+        &self,
+        squared_distances_and_indices: Vec<(f64, &usize)>,
+    ) -> Vec<(f64, &TopoNode)> {
+        squared_distances_and_indices
+            .into_iter()
+            .map(|(squared_distance, idx)| (squared_distance.sqrt(), &self.nodes[*idx]))
+            .collect()
+    }
+}
+
+impl serde::Serialize for GtIndex {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.nodes.serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for GtIndex {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let nodes = Vec::<TopoNode>::deserialize(deserializer)?;
+        build_gt_index(nodes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+struct RoadPoint {
+    coord: geo::Coord,
+    azimuth: f64,
+    /// The heading of the oriented segment the point was sampled on, in radians between -PI and PI, counterclockwise
+    /// from the x axis. Unlike the azimuth, it tells the two directions apart, see `TopoParams::directional_matching`.
+    #[serde(default)]
+    heading: f64,
+    /// Index of the edge the point was sampled on, in the order of the graph's edge geometries.
+    edge_idx: usize,
+}
+
+/// Prefix of propagated edge attributes whose names collide with the attributes computed for TOPO nodes.
+const PROPAGATED_ATTRIBUTE_COLLISION_PREFIX: &str = "edge_";
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct TopoNode {
+    road_point: RoadPoint,
+    /// Index of the node among the nodes of its graph. Unsigned and 64 bit, as runs on large areas with a small
+    /// resampling distance sample more than `i32::MAX` points.
+    id: u64,
+    matched: bool,
+    match_distance: Option<f64>,
+    /// The id of the node of the other graph the node was matched to.
+    #[serde(default)]
+    matched_node_id: Option<u64>,
+    /// The effective hole radius. For ground truth nodes, the radius of their road class, for matched proposal
+    /// nodes the radius of the ground truth node they matched.
+    hole_radius: Option<f64>,
+    /// Whether the node is outside the evaluation mask.
+    excluded: bool,
+    /// Attributes propagated from the edge the node was sampled on.
+    #[serde(
+        serialize_with = "serialize_data",
+        deserialize_with = "deserialize_data"
+    )]
+    edge_attributes: FeatureMap,
+    /// The road class of the edge the node was sampled on, if `TopoParams::road_class_attribute`, or for proposal
+    /// nodes `TopoParams::proposal_road_class_attribute`, is set.
+    #[serde(default)]
+    road_class: Option<String>,
+    /// The number of sampled points the node stands for, more than one for proposal nodes into which coincident
+    /// points of other edges were merged, see `TopoParams::deduplicate_proposal_points`.
+    #[serde(default = "default_node_weight")]
+    weight: usize,
+}
+
+fn default_node_weight() -> usize {
+    1
+}
+
+/// How boolean attributes of exported TOPO nodes are written.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BooleanEncoding {
+    /// "true" or "false".
+    #[default]
+    String,
+    /// 1 or 0.
+    Integer,
+}
+
+impl BooleanEncoding {
+    fn encode(&self, value: bool) -> FieldValue {
+        match self {
+            BooleanEncoding::String => FieldValue::StringValue(value.to_string()),
+            BooleanEncoding::Integer => FieldValue::IntegerValue(value as i32),
+        }
+    }
+}
+
+/// An Integer64 attribute of a node id or count. GDAL's 32 bit integer fields would overflow for large graphs, while
+/// ids and counts are bounded by the number of nodes held in memory, so that they always fit into an i64.
+fn integer64_field_value(value: u64) -> FieldValue {
+    FieldValue::Integer64Value(
+        i64::try_from(value).expect("Node ids and counts are bounded by the nodes in memory"),
+    )
+}
+
+/// The attribute names and types of TOPO nodes converted to features by `TopoNode::to_feature`, e.g. to match the
+/// columns expected by a downstream database. Omitted fields take their default values, which are the names used by
+/// the `From<&TopoNode>` conversion.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct NodeExportSchema {
+    pub id_field: String,
+    pub matched_field: String,
+    /// Only written for matched nodes.
+    pub match_distance_field: String,
+    /// Only written for ground truth nodes with a hole radius.
+    pub hole_radius_field: String,
+    /// Only written for nodes outside the evaluation mask.
+    pub excluded_field: String,
+    /// Only written for nodes which stand for more than one sampled point.
+    pub weight_field: String,
+    pub boolean_encoding: BooleanEncoding,
+}
+
+impl Default for NodeExportSchema {
+    fn default() -> Self {
+        Self {
+            id_field: "id".to_string(),
+            matched_field: "matched".to_string(),
+            match_distance_field: "match_distance".to_string(),
+            hole_radius_field: "hole_radius".to_string(),
+            excluded_field: "excluded".to_string(),
+            weight_field: "weight".to_string(),
+            boolean_encoding: BooleanEncoding::default(),
+        }
+    }
+}
+
+impl NodeExportSchema {
+    /// Fails if a field name is empty or used for more than one attribute.
+    pub fn validate(&self) -> Result<()> {
+        let field_names = [
+            &self.id_field,
+            &self.matched_field,
+            &self.match_distance_field,
+            &self.hole_radius_field,
+            &self.excluded_field,
+            &self.weight_field,
+        ];
+        for (idx, name) in field_names.iter().enumerate() {
+            if name.is_empty() {
+                return Err(Error::ConfigError(
+                    "The field names of the node export schema must not be empty".to_string(),
+                ));
+            }
+            if field_names[..idx].contains(name) {
+                return Err(Error::ConfigError(format!(
+                    "The field name '{}' is used twice in the node export schema",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&TopoNode> for Feature {
+    fn from(node: &TopoNode) -> Self {
+        node.to_feature(&NodeExportSchema::default())
+    }
+}
+
+impl TopoNode {
+    /// Convert the node to a point feature with the attributes named and typed as in `schema`, and the propagated
+    /// edge attributes. Propagated attributes whose names collide with the attributes of the schema are prefixed.
+    pub fn to_feature(&self, schema: &NodeExportSchema) -> Feature {
+        let mut attributes = HashMap::new();
+        attributes.insert(schema.id_field.clone(), integer64_field_value(self.id));
+        attributes.insert(
+            schema.matched_field.clone(),
+            schema.boolean_encoding.encode(self.matched),
+        );
+        if let Some(distance) = self.match_distance {
+            attributes.insert(
+                schema.match_distance_field.clone(),
+                FieldValue::RealValue(distance),
+            );
+        }
+        if let Some(hole_radius) = self.hole_radius {
+            attributes.insert(
+                schema.hole_radius_field.clone(),
+                FieldValue::RealValue(hole_radius),
+            );
+        }
+        if self.excluded {
+            attributes.insert(
+                schema.excluded_field.clone(),
+                schema.boolean_encoding.encode(self.excluded),
+            );
+        }
+        if self.weight > 1 {
+            attributes.insert(
+                schema.weight_field.clone(),
+                integer64_field_value(self.weight as u64),
+            );
+        }
+        for (name, value) in &self.edge_attributes {
+            let name = if attributes.contains_key(name) {
+                format!("{}{}", PROPAGATED_ATTRIBUTE_COLLISION_PREFIX, name)
+            } else {
+                name.clone()
+            };
+            attributes.insert(name, value.clone());
+        }
+        Feature {
+            geometry: geo::Geometry::Point(geo::Point::from(self.road_point.coord)),
+            attributes: Some(attributes),
+            null_fields: Default::default(),
+        }
+    }
+
+    fn new(point: RoadPoint, id: u64) -> Self {
+        TopoNode {
+            road_point: point,
+            id,
+            matched: false,
+            match_distance: None,
+            matched_node_id: None,
+            hole_radius: None,
+            excluded: false,
+            edge_attributes: FeatureMap::new(),
+            road_class: None,
+            weight: default_node_weight(),
+        }
+    }
+
+    /// The record of an unmatched ground truth node to spill to disk. Edge attributes, which only proposal nodes
+    /// carry, are not stored.
+    fn to_record(&self) -> NodeRecord<'_> {
+        NodeRecord {
+            coord: self.road_point.coord,
+            azimuth: self.road_point.azimuth,
+            heading: self.road_point.heading,
+            hole_radius: self.hole_radius,
+            id: self.id,
+            edge_idx: self.road_point.edge_idx,
+            weight: self.weight,
+            road_class: self.road_class.as_deref(),
+            excluded: self.excluded,
+        }
+    }
+
+    fn from_record(record: NodeRecord) -> Self {
+        TopoNode {
+            hole_radius: record.hole_radius,
+            excluded: record.excluded,
+            road_class: record.road_class.map(str::to_string),
+            weight: record.weight,
+            ..Self::new(
+                RoadPoint {
+                    coord: record.coord,
+                    azimuth: record.azimuth,
+                    heading: record.heading,
+                    edge_idx: record.edge_idx,
+                },
+                record.id,
+            )
+        }
+    }
+
+    /// Location of the node.
+    pub fn coord(&self) -> geo::Coord {
+        self.road_point.coord
+    }
+
+    /// Whether the node was matched to a node of the other graph.
+    pub fn matched(&self) -> bool {
+        self.matched
+    }
+
+    /// Whether the node is outside the evaluation mask, and thus not considered for matching.
+    pub fn excluded(&self) -> bool {
+        self.excluded
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Index of the edge the node was sampled on, in the order of `GeoGraph::edges_sorted`.
+    pub fn edge_idx(&self) -> usize {
+        self.road_point.edge_idx
+    }
+
+    /// The distance to the node of the other graph the node was matched to, if it was matched.
+    pub fn match_distance(&self) -> Option<f64> {
+        self.match_distance
+    }
+
+    /// The id of the node of the other graph the node was matched to, if it was matched.
+    pub fn matched_node_id(&self) -> Option<u64> {
+        self.matched_node_id
+    }
+
+    /// The azimuth of the edge at the node in radians, between -PI/2 and PI/2, the same for both directions.
+    pub fn azimuth(&self) -> f64 {
+        self.road_point.azimuth
+    }
+
+    /// The radius within which the node can be matched, set for ground truth nodes and matched proposal nodes.
+    pub fn hole_radius(&self) -> Option<f64> {
+        self.hole_radius
+    }
+
+    /// The number of sampled points the node stands for, e.g. to weight the nodes when scoring.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+}
+
+/// Index the nodes by their coordinates, holding their indices in `topo_nodes`, which may differ from their ids.
+fn build_kdtree_from_nodes(
+    topo_nodes: &[TopoNode],
+    stage_tracker: &StageTracker,
+) -> Result<kdtree::KdTree<f64, usize, [f64; 2]>> {
+    let mut kdtree = kdtree::KdTree::with_capacity(2, topo_nodes.len());
+    for (idx, node) in topo_nodes.iter().enumerate() {
+        stage_tracker.check_cancelled()?;
+        kdtree.add(<[f64; 2]>::from(node.road_point.coord), idx)?;
+    }
+    Ok(kdtree)
+}
+
+/// Whether each ground truth node is outside the evaluation mask, and thus excluded.
+fn evaluation_mask_exclusions(
+    evaluation_mask: &EvaluationMask,
+    proposal_nodes: &[TopoNode],
+    ground_truth_nodes: &GroundTruthNodes,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<bool>> {
+    let masked_out: Vec<bool> = match evaluation_mask {
+        EvaluationMask::ProposalBuffer { distance } => {
+            let proposal_kdtree = build_kdtree_from_nodes(proposal_nodes, stage_tracker)?;
+            let squared_distance = distance.powi(2);
+            (0..ground_truth_nodes.len())
+                .into_par_iter()
+                .map(|gt_idx| -> Result<bool> {
+                    stage_tracker.check_cancelled()?;
+                    let gt_node = ground_truth_nodes.node(gt_idx);
+                    let nearest = proposal_kdtree
+                        .nearest(
+                            &<[f64; 2]>::from(gt_node.road_point.coord),
+                            1,
+                            &squared_euclidean,
+                        )
+                        .with_context(|| {
+                            format!(
+                                "Could not get proposal nodes near GT node {} at {:?}",
+                                gt_node.id, gt_node.road_point.coord
+                            )
+                        })?;
+                    Ok(!matches!(
+                        nearest.first(),
+                        Some((nearest_squared_distance, _))
+                            if *nearest_squared_distance <= squared_distance
+                    ))
+                })
+                .collect::<Result<_>>()?
+        }
+    };
+    log::info!(
+        "Excluded {} of {} ground truth points outside the evaluation mask",
+        masked_out.iter().filter(|masked_out| **masked_out).count(),
+        masked_out.len()
+    );
+    Ok(masked_out)
+}
+
+/// Maximum number of offending edges listed in the error of `ensure_road_points_finite`.
+const MAX_REPORTED_NON_FINITE_EDGES: usize = 10;
+
+/// Ensure that all sampled points have finite coordinates. Non-finite coordinates, e.g. from a broken projection,
+/// would make the nearest neighbor lookups fail or silently return wrong results.
+///
+/// # Arguments
+/// * road_points - The points sampled on `edges`.
+/// * edges - The edge geometries the points were sampled on.
+/// * graph_name - Name of the graph for the error message, e.g. "proposal".
+fn ensure_road_points_finite(
+    road_points: &[RoadPoint],
+    edges: &[geo::LineString],
+    graph_name: &str,
+) -> Result<()> {
+    let mut offending_edge_indices: Vec<usize> = road_points
+        .iter()
+        .filter(|point| !(point.coord.x.is_finite() && point.coord.y.is_finite()))
+        .map(|point| point.edge_idx)
+        .collect();
+    if offending_edge_indices.is_empty() {
+        return Ok(());
+    }
+    offending_edge_indices.dedup();
+    let offending_edges: Vec<String> = offending_edge_indices
+        .iter()
+        .take(MAX_REPORTED_NON_FINITE_EDGES)
+        .map(|edge_idx| {
+            format!(
+                "edge {} starting at {:?}",
+                edge_idx,
+                edges[*edge_idx].coords().next()
+            )
+        })
+        .collect();
+    Err(Error::invalid_data(format!(
+        "{} {} edges have points with non-finite coordinates, check the input geometries and their CRS. \
+        Offending edges: {}{}",
+        offending_edge_indices.len(),
+        graph_name,
+        offending_edges.join(", "),
+        if offending_edge_indices.len() > MAX_REPORTED_NON_FINITE_EDGES {
+            ", ..."
+        } else {
+            ""
+        }
+    )))
+}
+
+/// Deduplicate RoadPoints by coordinate, and create TopoNodes from them.
+/// The created TopoNodes will have the same id as the index of the first RoadPoint with that coordinate.
+fn road_points_to_topo_nodes(
+    road_points: Vec<RoadPoint>,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<TopoNode>> {
+    let mut node_indexer = NodeIndexer::new();
+
+    // Coincident points are rare, so there are about as many nodes as points.
+    let mut nodes = Vec::with_capacity(road_points.len());
+
+    for point in road_points.into_iter() {
+        stage_tracker.check_cancelled()?;
+        let node_idx = node_indexer.get_index_for_coordinate(&point.coord);
+        if node_idx as usize == nodes.len() {
+            nodes.push(TopoNode::new(point, node_idx));
+        }
+    }
+    Ok(nodes)
+}
+
+fn sample_points_on_lines(
+    lines: &[geo::LineString],
+    resampling_distance: f64,
+    phase_offset: f64,
+    include_original_vertices: bool,
+    stage: &str,
+    stage_tracker: &StageTracker,
+) -> Result<Vec<RoadPoint>> {
+    stage_tracker.progress().begin(stage, lines.len() as u64);
+    let points_per_line: Result<Vec<Vec<RoadPoint>>> = lines
+        .par_iter()
+        .enumerate()
+        .map(|(edge_idx, linestr)| {
+            stage_tracker.check_cancelled()?;
+            let mut points = sample_points_on_line(
+                linestr,
+                resampling_distance,
+                phase_offset,
+                include_original_vertices,
+            );
+            for point in points.iter_mut() {
+                point.edge_idx = edge_idx;
+            }
+            stage_tracker.progress().advance(1);
+            Ok(points)
+        })
+        .collect();
+    stage_tracker.progress().end();
+    Ok(points_per_line?.into_iter().flatten().collect())
+}
+
+/// The coordinates of the points sampled on a linestring every `resampling_distance`, like the TOPO nodes of one
+/// edge without its interior vertices, see `sample_points_on_line`.
+pub fn sample_line_coords(linestr: &geo::LineString, resampling_distance: f64) -> Vec<geo::Coord> {
+    sample_points_on_line(linestr, resampling_distance, 0.0, false)
+        .into_iter()
+        .map(|point| point.coord)
+        .collect()
+}
+
+/// Sample points on a linestring every resampling_distance, starting from the first coordinate of the linestring.
+/// The interpolated points are shifted along the linestring by `phase_offset` times the resampling distance, i.e. the
+/// first one is at `(1 + phase_offset) * resampling_distance` from the start.
+/// If include_original_vertices is set, the interior vertices are sampled too, with the azimuth of the line starting
+/// at them. The edge index of the returned points is zero, the caller is responsible for setting it.
+///
+/// The sampled points are interpolated on the line they fall on from their offset to its start, so that the rounding
+/// errors do not accumulate over the lines. Lines shorter than `VERTEX_COINCIDENCE_EPSILON` are skipped.
+fn sample_points_on_line(
+    linestr: &geo::LineString,
+    resampling_distance: f64,
+    phase_offset: f64,
+    include_original_vertices: bool,
+) -> Vec<RoadPoint> {
+    if 2 > linestr.coords_count() {
+        return vec![];
+    }
+    if resampling_distance <= 0.0 {
+        return vec![];
+    }
+
+    let first_coord = *linestr.coords().next().unwrap();
+    let last_coord = *linestr.coords().last().unwrap();
+    let lines: Vec<geo::Line> = linestr
+        .lines()
+        .filter(|line| line.euclidean_length() > VERTEX_COINCIDENCE_EPSILON)
+        .collect();
+    if lines.is_empty() {
+        // The linestring has no length, only its endpoints are sampled.
+        let first_line = linestr.lines().next().unwrap();
+        let azimuth = get_normalized_line_azimuth(&first_line);
+        let heading = get_line_heading(&first_line);
+        return [first_coord, last_coord]
+            .into_iter()
+            .map(|coord| RoadPoint {
+                coord,
+                azimuth,
+                heading,
+                edge_idx: 0,
+            })
+            .collect();
+    }
+
+    let mut output_points = vec![RoadPoint {
+        coord: first_coord,
+        azimuth: get_normalized_line_azimuth(&lines[0]),
+        heading: get_line_heading(&lines[0]),
+        edge_idx: 0,
+    }];
+
+    // Distances along the linestring from the last output point, which may be an original vertex, and from the last
+    // point sampled every resampling_distance to the start of the current line.
+    let mut dist_since_output = 0.0;
+    let mut dist_since_sample = -phase_offset * resampling_distance;
+    for (line_idx, line) in lines.iter().enumerate() {
+        let line_len = line.euclidean_length();
+        let azimuth = get_normalized_line_azimuth(line);
+        let heading = get_line_heading(line);
+        if include_original_vertices
+            && line_idx > 0
+            && dist_since_output > VERTEX_COINCIDENCE_EPSILON
+        {
+            output_points.push(RoadPoint {
+                coord: line.start,
+                azimuth,
+                heading,
+                edge_idx: 0,
+            });
+            dist_since_output = 0.0;
+        }
+        // Points closer to the end of the linestring than the epsilon would duplicate its last coordinate.
+        let max_offset = if line_idx == lines.len() - 1 {
+            line_len - VERTEX_COINCIDENCE_EPSILON
+        } else {
+            line_len
+        };
+        let first_offset = resampling_distance - dist_since_sample;
+        let mut offset = first_offset;
+        let mut sample_count = 0;
+        while offset < max_offset {
+            dist_since_sample = -offset;
+            if dist_since_output + offset >= VERTEX_COINCIDENCE_EPSILON {
+                // Otherwise the point coincides with the original vertex at the start of the line, which is already
+                // sampled.
+                output_points.push(RoadPoint {
+                    coord: line.start + line.delta() * (offset / line_len),
+                    azimuth,
+                    heading,
+                    edge_idx: 0,
+                });
+                dist_since_output = -offset;
+            }
+            sample_count += 1;
+            offset = first_offset + sample_count as f64 * resampling_distance;
+        }
+        dist_since_output += line_len;
+        dist_since_sample += line_len;
+    }
+    output_points.push(RoadPoint {
+        coord: last_coord,
+        azimuth: get_normalized_line_azimuth(lines.last().unwrap()),
+        heading: get_line_heading(lines.last().unwrap()),
+        edge_idx: 0,
+    });
+    output_points
+}
+
+/// Distance along a linestring below which a sampled point is considered to coincide with an original vertex.
+const VERTEX_COINCIDENCE_EPSILON: f64 = 1e-9;
+
+/// The direction of a line from its start to its end, in radians between -PI and PI, counterclockwise from the x axis.
+/// Unlike `get_normalized_line_azimuth`, opposite directions are not folded together.
+fn get_line_heading(line: &geo::Line) -> f64 {
+    let delta = line.delta();
+    delta.y.atan2(delta.x)
+}
+
+fn get_normalized_line_azimuth(line: &geo::Line) -> f64 {
+    let mut delta = line.delta();
+
+    // Normalize the delta so the X component is always positive.
+    if delta.x < 0.0 {
+        delta = -delta;
+    }
+    let azimuth = delta.y.atan2(delta.x);
+    if azimuth == -FRAC_PI_2 {
+        // Treat a vertical upwards line the same as a vertical downwards line.
+        return FRAC_PI_2;
+    }
+    azimuth
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate approx;
+    use approx::assert_abs_diff_eq;
+    use geo::EuclideanLength;
+    use proptest::{collection, prelude::*};
+    use rstest::{fixture, rstest};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        f64::consts::{FRAC_PI_2, FRAC_PI_4, PI},
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+    use testdir::testdir;
+
+    #[cfg(feature = "gdal-io")]
+    use super::BooleanEncoding;
+    use crate::{
+        error::Error,
+        geofile::feature::{FeatureMap, FieldValue},
+        geograph::{
+            primitives::GeoGraph,
+            utils::{
+                build_geograph_from_lines, build_geograph_from_lines_in_crs,
+                build_geograph_from_lines_with_data_in_crs,
+            },
+        },
+        topo::{
+            cancellation::CancellationToken,
+            matching::{CandidatePair, CsvCandidateWriter, MatchingMode},
+        },
+        util::progress::ProgressSink,
+    };
+    #[cfg(feature = "gdal-io")]
+    use crate::{
+        geofile::feature::Feature,
+        geofile::gdal_geofile::{
+            read_features_from_geofile, write_features_to_geofile, GdalDriverType, WriteOptions,
+        },
+        geograph::utils::project_geograph,
+    };
+
+    use super::{
+        azimuth_difference, build_gt_index, calculate_topo, calculate_topo_from_lines,
+        calculate_topo_with_candidate_sink, ensure_road_points_finite, get_normalized_line_azimuth,
+        heading_difference, match_nodes, match_nodes_with_compatibility, sample_graph,
+        sample_points_on_line, ClassScore, DistanceUnits, EvaluationMask, F1ScoreResult, GraphRole,
+        GtIndex, MatchCompatibility, NodeExportSchema, RoadPoint, TopoEvaluator, TopoNode,
+        TopoParams, UNCLASSIFIED_ROAD_CLASS, VERTEX_COINCIDENCE_EPSILON,
+    };
+
+    #[rstest]
+    #[case((0.0, 0.0), (1.0, 0.0), 0.0)]
+    #[case((0.0, 0.0), (-1.0, 0.0), 0.0)]
+    #[case((0.0, 0.0), (0.0, 1.0), FRAC_PI_2)]
+    #[case((0.0, 0.0), (0.0, -1.0), FRAC_PI_2)]
+    #[case((0.0, 0.0), (1.0, 1.0), FRAC_PI_4)]
+    #[case((0.0, 0.0), (-1.0, -1.0), FRAC_PI_4)]
+    #[case((0.0, 0.0), (1.0, -1.0), -FRAC_PI_4)]
+    fn test_get_normalized_line_azimuth(
+        #[case] line_start: (f64, f64),
+        #[case] line_end: (f64, f64),
+        #[case] expected_aximuth: f64,
+    ) {
+        let line = geo::Line::new(geo::Coord::from(line_start), geo::Coord::from(line_end));
+        let azimuth = get_normalized_line_azimuth(&line);
+        assert_abs_diff_eq!(expected_aximuth, azimuth);
+    }
+
+    #[rstest]
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 5.0, false, vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)])] // Split exactly in two.
+    #[case(vec![(0.0, 0.0), (9.0, 0.0)], 4.5, false, vec![(0.0, 0.0), (4.5, 0.0), (9.0, 0.0)])] // Split exactly in two, float.
+    #[case(vec![(0.0, 0.0), (9.0, 0.0)], 3.0, false, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Split exactly in three.
+    #[case(vec![(0.0, 0.0), (12.0, 0.0)], 5.0, false, vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0), (12.0, 0.0)])] // Split in three with leeway.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 10.0, false, vec![(0.0, 0.0), (10.0, 0.0)])] // Split by length.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 11.0, false, vec![(0.0, 0.0), (10.0, 0.0)])] // Split by more than length.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 0.0, false, vec![])] // Split by zero.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], -1.0, false, vec![])] // Split by negative.
+    #[case(vec![(0.0, 0.0), (5.0, 0.0), (9.0, 0.0)], 3.0, false, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Split linestr with multiple vertices.
+    #[case(vec![(0.0, 0.0), (4.5, 0.0), (4.5, 4.5)], 3.0, false, vec![(0.0, 0.0), (3.0, 0.0), (4.5, 1.5), (4.5, 4.5)])] // Split curving linestr with multiple vertices.
+    #[case(vec![(0.0, 0.0), (10.0, 0.0)], 5.0, true, vec![(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)])] // Original vertices of a single line.
+    #[case(vec![(0.0, 0.0), (5.0, 0.0), (9.0, 0.0)], 3.0, true, vec![(0.0, 0.0), (3.0, 0.0), (5.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Original vertices of linestr with multiple vertices.
+    #[case(vec![(0.0, 0.0), (4.5, 0.0), (4.5, 4.5)], 3.0, true, vec![(0.0, 0.0), (3.0, 0.0), (4.5, 0.0), (4.5, 1.5), (4.5, 4.5)])] // Original vertices of curving linestr.
+    #[case(vec![(0.0, 0.0), (6.0, 0.0), (9.0, 0.0)], 3.0, true, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Original vertex coincides with a sampled point.
+    #[case(vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (9.0, 0.0)], 5.0, true, vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (5.0, 0.0), (9.0, 0.0)])] // Original vertices closer than the resampling distance.
+    #[case(vec![(0.0, 0.0), (5.0, 0.0), (5.0, 0.0)], 3.0, true, vec![(0.0, 0.0), (3.0, 0.0), (5.0, 0.0)])] // Repeated last vertex.
+    #[case(vec![(0.0, 0.0), (0.0, 0.0), (9.0, 0.0)], 3.0, true, vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0), (9.0, 0.0)])] // Repeated first vertex.
+    fn test_sample_points_on_line(
+        #[case] input_linestr: Vec<(f64, f64)>,
+        #[case] resampling_distance: f64,
+        #[case] include_original_vertices: bool,
+        #[case] expected_coordinates: Vec<(f64, f64)>,
+    ) {
+        let input_linestr: geo::LineString = input_linestr.into();
+        let result = sample_points_on_line(
+            &input_linestr,
+            resampling_distance,
+            0.0,
+            include_original_vertices,
+        );
+
+        let expected_coords_linestr: geo::LineString = expected_coordinates.into();
+        let actual_coords_linestr: geo::LineString =
+            result.iter().map(|point| point.coord).collect();
+        assert_abs_diff_eq!(
+            expected_coords_linestr,
+            actual_coords_linestr,
+            epsilon = 1e-6
+        );
+    }
+
+    /// Random linestrings of 2 to 50 vertices at scales from centimeters to tens of kilometers, with a resampling
+    /// distance relative to the scale. Some vertices are repeated, like in real data.
+    fn linestring_and_resampling_distance() -> impl Strategy<Value = (geo::LineString, f64)> {
+        (
+            -2.0f64..4.0,
+            collection::vec(
+                (0.0f64..1.0, 0.0f64..1.0, proptest::bool::weighted(0.1)),
+                2..=50,
+            ),
+            0.01f64..2.0,
+        )
+            .prop_map(|(scale_exponent, vertices, resampling_fraction)| {
+                let scale = 10f64.powf(scale_exponent);
+                let linestr: geo::LineString = vertices
+                    .into_iter()
+                    .flat_map(|(x, y, repeated)| {
+                        let repeat_count = if repeated { 2 } else { 1 };
+                        std::iter::repeat_n((x * scale, y * scale), repeat_count)
+                    })
+                    .collect::<Vec<_>>()
+                    .into();
+                (linestr, resampling_fraction * scale)
+            })
+    }
+
+    /// Check that the points sampled on the linestring start and end at its endpoints, lie on it in order and at most
+    /// resampling_distance apart along it, and have normalized azimuths.
+    fn assert_sampling_invariants(
+        linestr: &geo::LineString,
+        resampling_distance: f64,
+        include_original_vertices: bool,
+    ) {
+        let points =
+            sample_points_on_line(linestr, resampling_distance, 0.0, include_original_vertices);
+        let lines: Vec<geo::Line> = linestr.lines().collect();
+        let mut line_start_dists = vec![0.0];
+        for line in lines.iter() {
+            line_start_dists.push(line_start_dists.last().unwrap() + line.euclidean_length());
+        }
+        // Tolerance of the distances along the linestring for the rounding errors of summing the line lengths.
+        let dist_tolerance = VERTEX_COINCIDENCE_EPSILON + 1e-12 * line_start_dists.last().unwrap();
+
+        assert_eq!(linestr.0[0], points[0].coord);
+        assert_eq!(*linestr.0.last().unwrap(), points.last().unwrap().coord);
+        let mut line_idx = 0;
+        let mut prev_dist: Option<f64> = None;
+        for point in points.iter() {
+            assert!(
+                (-FRAC_PI_2..=FRAC_PI_2).contains(&point.azimuth),
+                "Azimuth {} of {:?} is not normalized",
+                point.azimuth,
+                point.coord
+            );
+            // The distance along the linestring of the point, on the first line from the line of the previous point
+            // where it is after the previous point.
+            let (point_line_idx, dist) = (line_idx..lines.len())
+                .find_map(|idx| {
+                    let line = lines[idx];
+                    let delta = line.delta();
+                    let offset = point.coord - line.start;
+                    let fraction = ((offset.x * delta.x + offset.y * delta.y)
+                        / (delta.x * delta.x + delta.y * delta.y))
+                        .clamp(0.0, 1.0);
+                    let closest = line.start + delta * fraction;
+                    let dist_to_line = (point.coord.x - closest.x).hypot(point.coord.y - closest.y);
+                    let dist = line_start_dists[idx] + fraction * line.euclidean_length();
+                    (dist_to_line <= 1e-9 && prev_dist.is_none_or(|prev_dist| dist > prev_dist))
+                        .then_some((idx, dist))
+                })
+                .unwrap_or_else(|| {
+                    panic!(
+                        "{:?} is not on the linestring after the previous point",
+                        point.coord
+                    )
+                });
+            if let Some(prev_dist) = prev_dist {
+                assert!(
+                    dist - prev_dist <= resampling_distance + dist_tolerance,
+                    "{:?} is {} after the previous point along the linestring",
+                    point.coord,
+                    dist - prev_dist
+                );
+            }
+            line_idx = point_line_idx;
+            prev_dist = Some(dist);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_sample_points_on_line_invariants(
+            (linestr, resampling_distance) in linestring_and_resampling_distance(),
+            include_original_vertices in any::<bool>(),
+        ) {
+            assert_sampling_invariants(&linestr, resampling_distance, include_original_vertices);
+        }
+    }
+
+    #[test]
+    fn test_ensure_road_points_finite() {
+        let edges: Vec<geo::LineString> = vec![
+            vec![(0.0, 0.0), (10.0, 0.0)].into(),
+            vec![(0.0, 10.0), (10.0, 10.0)].into(),
+        ];
+        let mut road_points: Vec<RoadPoint> = edges
+            .iter()
+            .enumerate()
+            .flat_map(|(edge_idx, edge)| {
+                let mut points = sample_points_on_line(edge, 5.0, 0.0, false);
+                for point in points.iter_mut() {
+                    point.edge_idx = edge_idx;
+                }
+                points
+            })
+            .collect();
+        assert!(ensure_road_points_finite(&road_points, &edges, "test").is_ok());
+
+        // Inject a NaN coordinate into a point sampled on the second edge.
+        road_points.last_mut().unwrap().coord.x = f64::NAN;
+        let error = ensure_road_points_finite(&road_points, &edges, "test")
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::InvalidData { .. }));
+        let error = error.to_string();
+        assert!(error.contains("1 test edges"), "{}", error);
+        assert!(error.contains("edge 1 starting at"), "{}", error);
+    }
+
+    #[rstest]
+    #[case(0.80, 0.80, 0.01, true)]
+    #[case(0.85, 0.80, 0.01, true)] // Improvement.
+    #[case(0.79, 0.80, 0.01, true)] // Exactly at the threshold.
+    #[case(0.7899, 0.80, 0.01, false)]
+    #[case(0.80, 0.80, 0.0, true)]
+    #[case(f64::NAN, 0.80, 0.01, false)]
+    fn test_f1_score_meets_threshold(
+        #[case] candidate_f1_score: f64,
+        #[case] baseline_f1_score: f64,
+        #[case] max_regression: f64,
+        #[case] expected: bool,
+    ) {
+        let result = |f1_score| F1ScoreResult {
+            precision: 0.5,
+            recall: 0.5,
+            f1_score,
+        };
+        assert_eq!(
+            expected,
+            result(candidate_f1_score).meets_threshold(&result(baseline_f1_score), max_regression)
+        );
+    }
+
+    #[rstest]
+    #[case(r#"{"precision": 1.0, "recall": 0.5, "f1_score": 0.6}"#, true)]
+    #[case(
+        r#"{"f1_score_result": {"precision": 1.0, "recall": 0.5, "f1_score": 0.6}, "stages": []}"#,
+        true
+    )]
+    #[case(r#"{"precision": 1.0, "recall": 0.5}"#, false)] // Missing field.
+    #[case(r#"{"f1_score_result": {"precision": 1.0, "f1_score": 0.6}}"#, false)] // Missing field.
+    fn test_f1_score_result_from_json(#[case] json: &str, #[case] expected_ok: bool) {
+        let result = F1ScoreResult::from_json(json);
+        assert_eq!(expected_ok, result.is_ok());
+        if let Ok(result) = result {
+            assert_eq!(0.6, result.f1_score());
+            let delta = result.diff(&F1ScoreResult {
+                precision: 0.5,
+                recall: 0.5,
+                f1_score: 0.5,
+            });
+            assert_abs_diff_eq!(0.5, delta.precision);
+            assert_abs_diff_eq!(0.0, delta.recall);
+            assert_abs_diff_eq!(0.1, delta.f1_score, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_f1_score_result_ordering() {
+        let result = |precision, recall, f1_score| F1ScoreResult {
+            precision,
+            recall,
+            f1_score,
+        };
+        assert!(result(0.5, 0.5, 0.5) < result(0.6, 0.6, 0.6));
+        assert!(result(0.5, 0.5, 0.5) <= result(0.5, 0.5, 0.5));
+        assert_eq!(
+            None,
+            result(1.0, 0.5, 0.5).partial_cmp(&result(0.5, 1.0, 0.5))
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_normalized_edge_orientation(default_topo_params: TopoParams) {
+        let mut ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (10.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_line: geo::LineString = vec![(0.0, 1.0), (12.0, 1.0)].into();
+        let mut reversed_proposal_line = proposal_line.clone();
+        reversed_proposal_line.0.reverse();
+
+        let mut proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            GeoGraph::new(ground_truth_graph.crs.clone());
+        proposal_graph.insert_edge(0, 1, proposal_line).unwrap();
+        // Same nodes, but the edge geometry points from the higher-indexed node to the lower-indexed one.
+        let mut reversed_proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            GeoGraph::new(ground_truth_graph.crs.clone());
+        reversed_proposal_graph
+            .insert_edge(1, 0, reversed_proposal_line)
+            .unwrap();
+
+        let params = TopoParams {
+            resampling_distance: 5.0,
+            hole_radius: 2.0,
+            ..default_topo_params
+        };
+        let reversed_result = calculate_topo(
+            &reversed_proposal_graph,
+            &ground_truth_graph,
+            &params,
+            None,
+            None,
+        )
+        .unwrap();
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        // Without normalization, the sampled points differ.
+        assert_ne!(result.f1_score_result, reversed_result.f1_score_result);
+
+        ground_truth_graph.normalize_edge_orientation();
+        proposal_graph.normalize_edge_orientation();
+        reversed_proposal_graph.normalize_edge_orientation();
+        let reversed_result = calculate_topo(
+            &reversed_proposal_graph,
+            &ground_truth_graph,
+            &params,
+            None,
+            None,
+        )
+        .unwrap();
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        assert_eq!(result.f1_score_result, reversed_result.f1_score_result);
+    }
+
+    /// UTM zone 54N, a projected CRS in meters.
+    fn projected_crs() -> crate::crs::Crs {
+        crate::crs::Crs::Epsg(32654)
+    }
+
+    #[fixture]
+    fn default_topo_params() -> TopoParams {
+        TopoParams {
+            resampling_distance: 11.0,
+            hole_radius: 6.0,
+            matching_mode: MatchingMode::Greedy,
+            road_class_attribute: None,
+            proposal_road_class_attribute: None,
+            hole_radius_by_class: None,
+            evaluation_mask: None,
+            units: DistanceUnits::CrsUnits,
+            anisotropy_warning_latitude: 60.0,
+            propagate_edge_attributes: Vec::new(),
+            include_original_vertices: false,
+            max_network_detour_factor: None,
+            deduplicate_proposal_points: false,
+            proposal_dedup_radius: None,
+            exclude_near_junction_distance: None,
+            collapse_reciprocal_edges: false,
+            sampling_phase_offsets: None,
+            directional_matching: false,
+            low_memory: false,
+        }
+    }
+
+    #[rstest]
+    #[case(vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)], vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)], F1ScoreResult {
+        f1_score: 1.0,
+        precision: 1.0,
+        recall: 1.0
+    })] // Perfectly matching lines.
+    #[case(vec![(0.0, 0.0), (6.0, 0.0)], vec![(0.0, 0.0), (6.0, 0.0), (12.0, 0.0)], F1ScoreResult {
+        f1_score: 4.0 / 5.0,
+        precision: 1.0,
+        recall: 2.0 / 3.0
+    })] // Two points match, one GT point is unmatched.
+    fn test_calculate_topo_two_lines(
+        #[case] proposal_line_coords: Vec<(f64, f64)>,
+        #[case] ground_truth_line_coods: Vec<(f64, f64)>,
+        #[case] expected_result: F1ScoreResult,
+        default_topo_params: TopoParams,
+    ) {
+        let proposal_line: geo::LineString = proposal_line_coords.into();
+        let ground_truth_line: geo::LineString = ground_truth_line_coods.into();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(vec![proposal_line], projected_crs()).unwrap();
+        let ground_truth_graph =
+            build_geograph_from_lines_in_crs(vec![ground_truth_line], projected_crs()).unwrap();
+
+        let result = calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &default_topo_params,
+            None,
+            None,
+        );
+        assert!(result.is_ok());
+        assert_eq!(expected_result, result.unwrap().f1_score_result)
+    }
+
+    #[rstest]
+    #[case(vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)], vec![(0.0, 0.0), (5.0, 0.0), (11.0, 0.0)])]
+    #[case(vec![(0.0, 0.0), (6.0, 0.0)], vec![(0.0, 0.0), (6.0, 0.0), (12.0, 0.0)])]
+    fn test_calculate_topo_from_lines_matches_graphs(
+        #[case] proposal_line_coords: Vec<(f64, f64)>,
+        #[case] ground_truth_line_coods: Vec<(f64, f64)>,
+        default_topo_params: TopoParams,
+    ) {
+        let proposal_lines: Vec<geo::LineString> = vec![proposal_line_coords.into()];
+        let ground_truth_lines: Vec<geo::LineString> = vec![ground_truth_line_coods.into()];
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(proposal_lines.clone(), projected_crs()).unwrap();
+        let ground_truth_graph =
+            build_geograph_from_lines_in_crs(ground_truth_lines.clone(), projected_crs()).unwrap();
+
+        let graph_result = calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &default_topo_params,
+            None,
+            None,
+        )
+        .unwrap();
+        let line_result =
+            calculate_topo_from_lines(&proposal_lines, &ground_truth_lines, &default_topo_params)
+                .unwrap();
+
+        assert_eq!(graph_result.f1_score_result, line_result.f1_score_result);
+        let to_json = |nodes: &Vec<TopoNode>| serde_json::to_string(nodes).unwrap();
+        assert_eq!(
+            to_json(&graph_result.proposal_nodes),
+            to_json(&line_result.proposal_nodes)
+        );
+        assert_eq!(
+            to_json(&graph_result.ground_truth_nodes),
+            to_json(&line_result.ground_truth_nodes)
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_sampling_phase_offsets(default_topo_params: TopoParams) {
+        let lines: Vec<geo::LineString> = vec![vec![(0.0, 0.0), (100.0, 0.0)].into()];
+        let params = TopoParams {
+            resampling_distance: 10.0,
+            hole_radius: 2.0,
+            sampling_phase_offsets: Some(vec![0.0, 0.5]),
+            ..default_topo_params
+        };
+
+        let result = calculate_topo_from_lines(&lines, &lines, &params).unwrap();
+
+        // Without offset, the proposal points coincide with the ground truth points.
+        assert_eq!(1.0, result.f1_score_result.f1_score());
+        let phase_offset_scores = result.phase_offset_scores.unwrap();
+        assert_eq!(
+            vec![0.0, 0.5],
+            phase_offset_scores
+                .scores
+                .iter()
+                .map(|score| score.phase_offset)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            1.0,
+            phase_offset_scores.scores[0].f1_score_result.f1_score()
+        );
+        // Shifted by half the resampling distance, the proposal points at 15, 25, ..., 95 are 5 from the ground truth
+        // points, and only the 2 endpoints of the 11 points match.
+        assert_abs_diff_eq!(
+            2.0 / 11.0,
+            phase_offset_scores.scores[1].f1_score_result.f1_score(),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            (1.0 + 2.0 / 11.0) / 2.0,
+            phase_offset_scores.mean_f1_score,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(2.0 / 11.0, phase_offset_scores.min_f1_score, epsilon = 1e-9);
+        assert_eq!(1.0, phase_offset_scores.max_f1_score);
+    }
+
+    #[rstest]
+    fn test_calculate_topo_exclude_near_junction_distance(default_topo_params: TopoParams) {
+        // The ground truth models the crossroads with a junction node, the proposal with two lines crossing 1 apart
+        // from it, without a node.
+        let ground_truth_lines: Vec<geo::LineString> = vec![
+            vec![(0.0, 0.0), (40.0, 0.0)].into(),
+            vec![(0.0, 0.0), (-40.0, 0.0)].into(),
+            vec![(0.0, 0.0), (0.0, 40.0)].into(),
+            vec![(0.0, 0.0), (0.0, -40.0)].into(),
+        ];
+        let proposal_lines: Vec<geo::LineString> = vec![
+            vec![(-40.0, 0.0), (40.0, 0.0)].into(),
+            vec![(1.0, -40.0), (1.0, 40.0)].into(),
+        ];
+        let params = TopoParams {
+            resampling_distance: 10.0,
+            hole_radius: 3.0,
+            ..default_topo_params
+        };
+        let without_exclusion =
+            calculate_topo_from_lines(&proposal_lines, &ground_truth_lines, &params).unwrap();
+        let params = TopoParams {
+            exclude_near_junction_distance: Some(5.0),
+            ..params
+        };
+
+        let result =
+            calculate_topo_from_lines(&proposal_lines, &ground_truth_lines, &params).unwrap();
+
+        // Both proposal points at the crossing compete for the one ground truth point at the junction.
+        assert_eq!(18, without_exclusion.proposal_nodes.len());
+        assert_eq!(17, without_exclusion.ground_truth_nodes.len());
+        assert!(without_exclusion.f1_score_result.precision() < 1.0);
+        // They are dropped from both sets, along with the junction point.
+        assert_eq!(16, result.proposal_nodes.len());
+        assert_eq!(16, result.ground_truth_nodes.len());
+        for node in result
+            .proposal_nodes
+            .iter()
+            .chain(&result.ground_truth_nodes)
+        {
+            assert!(
+                node.coord().x.hypot(node.coord().y) > 5.0,
+                "{:?}",
+                node.coord()
+            );
+        }
+        assert_eq!(1.0, result.f1_score_result.f1_score());
+        for (idx, node) in result.proposal_nodes.iter().enumerate() {
+            assert_eq!(idx as u64, node.id());
+        }
+    }
+
+    #[rstest]
+    fn test_calculate_topo_hole_radius_by_class(default_topo_params: TopoParams) {
+        let road_class_data = |road_class: &str| -> FeatureMap {
+            HashMap::from([(
+                "highway".to_string(),
+                FieldValue::StringValue(road_class.to_string()),
+            )])
+        };
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (10.0, 0.0)].into(),
+                    vec![(0.0, 100.0), (10.0, 100.0)].into(),
+                ],
+                vec![road_class_data("primary"), road_class_data("alley")],
+                projected_crs(),
+            )
+            .unwrap();
+        // Proposal lines 5 meters away from each ground truth line.
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 5.0), (10.0, 5.0)].into(),
+                    vec![(0.0, 105.0), (10.0, 105.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            road_class_attribute: Some("highway".to_string()),
+            hole_radius_by_class: Some(HashMap::from([
+                ("primary".to_string(), 10.0),
+                ("alley".to_string(), 3.0),
+            ])),
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        assert_eq!(
+            F1ScoreResult {
+                precision: 0.5,
+                recall: 0.5,
+                f1_score: 0.5
+            },
+            result.f1_score_result
+        );
+        for gt_node in &result.ground_truth_nodes {
+            let is_primary = gt_node.road_point.coord.y < 50.0;
+            assert_eq!(is_primary, gt_node.matched);
+            let expected_hole_radius = if is_primary { 10.0 } else { 3.0 };
+            assert_eq!(Some(expected_hole_radius), gt_node.hole_radius);
+        }
+    }
+
+    /// Build a graph of horizontal parallel lines starting at x = 0, 10 apart.
+    fn build_parallel_lines_graph(
+        line_count: usize,
+        line_length: f64,
+        offset: f64,
+    ) -> GeoGraph<(), (), petgraph::Undirected> {
+        let lines: Vec<geo::LineString> = (0..line_count)
+            .map(|line_idx| {
+                let y = line_idx as f64 * 10.0 + offset;
+                vec![(0.0, y), (line_length, y)].into()
+            })
+            .collect();
+        build_geograph_from_lines_in_crs(lines, projected_crs()).unwrap()
+    }
+
+    /// Build a graph of many long parallel lines, which takes a while to evaluate.
+    fn build_large_graph(offset: f64) -> GeoGraph<(), (), petgraph::Undirected> {
+        build_parallel_lines_graph(200, 10000.0, offset)
+    }
+
+    #[rstest]
+    fn test_topo_params_in_crs_units(default_topo_params: TopoParams) {
+        // A geographic graph around latitude 60, where a degree of latitude is shorter than one of longitude.
+        let graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines(vec![vec![(10.0, 59.9), (10.0, 60.1)].into()]).unwrap();
+        let params = TopoParams {
+            units: DistanceUnits::Meters,
+            ..default_topo_params
+        };
+
+        let converted_params = params.in_crs_units(&graph).unwrap();
+
+        assert_abs_diff_eq!(
+            6.0 / 111412.24,
+            converted_params.hole_radius,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            11.0 / 111412.24,
+            converted_params.resampling_distance,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            1.0 / 111412.24,
+            params.crs_units_factor(&graph).unwrap(),
+            epsilon = 1e-12
+        );
+        // Parameters in CRS units are not converted.
+        let crs_units_params = TopoParams {
+            units: DistanceUnits::CrsUnits,
+            ..params.clone()
+        };
+        assert_eq!(
+            crs_units_params,
+            crs_units_params.in_crs_units(&graph).unwrap()
+        );
+        assert_eq!(1.0, crs_units_params.crs_units_factor(&graph).unwrap());
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[test]
+    fn test_topo_params_in_crs_units_us_survey_feet() {
+        // A road in San Francisco, projected to California zone 3, whose unit is the US survey foot.
+        let mut graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines(vec![vec![(-122.42, 37.77), (-122.41, 37.77)].into()])
+                .unwrap();
+        project_geograph(&mut graph, &crate::crs::Crs::Epsg(2227)).unwrap();
+        let params = TopoParams {
+            units: DistanceUnits::Meters,
+            ..TopoParams::new_in_crs_units(11.0, 5.0)
+        };
+        let us_survey_foot = 1200.0 / 3937.0;
+
+        let converted_params = params.in_crs_units(&graph).unwrap();
+
+        assert_abs_diff_eq!(
+            5.0 / us_survey_foot,
+            converted_params.hole_radius,
+            epsilon = 1e-6
+        );
+        let result = calculate_topo(&graph, &graph, &params, None, None).unwrap();
+        for node in &result.ground_truth_nodes {
+            assert_abs_diff_eq!(
+                5.0 / us_survey_foot,
+                node.hole_radius().unwrap(),
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[rstest]
+    fn test_calculate_topo_propagate_edge_attributes(default_topo_params: TopoParams) {
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![vec![(0.0, 1.0), (22.0, 1.0)].into()],
+                vec![HashMap::from([
+                    ("confidence".to_string(), FieldValue::RealValue(0.9)),
+                    // Collides with the node id.
+                    ("id".to_string(), FieldValue::IntegerValue(7)),
+                    (
+                        "class".to_string(),
+                        FieldValue::StringValue("road".to_string()),
+                    ),
+                ])],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            propagate_edge_attributes: vec!["confidence".to_string(), "id".to_string()],
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        let nodes_filepath = testdir!().join("proposal_nodes.gpkg");
+        write_features_to_geofile(
+            &result.proposal_nodes.iter().map(Feature::from).collect(),
+            &nodes_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let (features, _) = read_features_from_geofile(&nodes_filepath).unwrap();
+        assert_eq!(3, features.len());
+        for feature in features {
+            let attributes = feature.attributes.unwrap();
+            assert_eq!(
+                Some("0.9".to_string()),
+                attributes["confidence"].clone().into_string()
+            );
+            assert_eq!(
+                Some("7".to_string()),
+                attributes["edge_id"].clone().into_string()
+            );
+            assert!(!attributes.contains_key("class"));
+        }
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[rstest]
+    fn test_write_nodes_with_export_schema(default_topo_params: TopoParams) {
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![vec![(0.0, 1.0), (22.0, 1.0)].into()],
+                vec![HashMap::from([(
+                    "dist_m".to_string(),
+                    FieldValue::StringValue("collides".to_string()),
+                )])],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            propagate_edge_attributes: vec!["dist_m".to_string()],
+            ..default_topo_params
+        };
+        let schema = NodeExportSchema {
+            id_field: "node_id".to_string(),
+            matched_field: "is_matched".to_string(),
+            match_distance_field: "dist_m".to_string(),
+            boolean_encoding: BooleanEncoding::Integer,
+            ..NodeExportSchema::default()
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        let features: Vec<Feature> = result
+            .proposal_nodes
+            .iter()
+            .map(|node| node.to_feature(&schema))
+            .collect();
+        let nodes_filepath = testdir!().join("proposal_nodes.gpkg");
+        write_features_to_geofile(
+            &features,
+            &nodes_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        for feature in features {
+            let attributes = feature.attributes.unwrap();
+            assert!(matches!(
+                attributes["node_id"],
+                FieldValue::Integer64Value(_)
+            ));
+            assert_eq!(FieldValue::IntegerValue(1), attributes["is_matched"]);
+            assert_eq!(FieldValue::RealValue(1.0), attributes["dist_m"]);
+        }
+        // The geofile writer writes all attributes as strings.
+        let (features, _) = read_features_from_geofile(&nodes_filepath).unwrap();
+        assert_eq!(3, features.len());
+        for feature in features {
+            let attributes = feature.attributes.unwrap();
+            let value = |name: &str| attributes[name].clone().into_string().unwrap();
+            assert!(value("node_id").parse::<i32>().is_ok());
+            assert_eq!("1", value("is_matched"));
+            assert_eq!(1.0, value("dist_m").parse::<f64>().unwrap());
+            assert_eq!("collides", value("edge_dist_m"));
+            assert!(!attributes.contains_key("id"));
+            assert!(!attributes.contains_key("matched"));
+        }
+    }
+
+    #[cfg(feature = "gdal-io")]
+    #[rstest]
+    fn test_node_ids_above_i32_max_are_exported() {
+        let ids = [i32::MAX as u64 + 1, 5_000_000_000];
+        let features: Vec<Feature> = ids
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| {
+                let point = RoadPoint {
+                    coord: geo::coord! { x: idx as f64, y: 0.0 },
+                    azimuth: 0.0,
+                    heading: 0.0,
+                    edge_idx: 0,
+                };
+                Feature::from(&TopoNode::new(point, *id))
+            })
+            .collect();
+        let nodes_filepath = testdir!().join("nodes.gpkg");
+
+        write_features_to_geofile(
+            &features,
+            &nodes_filepath,
+            None,
+            GdalDriverType::GeoPackage.name(),
+            &WriteOptions::new().typed_fields(true),
+        )
+        .unwrap();
+
+        let (features, _) = read_features_from_geofile(&nodes_filepath).unwrap();
+        let exported_ids: Vec<FieldValue> = features
+            .into_iter()
+            .map(|feature| feature.attributes.unwrap()["id"].clone())
+            .collect();
+        assert_eq!(
+            ids.iter()
+                .map(|id| FieldValue::Integer64Value(*id as i64))
+                .collect::<Vec<_>>(),
+            exported_ids
+        );
+    }
+
+    #[rstest]
+    #[case("", "matched", false)]
+    #[case("id", "id", false)]
+    #[case("node_id", "is_matched", true)]
+    fn test_node_export_schema_validate(
+        #[case] id_field: &str,
+        #[case] matched_field: &str,
+        #[case] expect_valid: bool,
+    ) {
+        let schema = NodeExportSchema {
+            id_field: id_field.to_string(),
+            matched_field: matched_field.to_string(),
+            ..NodeExportSchema::default()
+        };
+
+        let result = schema.validate();
+
+        assert_eq!(expect_valid, result.is_ok());
+        if let Err(error) = result {
+            assert!(matches!(error, Error::ConfigError(_)));
+        }
+    }
+
+    #[rstest]
+    #[case(vec![(0.0, 4.0), (22.0, 4.0)], "service", None, 3)] // Parallel street of another class, unchecked.
+    #[case(vec![(0.0, 4.0), (22.0, 4.0)], "service", Some(2.0), 0)] // Parallel street of another class.
+    #[case(vec![(0.0, 4.0), (22.0, 4.0)], "residential", Some(2.0), 3)] // Parallel street of the same class.
+    #[case(vec![(11.0, -11.0), (11.0, 11.0)], "residential", None, 1)] // Crossing street, unchecked.
+    #[case(vec![(11.0, -11.0), (11.0, 11.0)], "residential", Some(2.0), 0)] // Crossing street.
+    fn test_calculate_topo_network_detour_factor(
+        default_topo_params: TopoParams,
+        #[case] proposal_line: Vec<(f64, f64)>,
+        #[case] proposal_class: &str,
+        #[case] max_network_detour_factor: Option<f64>,
+        #[case] expected_matched_count: usize,
+    ) {
+        let road_class = |class: &str| {
+            HashMap::from([(
+                "highway".to_string(),
+                FieldValue::StringValue(class.to_string()),
+            )])
+        };
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                vec![road_class("residential")],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![proposal_line.into()],
+                vec![road_class(proposal_class)],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            road_class_attribute: Some("highway".to_string()),
+            max_network_detour_factor,
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        assert_eq!(
+            expected_matched_count,
+            result
+                .proposal_nodes
+                .iter()
+                .filter(|node| node.matched())
+                .count()
+        );
+    }
+
+    #[rstest]
+    #[case(false, 0.5)]
+    #[case(true, 1.0)]
+    fn test_calculate_topo_deduplicate_proposal_points(
+        default_topo_params: TopoParams,
+        #[case] deduplicate_proposal_points: bool,
+        #[case] expected_precision: f64,
+    ) {
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (110.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        // The same road drawn twice, slightly apart so that the sampled points do not coincide exactly.
+        let proposal_graph = build_geograph_from_lines_in_crs(
+            vec![
+                vec![(0.0, 0.2), (110.0, 0.2)].into(),
+                vec![(0.0, 0.4), (110.0, 0.4)].into(),
+            ],
+            projected_crs(),
+        )
+        .unwrap();
+        let params = TopoParams {
+            deduplicate_proposal_points,
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        assert_abs_diff_eq!(expected_precision, result.f1_score_result.precision);
+        assert_abs_diff_eq!(1.0, result.f1_score_result.recall);
+        let expected_weight = if deduplicate_proposal_points { 2 } else { 1 };
+        assert!(result
+            .proposal_nodes
+            .iter()
+            .all(|node| node.weight() == expected_weight));
+        let ids: Vec<u64> = result.proposal_nodes.iter().map(|node| node.id()).collect();
+        assert_eq!(
+            (0..result.proposal_nodes.len() as u64).collect::<Vec<_>>(),
+            ids
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_class_breakdown(default_topo_params: TopoParams) {
+        let attributes = |name: &str, class: Option<&str>| -> FeatureMap {
+            class
+                .map(|class| (name.to_string(), FieldValue::StringValue(class.to_string())))
+                .into_iter()
+                .collect()
+        };
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (22.0, 0.0)].into(),
+                    vec![(0.0, 100.0), (22.0, 100.0)].into(),
+                ],
+                vec![
+                    attributes("highway", Some("residential")),
+                    attributes("highway", Some("footway")),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        // The proposal misses the footway, and has an unclassified edge far from the ground truth.
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 1.0), (22.0, 1.0)].into(),
+                    vec![(0.0, 200.0), (22.0, 200.0)].into(),
+                ],
+                vec![
+                    attributes("class", Some("residential")),
+                    attributes("class", None),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            road_class_attribute: Some("highway".to_string()),
+            proposal_road_class_attribute: Some("class".to_string()),
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        let class_ratios = |scores: &BTreeMap<String, ClassScore>| -> Vec<(String, usize, f64)> {
+            scores
+                .iter()
+                .map(|(class, score)| (class.clone(), score.total_count, score.ratio))
+                .collect()
+        };
+        let class_breakdown = result.class_breakdown.unwrap();
+        assert_eq!(
+            vec![
+                ("footway".to_string(), 3, 0.0),
+                ("residential".to_string(), 3, 1.0),
+            ],
+            class_ratios(&class_breakdown.recall_by_ground_truth_class)
+        );
+        assert_eq!(
+            vec![
+                (UNCLASSIFIED_ROAD_CLASS.to_string(), 3, 0.0),
+                ("residential".to_string(), 3, 1.0),
+            ],
+            class_ratios(&class_breakdown.precision_by_proposal_class)
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_with_candidate_sink(default_topo_params: TopoParams) {
+        let ground_truth_graph = build_geograph_from_lines_in_crs(
+            vec![vec![(11.0, -11.0), (11.0, 11.0)].into()],
+            projected_crs(),
+        )
+        .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            hole_radius: 12.0,
+            ..default_topo_params
+        };
+        let candidates_filepath = testdir!().join("candidates.csv");
+
+        let mut candidate_writer = CsvCandidateWriter::create(&candidates_filepath).unwrap();
+        let result = calculate_topo_with_candidate_sink(
+            &proposal_graph,
+            &ground_truth_graph,
+            &params,
+            None,
+            None,
+            &mut candidate_writer,
+        )
+        .unwrap();
+
+        let node_coords = |nodes: &[TopoNode]| -> HashMap<u64, (f64, f64)> {
+            nodes
+                .iter()
+                .map(|node| (node.id, node.road_point.coord.x_y()))
+                .collect()
+        };
+        let proposal_coords = node_coords(&result.proposal_nodes);
+        let ground_truth_coords = node_coords(&result.ground_truth_nodes);
+        let mut candidates: Vec<_> = csv::Reader::from_path(&candidates_filepath)
+            .unwrap()
+            .deserialize()
+            .map(|candidate| {
+                let candidate: CandidatePair = candidate.unwrap();
+                (
+                    proposal_coords[&candidate.proposal_node_id],
+                    ground_truth_coords[&candidate.gt_node_id],
+                    candidate.distance,
+                    candidate.azimuth_diff,
+                )
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            vec![
+                ((0.0, 0.0), (11.0, 0.0), 11.0, FRAC_PI_2),
+                ((11.0, 0.0), (11.0, -11.0), 11.0, FRAC_PI_2),
+                ((11.0, 0.0), (11.0, 0.0), 0.0, FRAC_PI_2),
+                ((11.0, 0.0), (11.0, 11.0), 11.0, FRAC_PI_2),
+                ((22.0, 0.0), (11.0, 0.0), 11.0, FRAC_PI_2),
+            ],
+            candidates
+        );
+    }
+
+    #[rstest]
+    fn test_match_nodes_with_compatibility(default_topo_params: TopoParams) {
+        /// Rejects all matches whose allowed detour is below 3.
+        struct MinDetourFactor;
+
+        impl MatchCompatibility for MinDetourFactor {
+            fn is_compatible(&self, _: &TopoNode, _: &TopoNode, max_detour_factor: f64) -> bool {
+                max_detour_factor >= 3.0
+            }
+        }
+
+        let graph: GeoGraph<(), (), petgraph::Undirected> = build_geograph_from_lines_in_crs(
+            vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+            projected_crs(),
+        )
+        .unwrap();
+        let gt_index = build_gt_index(
+            sample_graph(&graph, &default_topo_params, GraphRole::GroundTruth).unwrap(),
+        )
+        .unwrap();
+        let proposal_nodes =
+            sample_graph(&graph, &default_topo_params, GraphRole::Proposal).unwrap();
+
+        for (max_network_detour_factor, expected_matched_count) in
+            [(None, 3), (Some(2.0), 0), (Some(3.0), 3)]
+        {
+            let params = TopoParams {
+                max_network_detour_factor,
+                ..default_topo_params.clone()
+            };
+            let outcome = match_nodes_with_compatibility(
+                proposal_nodes.clone(),
+                &gt_index,
+                &params,
+                &MinDetourFactor,
+            )
+            .unwrap();
+            assert_eq!(
+                expected_matched_count,
+                outcome
+                    .proposal_nodes
+                    .iter()
+                    .filter(|node| node.matched())
+                    .count()
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(0.0, 0.0, 0.0)]
+    #[case(FRAC_PI_4, -FRAC_PI_4, FRAC_PI_2)]
+    #[case(FRAC_PI_2, -FRAC_PI_4 - 0.1, FRAC_PI_4 - 0.1)]
+    #[case(1.5, -1.5, PI - 3.0)]
+    fn test_azimuth_difference(
+        #[case] azimuth: f64,
+        #[case] other_azimuth: f64,
+        #[case] expected_difference: f64,
+    ) {
+        assert_abs_diff_eq!(
+            expected_difference,
+            azimuth_difference(azimuth, other_azimuth),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            expected_difference,
+            azimuth_difference(other_azimuth, azimuth),
+            epsilon = 1e-9
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_evaluation_mask(default_topo_params: TopoParams) {
+        // The ground truth has twice as many points as the proposal, which only covers its first half.
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (30.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 1.0), (10.0, 1.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            resampling_distance: 10.0,
+            hole_radius: 3.0,
+            evaluation_mask: Some(EvaluationMask::ProposalBuffer { distance: 5.0 }),
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        assert_eq!(1.0, result.f1_score_result.recall);
+        assert_eq!(1.0, result.f1_score_result.precision);
+        assert_eq!(0.5, result.unmasked_f1_score_result.unwrap().recall);
+        let excluded_xs: Vec<f64> = result
+            .ground_truth_nodes
+            .iter()
+            .filter(|node| node.excluded)
+            .map(|node| node.road_point.coord.x)
+            .collect();
+        assert_eq!(vec![20.0, 30.0], excluded_xs);
+    }
+
+    #[rstest]
+    fn test_calculate_topo_low_memory_matches_in_memory(default_topo_params: TopoParams) {
+        // A grid of roads of two classes, and a proposal which is shifted, misses every fifth road and only covers
+        // the western part of the grid.
+        let grid_lines = |offset: (f64, f64)| -> Vec<(geo::LineString, String)> {
+            (0..20)
+                .flat_map(|line_idx| {
+                    let position = line_idx as f64 * 25.0;
+                    let road_class = if line_idx % 2 == 0 {
+                        "primary"
+                    } else {
+                        "residential"
+                    };
+                    [
+                        vec![
+                            (offset.0, position + offset.1),
+                            (475.0 + offset.0, position + offset.1),
+                        ],
+                        vec![
+                            (position + offset.0, offset.1),
+                            (position + offset.0, 475.0 + offset.1),
+                        ],
+                    ]
+                    .map(|coords| (coords.into(), road_class.to_string()))
+                })
+                .collect()
+        };
+        let build_graph = |lines: Vec<(geo::LineString, String)>| {
+            let (lines, road_classes): (Vec<_>, Vec<_>) = lines.into_iter().unzip();
+            let edge_data = road_classes
+                .into_iter()
+                .map(|road_class| {
+                    HashMap::from([("highway".to_string(), FieldValue::StringValue(road_class))])
+                })
+                .collect();
+            let graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+                build_geograph_from_lines_with_data_in_crs(lines, edge_data, projected_crs())
+                    .unwrap();
+            graph
+        };
+        let ground_truth_graph = build_graph(grid_lines((0.0, 0.0)));
+        let proposal_graph = build_graph(
+            grid_lines((1.5, 2.0))
+                .into_iter()
+                .enumerate()
+                .filter(|(line_idx, (line, _))| {
+                    line_idx % 5 != 0 && line.coords().all(|coord| coord.x < 300.0)
+                })
+                .map(|(_, line)| line)
+                .collect(),
+        );
+        let params = TopoParams {
+            resampling_distance: 5.0,
+            road_class_attribute: Some("highway".to_string()),
+            hole_radius_by_class: Some(HashMap::from([("residential".to_string(), 3.0)])),
+            evaluation_mask: Some(EvaluationMask::ProposalBuffer { distance: 30.0 }),
+            sampling_phase_offsets: Some(vec![0.25, 0.5]),
+            ..default_topo_params
+        };
+
+        let in_memory_result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        let low_memory_result = calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &TopoParams {
+                low_memory: true,
+                ..params
+            },
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert!(in_memory_result.ground_truth_nodes.len() > 3000);
+        assert!((0.1..0.9).contains(&in_memory_result.f1_score_result.recall));
+        assert_eq!(
+            in_memory_result.f1_score_result,
+            low_memory_result.f1_score_result
+        );
+        assert_eq!(
+            in_memory_result.unmasked_f1_score_result,
+            low_memory_result.unmasked_f1_score_result
+        );
+        assert_eq!(
+            in_memory_result.class_breakdown,
+            low_memory_result.class_breakdown
+        );
+        assert_eq!(
+            in_memory_result.phase_offset_scores,
+            low_memory_result.phase_offset_scores
+        );
+        assert_eq!(
+            serde_json::to_value(&in_memory_result.ground_truth_nodes).unwrap(),
+            serde_json::to_value(&low_memory_result.ground_truth_nodes).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&in_memory_result.proposal_nodes).unwrap(),
+            serde_json::to_value(&low_memory_result.proposal_nodes).unwrap()
+        );
+    }
+
+    #[rstest]
+    fn test_topo_evaluator_matches_individual_runs(default_topo_params: TopoParams) {
+        // A grid of roads of two classes, and a proposal which is shifted and misses every third road.
+        let grid_lines = |offset: f64| -> Vec<(geo::LineString, String)> {
+            (0..8)
+                .flat_map(|line_idx| {
+                    let position = line_idx as f64 * 30.0;
+                    let road_class = if line_idx % 2 == 0 {
+                        "primary"
+                    } else {
+                        "residential"
+                    };
+                    [
+                        vec![(0.0, position + offset), (210.0, position + offset)],
+                        vec![(position + offset, 0.0), (position + offset, 210.0)],
+                    ]
+                    .map(|coords| (coords.into(), road_class.to_string()))
+                })
+                .collect()
+        };
+        let build_graph = |lines: Vec<(geo::LineString, String)>| {
+            let (lines, road_classes): (Vec<_>, Vec<_>) = lines.into_iter().unzip();
+            let edge_data = road_classes
+                .into_iter()
+                .map(|road_class| {
+                    HashMap::from([("highway".to_string(), FieldValue::StringValue(road_class))])
+                })
+                .collect();
+            let graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+                build_geograph_from_lines_with_data_in_crs(lines, edge_data, projected_crs())
+                    .unwrap();
+            graph
+        };
+        let ground_truth_graph = build_graph(grid_lines(0.0));
+        let proposal_graph = build_graph(
+            grid_lines(2.5)
+                .into_iter()
+                .enumerate()
+                .filter(|(line_idx, _)| line_idx % 3 != 0)
+                .map(|(_, line)| line)
+                .collect(),
+        );
+        let base_params = TopoParams {
+            road_class_attribute: Some("highway".to_string()),
+            sampling_phase_offsets: Some(vec![0.5]),
+            ..default_topo_params
+        };
+        let params_sweep: Vec<TopoParams> = [4.0, 5.0]
+            .into_iter()
+            .flat_map(|resampling_distance| {
+                [2.0, 4.0].map(|hole_radius| TopoParams {
+                    resampling_distance,
+                    hole_radius,
+                    hole_radius_by_class: Some(HashMap::from([(
+                        "residential".to_string(),
+                        hole_radius + 1.0,
+                    )])),
+                    ..base_params.clone()
+                })
+            })
+            .collect();
+        let mut evaluator = TopoEvaluator::new(&proposal_graph, &ground_truth_graph, 1);
+
+        // Evaluate the first parameters again at the end, after their nodes were evicted from the cache.
+        let mut f1_scores = Vec::new();
+        for params in params_sweep.iter().chain(&params_sweep[..1]) {
+            let result = evaluator.evaluate(params).unwrap();
+            let individual_result =
+                calculate_topo(&proposal_graph, &ground_truth_graph, params, None, None).unwrap();
+
+            assert_eq!(individual_result.f1_score_result, result.f1_score_result);
+            assert_eq!(individual_result.class_breakdown, result.class_breakdown);
+            assert_eq!(
+                individual_result.phase_offset_scores,
+                result.phase_offset_scores
+            );
+            assert_eq!(
+                serde_json::to_value(&individual_result.ground_truth_nodes).unwrap(),
+                serde_json::to_value(&result.ground_truth_nodes).unwrap()
+            );
+            assert_eq!(
+                serde_json::to_value(&individual_result.proposal_nodes).unwrap(),
+                serde_json::to_value(&result.proposal_nodes).unwrap()
+            );
+            assert_eq!(1, evaluator.cache.len());
+            f1_scores.push(result.f1_score_result.f1_score());
+        }
+        // The hole radius changes the scores, and the second evaluation of each resampling distance reuses its
+        // sampled nodes.
+        assert_ne!(f1_scores[0], f1_scores[1]);
+        let sampled_stages = |params: &TopoParams| {
+            let mut evaluator = TopoEvaluator::new(&proposal_graph, &ground_truth_graph, 1);
+            [params, params].map(|params| {
+                evaluator
+                    .evaluate(params)
+                    .unwrap()
+                    .stage_metrics
+                    .iter()
+                    .filter(|metrics| metrics.stage == "sample_ground_truth")
+                    .count()
+            })
+        };
+        assert_eq!([1, 0], sampled_stages(&params_sweep[0]));
+    }
+
+    #[rstest]
+    #[case(
+        TopoParams::new_in_crs_units(0.0, 6.0),
+        "resampling_distance must be positive, but is 0"
+    )]
+    #[case(
+        TopoParams::new_in_crs_units(11.0, f64::NAN),
+        "hole_radius must be positive, but is NaN"
+    )]
+    #[case(
+        TopoParams {
+            hole_radius_by_class: Some(HashMap::from([("primary".to_string(), -1.0)])),
+            ..TopoParams::new_in_crs_units(11.0, 6.0)
+        },
+        "The hole radius of road class 'primary' must be positive, but is -1"
+    )]
+    #[case(
+        TopoParams {
+            max_network_detour_factor: Some(0.5),
+            ..TopoParams::new_in_crs_units(11.0, 6.0)
+        },
+        "max_network_detour_factor must be at least 1, but is 0.5"
+    )]
+    #[case(
+        TopoParams {
+            exclude_near_junction_distance: Some(-3.0),
+            ..TopoParams::new_in_crs_units(11.0, 6.0)
+        },
+        "exclude_near_junction_distance must be non-negative, but is -3"
+    )]
+    fn test_topo_params_validate_fails(#[case] params: TopoParams, #[case] expected_message: &str) {
+        let error = params.validate().unwrap_err();
+
+        assert!(matches!(error, Error::ConfigError(_)));
+        assert_eq!(
+            format!("Invalid config: {}", expected_message),
+            error.to_string()
+        );
+    }
+
+    #[rstest]
+    fn test_topo_params_validate(default_topo_params: TopoParams) {
+        assert!(default_topo_params.validate().is_ok());
+        assert!(TopoParams::default().validate().is_ok());
+    }
+
+    #[rstest]
+    #[case(true, 0)]
+    #[case(false, 2)]
+    fn test_sample_graph_collapses_reciprocal_edges(
+        default_topo_params: TopoParams,
+        #[case] collapse_reciprocal_edges: bool,
+        #[case] expected_extra_node_count: usize,
+    ) {
+        // A two-way street as two opposing edges, and a one-way street. The length of the two-way street is not a
+        // multiple of the resampling distance, so that the points sampled in either direction do not coincide.
+        let directed_graph: GeoGraph<(), (), petgraph::Directed> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (25.0, 0.0)].into(),
+                    vec![(25.0, 0.0), (0.0, 0.0)].into(),
+                    vec![(25.0, 0.0), (25.0, 30.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let undirected_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (25.0, 0.0)].into(),
+                    vec![(25.0, 0.0), (25.0, 30.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            collapse_reciprocal_edges,
+            ..default_topo_params
+        };
+
+        let directed_nodes =
+            sample_graph(&directed_graph, &params, GraphRole::GroundTruth).unwrap();
+        let undirected_nodes =
+            sample_graph(&undirected_graph, &params, GraphRole::GroundTruth).unwrap();
+
+        assert_eq!(
+            undirected_nodes.len() + expected_extra_node_count,
+            directed_nodes.len()
+        );
+    }
+
+    #[rstest]
+    #[case(false, 1.0)]
+    #[case(true, 0.0)]
+    fn test_calculate_topo_directional_matching(
+        default_topo_params: TopoParams,
+        #[case] directional_matching: bool,
+        #[case] expected_precision_and_recall: f64,
+    ) {
+        // A one-way street, and a proposal edge drawn against its direction.
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Directed> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (110.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Directed> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(110.0, 1.0), (0.0, 1.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            directional_matching,
+            ..default_topo_params
+        };
+
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        assert_abs_diff_eq!(
+            expected_precision_and_recall,
+            result.f1_score_result.precision(),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            expected_precision_and_recall,
+            result.f1_score_result.recall(),
+            epsilon = 1e-9
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_directional_matching_requires_directed_graphs(
+        default_topo_params: TopoParams,
+    ) {
+        let graph: GeoGraph<(), (), petgraph::Undirected> = build_geograph_from_lines_in_crs(
+            vec![vec![(0.0, 0.0), (110.0, 0.0)].into()],
+            projected_crs(),
+        )
+        .unwrap();
+        let params = TopoParams {
+            directional_matching: true,
+            ..default_topo_params
+        };
+
+        assert!(matches!(
+            calculate_topo(&graph, &graph, &params, None, None),
+            Err(Error::ConfigError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(0.0, PI, PI)]
+    #[case(FRAC_PI_4, -FRAC_PI_4, FRAC_PI_2)]
+    #[case(0.9 * PI, -0.9 * PI, 0.2 * PI)]
+    #[case(FRAC_PI_2, FRAC_PI_2, 0.0)]
+    fn test_heading_difference(#[case] heading: f64, #[case] other: f64, #[case] expected: f64) {
+        assert_abs_diff_eq!(
+            expected,
+            heading_difference(heading, other),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            expected,
+            heading_difference(other, heading),
+            epsilon = 1e-12
+        );
+    }
+
+    #[rstest]
+    fn test_calculate_topo_samples_parallel_edges(default_topo_params: TopoParams) {
+        // A dual carriageway, with both carriageways between the same nodes.
+        let mut ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            GeoGraph::new(projected_crs());
+        ground_truth_graph
+            .insert_edge(
+                0,
+                1,
+                vec![(0.0, 0.0), (0.0, 20.0), (22.0, 20.0), (22.0, 0.0)].into(),
+            )
+            .unwrap();
+        ground_truth_graph
+            .insert_edge(
+                0,
+                1,
+                vec![(0.0, 0.0), (0.0, -20.0), (22.0, -20.0), (22.0, 0.0)].into(),
+            )
+            .unwrap();
+        // The proposal only has the first carriageway.
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (0.0, 20.0), (22.0, 20.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+
+        let result = calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &default_topo_params,
+            None,
+            None,
+        )
+        .unwrap();
+
+        // Both carriageways are sampled, the shared endpoints only once.
+        let sampled_ys: Vec<f64> = result
+            .ground_truth_nodes
+            .iter()
+            .map(|node| node.coord().y)
+            .collect();
+        assert!(sampled_ys.iter().any(|y| *y > 0.0));
+        assert!(sampled_ys.iter().any(|y| *y < 0.0));
+        assert_eq!(1.0, result.f1_score_result.precision);
+        assert!(result.f1_score_result.recall < 0.6);
+    }
+
+    #[rstest]
+    fn test_gt_index_queries(default_topo_params: TopoParams) {
+        // Sampled at x = 0, 11 and 22 on the first line, and at x = 0 and 10 on the second.
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (22.0, 0.0)].into(),
+                    vec![(0.0, 20.0), (10.0, 20.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+
+        let gt_index = GtIndex::from_graph(&ground_truth_graph, &default_topo_params).unwrap();
+        assert_eq!(5, gt_index.nodes().len());
+
+        let coords_and_distances = |results: Vec<(f64, &TopoNode)>| -> Vec<((f64, f64), f64)> {
+            results
+                .into_iter()
+                .map(|(distance, node)| (node.coord().x_y(), distance))
+                .collect()
+        };
+        let nearest = gt_index.nearest(geo::Coord { x: 10.0, y: 4.0 }, 3).unwrap();
+        assert_eq!(
+            vec![
+                ((11.0, 0.0), 17.0_f64.sqrt()),
+                ((0.0, 0.0), 116.0_f64.sqrt()),
+                ((22.0, 0.0), 160.0_f64.sqrt())
+            ],
+            coords_and_distances(nearest)
+        );
+        let within = gt_index
+            .within(geo::Coord { x: 10.0, y: 14.0 }, 8.0)
+            .unwrap();
+        assert_eq!(vec![((10.0, 20.0), 6.0)], coords_and_distances(within));
+        assert!(gt_index
+            .nearest(
+                geo::Coord {
+                    x: f64::NAN,
+                    y: 0.0
+                },
+                1
+            )
+            .is_err());
+        for node in gt_index.nodes() {
+            assert_eq!(Some(6.0), node.hole_radius());
+        }
+    }
+
+    #[rstest]
+    #[case(None)]
+    #[case(Some(EvaluationMask::ProposalBuffer { distance: 5.0 }))]
+    fn test_phases_reproduce_calculate_topo(
+        #[case] evaluation_mask: Option<EvaluationMask>,
+        default_topo_params: TopoParams,
+    ) {
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (30.0, 0.0)].into(),
+                    vec![(30.0, 0.0), (30.0, 20.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 1.0), (12.0, 1.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let params = TopoParams {
+            resampling_distance: 10.0,
+            hole_radius: 3.0,
+            evaluation_mask,
+            ..default_topo_params
+        };
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+
+        // Compose the phases, caching the intermediate values in between.
+        let proposal_nodes = sample_graph(&proposal_graph, &params, GraphRole::Proposal).unwrap();
+        let ground_truth_nodes =
+            sample_graph(&ground_truth_graph, &params, GraphRole::GroundTruth).unwrap();
+        let proposal_nodes: Vec<TopoNode> =
+            serde_json::from_str(&serde_json::to_string(&proposal_nodes).unwrap()).unwrap();
+        let gt_index = build_gt_index(ground_truth_nodes).unwrap();
+        let gt_index: GtIndex =
+            serde_json::from_str(&serde_json::to_string(&gt_index).unwrap()).unwrap();
+        let outcome = match_nodes(proposal_nodes, &gt_index, &params).unwrap();
+
+        assert_eq!(result.f1_score_result, outcome.f1_score_result);
+        assert_eq!(
+            result.unmasked_f1_score_result,
+            outcome.unmasked_f1_score_result
+        );
+        let node_states = |nodes: &[TopoNode]| -> Vec<((f64, f64), bool, bool)> {
+            nodes
+                .iter()
+                .map(|node| (node.coord().x_y(), node.matched, node.excluded))
+                .collect()
+        };
+        assert_eq!(
+            node_states(&result.proposal_nodes),
+            node_states(&outcome.proposal_nodes)
+        );
+        assert_eq!(
+            node_states(&result.ground_truth_nodes),
+            node_states(&outcome.ground_truth_nodes)
+        );
+        // The index is unchanged by matching.
+        assert!(gt_index.nodes().iter().all(|node| !node.matched));
+    }
+
+    /// Records the stages, totals, and advanced units reported to it.
+    #[derive(Default)]
+    struct RecordingProgress {
+        stages: Mutex<Vec<(String, u64, u64)>>,
+    }
+
+    impl ProgressSink for RecordingProgress {
+        fn begin(&self, stage: &str, total: u64) {
+            self.stages
+                .lock()
+                .unwrap()
+                .push((stage.to_string(), total, 0));
+        }
+
+        fn advance(&self, n: u64) {
+            self.stages.lock().unwrap().last_mut().unwrap().2 += n;
+        }
+
+        fn end(&self) {}
+    }
+
+    #[rstest]
+    fn test_calculate_topo_reports_progress(default_topo_params: TopoParams) {
+        let ground_truth_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 0.0), (22.0, 0.0)].into()],
+                projected_crs(),
+            )
+            .unwrap();
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![
+                    vec![(0.0, 1.0), (22.0, 1.0)].into(),
+                    vec![(0.0, 50.0), (22.0, 50.0)].into(),
+                ],
+                projected_crs(),
+            )
+            .unwrap();
+        let progress = RecordingProgress::default();
+
+        calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &default_topo_params,
+            None,
+            Some(&progress),
+        )
+        .unwrap();
+
+        // Two proposal edges with three points each.
+        let expected_stages: Vec<(String, u64, u64)> = vec![
+            ("sample_proposal".to_string(), 2, 2),
+            ("sample_ground_truth".to_string(), 1, 1),
+            ("lookup_within_hole_radius".to_string(), 6, 6),
+            ("match".to_string(), 6, 6),
+        ];
+        assert_eq!(expected_stages, *progress.stages.lock().unwrap());
+    }
+
+    #[rstest]
+    fn test_calculate_topo_cancelled_before_start(default_topo_params: TopoParams) {
+        let graph = build_large_graph(0.0);
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+
+        let error = calculate_topo(
+            &graph,
+            &graph,
+            &default_topo_params,
+            Some(&cancellation_token),
+            None,
+        )
+        .err()
+        .unwrap();
+        match error {
+            Error::Cancelled {
+                stage,
+                completed_stages,
+            } => {
+                assert_eq!("sample_proposal", stage);
+                assert!(completed_stages.is_empty());
+            }
+            _ => panic!("Expected a Cancelled error, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_calculate_topo_cancelled_from_other_thread() {
+        let proposal_graph = build_large_graph(1.0);
+        let ground_truth_graph = build_large_graph(0.0);
+        let params = TopoParams {
+            resampling_distance: 1.0,
+            hole_radius: 6.0,
+            matching_mode: MatchingMode::Greedy,
+            road_class_attribute: None,
+            proposal_road_class_attribute: None,
+            hole_radius_by_class: None,
+            evaluation_mask: None,
+            units: DistanceUnits::CrsUnits,
+            anisotropy_warning_latitude: 60.0,
+            propagate_edge_attributes: Vec::new(),
+            include_original_vertices: false,
+            max_network_detour_factor: None,
+            deduplicate_proposal_points: false,
+            proposal_dedup_radius: None,
+            exclude_near_junction_distance: None,
+            collapse_reciprocal_edges: false,
+            sampling_phase_offsets: None,
+            directional_matching: false,
+            low_memory: false,
+        };
+        let cancellation_token = CancellationToken::new();
+
+        let cancelling_thread = {
+            let cancellation_token = cancellation_token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                cancellation_token.cancel();
+                Instant::now()
+            })
+        };
+        let result = calculate_topo(
+            &proposal_graph,
+            &ground_truth_graph,
+            &params,
+            Some(&cancellation_token),
+            None,
+        );
+        let returned_at = Instant::now();
+        let cancelled_at = cancelling_thread.join().unwrap();
+
+        let error = result.err().unwrap();
+        assert!(matches!(error, Error::Cancelled { .. }));
+        assert!(returned_at.duration_since(cancelled_at) < Duration::from_secs(2));
+    }
+
+    #[rstest]
+    #[case(MatchingMode::Greedy)]
+    #[case(MatchingMode::OptimalAssignment)]
+    fn test_calculate_topo_independent_of_thread_count(
+        default_topo_params: TopoParams,
+        #[case] matching_mode: MatchingMode,
+    ) {
+        let params = TopoParams {
+            matching_mode,
+            ..default_topo_params
+        };
+        // The graphs are not Sync, so they are built within the thread pool.
+        let calculate_topo_with_threads = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| {
+                    // Each proposal line is halfway between two ground truth lines, so many candidates are equally
+                    // far away.
+                    let proposal_graph = build_parallel_lines_graph(15, 500.0, 5.0);
+                    let ground_truth_graph = build_parallel_lines_graph(15, 500.0, 0.0);
+                    calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None)
+                })
+                .unwrap()
+        };
+        let matches = |nodes: &[TopoNode]| -> Vec<(u64, bool, Option<f64>)> {
+            nodes
+                .iter()
+                .map(|node| (node.id, node.matched, node.match_distance))
+                .collect()
+        };
+
+        let single_threaded_result = calculate_topo_with_threads(1);
+        let multi_threaded_result = calculate_topo_with_threads(8);
+
+        assert_eq!(
+            single_threaded_result.f1_score_result,
+            multi_threaded_result.f1_score_result
+        );
+        assert_eq!(
+            matches(&single_threaded_result.proposal_nodes),
+            matches(&multi_threaded_result.proposal_nodes)
+        );
+        assert_eq!(
+            matches(&single_threaded_result.ground_truth_nodes),
+            matches(&multi_threaded_result.ground_truth_nodes)
+        );
+    }
+
+    #[rstest]
+    #[case(MatchingMode::Greedy, false)]
+    #[case(MatchingMode::Greedy, true)]
+    #[case(MatchingMode::OptimalAssignment, false)]
+    #[case(MatchingMode::OptimalAssignment, true)]
+    fn test_match_nodes_duplicate_ground_truth_points(
+        default_topo_params: TopoParams,
+        #[case] matching_mode: MatchingMode,
+        #[case] reverse_ground_truth_nodes: bool,
+    ) {
+        let params = TopoParams {
+            matching_mode,
+            ..default_topo_params
+        };
+        let node = |x: f64, id: u64, hole_radius: Option<f64>| {
+            let point = RoadPoint {
+                coord: geo::coord! { x: x, y: 0.0 },
+                azimuth: 0.0,
+                heading: 0.0,
+                edge_idx: 0,
+            };
+            TopoNode {
+                hole_radius,
+                ..TopoNode::new(point, id)
+            }
+        };
+        // Two coincident ground truth nodes with different hole radii, e.g. from overlapping roads of different
+        // classes. The first proposal node is equally far from both, the second one only within the larger radius.
+        let mut ground_truth_nodes = vec![node(0.0, 0, Some(2.0)), node(0.0, 1, Some(6.0))];
+        if reverse_ground_truth_nodes {
+            ground_truth_nodes.reverse();
+        }
+        let proposal_nodes = vec![node(1.0, 0, None), node(4.0, 1, None)];
+        let gt_index = build_gt_index(ground_truth_nodes).unwrap();
+
+        let outcome = match_nodes(proposal_nodes, &gt_index, &params).unwrap();
+
+        // Ties are broken by the lower GT node id regardless of the order of the nodes, which leaves the node with
+        // the larger radius for the second proposal node.
+        assert_eq!(F1ScoreResult::from_counts(2, 2, 2), outcome.f1_score_result);
+        let hole_radii: Vec<Option<f64>> = outcome
+            .proposal_nodes
+            .iter()
+            .map(|node| node.hole_radius)
+            .collect();
+        assert_eq!(vec![Some(2.0), Some(6.0)], hole_radii);
     }
 }
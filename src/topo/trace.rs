@@ -0,0 +1,384 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{
+    error::{Error, Result},
+    geofile::feature::{Feature, FieldValue},
+    geograph::primitives::GeoGraph,
+};
+
+use super::{
+    matching::RejectionReason,
+    topo::{
+        azimuth_difference, build_gt_index, candidate_rejection, EdgeAttributeData,
+        EdgeCompatibility, TopoNode, TopoParams, TopoResult,
+    },
+};
+
+/// Default of `TraceEdgeConfig::nearest_candidates`.
+pub const DEFAULT_TRACE_CANDIDATE_COUNT: usize = 5;
+
+/// Identifies the proposal edge traced by `trace_proposal_edge`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub enum EdgeSelector {
+    /// The index of the edge in the order of `GeoGraph::edges_sorted`, see `TopoNode::edge_idx`.
+    Index(usize),
+    /// The first edge whose attribute `name` has the value `value`, compared as text, e.g. an OSM way id.
+    Attribute { name: String, value: String },
+}
+
+impl FromStr for EdgeSelector {
+    type Err = Error;
+
+    /// Parse an edge index, e.g. `12`, or an attribute and its value, e.g. `osm_id=4711`.
+    fn from_str(selector: &str) -> Result<Self> {
+        if let Ok(edge_idx) = selector.parse() {
+            return Ok(EdgeSelector::Index(edge_idx));
+        }
+        match selector.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok(EdgeSelector::Attribute {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(Error::ConfigError(format!(
+                "Invalid edge selector '{}', expected an edge index or ATTRIBUTE=VALUE",
+                selector
+            ))),
+        }
+    }
+}
+
+fn default_trace_candidate_count() -> usize {
+    DEFAULT_TRACE_CANDIDATE_COUNT
+}
+
+/// Configuration of tracing the matching of one proposal edge, see `trace_proposal_edge`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct TraceEdgeConfig {
+    pub edge: EdgeSelector,
+    /// The number of ground truth nodes nearest to each proposal node which are traced.
+    #[serde(default = "default_trace_candidate_count")]
+    pub nearest_candidates: usize,
+}
+
+/// A ground truth node near a traced proposal node, and whether it was matched to the proposal node.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct TracedCandidate {
+    pub gt_node_id: u64,
+    pub coord: (f64, f64),
+    pub distance: f64,
+    /// The angle between the edges of the nodes at the nodes, between 0 and PI/2.
+    pub azimuth_diff: f64,
+    pub hole_radius: Option<f64>,
+    /// Whether the proposal node was matched to this node.
+    pub chosen: bool,
+    /// Why the proposal node was not matched to this node, `None` if it was.
+    pub rejection: Option<RejectionReason>,
+}
+
+/// A proposal node sampled on the traced edge, with its nearest ground truth nodes.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct TracedNode {
+    pub node_id: u64,
+    pub coord: (f64, f64),
+    pub azimuth: f64,
+    pub matched: bool,
+    pub match_distance: Option<f64>,
+    /// The nearest ground truth nodes, in order of increasing distance.
+    pub candidates: Vec<TracedCandidate>,
+}
+
+/// The matching of the nodes of one proposal edge, see `trace_proposal_edge`.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct EdgeTrace {
+    pub edge_idx: usize,
+    pub nodes: Vec<TracedNode>,
+}
+
+/// Find the proposal edge identified by `selector`, as its index in the order of `GeoGraph::edges_sorted`.
+pub fn find_edge<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    graph: &GeoGraph<E, N, Ty>,
+    selector: &EdgeSelector,
+) -> Result<usize> {
+    match selector {
+        EdgeSelector::Index(edge_idx) if *edge_idx < graph.edge_count() => Ok(*edge_idx),
+        EdgeSelector::Index(edge_idx) => Err(Error::ConfigError(format!(
+            "No edge with index {}, the graph has {} edges",
+            edge_idx,
+            graph.edge_count()
+        ))),
+        EdgeSelector::Attribute { name, value } => graph
+            .edges_sorted()
+            .position(|(_, _, _, edge)| {
+                edge.data.attribute(name).and_then(attribute_text).as_ref() == Some(value)
+            })
+            .ok_or_else(|| {
+                Error::ConfigError(format!("No edge whose attribute '{}' is '{}'", name, value))
+            }),
+    }
+}
+
+/// Trace why the nodes sampled on one proposal edge were or were not matched in an evaluation: for each node, look
+/// up the nearest ground truth nodes again, and tell which one it was matched to and why the others were rejected.
+///
+/// The nodes are those of `result`, so that the trace shows the matches of the evaluation. `params` must be in the
+/// units of the CRS of the graphs, see `TopoParams::in_crs_units`.
+pub fn trace_proposal_edge<E: Default + EdgeAttributeData, N: Default, Ty: petgraph::EdgeType>(
+    proposal_graph: &GeoGraph<E, N, Ty>,
+    result: &TopoResult,
+    params: &TopoParams,
+    config: &TraceEdgeConfig,
+) -> Result<EdgeTrace> {
+    let edge_idx = find_edge(proposal_graph, &config.edge)?;
+    trace_edge_nodes(result, params, edge_idx, config.nearest_candidates)
+}
+
+/// Like `trace_proposal_edge`, for the edge with the given index, e.g. the index of a line passed to
+/// `calculate_topo_from_lines`.
+pub fn trace_edge_nodes(
+    result: &TopoResult,
+    params: &TopoParams,
+    edge_idx: usize,
+    nearest_candidates: usize,
+) -> Result<EdgeTrace> {
+    let gt_index = build_gt_index(result.ground_truth_nodes.clone())?;
+    let nodes = result
+        .proposal_nodes
+        .iter()
+        .filter(|node| node.edge_idx() == edge_idx)
+        .map(|proposal_node| {
+            let candidates = gt_index
+                .nearest(proposal_node.coord(), nearest_candidates)?
+                .into_iter()
+                .map(|(distance, gt_node)| {
+                    traced_candidate(proposal_node, gt_node, distance, params)
+                })
+                .collect();
+            Ok(TracedNode {
+                node_id: proposal_node.id(),
+                coord: proposal_node.coord().x_y(),
+                azimuth: proposal_node.azimuth(),
+                matched: proposal_node.matched(),
+                match_distance: proposal_node.match_distance(),
+                candidates,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    log::info!(
+        "Traced {} proposal nodes of edge {}, {} of them matched",
+        nodes.len(),
+        edge_idx,
+        nodes.iter().filter(|node| node.matched).count()
+    );
+    Ok(EdgeTrace { edge_idx, nodes })
+}
+
+fn traced_candidate(
+    proposal_node: &TopoNode,
+    gt_node: &TopoNode,
+    distance: f64,
+    params: &TopoParams,
+) -> TracedCandidate {
+    let chosen = proposal_node.matched_node_id() == Some(gt_node.id());
+    let rejection = if chosen {
+        None
+    } else {
+        candidate_rejection(
+            proposal_node,
+            gt_node,
+            distance.powi(2),
+            params,
+            &EdgeCompatibility,
+        )
+        .or(if gt_node.matched() {
+            Some(RejectionReason::AlreadyMatched)
+        } else if proposal_node.matched() {
+            Some(RejectionReason::OtherCandidateChosen)
+        } else {
+            None
+        })
+    };
+    TracedCandidate {
+        gt_node_id: gt_node.id(),
+        coord: gt_node.coord().x_y(),
+        distance,
+        azimuth_diff: azimuth_difference(proposal_node.azimuth(), gt_node.azimuth()),
+        hole_radius: gt_node.hole_radius(),
+        chosen,
+        rejection,
+    }
+}
+
+/// The trace as features, a line from each traced proposal node to each of its candidates with the attributes
+/// `node_id`, `matched`, `match_distance` of the proposal node, and `gt_node_id`, `distance`, `azimuth_diff`, `chosen`
+/// and `rejection` of the candidate.
+pub fn trace_features(trace: &EdgeTrace) -> Vec<Feature> {
+    trace
+        .nodes
+        .iter()
+        .flat_map(|node| {
+            node.candidates.iter().map(|candidate| {
+                let mut attributes = HashMap::from([
+                    (
+                        "node_id".to_string(),
+                        FieldValue::Integer64Value(node.node_id as i64),
+                    ),
+                    (
+                        "matched".to_string(),
+                        FieldValue::IntegerValue(node.matched as i32),
+                    ),
+                    (
+                        "gt_node_id".to_string(),
+                        FieldValue::Integer64Value(candidate.gt_node_id as i64),
+                    ),
+                    (
+                        "distance".to_string(),
+                        FieldValue::RealValue(candidate.distance),
+                    ),
+                    (
+                        "azimuth_diff".to_string(),
+                        FieldValue::RealValue(candidate.azimuth_diff),
+                    ),
+                    (
+                        "chosen".to_string(),
+                        FieldValue::IntegerValue(candidate.chosen as i32),
+                    ),
+                ]);
+                if let Some(match_distance) = node.match_distance {
+                    attributes.insert(
+                        "match_distance".to_string(),
+                        FieldValue::RealValue(match_distance),
+                    );
+                }
+                if let Some(rejection) = candidate.rejection {
+                    attributes.insert(
+                        "rejection".to_string(),
+                        FieldValue::StringValue(rejection.to_string()),
+                    );
+                }
+                Feature {
+                    geometry: geo::Geometry::LineString(vec![node.coord, candidate.coord].into()),
+                    attributes: Some(attributes),
+                    null_fields: Default::default(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// The value of an attribute as text, for comparing it to the value of an `EdgeSelector`.
+fn attribute_text(value: FieldValue) -> Option<String> {
+    match value {
+        FieldValue::StringValue(value) => Some(value),
+        FieldValue::IntegerValue(value) => Some(value.to_string()),
+        FieldValue::Integer64Value(value) => Some(value.to_string()),
+        FieldValue::RealValue(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::{
+        geofile::feature::{FeatureMap, FieldValue},
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_with_data_in_crs},
+        topo::{
+            matching::{MatchingMode, RejectionReason},
+            topo::{calculate_topo, TopoParams},
+        },
+    };
+
+    use super::{trace_proposal_edge, EdgeSelector, TraceEdgeConfig};
+
+    fn name_data(name: &str) -> FeatureMap {
+        HashMap::from([(
+            "name".to_string(),
+            FieldValue::StringValue(name.to_string()),
+        )])
+    }
+
+    #[test]
+    fn test_edge_selector_from_str() {
+        assert_eq!(EdgeSelector::Index(12), "12".parse().unwrap());
+        assert_eq!(
+            EdgeSelector::Attribute {
+                name: "osm_id".to_string(),
+                value: "4711".to_string()
+            },
+            "osm_id=4711".parse().unwrap()
+        );
+        assert!("osm_id".parse::<EdgeSelector>().is_err());
+    }
+
+    #[test]
+    fn test_trace_proposal_edge() {
+        // A ground truth road, and a crossing road which is within hole radius of the proposal nodes next to it.
+        let ground_truth_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 0.0), (100.0, 0.0)].into(),
+                    vec![(55.0, -50.0), (55.0, 50.0)].into(),
+                ],
+                vec![FeatureMap::new(), FeatureMap::new()],
+                crate::crs::Crs::Epsg(32654),
+            )
+            .unwrap();
+        // Two detections of the road, of which the closer one takes all the matches.
+        let proposal_graph: GeoGraph<FeatureMap, (), petgraph::Undirected> =
+            build_geograph_from_lines_with_data_in_crs(
+                vec![
+                    vec![(0.0, 1.0), (100.0, 1.0)].into(),
+                    vec![(0.0, 1.5), (100.0, 1.5)].into(),
+                ],
+                vec![name_data("close"), name_data("far")],
+                crate::crs::Crs::Epsg(32654),
+            )
+            .unwrap();
+        let params = TopoParams {
+            matching_mode: MatchingMode::OptimalAssignment,
+            max_network_detour_factor: Some(2.0),
+            ..TopoParams::new_in_crs_units(10.0, 6.0)
+        };
+        let result =
+            calculate_topo(&proposal_graph, &ground_truth_graph, &params, None, None).unwrap();
+        let trace = |name: &str| {
+            trace_proposal_edge(
+                &proposal_graph,
+                &result,
+                &params,
+                &TraceEdgeConfig {
+                    edge: EdgeSelector::Attribute {
+                        name: "name".to_string(),
+                        value: name.to_string(),
+                    },
+                    nearest_candidates: 5,
+                },
+            )
+            .unwrap()
+        };
+
+        let close_trace = trace("close");
+        let far_trace = trace("far");
+
+        assert!(!close_trace.nodes.is_empty());
+        assert!(close_trace.nodes.iter().all(|node| node.matched
+            && node.candidates[0].chosen
+            && node.candidates[0].rejection.is_none()));
+        assert!(far_trace.nodes.iter().all(|node| !node.matched));
+        let far_rejections: HashSet<Option<RejectionReason>> = far_trace
+            .nodes
+            .iter()
+            .flat_map(|node| &node.candidates)
+            .map(|candidate| candidate.rejection)
+            .collect();
+        assert_eq!(
+            HashSet::from([
+                Some(RejectionReason::AlreadyMatched),
+                Some(RejectionReason::AzimuthGate),
+                Some(RejectionReason::OutOfRadius),
+            ]),
+            far_rejections
+        );
+    }
+}
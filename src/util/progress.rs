@@ -0,0 +1,288 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+use crate::error::{Result, ResultExt};
+
+/// Receiver of progress reports from long-running computations, e.g. `calculate_topo`.
+///
+/// A computation reports one stage at a time: `begin`, any number of `advance` calls, then `end`. `advance` may be
+/// called from several threads at once.
+pub trait ProgressSink: Sync {
+    /// Start a stage with `total` units of work.
+    fn begin(&self, stage: &str, total: u64);
+    /// Report `n` more units of work of the current stage done.
+    fn advance(&self, n: u64);
+    /// Finish the current stage.
+    fn end(&self);
+}
+
+/// Ignores all progress reports.
+pub struct NoopProgress;
+
+impl ProgressSink for NoopProgress {
+    fn begin(&self, _: &str, _: u64) {}
+
+    fn advance(&self, _: u64) {}
+
+    fn end(&self) {}
+}
+
+/// Shows a progress bar on the terminal for each stage.
+#[derive(Default)]
+pub struct IndicatifProgress {
+    progress_bar: RwLock<Option<ProgressBar>>,
+}
+
+impl IndicatifProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressSink for IndicatifProgress {
+    fn begin(&self, stage: &str, total: u64) {
+        let progress_bar = ProgressBar::new(total).with_style(
+            ProgressStyle::with_template(
+                "{msg} {wide_bar} {pos}/{len} {percent}% elapsed: {elapsed_precise}",
+            )
+            .unwrap(),
+        );
+        progress_bar.set_message(stage.to_string());
+        if let Some(previous_progress_bar) =
+            self.progress_bar.write().unwrap().replace(progress_bar)
+        {
+            previous_progress_bar.finish();
+        }
+    }
+
+    fn advance(&self, n: u64) {
+        if let Some(progress_bar) = self.progress_bar.read().unwrap().as_ref() {
+            progress_bar.inc(n);
+        }
+    }
+
+    fn end(&self) {
+        if let Some(progress_bar) = self.progress_bar.write().unwrap().take() {
+            progress_bar.finish();
+        }
+    }
+}
+
+/// Forwards all progress reports to each of several sinks, e.g. to show progress bars and write a progress file.
+pub struct TeeProgress<'a> {
+    sinks: Vec<&'a dyn ProgressSink>,
+}
+
+impl<'a> TeeProgress<'a> {
+    pub fn new(sinks: Vec<&'a dyn ProgressSink>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl ProgressSink for TeeProgress<'_> {
+    fn begin(&self, stage: &str, total: u64) {
+        for sink in &self.sinks {
+            sink.begin(stage, total);
+        }
+    }
+
+    fn advance(&self, n: u64) {
+        for sink in &self.sinks {
+            sink.advance(n);
+        }
+    }
+
+    fn end(&self) {
+        for sink in &self.sinks {
+            sink.end();
+        }
+    }
+}
+
+/// Minimum time between two lines of the same stage written by `JsonlProgressSink::advance`.
+const JSONL_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One line of the file written by `JsonlProgressSink`.
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    stage: &'a str,
+    completed: u64,
+    total: u64,
+    /// RFC 3339 time of the update.
+    timestamp: String,
+    /// Estimated seconds until the stage is complete, extrapolated from its progress so far. None until some work is
+    /// done.
+    eta_seconds: Option<f64>,
+}
+
+/// The stage a `JsonlProgressSink` currently reports on.
+struct JsonlStage {
+    name: String,
+    total: u64,
+    completed: AtomicU64,
+    started_at: Instant,
+    last_written_at: Mutex<Instant>,
+}
+
+/// Writes progress updates as JSON lines to a file, for other programs to poll, e.g. a dashboard. Each line has the
+/// `stage`, the `completed` and `total` units of work, a `timestamp` and the `eta_seconds`.
+///
+/// A line is written when a stage begins and ends, and in between at most once per second. The file is flushed after
+/// each line.
+pub struct JsonlProgressSink {
+    file: Mutex<File>,
+    stage: RwLock<Option<JsonlStage>>,
+}
+
+impl JsonlProgressSink {
+    /// Create the progress file, replacing an existing one.
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(
+                File::create(path).with_context(|| format!("Creating progress file {:?}", path))?,
+            ),
+            stage: RwLock::new(None),
+        })
+    }
+
+    fn write_event(&self, stage: &JsonlStage, completed: u64) {
+        let elapsed = stage.started_at.elapsed().as_secs_f64();
+        let eta_seconds = (completed > 0)
+            .then(|| elapsed * stage.total.saturating_sub(completed) as f64 / completed as f64);
+        let event = ProgressEvent {
+            stage: &stage.name,
+            completed,
+            total: stage.total,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            eta_seconds,
+        };
+        let result = serde_json::to_string(&event)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                let mut file = self.file.lock().unwrap();
+                file.write_all(format!("{}\n", line).as_bytes())?;
+                file.flush()
+            });
+        if let Err(error) = result {
+            log::warn!(
+                "Could not write progress of stage '{}': {}",
+                stage.name,
+                error
+            );
+        }
+    }
+}
+
+impl ProgressSink for JsonlProgressSink {
+    fn begin(&self, stage: &str, total: u64) {
+        let stage = JsonlStage {
+            name: stage.to_string(),
+            total,
+            completed: AtomicU64::new(0),
+            started_at: Instant::now(),
+            last_written_at: Mutex::new(Instant::now()),
+        };
+        self.write_event(&stage, 0);
+        *self.stage.write().unwrap() = Some(stage);
+    }
+
+    fn advance(&self, n: u64) {
+        if let Some(stage) = self.stage.read().unwrap().as_ref() {
+            let completed = stage.completed.fetch_add(n, Ordering::Relaxed) + n;
+            // Skip the update if another thread is writing one.
+            if let Ok(mut last_written_at) = stage.last_written_at.try_lock() {
+                if last_written_at.elapsed() >= JSONL_PROGRESS_INTERVAL {
+                    *last_written_at = Instant::now();
+                    self.write_event(stage, completed);
+                }
+            }
+        }
+    }
+
+    fn end(&self) {
+        if let Some(stage) = self.stage.write().unwrap().take() {
+            let completed = stage.completed.load(Ordering::Relaxed);
+            self.write_event(&stage, completed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use testdir::testdir;
+
+    use crate::{
+        geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_in_crs},
+        topo::topo::{calculate_topo, TopoParams},
+    };
+
+    use super::JsonlProgressSink;
+
+    #[test]
+    fn test_jsonl_progress_sink_writes_all_stages() {
+        let crs = crate::crs::Crs::Epsg(32654);
+        let proposal_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(
+                vec![vec![(0.0, 1.0), (100.0, 1.0)].into()],
+                crs.clone(),
+            )
+            .unwrap();
+        let gt_graph: GeoGraph<(), (), petgraph::Undirected> =
+            build_geograph_from_lines_in_crs(vec![vec![(0.0, 0.0), (100.0, 0.0)].into()], crs)
+                .unwrap();
+        let progress_filepath = testdir!().join("progress.jsonl");
+        let progress = JsonlProgressSink::create(&progress_filepath).unwrap();
+
+        calculate_topo(
+            &proposal_graph,
+            &gt_graph,
+            &TopoParams::new_in_crs_units(10.0, 5.0),
+            None,
+            Some(&progress),
+        )
+        .unwrap();
+
+        let contents = std::fs::read_to_string(&progress_filepath).unwrap();
+        let events: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        for event in &events {
+            assert!(event["completed"].as_u64().unwrap() <= event["total"].as_u64().unwrap());
+            chrono::DateTime::parse_from_rfc3339(event["timestamp"].as_str().unwrap()).unwrap();
+            assert!(event["eta_seconds"].is_null() || event["eta_seconds"].is_f64());
+        }
+        let stages: HashSet<&str> = events
+            .iter()
+            .map(|event| event["stage"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            HashSet::from([
+                "sample_proposal",
+                "sample_ground_truth",
+                "lookup_within_hole_radius",
+                "match"
+            ]),
+            stages
+        );
+        // The last line of each stage reports it complete.
+        let last_match_event = events.iter().rev().find(|event| event["stage"] == "match");
+        assert_eq!(
+            last_match_event.unwrap()["total"],
+            last_match_event.unwrap()["completed"]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Instant;
+
+/// Wall-clock duration and memory growth of one stage of a computation.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct StageMetrics {
+    pub stage: &'static str,
+    pub elapsed_seconds: f64,
+    /// How much the peak resident set size of the process grew during the stage. None if the platform does not
+    /// report it.
+    pub peak_rss_delta_bytes: Option<u64>,
+}
+
+/// Records `StageMetrics` for a sequence of stages, one stage at a time.
+#[derive(Default)]
+pub struct StageTimer {
+    completed_stages: Vec<StageMetrics>,
+    /// The running stage, when it started and the peak RSS at its start.
+    current_stage: Option<(&'static str, Instant, Option<u64>)>,
+}
+
+impl StageTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finish the current stage, if any, and start a new one.
+    pub fn start_stage(&mut self, stage: &'static str) {
+        self.finish_stage();
+        self.current_stage = Some((stage, Instant::now(), peak_rss_bytes()));
+    }
+
+    /// Finish the current stage, if any.
+    pub fn finish_stage(&mut self) {
+        if let Some((stage, start, start_peak_rss)) = self.current_stage.take() {
+            self.completed_stages.push(StageMetrics {
+                stage,
+                elapsed_seconds: start.elapsed().as_secs_f64(),
+                peak_rss_delta_bytes: start_peak_rss
+                    .zip(peak_rss_bytes())
+                    .map(|(start, end)| end.saturating_sub(start)),
+            });
+        }
+    }
+
+    /// Run `f` as a stage of its own.
+    pub fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        self.start_stage(stage);
+        let result = f();
+        self.finish_stage();
+        result
+    }
+
+    /// Append stages which were recorded elsewhere, e.g. by a nested computation.
+    pub fn extend(&mut self, stages: impl IntoIterator<Item = StageMetrics>) {
+        self.finish_stage();
+        self.completed_stages.extend(stages);
+    }
+
+    /// The name of the running stage, empty if there is none.
+    pub fn current_stage(&self) -> &'static str {
+        self.current_stage.map_or("", |(stage, _, _)| stage)
+    }
+
+    pub fn completed_stages(&self) -> &[StageMetrics] {
+        &self.completed_stages
+    }
+
+    /// Finish the current stage, if any, and return the metrics of all stages.
+    pub fn into_metrics(mut self) -> Vec<StageMetrics> {
+        self.finish_stage();
+        self.completed_stages
+    }
+}
+
+/// Log the metrics of the stages as a table.
+pub fn log_stage_summary(stages: &[StageMetrics]) {
+    let stage_width = stages
+        .iter()
+        .map(|metrics| metrics.stage.len())
+        .max()
+        .unwrap_or(0)
+        .max("stage".len());
+    let mut summary = format!(
+        "{:<stage_width$}  {:>10}  {:>14}",
+        "stage", "time [s]", "peak RSS +[MB]"
+    );
+    for metrics in stages {
+        let peak_rss_delta = metrics
+            .peak_rss_delta_bytes
+            .map_or("-".to_string(), |bytes| {
+                format!("{:.1}", bytes as f64 / (1024.0 * 1024.0))
+            });
+        summary.push_str(&format!(
+            "\n{:<stage_width$}  {:>10.3}  {:>14}",
+            metrics.stage, metrics.elapsed_seconds, peak_rss_delta
+        ));
+    }
+    log::info!("Stage summary:\n{}", summary);
+}
+
+/// The peak resident set size of the process so far, best effort. None on platforms other than Linux, or if it
+/// cannot be read.
+pub fn peak_rss_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let peak_rss_kilobytes: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(peak_rss_kilobytes * 1024)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{peak_rss_bytes, StageTimer};
+
+    #[test]
+    fn test_stage_timer() {
+        let mut timer = StageTimer::new();
+        timer.start_stage("first");
+        assert_eq!("first", timer.current_stage());
+        let sum = timer.time("second", || (0..1000u64).sum::<u64>());
+        timer.start_stage("third");
+        let stages = timer.into_metrics();
+
+        assert_eq!(499500, sum);
+        let stage_names: Vec<&str> = stages.iter().map(|metrics| metrics.stage).collect();
+        assert_eq!(vec!["first", "second", "third"], stage_names);
+        for metrics in &stages {
+            assert!(metrics.elapsed_seconds > 0.0);
+            assert_eq!(
+                peak_rss_bytes().is_some(),
+                metrics.peak_rss_delta_bytes.is_some()
+            );
+        }
+    }
+}
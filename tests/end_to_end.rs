@@ -0,0 +1,355 @@
+//! End-to-end tests of the executable on the small proposal and ground truth in `tests/fixtures/end_to_end`.
+//!
+//! The scores and the outputs of a run are compared to `golden.json` in the same directory. The fixtures and the
+//! golden file are generated by `test_end_to_end_matches_golden` if the `TOPO_UPDATE_GOLDEN` environment variable is
+//! set. After an intentional change of the scores or the outputs, regenerate them with `make golden` and review the
+//! diff before committing it.
+
+#![cfg(feature = "gdal-io")]
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use gdal::vector::LayerAccess;
+use serde::{Deserialize, Serialize};
+use testdir::testdir;
+
+/// Set to regenerate the fixtures and the golden file instead of comparing to them.
+const UPDATE_GOLDEN_ENV_VAR: &str = "TOPO_UPDATE_GOLDEN";
+
+/// The outputs of a run which are compared to the golden file.
+const COMPARED_OUTPUTS: [&str; 3] = [
+    "proposal_nodes.gpkg",
+    "ground_truth_nodes.gpkg",
+    "ground_truth.geojson",
+];
+
+/// Maximum difference of the F1 score from the golden value.
+const F1_SCORE_TOLERANCE: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct OutputSummary {
+    feature_count: usize,
+    /// Hash of the sorted attributes of all features, see `summarize_output`.
+    attributes_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Golden {
+    f1_score: f64,
+    outputs: BTreeMap<String, OutputSummary>,
+}
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/end_to_end")
+}
+
+/// A linestring feature in EPSG:4326 with the given properties.
+fn line_feature(coords: &[(f64, f64)], properties: serde_json::Value) -> geojson::Feature {
+    geojson::Feature {
+        bbox: None,
+        geometry: Some(geojson::Geometry::new(geojson::Value::LineString(
+            coords.iter().map(|(lon, lat)| vec![*lon, *lat]).collect(),
+        ))),
+        id: None,
+        properties: properties.as_object().cloned(),
+        foreign_members: None,
+    }
+}
+
+fn write_feature_collection(features: Vec<geojson::Feature>, filepath: &Path) {
+    let collection = geojson::FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    };
+    std::fs::write(filepath, serde_json::to_string_pretty(&collection).unwrap()).unwrap();
+}
+
+/// Write the ground truth and the proposal fixtures to `fixture_dir`.
+///
+/// The ground truth is a 3x3 grid of streets in Tokyo, with an edge between each pair of neighbouring intersections.
+/// The proposal is the same grid shifted by about a meter, with one edge missing and one spurious diagonal edge.
+fn write_fixtures(fixture_dir: &Path) {
+    let grid_coord =
+        |col: usize, row: usize| (139.78 + 0.002 * col as f64, 35.68 + 0.002 * row as f64);
+    let mut grid_edges = Vec::new();
+    for row in 0..3 {
+        for col in 0..2 {
+            grid_edges.push((grid_coord(col, row), grid_coord(col + 1, row)));
+        }
+    }
+    for col in 0..3 {
+        for row in 0..2 {
+            grid_edges.push((grid_coord(col, row), grid_coord(col, row + 1)));
+        }
+    }
+
+    let ground_truth_features = grid_edges
+        .iter()
+        .map(|(start, end)| {
+            let highway = if start.1 == end.1 && start.1 == grid_coord(0, 1).1 {
+                "primary"
+            } else {
+                "residential"
+            };
+            line_feature(&[*start, *end], serde_json::json!({ "highway": highway }))
+        })
+        .collect();
+    write_feature_collection(
+        ground_truth_features,
+        &fixture_dir.join("ground_truth.geojson"),
+    );
+
+    let shift = |(lon, lat): (f64, f64)| (lon + 1e-5, lat + 1e-5);
+    let mut proposal_features: Vec<geojson::Feature> = grid_edges
+        .iter()
+        .take(grid_edges.len() - 1)
+        .enumerate()
+        .map(|(idx, (start, end))| {
+            line_feature(
+                &[shift(*start), shift(*end)],
+                serde_json::json!({ "confidence": 0.5 + 0.04 * idx as f64 }),
+            )
+        })
+        .collect();
+    proposal_features.push(line_feature(
+        &[shift(grid_coord(0, 0)), shift(grid_coord(1, 1))],
+        serde_json::json!({ "confidence": 0.1 }),
+    ));
+    write_feature_collection(proposal_features, &fixture_dir.join("proposal.geojson"));
+}
+
+/// Write a config which evaluates the fixtures to `data_dir`, and return its path.
+fn write_config(data_dir: &Path) -> PathBuf {
+    let config_filepath = data_dir.join("config.yaml");
+    std::fs::write(
+        &config_filepath,
+        format!(
+            r#"
+proposal_geofile_path: {fixture_dir}/proposal.geojson
+ground_truth:
+  !Geofile
+    filepath: {fixture_dir}/ground_truth.geojson
+topo_params:
+  resampling_distance: 11.0
+  hole_radius: 6.0
+data_dir: {data_dir}
+"#,
+            fixture_dir = fixture_dir().display(),
+            data_dir = data_dir.display()
+        ),
+    )
+    .unwrap();
+    config_filepath
+}
+
+/// Run the executable with the config and the other arguments, and fail the test if it fails.
+fn run_topo(config_filepath: &Path, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_topo_rust"))
+        .arg("--config-filepath")
+        .arg(config_filepath)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// FNV-1a hash, which unlike the hasher of the standard library is stable across Rust versions.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The feature count of a geofile, and a hash of the attributes of its features. The attributes of each feature are
+/// sorted by name, and the features by their attributes, so that the hash does not depend on the order in which they
+/// were written.
+fn summarize_output(filepath: &Path) -> OutputSummary {
+    let dataset = gdal::Dataset::open(filepath).unwrap();
+    let mut layer = dataset.layer(0).unwrap();
+    let mut feature_attributes: Vec<String> = layer
+        .features()
+        .map(|feature| {
+            let mut fields: Vec<String> = feature
+                .fields()
+                .map(|(name, value)| format!("{}={:?}", name, value))
+                .collect();
+            fields.sort();
+            fields.join(";")
+        })
+        .collect();
+    feature_attributes.sort();
+    OutputSummary {
+        feature_count: feature_attributes.len(),
+        attributes_hash: format!(
+            "{:016x}",
+            fnv1a_hash(feature_attributes.join("\n").as_bytes())
+        ),
+    }
+}
+
+#[test]
+fn test_end_to_end_matches_golden() {
+    let update_golden = std::env::var_os(UPDATE_GOLDEN_ENV_VAR).is_some();
+    let golden_filepath = fixture_dir().join("golden.json");
+    if update_golden {
+        std::fs::create_dir_all(fixture_dir()).unwrap();
+        write_fixtures(&fixture_dir());
+    }
+    let data_dir = testdir!();
+
+    run_topo(&write_config(&data_dir), &[]);
+
+    let result: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(data_dir.join("result.json")).unwrap())
+            .unwrap();
+    let actual = Golden {
+        f1_score: result["f1_score_result"]["f1_score"].as_f64().unwrap(),
+        outputs: COMPARED_OUTPUTS
+            .iter()
+            .map(|filename| {
+                (
+                    filename.to_string(),
+                    summarize_output(&data_dir.join(filename)),
+                )
+            })
+            .collect(),
+    };
+    if update_golden {
+        std::fs::write(
+            &golden_filepath,
+            serde_json::to_string_pretty(&actual).unwrap(),
+        )
+        .unwrap();
+        return;
+    }
+    let golden: Golden = serde_json::from_str(
+        &std::fs::read_to_string(&golden_filepath).unwrap_or_else(|_| {
+            panic!(
+                "Golden file {:?} not found, generate it with `make golden`",
+                golden_filepath
+            )
+        }),
+    )
+    .unwrap();
+    assert!(
+        (golden.f1_score - actual.f1_score).abs() <= F1_SCORE_TOLERANCE,
+        "F1 score {} differs from the golden {}",
+        actual.f1_score,
+        golden.f1_score
+    );
+    assert_eq!(golden.outputs, actual.outputs);
+}
+
+#[test]
+fn test_end_to_end_check_writes_nothing() {
+    let data_dir = testdir!();
+    let config_filepath = write_config(&data_dir);
+
+    run_topo(&config_filepath, &["--check"]);
+
+    let filenames: Vec<PathBuf> = std::fs::read_dir(&data_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(vec![config_filepath], filenames);
+}
+
+#[test]
+fn test_validate_reports_missing_proposal() {
+    let data_dir = testdir!();
+    let config_filepath = data_dir.join("config.yaml");
+    let missing_proposal_filepath = data_dir.join("missing_proposal.gpkg");
+    std::fs::write(
+        &config_filepath,
+        format!(
+            r#"
+proposal:
+  !Geofile
+    filepath: {proposal}
+ground_truth:
+  !Geofile
+    filepath: {fixture_dir}/ground_truth.geojson
+data_dir: {data_dir}
+"#,
+            proposal = missing_proposal_filepath.display(),
+            fixture_dir = fixture_dir().display(),
+            data_dir = data_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_topo_rust"))
+        .arg("validate")
+        .arg("--config")
+        .arg(&config_filepath)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let report = String::from_utf8_lossy(&output.stdout);
+    let failed_items: Vec<&str> = report
+        .lines()
+        .filter(|line| line.starts_with("[FAIL]"))
+        .collect();
+    assert_eq!(1, failed_items.len(), "{}", report);
+    assert!(failed_items[0].starts_with("[FAIL] Proposal: "));
+    assert!(failed_items[0].contains("missing_proposal.gpkg"));
+    assert!(report.contains("[PASS] Ground truth: "), "{}", report);
+    assert!(report.contains("[PASS] Data dir: "), "{}", report);
+}
+
+#[test]
+fn test_validate_reports_input_overlap() {
+    let data_dir = testdir!();
+    let config_filepath = write_config(&data_dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_topo_rust"))
+        .arg("validate")
+        .arg("--config")
+        .arg(&config_filepath)
+        .output()
+        .unwrap();
+
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", report);
+    assert!(
+        report.contains("layer 'ground_truth' with 12 Line String features"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("[PASS] Overlap with ground truth: "),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn test_inspect_prints_layer_metadata() {
+    let output = Command::new(env!("CARGO_BIN_EXE_topo_rust"))
+        .arg("inspect")
+        .arg("--path")
+        .arg(fixture_dir().join("proposal.geojson"))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let layers: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(1, layers.as_array().unwrap().len());
+    assert_eq!(12, layers[0]["feature_count"]);
+    assert_eq!("Line String", layers[0]["geometry_type"]);
+    assert_eq!("confidence", layers[0]["fields"][0]["name"]);
+}
@@ -0,0 +1,121 @@
+//! Test of the memory held while matching with `TopoParams::low_memory`. It counts the allocated bytes with a global
+//! allocator, which is why it is in its own test binary.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use topo_rust::{
+    crs::Crs,
+    error::Result,
+    geograph::{primitives::GeoGraph, utils::build_geograph_from_lines_in_crs},
+    topo::{
+        matching::{CandidatePair, CandidateSink},
+        topo::{calculate_topo_with_candidate_sink, DistanceUnits, TopoNode, TopoParams},
+    },
+};
+
+/// UTM zone 54N, whose unit is the meter.
+const PROJECTED_CRS_CODE: u32 = 32654;
+
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, which keeps track of the bytes currently allocated in `ALLOCATED_BYTES`.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Records the allocated bytes once all candidates were written, i.e. after the ground truth nodes were spilled and
+/// before the proposal nodes are matched.
+#[derive(Default)]
+struct AllocatedBytesSink {
+    allocated_bytes: Option<usize>,
+}
+
+impl CandidateSink for AllocatedBytesSink {
+    fn write(&mut self, _: &CandidatePair) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.allocated_bytes = Some(ALLOCATED_BYTES.load(Ordering::Relaxed));
+        Ok(())
+    }
+}
+
+/// Parallel horizontal lines, 100 units apart, shifted by `offset` along both axes.
+fn build_parallel_lines_graph(offset: f64) -> GeoGraph<(), (), petgraph::Undirected> {
+    let lines = (0..10)
+        .map(|idx| {
+            let y = 100.0 * idx as f64 + offset;
+            vec![(offset, y), (2000.0 + offset, y)].into()
+        })
+        .collect();
+    build_geograph_from_lines_in_crs(lines, Crs::Epsg(PROJECTED_CRS_CODE)).unwrap()
+}
+
+/// The number of ground truth nodes, and the bytes allocated while matching.
+fn allocated_bytes_while_matching(
+    proposal_graph: &GeoGraph<(), (), petgraph::Undirected>,
+    ground_truth_graph: &GeoGraph<(), (), petgraph::Undirected>,
+    low_memory: bool,
+) -> (usize, usize) {
+    let params = TopoParams {
+        units: DistanceUnits::CrsUnits,
+        low_memory,
+        ..TopoParams::new_in_crs_units(11.0, 5.0)
+    };
+    let mut sink = AllocatedBytesSink::default();
+    let result = calculate_topo_with_candidate_sink(
+        proposal_graph,
+        ground_truth_graph,
+        &params,
+        None,
+        None,
+        &mut sink,
+    )
+    .unwrap();
+    (
+        result.ground_truth_nodes.len(),
+        sink.allocated_bytes.unwrap(),
+    )
+}
+
+#[test]
+fn test_low_memory_does_not_hold_ground_truth_nodes_while_matching() {
+    let proposal_graph = build_parallel_lines_graph(1.0);
+    let ground_truth_graph = build_parallel_lines_graph(0.0);
+
+    let (ground_truth_node_count, in_memory_bytes) =
+        allocated_bytes_while_matching(&proposal_graph, &ground_truth_graph, false);
+    let (_, low_memory_bytes) =
+        allocated_bytes_while_matching(&proposal_graph, &ground_truth_graph, true);
+
+    // The ground truth nodes are only held in memory without low_memory. Allow for other allocations to differ by
+    // half of the size of the nodes.
+    let ground_truth_node_bytes = ground_truth_node_count * std::mem::size_of::<TopoNode>();
+    assert!(
+        in_memory_bytes >= low_memory_bytes + ground_truth_node_bytes / 2,
+        "{} bytes allocated while matching with low_memory, {} without, for {} bytes of ground truth nodes",
+        low_memory_bytes,
+        in_memory_bytes,
+        ground_truth_node_bytes
+    );
+}
@@ -0,0 +1,86 @@
+//! Test of the GeoJSON-only pipeline, which must work without GDAL and PROJ. Run it as CI does for the pure build with
+//! `cargo test --no-default-features --features pure --test pure_pipeline`.
+
+use std::collections::HashSet;
+
+use testdir::testdir;
+use topo_rust::{
+    crs::Crs,
+    geofile::{
+        feature::{Feature, FeatureMap, FieldValue},
+        geojson::{read_features_from_geojson, write_features_to_geojson, GeoJsonWriteOptions},
+    },
+    geograph::geo_feature_graph::GeoFeatureGraph,
+    topo::topo::{calculate_topo, TopoParams},
+};
+
+/// UTM zone 54N, whose unit is the meter.
+const PROJECTED_CRS_CODE: u32 = 32654;
+
+/// A road feature in UTM zone 54N, near Tokyo station.
+fn road_feature(coords: &[(f64, f64)], highway: &str) -> Feature {
+    Feature {
+        geometry: geo::LineString::from(
+            coords
+                .iter()
+                .map(|(x, y)| (388_000.0 + x, 3_949_000.0 + y))
+                .collect::<Vec<_>>(),
+        )
+        .into(),
+        attributes: Some(FeatureMap::from([(
+            "highway".to_string(),
+            FieldValue::StringValue(highway.to_string()),
+        )])),
+        null_fields: HashSet::new(),
+    }
+}
+
+/// Write the features to a GeoJSON file in UTM zone 54N, and build a graph from the features read back from it.
+fn write_and_load(features: &[Feature], filename: &str) -> GeoFeatureGraph<petgraph::Undirected> {
+    let filepath = testdir!().join(filename);
+    let crs = Crs::Epsg(PROJECTED_CRS_CODE);
+    let options = GeoJsonWriteOptions {
+        crs: Some(&crs),
+        force_projected_crs: true,
+        ..Default::default()
+    };
+    write_features_to_geojson(features, &filepath, &options).unwrap();
+
+    let (read_features, read_crs) = read_features_from_geojson(&filepath).unwrap();
+    assert_eq!(crs, read_crs);
+    assert_eq!(features, read_features.as_slice());
+    GeoFeatureGraph::from_features_in_crs(read_features, read_crs, None).unwrap()
+}
+
+#[test]
+fn test_geojson_pipeline_without_gdal() {
+    let ground_truth = write_and_load(
+        &[
+            road_feature(&[(0.0, 0.0), (200.0, 0.0)], "primary"),
+            road_feature(&[(100.0, -100.0), (100.0, 100.0)], "residential"),
+        ],
+        "ground_truth.geojson",
+    );
+    // The primary road is shifted by a meter, the residential road is missing.
+    let proposal = write_and_load(
+        &[road_feature(&[(0.0, 1.0), (200.0, 1.0)], "primary")],
+        "proposal.geojson.gz",
+    );
+
+    let result = calculate_topo(
+        &proposal,
+        &ground_truth,
+        &TopoParams::new_in_crs_units(11.0, 5.0),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let scores = result.f1_score_result;
+    assert!(scores.precision() > 0.95, "{:?}", scores);
+    assert!(
+        (0.4..0.6).contains(&scores.recall()),
+        "Only the primary road is found: {:?}",
+        scores
+    );
+}